@@ -0,0 +1,46 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+// Feeds soups of segments to polygon assembly. The first byte picks the scale, then
+// every 3 bytes are a segment between two points of a 16x16 grid and its normal, so that
+// loops, branches and dead ends are common.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nalgebra::Vector3;
+
+#[allow(dead_code)]
+#[path = "../../src/polygon_assembly.rs"]
+mod polygon_assembly;
+
+use polygon_assembly::{assemble_polygons, Segment};
+
+const SCALES: [f64; 8] = [1.0, 0.1, 3.3e-7, 1e-12, 1e9, 1e300, f64::INFINITY, f64::NAN];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&scale, data)) = data.split_first() else {
+        return;
+    };
+    let scale = SCALES[scale as usize % SCALES.len()];
+    let point = |byte: u8| {
+        let (x, y) = ((byte & 15) as f64 - 7.5, (byte >> 4) as f64 - 7.5);
+        Vector3::new(x * scale, y * scale, 0.0)
+    };
+    let segments: Vec<Segment> = data
+        .chunks_exact(3)
+        .map(|bytes| {
+            let normal = [
+                (bytes[2] % 3) as f32 - 1.0,
+                (bytes[2] / 3 % 3) as f32 - 1.0,
+                0.0,
+            ];
+            ((point(bytes[0]), point(bytes[1])), normal)
+        })
+        .collect();
+
+    let contours = assemble_polygons(&segments, 1e-6);
+    for contour in &contours {
+        assert!(contour.points.len() >= 3);
+        assert!(contour.parent.is_none_or(|parent| parent < contours.len()));
+    }
+});
@@ -0,0 +1,28 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+// Feeds arbitrary bytes to the STL importer, which must return an error for anything
+// it can't read rather than panic or hand back coordinates the slicer can't handle
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+
+#[allow(dead_code)]
+#[path = "../../src/stl_processor.rs"]
+mod stl_processor;
+
+use stl_processor::{StlProcessor, MAX_COORDINATE};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(triangles) = StlProcessor::read_stl_from(&mut Cursor::new(data)) {
+        for triangle in &triangles {
+            assert!(triangle.normal.iter().all(|c| c.is_finite()));
+            assert!(triangle
+                .vertices
+                .iter()
+                .flatten()
+                .all(|c| c.abs() <= MAX_COORDINATE));
+        }
+    }
+});
@@ -24,6 +24,12 @@ uniform bool visualize_normals;   // Toggle normal visualization
 uniform bool visualize_edges;     // Toggle edge visualization
 uniform float edge_thickness;    // Thickness of the edge lines
 
+// Uniform for Mesh Defect Visualization
+uniform bool visualize_back_faces; // Color the faces seen from behind
+
+// Uniform for see-through overlays like the resin vat
+uniform float opacity;            // 1.0 for solid surfaces
+
 // Constants
 const float PI = 3.14159265359;
 
@@ -119,6 +125,10 @@ void main() {
             max(blend_factor - tchayen_edge_factor, 0.0)
         );
     }
-    // Set the final fragment color with full opacity
-    fragColor = vec4(color, 1.0);
+    // Faces seen from behind are inside out or show through a hole in the mesh
+    if (visualize_back_faces && !gl_FrontFacing) {
+        color = vec3(0.9, 0.05, 0.6);
+    }
+    // Set the final fragment color, blended over what is behind it when below 1.0
+    fragColor = vec4(color, opacity);
 }
\ No newline at end of file
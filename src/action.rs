@@ -1,12 +1,62 @@
 use std::{cell::RefCell, rc::Rc};
 
-use nalgebra::{Quaternion, Vector3};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
 
 use crate::body::Body;
+use crate::mesh::Mesh;
+use uuid::Uuid;
 
 pub trait Action {
     fn execute(&mut self);
     fn undo(&mut self);
+
+    /// Rhai statements repeating this action on a `body` variable, relative to where the
+    /// body was, each with the uuid of the body it was applied to. Used to record macros.
+    fn script(&self) -> Vec<(Uuid, String)> {
+        Vec::new()
+    }
+
+    /// A statement repeating this action on all of `targets()` at once, for actions that
+    /// make a different change to each body. Takes precedence over `script`.
+    fn script_statement(&self) -> Option<String> {
+        None
+    }
+}
+
+// Rounded so recorded macros don't carry float noise like 9.999999
+fn script_number(value: f32) -> String {
+    let rounded = (value * 1e4).round() / 1e4;
+    format!("{}", rounded + 0.0)
+}
+
+fn script_call(body: &Rc<RefCell<Body>>, method: &str, values: Vector3<f32>) -> (Uuid, String) {
+    (
+        body.borrow().uuid,
+        format!(
+            "body.{}({}, {}, {});",
+            method,
+            script_number(values.x),
+            script_number(values.y),
+            script_number(values.z)
+        ),
+    )
+}
+
+fn rotation_script(
+    body: &Rc<RefCell<Body>>,
+    input: Quaternion<f32>,
+    previous: Quaternion<f32>,
+) -> Vec<(Uuid, String)> {
+    let delta = UnitQuaternion::from_quaternion(input)
+        * UnitQuaternion::from_quaternion(previous).inverse();
+    if delta.angle() < 1e-5 {
+        return Vec::new();
+    }
+    vec![script_call(
+        body,
+        "rotate",
+        Body::quaternion_to_euler(&delta.into_inner()),
+    )]
 }
 
 pub struct SetPositionAction {
@@ -23,6 +73,14 @@ impl Action for SetPositionAction {
     fn undo(&mut self) {
         self.body.borrow_mut().set_position(self.previous);
     }
+
+    fn script(&self) -> Vec<(Uuid, String)> {
+        let delta = self.input - self.previous;
+        if delta == Vector3::zeros() {
+            return Vec::new();
+        }
+        vec![script_call(&self.body, "translate", delta)]
+    }
 }
 
 pub struct SetRotationAction {
@@ -39,6 +97,14 @@ impl Action for SetRotationAction {
     fn undo(&mut self) {
         self.body.borrow_mut().set_rotation_quat(self.previous);
     }
+
+    fn script(&self) -> Vec<(Uuid, String)> {
+        rotation_script(
+            &self.body,
+            Body::euler_to_quaternion(self.input),
+            self.previous,
+        )
+    }
 }
 
 pub struct SetScaleAction {
@@ -55,7 +121,199 @@ impl Action for SetScaleAction {
     fn undo(&mut self) {
         self.body.borrow_mut().set_scale(self.previous);
     }
+
+    fn script(&self) -> Vec<(Uuid, String)> {
+        let factor = self.input.component_div(&self.previous);
+        if factor == Vector3::repeat(1.0) || factor.iter().any(|f| !f.is_finite()) {
+            return Vec::new();
+        }
+        vec![script_call(&self.body, "scale", factor)]
+    }
+}
+pub struct SetRotationQuatAction {
+    pub body: Rc<RefCell<Body>>,
+    pub input: Quaternion<f32>,
+    pub previous: Quaternion<f32>,
 }
+
+impl Action for SetRotationQuatAction {
+    fn execute(&mut self) {
+        self.body.borrow_mut().set_rotation_quat(self.input);
+    }
+
+    fn undo(&mut self) {
+        self.body.borrow_mut().set_rotation_quat(self.previous);
+    }
+
+    fn script(&self) -> Vec<(Uuid, String)> {
+        rotation_script(&self.body, self.input, self.previous)
+    }
+}
+
+/// Replaces the mesh of a body, e.g. with its hollowed one
+pub struct SetMeshAction {
+    pub body: Rc<RefCell<Body>>,
+    pub input: Mesh,
+    pub previous: Mesh,
+}
+
+impl Action for SetMeshAction {
+    fn execute(&mut self) {
+        self.body.borrow_mut().set_mesh(self.input.clone());
+    }
+
+    fn undo(&mut self) {
+        self.body.borrow_mut().set_mesh(self.previous.clone());
+    }
+}
+
+/// Groups several actions so they are executed and undone as one step
+pub struct CompoundAction {
+    pub actions: Vec<Box<dyn Action>>,
+}
+
+impl Action for CompoundAction {
+    fn execute(&mut self) {
+        for action in self.actions.iter_mut() {
+            action.execute();
+        }
+    }
+
+    fn undo(&mut self) {
+        for action in self.actions.iter_mut().rev() {
+            action.undo();
+        }
+    }
+
+    fn script(&self) -> Vec<(Uuid, String)> {
+        self.actions
+            .iter()
+            .flat_map(|action| action.script())
+            .collect()
+    }
+}
+
+/// A relative transform applied to several bodies at once
+pub struct BatchTransform {
+    pub translation: Vector3<f32>,
+    /// Euler angles in degrees, applied on top of each body's current rotation
+    pub rotation: Vector3<f32>,
+    /// Per axis scale factor, 1.0 leaves the scale unchanged
+    pub scale: Vector3<f32>,
+}
+
+impl BatchTransform {
+    /// Builds a single undoable action that applies this transform to every body
+    pub fn to_action(&self, bodies: &[Rc<RefCell<Body>>]) -> CompoundAction {
+        let delta_rotation =
+            UnitQuaternion::from_quaternion(Body::euler_to_quaternion(self.rotation));
+        let mut actions: Vec<Box<dyn Action>> = Vec::new();
+        for body_rc in bodies {
+            let (position, rotation, scale) = {
+                let body = body_rc.borrow();
+                (body.position, body.rotation, body.scale)
+            };
+            let new_rotation = delta_rotation * UnitQuaternion::from_quaternion(rotation);
+            actions.push(Box::new(SetPositionAction {
+                body: body_rc.clone(),
+                input: position + self.translation,
+                previous: position,
+            }));
+            actions.push(Box::new(SetRotationQuatAction {
+                body: body_rc.clone(),
+                input: new_rotation.into_inner(),
+                previous: rotation,
+            }));
+            actions.push(Box::new(SetScaleAction {
+                body: body_rc.clone(),
+                input: scale.component_mul(&self.scale),
+                previous: scale,
+            }));
+        }
+        CompoundAction { actions }
+    }
+}
+
+/// Gives the bodies scaled differently along each axis the same scale on all of them
+pub fn make_scale_uniform(bodies: &[Rc<RefCell<Body>>]) -> CompoundAction {
+    let actions = bodies
+        .iter()
+        .filter(|body_rc| body_rc.borrow().has_uneven_scale())
+        .map(|body_rc| {
+            let body = body_rc.borrow();
+            Box::new(SetScaleAction {
+                body: body_rc.clone(),
+                input: body.uniform_scale(),
+                previous: body.scale,
+            }) as Box<dyn Action>
+        })
+        .collect();
+    CompoundAction { actions }
+}
+
+/// Adds bodies to the scene, undone by taking them out again
+pub struct AddBodiesAction {
+    pub scene: Rc<RefCell<Vec<Rc<RefCell<Body>>>>>,
+    pub bodies: Vec<Rc<RefCell<Body>>>,
+}
+
+impl Action for AddBodiesAction {
+    fn execute(&mut self) {
+        self.scene.borrow_mut().extend(self.bodies.iter().cloned());
+    }
+
+    fn undo(&mut self) {
+        self.scene
+            .borrow_mut()
+            .retain(|body| !self.bodies.iter().any(|added| Rc::ptr_eq(body, added)));
+    }
+}
+
+/// Copies of bodies turned around a vertical axis through `center`, evenly spaced so each
+/// body and its copies make up `count` parts around the circle
+pub struct CircularArray {
+    pub center: Vector3<f32>,
+    /// Parts around the circle, the original included
+    pub count: usize,
+}
+
+impl CircularArray {
+    /// The copies of a body, going counterclockwise seen from above
+    pub fn copies(&self, body: &Body) -> Vec<Body> {
+        let center = Vector3::new(self.center.x, self.center.y, 0.0);
+        (1..self.count)
+            .map(|i| {
+                let angle = std::f32::consts::TAU * i as f32 / self.count as f32;
+                let turn = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), angle);
+                let mut copy = body.clone();
+                copy.uuid = Uuid::new_v4();
+                copy.name = format!("{} copy {}", body.name, i);
+                copy.selected = false;
+                copy.position = center + turn * (body.position - center);
+                copy.rotation =
+                    (turn * UnitQuaternion::from_quaternion(body.rotation)).into_inner();
+                copy
+            })
+            .collect()
+    }
+
+    /// Builds a single undoable action adding the copies of every body to the scene
+    pub fn to_action(
+        &self,
+        scene: &Rc<RefCell<Vec<Rc<RefCell<Body>>>>>,
+        bodies: &[Rc<RefCell<Body>>],
+    ) -> AddBodiesAction {
+        AddBodiesAction {
+            scene: Rc::clone(scene),
+            bodies: bodies
+                .iter()
+                .flat_map(|body| self.copies(&body.borrow()))
+                .map(|copy| Rc::new(RefCell::new(copy)))
+                .collect(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,4 +467,79 @@ mod tests {
         // Verify that the body's scale has been restored
         assert_vectors_approx_equal(&body.borrow().scale, &previous_scale);
     }
+
+    #[test]
+    fn test_batch_transform_is_one_undoable_step() {
+        let bodies = vec![
+            Rc::new(RefCell::new(Body::default())),
+            Rc::new(RefCell::new(Body::default())),
+        ];
+        bodies[1].borrow_mut().set_position(Vector3::new(10.0, 0.0, 0.0));
+        bodies[1].borrow_mut().set_scale(Vector3::new(2.0, 2.0, 2.0));
+        let previous: Vec<_> = bodies
+            .iter()
+            .map(|b| {
+                let b = b.borrow();
+                (b.position, b.rotation, b.scale)
+            })
+            .collect();
+
+        let transform = BatchTransform {
+            translation: Vector3::new(1.0, 2.0, 3.0),
+            rotation: Vector3::new(0.0, 0.0, 45.0),
+            scale: Vector3::new(1.015, 1.015, 1.015),
+        };
+        let mut action = transform.to_action(&bodies);
+        action.execute();
+
+        let expected_rotation = Body::euler_to_quaternion(Vector3::new(0.0, 0.0, 45.0));
+        for (body, (position, _, scale)) in bodies.iter().zip(previous.iter()) {
+            let body = body.borrow();
+            assert_vectors_approx_equal(&body.position, &(position + transform.translation));
+            assert_vectors_approx_equal(&body.scale, &(scale * 1.015));
+            assert_quaternions_approx_equal(&body.rotation, &expected_rotation);
+        }
+
+        action.undo();
+
+        for (body, (position, rotation, scale)) in bodies.iter().zip(previous.iter()) {
+            let body = body.borrow();
+            assert_vectors_approx_equal(&body.position, position);
+            assert_quaternions_approx_equal(&body.rotation, rotation);
+            assert_vectors_approx_equal(&body.scale, scale);
+        }
+    }
+
+    #[test]
+    fn test_circular_array() {
+        let body = Rc::new(RefCell::new(Body::default()));
+        body.borrow_mut().set_position(Vector3::new(10.0, 0.0, 2.0));
+        let scene = Rc::new(RefCell::new(vec![Rc::clone(&body)]));
+        let array = CircularArray {
+            center: Vector3::new(0.0, 0.0, 0.0),
+            count: 4,
+        };
+        let mut action = array.to_action(&scene, &[Rc::clone(&body)]);
+        action.execute();
+
+        let scene_bodies = scene.borrow().clone();
+        assert_eq!(scene_bodies.len(), 4);
+        let expected = [
+            Vector3::new(0.0, 10.0, 2.0),
+            Vector3::new(-10.0, 0.0, 2.0),
+            Vector3::new(0.0, -10.0, 2.0),
+        ];
+        for (copy, position) in scene_bodies[1..].iter().zip(expected) {
+            let copy = copy.borrow();
+            assert_vectors_approx_equal(&copy.position, &position);
+            assert_ne!(copy.uuid, body.borrow().uuid);
+        }
+        // Turned along with their position, so every copy faces the center the same way
+        let quarter = Body::euler_to_quaternion(Vector3::new(0.0, 0.0, 90.0));
+        assert_quaternions_approx_equal(&scene_bodies[1].borrow().rotation, &quarter);
+
+        action.undo();
+        assert_eq!(scene.borrow().len(), 1);
+        assert!(Rc::ptr_eq(&scene.borrow()[0], &body));
+    }
 }
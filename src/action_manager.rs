@@ -1,8 +1,18 @@
 use crate::action::Action;
 
+// Only scripts replay recordings
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+enum RecordedStep {
+    /// The statement replaying an action, None if it can't be replayed
+    Action(Option<String>),
+    /// Something done outside of the undo history, like slicing
+    Statement(String),
+}
+
 pub struct ActionManager {
     history: Vec<Box<dyn Action>>,
     future: Vec<Box<dyn Action>>,
+    recording: Option<Vec<RecordedStep>>,
 }
 
 impl ActionManager {
@@ -10,11 +20,13 @@ impl ActionManager {
         Self {
             history: Vec::new(),
             future: Vec::new(),
+            recording: None,
         }
     }
 
     pub fn execute(&mut self, mut action: Box<dyn Action>) {
         action.execute();
+        self.record_action(action.as_ref());
         self.history.push(action);
         self.future.clear(); // Clear the redo stack on new action
     }
@@ -22,6 +34,15 @@ impl ActionManager {
     pub fn undo(&mut self) {
         if let Some(mut action) = self.history.pop() {
             action.undo();
+            // Undoing a recorded action takes it out of the recording again
+            if let Some(steps) = &mut self.recording {
+                if let Some(index) = steps
+                    .iter()
+                    .rposition(|step| matches!(step, RecordedStep::Action(_)))
+                {
+                    steps.remove(index);
+                }
+            }
             self.future.push(action);
         }
     }
@@ -29,14 +50,78 @@ impl ActionManager {
     pub fn redo(&mut self) {
         if let Some(mut action) = self.future.pop() {
             action.execute();
+            self.record_action(action.as_ref());
             self.history.push(action);
         }
     }
+
+    /// Starts recording the actions executed from now on as a macro
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Adds a statement that doesn't go through the undo history to the recording
+    pub fn record(&mut self, statement: &str) {
+        if let Some(steps) = &mut self.recording {
+            steps.push(RecordedStep::Statement(statement.to_string()));
+        }
+    }
+
+    /// Ends the recording, returning the Rhai statements in the order they were made.
+    /// Actions apply to every body of a `targets()` loop.
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn stop_recording(&mut self) -> Option<Vec<String>> {
+        let steps = self.recording.take()?;
+        Some(
+            steps
+                .into_iter()
+                .filter_map(|step| match step {
+                    RecordedStep::Action(statement) => statement,
+                    RecordedStep::Statement(statement) => Some(statement),
+                })
+                .collect(),
+        )
+    }
+
+    fn record_action(&mut self, action: &dyn Action) {
+        let Some(steps) = &mut self.recording else {
+            return;
+        };
+        if let Some(statement) = action.script_statement() {
+            steps.push(RecordedStep::Action(Some(statement)));
+            return;
+        }
+        // Batch transforms make the same change to every body, so the statements of
+        // the first body stand for all of them
+        let script = action.script();
+        let lines: Vec<String> = match script.first() {
+            Some((first, _)) => script
+                .iter()
+                .filter(|(uuid, _)| uuid == first)
+                .map(|(_, line)| line.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        steps.push(RecordedStep::Action((!lines.is_empty()).then(|| {
+            format!("for body in targets() {{ {} }}", lines.join(" "))
+        })));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::action::{BatchTransform, SetPositionAction};
+    use crate::body::Body;
+    use nalgebra::Vector3;
+    use std::cell::RefCell;
+    use std::rc::Rc;
     use std::sync::{Arc, Mutex};
 
     /// A mock implementation of the Action trait for testing purposes.
@@ -185,6 +270,47 @@ mod tests {
         assert!(was_executed);
     }
 
+    #[test]
+    fn test_recording() {
+        let body = Rc::new(RefCell::new(Body::default()));
+        let mut manager = ActionManager::new();
+        manager.execute(Box::new(SetPositionAction {
+            body: Rc::clone(&body),
+            input: Vector3::new(1.0, 0.0, 0.0),
+            previous: Vector3::zeros(),
+        }));
+
+        manager.start_recording();
+        assert!(manager.is_recording());
+        manager.execute(Box::new(
+            BatchTransform {
+                translation: Vector3::new(0.0, 2.5, 0.0),
+                rotation: Vector3::new(0.0, 0.0, 45.0),
+                scale: Vector3::new(2.0, 2.0, 1.0),
+            }
+            .to_action(&[Rc::clone(&body), Rc::new(RefCell::new(Body::default()))]),
+        ));
+        manager.execute(Box::new(MockAction::new()));
+        manager.record("slice();");
+        let previous = body.borrow().position;
+        manager.execute(Box::new(SetPositionAction {
+            body: Rc::clone(&body),
+            input: Vector3::new(0.0, 0.0, 9.0),
+            previous,
+        }));
+        manager.undo();
+
+        assert_eq!(
+            manager.stop_recording().unwrap(),
+            vec![
+                "for body in targets() { body.translate(0, 2.5, 0); body.rotate(0, 0, 45); body.scale(2, 2, 1); }",
+                "slice();",
+            ]
+        );
+        assert!(!manager.is_recording());
+        assert_eq!(manager.stop_recording(), None);
+    }
+
     #[test]
     fn test_multiple_actions() {
         let mut manager = ActionManager::new();
@@ -0,0 +1,284 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::action::{Action, CompoundAction, SetPositionAction};
+use crate::body::{Body, AABB};
+use nalgebra::Vector3;
+use std::cell::RefCell;
+use std::rc::Rc;
+use uuid::Uuid;
+
+const AXIS_NAMES: [&str; 3] = ["x", "y", "z"];
+
+/// 0, 1 or 2 for "x", "y" or "z", the axis names scripts use
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub fn axis_from_name(name: &str) -> Option<usize> {
+    AXIS_NAMES
+        .iter()
+        .position(|axis| axis.eq_ignore_ascii_case(name))
+}
+
+/// Which side of their bounding boxes the bodies line up on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Min,
+    Center,
+    Max,
+}
+
+impl Alignment {
+    pub fn from_index(index: i32) -> Option<Self> {
+        match index {
+            0 => Some(Self::Min),
+            1 => Some(Self::Center),
+            2 => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Min, Self::Center, Self::Max]
+            .into_iter()
+            .find(|alignment| alignment.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::Center => "center",
+            Self::Max => "max",
+        }
+    }
+
+    fn of(self, aabb: &AABB, axis: usize) -> f32 {
+        match self {
+            Self::Min => aabb.min[axis],
+            Self::Center => (aabb.min[axis] + aabb.max[axis]) / 2.0,
+            Self::Max => aabb.max[axis],
+        }
+    }
+}
+
+// Bodies with their bounds after transforming, leaving out the ones without a mesh
+fn with_bounds(bodies: &[Rc<RefCell<Body>>]) -> Vec<(Rc<RefCell<Body>>, AABB)> {
+    bodies
+        .iter()
+        .filter_map(|body| Some((Rc::clone(body), body.borrow().world_aabb()?)))
+        .collect()
+}
+
+/// The moves of one alignment command, undone as one step. Recorded as the command
+/// itself, since every body moves by a different amount.
+pub struct AlignmentAction {
+    moves: CompoundAction,
+    statement: String,
+}
+
+impl AlignmentAction {
+    /// True when every body already was in place
+    pub fn is_empty(&self) -> bool {
+        self.moves.actions.is_empty()
+    }
+}
+
+impl Action for AlignmentAction {
+    fn execute(&mut self) {
+        self.moves.execute();
+    }
+
+    fn undo(&mut self) {
+        self.moves.undo();
+    }
+
+    fn script(&self) -> Vec<(Uuid, String)> {
+        Vec::new()
+    }
+
+    fn script_statement(&self) -> Option<String> {
+        Some(self.statement.clone())
+    }
+}
+
+// Moves each body along `axis`, skipping those that stay put
+fn moves(moves: impl IntoIterator<Item = (Rc<RefCell<Body>>, f32)>, axis: usize) -> CompoundAction {
+    let actions = moves
+        .into_iter()
+        .filter(|(_, distance)| distance.abs() > 1e-6)
+        .map(|(body, distance)| {
+            let previous = body.borrow().position;
+            let mut offset = Vector3::zeros();
+            offset[axis] = distance;
+            Box::new(SetPositionAction {
+                body,
+                input: previous + offset,
+                previous,
+            }) as Box<dyn Action>
+        })
+        .collect();
+    CompoundAction { actions }
+}
+
+/// Lines the bodies up on the lowest minimum, the middle of their combined bounds or the
+/// highest maximum along `axis`
+pub fn align(bodies: &[Rc<RefCell<Body>>], axis: usize, alignment: Alignment) -> AlignmentAction {
+    let bounded = with_bounds(bodies);
+    let combined = bounded
+        .iter()
+        .map(|(_, aabb)| aabb.clone())
+        .reduce(|a, b| AABB {
+            min: a.min.inf(&b.min),
+            max: a.max.sup(&b.max),
+        });
+    let moves = match combined {
+        Some(combined) => {
+            let target = alignment.of(&combined, axis);
+            moves(
+                bounded
+                    .into_iter()
+                    .map(|(body, aabb)| (body, target - alignment.of(&aabb, axis))),
+                axis,
+            )
+        }
+        None => moves(Vec::new(), axis),
+    };
+    AlignmentAction {
+        moves,
+        statement: format!(
+            "align(targets(), \"{}\", \"{}\");",
+            AXIS_NAMES[axis],
+            alignment.name()
+        ),
+    }
+}
+
+/// Spaces the bodies so the gaps between them along `axis` are all the same. The first
+/// and last body stay where they are.
+pub fn distribute(bodies: &[Rc<RefCell<Body>>], axis: usize) -> AlignmentAction {
+    let statement = format!("distribute(targets(), \"{}\");", AXIS_NAMES[axis]);
+    let mut bounded = with_bounds(bodies);
+    if bounded.len() < 3 {
+        return AlignmentAction {
+            moves: moves(Vec::new(), axis),
+            statement,
+        };
+    }
+    bounded.sort_by(|(_, a), (_, b)| {
+        Alignment::Center
+            .of(a, axis)
+            .total_cmp(&Alignment::Center.of(b, axis))
+    });
+    let first = &bounded[0].1;
+    let last = &bounded[bounded.len() - 1].1;
+    let widths: f32 = bounded
+        .iter()
+        .map(|(_, aabb)| aabb.max[axis] - aabb.min[axis])
+        .sum();
+    let gap = (last.max[axis] - first.min[axis] - widths) / (bounded.len() - 1) as f32;
+    AlignmentAction {
+        moves: snap_sorted(bounded, axis, gap),
+        statement,
+    }
+}
+
+/// Packs the bodies next to each other along `axis`, in the order they already are, with
+/// `gap` millimeters between neighbors. The first body stays where it is.
+pub fn snap_adjacent(bodies: &[Rc<RefCell<Body>>], axis: usize, gap: f32) -> AlignmentAction {
+    let mut bounded = with_bounds(bodies);
+    bounded.sort_by(|(_, a), (_, b)| a.min[axis].total_cmp(&b.min[axis]));
+    AlignmentAction {
+        moves: snap_sorted(bounded, axis, gap),
+        statement: format!(
+            "snap_adjacent(targets(), \"{}\", {});",
+            AXIS_NAMES[axis], gap
+        ),
+    }
+}
+
+fn snap_sorted(bounded: Vec<(Rc<RefCell<Body>>, AABB)>, axis: usize, gap: f32) -> CompoundAction {
+    let Some((_, first)) = bounded.first() else {
+        return moves(Vec::new(), axis);
+    };
+    let mut next_min = first.min[axis];
+    let mut distances = Vec::new();
+    for (body, aabb) in bounded {
+        distances.push((body, next_min - aabb.min[axis]));
+        next_min += aabb.max[axis] - aabb.min[axis] + gap;
+    }
+    moves(distances, axis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action_manager::ActionManager;
+    use crate::mesh::{Mesh, Vertex};
+
+    // A body spanning `width` along X from `x`
+    fn bar(x: f32, width: f32) -> Rc<RefCell<Body>> {
+        let mesh = Mesh {
+            vertices: vec![
+                Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, 0.0]),
+                Vertex::new([width, 1.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, 0.0]),
+            ],
+            ..Default::default()
+        };
+        let mut body = Body::new(mesh);
+        body.set_position(Vector3::new(x, 0.0, 0.0));
+        Rc::new(RefCell::new(body))
+    }
+
+    fn min_x(bodies: &[Rc<RefCell<Body>>]) -> Vec<f32> {
+        bodies
+            .iter()
+            .map(|b| b.borrow().world_aabb().unwrap().min.x)
+            .collect()
+    }
+
+    #[test]
+    fn test_align() {
+        let bodies = vec![bar(0.0, 2.0), bar(5.0, 4.0), bar(-3.0, 1.0)];
+        let mut manager = ActionManager::new();
+
+        manager.execute(Box::new(align(&bodies, 0, Alignment::Max)));
+        assert_eq!(min_x(&bodies), vec![7.0, 5.0, 8.0]);
+
+        manager.execute(Box::new(align(&bodies, 0, Alignment::Center)));
+        assert_eq!(min_x(&bodies), vec![6.0, 5.0, 6.5]);
+
+        manager.execute(Box::new(align(&bodies, 0, Alignment::Min)));
+        assert_eq!(min_x(&bodies), vec![5.0, 5.0, 5.0]);
+
+        // Each alignment is one undo step
+        manager.undo();
+        assert_eq!(min_x(&bodies), vec![6.0, 5.0, 6.5]);
+    }
+
+    #[test]
+    fn test_distribute() {
+        // Listed out of order, spanning 0 to 20 with 10 mm of bodies in between
+        let bodies = vec![bar(16.0, 4.0), bar(0.0, 2.0), bar(3.0, 4.0)];
+        let mut action = distribute(&bodies, 0);
+        action.execute();
+        assert_eq!(min_x(&bodies), vec![16.0, 0.0, 7.0]);
+
+        assert!(distribute(&bodies[..2], 0).is_empty());
+        assert_eq!(
+            action.script_statement().unwrap(),
+            "distribute(targets(), \"x\");"
+        );
+    }
+
+    #[test]
+    fn test_snap_adjacent() {
+        let bodies = vec![bar(20.0, 1.0), bar(-5.0, 2.0), bar(3.0, 4.0)];
+        let mut action = snap_adjacent(&bodies, 0, 0.5);
+        action.execute();
+        assert_eq!(min_x(&bodies), vec![2.0, -5.0, -2.5]);
+
+        // The leftmost body stays and isn't part of the action
+        assert_eq!(action.moves.actions.len(), 2);
+        assert_eq!(axis_from_name("Y"), Some(1));
+        assert_eq!(Alignment::from_name("center"), Some(Alignment::Center));
+    }
+}
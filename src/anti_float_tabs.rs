@@ -0,0 +1,324 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::Body;
+use crate::footprint::Footprint;
+use crate::mesh::Mesh;
+use crate::settings::AntiFloatTabSettings;
+use nalgebra::{Vector2, Vector3};
+use stl_io::Triangle;
+
+/// Bodies whose lowest point is this close to the plate stand on it, in millimeters
+const PLATE_TOLERANCE: f32 = 0.01;
+
+/// The scaffold bar is this many tabs wide, so it holds the parts instead of lifting off
+/// with them
+const SCAFFOLD_WIDTH_FACTOR: f32 = 2.0;
+
+/// The footprint of a body standing on the plate and small enough to come off the film
+/// with it, None for any other body
+pub fn small_part(body: &Body, settings: &AntiFloatTabSettings) -> Option<Footprint> {
+    let aabb = body.world_aabb()?;
+    let size = aabb.max - aabb.min;
+    (aabb.min.z.abs() <= PLATE_TOLERANCE
+        && size.x <= settings.max_part_size
+        && size.y <= settings.max_part_size)
+        .then(|| body.footprint())
+        .flatten()
+}
+
+/// Where tabs go between small parts on the plate, as an alternative to printing them on a
+/// raft. Tabs are low and narrow at the top so they break off in post-processing.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AntiFloatTabs {
+    /// Each tab from end to end on the plate, reaching into the parts it ties
+    pub tabs: Vec<[Vector2<f32>; 2]>,
+    /// The bar the parts are tied to, with a scaffold
+    pub scaffold: Option<[Vector2<f32>; 2]>,
+    /// Indices of the parts too far from every other one to be tied
+    pub untied: Vec<usize>,
+}
+
+impl AntiFloatTabs {
+    pub fn plan(parts: &[Footprint], settings: &AntiFloatTabSettings) -> Self {
+        if settings.scaffold {
+            Self::to_scaffold(parts, settings)
+        } else {
+            Self::to_each_other(parts, settings)
+        }
+    }
+
+    // The shortest tabs that tie the parts in reach of each other into one piece, a minimum
+    // spanning tree over the gaps between them
+    fn to_each_other(parts: &[Footprint], settings: &AntiFloatTabSettings) -> Self {
+        let mut gaps: Vec<(f32, usize, usize)> = (0..parts.len())
+            .flat_map(|i| (i + 1..parts.len()).map(move |j| (i, j)))
+            .map(|(i, j)| (parts[i].distance(&parts[j]), i, j))
+            .filter(|&(distance, _, _)| distance <= settings.max_span)
+            .collect();
+        gaps.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut group: Vec<usize> = (0..parts.len()).collect();
+        fn root(group: &mut [usize], mut i: usize) -> usize {
+            while group[i] != i {
+                group[i] = group[group[i]];
+                i = group[i];
+            }
+            i
+        }
+        let mut tied = vec![false; parts.len()];
+        let mut tabs = Vec::new();
+        for (distance, i, j) in gaps {
+            let (a, b) = (root(&mut group, i), root(&mut group, j));
+            if a == b {
+                continue;
+            }
+            group[a] = b;
+            tied[i] = true;
+            tied[j] = true;
+            // Touching parts already print as one piece
+            if distance > 0.0 {
+                let (from, to) = closest_points(&parts[i], &parts[j]);
+                tabs.push(reaching_in(from, to, settings.width / 2.0));
+            }
+        }
+        Self {
+            tabs,
+            scaffold: None,
+            untied: (0..parts.len()).filter(|&i| !tied[i]).collect(),
+        }
+    }
+
+    // A bar along the front of the parts, each part tied to it straight from its front
+    fn to_scaffold(parts: &[Footprint], settings: &AntiFloatTabSettings) -> Self {
+        let Some((min, max)) =
+            parts
+                .iter()
+                .map(Footprint::bounds)
+                .reduce(|(min, max), (other_min, other_max)| {
+                    (min.inf(&other_min), max.sup(&other_max))
+                })
+        else {
+            return Self::default();
+        };
+        let bar_y = min.y - settings.scaffold_gap;
+        let tabs = parts
+            .iter()
+            .map(|part| {
+                let front = part
+                    .hull()
+                    .iter()
+                    .copied()
+                    .min_by(|a, b| a.y.total_cmp(&b.y))
+                    .unwrap();
+                reaching_in(Vector2::new(front.x, bar_y), front, settings.width / 2.0)
+            })
+            .collect();
+        let margin = settings.width * SCAFFOLD_WIDTH_FACTOR;
+        Self {
+            tabs,
+            scaffold: Some([
+                Vector2::new(min.x - margin, bar_y),
+                Vector2::new(max.x + margin, bar_y),
+            ]),
+            untied: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty() && self.scaffold.is_none()
+    }
+
+    /// The tabs and the scaffold as one body, removed like any other once it isn't wanted
+    pub fn to_body(&self, settings: &AntiFloatTabSettings) -> Body {
+        let mut triangles = Vec::new();
+        for &[from, to] in &self.tabs {
+            triangles.extend(bar(from, to, settings.width, settings));
+        }
+        if let Some([from, to]) = self.scaffold {
+            let width = settings.width * SCAFFOLD_WIDTH_FACTOR;
+            triangles.extend(bar(from, to, width, settings));
+        }
+        let mut body = Body::new(Mesh::from_triangles(&triangles));
+        body.name = "Anti-float tabs".to_string();
+        body.selected = false;
+        body
+    }
+}
+
+/// The segment from `from` to `to` lengthened by `depth` at both ends, so the tab fuses with
+/// the parts instead of just touching them
+fn reaching_in(from: Vector2<f32>, to: Vector2<f32>, depth: f32) -> [Vector2<f32>; 2] {
+    let direction = (to - from).try_normalize(0.0).unwrap_or_default();
+    [from - direction * depth, to + direction * depth]
+}
+
+/// The closest points of two hulls that don't touch, on the first and on the second one
+fn closest_points(a: &Footprint, b: &Footprint) -> (Vector2<f32>, Vector2<f32>) {
+    let to_edges = |points: &Footprint, edges: &Footprint| {
+        let hull = edges.hull();
+        points
+            .hull()
+            .iter()
+            .flat_map(|&p| {
+                (0..hull.len()).map(move |i| {
+                    let q = closest_on_segment(p, hull[i], hull[(i + 1) % hull.len()]);
+                    ((q - p).norm_squared(), p, q)
+                })
+            })
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+            .unwrap()
+    };
+    let (from_a, p, q) = to_edges(a, b);
+    let (from_b, q_b, p_a) = to_edges(b, a);
+    if from_a <= from_b {
+        (p, q)
+    } else {
+        (p_a, q_b)
+    }
+}
+
+fn closest_on_segment(p: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>) -> Vector2<f32> {
+    let ab = b - a;
+    let t = if ab.norm_squared() > 0.0 {
+        ((p - a).dot(&ab) / ab.norm_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    a + ab * t
+}
+
+/// A bar on the plate from `from` to `to`, `width` wide at the bottom and chamfered along
+/// its top edges
+fn bar(
+    from: Vector2<f32>,
+    to: Vector2<f32>,
+    width: f32,
+    settings: &AntiFloatTabSettings,
+) -> Vec<Triangle> {
+    let Some(direction) = (to - from).try_normalize(0.0) else {
+        return Vec::new();
+    };
+    let side = Vector2::new(-direction.y, direction.x);
+    let bottom = width / 2.0;
+    // Never chamfered to a knife edge, which slices into nothing
+    let top = (bottom - settings.chamfer).max(bottom * 0.2);
+    let corner = |end: Vector2<f32>, offset: f32, z: f32| {
+        let p = end + side * offset;
+        Vector3::new(p.x, p.y, z)
+    };
+    let h = settings.height;
+    let [b0, b1, b2, b3] = [
+        corner(from, -bottom, 0.0),
+        corner(to, -bottom, 0.0),
+        corner(to, bottom, 0.0),
+        corner(from, bottom, 0.0),
+    ];
+    let [t0, t1, t2, t3] = [
+        corner(from, -top, h),
+        corner(to, -top, h),
+        corner(to, top, h),
+        corner(from, top, h),
+    ];
+    let center = (b0 + b2 + t0 + t2) / 4.0;
+    [
+        [b0, b1, b2, b3],
+        [t0, t1, t2, t3],
+        [b0, b1, t1, t0],
+        [b3, b2, t2, t3],
+        [b0, b3, t3, t0],
+        [b1, b2, t2, t1],
+    ]
+    .into_iter()
+    .flat_map(|[a, b, c, d]| [outward([a, b, c], center), outward([a, c, d], center)])
+    .collect()
+}
+
+/// A triangle wound so its normal points away from the inside of the convex solid it bounds
+fn outward(corners: [Vector3<f32>; 3], inside: Vector3<f32>) -> Triangle {
+    let [a, b, c] = corners;
+    let mut normal = (b - a)
+        .cross(&(c - a))
+        .try_normalize(0.0)
+        .unwrap_or_default();
+    let vertices = if normal.dot(&(a - inside)) < 0.0 {
+        normal = -normal;
+        [a, c, b]
+    } else {
+        [a, b, c]
+    };
+    Triangle {
+        normal: normal.into(),
+        vertices: vertices.map(Into::into),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32, y: f32, size: f32) -> Footprint {
+        Footprint::from_points([
+            Vector2::new(x, y),
+            Vector2::new(x + size, y),
+            Vector2::new(x + size, y + size),
+            Vector2::new(x, y + size),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tabs_tie_neighbors() {
+        let settings = AntiFloatTabSettings::default();
+        let parts = [
+            square(0.0, 0.0, 4.0),
+            square(6.0, 0.0, 4.0),
+            square(6.0, 7.0, 4.0),
+            square(60.0, 60.0, 4.0),
+        ];
+        let plan = AntiFloatTabs::plan(&parts, &settings);
+
+        // Two tabs tie the first three together, the shortest one between the first two
+        assert_eq!(plan.tabs.len(), 2);
+        assert_eq!(plan.untied, [3]);
+        let [from, to] = plan.tabs[0];
+        assert!((from.x - 3.5).abs() < 1e-5 && (to.x - 6.5).abs() < 1e-5);
+
+        let body = plan.to_body(&settings);
+        let aabb = body.world_aabb().unwrap();
+        assert!(aabb.min.z.abs() < 1e-6);
+        assert!((aabb.max.z - settings.height).abs() < 1e-6);
+        for triangle in body.mesh.get_triangles_for_slicing() {
+            let [a, b, c] = triangle.vertices.map(Vector3::from);
+            let center = (a + b + c) / 3.0;
+            // Every face points out of the bar it belongs to
+            let tab = if center.y < 2.0 {
+                plan.tabs[0]
+            } else {
+                plan.tabs[1]
+            };
+            let middle = (tab[0] + tab[1]) / 2.0;
+            let inside = Vector3::new(middle.x, middle.y, settings.height / 2.0);
+            assert!(Vector3::from(triangle.normal).dot(&(center - inside)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_scaffold() {
+        let settings = AntiFloatTabSettings {
+            scaffold: true,
+            ..Default::default()
+        };
+        let parts = [square(0.0, 0.0, 4.0), square(40.0, 2.0, 4.0)];
+        let plan = AntiFloatTabs::plan(&parts, &settings);
+
+        let [start, end] = plan.scaffold.unwrap();
+        assert_eq!(start.y, -settings.scaffold_gap);
+        assert!(start.x < 0.0 && end.x > 44.0);
+        assert_eq!(plan.tabs.len(), 2);
+        assert!(plan.untied.is_empty());
+        for [from, to] in plan.tabs {
+            assert!(from.y < start.y && to.y > 0.0);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::mesh::Vertex;
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+/// Side of the gizmo as a fraction of the shorter side of the viewport
+const GIZMO_FRACTION: f32 = 0.18;
+
+/// The gizmo shows unit axes, this leaves room around the tips
+const GIZMO_EXTENT: f32 = 1.4;
+
+/// Clicks closer to a tip than this fraction of the gizmo side pick that axis
+const TIP_PICK_RADIUS: f32 = 0.12;
+
+const SHAFT_THICKNESS: f32 = 0.06;
+const TIP_SIZE: f32 = 0.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    pub fn unit(&self) -> Vector3<f32> {
+        match self {
+            Axis::X => Vector3::x(),
+            Axis::Y => Vector3::y(),
+            Axis::Z => Vector3::z(),
+        }
+    }
+
+    /// The colors CAD applications use, X red, Y green and Z blue
+    pub fn color(&self) -> Vector3<f32> {
+        match self {
+            Axis::X => Vector3::new(1.0, 0.1, 0.1),
+            Axis::Y => Vector3::new(0.1, 1.0, 0.1),
+            Axis::Z => Vector3::new(0.1, 0.3, 1.0),
+        }
+    }
+}
+
+/// Where the gizmo sits in a viewport of the given size, as (x, y, side) with the
+/// origin at the top left. It is sized relative to the viewport, so the same rectangle
+/// is found in logical and in physical pixels.
+pub fn gizmo_rect(width: f32, height: f32) -> (f32, f32, f32) {
+    let side = width.min(height) * GIZMO_FRACTION;
+    (0.0, height - side, side)
+}
+
+/// Projection of the gizmo: the rotation of the camera without its position, so the
+/// axes turn with the scene but always stay in the corner at the same size
+pub fn view_proj(view: &Matrix4<f32>) -> Matrix4<f32> {
+    let mut rotation = *view;
+    rotation.fixed_view_mut::<3, 1>(0, 3).fill(0.0);
+    let projection = Matrix4::new_orthographic(
+        -GIZMO_EXTENT,
+        GIZMO_EXTENT,
+        -GIZMO_EXTENT,
+        GIZMO_EXTENT,
+        -GIZMO_EXTENT,
+        GIZMO_EXTENT,
+    );
+    projection * rotation
+}
+
+/// The axis whose tip is under the pointer, if the pointer is on the gizmo
+pub fn hit_test(view: &Matrix4<f32>, x: f32, y: f32, width: f32, height: f32) -> Option<Axis> {
+    let (left, top, side) = gizmo_rect(width, height);
+    let view_proj = view_proj(view);
+    Axis::ALL
+        .into_iter()
+        .map(|axis| {
+            let tip = view_proj * Vector4::new(axis.unit().x, axis.unit().y, axis.unit().z, 1.0);
+            // The rendered image is shown with its first row at the top, so clip space y
+            // grows downwards on screen like the pointer coordinates
+            let tip_x = left + (tip.x + 1.0) / 2.0 * side;
+            let tip_y = top + (tip.y + 1.0) / 2.0 * side;
+            (axis, ((tip_x - x).powi(2) + (tip_y - y).powi(2)).sqrt())
+        })
+        .filter(|(_, distance)| *distance <= side * TIP_PICK_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(axis, _)| axis)
+}
+
+/// A unit arrow along the axis: a thin shaft with a block at the tip to click on
+pub fn axis_vertices(axis: Axis) -> Vec<Vertex> {
+    let along = axis.unit();
+    let half_shaft = Vector3::repeat(SHAFT_THICKNESS / 2.0);
+    let half_tip = Vector3::repeat(TIP_SIZE / 2.0);
+    let mut vertices = cuboid(-half_shaft, along + half_shaft - along * TIP_SIZE);
+    vertices.extend(cuboid(along - half_tip, along + half_tip));
+    vertices
+}
+
+// Two triangles per face, wound counter-clockwise seen from outside
+fn cuboid(min: Vector3<f32>, max: Vector3<f32>) -> Vec<Vertex> {
+    let corner = |x: bool, y: bool, z: bool| {
+        [
+            if x { max.x } else { min.x },
+            if y { max.y } else { min.y },
+            if z { max.z } else { min.z },
+        ]
+    };
+    let faces: [([f32; 3], [[bool; 3]; 4]); 6] = [
+        (
+            [1.0, 0.0, 0.0],
+            [
+                [true, false, false],
+                [true, true, false],
+                [true, true, true],
+                [true, false, true],
+            ],
+        ),
+        (
+            [-1.0, 0.0, 0.0],
+            [
+                [false, false, false],
+                [false, false, true],
+                [false, true, true],
+                [false, true, false],
+            ],
+        ),
+        (
+            [0.0, 1.0, 0.0],
+            [
+                [false, true, false],
+                [false, true, true],
+                [true, true, true],
+                [true, true, false],
+            ],
+        ),
+        (
+            [0.0, -1.0, 0.0],
+            [
+                [false, false, false],
+                [true, false, false],
+                [true, false, true],
+                [false, false, true],
+            ],
+        ),
+        (
+            [0.0, 0.0, 1.0],
+            [
+                [false, false, true],
+                [true, false, true],
+                [true, true, true],
+                [false, true, true],
+            ],
+        ),
+        (
+            [0.0, 0.0, -1.0],
+            [
+                [false, false, false],
+                [false, true, false],
+                [true, true, false],
+                [true, false, false],
+            ],
+        ),
+    ];
+    let barycentric = [1.0, 1.0, 1.0];
+    let mut vertices = Vec::with_capacity(36);
+    for (normal, quad) in faces {
+        for i in [0, 1, 2, 0, 2, 3] {
+            let [x, y, z] = quad[i];
+            vertices.push(Vertex::new(corner(x, y, z), normal, barycentric));
+        }
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    #[test]
+    fn test_hit_test_finds_tips() {
+        let camera = Camera::new(16.0 / 9.0);
+        let view = camera.view_matrix();
+        let (width, height) = (1600.0, 900.0);
+        let (left, top, side) = gizmo_rect(width, height);
+        assert_eq!((left, top + side), (0.0, height));
+
+        for axis in Axis::ALL {
+            let unit = axis.unit();
+            let tip = view_proj(&view) * Vector4::new(unit.x, unit.y, unit.z, 1.0);
+            let x = left + (tip.x + 1.0) / 2.0 * side;
+            let y = top + (tip.y + 1.0) / 2.0 * side;
+            assert_eq!(hit_test(&view, x, y, width, height), Some(axis));
+        }
+        // The origin is where all the shafts meet, not on any tip
+        let center = (left + side / 2.0, top + side / 2.0);
+        assert_eq!(hit_test(&view, center.0, center.1, width, height), None);
+        assert_eq!(hit_test(&view, width / 2.0, height / 2.0, width, height), None);
+    }
+
+    #[test]
+    fn test_gizmo_ignores_camera_position() {
+        let mut camera = Camera::new(1.0);
+        let view = camera.view_matrix();
+        camera.pan(40.0, -25.0);
+        camera.zoom(300.0);
+
+        let moved = camera.view_matrix();
+
+        assert!((view_proj(&view) - view_proj(&moved)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_axis_vertices() {
+        let vertices = axis_vertices(Axis::Y);
+
+        assert_eq!(vertices.len(), 72);
+        let furthest = vertices
+            .iter()
+            .map(|v| v.position[1])
+            .fold(f32::MIN, f32::max);
+        assert!((furthest - (1.0 + TIP_SIZE / 2.0)).abs() < 1e-6);
+        // Every face points away from the center of its block
+        for face in vertices.chunks(6) {
+            let normal = Vector3::from(face[0].normal);
+            let a = Vector3::from(face[0].position);
+            let b = Vector3::from(face[1].position);
+            let c = Vector3::from(face[2].position);
+            assert!((b - a).cross(&(c - a)).dot(&normal) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use image::{ImageBuffer, Luma};
+use imageproc::filter::sharpen_gaussian;
+use imageproc::morphology::{grayscale_erode, Mask};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// Counteracts light bleeding from lit LCD pixels into their neighbours, which cures
+/// resin past the modeled edges and makes holes and gaps come out smaller than designed.
+/// Tuned per printer in the printer profile, and per resin in the resin profile, which
+/// replaces the printer's:
+///
+/// ```toml
+/// [bleed_compensation]
+/// erode_pixels = 1
+/// sharpen_amount = 0.5
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BleedCompensation {
+    /// Radius in pixels by which lit areas are shrunk
+    #[serde(default)]
+    pub erode_pixels: u8,
+    /// Strength of the unsharp mask, 0 disables it
+    #[serde(default)]
+    pub sharpen_amount: f32,
+    /// Standard deviation in pixels of the blur the unsharp mask subtracts
+    #[serde(default = "default_sharpen_sigma")]
+    pub sharpen_sigma: f32,
+}
+
+fn default_sharpen_sigma() -> f32 {
+    1.0
+}
+
+impl BleedCompensation {
+    pub fn is_noop(&self) -> bool {
+        self.erode_pixels == 0 && self.sharpen_amount <= 0.0
+    }
+
+    pub fn apply(&self, image: &ImageBuffer<Luma<u8>, Vec<u8>>) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let mut output = image.clone();
+        if self.erode_pixels > 0 {
+            output = grayscale_erode(&output, &Mask::disk(self.erode_pixels));
+        }
+        if self.sharpen_amount > 0.0 {
+            output = sharpen_gaussian(&output, self.sharpen_sigma, self.sharpen_amount);
+        }
+        output
+    }
+
+    /// Applies the compensation to every layer, returning what it changed on each. Nothing
+    /// is returned when the compensation does nothing.
+    pub fn apply_to_all(&self, images: &mut [Layer]) -> Vec<BleedChanges> {
+        if self.is_noop() {
+            return Vec::new();
+        }
+        images
+            .par_iter_mut()
+            .map(|image| {
+                let compensated = self.apply(image);
+                let changes = BleedChanges::between(image, &compensated);
+                *image = compensated;
+                changes
+            })
+            .collect()
+    }
+}
+
+/// The pixels bleed compensation changed on one layer, by index, with their values before
+/// and after it. Only the edges of parts change, so this stays small next to the layer and
+/// is kept with the cached layers to compare them with and without the compensation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BleedChanges(Vec<(u32, u8, u8)>);
+
+impl BleedChanges {
+    pub fn between(before: &Layer, after: &Layer) -> Self {
+        Self(
+            before
+                .as_raw()
+                .iter()
+                .zip(after.as_raw())
+                .enumerate()
+                .filter(|(_, (before, after))| before != after)
+                .map(|(index, (&before, &after))| (index as u32, before, after))
+                .collect(),
+        )
+    }
+
+    /// The value of the pixel at `index` before the compensation, None if it didn't change
+    pub fn before(&self, index: u32) -> Option<u8> {
+        self.0
+            .binary_search_by_key(&index, |&(changed, _, _)| changed)
+            .ok()
+            .map(|found| self.0[found].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A lit 20x20 square with a 4x4 hole in the middle
+    fn square_with_hole() -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(30, 30, |x, y| {
+            let in_square = (5..25).contains(&x) && (5..25).contains(&y);
+            let in_hole = (13..17).contains(&x) && (13..17).contains(&y);
+            Luma([if in_square && !in_hole { 255 } else { 0 }])
+        })
+    }
+
+    fn lit_pixels(image: &ImageBuffer<Luma<u8>, Vec<u8>>) -> usize {
+        image.pixels().filter(|p| p[0] > 127).count()
+    }
+
+    #[test]
+    fn test_erosion_grows_holes() {
+        let compensation = BleedCompensation {
+            erode_pixels: 1,
+            sharpen_amount: 0.0,
+            sharpen_sigma: 1.0,
+        };
+        let before = square_with_hole();
+        let after = compensation.apply(&before);
+
+        assert!(lit_pixels(&after) < lit_pixels(&before));
+        // The hole grew by one pixel on every side
+        assert_eq!(after.get_pixel(12, 15)[0], 0);
+        assert_eq!(after.get_pixel(17, 15)[0], 0);
+        assert_eq!(after.get_pixel(10, 10)[0], 255);
+    }
+
+    #[test]
+    fn test_noop_leaves_images_untouched() {
+        let compensation: BleedCompensation = toml::from_str("").unwrap();
+        assert!(compensation.is_noop());
+        assert_eq!(compensation.sharpen_sigma, 1.0);
+
+        let mut images = vec![square_with_hole()];
+        assert!(compensation.apply_to_all(&mut images).is_empty());
+        assert_eq!(images[0], square_with_hole());
+    }
+
+    #[test]
+    fn test_changes_keep_the_uncompensated_pixels() {
+        let compensation = BleedCompensation {
+            erode_pixels: 1,
+            sharpen_amount: 0.0,
+            sharpen_sigma: 1.0,
+        };
+        let mut images = vec![square_with_hole()];
+        let changes = compensation.apply_to_all(&mut images);
+        assert_eq!(changes.len(), 1);
+        assert_ne!(changes[0], BleedChanges::default());
+
+        // The rim of the square was eroded, the middle of its wall and the outside weren't
+        let at = |x: u32, y: u32| y * 30 + x;
+        assert_eq!(images[0].get_pixel(5, 10)[0], 0);
+        assert_eq!(changes[0].before(at(5, 10)), Some(255));
+        assert_eq!(changes[0].before(at(12, 15)), Some(255));
+        assert_eq!(changes[0].before(at(8, 10)), None);
+        assert_eq!(changes[0].before(at(0, 0)), None);
+    }
+
+    #[test]
+    fn test_printer_profile_round_trip() {
+        use crate::printer::Printer;
+
+        let printer = Printer {
+            bleed_compensation: Some(BleedCompensation {
+                erode_pixels: 2,
+                sharpen_amount: 0.5,
+                sharpen_sigma: 1.5,
+            }),
+            ..Printer::default()
+        };
+        let content = toml::to_string(&printer).unwrap();
+        let loaded: Printer = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.bleed_compensation, printer.bleed_compensation);
+    }
+
+    #[test]
+    fn test_resin_replaces_the_printer_compensation() {
+        use crate::resin::Resin;
+        use crate::slice_parameters::SliceParameters;
+
+        let printer_compensation = BleedCompensation {
+            erode_pixels: 1,
+            sharpen_amount: 0.0,
+            sharpen_sigma: 1.0,
+        };
+        let mut parameters = SliceParameters::default();
+        parameters.printer.bleed_compensation = Some(printer_compensation.clone());
+        assert_eq!(parameters.bleed_compensation(), Some(&printer_compensation));
+
+        let resin_compensation = BleedCompensation {
+            erode_pixels: 2,
+            sharpen_amount: 0.5,
+            sharpen_sigma: 1.5,
+        };
+        parameters.resin.bleed_compensation = Some(resin_compensation.clone());
+        assert_eq!(parameters.bleed_compensation(), Some(&resin_compensation));
+
+        let content = toml::to_string(&parameters.resin).unwrap();
+        assert!(content.contains("[bleed_compensation]"));
+        let loaded: Resin = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.bleed_compensation, Some(resin_compensation));
+        // Resins without it leave the printer's in place
+        assert!(!toml::to_string(&Resin::default())
+            .unwrap()
+            .contains("bleed_compensation"));
+    }
+}
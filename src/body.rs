@@ -2,24 +2,66 @@
 // Distributed under the GNU Affero General Public License v3.0 or later.
 // See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
 
+use std::cell::RefCell;
 use std::ffi::OsStr;
+use std::io;
 use std::path::Path;
 
+use crate::footprint::{Footprint, FootprintCache};
+use crate::geometry;
+use crate::mesh_cache::MeshCache;
 use crate::stl_processor::StlProcessorTrait;
 use crate::{material::Material, mesh::Mesh};
 use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector3};
 use slint::SharedString;
 use uuid::Uuid;
+
+/// Scale factors further apart than this fraction of the largest one count as uneven
+const UNEVEN_SCALE_TOLERANCE: f32 = 1e-3;
+
 #[allow(dead_code)]
 #[derive(Default, Clone)]
 pub struct AABB {
-    min: Vector3<f32>,
-    max: Vector3<f32>,
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+/// What the world bounds of a body depend on besides its mesh: its position, rotation and
+/// scale
+type BoundsKey = (Vector3<f32>, Quaternion<f32>, Vector3<f32>);
+
+/// A body's bounds after its transform. Recomputed when the transform changes, which is
+/// set directly in too many places to invalidate it from setters. `Body::set_mesh` clears
+/// it.
+#[derive(Clone, Default)]
+pub struct BoundsCache(RefCell<Option<(BoundsKey, Option<AABB>)>>);
+
+impl BoundsCache {
+    pub fn get(&self, key: BoundsKey, compute: impl FnOnce() -> Option<AABB>) -> Option<AABB> {
+        let mut cached = self.0.borrow_mut();
+        match &*cached {
+            Some((cached_key, bounds)) if *cached_key == key => bounds.clone(),
+            _ => {
+                let bounds = compute();
+                *cached = Some((key, bounds.clone()));
+                bounds
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().take();
+    }
 }
 
 impl AABB {
     #[allow(dead_code)]
     fn intersect_ray(&self, ray_origin: Vector3<f32>, ray_dir: Vector3<f32>) -> bool {
+        self.ray_distance(ray_origin, ray_dir).is_some()
+    }
+
+    /// How far along the ray it enters the box, 0 when it starts inside
+    pub fn ray_distance(&self, ray_origin: Vector3<f32>, ray_dir: Vector3<f32>) -> Option<f32> {
         let inv_dir = Vector3::new(1.0 / ray_dir.x, 1.0 / ray_dir.y, 1.0 / ray_dir.z);
 
         let t1 = (self.min.x - ray_origin.x) * inv_dir.x;
@@ -32,10 +74,10 @@ impl AABB {
         let tmin = t1.min(t2).max(t3.min(t4)).max(t5.min(t6));
         let tmax = t1.max(t2).min(t3.max(t4)).min(t5.max(t6));
 
-        tmax >= tmin.max(0.0)
+        (tmax >= tmin.max(0.0)).then(|| tmin.max(0.0))
     }
 
-    fn from_vertices(vertices: &Vec<crate::mesh::Vertex>) -> Self {
+    pub fn from_vertices(vertices: &[crate::mesh::Vertex]) -> Self {
         // Initialize min and max with the first vertex
         let mut min = vertices[0];
         let mut max = vertices[0];
@@ -58,21 +100,51 @@ impl AABB {
     }
 }
 
+/// How a body combines with the bodies it overlaps in the sliced layers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SliceRole {
+    /// Cured wherever it is solid, overlaps with other bodies are merged into one part
+    #[default]
+    Merge,
+    /// Punches its volume out of the merged bodies and is never cured itself
+    Subtract,
+}
+
+impl SliceRole {
+    pub fn toggled(self) -> Self {
+        match self {
+            SliceRole::Merge => SliceRole::Subtract,
+            SliceRole::Subtract => SliceRole::Merge,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Body {
     pub position: Vector3<f32>,
     pub rotation: Quaternion<f32>,
     pub scale: Vector3<f32>,
+    /// Replaced through `set_mesh`, which the cached bounds and footprint follow
     pub mesh: Mesh,
     pub enabled: bool,
     pub selected: bool,
     pub name: String,
     pub visible: bool,
     pub uuid: Uuid,
-    pub aabb: AABB,
     pub material: Material,
     pub display_in_ui_list: bool,
     pub selectable: bool,
+    pub slice_role: SliceRole,
+    /// Name of the parameter snapshot the body is printed with on mixed-material plates,
+    /// None prints it with every profile
+    pub print_profile: Option<String>,
+    /// Name of the support style out of the support library its supports are topped with,
+    /// None for plain pillars
+    pub support_style: Option<String>,
+    /// Set while the body is dragged onto another body's footprint, drawn in red
+    pub overlapping: bool,
+    pub footprint_cache: FootprintCache,
+    pub bounds_cache: BoundsCache,
 }
 
 impl Default for Body {
@@ -87,10 +159,15 @@ impl Default for Body {
             name: "".to_string(),
             visible: true,
             uuid: Uuid::new_v4(),
-            aabb: AABB::default(),
             material: Material::default_resin(),
             display_in_ui_list: true,
             selectable: true,
+            slice_role: SliceRole::default(),
+            print_profile: None,
+            support_style: None,
+            overlapping: false,
+            footprint_cache: FootprintCache::default(),
+            bounds_cache: BoundsCache::default(),
         }
     }
 }
@@ -120,7 +197,24 @@ impl Body {
     pub fn new_from_stl<P: AsRef<OsStr>, Processor: StlProcessorTrait>(
         filename: P,
         processor: &Processor,
-    ) -> Self {
+    ) -> io::Result<Self> {
+        let mut mesh = Mesh::default();
+        mesh.import_stl(&filename, processor)?;
+        Ok(Self::new_from_imported_mesh(filename, mesh))
+    }
+
+    /// Same as `new_from_stl` but goes through the on-disk mesh cache
+    pub fn new_from_stl_cached<P: AsRef<OsStr>, Processor: StlProcessorTrait>(
+        filename: P,
+        processor: &Processor,
+        cache: &MeshCache,
+    ) -> io::Result<Self> {
+        let mut mesh = Mesh::default();
+        mesh.import_stl_cached(&filename, processor, cache)?;
+        Ok(Self::new_from_imported_mesh(filename, mesh))
+    }
+
+    fn new_from_imported_mesh<P: AsRef<OsStr>>(filename: P, mesh: Mesh) -> Self {
         let mut body = Body::default();
         let path = Path::new(filename.as_ref());
         body.name = path
@@ -128,9 +222,9 @@ impl Body {
             .unwrap_or_default()
             .to_string_lossy()
             .into_owned();
-        body.mesh.import_stl(filename, processor);
-        body.aabb = AABB::from_vertices(&body.mesh.vertices);
-        body.translate(Vector3::new(0.0, 0.0, body.aabb.min.z * -1.0));
+        let aabb = AABB::from_vertices(&mesh.vertices);
+        body.mesh = mesh;
+        body.translate(Vector3::new(0.0, 0.0, aabb.min.z * -1.0));
         body
     }
 
@@ -147,6 +241,68 @@ impl Body {
         model *= Matrix4::new_nonuniform_scaling(&self.scale);
         model
     }
+
+    /// Replaces the mesh, e.g. with its hollowed one, and forgets the bounds and footprint
+    /// of the one before
+    pub fn set_mesh(&mut self, mesh: Mesh) {
+        self.mesh = mesh;
+        self.bounds_cache.clear();
+        self.footprint_cache.clear();
+    }
+
+    /// Bounds of the mesh after its scale, rotation and position, None without vertices.
+    /// Cached until the transform or the mesh change.
+    pub fn world_aabb(&self) -> Option<AABB> {
+        let key = (self.position, self.rotation, self.scale);
+        self.bounds_cache.get(key, || {
+            let model = self.get_model_matrix();
+            self.mesh
+                .vertices
+                .iter()
+                .map(|v| model.transform_point(&v.position.into()).coords)
+                .fold(None, |aabb: Option<AABB>, p| {
+                    Some(match aabb {
+                        Some(aabb) => AABB {
+                            min: aabb.min.inf(&p),
+                            max: aabb.max.sup(&p),
+                        },
+                        None => AABB { min: p, max: p },
+                    })
+                })
+        })
+    }
+
+    /// How far along the ray it first meets the surface of the mesh, after its scale,
+    /// rotation and position. Exact where `AABB::ray_distance` only finds the bounds.
+    pub fn ray_hit(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> Option<f32> {
+        // The ray is brought into the mesh instead, which keeps distances along it
+        let inverse = self.get_model_matrix().try_inverse()?;
+        let origin = inverse.transform_point(&origin.into()).coords;
+        let direction = inverse.transform_vector(&direction);
+        self.mesh
+            .indices
+            .chunks_exact(3)
+            .filter_map(|face| {
+                let corners =
+                    [0, 1, 2].map(|i| Vector3::from(self.mesh.vertices[face[i] as usize].position));
+                geometry::ray_triangle_distance(origin, direction, corners)
+            })
+            .min_by(f32::total_cmp)
+    }
+
+    /// Convex hull of the body seen from above, after its scale, rotation and position,
+    /// None without vertices
+    pub fn footprint(&self) -> Option<Footprint> {
+        let key = (self.rotation, self.scale);
+        let local = self.footprint_cache.get(key, || {
+            let model = self.get_model_matrix();
+            Footprint::from_points(self.mesh.vertices.iter().map(|v| {
+                (model.transform_point(&v.position.into()).coords - self.position).xy()
+            }))
+        })?;
+        Some(local.translated(self.position.xy()))
+    }
+
     #[allow(dead_code)]
     pub fn translate(&mut self, val: Vector3<f32>) {
         self.position += val;
@@ -175,6 +331,19 @@ impl Body {
     pub fn set_scale(&mut self, scale: Vector3<f32>) {
         self.scale = scale;
     }
+
+    /// Whether the axes are scaled by different factors, which turns round threads and
+    /// fits oval. Mirroring an axis doesn't count.
+    pub fn has_uneven_scale(&self) -> bool {
+        let scale = self.scale.abs();
+        scale.max() - scale.min() > UNEVEN_SCALE_TOLERANCE * scale.max()
+    }
+
+    /// The same factor on every axis, keeping the volume and the mirrored axes
+    pub fn uniform_scale(&self) -> Vector3<f32> {
+        let factor = self.scale.abs().product().cbrt();
+        self.scale.map(|axis| factor.copysign(axis))
+    }
     pub fn euler_to_quaternion(euler: Vector3<f32>) -> Quaternion<f32> {
         // Convert Euler angles (in degrees) to radians
         // convert to f64 for more accuracy during calculations, hopefully
@@ -236,7 +405,7 @@ mod tests {
     use crate::mesh::Vertex;
     use crate::stl_processor::StlProcessorTrait;
     use approx::relative_eq;
-    use nalgebra::{Matrix4, UnitQuaternion, Vector3};
+    use nalgebra::{Matrix4, UnitQuaternion, Vector2, Vector3};
     use stl_io::Triangle;
 
     const EPSILON: f32 = 1e-4;
@@ -253,6 +422,15 @@ mod tests {
         }
     }
 
+    // Reads a valid STL file without any facets
+    struct EmptyStlProcessor;
+
+    impl StlProcessorTrait for EmptyStlProcessor {
+        fn read_stl(&self, _filename: &OsStr) -> Result<Vec<Triangle>, std::io::Error> {
+            Ok(Vec::new())
+        }
+    }
+
     // Helper function to create a triangle
     fn create_triangle(v0: [f32; 3], v1: [f32; 3], v2: [f32; 3]) -> Triangle {
         Triangle {
@@ -290,13 +468,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_new_from_empty_stl() {
+        let result = Body::new_from_stl("empty.stl", &EmptyStlProcessor);
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(std::io::ErrorKind::InvalidData)
+        );
+    }
+
     #[test]
     fn test_new_from_stl() {
         // Arrange: Create a mock processor
         let mock_processor = MockStlProcessor;
 
         // Act: Create Body from STL using mock processor
-        let body = Body::new_from_stl("dummy_filename.stl", &mock_processor);
+        let body = Body::new_from_stl("dummy_filename.stl", &mock_processor).unwrap();
 
         // Additionally, check that vertices and indices are generated correctly
         let expected_vertices = vec![
@@ -386,10 +573,15 @@ mod tests {
             name: "".to_string(),
             visible: true,
             uuid: Uuid::new_v4(),
-            aabb: AABB::default(),
             material: Material::default_resin(),
             display_in_ui_list: true,
             selectable: true,
+            slice_role: SliceRole::default(),
+            print_profile: None,
+            support_style: None,
+            overlapping: false,
+            footprint_cache: FootprintCache::default(),
+            bounds_cache: BoundsCache::default(),
         };
 
         // Act: Compute the model matrix
@@ -617,4 +809,105 @@ mod tests {
         assert!((euler.y - expected_euler.y).abs() < 1e-1);
         assert!((euler.z - expected_euler.z).abs() < 1e-1);
     }
+
+    #[test]
+    fn test_world_aabb() {
+        let mut body = Body::new(Mesh::default());
+        assert!(body.world_aabb().is_none());
+
+        let mut mesh = Mesh {
+            vertices: vec![
+                Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, 0.0]),
+                Vertex::new([2.0, 1.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, 0.0]),
+            ],
+            ..Default::default()
+        };
+        body.set_mesh(mesh.clone());
+        body.set_scale(Vector3::new(2.0, 1.0, 1.0));
+        body.set_rotation(Vector3::new(0.0, 0.0, 90.0));
+        body.set_position(Vector3::new(10.0, 0.0, 5.0));
+
+        // Scaled to 4 x 1, turned to 1 x 4, then moved
+        let aabb = body.world_aabb().unwrap();
+        assert!((aabb.min - Vector3::new(9.0, 0.0, 5.0)).norm() < 1e-4);
+        assert!((aabb.max - Vector3::new(10.0, 4.0, 6.0)).norm() < 1e-4);
+
+        // The cached bounds follow fields set directly as well as the setters
+        body.position.z = 0.0;
+        assert!((body.world_aabb().unwrap().min.z).abs() < 1e-4);
+        body.set_scale(Vector3::new(1.0, 1.0, 1.0));
+        assert!((body.world_aabb().unwrap().max.y - 2.0).abs() < 1e-4);
+
+        // A mesh with as many vertices in other places is measured again
+        mesh.vertices[1].position = [4.0, 1.0, 1.0];
+        body.set_mesh(mesh);
+        assert!((body.world_aabb().unwrap().max.y - 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_footprint_follows_transform() {
+        let mut body = Body::new(Mesh::default());
+        assert!(body.footprint().is_none());
+
+        // A right triangle with its tip raised, seen from above the tip is inside it
+        let mesh = Mesh {
+            vertices: [
+                [0.0, 0.0, 0.0],
+                [4.0, 0.0, 0.0],
+                [0.0, 2.0, 0.0],
+                [1.0, 0.5, 3.0],
+            ]
+            .into_iter()
+            .map(|p| Vertex::new(p, [0.0, 0.0, 1.0], [0.0, 0.0, 0.0]))
+            .collect(),
+            ..Default::default()
+        };
+        body.set_mesh(mesh);
+        assert_eq!(body.footprint().unwrap().hull().len(), 3);
+        assert!((body.footprint().unwrap().area() - 4.0).abs() < 1e-4);
+
+        // Moving only shifts the cached hull
+        body.set_position(Vector3::new(10.0, 20.0, 5.0));
+        let (min, _) = body.footprint().unwrap().bounds();
+        assert!((min - Vector2::new(10.0, 20.0)).norm() < 1e-4);
+
+        // Turning and scaling compute it again
+        body.set_rotation(Vector3::new(0.0, 0.0, 90.0));
+        body.set_scale(Vector3::new(2.0, 1.0, 1.0));
+        let footprint = body.footprint().unwrap();
+        let (min, max) = footprint.bounds();
+        assert!((min - Vector2::new(8.0, 20.0)).norm() < 1e-4);
+        assert!((max - Vector2::new(10.0, 28.0)).norm() < 1e-4);
+        assert!((footprint.area() - 8.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_ray_hit_follows_transform() {
+        // A square at z = 0, raised and stretched: the ray hits its face, not its bounds
+        let mut body = Body::new(Mesh::from_triangles(&[
+            create_triangle([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            create_triangle([1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]),
+        ]));
+        body.set_position(Vector3::new(0.0, 0.0, 5.0));
+        body.set_scale(Vector3::new(4.0, 4.0, 1.0));
+        let down = Vector3::new(0.0, 0.0, -1.0);
+        let distance = body.ray_hit(Vector3::new(3.0, 3.0, 10.0), down).unwrap();
+        assert!((distance - 5.0).abs() < EPSILON);
+        assert!(body.ray_hit(Vector3::new(5.0, 3.0, 10.0), down).is_none());
+        // Looking away from it
+        assert!(body.ray_hit(Vector3::new(3.0, 3.0, 10.0), -down).is_none());
+    }
+
+    #[test]
+    fn test_uniform_scale() {
+        let mut body = Body::default();
+        assert!(!body.has_uneven_scale());
+        body.set_scale(Vector3::new(-2.0, 2.0, 2.0));
+        assert!(!body.has_uneven_scale());
+
+        body.set_scale(Vector3::new(-1.0, 2.0, 4.0));
+        assert!(body.has_uneven_scale());
+        let uniform = body.uniform_scale();
+        assert!((uniform - Vector3::new(-2.0, 2.0, 2.0)).norm() < EPSILON);
+    }
 }
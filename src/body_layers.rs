@@ -0,0 +1,250 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::SliceRole;
+use crate::layer_thickness::LayerThickness;
+use image::{ImageBuffer, Luma};
+use rayon::prelude::*;
+use std::ops::DerefMut;
+use std::sync::Arc;
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// Pixels of a subtracting body at or above this value are cleared from the layer,
+/// the darker shades are holes inside the subtracting body
+const SUBTRACT_THRESHOLD: u8 = 128;
+
+/// The pixels one body covers in a layer, cropped to their bounds
+#[derive(Debug, Clone, PartialEq)]
+pub struct BodyRaster {
+    x: u32,
+    y: u32,
+    image: Layer,
+}
+
+impl BodyRaster {
+    /// Crops a layer with only one body drawn in it to the pixels the body covers
+    pub fn crop(layer: &Layer) -> Self {
+        let (width, height) = layer.dimensions();
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+        for (x, y, pixel) in layer.enumerate_pixels() {
+            if pixel[0] > 0 {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x + 1);
+                max_y = max_y.max(y + 1);
+            }
+        }
+        if min_x >= max_x {
+            return Self {
+                x: 0,
+                y: 0,
+                image: ImageBuffer::new(0, 0),
+            };
+        }
+        let image = ImageBuffer::from_fn(max_x - min_x, max_y - min_y, |x, y| {
+            *layer.get_pixel(min_x + x, min_y + y)
+        });
+        Self {
+            x: min_x,
+            y: min_y,
+            image,
+        }
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32, u8)> + '_ {
+        self.image
+            .enumerate_pixels()
+            .map(|(x, y, pixel)| (self.x + x, self.y + y, pixel[0]))
+    }
+}
+
+/// The layers of one body, sliced on the layer heights of a job starting at `first_z`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BodyLayers {
+    pub role: SliceRole,
+    pub first_z: f64,
+    pub thickness: LayerThickness,
+    /// By layer of the job, None where the body isn't cut
+    pub layers: Vec<Option<BodyRaster>>,
+}
+
+impl BodyLayers {
+    /// True when the layers were sliced at the same heights as a job starting at
+    /// `first_z`, so they can be composited into it as they are
+    pub fn fits(&self, first_z: f64, thickness: &LayerThickness) -> bool {
+        self.first_z.to_bits() == first_z.to_bits() && self.thickness == *thickness
+    }
+}
+
+/// The layers of a plate out of the layers of its bodies: merging bodies cure the union of
+/// all of them, subtracting bodies then cut through whatever they overlap. Layers no merging
+/// body is cut in are left blank, so the layers above stay at their height. Only the ones
+/// below the first and above the last printed layer are left out, like the first layer of
+/// a job, which just touches the bottom of its parts.
+pub fn composite(
+    bodies: &[Arc<BodyLayers>],
+    layer_count: usize,
+    width: u32,
+    height: u32,
+) -> Vec<Layer> {
+    let mut ordered: Vec<&BodyLayers> = bodies.iter().map(Arc::as_ref).collect();
+    ordered.sort_by_key(|body| body.role == SliceRole::Subtract);
+    let mut layers: Vec<Option<Layer>> = (0..layer_count)
+        .into_par_iter()
+        .map(|index| {
+            let mut image: Option<Layer> = None;
+            for body in &ordered {
+                let Some(Some(raster)) = body.layers.get(index) else {
+                    continue;
+                };
+                match (body.role, image.as_mut()) {
+                    (SliceRole::Merge, None) => {
+                        let mut layer = ImageBuffer::new(width, height);
+                        merge(&mut layer, raster);
+                        image = Some(layer);
+                    }
+                    (SliceRole::Merge, Some(layer)) => merge(layer, raster),
+                    (SliceRole::Subtract, Some(layer)) => subtract(layer, raster),
+                    // Nothing to cut from
+                    (SliceRole::Subtract, None) => {}
+                }
+            }
+            image
+        })
+        .collect();
+    while layers.last().is_some_and(Option::is_none) {
+        layers.pop();
+    }
+    let first = layers.iter().position(Option::is_some).unwrap_or(0);
+    layers
+        .into_iter()
+        .skip(first)
+        .map(|layer| layer.unwrap_or_else(|| ImageBuffer::new(width, height)))
+        .collect()
+}
+
+/// Composites the rasters of the bodies cut in one layer into it, the way `composite` does
+/// for a whole job. False when no merging body is cut, the layer is left blank then.
+pub fn composite_layer<C: DerefMut<Target = [u8]>>(
+    rasters: &[(SliceRole, BodyRaster)],
+    layer: &mut ImageBuffer<Luma<u8>, C>,
+) -> bool {
+    let merging = rasters.iter().filter(|(role, _)| *role == SliceRole::Merge);
+    let subtracting = rasters
+        .iter()
+        .filter(|(role, _)| *role == SliceRole::Subtract);
+    let mut printed = false;
+    for (_, raster) in merging {
+        merge(layer, raster);
+        printed = true;
+    }
+    if printed {
+        for (_, raster) in subtracting {
+            subtract(layer, raster);
+        }
+    }
+    printed
+}
+
+fn merge<C: DerefMut<Target = [u8]>>(layer: &mut ImageBuffer<Luma<u8>, C>, raster: &BodyRaster) {
+    for (x, y, value) in raster.pixels() {
+        let pixel = layer.get_pixel_mut(x, y);
+        pixel[0] = pixel[0].max(value);
+    }
+}
+
+fn subtract<C: DerefMut<Target = [u8]>>(layer: &mut ImageBuffer<Luma<u8>, C>, raster: &BodyRaster) {
+    for (x, y, value) in raster.pixels() {
+        if value >= SUBTRACT_THRESHOLD {
+            layer.get_pixel_mut(x, y)[0] = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::layer;
+
+    fn body(role: SliceRole, layers: &[Option<Layer>]) -> Arc<BodyLayers> {
+        Arc::new(BodyLayers {
+            role,
+            first_z: 0.0,
+            thickness: LayerThickness::uniform(0.1),
+            layers: layers
+                .iter()
+                .map(|layer| layer.as_ref().map(BodyRaster::crop))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_crop() {
+        let full = layer(&["....", ".#+.", "..#.", "...."]);
+        let raster = BodyRaster::crop(&full);
+        assert_eq!((raster.x, raster.y), (1, 1));
+        assert_eq!(raster.image.dimensions(), (2, 2));
+        assert_eq!(raster.pixels().count(), 4);
+
+        let empty = BodyRaster::crop(&layer(&["..", ".."]));
+        assert_eq!(empty.image.dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn test_composite() {
+        let part = body(
+            SliceRole::Merge,
+            &[
+                Some(layer(&["##..", "##.."])),
+                Some(layer(&["##..", "...."])),
+            ],
+        );
+        let shifted = body(SliceRole::Merge, &[Some(layer(&[".##.", "...."]))]);
+        let drain = body(
+            SliceRole::Subtract,
+            &[
+                Some(layer(&["#...", "+..."])),
+                None,
+                Some(layer(&["####", "####"])),
+            ],
+        );
+
+        // One layer at a time, into pixels borrowed from elsewhere
+        let rasters: Vec<(SliceRole, BodyRaster)> = [&drain, &part, &shifted]
+            .iter()
+            .filter_map(|body| Some((body.role, body.layers[0].clone()?)))
+            .collect();
+        let mut pixels = [0u8; 8];
+        let mut single = ImageBuffer::from_raw(4, 2, &mut pixels[..]).unwrap();
+        assert!(composite_layer(&rasters, &mut single));
+        assert!(!composite_layer(&rasters[..1], &mut single));
+        assert_eq!(pixels[..], *layer(&[".##.", "##.."]).as_raw());
+
+        // Subtracting bodies cut after every merging body, wherever they come in the list
+        let layers = composite(&[drain, part, shifted], 3, 4, 2);
+        assert_eq!(layers, [layer(&[".##.", "##.."]), layer(&["##..", "...."])]);
+        assert!(composite(&[], 3, 4, 2).is_empty());
+
+        // A layer between two parts stays, blank
+        let parts = body(
+            SliceRole::Merge,
+            &[
+                Some(layer(&["#...", "...."])),
+                None,
+                Some(layer(&["...#", "...."])),
+            ],
+        );
+        let layers = composite(&[parts], 3, 4, 2);
+        assert_eq!(layers.len(), 3);
+        assert_eq!(layers[1], layer(&["....", "...."]));
+    }
+
+    #[test]
+    fn test_fits() {
+        let layers = body(SliceRole::Merge, &[Some(layer(&["#."]))]);
+        assert!(layers.fits(0.0, &LayerThickness::uniform(0.1)));
+        assert!(!layers.fits(0.05, &LayerThickness::uniform(0.1)));
+        assert!(!layers.fits(0.0, &LayerThickness::uniform(0.05)));
+    }
+}
@@ -0,0 +1,111 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::printer::Printer;
+use image::{ImageBuffer, ImageError, Luma};
+use rayon::prelude::*;
+use std::path::Path;
+use thiserror::Error;
+
+/// Per-pixel brightness correction for printers with uneven LCD illumination.
+/// Each slice pixel is multiplied by the mask value / 255, so white (255) leaves
+/// a pixel untouched and darker mask values dim the brighter areas of the screen.
+pub struct CalibrationMask {
+    mask: ImageBuffer<Luma<u8>, Vec<u8>>,
+}
+
+impl CalibrationMask {
+    pub fn new(mask: ImageBuffer<Luma<u8>, Vec<u8>>) -> Self {
+        Self { mask }
+    }
+
+    /// Loads a mask image and checks it matches the resolution of the printer
+    pub fn load(path: &Path, printer: &Printer) -> Result<Self, CalibrationMaskError> {
+        let mask = image::open(path)?.into_luma8();
+        if mask.dimensions() != (printer.pixel_x, printer.pixel_y) {
+            return Err(CalibrationMaskError::DimensionMismatch {
+                mask_x: mask.width(),
+                mask_y: mask.height(),
+                printer_x: printer.pixel_x,
+                printer_y: printer.pixel_y,
+            });
+        }
+        Ok(Self::new(mask))
+    }
+
+    /// Loads the mask configured for the printer, if it has one
+    pub fn for_printer(printer: &Printer) -> Result<Option<Self>, CalibrationMaskError> {
+        printer
+            .calibration_mask
+            .as_deref()
+            .map(|path| Self::load(path, printer))
+            .transpose()
+    }
+
+    pub fn apply(&self, image: &mut ImageBuffer<Luma<u8>, Vec<u8>>) {
+        image
+            .iter_mut()
+            .zip(self.mask.iter())
+            .for_each(|(pixel, &mask)| {
+                *pixel = ((*pixel as u16 * mask as u16 + 127) / 255) as u8;
+            });
+    }
+
+    pub fn apply_to_all(&self, images: &mut [ImageBuffer<Luma<u8>, Vec<u8>>]) {
+        images.par_iter_mut().for_each(|image| self.apply(image));
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CalibrationMaskError {
+    #[error("Could not read calibration mask: {0}")]
+    Image(#[from] ImageError),
+
+    #[error("Calibration mask is {mask_x}x{mask_y} but the printer is {printer_x}x{printer_y}")]
+    DimensionMismatch {
+        mask_x: u32,
+        mask_y: u32,
+        printer_x: u32,
+        printer_y: u32,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_apply_multiplies_pixels() {
+        let mask = CalibrationMask::new(ImageBuffer::from_fn(4, 1, |x, _| {
+            Luma([[255u8, 128, 0, 200][x as usize]])
+        }));
+        let mut image = ImageBuffer::from_fn(4, 1, |x, _| Luma([[200u8, 255, 255, 0][x as usize]]));
+
+        mask.apply(&mut image);
+
+        assert_eq!(image.into_raw(), vec![200, 128, 0, 0]);
+    }
+
+    #[test]
+    fn test_load_checks_dimensions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("mask.png");
+        let mut printer = Printer::default();
+        ImageBuffer::from_pixel(printer.pixel_x, printer.pixel_y, Luma([240u8]))
+            .save(&path)
+            .unwrap();
+
+        printer.calibration_mask = Some(path.clone());
+        assert!(CalibrationMask::for_printer(&printer).unwrap().is_some());
+
+        printer.pixel_x += 1;
+        assert!(matches!(
+            CalibrationMask::load(&path, &printer),
+            Err(CalibrationMaskError::DimensionMismatch { .. })
+        ));
+
+        printer.calibration_mask = None;
+        assert!(CalibrationMask::for_printer(&printer).unwrap().is_none());
+    }
+}
@@ -1,6 +1,7 @@
 // Distributed under the GNU Affero General Public License v3.0 or later.
 // See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
 
+use crate::axis_gizmo::Axis;
 use nalgebra::{Matrix4, Point3, Vector3, Vector4};
 
 pub struct Camera {
@@ -54,7 +55,6 @@ impl Camera {
         self.view_matrix() * self.projection_matrix
     }
 
-    #[allow(dead_code)]
     pub fn get_view_direction_vector(&self) -> Vector3<f32> {
         (self.target - self.position).normalize()
     }
@@ -101,6 +101,16 @@ impl Camera {
         self.position = self.target - (direction * self.distance);
     }
 
+    /// Looks at the target from the positive end of a world axis, straight down for Z
+    pub fn snap_to_axis(&mut self, axis: Axis) {
+        (self.yaw, self.pitch) = match axis {
+            Axis::X => (180.0, 0.0),
+            Axis::Y => (-90.0, 0.0),
+            Axis::Z => (-90.0, -89.9),
+        };
+        self.update_camera_position();
+    }
+
     /// Zooms the camera in or out by adjusting the distance from the target.
     pub fn zoom(&mut self, delta: f32) {
         self.distance -= delta * self.sensitivity;
@@ -118,6 +128,26 @@ impl Camera {
         self.right().cross(&forward).normalize() // Get the up direction relative to the camera's view
     }
 
+    /// The ray under a point of the rendered view, in its pixels from the top left, as
+    /// (origin on the near plane, direction). The view is shown with its first row at the
+    /// top, so unlike `screen_to_ndc` y isn't flipped.
+    pub fn pick_ray(
+        view_proj: &Matrix4<f32>,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let ndc_x = 2.0 * x / width - 1.0;
+        let ndc_y = 2.0 * y / height - 1.0;
+        let inv_view_proj = view_proj.try_inverse()?;
+        let near = inv_view_proj * Vector4::new(ndc_x, ndc_y, -1.0, 1.0);
+        let far = inv_view_proj * Vector4::new(ndc_x, ndc_y, 1.0, 1.0);
+        let near = near.xyz() / near.w;
+        let far = far.xyz() / far.w;
+        Some((near, (far - near).try_normalize(f32::EPSILON)?))
+    }
+
     #[allow(dead_code)]
     fn screen_to_ndc(x: f32, y: f32, width: u32, height: u32) -> (f32, f32) {
         let ndc_x = (2.0 * x) / width as f32 - 1.0;
@@ -413,6 +443,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_snap_to_axis() {
+        let mut camera = Camera::new(16.0 / 9.0);
+        camera.pan(10.0, 5.0);
+
+        for axis in Axis::ALL {
+            camera.snap_to_axis(axis);
+            let from_target = (camera.position - camera.target).normalize();
+            assert!(
+                from_target.dot(&axis.unit()) > 0.999,
+                "Camera not looking down {:?}: {:?}",
+                axis,
+                from_target
+            );
+            assert!(relative_eq!(
+                (camera.position - camera.target).norm(),
+                camera.distance,
+                epsilon = EPSILON
+            ));
+        }
+    }
+
     #[test]
     fn test_right_vector() {
         let camera = Camera::new(16.0 / 9.0);
@@ -465,6 +517,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_pick_ray() {
+        let camera = Camera::new(2.0);
+        let view_proj = camera.projection_matrix * camera.view_matrix();
+        let (origin, direction) =
+            Camera::pick_ray(&view_proj, 960.0, 540.0, 1920.0, 1080.0).unwrap();
+        // The middle of the view looks at the target
+        let to_target = camera.target.coords - origin;
+        assert!((to_target.normalize() - direction).norm() < EPSILON);
+
+        // A point in the top half of the view unprojects to a ray going to where that
+        // point is drawn again
+        let (origin, direction) =
+            Camera::pick_ray(&view_proj, 480.0, 270.0, 1920.0, 1080.0).unwrap();
+        let point = origin + direction * 50.0;
+        let clip = view_proj * Vector4::new(point.x, point.y, point.z, 1.0);
+        assert!((clip.x / clip.w + 0.5).abs() < EPSILON);
+        assert!((clip.y / clip.w + 0.5).abs() < EPSILON);
+    }
+
     #[test]
     fn test_screen_to_ndc() {
         // Case 1: Center of the screen
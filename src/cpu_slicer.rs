@@ -1,439 +1,583 @@
 // Distributed under the GNU Affero General Public License v3.0 or later.
 // See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
 
-use crate::body::Body;
+use crate::body::{Body, SliceRole};
+use crate::body_layers::{self, BodyLayers, BodyRaster};
+use crate::calibration_mask::CalibrationMaskError;
+use crate::export_queue::ExportError;
+use crate::geometry;
+use crate::layer_spool::LayerSpool;
+use crate::layer_thickness::LayerThickness;
+use crate::memory_budget::{self, BudgetCheck};
+#[cfg(feature = "network-printing")]
+use crate::network_printer::NetworkPrinterError;
+use crate::performance_overlay;
+use crate::polygon_assembly::{assemble_polygons, NestedContour, Segment};
 use crate::printer::Printer;
+use crate::profiler::{self, Stage};
+use crate::slice_debugger;
+use crate::slice_parameters::Supersampling;
+use crate::tolerance::{TolerancePolicy, ToleranceOverrides};
 use geo::algorithm::area::Area;
-use geo::{Contains, Coord, Line, LineString, Polygon};
-use image::{ImageBuffer, ImageError, Luma};
-use imageproc::drawing::draw_polygon_mut;
-use imageproc::point::Point;
+use geo::{Coord, LineString, Polygon};
+use image::{imageops, ImageBuffer, ImageError, Luma};
+use imageproc::drawing::draw_line_segment_mut;
 use log::debug;
-use nalgebra::{OPoint, Vector3};
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use std::collections::{HashMap, HashSet};
+use nalgebra::{Matrix4, Vector2, Vector3};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
 use stl_io::{self, Triangle};
 use thiserror::Error;
+use uuid::Uuid;
+use wide::{f64x4, CmpGt, CmpLt};
 // use geo_types::line_string;
-use geo::algorithm::intersects::Intersects; // Provides intersects method for line strings
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum Orientation {
-    INSIDE,
-    OUTSIDE,
-}
+/// Background of the 2D layer preview, the outlines are white
+pub const LCD_PREVIEW_BACKGROUND: u8 = 40;
+
+/// Image coordinates are clamped to this many pixels either way of the origin
+const MAX_IMAGE_COORDINATE: i32 = 1 << 24;
+
+/// Supersampled layers are drawn in bands of up to this many samples, so the supersampled
+/// image of a whole layer never has to fit in memory
+const MAX_BAND_SAMPLES: u64 = 1 << 22;
+
+/// The layers of a job, and the layers of every body in it by uuid
+pub type SlicedBodies = (
+    Vec<ImageBuffer<Luma<u8>, Vec<u8>>>,
+    Vec<(Uuid, Arc<BodyLayers>)>,
+);
+
+// Segments and polygons of a layer, for the slice debugger
+type LayerDebug = (Vec<Segment>, Vec<Vec<Vector3<f64>>>);
 
 #[derive(Default)]
 pub struct CPUSlicer {}
 
 impl CPUSlicer {
+    /// Slices the bodies into one image per layer. `shrinkage_compensation` scales the
+    /// whole build volume about the center of the build plate after the body transforms.
+    /// Each body is rasterized on its own, so overlapping bodies merge and subtracting
+    /// bodies cut through whatever they overlap.
     pub fn slice_bodies(
         bodies: Vec<Body>,
-        slice_thickness: f64,
+        thickness: &LayerThickness,
         printer: &Printer,
+        shrinkage_compensation: Vector3<f32>,
     ) -> Result<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>, CPUSlicerError> {
-        let mut triangles: Vec<Triangle> = Vec::new();
-
-        for mut body in bodies {
-            body.mesh.get_triangles_for_slicing();
-            let model_matrix = body.get_model_matrix();
-
-            for tri in &body.mesh.get_triangles_for_slicing() {
-                // Convert each vertex from [f32; 3] to OPoint<f32, 3>
-                let vertex0 = OPoint::from(tri.vertices[0]);
-                let vertex1 = OPoint::from(tri.vertices[1]);
-                let vertex2 = OPoint::from(tri.vertices[2]);
-
-                // Transform each vertex using the model matrix
-                let transformed_vertex0 = model_matrix.transform_point(&vertex0).coords.into();
-                let transformed_vertex1 = model_matrix.transform_point(&vertex1).coords.into();
-                let transformed_vertex2 = model_matrix.transform_point(&vertex2).coords.into();
-
-                let transformed_vertices = [
-                    transformed_vertex0,
-                    transformed_vertex1,
-                    transformed_vertex2,
-                ];
-
-                // Convert normal from [f32; 3] to Vector3<f32>
-                let normal_vector = Vector3::from(tri.normal);
-                // println!("Position vector for triangle: {:?}", transformed_vertices4);
-                // Transform and normalize the normal vector
-                let transformed_normal = model_matrix.transform_vector(&normal_vector).normalize();
-                // println!("Normal Vector for triangle: {:?}", normal_vector);
-
-                // Convert the transformed normal back to [f32; 3]
-                let transformed_normal_array: [f32; 3] = transformed_normal.into();
-
-                // Create a new Triangle with transformed data
-                let transformed_triangle = Triangle {
-                    normal: transformed_normal_array,
-                    vertices: transformed_vertices,
-                };
-
-                // Add the transformed triangle to the list
-                triangles.push(transformed_triangle);
-            }
-        }
-        Self::generate_slice_images(&triangles, slice_thickness, printer)
+        Self::slice_bodies_reusing(
+            bodies,
+            thickness,
+            printer,
+            Supersampling::Off,
+            shrinkage_compensation,
+            &HashMap::new(),
+        )
+        .map(|(images, _)| images)
     }
 
-    fn generate_slice_images(
-        triangles: &[Triangle],
-        slice_thickness: f64,
+    /// Like `slice_bodies`, but takes the layers of a body from `reusable` instead of
+    /// slicing it again when they were sliced at the layer heights of this job, and fills
+    /// the layers with `supersampling`. Returns the layers of every body along with the
+    /// images, to be reused by the next job.
+    pub fn slice_bodies_reusing(
+        bodies: Vec<Body>,
+        thickness: &LayerThickness,
         printer: &Printer,
-    ) -> Result<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>, CPUSlicerError> {
-        let (min_z, max_z) = CPUSlicer::z_range(triangles);
-        let mut slice_z_values = Vec::new();
-        let mut z = min_z;
-        while z <= max_z {
-            slice_z_values.push(z);
-            z += slice_thickness;
-        }
-
-        let images: Vec<ImageBuffer<Luma<u8>, Vec<u8>>> = slice_z_values
-            .par_iter()
-            .filter_map(|plane_z| {
-                let segments = CPUSlicer::collect_intersection_segments(triangles, *plane_z);
-                if segments.is_empty() {
-                    return None;
-                }
-
-                let raw_polygons = CPUSlicer::assemble_polygons(&segments);
-                if raw_polygons.is_empty() {
-                    return None;
-                }
-
-                let mut image =
-                    ImageBuffer::from_pixel(printer.pixel_x, printer.pixel_y, Luma([0u8]));
-
-                // Now using classify_and_structure_polygons with depth information
-                let (exterior_with_depth, holes_with_depth) =
-                    Self::classify_and_structure_polygons(raw_polygons);
-
-                // Combine exteriors and holes into one list for rendering
-                let mut all_polygons_with_depth: Vec<((Polygon, Orientation), usize)> =
-                    exterior_with_depth;
-                all_polygons_with_depth.extend(holes_with_depth);
-                all_polygons_with_depth.sort_by(|a, b| {
-                    // Compare depths first
-                    a.1.cmp(&b.1)
-                        // If depths are equal, compare orientations
-                        .then_with(|| a.0 .1.cmp(&b.0 .1))
-                });
-
-                for (polygon, depth) in all_polygons_with_depth {
-                    let points: Vec<Point<i32>> = polygon
-                        .0
-                        .exterior()
-                        .points()
-                        .map(|p| {
-                            let (x, y) = Self::model_to_image_coords(
-                                p.x(),
-                                p.y(),
-                                printer.pixel_x,
-                                printer.physical_x,
-                                printer.pixel_y,
-                                printer.physical_y,
-                            );
-                            Point::new(x, y)
-                        })
-                        .collect();
-
-                    let mut unique_points: Vec<Point<i32>> = Vec::new();
-
-                    // Manually check for duplicates
-                    for point in points {
-                        // Check if the point is already in the unique_points vector
-                        if !unique_points.iter().any(|p| p == &point) {
-                            unique_points.push(point);
-                        }
-                    }
+        supersampling: Supersampling,
+        shrinkage_compensation: Vector3<f32>,
+        reusable: &HashMap<Uuid, Arc<BodyLayers>>,
+    ) -> Result<SlicedBodies, CPUSlicerError> {
+        let body_triangles = Self::body_triangles(&bodies, shrinkage_compensation);
+        Self::generate_slice_images(&body_triangles, thickness, printer, supersampling, reusable)
+    }
 
-                    if unique_points.len() >= 3 {
-                        match polygon.1 {
-                            Orientation::INSIDE => {
-                                if depth == 0 {
-                                    // This really shouldn't happen but it seems there is an issue with my orientation algorithm and
-                                    // this is a bandaid fix that semms to work in most cases
-                                    draw_polygon_mut(&mut image, &unique_points, Luma([200u8]));
-                                } else {
-                                    // Draw interior polygons, holes, black (or grey for debugging)
-                                    draw_polygon_mut(&mut image, &unique_points, Luma([69u8]));
-                                }
-                            }
-                            Orientation::OUTSIDE => {
-                                // Draw exterior polygons white
-                                draw_polygon_mut(&mut image, &unique_points, Luma([255u8]));
-                            }
-                        }
-                    }
-                }
+    /// Like `slice_bodies`, for jobs too big to keep in memory: every layer is composited
+    /// straight into a spool file at `path` as soon as its bodies are cut, and the layers of
+    /// the bodies aren't kept for reuse.
+    pub fn slice_bodies_to_spool(
+        bodies: Vec<Body>,
+        thickness: &LayerThickness,
+        printer: &Printer,
+        supersampling: Supersampling,
+        shrinkage_compensation: Vector3<f32>,
+        path: &Path,
+    ) -> Result<LayerSpool, CPUSlicerError> {
+        let body_triangles = Self::body_triangles(&bodies, shrinkage_compensation);
+        drop(bodies);
+        let (min_z, max_z) = Self::job_z_range(&body_triangles);
+        let slice_z_values = thickness.slice_heights(min_z, max_z);
+        let tolerances: Vec<TolerancePolicy> = body_triangles
+            .iter()
+            .map(|(_, _, triangles)| {
+                TolerancePolicy::for_printer(geometry::longest_side(triangles), printer)
+            })
+            .collect();
 
-                Some(image)
+        let mut spool =
+            LayerSpool::create(path, slice_z_values.len(), printer.pixel_x, printer.pixel_y)?;
+        let printed: Vec<bool> = spool
+            .layers_mut()
+            .zip(slice_z_values.par_iter())
+            .map(|(mut layer, plane_z)| {
+                let rasters: Vec<(SliceRole, BodyRaster)> = body_triangles
+                    .iter()
+                    .zip(&tolerances)
+                    .filter_map(|((_, role, triangles), tolerances)| {
+                        let (raster, _) = Self::slice_layer(
+                            triangles,
+                            *plane_z,
+                            tolerances,
+                            printer,
+                            supersampling,
+                            false,
+                        );
+                        Some((*role, raster?))
+                    })
+                    .collect();
+                body_layers::composite_layer(&rasters, &mut layer)
             })
             .collect();
+        // Like `composite`, only the layers below the first and above the last printed one
+        // are left out
+        let first = printed.iter().position(|&printed| printed).unwrap_or(0);
+        let last = printed
+            .iter()
+            .rposition(|&printed| printed)
+            .map_or(0, |last| last + 1);
+        let keep: Vec<bool> = (0..printed.len())
+            .map(|index| (first..last).contains(&index))
+            .collect();
+        spool.retain(&keep);
+        Ok(spool)
+    }
 
-        Ok(images)
+    // The triangles of every body in world space, with its uuid and role
+    fn body_triangles(
+        bodies: &[Body],
+        shrinkage_compensation: Vector3<f32>,
+    ) -> Vec<(Uuid, SliceRole, Vec<Triangle>)> {
+        bodies
+            .iter()
+            .map(|body| {
+                (
+                    body.uuid,
+                    body.slice_role,
+                    Self::world_triangles([body], shrinkage_compensation),
+                )
+            })
+            .collect()
     }
 
-    fn classify_and_structure_polygons(
-        polygons: Vec<(Vec<Vector3<f64>>, Orientation)>,
-    ) -> (
-        Vec<((Polygon<f64>, Orientation), usize)>,
-        Vec<((Polygon<f64>, Orientation), usize)>,
-    ) {
-        let mut exteriors_with_depth: Vec<((Polygon<f64>, Orientation), usize)> = Vec::new();
-        let mut holes_with_depth: Vec<((Polygon<f64>, Orientation), usize)> = Vec::new();
+    // Lowest and highest point of the merging bodies, subtracting bodies are never
+    // printed so they don't add layers
+    fn job_z_range(body_triangles: &[(Uuid, SliceRole, Vec<Triangle>)]) -> (f64, f64) {
+        body_triangles
+            .iter()
+            .filter(|(_, role, _)| *role == SliceRole::Merge)
+            .filter_map(|(_, _, triangles)| geometry::z_range(triangles))
+            .fold(
+                (f64::INFINITY, f64::NEG_INFINITY),
+                |(min, max), (low, high)| (min.min(low), max.max(high)),
+            )
+    }
 
-        let mut all_polygons: Vec<(Polygon<f64>, Orientation)> = Vec::new();
+    /// Outlines of the bodies at the given height as closed loops in world coordinates,
+    /// used to preview a layer in the 3D view without rasterizing it
+    pub fn layer_contours<'a>(
+        bodies: impl IntoIterator<Item = &'a Body>,
+        plane_z: f64,
+    ) -> Vec<Vec<Vector3<f64>>> {
+        let triangles = Self::world_triangles(bodies, Vector3::new(1.0, 1.0, 1.0));
+        let tolerances = TolerancePolicy::derive(
+            geometry::longest_side(&triangles),
+            f64::INFINITY,
+            &ToleranceOverrides::default(),
+        );
+        let segments = Self::collect_intersection_segments(&triangles, plane_z, &tolerances);
+        assemble_polygons(&segments, tolerances.assembly)
+            .into_iter()
+            .map(|contour| contour.points)
+            .collect()
+    }
 
-        for (_i, (points, orientation)) in polygons.iter().enumerate() {
-            // Convert the points to a geo::Polygon and store with its orientation
-            let linestring_geo: LineString<f64> = LineString::from(
-                points
-                    .iter()
-                    .map(|p| (p.x, p.y))
-                    .collect::<Vec<(f64, f64)>>(),
-            );
-            let polygon = Polygon::new(linestring_geo, vec![]);
-            all_polygons.push((polygon, *orientation));
-        }
+    /// Number of layers a slicing job would produce, without slicing anything
+    pub fn layer_count<'a>(
+        bodies: impl IntoIterator<Item = &'a Body>,
+        thickness: &LayerThickness,
+        shrinkage_compensation: Vector3<f32>,
+    ) -> usize {
+        Self::layer_heights(bodies, thickness, shrinkage_compensation).len()
+    }
 
-        // Now check nesting and assign depths
-        for (i, (polygon, orientation)) in all_polygons.iter().enumerate() {
-            let mut depth = 0;
-            for (j, (other_polygon, _)) in all_polygons.iter().enumerate() {
-                if i != j && Self::is_polygon_inside(polygon, other_polygon) {
-                    depth += 1;
-                }
-            }
+    /// Height of the slicing plane of every layer a job would produce
+    pub fn layer_heights<'a>(
+        bodies: impl IntoIterator<Item = &'a Body>,
+        thickness: &LayerThickness,
+        shrinkage_compensation: Vector3<f32>,
+    ) -> Vec<f64> {
+        // Subtracting bodies are never printed, so they don't add layers
+        let merging = bodies
+            .into_iter()
+            .filter(|body| body.slice_role == SliceRole::Merge);
+        let triangles = Self::world_triangles(merging, shrinkage_compensation);
+        let Some((min_z, max_z)) = geometry::z_range(&triangles) else {
+            return Vec::new();
+        };
+        thickness.slice_heights(min_z, max_z)
+    }
 
-            println!("Signed area: {:?}", polygon.signed_area());
+    // Transforms the triangles of every body into world space
+    pub fn world_triangles<'a>(
+        bodies: impl IntoIterator<Item = &'a Body>,
+        shrinkage_compensation: Vector3<f32>,
+    ) -> Vec<Triangle> {
+        let mut triangles: Vec<Triangle> = Vec::new();
 
-            // Assign the polygon to the appropriate list (exterior or interior) with depth
-            match orientation {
-                Orientation::INSIDE => {
-                    holes_with_depth.push(((polygon.clone(), *orientation), depth));
-                    println!("Pushing inside polygon with depth: {}", depth);
-                    if depth == 0 {
-                        println!("Inside polygon with depth 0. BAD!");
-                    }
-                }
-                Orientation::OUTSIDE => {
-                    exteriors_with_depth.push(((polygon.clone(), *orientation), depth));
-                    println!("Pushing outside polygon with depth: {}", depth);
-                    // It is okay to have outside polygons of any depth
-                }
-            }
-        }
+        for body in bodies {
+            let model_matrix =
+                Matrix4::new_nonuniform_scaling(&shrinkage_compensation) * body.get_model_matrix();
 
-        (exteriors_with_depth, holes_with_depth)
+            triangles.extend(geometry::transform_triangles(
+                &body.mesh.get_triangles_for_slicing(),
+                &model_matrix,
+            ));
+        }
+        triangles
     }
 
-    fn is_polygon_inside(polygon: &Polygon<f64>, other_polygon: &Polygon<f64>) -> bool {
-        // First, check if all vertices of `polygon` are inside `other_polygon`
-        for point in polygon.exterior().points() {
-            if !other_polygon.contains(&point) {
-                return false; // If any vertex is outside, return false
-            }
+    fn generate_slice_images(
+        body_triangles: &[(Uuid, SliceRole, Vec<Triangle>)],
+        thickness: &LayerThickness,
+        printer: &Printer,
+        supersampling: Supersampling,
+        reusable: &HashMap<Uuid, Arc<BodyLayers>>,
+    ) -> Result<SlicedBodies, CPUSlicerError> {
+        let (min_z, max_z) = Self::job_z_range(body_triangles);
+        let slice_z_values = thickness.slice_heights(min_z, max_z);
+
+        // Refuse jobs that would get the process OOM-killed halfway through
+        let required = memory_budget::estimate_slice_bytes(
+            slice_z_values.len(),
+            printer.pixel_x,
+            printer.pixel_y,
+        );
+        if let BudgetCheck::Exceeds {
+            required,
+            available,
+            suggested_scale,
+        } = memory_budget::check(required, memory_budget::available_memory())
+        {
+            return Err(CPUSlicerError::InsufficientMemory {
+                required,
+                available,
+                suggested_scale,
+            });
         }
 
-        // Prepare to check if the edges of `polygon` intersect with the edges of `other_polygon`
-        let polygon_lines: Vec<Line<f64>> = polygon.exterior().lines().collect(); // Collecting lines for the edges of the polygon
-        let other_polygon_lines: Vec<Line<f64>> = other_polygon.exterior().lines().collect(); // Collecting lines for the edges of the other polygon
-
-        // Check for intersections between edges
-        for polygon_line in &polygon_lines {
-            // Iterate over references
-            for other_polygon_line in &other_polygon_lines {
-                // Iterate over references
-                if polygon_line.intersects(other_polygon_line) {
-                    return false; // If any edges intersect, return false
+        let debugger = slice_debugger::global();
+        if debugger.is_enabled() {
+            debugger.begin_job();
+        }
+        // The debugger inspects whole layers, so every body is sliced again for it
+        let mut layer_debug = vec![(Vec::new(), Vec::new()); slice_z_values.len()];
+        let mut body_layers = Vec::with_capacity(body_triangles.len());
+        for (uuid, role, triangles) in body_triangles {
+            let reused = reusable
+                .get(uuid)
+                .filter(|layers| layers.role == *role && layers.fits(min_z, thickness))
+                .filter(|_| !debugger.is_enabled());
+            let layers = match reused {
+                Some(layers) => Arc::clone(layers),
+                None => {
+                    let (layers, debug) = Self::slice_body(
+                        *role,
+                        triangles,
+                        min_z,
+                        thickness,
+                        printer,
+                        supersampling,
+                        debugger.is_enabled(),
+                    );
+                    for ((segments, polygons), (layer_segments, layer_polygons)) in
+                        layer_debug.iter_mut().zip(debug)
+                    {
+                        segments.extend(layer_segments);
+                        polygons.extend(layer_polygons);
+                    }
+                    Arc::new(layers)
                 }
+            };
+            body_layers.push((*uuid, layers));
+        }
+        for (index, (segments, polygons)) in layer_debug.iter().enumerate() {
+            if !segments.is_empty() {
+                debugger.inspect_layer(index, slice_z_values[index], segments, polygons);
             }
         }
 
-        true // All vertices are inside, and no edges intersect
+        let layers: Vec<Arc<BodyLayers>> = body_layers
+            .iter()
+            .map(|(_, layers)| Arc::clone(layers))
+            .collect();
+        let images = body_layers::composite(
+            &layers,
+            slice_z_values.len(),
+            printer.pixel_x,
+            printer.pixel_y,
+        );
+        Ok((images, body_layers))
     }
 
-    /// Checks if a point should be considered part of an inside or outside segment of the polygon
-    fn check_point_orientation(position: Vector3<f64>, normal: [f32; 3]) -> i32 {
-        let px = position.x;
-        let py = position.y;
-        let nx = normal[0] as f64;
-        let ny = normal[1] as f64;
+    /// Slices one body at the layer heights of a job starting at `first_z`, up to the top of
+    /// the body, with the tolerances for its size. With `debug` the segments and polygons of
+    /// every layer are returned too.
+    fn slice_body(
+        role: SliceRole,
+        triangles: &[Triangle],
+        first_z: f64,
+        thickness: &LayerThickness,
+        printer: &Printer,
+        supersampling: Supersampling,
+        debug: bool,
+    ) -> (BodyLayers, Vec<LayerDebug>) {
+        let tolerances = TolerancePolicy::for_printer(geometry::longest_side(triangles), printer);
+        let top = geometry::z_range(triangles).map_or(f64::NEG_INFINITY, |(_, max)| max);
+        let (layers, debug): (Vec<_>, Vec<_>) = thickness
+            .slice_heights(first_z, top)
+            .par_iter()
+            .map(|plane_z| {
+                Self::slice_layer(
+                    triangles,
+                    *plane_z,
+                    &tolerances,
+                    printer,
+                    supersampling,
+                    debug,
+                )
+            })
+            .unzip();
+        let layers = BodyLayers {
+            role,
+            first_z,
+            thickness: thickness.clone(),
+            layers,
+        };
+        (layers, debug)
+    }
 
-        // Check if the signs match for both x and y components
-        // 0 matches both signs
-        let x_sign_matches = (px >= 0.0 && nx >= 0.0) || (px <= 0.0 && nx <= 0.0);
-        let y_sign_matches = (py >= 0.0 && ny >= 0.0) || (py <= 0.0 && ny <= 0.0);
+    /// Cuts one body at `plane_z` and rasterizes what it leaves, None where it isn't cut.
+    /// With `debug` the segments and polygons of the cut are returned too.
+    fn slice_layer(
+        triangles: &[Triangle],
+        plane_z: f64,
+        tolerances: &TolerancePolicy,
+        printer: &Printer,
+        supersampling: Supersampling,
+        debug: bool,
+    ) -> (Option<BodyRaster>, LayerDebug) {
+        let segments = {
+            let _timer = profiler::scope(Stage::Intersection);
+            CPUSlicer::collect_intersection_segments(triangles, plane_z, tolerances)
+        };
+        if segments.is_empty() {
+            return (None, (Vec::new(), Vec::new()));
+        }
 
-        // If both x and y signs match, the point is part of an outward-facing segment, otherwise inward
-        if x_sign_matches && y_sign_matches {
-            1 // OUTSIDE
+        let contours: Vec<NestedContour> = {
+            let _timer = profiler::scope(Stage::Assembly);
+            assemble_polygons(&segments, tolerances.assembly)
+                .into_iter()
+                .map(|contour| NestedContour {
+                    points: Self::simplify_polygon(&contour.points, tolerances.simplification),
+                    ..contour
+                })
+                .collect()
+        };
+        let debug = if debug {
+            let polygons = contours.iter().map(|c| c.points.clone()).collect();
+            (segments, polygons)
         } else {
-            -1 // INSIDE
+            (Vec::new(), Vec::new())
+        };
+        if contours.is_empty() {
+            return (None, debug);
         }
+        let image = Self::rasterize_polygons(&contours, printer, supersampling);
+        performance_overlay::slicing_throughput().add_layer();
+        (Some(BodyRaster::crop(&image)), debug)
     }
 
-    // Assembles segments into closed polygons
-    fn assemble_polygons(
-        segments: &[((Vector3<f64>, Vector3<f64>), [f32; 3])],
-    ) -> Vec<(Vec<Vector3<f64>>, Orientation)> {
-        fn point_to_key(p: &Vector3<f64>, epsilon: f64) -> (i64, i64) {
-            let scale = 1.0 / epsilon;
-            let x = (p[0] * scale).round() as i64;
-            let y = (p[1] * scale).round() as i64;
-            (x, y)
+    /// Fills the contours of one body in a layer, each outer boundary with the holes right
+    /// inside it. A region is filled by the even-odd rule: a pixel is cured when a line from
+    /// it crosses its contours an odd number of times, so the bore of a tube stays empty
+    /// however the contours are wound, and an island in the bore is a region of its own.
+    /// With supersampling they are filled at a multiple of the resolution of the LCD and
+    /// scaled down, with anti-aliasing the edges are blurred afterwards. Either leaves them
+    /// gray.
+    fn rasterize_polygons(
+        contours: &[NestedContour],
+        printer: &Printer,
+        supersampling: Supersampling,
+    ) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let samples = supersampling.factor();
+        let sampled = Printer {
+            pixel_x: printer.pixel_x * samples,
+            pixel_y: printer.pixel_y * samples,
+            ..printer.clone()
+        };
+
+        let _timer = profiler::scope(Stage::Rasterization);
+        // A hole belongs to the region of the boundary around it. Holes in holes only
+        // come from broken meshes and are filled as regions of their own.
+        let region_of = |i: usize| match contours[i].parent {
+            Some(parent) if contours[i].is_hole() && !contours[parent].is_hole() => parent,
+            _ => i,
+        };
+        let mut regions: BTreeMap<usize, Vec<&NestedContour>> = BTreeMap::new();
+        for (i, contour) in contours.iter().enumerate() {
+            regions.entry(region_of(i)).or_default().push(contour);
         }
+        let regions: Vec<EvenOddEdges> = regions
+            .into_values()
+            .map(|region| {
+                EvenOddEdges::of(region.into_iter().map(|contour| {
+                    contour.points.iter().map(|p| {
+                        let (x, y) = Self::model_to_lcd_coords(p.x, p.y, &sampled);
+                        (x as f64, y as f64)
+                    })
+                }))
+            })
+            .collect();
 
-        let epsilon = 1e-6;
-        let mut point_coords: HashMap<(i64, i64), (Vector3<f64>, [f32; 3])> = HashMap::new();
-        let mut adjacency: HashMap<(i64, i64), Vec<(i64, i64)>> = HashMap::new();
-
-        // Build adjacency map
-        for &((ref start, ref end), normal) in segments {
-            let start_key = point_to_key(start, epsilon);
-            let end_key = point_to_key(end, epsilon);
-
-            point_coords
-                .entry(start_key)
-                .or_insert_with(|| (*start, normal));
-            point_coords
-                .entry(end_key)
-                .or_insert_with(|| (*end, normal));
-
-            adjacency.entry(start_key).or_default().push(end_key);
-            adjacency.entry(end_key).or_default().push(start_key);
+        let mut image = if samples == 1 {
+            let mut image = ImageBuffer::from_pixel(printer.pixel_x, printer.pixel_y, Luma([0u8]));
+            for edges in &regions {
+                edges.fill(&mut image, (0, 0));
+            }
+            image
+        } else {
+            Self::rasterize_supersampled(&regions, printer, samples)
+        };
+        if !printer.anti_aliasing.is_off() {
+            Self::blur_edges(&mut image, printer.anti_aliasing.levels());
         }
+        image
+    }
 
-        let mut polygons = Vec::new();
-        let mut visited_edges: HashSet<((i64, i64), (i64, i64))> = HashSet::new();
-
-        // Traverse the graph to assemble polygons
-        for &start_key in adjacency.keys() {
-            for &next_key in &adjacency[&start_key] {
-                let edge = (start_key, next_key);
-                if visited_edges.contains(&edge) || visited_edges.contains(&(next_key, start_key)) {
-                    continue;
-                }
-
-                let mut polygon_keys = vec![start_key];
-                let mut current_key = next_key;
-                visited_edges.insert(edge);
-
-                loop {
-                    polygon_keys.push(current_key);
-
-                    if let Some(neighbors) = adjacency.get(&current_key) {
-                        // Find the next neighbor that hasn't been visited
-                        let mut found = false;
-                        for &neighbor_key in neighbors {
-                            let edge = (current_key, neighbor_key);
-                            if neighbor_key != polygon_keys[polygon_keys.len() - 2]
-                                && !visited_edges.contains(&edge)
-                                && !visited_edges.contains(&(neighbor_key, current_key))
-                            {
-                                visited_edges.insert(edge);
-                                current_key = neighbor_key;
-                                found = true;
-                                break;
-                            }
-                        }
-
-                        if !found {
-                            break;
-                        }
-
-                        // Check if the polygon is closed
-                        if current_key == start_key {
-                            break;
+    /// Fills the regions, given in samples, band by band and averages the samples of every
+    /// pixel. Only the columns the regions cover are filled.
+    fn rasterize_supersampled(
+        regions: &[EvenOddEdges],
+        printer: &Printer,
+        samples: u32,
+    ) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let mut image = ImageBuffer::from_pixel(printer.pixel_x, printer.pixel_y, Luma([0u8]));
+        let Some((min_x, max_x)) = regions
+            .iter()
+            .filter_map(EvenOddEdges::x_range)
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+        else {
+            return image;
+        };
+        let first_column = (min_x.max(0.0) as u32 / samples).min(printer.pixel_x);
+        let end_column = ((max_x.max(0.0) as u32 / samples) + 1).min(printer.pixel_x);
+        if first_column >= end_column {
+            return image;
+        }
+        let columns = end_column - first_column;
+        let left = (first_column * samples) as i32;
+
+        let area = (samples * samples) as u64;
+        let band_rows = (MAX_BAND_SAMPLES / (columns as u64 * area)).max(1) as u32;
+        for band_start in (0..printer.pixel_y).step_by(band_rows as usize) {
+            let rows = band_rows.min(printer.pixel_y - band_start);
+            let top = (band_start * samples) as i32;
+            let mut band = ImageBuffer::from_pixel(columns * samples, rows * samples, Luma([0u8]));
+            let mut filled = false;
+            for edges in regions {
+                filled |= edges.fill(&mut band, (left, top));
+            }
+            if !filled {
+                continue;
+            }
+            for row in 0..rows {
+                for column in 0..columns {
+                    let mut sum = 0u64;
+                    for sy in 0..samples {
+                        for sx in 0..samples {
+                            sum +=
+                                band.get_pixel(column * samples + sx, row * samples + sy)[0] as u64;
                         }
-                    } else {
-                        break;
-                    }
-                }
-
-                // If we have a closed polygon
-                if polygon_keys.len() >= 3 && current_key == start_key {
-                    // Convert keys back to points
-                    let polygon: Vec<Vector3<f64>> =
-                        polygon_keys.iter().map(|key| point_coords[key].0).collect();
-                    let normals: Vec<[f32; 3]> = polygon
-                        .iter()
-                        .map(|point| point_coords[&point_to_key(point, epsilon)].1)
-                        .collect();
-
-                    // **Calculate the centroid of the polygon**
-                    let centroid = Self::calculate_centroid(&polygon);
-
-                    // **Sum the orientations of the points offset by the centroid, weighted by segment length**
-                    let mut orientation_sum = 0.0;
-
-                    for i in 0..polygon.len() {
-                        let point = polygon[i];
-                        let last_point = if i == 0 {
-                            polygon[polygon.len() - 1] // Wrap around to the last point for the first point
-                        } else {
-                            polygon[i - 1] // Normal case: get the previous point
-                        };
-
-                        // Calculate the segment length between the current point and the last point
-                        let segment_length = (point - last_point).norm();
-
-                        // Offset the current point by subtracting the centroid
-                        let offset_point = point - centroid;
-
-                        // Calculate the weighted orientation, multiplying by the segment length
-                        orientation_sum += Self::check_point_orientation(offset_point, normals[i])
-                            as f64
-                            * segment_length;
                     }
-
-                    // Decide if the polygon is an exterior or a hole based on the sum of orientations
-                    let orientation = if orientation_sum >= 0.0 {
-                        Orientation::OUTSIDE
-                    } else {
-                        Orientation::INSIDE
-                    };
-                    polygons.push((polygon, orientation));
+                    let value = ((sum + area / 2) / area) as u8;
+                    image.put_pixel(first_column + column, band_start + row, Luma([value]));
                 }
             }
         }
-        polygons
+        image
     }
 
-    /// Calculates the centroid of a polygon (assumes a 2D polygon in 3D space)
-    fn calculate_centroid(polygon: &[Vector3<f64>]) -> Vector3<f64> {
-        let mut centroid = Vector3::new(0.0, 0.0, 0.0);
-        for point in polygon {
-            centroid += *point;
+    /// Gives every pixel on an edge the mean of the 3×3 pixels around it, rounded to one of
+    /// `levels` steps between black and white. Pixels with nothing but their own value
+    /// around them keep it, so only the outlines turn gray.
+    fn blur_edges(image: &mut ImageBuffer<Luma<u8>, Vec<u8>>, levels: u32) {
+        let (width, height) = image.dimensions();
+        let lit: Option<(u32, u32, u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] != 0)
+            .fold(None, |bounds, (x, y, _)| match bounds {
+                Some((min_x, min_y, max_x, max_y)) => {
+                    Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)))
+                }
+                None => Some((x, y, x, y)),
+            });
+        let Some((min_x, min_y, max_x, max_y)) = lit else {
+            return;
+        };
+        // The blur reaches one pixel past the lit ones
+        let (left, top) = (min_x.saturating_sub(1), min_y.saturating_sub(1));
+        let (right, bottom) = ((max_x + 2).min(width), (max_y + 2).min(height));
+        let source = imageops::crop_imm(image, left, top, right - left, bottom - top).to_image();
+
+        let step = 255.0 / levels as f32;
+        for y in 0..source.height() {
+            for x in 0..source.width() {
+                let (mut sum, mut count) = (0u32, 0u32);
+                let (mut darkest, mut brightest) = (u8::MAX, u8::MIN);
+                for ny in y.saturating_sub(1)..(y + 2).min(source.height()) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(source.width()) {
+                        let value = source.get_pixel(nx, ny)[0];
+                        sum += value as u32;
+                        count += 1;
+                        darkest = darkest.min(value);
+                        brightest = brightest.max(value);
+                    }
+                }
+                if darkest == brightest {
+                    continue;
+                }
+                let mean = sum as f32 / count as f32;
+                let value = ((mean / step).round() * step).round() as u8;
+                image.put_pixel(left + x, top + y, Luma([value]));
+            }
         }
-        centroid /= polygon.len() as f64;
-        centroid
     }
 
-    // Determine the Z-axis range of the model
-    fn z_range(triangles: &[Triangle]) -> (f64, f64) {
-        let z_coords: Vec<f64> = triangles
-            .iter()
-            .flat_map(|tri| tri.vertices.iter().map(|v| v[2] as f64))
-            .collect();
-
-        let min_z = z_coords.iter().cloned().fold(f64::INFINITY, f64::min);
-        let max_z = z_coords.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
-        (min_z, max_z)
-    }
-
-    // Compute the intersection of a triangle with a horizontal plane at z = plane_z
-    fn intersect_triangle_with_plane(triangle: &Triangle, plane_z: f64) -> Vec<Vector3<f64>> {
-        let epsilon = 1e-6; // Tolerance for floating-point comparisons
-
+    // Compute the intersection of a triangle with a horizontal plane at z = plane_z,
+    // vertices within epsilon of the plane count as on it
+    fn intersect_triangle_with_plane(
+        triangle: &Triangle,
+        plane_z: f64,
+        epsilon: f64,
+    ) -> Vec<Vector3<f64>> {
         let points: Vec<Vector3<f64>> = triangle
             .vertices
             .iter()
-            .map(|v| Vector3::new(v[0] as f64, v[1] as f64, v[2] as f64))
+            .map(|&v| geometry::to_f64(v))
             .collect();
 
         let distances: Vec<f64> = points.iter().map(|p| p[2] - plane_z).collect();
@@ -468,7 +612,12 @@ impl CPUSlicer {
             }
         }
 
-        // Remove duplicate points
+        Self::dedup_intersections(&mut intersections, epsilon);
+        intersections
+    }
+
+    // Remove duplicate points
+    fn dedup_intersections(intersections: &mut Vec<Vector3<f64>>, epsilon: f64) {
         intersections.sort_by(|a, b| {
             a[0].partial_cmp(&b[0])
                 .unwrap_or(std::cmp::Ordering::Equal)
@@ -476,23 +625,96 @@ impl CPUSlicer {
                 .then(a[2].partial_cmp(&b[2]).unwrap_or(std::cmp::Ordering::Equal))
         });
         intersections.dedup_by(|a, b| a.metric_distance(b) < epsilon);
+    }
 
+    /// SIMD version of `intersect_triangle_with_plane` for four triangles at once. Most
+    /// triangles don't reach a given plane, so whole batches are usually rejected with
+    /// a single comparison. The results are bit-identical to the scalar version.
+    fn intersect_triangles_with_plane_x4(
+        triangles: &[Triangle; 4],
+        plane_z: f64,
+        epsilon: f64,
+    ) -> [Vec<Vector3<f64>>; 4] {
+        let mut intersections: [Vec<Vector3<f64>>; 4] = Default::default();
+        let coordinate = |vertex: usize, axis: usize| {
+            f64x4::from(triangles.each_ref().map(|t| t.vertices[vertex][axis] as f64))
+        };
+        let lane_epsilon = f64x4::splat(epsilon);
+        let distances = [0, 1, 2].map(|vertex| coordinate(vertex, 2) - f64x4::splat(plane_z));
+        let above = distances.map(|d| d.cmp_gt(lane_epsilon));
+        let below = distances.map(|d| d.cmp_lt(-lane_epsilon));
+
+        // Only triangles with points on both sides of the plane intersect it
+        let crossing = (above[0] | above[1] | above[2]) & (below[0] | below[1] | below[2]);
+        if crossing.none() {
+            return intersections;
+        }
+
+        let points = [0, 1, 2].map(|vertex| [0, 1, 2].map(|axis| coordinate(vertex, axis)));
+        for i in 0..3 {
+            let j = (i + 1) % 3;
+            let edge_crosses = (above[i] & below[j]) | (below[i] & above[j]);
+            let lanes = (edge_crosses & crossing).move_mask();
+            if lanes == 0 {
+                continue;
+            }
+            let t = distances[i] / (distances[i] - distances[j]);
+            let point = [0, 1, 2].map(|axis| {
+                (points[i][axis] + (points[j][axis] - points[i][axis]) * t).to_array()
+            });
+            for (lane, lane_intersections) in intersections.iter_mut().enumerate() {
+                if lanes & (1 << lane) != 0 {
+                    lane_intersections.push(Vector3::new(
+                        point[0][lane],
+                        point[1][lane],
+                        point[2][lane],
+                    ));
+                }
+            }
+        }
+
+        for lane_intersections in intersections.iter_mut() {
+            Self::dedup_intersections(lane_intersections, epsilon);
+        }
         intersections
     }
 
+    /// Intersects every triangle with the plane, four at a time with the remainder
+    /// going through the scalar path, calling `f` with the points of each triangle in order
+    fn for_each_triangle_intersection<'a>(
+        triangles: &'a [Triangle],
+        plane_z: f64,
+        epsilon: f64,
+        mut f: impl FnMut(&'a Triangle, Vec<Vector3<f64>>),
+    ) {
+        let mut batches = triangles.chunks_exact(4);
+        for batch in &mut batches {
+            let batch: &[Triangle; 4] = batch.try_into().unwrap();
+            let intersections = Self::intersect_triangles_with_plane_x4(batch, plane_z, epsilon);
+            for (triangle, points) in batch.iter().zip(intersections) {
+                f(triangle, points);
+            }
+        }
+        for triangle in batches.remainder() {
+            let points = Self::intersect_triangle_with_plane(triangle, plane_z, epsilon);
+            f(triangle, points);
+        }
+    }
+
     // Collect all intersection segments at a given plane_z
     fn collect_intersection_segments(
         triangles: &[Triangle],
         plane_z: f64,
+        tolerances: &TolerancePolicy,
     ) -> Vec<((Vector3<f64>, Vector3<f64>), [f32; 3])> {
         let mut segments = Vec::new();
         let mut seen_segments = HashSet::new(); // To track unique segments
+        let scale = 1.0 / tolerances.assembly;
 
-        for triangle in triangles {
-            let intersection_points = CPUSlicer::intersect_triangle_with_plane(triangle, plane_z);
-
-            if intersection_points.len() == 2 {
-                let mut segment = (intersection_points[0], intersection_points[1]);
+        let epsilon = tolerances.intersection;
+        Self::for_each_triangle_intersection(triangles, plane_z, epsilon, |triangle, points| {
+            if points.len() == 2 {
+                let mut segment = (points[0], points[1]);
 
                 // Sort the segment endpoints to ensure consistency
                 if (segment.0[0], segment.0[1]) > (segment.1[0], segment.1[1]) {
@@ -501,23 +723,23 @@ impl CPUSlicer {
 
                 // Create a unique key for the segment
                 let key = (
-                    (segment.0[0] * 1e6).round() as i64,
-                    (segment.0[1] * 1e6).round() as i64,
-                    (segment.1[0] * 1e6).round() as i64,
-                    (segment.1[1] * 1e6).round() as i64,
+                    (segment.0[0] * scale).round() as i64,
+                    (segment.0[1] * scale).round() as i64,
+                    (segment.1[0] * scale).round() as i64,
+                    (segment.1[1] * scale).round() as i64,
                 );
 
                 if !seen_segments.contains(&key) {
                     segments.push((segment, triangle.normal)); // push the segment with the triangle's normal
                     seen_segments.insert(key);
                 }
-            } else if intersection_points.len() > 2 {
+            } else if points.len() > 2 {
                 debug!(
                     "Skipped a triangle intersecting the plane in multiple points at z={}",
                     plane_z
                 );
             }
-        }
+        });
         segments
     }
 
@@ -534,8 +756,124 @@ impl CPUSlicer {
         area
     }
 
+    /// Douglas-Peucker simplification of a closed polygon, whose last point connects to
+    /// the first. Removes the runs of nearly collinear micro-segments dense meshes produce
+    /// while keeping every remaining point within `tolerance` of the original outline.
+    fn simplify_polygon(polygon: &[Vector3<f64>], tolerance: f64) -> Vec<Vector3<f64>> {
+        let n = polygon.len();
+        if n <= 3 || tolerance <= 0.0 {
+            return polygon.to_vec();
+        }
+
+        // Split the ring at the point farthest from the first one, so both halves are open
+        let farthest = (1..n)
+            .max_by(|&a, &b| {
+                let da = (polygon[a] - polygon[0]).xy().norm_squared();
+                let db = (polygon[b] - polygon[0]).xy().norm_squared();
+                da.total_cmp(&db)
+            })
+            .unwrap();
+
+        let mut keep = vec![false; n];
+        keep[0] = true;
+        keep[farthest] = true;
+        // Index n stands for the first point again, closing the ring
+        let mut ranges = vec![(0, farthest), (farthest, n)];
+        while let Some((start, end)) = ranges.pop() {
+            if end - start < 2 {
+                continue;
+            }
+            let a = polygon[start].xy();
+            let b = polygon[end % n].xy();
+            let (index, distance) = (start + 1..end)
+                .map(|i| (i, Self::distance_to_segment(polygon[i].xy(), a, b)))
+                .max_by(|x, y| x.1.total_cmp(&y.1))
+                .unwrap();
+            if distance > tolerance {
+                keep[index] = true;
+                ranges.push((start, index));
+                ranges.push((index, end));
+            }
+        }
+
+        let simplified: Vec<Vector3<f64>> = polygon
+            .iter()
+            .zip(keep)
+            .filter_map(|(point, keep)| keep.then_some(*point))
+            .collect();
+        // A polygon thinner than the tolerance would collapse, leave it to the rasterizer
+        if simplified.len() < 3 {
+            return polygon.to_vec();
+        }
+        simplified
+    }
+
+    fn distance_to_segment(p: Vector2<f64>, a: Vector2<f64>, b: Vector2<f64>) -> f64 {
+        let ab = b - a;
+        let length_squared = ab.norm_squared();
+        if length_squared == 0.0 {
+            return (p - a).norm();
+        }
+        let t = ((p - a).dot(&ab) / length_squared).clamp(0.0, 1.0);
+        (p - (a + ab * t)).norm()
+    }
+
+    /// Pixel of the LCD of the printer showing a point of the plate, honoring its orientation
+    pub fn model_to_lcd_coords(x: f64, y: f64, printer: &Printer) -> (i32, i32) {
+        let (x, y) = printer.lcd_orientation.apply(x, y);
+        Self::model_to_image_coords(
+            x,
+            y,
+            printer.pixel_x,
+            printer.physical_x,
+            printer.pixel_y,
+            printer.physical_y,
+        )
+    }
+
+    /// Point of the plate shown by a pixel of the LCD of the printer, the inverse of
+    /// `model_to_lcd_coords` without its rounding
+    pub fn lcd_to_model_coords(x: f64, y: f64, printer: &Printer) -> (f64, f64) {
+        let x = (x - printer.pixel_x as f64 / 2.0) * printer.physical_x / printer.pixel_x as f64;
+        let y = (y - printer.pixel_y as f64 / 2.0) * printer.physical_y / printer.pixel_y as f64;
+        printer.lcd_orientation.invert(x, y)
+    }
+
+    /// Outlines of a layer as the LCD of the printer will show them, scaled down to
+    /// `width` pixels wide so the orientation can be checked while previewing layers
+    pub fn lcd_outline_preview(
+        contours: &[Vec<Vector3<f64>>],
+        printer: &Printer,
+        width: u32,
+    ) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let height = ((width as f64 * printer.pixel_y as f64 / printer.pixel_x as f64).round()
+            as u32)
+            .max(1);
+        let thumbnail = Printer {
+            pixel_x: width,
+            pixel_y: height,
+            ..printer.clone()
+        };
+        let mut image = ImageBuffer::from_pixel(width, height, Luma([LCD_PREVIEW_BACKGROUND]));
+        for contour in contours {
+            for i in 0..contour.len() {
+                let a = contour[i];
+                let b = contour[(i + 1) % contour.len()];
+                let (ax, ay) = Self::model_to_lcd_coords(a.x, a.y, &thumbnail);
+                let (bx, by) = Self::model_to_lcd_coords(b.x, b.y, &thumbnail);
+                draw_line_segment_mut(
+                    &mut image,
+                    (ax as f32, ay as f32),
+                    (bx as f32, by as f32),
+                    Luma([255u8]),
+                );
+            }
+        }
+        image
+    }
+
     // Translates points so that that 0,0 is at the center of the image
-    fn model_to_image_coords(
+    pub fn model_to_image_coords(
         x: f64,
         y: f64,
         pixel_x: u32,
@@ -555,7 +893,94 @@ impl CPUSlicer {
         let image_x = scaled_x + (pixel_x as f64 / 2.0);
         let image_y = scaled_y + (pixel_y as f64 / 2.0);
 
-        (image_x.round() as i32, image_y.round() as i32)
+        // Points this far out are way off any LCD, and clamping them keeps drawing lines
+        // to them from overflowing
+        let limit = MAX_IMAGE_COORDINATE as f64;
+        (
+            image_x.clamp(-limit, limit).round() as i32,
+            image_y.clamp(-limit, limit).round() as i32,
+        )
+    }
+}
+
+/// The edges of the contours of a layer in image coordinates, for filling them by the
+/// even-odd rule
+struct EvenOddEdges {
+    /// From the end with the smaller y to the other, sorted by that end. Horizontal edges
+    /// never cross a row and are left out.
+    edges: Vec<Edge>,
+}
+
+type Edge = ((f64, f64), (f64, f64));
+
+impl EvenOddEdges {
+    fn of<C: IntoIterator<Item = (f64, f64)>>(contours: impl Iterator<Item = C>) -> Self {
+        let mut edges = Vec::new();
+        for contour in contours {
+            let points: Vec<(f64, f64)> = contour.into_iter().collect();
+            for (i, &a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                match a.1.total_cmp(&b.1) {
+                    Ordering::Less => edges.push((a, b)),
+                    Ordering::Greater => edges.push((b, a)),
+                    Ordering::Equal => {}
+                }
+            }
+        }
+        edges.sort_by(|a, b| a.0 .1.total_cmp(&b.0 .1));
+        Self { edges }
+    }
+
+    fn x_range(&self) -> Option<(f64, f64)> {
+        let xs = self.edges.iter().flat_map(|&(a, b)| [a.0, b.0]);
+        xs.fold(None, |range, x| match range {
+            None => Some((x, x)),
+            Some((min, max)) => Some((f64::min(min, x), f64::max(max, x))),
+        })
+    }
+
+    /// Cures the pixels of `image` inside an odd number of contours, the image showing the
+    /// part of the LCD from `origin` on. A row crosses the edges that start on it but not
+    /// the ones that end on it, so a vertex where two edges meet counts once. Pixels cured
+    /// before stay cured. Returns whether any pixel was cured.
+    fn fill(&self, image: &mut ImageBuffer<Luma<u8>, Vec<u8>>, origin: (i32, i32)) -> bool {
+        let (width, height) = image.dimensions();
+        let mut next = 0;
+        let mut active: Vec<Edge> = Vec::new();
+        let mut crossings: Vec<f64> = Vec::new();
+        let mut filled = false;
+        // Only the rows from the top of the contours down to their bottom
+        let first_row = self.edges.first().map_or(height, |&(top, _)| {
+            (top.1.ceil() - origin.1 as f64).clamp(0.0, height as f64) as u32
+        });
+        for row in first_row..height {
+            let y = (origin.1 + row as i32) as f64;
+            while next < self.edges.len() && self.edges[next].0 .1 <= y {
+                active.push(self.edges[next]);
+                next += 1;
+            }
+            active.retain(|&(_, bottom)| bottom.1 > y);
+            if active.is_empty() && next == self.edges.len() {
+                break;
+            }
+            crossings.clear();
+            crossings.extend(
+                active
+                    .iter()
+                    .map(|&(a, b)| a.0 + (y - a.1) * (b.0 - a.0) / (b.1 - a.1)),
+            );
+            crossings.sort_by(f64::total_cmp);
+            // Inside from the first crossing of each pair up to the second
+            for span in crossings.chunks_exact(2) {
+                let column = |x: f64| (x.ceil() - origin.0 as f64).clamp(0.0, width as f64) as u32;
+                let (from, to) = (column(span[0]), column(span[1]));
+                for x in from..to {
+                    image.put_pixel(x, row, Luma([255]));
+                }
+                filled |= from < to;
+            }
+        }
+        filled
     }
 }
 
@@ -566,108 +991,512 @@ pub enum CPUSlicerError {
 
     #[error("Thread join error: {0}")]
     ThreadJoinError(String),
+
+    #[error(transparent)]
+    CalibrationMask(#[from] CalibrationMaskError),
+
+    #[error(transparent)]
+    Export(#[from] ExportError),
+
+    #[cfg(feature = "network-printing")]
+    #[error(transparent)]
+    NetworkPrinter(#[from] NetworkPrinterError),
+
+    #[error("Could not write slices: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error(
+        "Slicing needs about {} but only {} is available. Try a resolution scale of {suggested_scale:.2} or fewer layers",
+        memory_budget::format_bytes(*required),
+        memory_budget::format_bytes(*available)
+    )]
+    InsufficientMemory {
+        required: u64,
+        available: u64,
+        suggested_scale: f64,
+    },
 }
 
 #[cfg(test)]
 mod tests {
     use crate::mesh::Mesh;
+    use crate::polygon_assembly::nest_contours;
+    use crate::printer::{AntiAliasing, LcdOrientation};
     use crate::stl_processor::StlProcessor;
 
     use super::*;
-    use geo::{LineString, Polygon};
     use nalgebra::Vector3;
+    use uuid::Uuid;
+
+    /// Triangles with pseudo-random vertices in a 10mm cube, including some that touch
+    /// the plane at z = 5 with one or two vertices
+    fn pseudo_random_triangles(count: usize) -> Vec<Triangle> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 10_000) as f32 / 1_000.0
+        };
+        (0..count)
+            .map(|i| {
+                let mut vertices = [[0.0; 3]; 3];
+                for vertex in vertices.iter_mut() {
+                    *vertex = [next(), next(), next()];
+                }
+                for vertex in vertices.iter_mut().take(i % 3) {
+                    vertex[2] = 5.0;
+                }
+                Triangle {
+                    normal: [0.0, 0.0, 1.0],
+                    vertices,
+                }
+            })
+            .collect()
+    }
 
     #[test]
-    fn test_is_polygon_inside() {
-        // Create an outer polygon (a square)
-        let outer_polygon = Polygon::new(
-            LineString::from(vec![
-                (0.0, 0.0),
-                (0.0, 10.0),
-                (10.0, 10.0),
-                (10.0, 0.0),
-                (0.0, 0.0),
-            ]),
-            vec![],
-        );
+    fn test_simd_intersection_matches_scalar() {
+        // Not a multiple of 4, so the scalar remainder path is covered as well
+        let triangles = pseudo_random_triangles(1001);
+        for plane_z in [0.5, 2.25, 5.0, 7.3] {
+            let mut simd = Vec::new();
+            CPUSlicer::for_each_triangle_intersection(&triangles, plane_z, 1e-6, |_, points| {
+                simd.push(points)
+            });
+            let scalar: Vec<_> = triangles
+                .iter()
+                .map(|t| CPUSlicer::intersect_triangle_with_plane(t, plane_z, 1e-6))
+                .collect();
+            assert_eq!(simd, scalar);
+            assert!(scalar.iter().any(|points| points.len() == 2));
+        }
+    }
 
-        // Create an inner polygon (a smaller square)
-        let inner_polygon = Polygon::new(
-            LineString::from(vec![
-                (1.0, 1.0),
-                (1.0, 9.0),
-                (9.0, 9.0),
-                (9.0, 1.0),
-                (1.0, 1.0),
-            ]),
-            vec![],
+    /// Compares the scalar and SIMD intersection paths, run with
+    /// `cargo test --release bench_triangle_plane_intersection -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_triangle_plane_intersection() {
+        use std::time::Instant;
+
+        let triangles = pseudo_random_triangles(200_000);
+        let planes: Vec<f64> = (0..100).map(|i| i as f64 * 0.1).collect();
+
+        let start = Instant::now();
+        let mut scalar_points = 0;
+        for &plane_z in &planes {
+            for triangle in &triangles {
+                scalar_points +=
+                    CPUSlicer::intersect_triangle_with_plane(triangle, plane_z, 1e-6).len();
+            }
+        }
+        let scalar = start.elapsed();
+
+        let start = Instant::now();
+        let mut simd_points = 0;
+        for &plane_z in &planes {
+            CPUSlicer::for_each_triangle_intersection(&triangles, plane_z, 1e-6, |_, points| {
+                simd_points += points.len()
+            });
+        }
+        let simd = start.elapsed();
+
+        assert_eq!(scalar_points, simd_points);
+        println!(
+            "scalar: {:?}, simd: {:?}, speedup: {:.2}x",
+            scalar,
+            simd,
+            scalar.as_secs_f64() / simd.as_secs_f64()
         );
+    }
 
-        assert!(CPUSlicer::is_polygon_inside(&inner_polygon, &outer_polygon));
-        assert!(!CPUSlicer::is_polygon_inside(
-            &outer_polygon,
-            &inner_polygon
-        ));
-
-        // Create a non-nested polygon (overlapping but not contained)
-        let overlapping_polygon = Polygon::new(
-            LineString::from(vec![
-                (5.0, 5.0),
-                (5.0, 15.0),
-                (15.0, 15.0),
-                (15.0, 5.0),
-                (5.0, 5.0),
-            ]),
-            vec![],
-        );
+    #[test]
+    fn test_simplify_polygon_removes_collinear_points() {
+        // A 10mm square with a point every 0.01mm along its sides
+        let mut square = Vec::new();
+        for (start, direction) in [
+            (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0)),
+            (Vector3::new(10.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0)),
+            (Vector3::new(10.0, 10.0, 1.0), Vector3::new(-1.0, 0.0, 0.0)),
+            (Vector3::new(0.0, 10.0, 1.0), Vector3::new(0.0, -1.0, 0.0)),
+        ] {
+            for i in 0..1000 {
+                square.push(start + direction * (i as f64 * 0.01));
+            }
+        }
+
+        let simplified = CPUSlicer::simplify_polygon(&square, 0.01);
+
+        assert_eq!(simplified.len(), 4);
+        for corner in [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)] {
+            assert!(simplified.contains(&Vector3::new(corner.0, corner.1, 1.0)));
+        }
+    }
+
+    #[test]
+    fn test_simplify_polygon_stays_within_tolerance() {
+        let circle: Vec<Vector3<f64>> = (0..5000)
+            .map(|i| {
+                let angle = i as f64 / 5000.0 * std::f64::consts::TAU;
+                Vector3::new(20.0 * angle.cos(), 20.0 * angle.sin(), 0.0)
+            })
+            .collect();
+        let tolerance = 0.025;
+
+        let simplified = CPUSlicer::simplify_polygon(&circle, tolerance);
+
+        assert!(simplified.len() < circle.len() / 10);
+        for point in &circle {
+            let distance = (0..simplified.len())
+                .map(|i| {
+                    let a = simplified[i].xy();
+                    let b = simplified[(i + 1) % simplified.len()].xy();
+                    CPUSlicer::distance_to_segment(point.xy(), a, b)
+                })
+                .fold(f64::INFINITY, f64::min);
+            assert!(distance <= tolerance + 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_slicing_is_deterministic() {
+        let stl_processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &stl_processor).unwrap();
+        let body = Body::new(mesh);
+        let triangles = CPUSlicer::world_triangles([&body], Vector3::new(1.0, 1.0, 1.0));
+        let (min_z, max_z) = geometry::z_range(&triangles).unwrap();
+        let mid_z = (min_z + max_z) / 2.0;
 
-        assert!(!CPUSlicer::is_polygon_inside(
-            &overlapping_polygon,
-            &outer_polygon
-        ));
-        assert!(!CPUSlicer::is_polygon_inside(
-            &outer_polygon,
-            &overlapping_polygon
-        ));
+        let printer = Printer::default();
+        let tolerances = TolerancePolicy::for_printer(geometry::longest_side(&triangles), &printer);
+        let segments = CPUSlicer::collect_intersection_segments(&triangles, mid_z, &tolerances);
+        let first = assemble_polygons(&segments, tolerances.assembly);
+        for _ in 0..5 {
+            assert_eq!(assemble_polygons(&segments, tolerances.assembly), first);
+        }
+
+        let images = |body: &Body| {
+            CPUSlicer::slice_bodies(
+                vec![body.clone()],
+                &LayerThickness::uniform(0.5),
+                &printer,
+                Vector3::new(1.0, 1.0, 1.0),
+            )
+            .unwrap()
+        };
+        assert_eq!(images(&body), images(&body));
     }
 
     #[test]
-    fn test_classify_and_structure_polygons() {
-        // Define a simple test for classify_and_structure_polygons
-        let polygons = vec![
-            (
-                vec![
-                    Vector3::new(0.0, 0.0, 0.0),
-                    Vector3::new(0.0, 2.0, 0.0),
-                    Vector3::new(2.0, 2.0, 0.0),
-                    Vector3::new(2.0, 0.0, 0.0),
-                ],
-                Orientation::OUTSIDE,
-            ),
-            (
-                vec![
-                    Vector3::new(1.0, 1.0, 0.0),
-                    Vector3::new(1.0, 3.0, 0.0),
-                    Vector3::new(3.0, 3.0, 0.0),
-                    Vector3::new(3.0, 1.0, 0.0),
-                ],
-                Orientation::INSIDE,
-            ),
+    fn test_slice_roles() {
+        let stl_processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &stl_processor).unwrap();
+        let part = Body::new(mesh);
+        let printer = Printer::default();
+        let slice = |bodies: Vec<Body>| {
+            CPUSlicer::slice_bodies(
+                bodies,
+                &LayerThickness::uniform(0.5),
+                &printer,
+                Vector3::new(1.0, 1.0, 1.0),
+            )
+            .unwrap()
+        };
+        let white = |images: &[ImageBuffer<Luma<u8>, Vec<u8>>]| {
+            images
+                .iter()
+                .flat_map(|image| image.pixels())
+                .filter(|pixel| pixel[0] == 255)
+                .count()
+        };
+        let alone = slice(vec![part.clone()]);
+
+        // A narrower, taller plug through the middle of the part
+        let mut plug = part.clone();
+        plug.uuid = Uuid::new_v4();
+        plug.scale = Vector3::new(0.5, 0.5, 2.0);
+        plug.slice_role = SliceRole::Subtract;
+        let cut = slice(vec![plug.clone(), part.clone()]);
+        assert_eq!(cut.len(), alone.len());
+        assert!(white(&cut) < white(&alone));
+        for (cut_image, image) in cut.iter().zip(&alone) {
+            for (cut_pixel, pixel) in cut_image.pixels().zip(image.pixels()) {
+                assert!(cut_pixel[0] <= pixel[0]);
+            }
+        }
+        assert!(slice(vec![plug]).is_empty());
+
+        // Overlapping merged bodies cure the union of both
+        let mut shifted = part.clone();
+        shifted.uuid = Uuid::new_v4();
+        shifted.set_position(Vector3::new(10.0, 0.0, 0.0));
+        let merged = slice(vec![part.clone(), shifted]);
+        assert_eq!(merged.len(), alone.len());
+        assert!(white(&merged) > white(&alone));
+    }
+
+    #[test]
+    fn test_reslice_only_changed_bodies() {
+        let stl_processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &stl_processor).unwrap();
+        let part = Body::new(mesh);
+        let mut plug = part.clone();
+        plug.uuid = Uuid::new_v4();
+        plug.scale = Vector3::new(0.5, 0.5, 2.0);
+        plug.slice_role = SliceRole::Subtract;
+        let mut shifted = part.clone();
+        shifted.uuid = Uuid::new_v4();
+        shifted.set_position(Vector3::new(10.0, 0.0, 0.0));
+        let printer = Printer {
+            pixel_x: 480,
+            pixel_y: 270,
+            ..Printer::default()
+        };
+        let compensation = Vector3::new(1.0, 1.0, 1.0);
+        let slice = |bodies: &[Body], reusable: &HashMap<Uuid, Arc<BodyLayers>>| {
+            CPUSlicer::slice_bodies_reusing(
+                bodies.to_vec(),
+                &LayerThickness::uniform(0.25),
+                &printer,
+                Supersampling::Off,
+                compensation,
+                reusable,
+            )
+            .unwrap()
+        };
+        let fresh = |bodies: &[Body]| {
+            CPUSlicer::slice_bodies(
+                bodies.to_vec(),
+                &LayerThickness::uniform(0.25),
+                &printer,
+                compensation,
+            )
+            .unwrap()
+        };
+
+        let first = [part.clone(), plug.clone(), shifted.clone()];
+        let (_, body_layers) = slice(&first, &HashMap::new());
+        // The layers of the bodies that stay as they are
+        let reusable: HashMap<_, _> = body_layers
+            .into_iter()
+            .filter(|(uuid, _)| *uuid != shifted.uuid)
+            .collect();
+
+        // Nudging one body keeps the layer heights, so only it is sliced again
+        shifted.set_position(Vector3::new(12.0, 3.0, 0.0));
+        let bodies = [part.clone(), plug.clone(), shifted.clone()];
+        let (images, body_layers) = slice(&bodies, &reusable);
+        assert!(images.len() > 1);
+        assert_eq!(images, fresh(&bodies));
+        assert!(Arc::ptr_eq(&body_layers[0].1, &reusable[&part.uuid]));
+        assert!(Arc::ptr_eq(&body_layers[1].1, &reusable[&plug.uuid]));
+
+        // Lowering it moves the first layer, which leaves nothing to reuse
+        shifted.set_position(Vector3::new(12.0, 3.0, -0.05));
+        let bodies = [part.clone(), plug, shifted];
+        let (images, body_layers) = slice(&bodies, &reusable);
+        assert_eq!(images, fresh(&bodies));
+        assert!(!Arc::ptr_eq(&body_layers[0].1, &reusable[&part.uuid]));
+    }
+
+    #[test]
+    fn test_image_coords_are_clamped() {
+        let clamped = |x: f64| CPUSlicer::model_to_image_coords(x, 0.0, 100, 10.0, 100, 10.0).0;
+        assert_eq!(clamped(1.0), 60);
+        assert_eq!(clamped(1e300), MAX_IMAGE_COORDINATE);
+        assert_eq!(clamped(f64::NEG_INFINITY), -MAX_IMAGE_COORDINATE);
+    }
+
+    #[test]
+    fn test_lcd_orientation() {
+        let stl_processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &stl_processor).unwrap();
+        let mut part = Body::new(mesh);
+        part.set_position(Vector3::new(50.0, 0.0, 0.0));
+        let upright = Printer::default();
+        let mirrored = Printer {
+            lcd_orientation: LcdOrientation {
+                mirror_x: true,
+                ..LcdOrientation::default()
+            },
+            ..Printer::default()
+        };
+        // White pixels in the left and right halves of an image
+        let halves = |image: &ImageBuffer<Luma<u8>, Vec<u8>>| {
+            let mut halves = (0, 0);
+            for (x, _, pixel) in image.enumerate_pixels() {
+                if pixel[0] == 255 {
+                    if x < image.width() / 2 {
+                        halves.0 += 1;
+                    } else {
+                        halves.1 += 1;
+                    }
+                }
+            }
+            halves
+        };
+        let slice = |printer: &Printer| {
+            CPUSlicer::slice_bodies(
+                vec![part.clone()],
+                &LayerThickness::uniform(0.5),
+                printer,
+                Vector3::new(1.0, 1.0, 1.0),
+            )
+            .unwrap()
+        };
+
+        let upright_layers = slice(&upright);
+        let mirrored_layers = slice(&mirrored);
+        let middle = upright_layers.len() / 2;
+        let (left, right) = halves(&upright_layers[middle]);
+        assert_eq!(left, 0);
+        assert!(right > 0);
+        let (left, right) = halves(&mirrored_layers[middle]);
+        assert!(left > 0);
+        assert_eq!(right, 0);
+
+        // The layer preview shows the same flip
+        let contours = CPUSlicer::layer_contours([&part], 5.0);
+        assert_eq!(halves(&CPUSlicer::lcd_outline_preview(&contours, &upright, 200)).0, 0);
+        assert_eq!(halves(&CPUSlicer::lcd_outline_preview(&contours, &mirrored, 200)).1, 0);
+
+        let swapped = LcdOrientation {
+            swap_xy: true,
+            mirror_y: true,
+            ..LcdOrientation::default()
+        };
+        assert_eq!(swapped.apply(1.0, 2.0), (2.0, -1.0));
+        assert_eq!(swapped.invert(2.0, -1.0), (1.0, 2.0));
+        let (x, y) = CPUSlicer::model_to_lcd_coords(30.0, -12.0, &mirrored);
+        let (x, y) = CPUSlicer::lcd_to_model_coords(x as f64, y as f64, &mirrored);
+        assert!((x - 30.0).abs() < 0.1 && (y + 12.0).abs() < 0.1);
+        assert!(LcdOrientation::default().is_identity());
+    }
+
+    #[test]
+    fn test_holes_stay_empty() {
+        let square = |half: f64| {
+            vec![
+                Vector3::new(-half, -half, 0.0),
+                Vector3::new(half, -half, 0.0),
+                Vector3::new(half, half, 0.0),
+                Vector3::new(-half, half, 0.0),
+            ]
+        };
+        let printer = Printer::default();
+        let pixel = |x: f64, image: &ImageBuffer<Luma<u8>, Vec<u8>>| {
+            let (x, y) = CPUSlicer::model_to_lcd_coords(x, 0.0, &printer);
+            image.get_pixel(x as u32, y as u32)[0]
+        };
+        // A tube, with both walls wound the same way, and a rod inside its bore
+        let contours = nest_contours(vec![square(5.0), square(3.0), square(1.0)]);
+        let layer = CPUSlicer::rasterize_polygons(&contours, &printer, Supersampling::Off);
+        assert_eq!(pixel(4.0, &layer), 255);
+        assert_eq!(pixel(2.0, &layer), 0);
+        assert_eq!(pixel(0.0, &layer), 255);
+        assert_eq!(pixel(6.0, &layer), 0);
+        assert!(layer.pixels().all(|pixel| pixel[0] == 0 || pixel[0] == 255));
+    }
+
+    #[test]
+    fn test_anti_aliasing() {
+        // A square that doesn't line up with the pixels
+        let square = vec![
+            Vector3::new(-3.01, -3.01, 0.0),
+            Vector3::new(3.01, -3.01, 0.0),
+            Vector3::new(3.01, 3.01, 0.0),
+            Vector3::new(-3.01, 3.01, 0.0),
+        ];
+        let rasterize = |anti_aliasing: AntiAliasing| {
+            let printer = Printer {
+                anti_aliasing,
+                ..Printer::default()
+            };
+            CPUSlicer::rasterize_polygons(
+                &nest_contours(vec![square.clone()]),
+                &printer,
+                Supersampling::Off,
+            )
+        };
+        let is_gray = |pixel: &Luma<u8>| pixel[0] != 0 && pixel[0] != 255;
+        let total = |image: &ImageBuffer<Luma<u8>, Vec<u8>>| {
+            image.pixels().map(|pixel| pixel[0] as u64).sum::<u64>()
+        };
+
+        let hard = rasterize(AntiAliasing::Off);
+        assert!(!hard.pixels().any(is_gray));
+        for anti_aliasing in [AntiAliasing::X2, AntiAliasing::X4, AntiAliasing::X8] {
+            let smooth = rasterize(anti_aliasing);
+            assert_eq!(smooth.dimensions(), hard.dimensions());
+            assert!(smooth.pixels().any(is_gray));
+            // The same area is lit, give or take the edges
+            let (hard, smooth) = (total(&hard) as f64, total(&smooth) as f64);
+            assert!((hard - smooth).abs() / hard < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_supersampling() {
+        // A square that doesn't line up with the pixels
+        let square = vec![
+            Vector3::new(-3.01, -3.01, 0.0),
+            Vector3::new(3.01, -3.01, 0.0),
+            Vector3::new(3.01, 3.01, 0.0),
+            Vector3::new(-3.01, 3.01, 0.0),
         ];
+        let rasterize = |printer: &Printer, supersampling: Supersampling| {
+            CPUSlicer::rasterize_polygons(
+                &nest_contours(vec![square.clone()]),
+                printer,
+                supersampling,
+            )
+        };
+        let is_gray = |pixel: &&Luma<u8>| pixel[0] != 0 && pixel[0] != 255;
+        let total = |image: &ImageBuffer<Luma<u8>, Vec<u8>>| {
+            image.pixels().map(|pixel| pixel[0] as u64).sum::<u64>()
+        };
 
-        let (exteriors, holes) = CPUSlicer::classify_and_structure_polygons(polygons);
-        assert_eq!(exteriors.len(), 1);
-        assert_eq!(holes.len(), 1);
+        let printer = Printer::default();
+        let hard = rasterize(&printer, Supersampling::Off);
+        let blurred = rasterize(
+            &Printer {
+                anti_aliasing: AntiAliasing::X8,
+                ..printer.clone()
+            },
+            Supersampling::Off,
+        );
+        for supersampling in [Supersampling::X2, Supersampling::X3, Supersampling::X4] {
+            let smooth = rasterize(&printer, supersampling);
+            assert_eq!(smooth.dimensions(), hard.dimensions());
+            assert!(smooth.pixels().any(|pixel| is_gray(&pixel)));
+            // The same area is lit, give or take the edges
+            let (hard, smooth) = (total(&hard) as f64, total(&smooth) as f64);
+            assert!((hard - smooth).abs() / hard < 0.05);
+        }
+        // Only the pixels the outline passes through turn gray, the blur spreads wider
+        let supersampled = rasterize(&printer, Supersampling::X4);
+        let gray = |image: &ImageBuffer<Luma<u8>, Vec<u8>>| image.pixels().filter(is_gray).count();
+        assert!(gray(&supersampled) < gray(&blurred));
     }
 
     #[test]
     fn test_slice_bodies() {
         let stl_processor = StlProcessor::new();
         let mut mesh = Mesh::default();
-        mesh.import_stl("test_stls/with_holes.stl", &stl_processor);
+        mesh.import_stl("test_stls/with_holes.stl", &stl_processor).unwrap();
         let body = Body::new(mesh);
         let printer = Printer::default();
-        let result = CPUSlicer::slice_bodies(vec![body.clone()], 0.1, &printer);
+        let result = CPUSlicer::slice_bodies(
+            vec![body.clone()],
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Vector3::new(1.0, 1.0, 1.0),
+        );
         // it would really be nice to get some kind of data back from the slice bodies function that we can use to verify
         // the functionality in tests. It could possibly be useful for other things
         assert!(result.is_ok());
@@ -676,4 +1505,69 @@ mod tests {
         assert!(!images.is_empty()); // Ensure that at least one image is generated
         assert_eq!(images[0].dimensions(), (printer.pixel_x, printer.pixel_y)); // Check the image dimensions
     }
+
+    #[test]
+    fn test_spooled_layers_match_slice_bodies() {
+        let stl_processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &stl_processor).unwrap();
+        let body = Body::new(mesh);
+        let printer = Printer::default();
+        let shrinkage = Vector3::new(1.0, 1.0, 1.0);
+        let images = CPUSlicer::slice_bodies(
+            vec![body.clone()],
+            &LayerThickness::uniform(0.1),
+            &printer,
+            shrinkage,
+        )
+        .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let spool = CPUSlicer::slice_bodies_to_spool(
+            vec![body],
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            shrinkage,
+            &dir.path().join("layers.spool"),
+        )
+        .unwrap();
+        assert_eq!(spool.len(), images.len());
+        for (index, image) in images.iter().enumerate() {
+            assert_eq!(spool.layer(index).as_raw(), &image.as_raw().as_slice());
+        }
+    }
+
+    #[test]
+    fn test_layer_contours_follow_body_position() {
+        let stl_processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &stl_processor).unwrap();
+        let mut body = Body::new(mesh);
+        let (min_z, max_z) = geometry::z_range(&CPUSlicer::world_triangles(
+            [&body],
+            Vector3::new(1.0, 1.0, 1.0),
+        ))
+        .unwrap();
+        let mid_z = (min_z + max_z) / 2.0;
+
+        let contours = CPUSlicer::layer_contours([&body], mid_z);
+        assert!(!contours.is_empty());
+        for point in contours.iter().flatten() {
+            assert!((point.z - mid_z).abs() < 1e-4);
+        }
+        assert!(CPUSlicer::layer_contours([&body], max_z + 1.0).is_empty());
+
+        // Moving the body sideways moves the contours with it
+        body.set_position(body.position + Vector3::new(10.0, 0.0, 0.0));
+        let moved = CPUSlicer::layer_contours([&body], mid_z);
+        let min_x = |contours: &[Vec<Vector3<f64>>]| {
+            contours
+                .iter()
+                .flatten()
+                .map(|p| p.x)
+                .fold(f64::INFINITY, f64::min)
+        };
+        assert!((min_x(&moved) - min_x(&contours) - 10.0).abs() < 1e-3);
+    }
 }
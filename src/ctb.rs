@@ -0,0 +1,461 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::motion_profile::MotionProfile;
+use crate::output_formats::{
+    layer_of_runs, price_per_liter, runs, Layer, LayerEntry, OutputFormat, PrinterFile, ReadError,
+    Reader, Writer,
+};
+use crate::preview::{PreviewEncoding, PreviewFormat};
+use crate::printer::Printer;
+use image::{ImageBuffer, Rgb};
+
+/// Starts every .ctb file, .cbddlp files have another one
+const MAGIC: u32 = 0x12FD_0086;
+/// Version 3 adds the two stage moves of ChiTuBox 1.6.3 and later to version 2
+const VERSION: u32 = 3;
+/// ChiTuBox 1.6.3, which wrote the first version 3 files
+const SOFTWARE_VERSION: u32 = 0x0106_0300;
+/// Set in the slicer info of a version 3 file when the printer should take the height and
+/// exposure of every layer from the layer table instead of the header
+const PER_LAYER_SETTINGS: u32 = 0x2000_000F;
+
+const HEADER_LENGTH: usize = 112;
+const PREVIEW_HEADER_LENGTH: usize = 32;
+const PRINT_PARAMETERS_LENGTH: usize = 60;
+const SLICER_INFO_LENGTH: usize = 76;
+const LAYER_DEFINITION_LENGTH: usize = 36;
+
+/// The previews ChiTuBox writes, shown by the printer when picking a file
+const LARGE_PREVIEW: (u32, u32) = (400, 300);
+const SMALL_PREVIEW: (u32, u32) = (200, 125);
+
+/// Longest run of one color in a preview, 12 bits of the length less one
+const PREVIEW_MAX_RUN: u32 = 0x1000;
+/// Marks a preview pixel followed by the length of its run
+const PREVIEW_REPEAT: u16 = 0x20;
+/// Longest run of one value in a layer, 28 bits
+const LAYER_MAX_RUN: u32 = 0x0FFF_FFFF;
+
+/// A .ctb file: a header with the addresses of the rest, two previews, the print
+/// parameters, the slicer info with the name of the printer, a table with the height and
+/// exposure of every layer and the encoded layers, little endian throughout. Moves are in
+/// two stages like the motion profile and the .goo files, the same for every layer, so
+/// layer overrides of the lift or the light off delay can't be exported to it.
+pub fn encode(file: &PrinterFile, layers: &[Layer]) -> Vec<u8> {
+    let printer = &file.printer;
+    let motion = &file.motion;
+    let lift_fast_distance = (motion.lift_distance - motion.lift_slow_distance).max(0.0);
+    let retract_distance = (motion.lift_distance - file.layer_height).max(0.0);
+    let retract_slow_distance = motion.retract_slow_distance.min(retract_distance);
+
+    let previews = [LARGE_PREVIEW, SMALL_PREVIEW].map(|(width, height)| {
+        let format = PreviewFormat {
+            width,
+            height,
+            encoding: PreviewEncoding::Rgb565,
+            model_color: [255, 140, 40],
+            background_color: [0, 0, 0],
+        };
+        encode_preview(&format.render(layers))
+    });
+    let encoded: Vec<Vec<u8>> = layers.iter().map(encode_layer).collect();
+    let plan = file.plan(layers.len());
+
+    let large_preview_address = HEADER_LENGTH;
+    let small_preview_address = large_preview_address + PREVIEW_HEADER_LENGTH + previews[0].len();
+    let print_parameters_address =
+        small_preview_address + PREVIEW_HEADER_LENGTH + previews[1].len();
+    let slicer_info_address = print_parameters_address + PRINT_PARAMETERS_LENGTH;
+    let machine_name_address = slicer_info_address + SLICER_INFO_LENGTH;
+    let layer_definition_address = machine_name_address + printer.model.len();
+    let layer_image_address = layer_definition_address + LAYER_DEFINITION_LENGTH * layers.len();
+
+    let mut bytes = Writer::default();
+    bytes.u32(MAGIC);
+    bytes.u32(VERSION);
+    bytes.f32(printer.physical_x);
+    bytes.f32(printer.physical_y);
+    bytes.f32(printer.physical_z);
+    bytes.u32(0);
+    bytes.u32(0);
+    bytes.f32(plan.last().map_or(0.0, |layer| layer.top)); // Height of the print
+    bytes.f32(file.layer_height);
+    bytes.f32(motion.exposure_time);
+    bytes.f32(motion.bottom_exposure_time);
+    bytes.f32(0.0); // Light off delay, the waits of the slicer info replace it
+    bytes.u32(motion.bottom_layer_count as u32);
+    bytes.u32(printer.pixel_x);
+    bytes.u32(printer.pixel_y);
+    bytes.u32(large_preview_address as u32);
+    bytes.u32(layer_definition_address as u32);
+    bytes.u32(layers.len() as u32);
+    bytes.u32(small_preview_address as u32);
+    bytes.u32(file.print_time(layers.len()).round() as u32);
+    bytes.u32(0); // Projector not mirrored
+    bytes.u32(print_parameters_address as u32);
+    bytes.u32(PRINT_PARAMETERS_LENGTH as u32);
+    bytes.u32(1); // Anti-aliasing, the layers are already anti-aliased
+    bytes.u16(255); // Light power
+    bytes.u16(255); // Bottom light power
+    bytes.u32(0); // Not encrypted
+    bytes.u32(slicer_info_address as u32);
+    bytes.u32(SLICER_INFO_LENGTH as u32);
+
+    for ((width, height), preview) in [LARGE_PREVIEW, SMALL_PREVIEW].iter().zip(&previews) {
+        let address = bytes.bytes.len() + PREVIEW_HEADER_LENGTH;
+        bytes.u32(*width);
+        bytes.u32(*height);
+        bytes.u32(address as u32);
+        bytes.u32(preview.len() as u32);
+        for _ in 0..4 {
+            bytes.u32(0);
+        }
+        bytes.bytes.extend(preview);
+    }
+
+    // The first stage of the lift and the retract, for bottom then normal layers
+    for _ in 0..2 {
+        bytes.f32(motion.lift_slow_distance);
+        bytes.f32(motion.lift_slow_speed); // millimeters per minute
+    }
+    bytes.f32(motion.retract_fast_speed);
+    bytes.f32(file.volume_ml(layers));
+    bytes.f32(0.0); // Weight in grams
+    bytes.f32(file.price(layers));
+    bytes.f32(0.0); // Bottom light off delay
+    bytes.f32(0.0); // Light off delay
+    bytes.u32(motion.bottom_layer_count as u32);
+    for _ in 0..4 {
+        bytes.u32(0);
+    }
+
+    // The second stages, for bottom then normal layers
+    for _ in 0..2 {
+        bytes.f32(lift_fast_distance);
+        bytes.f32(motion.lift_fast_speed);
+    }
+    bytes.f32(retract_slow_distance);
+    bytes.f32(motion.retract_slow_speed);
+    bytes.f32(0.0); // Wait after lifting
+    bytes.u32(machine_name_address as u32);
+    bytes.u32(printer.model.len() as u32);
+    let layer_settings = !file.layer_height_ranges.is_empty() || !file.layer_overrides.is_empty();
+    bytes.u32(if layer_settings {
+        PER_LAYER_SETTINGS
+    } else {
+        0
+    });
+    bytes.u32(0); // Modification time, which printers don't show
+    bytes.u32(1); // Anti-aliasing
+    bytes.u32(SOFTWARE_VERSION);
+    bytes.f32(motion.rest_before_exposure); // Wait after retracting
+    bytes.f32(0.0); // Wait after the second stage of the lift
+    bytes.u32(0); // Transition layers
+    for _ in 0..3 {
+        bytes.u32(0);
+    }
+    bytes.bytes.extend(printer.model.as_bytes());
+
+    let mut data_address = layer_image_address;
+    for (data, layer) in encoded.iter().zip(&plan) {
+        bytes.f32(layer.top);
+        bytes.f32(layer.timing.exposure);
+        bytes.f32(0.0); // Light off delay
+        bytes.u32(data_address as u32);
+        bytes.u32(data.len() as u32);
+        for _ in 0..4 {
+            bytes.u32(0);
+        }
+        data_address += data.len();
+    }
+    for data in &encoded {
+        bytes.bytes.extend(data);
+    }
+    bytes.bytes
+}
+
+/// Reads a file `encode` wrote. The resin isn't stored, it comes back empty.
+pub fn decode(name: &str, bytes: &[u8]) -> Result<(PrinterFile, Vec<Layer>), ReadError> {
+    let mut file = Reader::new(bytes);
+    if file.u32()? != MAGIC {
+        return Err(ReadError::WrongFormat(".ctb"));
+    }
+    if file.u32()? < VERSION {
+        return Err(ReadError::WrongFormat("version 3 .ctb"));
+    }
+    let (physical_x, physical_y, physical_z) = (file.f32()?, file.f32()?, file.f32()?);
+    file.take(4 * 3)?; // Padding and the height of the print
+    let layer_height = file.f32()?;
+    let exposure_time = file.f32()?;
+    let bottom_exposure_time = file.f32()?;
+    file.f32()?; // Light off delay
+    let bottom_layer_count = file.u32()? as usize;
+    let (width, height) = (file.u32()?, file.u32()?);
+    file.u32()?; // Large preview
+    let layer_definition_address = file.u32()? as usize;
+    let layer_count = file.u32()? as usize;
+    file.take(4 * 3)?; // Small preview, print time and projector
+    let print_parameters_address = file.u32()? as usize;
+    file.take(4 + 4 + 2 * 2)?; // Their length, anti-aliasing and light power
+    if file.u32()? != 0 {
+        return Err(ReadError::WrongFormat("unencrypted .ctb"));
+    }
+    let slicer_info_address = file.u32()? as usize;
+
+    file.at = print_parameters_address;
+    file.take(4 * 2)?; // Bottom lift, the same as the normal one
+    let (lift_slow_distance, lift_slow_speed) = (file.f32()?, file.f32()?);
+    let retract_fast_speed = file.f32()?;
+    let volume = file.f32()?;
+    file.f32()?; // Weight
+    let price = file.f32()?;
+
+    file.at = slicer_info_address;
+    file.take(4 * 2)?;
+    let (lift_fast_distance, lift_fast_speed) = (file.f32()?, file.f32()?);
+    let (retract_slow_distance, retract_slow_speed) = (file.f32()?, file.f32()?);
+    file.f32()?; // Wait after lifting
+    let machine_name_address = file.u32()? as usize;
+    let machine_name_length = file.u32()? as usize;
+    file.take(4 * 4)?; // Layer settings, time, anti-aliasing and software
+    let rest_before_exposure = file.f32()?;
+    let motion = MotionProfile {
+        exposure_time,
+        bottom_exposure_time,
+        bottom_layer_count,
+        lift_distance: lift_slow_distance + lift_fast_distance,
+        lift_slow_distance,
+        lift_slow_speed,
+        lift_fast_speed,
+        retract_slow_distance,
+        retract_slow_speed,
+        retract_fast_speed,
+        rest_before_exposure,
+    };
+
+    file.at = machine_name_address;
+    let model = file.text(machine_name_length)?;
+    let printer = Printer {
+        name: model.clone(),
+        model,
+        pixel_x: width,
+        pixel_y: height,
+        physical_x,
+        physical_y,
+        physical_z,
+        output_format: OutputFormat::Ctb,
+        ..Printer::default()
+    };
+
+    file.at = layer_definition_address;
+    let mut table = Vec::with_capacity(layer_count);
+    let layers = (0..layer_count)
+        .map(|index| {
+            table.push(LayerEntry {
+                top: file.f32()?,
+                exposure: file.f32()?,
+                lift_distance: None,
+                rest: None,
+            });
+            file.f32()?; // Light off delay, the waits of the slicer info replace it
+            let address = file.u32()? as usize;
+            let length = file.u32()? as usize;
+            file.take(4 * 4)?;
+            let data = bytes
+                .get(address..address + length)
+                .ok_or(ReadError::Truncated)?;
+            decode_layer(data, width, height).ok_or(ReadError::DamagedLayer(index))
+        })
+        .collect::<Result<_, _>>()?;
+    let mut file = PrinterFile {
+        format: OutputFormat::Ctb,
+        name: name.to_string(),
+        printer,
+        resin: String::new(),
+        motion,
+        layer_height,
+        layer_height_ranges: Vec::new(),
+        layer_overrides: Vec::new(),
+        price_per_liter: price_per_liter(price, volume),
+    };
+    file.read_layer_table(&table);
+    Ok((file, layers))
+}
+
+/// Runs of 15 bit pixels, 5 bits each of red, green and blue around a flag that the next
+/// two bytes hold the length of the run, less one
+fn encode_preview(preview: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<u8> {
+    let mut runs: Vec<(u16, u32)> = Vec::new();
+    for Rgb([r, g, b]) in preview.pixels() {
+        let color = ((*r as u16 >> 3) << 11) | ((*g as u16 >> 3) << 6) | (*b as u16 >> 3);
+        match runs.last_mut() {
+            Some((last, count)) if *last == color && *count < PREVIEW_MAX_RUN => *count += 1,
+            _ => runs.push((color, 1)),
+        }
+    }
+    let mut bytes = Vec::new();
+    for (color, count) in runs {
+        if count == 1 {
+            bytes.extend(color.to_le_bytes());
+        } else {
+            bytes.extend((color | PREVIEW_REPEAT).to_le_bytes());
+            bytes.extend((0x3000 | (count - 1) as u16).to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Runs of the top seven bits of the pixels, across the rows. A set top bit in the first
+/// byte of a run means its length follows in one to four bytes, their leading bits telling
+/// how many.
+fn encode_layer(layer: &Layer) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (value, count) in runs(layer, |pixel| pixel >> 1, |_| LAYER_MAX_RUN) {
+        if count == 1 {
+            bytes.push(value);
+            continue;
+        }
+        bytes.push(0x80 | value);
+        match count {
+            0..=0x7F => bytes.push(count as u8),
+            0x80..=0x3FFF => bytes.extend([0x80 | (count >> 8) as u8, count as u8]),
+            0x4000..=0x1F_FFFF => {
+                bytes.extend([0xC0 | (count >> 16) as u8, (count >> 8) as u8, count as u8])
+            }
+            _ => bytes.extend([
+                0xE0 | (count >> 24) as u8,
+                (count >> 16) as u8,
+                (count >> 8) as u8,
+                count as u8,
+            ]),
+        }
+    }
+    bytes
+}
+
+fn decode_layer(data: &[u8], width: u32, height: u32) -> Option<Layer> {
+    let mut runs = Vec::new();
+    let mut bytes = data.iter();
+    while let Some(&first) = bytes.next() {
+        let value = first & 0x7F;
+        let mut count = 1;
+        if first & 0x80 != 0 {
+            let length = *bytes.next()? as u32;
+            let (extra_bytes, top) = match length {
+                0..=0x7F => (0, length),
+                0x80..=0xBF => (1, length & 0x3F),
+                0xC0..=0xDF => (2, length & 0x1F),
+                _ => (3, length & 0x0F),
+            };
+            count = top;
+            for _ in 0..extra_bytes {
+                count = (count << 8) | *bytes.next()? as u32;
+            }
+        }
+        runs.push(((value << 1) | (value & 1), count));
+    }
+    layer_of_runs(width, height, runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_overrides::LayerOverride;
+    use crate::slice_parameters::SliceParameters;
+    use image::Luma;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn f32_at(bytes: &[u8], at: usize) -> f32 {
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_ctb_layout() {
+        let mut printer = SliceParameters::default().printer;
+        (printer.pixel_x, printer.pixel_y) = (100, 80);
+        (printer.physical_x, printer.physical_y) = (5.0, 4.0);
+        printer.model = "Mars".to_string();
+        let file = PrinterFile {
+            format: OutputFormat::Ctb,
+            name: "job".to_string(),
+            printer,
+            resin: "Grey".to_string(),
+            motion: MotionProfile::default(),
+            layer_height: 0.05,
+            layer_height_ranges: Vec::new(),
+            layer_overrides: Vec::new(),
+            price_per_liter: None,
+        };
+        let layers: Vec<Layer> = (0..3)
+            .map(|i| ImageBuffer::from_fn(100, 80, |x, _| Luma([if x < 10 + i { 255 } else { 0 }])))
+            .collect();
+        assert_eq!(file.file_name(), "job.ctb");
+        let bytes = encode(&file, &layers);
+
+        assert_eq!(u32_at(&bytes, 0), MAGIC);
+        assert_eq!(u32_at(&bytes, 4), 3);
+        assert_eq!(f32_at(&bytes, 28), 0.15); // Height of the print
+        assert_eq!((u32_at(&bytes, 52), u32_at(&bytes, 56)), (100, 80));
+        let large_preview = u32_at(&bytes, 60) as usize;
+        assert_eq!(large_preview, HEADER_LENGTH);
+        assert_eq!(u32_at(&bytes, large_preview), 400);
+        assert_eq!(u32_at(&bytes, 68), 3);
+
+        // The name of the printer follows the slicer info
+        let slicer_info = u32_at(&bytes, 104) as usize;
+        let name = u32_at(&bytes, slicer_info + 28) as usize;
+        assert_eq!(&bytes[name..name + 4], b"Mars");
+
+        let motion = MotionProfile::default();
+        let definitions = u32_at(&bytes, 64) as usize;
+        assert_eq!(definitions, name + 4);
+        for (index, layer) in layers.iter().enumerate() {
+            let entry = definitions + index * LAYER_DEFINITION_LENGTH;
+            assert!((f32_at(&bytes, entry) - 0.05 * (index + 1) as f32).abs() < 1e-6);
+            // The first layers are bottom layers
+            assert_eq!(
+                f32_at(&bytes, entry + 4) as f64,
+                motion.bottom_exposure_time
+            );
+            let address = u32_at(&bytes, entry + 12) as usize;
+            let length = u32_at(&bytes, entry + 16) as usize;
+            let data = &bytes[address..address + length];
+            assert_eq!(decode_layer(data, 100, 80).as_ref(), Some(layer));
+        }
+
+        // The printer only reads the settings of the layer table when told to
+        assert_eq!(u32_at(&bytes, slicer_info + 36), 0);
+        let mut file = file;
+        file.layer_overrides.push(LayerOverride {
+            first_layer: 1,
+            last_layer: 1,
+            exposure_time: Some(4.0),
+            lift_distance: None,
+            light_off_delay: None,
+        });
+        let bytes = encode(&file, &layers);
+        assert_eq!(u32_at(&bytes, slicer_info + 36), PER_LAYER_SETTINGS);
+        let (decoded, _) = decode("job", &bytes).unwrap();
+        assert_eq!(decoded.layer_overrides, file.layer_overrides);
+    }
+
+    #[test]
+    fn test_long_runs() {
+        for width in [1, 2, 0x7F, 0x80, 0x3FFF, 0x4000, 0x1F_FFFF, 0x20_0000] {
+            let layer = ImageBuffer::from_fn(width, 1, |_, _| Luma([255]));
+            let data = encode_layer(&layer);
+            assert_eq!(decode_layer(&data, width, 1), Some(layer));
+        }
+        // Gray keeps its top seven bits
+        let layer = ImageBuffer::from_pixel(3, 1, Luma([128]));
+        assert_eq!(encode_layer(&layer), [0x80 | 64, 3]);
+        let preview = ImageBuffer::from_pixel(5000, 1, Rgb([255, 255, 255]));
+        let data = encode_preview(&preview);
+        assert_eq!(data.len(), 8);
+        assert_eq!(u16::from_le_bytes([data[2], data[3]]), 0x3FFF);
+    }
+}
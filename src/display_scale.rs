@@ -0,0 +1,125 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+/// Smallest and largest UI scale the setting accepts
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+
+/// The window's device pixel ratio: the scale factor the window system reports for the
+/// monitor times the UI scale setting. Slint positions the pointer in logical pixels while
+/// the 3D view is rendered in physical ones, so everything crossing between the two goes
+/// through here.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayScale {
+    system: f32,
+    ui: f32,
+    /// The factor last handed to the window
+    applied: Option<f32>,
+}
+
+impl DisplayScale {
+    pub fn new(ui_scale: f32) -> Self {
+        Self {
+            system: 1.0,
+            ui: ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE),
+            applied: None,
+        }
+    }
+
+    /// Physical pixels per logical pixel
+    pub fn factor(&self) -> f32 {
+        self.system * self.ui
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.ui
+    }
+
+    /// Returns the factor the window should switch to
+    pub fn set_ui_scale(&mut self, ui_scale: f32) -> f32 {
+        self.ui = ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+        self.applied = Some(self.factor());
+        self.factor()
+    }
+
+    /// Called with the factor the window currently uses. Anything other than the factor
+    /// we applied came from the window system, e.g. on start up or after moving the window
+    /// to a monitor with another DPI, and is taken as the new system factor. Returns the
+    /// factor to apply when the window doesn't use it yet.
+    pub fn sync(&mut self, window_factor: f32) -> Option<f32> {
+        let same = |a: f32, b: f32| (a - b).abs() < 1e-4;
+        if window_factor <= 0.0 || self.applied.is_some_and(|f| same(f, window_factor)) {
+            return None;
+        }
+        self.system = window_factor;
+        let factor = self.factor();
+        self.applied = Some(factor);
+        (!same(factor, window_factor)).then_some(factor)
+    }
+
+    /// A pointer position or size in logical pixels, in pixels of the rendered view
+    pub fn to_physical(self, logical: f32) -> f32 {
+        logical * self.factor()
+    }
+
+    /// Pointer movement in pixels of the window system, so the UI scale doesn't change how
+    /// fast dragging orbits or pans the view
+    pub fn pointer_delta(&self, logical: f32) -> f32 {
+        logical * self.ui
+    }
+}
+
+impl Default for DisplayScale {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+/// Applies `factor` to the window and lays it out again at its current physical size
+pub fn apply(window: &slint::Window, factor: f32) {
+    window.dispatch_event(slint::platform::WindowEvent::ScaleFactorChanged {
+        scale_factor: factor,
+    });
+    // Slint only relayouts on a resize, the physical size stays the same
+    window.dispatch_event(slint::platform::WindowEvent::Resized {
+        size: window.size().to_logical(factor),
+    });
+    window.request_redraw();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_with_window_system() {
+        let mut scale = DisplayScale::new(1.5);
+        // A 4K monitor at 200%
+        assert_eq!(scale.sync(2.0), Some(3.0));
+        assert_eq!(scale.factor(), 3.0);
+        // Our own factor coming back is not a change
+        assert_eq!(scale.sync(3.0), None);
+        // Moved to a 100% monitor
+        assert_eq!(scale.sync(1.0), Some(1.5));
+        assert_eq!(scale.set_ui_scale(1.0), 1.0);
+        assert_eq!(scale.sync(1.0), None);
+
+        // Without a UI scale the window system's factor is used as is
+        let mut scale = DisplayScale::default();
+        assert_eq!(scale.sync(2.0), None);
+        assert_eq!(scale.factor(), 2.0);
+    }
+
+    #[test]
+    fn test_pointer_mapping() {
+        let mut scale = DisplayScale::new(2.0);
+        scale.sync(2.0);
+        // A click 100 logical pixels in lands 400 texture pixels in on a 4K monitor
+        assert_eq!(scale.to_physical(100.0), 400.0);
+        // Moving the mouse 40 physical pixels moves it 10 logical ones, 20 system ones
+        assert_eq!(scale.pointer_delta(10.0), 20.0);
+
+        assert_eq!(scale.set_ui_scale(10.0), 2.0 * MAX_UI_SCALE);
+        assert_eq!(scale.ui_scale(), MAX_UI_SCALE);
+    }
+}
@@ -0,0 +1,287 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::{Body, AABB};
+use crate::geometry::{bounding_box, transform_triangles};
+use crate::hollow::inner_shells;
+use crate::infill::cuboid;
+use crate::mesh::Mesh;
+use crate::number_format;
+use nalgebra::{Matrix3, Vector2, Vector3};
+use stl_io::Triangle;
+use thiserror::Error;
+
+/// Faces whose corners are all this close to a height, in millimeters, lie flat at it
+const FLAT: f32 = 1e-3;
+
+/// Places tried for the holes across the widest side of a hollow at most, so large hollows
+/// don't take long to drain
+const MAX_PLACES_ACROSS: f32 = 64.0;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DrainError {
+    #[error("The drain holes have to be wider than 0 mm")]
+    NoDiameter,
+
+    #[error("{0} is not hollow, hollow it before adding drain holes")]
+    NotHollow(String),
+
+    #[error("{0} has no flat bottom under its hollow inside with room for {1} mm drain holes")]
+    NoFlatBottom(String, String),
+}
+
+/// Where up to `count` square holes `diameter` wide go up through the flat bottom of a hollow
+/// body into its inside, so the resin drains out and air comes in as the body is lifted out
+/// of the vat. The holes are as far apart as they fit, at least a hole apart and half a hole
+/// away from the walls. Each reaches up to the floor of the inside, since a box sticking
+/// into the hollow would be filled as an island of its own. The boxes are in the coordinates
+/// of the mesh, but scaled and rotated as on the plate, like the infill.
+pub fn drain_holes(body: &Body, count: usize, diameter: f32) -> Result<Vec<AABB>, DrainError> {
+    if diameter <= 0.0 {
+        return Err(DrainError::NoDiameter);
+    }
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let shells = inner_shells(&body.mesh);
+    if shells.is_empty() {
+        return Err(DrainError::NotHollow(body.name.clone()));
+    }
+    let no_room = || DrainError::NoFlatBottom(body.name.clone(), number_format::shortest(diameter));
+
+    let linear: Matrix3<f32> = body.get_model_matrix().fixed_view::<3, 3>(0, 0).into();
+    if linear.try_inverse().is_none() {
+        return Err(no_room());
+    }
+    let plate = linear.to_homogeneous();
+    let surface = transform_triangles(&body.mesh.get_triangles_for_slicing(), &plate);
+    let bounds = bounding_box(&surface).ok_or_else(no_room)?;
+    let bottom = flat_at(&surface, bounds.min.z);
+    let half = diameter / 2.0;
+
+    // Places over the floor of each hollow where the floor and the bottom below it are both
+    // flat under the whole hole
+    let mut places = Vec::new();
+    for shell in &shells {
+        let shell = transform_triangles(shell, &plate);
+        let Some(inside) = bounding_box(&shell) else {
+            continue;
+        };
+        let floor = flat_at(&shell, inside.min.z);
+        let size = inside.max - inside.min;
+        let spacing = half.max(size.x.max(size.y) / MAX_PLACES_ACROSS);
+        for i in 0..=(size.x / spacing) as usize {
+            for j in 0..=(size.y / spacing) as usize {
+                let center = inside.min.xy() + Vector2::new(i as f32, j as f32) * spacing;
+                let under_the_hole = [
+                    (0.0, 0.0),
+                    (-1.0, -1.0),
+                    (1.0, -1.0),
+                    (1.0, 1.0),
+                    (-1.0, 1.0),
+                ]
+                .map(|(x, y)| center + Vector2::new(x, y) * half);
+                if under_the_hole
+                    .iter()
+                    .all(|&p| covers(&bottom, p) && covers(&floor, p))
+                {
+                    places.push(AABB {
+                        min: Vector3::new(center.x - half, center.y - half, bounds.min.z),
+                        max: Vector3::new(center.x + half, center.y + half, inside.min.z),
+                    });
+                }
+            }
+        }
+    }
+    if places.is_empty() {
+        return Err(no_room());
+    }
+
+    // Faces around a hole other than the bottom and the floor it goes through, e.g. walls
+    let in_the_way = |hole: &AABB| {
+        let floor = hole.max.z;
+        let around = AABB {
+            min: hole.min - Vector3::new(half, half, 0.0),
+            max: hole.max + Vector3::repeat(half),
+        };
+        surface.iter().any(|triangle| {
+            let z = triangle.vertices.map(|v| v[2]);
+            if [hole.min.z, floor]
+                .iter()
+                .any(|&height| z.iter().all(|z| (z - height).abs() < FLAT))
+            {
+                return false;
+            }
+            let Some(face) = bounding_box(std::slice::from_ref(triangle)) else {
+                return false;
+            };
+            (0..3).all(|i| face.min[i] < around.max[i] && around.min[i] < face.max[i])
+        })
+    };
+    let middle = places
+        .iter()
+        .map(|place| (place.min + place.max).xy() / 2.0)
+        .sum::<Vector2<f32>>()
+        / places.len() as f32;
+    let mut holes: Vec<AABB> = Vec::new();
+    while holes.len() < count {
+        // Farthest from the holes so far, the first one from the middle of the places
+        let distance = |place: &AABB| {
+            let center = (place.min + place.max).xy() / 2.0;
+            if holes.is_empty() {
+                return (center - middle).norm();
+            }
+            holes
+                .iter()
+                .map(|hole| ((hole.min + hole.max).xy() / 2.0 - center).norm())
+                .fold(f32::MAX, f32::min)
+        };
+        places.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+        let mut next = None;
+        while let Some(place) = places.first() {
+            if !holes.is_empty() && distance(place) < 2.0 * diameter {
+                break;
+            }
+            let place = places.remove(0);
+            if !in_the_way(&place) {
+                next = Some(place);
+                break;
+            }
+        }
+        let Some(hole) = next else {
+            break;
+        };
+        holes.push(hole);
+    }
+    if holes.is_empty() {
+        return Err(no_room());
+    }
+    Ok(holes)
+}
+
+/// Adds the holes to the mesh of the body as boxes wound inwards, which the slicer leaves
+/// empty like the hollow inside
+pub fn drill(body: &mut Body, holes: &[AABB]) {
+    let linear: Matrix3<f32> = body.get_model_matrix().fixed_view::<3, 3>(0, 0).into();
+    let Some(inverse) = linear.try_inverse() else {
+        return;
+    };
+    let mut triangles = body.mesh.get_triangles_for_slicing();
+    triangles.extend(
+        holes
+            .iter()
+            .flat_map(|hole| cuboid(hole.min, hole.max))
+            .map(|[p, q, r]| {
+                let vertices = [p, r, q].map(|p| inverse * p);
+                let [p, q, r] = vertices;
+                Triangle {
+                    normal: (q - p)
+                        .cross(&(r - p))
+                        .try_normalize(0.0)
+                        .unwrap_or_default()
+                        .into(),
+                    vertices: vertices.map(Into::into),
+                }
+            }),
+    );
+    body.set_mesh(Mesh::from_triangles(&triangles));
+}
+
+/// The faces lying flat at `height`
+fn flat_at(triangles: &[Triangle], height: f32) -> Vec<&Triangle> {
+    triangles
+        .iter()
+        .filter(|triangle| {
+            triangle
+                .vertices
+                .iter()
+                .all(|v| (v[2] - height).abs() < FLAT)
+        })
+        .collect()
+}
+
+/// Whether any of the faces is above or below `point` of the plate
+fn covers(triangles: &[&Triangle], point: Vector2<f32>) -> bool {
+    triangles.iter().any(|triangle| {
+        let [a, b, c] = triangle.vertices.map(|v| Vector2::new(v[0], v[1]));
+        let (ab, ac, ap) = (b - a, c - a, point - a);
+        let area = ab.perp(&ac);
+        if area.abs() < f32::EPSILON {
+            return false;
+        }
+        let s = ap.perp(&ac) / area;
+        let t = ab.perp(&ap) / area;
+        s >= 0.0 && t >= 0.0 && s + t <= 1.0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::signed_volume;
+    use crate::hollow::hollow;
+    use crate::test_support::solid_box;
+
+    #[test]
+    fn test_drain_hollow_box() {
+        let mut body = solid_box([20.0, 20.0, 20.0]);
+        hollow(&mut body, 2.0).unwrap();
+        let holes = drain_holes(&body, 2, 3.0).unwrap();
+        assert_eq!(holes.len(), 2);
+        for hole in &holes {
+            // From the bottom up to the floor of the inside
+            assert_eq!((hole.min.z, hole.max.z), (0.0, 2.0));
+            assert!((hole.max.x - hole.min.x - 3.0).abs() < 1e-5);
+            // Half a hole away from the inner walls
+            assert!(hole.min.x >= 3.5 && hole.max.x <= 16.5);
+            assert!(hole.min.y >= 3.5 && hole.max.y <= 16.5);
+        }
+        // In opposite corners of the floor
+        let centers: Vec<Vector2<f32>> = holes
+            .iter()
+            .map(|hole| (hole.min + hole.max).xy() / 2.0)
+            .collect();
+        assert!((centers[0] - centers[1]).norm() > 10.0);
+
+        let volume = signed_volume(&body.mesh.get_triangles_for_slicing());
+        drill(&mut body, &holes);
+        let drilled = signed_volume(&body.mesh.get_triangles_for_slicing());
+        assert!((volume - drilled - 2.0 * 3.0 * 3.0 * 2.0).abs() < 1e-3);
+        // Still one inner shell wound inwards for each hole and the hollow
+        assert_eq!(inner_shells(&body.mesh).len(), 3);
+    }
+
+    #[test]
+    fn test_holes_fit_the_floor() {
+        // Only one 4 mm hole fits a hole apart on a 12 mm floor
+        let mut body = solid_box([16.0, 16.0, 10.0]);
+        hollow(&mut body, 2.0).unwrap();
+        assert_eq!(drain_holes(&body, 3, 4.0).unwrap().len(), 1);
+        assert!(drain_holes(&body, 0, 4.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_undrainable_bodies() {
+        let mut body = solid_box([20.0, 20.0, 20.0]);
+        assert!(matches!(
+            drain_holes(&body, 2, 3.0),
+            Err(DrainError::NotHollow(_))
+        ));
+        hollow(&mut body, 2.0).unwrap();
+        assert!(matches!(
+            drain_holes(&body, 2, 0.0),
+            Err(DrainError::NoDiameter)
+        ));
+        assert!(matches!(
+            drain_holes(&body, 2, 15.0),
+            Err(DrainError::NoFlatBottom(_, _))
+        ));
+
+        // Standing on an edge, nothing under the hollow is flat
+        body.set_rotation(Vector3::new(0.0, 45.0, 0.0));
+        assert!(matches!(
+            drain_holes(&body, 2, 3.0),
+            Err(DrainError::NoFlatBottom(_, _))
+        ));
+    }
+}
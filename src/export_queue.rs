@@ -0,0 +1,531 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::file_manager::file_manager::{encode_webp, slice_file_name, webp_len};
+use crate::layer_spool::LayerSpool;
+use crate::memory_budget;
+use crate::output_formats::PrinterFile;
+use crate::preview::EncodedPreview;
+use crate::profiler::{self, Stage};
+use crate::settings::CompressionSettings;
+use image::{ImageBuffer, Luma};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+/// Number of layers encoded up front to estimate the size of the whole export
+const SIZE_SAMPLE_LAYERS: usize = 8;
+
+/// Headroom on top of the estimate, the sampled layers may be smaller than average
+const SIZE_ESTIMATE_MARGIN: f64 = 1.25;
+
+/// The layers of a job, on the heap or spooled to disk for jobs that don't fit in memory
+pub enum ExportLayers {
+    InMemory(Arc<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>>),
+    Spooled(LayerSpool),
+}
+
+impl ExportLayers {
+    pub fn len(&self) -> usize {
+        match self {
+            ExportLayers::InMemory(layers) => layers.len(),
+            ExportLayers::Spooled(spool) => spool.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            ExportLayers::InMemory(layers) => layers.is_empty(),
+            ExportLayers::Spooled(spool) => spool.is_empty(),
+        }
+    }
+
+    /// Every layer, borrowing its pixels
+    pub fn views(&self) -> Vec<ImageBuffer<Luma<u8>, &[u8]>> {
+        match self {
+            ExportLayers::InMemory(layers) => layers
+                .iter()
+                .map(|layer| {
+                    ImageBuffer::from_raw(layer.width(), layer.height(), layer.as_raw().as_slice())
+                        .expect("a layer fills its own buffer")
+                })
+                .collect(),
+            ExportLayers::Spooled(spool) => spool.layers(),
+        }
+    }
+}
+
+/// The layers and previews of one slicing job and where to write them
+pub struct ExportJob {
+    pub layers: ExportLayers,
+    pub previews: Vec<EncodedPreview>,
+    pub output_dir: PathBuf,
+    /// Packs the layers into one file for the printer instead of writing them one by one
+    pub printer_file: Option<PrinterFile>,
+    /// How the WebP layers are encoded, the previews come already encoded
+    pub compression: CompressionSettings,
+}
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Could not write export: {0}")]
+    Io(#[from] io::Error),
+
+    #[error(
+        "The export needs about {} of disk space but only {} is free",
+        memory_budget::format_bytes(*required),
+        memory_budget::format_bytes(*available)
+    )]
+    InsufficientDiskSpace { required: u64, available: u64 },
+
+    #[error("Printer files are packed in memory, a job too big for it can only be exported as a folder of layers")]
+    SpooledPrinterFile,
+
+    #[error("{0} files have the same {1} for every layer, export the layer height ranges and overrides in another format")]
+    UniformLayers(&'static str, &'static str),
+
+    #[error("Export was cancelled")]
+    Cancelled,
+
+    #[error("The export queue has stopped")]
+    QueueClosed,
+}
+
+#[derive(Default)]
+struct ControlState {
+    paused: bool,
+    cancelled: bool,
+}
+
+/// Pauses, resumes and cancels the running export. The export checks in between
+/// batches of layers, so it stops within one batch.
+#[derive(Default)]
+pub struct ExportControl {
+    state: Mutex<ControlState>,
+    changed: Condvar,
+}
+
+impl ExportControl {
+    fn update(&self, update: impl FnOnce(&mut ControlState)) {
+        update(&mut self.state.lock().unwrap());
+        self.changed.notify_all();
+    }
+
+    pub fn pause(&self) {
+        self.update(|state| state.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.update(|state| state.paused = false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Cancels the running export, a paused export is cancelled without resuming
+    pub fn cancel(&self) {
+        self.update(|state| state.cancelled = true);
+    }
+
+    /// Blocks while paused, fails once cancelled
+    fn checkpoint(&self) -> Result<(), ExportError> {
+        let state = self.state.lock().unwrap();
+        let state = self
+            .changed
+            .wait_while(state, |state| state.paused && !state.cancelled)
+            .unwrap();
+        if state.cancelled {
+            return Err(ExportError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// A cancel only applies to the job that was running
+    fn begin_job(&self) {
+        self.state.lock().unwrap().cancelled = false;
+    }
+}
+
+struct QueuedJob {
+    job: ExportJob,
+    done: oneshot::Sender<Result<PathBuf, ExportError>>,
+}
+
+/// Writes export jobs one after the other on a background thread, so a second slice
+/// can finish while the first one is still being written.
+pub struct ExportQueue {
+    sender: mpsc::Sender<QueuedJob>,
+    control: Arc<ExportControl>,
+}
+
+impl ExportQueue {
+    /// Starts the queue, encoding on the given worker pool
+    pub fn new(worker_pool: Arc<ThreadPool>) -> Self {
+        let (sender, receiver) = mpsc::channel::<QueuedJob>();
+        let control = Arc::new(ExportControl::default());
+
+        let worker_control = Arc::clone(&control);
+        thread::Builder::new()
+            .name("export-queue".into())
+            .spawn(move || {
+                for queued in receiver {
+                    worker_control.begin_job();
+                    let result = worker_pool.install(|| export(&queued.job, &worker_control));
+                    // The submitter may have gone away, the files are written either way
+                    let _ = queued.done.send(result);
+                }
+            })
+            .expect("Failed to start the export queue");
+
+        Self { sender, control }
+    }
+
+    /// Queues a job and returns the directory it was written to once it is done
+    pub async fn submit(&self, job: ExportJob) -> Result<PathBuf, ExportError> {
+        let (done, result) = oneshot::channel();
+        if self.sender.send(QueuedJob { job, done }).is_err() {
+            return Err(ExportError::QueueClosed);
+        }
+        result.await.map_err(|_| ExportError::QueueClosed)?
+    }
+
+    pub fn control(&self) -> &ExportControl {
+        &self.control
+    }
+}
+
+/// Estimates the size of a folder of WebP layers by encoding a few evenly spaced layers.
+/// Printer files are packed whole before anything is written, so their size is known.
+pub fn estimate_export_bytes(job: &ExportJob) -> u64 {
+    let preview_bytes = preview_bytes(job);
+    if job.layers.is_empty() {
+        return preview_bytes;
+    }
+    let layers = job.layers.views();
+    let samples = SIZE_SAMPLE_LAYERS.min(layers.len());
+    let step = layers.len() / samples;
+    let sampled_bytes: usize = (0..samples)
+        .into_par_iter()
+        .map(|i| webp_len(&layers[i * step], &job.compression))
+        .sum();
+    let average = sampled_bytes as f64 / samples as f64;
+    (average * job.layers.len() as f64 * SIZE_ESTIMATE_MARGIN) as u64 + preview_bytes
+}
+
+fn preview_bytes(job: &ExportJob) -> u64 {
+    job.previews.iter().map(|p| p.bytes.len() as u64).sum()
+}
+
+/// Free space on the file system holding `path`, or None if it can't be determined
+#[cfg(unix)]
+// The field types of statvfs differ between platforms
+#[allow(clippy::unnecessary_cast)]
+pub fn available_disk_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    // The output directory usually doesn't exist yet, so ask about its closest existing
+    // ancestor. Relative paths without one are in the working directory.
+    let existing = path
+        .ancestors()
+        .find(|p| !p.as_os_str().is_empty() && p.exists())
+        .unwrap_or(Path::new("."));
+    let c_path = CString::new(existing.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_disk_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Fails if `required` bytes would not fit on the disk holding `output_dir`. Unknown free
+/// space always fits.
+pub fn check_disk_space(output_dir: &Path, required: u64) -> Result<(), ExportError> {
+    let Some(available) = available_disk_space(output_dir) else {
+        return Ok(());
+    };
+    if required > available {
+        return Err(ExportError::InsufficientDiskSpace {
+            required,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Writes a job, removing everything it wrote if it fails or is cancelled
+pub fn export(job: &ExportJob, control: &ExportControl) -> Result<PathBuf, ExportError> {
+    // Estimating the size encodes a few layers, which isn't part of the export. Printer
+    // files are checked once they are packed.
+    if job.printer_file.is_none() {
+        check_disk_space(&job.output_dir, estimate_export_bytes(job))?;
+    }
+    let _timer = profiler::scope(Stage::Export);
+
+    let created_dir = !job.output_dir.exists();
+    fs::create_dir_all(&job.output_dir)?;
+    let written = Mutex::new(Vec::new());
+    match write_job(job, control, &written) {
+        Ok(()) => Ok(job.output_dir.clone()),
+        Err(e) => {
+            if created_dir {
+                let _ = fs::remove_dir_all(&job.output_dir);
+            } else {
+                for path in written.into_inner().unwrap() {
+                    let _ = fs::remove_file(path);
+                }
+            }
+            Err(e)
+        }
+    }
+}
+
+fn write_job(
+    job: &ExportJob,
+    control: &ExportControl,
+    written: &Mutex<Vec<PathBuf>>,
+) -> Result<(), ExportError> {
+    let write = |path: PathBuf, bytes: &[u8]| -> Result<(), ExportError> {
+        written.lock().unwrap().push(path.clone());
+        fs::write(path, bytes)?;
+        Ok(())
+    };
+
+    if let Some(file) = &job.printer_file {
+        let ExportLayers::InMemory(layers) = &job.layers else {
+            return Err(ExportError::SpooledPrinterFile);
+        };
+        let dropped = file
+            .layer_settings()
+            .into_iter()
+            .find(|&setting| !file.format.keeps_layer(setting));
+        if let Some(setting) = dropped {
+            return Err(ExportError::UniformLayers(
+                file.format.label(),
+                setting.label(),
+            ));
+        }
+        control.checkpoint()?;
+        let bytes = file.encode(layers);
+        check_disk_space(&job.output_dir, bytes.len() as u64 + preview_bytes(job))?;
+        write(job.output_dir.join(file.file_name()), &bytes)?;
+        return write_previews(job, control, write);
+    }
+
+    // Encode in parallel one batch at a time, checking for pause and cancel in between
+    let batch_size = rayon::current_num_threads().max(1);
+    let layers = job.layers.views();
+    for (batch_index, batch) in layers.chunks(batch_size).enumerate() {
+        control.checkpoint()?;
+        batch.par_iter().enumerate().try_for_each(|(i, layer)| {
+            let index = batch_index * batch_size + i;
+            write(
+                job.output_dir.join(slice_file_name(index)),
+                &encode_webp(layer, &job.compression),
+            )
+        })?;
+    }
+
+    write_previews(job, control, write)
+}
+
+fn write_previews(
+    job: &ExportJob,
+    control: &ExportControl,
+    write: impl Fn(PathBuf, &[u8]) -> Result<(), ExportError>,
+) -> Result<(), ExportError> {
+    control.checkpoint()?;
+    for preview in &job.previews {
+        write(job.output_dir.join(preview.file_name()), &preview.bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_overrides::LayerOverride;
+    use crate::layer_thickness::LayerHeightRange;
+    use crate::output_formats::{LayerSetting, OutputFormat};
+    use crate::slice_parameters::SliceParameters;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn job(output_dir: PathBuf, layer_count: usize) -> ExportJob {
+        ExportJob {
+            layers: ExportLayers::InMemory(Arc::new(
+                (0..layer_count)
+                    .map(|i| {
+                        ImageBuffer::from_fn(64, 64, |x, _| {
+                            Luma([if x as usize > i { 255 } else { 0 }])
+                        })
+                    })
+                    .collect(),
+            )),
+            previews: Vec::new(),
+            output_dir,
+            printer_file: None,
+            compression: CompressionSettings::default(),
+        }
+    }
+
+    #[test]
+    fn test_export_writes_every_layer() {
+        let dir = tempdir().unwrap();
+        let output_dir = dir.path().join("out");
+        let control = ExportControl::default();
+
+        let written = export(&job(output_dir.clone(), 10), &control).unwrap();
+
+        assert_eq!(written, output_dir);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 10);
+        assert!(output_dir.join("slice_0009.webp").exists());
+    }
+
+    #[test]
+    fn test_export_spooled_layers() {
+        let dir = tempdir().unwrap();
+        let output_dir = dir.path().join("out");
+        let control = ExportControl::default();
+        let mut spool = LayerSpool::create(&dir.path().join("layers.spool"), 3, 64, 64).unwrap();
+        spool.layers_mut().for_each(|mut layer| layer.fill(255));
+        let mut job = job(output_dir.clone(), 0);
+        job.layers = ExportLayers::Spooled(spool);
+
+        export(&job, &control).unwrap();
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 3);
+        assert!(output_dir.join("slice_0002.webp").exists());
+
+        // Printer files are only packed from layers in memory
+        let mut parameters = SliceParameters::default();
+        parameters.printer.output_format = OutputFormat::Goo;
+        job.printer_file = OutputFormat::printer_file(&parameters, "job");
+        let result = export(&job, &control);
+        assert!(matches!(result, Err(ExportError::SpooledPrinterFile)));
+    }
+
+    #[test]
+    fn test_uniform_layer_formats() {
+        let dir = tempdir().unwrap();
+        let control = ExportControl::default();
+        let mut parameters = SliceParameters::default();
+        parameters.printer.output_format = OutputFormat::Sl1;
+        parameters.layer_height_ranges.push(LayerHeightRange {
+            from: 1.0,
+            to: 2.0,
+            thickness: 0.05,
+            exposure_time: None,
+        });
+        let mut job = job(dir.path().join("out"), 4);
+        job.printer_file = OutputFormat::printer_file(&parameters, "job");
+        let result = export(&job, &control);
+        assert!(matches!(
+            result,
+            Err(ExportError::UniformLayers(_, "height"))
+        ));
+
+        parameters.layer_height_ranges.clear();
+        parameters.layer_overrides.push(LayerOverride {
+            first_layer: 0,
+            last_layer: 1,
+            exposure_time: Some(5.0),
+            lift_distance: None,
+            light_off_delay: None,
+        });
+        job.printer_file = OutputFormat::printer_file(&parameters, "job");
+        let result = export(&job, &control);
+        assert!(matches!(
+            result,
+            Err(ExportError::UniformLayers(_, "exposure"))
+        ));
+
+        // The other formats refuse only the settings they would drop
+        parameters.printer.output_format = OutputFormat::Ctb;
+        job.printer_file = OutputFormat::printer_file(&parameters, "job");
+        export(&job, &control).unwrap();
+        parameters.layer_overrides[0].lift_distance = Some(3.0);
+        job.printer_file = OutputFormat::printer_file(&parameters, "job");
+        let result = export(&job, &control);
+        assert!(matches!(result, Err(ExportError::UniformLayers(_, "lift"))));
+
+        parameters.printer.output_format = OutputFormat::Pws;
+        job.printer_file = OutputFormat::printer_file(&parameters, "job");
+        export(&job, &control).unwrap();
+        parameters.layer_overrides[0].light_off_delay = Some(2.0);
+        job.printer_file = OutputFormat::printer_file(&parameters, "job");
+        let result = export(&job, &control);
+        assert!(matches!(
+            result,
+            Err(ExportError::UniformLayers(_, "light off delay"))
+        ));
+        assert!(OutputFormat::Goo.keeps_layer(LayerSetting::LightOffDelay));
+    }
+
+    #[test]
+    fn test_cancel_removes_partial_output() {
+        let dir = tempdir().unwrap();
+        let output_dir = dir.path().join("out");
+        let control = ExportControl::default();
+        control.cancel();
+
+        let result = export(&job(output_dir.clone(), 10), &control);
+
+        assert!(matches!(result, Err(ExportError::Cancelled)));
+        assert!(!output_dir.exists());
+    }
+
+    #[test]
+    fn test_estimate_and_free_space() {
+        let dir = tempdir().unwrap();
+        let small = job(dir.path().join("out"), 4);
+        let large = job(dir.path().join("out"), 40);
+
+        assert!(estimate_export_bytes(&small) > 0);
+        assert!(estimate_export_bytes(&large) > estimate_export_bytes(&small));
+        if cfg!(unix) {
+            assert!(available_disk_space(&dir.path().join("missing/dir")).unwrap() > 0);
+        }
+        assert!(check_disk_space(&small.output_dir, estimate_export_bytes(&small)).is_ok());
+        if cfg!(unix) {
+            assert!(matches!(
+                check_disk_space(&small.output_dir, u64::MAX),
+                Err(ExportError::InsufficientDiskSpace { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queue_pause_and_resume() {
+        let dir = tempdir().unwrap();
+        let output_dir = dir.path().join("out");
+        let worker_pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+        let queue = ExportQueue::new(Arc::new(worker_pool));
+        queue.control().pause();
+
+        let submitted = queue.submit(job(output_dir.clone(), 10));
+        tokio::pin!(submitted);
+        // Nothing is written while paused
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), &mut submitted)
+                .await
+                .is_err()
+        );
+        assert!(!output_dir.join("slice_0000.webp").exists());
+
+        queue.control().resume();
+        assert_eq!(submitted.await.unwrap(), output_dir);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 10);
+    }
+}
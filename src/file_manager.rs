@@ -6,7 +6,8 @@ pub mod file_manager {
     use std::fs;
     use std::fs::File;
     use std::io::Write;
-    use std::path::Path;
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
     use std::time::SystemTime;
     use std::time::UNIX_EPOCH;
     use webp::Encoder as WebpEncoder;
@@ -16,9 +17,14 @@ pub mod file_manager {
     use zip::ZipWriter;
 
     use crate::cpu_slicer::CPUSlicerError;
+    use crate::preview::EncodedPreview;
+    use crate::profiler::{self, Stage};
+    use crate::settings::CompressionSettings;
     #[allow(dead_code)]
     pub async fn write_images_to_zip_file(
         images: &Vec<ImageBuffer<Luma<u8>, Vec<u8>>>,
+        previews: &[EncodedPreview],
+        compression: &CompressionSettings,
     ) -> Result<String, ZipError> {
         // Get current timestamp for the zip file name
         let start = SystemTime::now();
@@ -39,92 +45,154 @@ pub mod file_manager {
         let zip_file = File::create(&zip_file_path).expect("Failed to create zip file");
         let mut zip = ZipWriter::new(zip_file);
 
-        // Iterate over the output images and save each one to the zip file in lossless WebP format
+        // Iterate over the output images and save each one to the zip file in WebP format
         for (i, image) in images.iter().enumerate() {
-            // Convert ImageBuffer<Luma<u8>, Vec<u8>> to ImageBuffer<Rgb<u8>, Vec<u8>>
-            let rgb_image: ImageBuffer<Rgb<u8>, Vec<u8>> = convert_luma_to_rgb(image);
-
-            // Retrieve width and height
-            let width = rgb_image.width();
-            let height = rgb_image.height();
-
-            // Flatten the RGB image into a Vec<u8>
-            let rgb_data = rgb_image.into_raw();
-
-            // Create a WebP encoder with lossless encoding
-            let encoder = WebpEncoder::from_rgb(&rgb_data, width, height);
-
-            // Encode the image in lossless mode
-            let webp_data = encoder.encode_lossless();
-            let webp_bytes = webp_data.as_bytes();
+            let webp_bytes = encode_webp(image, compression);
 
             // Create a file entry in the zip
             let file_name = format!("slice_{:04}.webp", i);
 
-            // Define the file options for the zip entry
-            let options =
-                SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
-
             // Start a new file in the zip archive
-            zip.start_file(&file_name, options)?;
+            zip.start_file(&file_name, zip_file_options(compression))?;
 
             // Write the image data to the zip file
-            zip.write_all(webp_bytes)?; // Use write_all to ensure all data is written
+            zip.write_all(&webp_bytes)?; // Use write_all to ensure all data is written
 
             // Log progress if needed
             debug!("Added {} to zip", &file_name);
         }
 
+        // Add the preview images the printer firmware expects
+        for preview in previews {
+            zip.start_file(preview.file_name(), zip_file_options(compression))?;
+            zip.write_all(&preview.bytes)?;
+        }
+
         // Finish the zip file
         zip.finish()?;
         Ok(zip_file_path)
     }
 
+    #[allow(dead_code)]
     pub async fn write_webps_to_folder(
         images: &Vec<ImageBuffer<Luma<u8>, Vec<u8>>>,
+        previews: &[EncodedPreview],
+        compression: &CompressionSettings,
     ) -> Result<String, CPUSlicerError> {
-        let start = SystemTime::now();
-        let since_the_epoch = start
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards");
-        let timestamp = since_the_epoch.as_secs();
+        let _timer = profiler::scope(Stage::Export);
 
         // Create a new directory inside "slices" with the timestamp as its name
-        let dir_path = format!("slices/{}", timestamp);
-        fs::create_dir_all(&dir_path).expect("Failed to create directory");
+        let dir_path = timestamped_output_dir(Path::new(DEFAULT_OUTPUT_DIR))
+            .display()
+            .to_string();
+        fs::create_dir_all(&dir_path)?;
 
-        // Iterate over the output images and save each one to a file in lossless WebP format
-        images.par_iter().enumerate().for_each(|(i, image)| {
-            let file_path = format!("{}/slice_{:04}.webp", dir_path, i);
+        // Iterate over the output images and save each one to a file in WebP format
+        images.par_iter().enumerate().try_for_each(|(i, image)| {
+            let file_path = format!("{}/{}", dir_path, slice_file_name(i));
+            let webp_bytes = encode_webp(image, compression);
 
-            // Convert ImageBuffer<Luma<u8>, Vec<u8>> to ImageBuffer<Rgb<u8>, Vec<u8>>
-            let rgb_image: ImageBuffer<Rgb<u8>, Vec<u8>> = convert_luma_to_rgb(image);
+            // Save the encoded WebP data to a file
+            fs::write(&file_path, webp_bytes)
+        })?;
 
-            // Retrieve width and height before moving rgb_image
-            let width = rgb_image.width();
-            let height = rgb_image.height();
+        for preview in previews {
+            fs::write(format!("{}/{}", dir_path, preview.file_name()), &preview.bytes)?;
+        }
+        Ok(dir_path)
+    }
 
-            // Flatten the RGB image into a Vec<u8>
-            let rgb_data = rgb_image.into_raw();
+    /// Where exports go until the user picks another folder
+    pub const DEFAULT_OUTPUT_DIR: &str = "slices";
 
-            // Create a WebP encoder with lossless encoding
-            let encoder = WebpEncoder::from_rgb(&rgb_data, width, height);
+    /// A new directory inside `base` named after the current time
+    pub fn timestamped_output_dir(base: &Path) -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        base.join(timestamp.to_string())
+    }
 
-            // Encode the image in lossless mode
-            let webp_data = encoder.encode_lossless();
+    /// `base` with the name of a print profile appended, keeping only characters that
+    /// are safe in file names
+    pub fn profile_output_dir(base: &Path, profile: &str) -> PathBuf {
+        let profile: String = profile
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let mut name = base.file_name().unwrap_or_default().to_os_string();
+        name.push(format!("_{}", profile));
+        base.with_file_name(name)
+    }
 
-            // Convert WebPMemory to Vec<u8> using as_bytes()
-            let webp_bytes = webp_data.as_bytes();
+    pub fn slice_file_name(index: usize) -> String {
+        format!("slice_{:04}.webp", index)
+    }
 
-            // Save the encoded WebP data to a file
-            fs::write(&file_path, webp_bytes).unwrap(); // Todo: Handle this better
-        });
-        Ok(dir_path)
+    /// Encodes a layer as WebP, lossless unless the compression settings trade the exact
+    /// gray levels for size
+    pub fn encode_webp<C: Deref<Target = [u8]>>(
+        image: &ImageBuffer<Luma<u8>, C>,
+        compression: &CompressionSettings,
+    ) -> Vec<u8> {
+        let _timer = profiler::scope(Stage::Encoding);
+        webp(image, compression)
+    }
+
+    /// Size of a layer encoded as WebP. Estimates use it so their trial encodes aren't
+    /// profiled as encoding.
+    pub fn webp_len<C: Deref<Target = [u8]>>(
+        image: &ImageBuffer<Luma<u8>, C>,
+        compression: &CompressionSettings,
+    ) -> usize {
+        webp(image, compression).len()
+    }
+
+    fn webp<C: Deref<Target = [u8]>>(
+        image: &ImageBuffer<Luma<u8>, C>,
+        compression: &CompressionSettings,
+    ) -> Vec<u8> {
+        // Convert ImageBuffer<Luma<u8>, Vec<u8>> to ImageBuffer<Rgb<u8>, Vec<u8>>
+        let rgb_image: ImageBuffer<Rgb<u8>, Vec<u8>> = convert_luma_to_rgb(image);
+
+        // Retrieve width and height before moving rgb_image
+        let width = rgb_image.width();
+        let height = rgb_image.height();
+
+        // Flatten the RGB image into a Vec<u8>
+        let rgb_data = rgb_image.into_raw();
+
+        let encoder = WebpEncoder::from_rgb(&rgb_data, width, height);
+        if compression.webp_lossless {
+            encoder.encode_lossless().as_bytes().to_vec()
+        } else {
+            encoder
+                .encode(compression.webp_quality.clamp(0.0, 100.0))
+                .as_bytes()
+                .to_vec()
+        }
+    }
+
+    /// Zip entries deflated at the configured level, or stored as they are at level 0
+    pub fn zip_file_options(compression: &CompressionSettings) -> SimpleFileOptions {
+        match compression.zip_level.min(9) {
+            0 => SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored),
+            level => SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .compression_level(Some(level as i64)),
+        }
     }
 
     /// Converts an ImageBuffer with Luma<u8> pixels to an ImageBuffer with Rgb<u8> pixels
-    pub fn convert_luma_to_rgb(
-        image: &ImageBuffer<Luma<u8>, Vec<u8>>,
+    pub fn convert_luma_to_rgb<C: Deref<Target = [u8]>>(
+        image: &ImageBuffer<Luma<u8>, C>,
     ) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
         let (width, height) = image.dimensions();
         let mut rgb_image = ImageBuffer::new(width, height);
@@ -140,6 +208,8 @@ pub mod file_manager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::preview::{PreviewEncoding, PreviewFormat};
+    use crate::settings::{CompressionSettings, PngCompression};
     use image::{ImageBuffer, Luma};
     use std::fs;
     use std::path::Path;
@@ -155,7 +225,9 @@ mod tests {
             create_test_image(200, 200, 128),
         ];
 
-        let result = file_manager::write_images_to_zip_file(&images).await;
+        let result =
+            file_manager::write_images_to_zip_file(&images, &[], &CompressionSettings::default())
+                .await;
 
         assert!(result.is_ok());
         let zip_file_path = result.unwrap();
@@ -174,7 +246,25 @@ mod tests {
             create_test_image(200, 200, 128),
         ];
 
-        let result = file_manager::write_webps_to_folder(&images).await;
+        let previews = PreviewFormat::render_all(
+            &[PreviewFormat {
+                width: 224,
+                height: 168,
+                encoding: PreviewEncoding::Rgb565,
+                model_color: [255, 255, 255],
+                background_color: [0, 0, 0],
+            }],
+            &images,
+            PngCompression::Fast,
+        )
+        .unwrap();
+
+        let result = file_manager::write_webps_to_folder(
+            &images,
+            &previews,
+            &CompressionSettings::default(),
+        )
+        .await;
 
         assert!(result.is_ok());
         let dir_path = result.unwrap();
@@ -187,11 +277,49 @@ mod tests {
             let file_path = format!("{}/slice_{:04}.webp", dir_path, i);
             assert!(Path::new(&file_path).exists());
         }
+        let preview = fs::read(format!("{}/preview_224x168.rgb565", dir_path)).unwrap();
+        assert_eq!(preview.len(), 224 * 168 * 2);
 
         // Clean up
         fs::remove_dir_all(dir_path).expect("Failed to delete directory");
     }
 
+    #[test]
+    fn test_compression_settings() {
+        // Noise that only lossy WebP can shrink much
+        let image = ImageBuffer::from_fn(64, 64, |x, y| {
+            Luma([((x * x * 31 + y * 17 + x * y * 13) % 256) as u8])
+        });
+        let lossless = CompressionSettings::default();
+        let lossy = CompressionSettings {
+            webp_lossless: false,
+            webp_quality: 10.0,
+            ..CompressionSettings::default()
+        };
+
+        let exact = image::load_from_memory(&file_manager::encode_webp(&image, &lossless))
+            .unwrap()
+            .to_luma8();
+        assert_eq!(exact, image);
+        assert!(
+            file_manager::encode_webp(&image, &lossy).len()
+                < file_manager::encode_webp(&image, &lossless).len()
+        );
+
+        let zip_size = |zip_level: u8| {
+            let compression = CompressionSettings {
+                zip_level,
+                ..CompressionSettings::default()
+            };
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+            zip.start_file("layer", file_manager::zip_file_options(&compression))
+                .unwrap();
+            std::io::Write::write_all(&mut zip, image.as_raw()).unwrap();
+            zip.finish().unwrap().into_inner().len()
+        };
+        assert!(zip_size(9) < zip_size(0));
+    }
+
     #[test]
     fn test_convert_luma_to_rgb() {
         let luma_image = create_test_image(2, 2, 100); // 2x2 image with Luma value 100
@@ -207,4 +335,13 @@ mod tests {
             assert_eq!(pixel.0[2], 100);
         }
     }
+
+    #[test]
+    fn test_profile_output_dir() {
+        let base = Path::new("slices").join("1700000000");
+
+        let dir = file_manager::profile_output_dir(&base, "Grey resin/v2");
+
+        assert_eq!(dir, Path::new("slices").join("1700000000_Grey_resin_v2"));
+    }
 }
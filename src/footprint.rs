@@ -0,0 +1,274 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use nalgebra::{Quaternion, Vector2, Vector3};
+use std::cell::RefCell;
+
+// Slack for footprints placed exactly `gap` apart
+const EPSILON: f32 = 1e-4;
+
+/// The area a body covers on the plate: the convex hull of its vertices seen from above
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footprint {
+    /// Counter-clockwise, without repeating the first point
+    hull: Vec<Vector2<f32>>,
+}
+
+fn cross(o: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    (a - o).perp(&(b - o))
+}
+
+impl Footprint {
+    /// Convex hull of the points, None without any
+    pub fn from_points(points: impl IntoIterator<Item = Vector2<f32>>) -> Option<Self> {
+        let mut points: Vec<_> = points.into_iter().collect();
+        points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+        points.dedup();
+        if points.len() < 3 {
+            return (!points.is_empty()).then_some(Self { hull: points });
+        }
+        // Andrew's monotone chain: the lower half left to right, then the upper half back
+        let mut hull = half_hull(points.iter());
+        let mut upper = half_hull(points.iter().rev());
+        hull.pop();
+        upper.pop();
+        hull.extend(upper);
+        Some(Self { hull })
+    }
+
+    pub fn hull(&self) -> &[Vector2<f32>] {
+        &self.hull
+    }
+
+    /// Corners of the axis aligned box around the hull, as (min, max)
+    pub fn bounds(&self) -> (Vector2<f32>, Vector2<f32>) {
+        self.hull.iter().fold(
+            (
+                Vector2::repeat(f32::INFINITY),
+                Vector2::repeat(f32::NEG_INFINITY),
+            ),
+            |(min, max), p| (min.inf(p), max.sup(p)),
+        )
+    }
+
+    pub fn translated(&self, offset: Vector2<f32>) -> Self {
+        Self {
+            hull: self.hull.iter().map(|p| p + offset).collect(),
+        }
+    }
+
+    /// In square millimeters
+    #[cfg(test)]
+    pub fn area(&self) -> f32 {
+        self.edges().map(|(a, b)| a.perp(&b)).sum::<f32>() / 2.0
+    }
+
+    /// True for points inside or on the hull
+    pub fn contains(&self, point: Vector2<f32>) -> bool {
+        self.hull.len() >= 3 && self.edges().all(|(a, b)| cross(a, b, point) >= 0.0)
+    }
+
+    /// Shortest distance between the hulls, 0 when they touch or overlap
+    pub fn distance(&self, other: &Self) -> f32 {
+        if self.hull.iter().any(|p| other.contains(*p))
+            || other.hull.iter().any(|p| self.contains(*p))
+        {
+            return 0.0;
+        }
+        self.edges()
+            .flat_map(|a| other.edges().map(move |b| segment_distance(a, b)))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// True when a line between the hulls keeps them apart, touching still counting as apart
+    pub fn separated(&self, other: &Self) -> bool {
+        // Points and lines have no inside, the edge normals alone don't tell them apart
+        if self.hull.len() < 3 || other.hull.len() < 3 {
+            return self.distance(other) > EPSILON;
+        }
+        let project = |hull: &[Vector2<f32>], axis: Vector2<f32>| {
+            hull.iter()
+                .map(|p| p.dot(&axis))
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), d| {
+                    (min.min(d), max.max(d))
+                })
+        };
+        self.edges()
+            .chain(other.edges())
+            .map(|(a, b)| Vector2::new(a.y - b.y, b.x - a.x))
+            .filter(|axis| axis.norm_squared() > 0.0)
+            .any(|axis| {
+                let axis = axis.normalize();
+                let (min, max) = project(&self.hull, axis);
+                let (other_min, other_max) = project(&other.hull, axis);
+                max <= other_min + EPSILON || other_max <= min + EPSILON
+            })
+    }
+
+    /// True when the hulls overlap or are less than `gap` apart
+    pub fn overlaps(&self, other: &Self, gap: f32) -> bool {
+        // Most bodies on a plate are far apart, which their bounds already tell
+        let ((min, max), (other_min, other_max)) = (self.bounds(), other.bounds());
+        let near = (0..2).all(|axis| {
+            min[axis] < other_max[axis] + gap - EPSILON
+                && other_min[axis] < max[axis] + gap - EPSILON
+        });
+        near && (!self.separated(other) || self.distance(other) < gap - EPSILON)
+    }
+
+    // Each side as (start, end), a single point being a side from and to itself
+    fn edges(&self) -> impl Iterator<Item = (Vector2<f32>, Vector2<f32>)> + '_ {
+        (0..self.hull.len()).map(|i| (self.hull[i], self.hull[(i + 1) % self.hull.len()]))
+    }
+}
+
+/// What a body's footprint depends on besides its position and mesh: rotation and scale
+type FootprintKey = (Quaternion<f32>, Vector3<f32>);
+
+/// A body's footprint before moving it into place. Recomputed when the rotation or scale
+/// change, while moving the body only translates it. `Body::set_mesh` clears it.
+#[derive(Debug, Clone, Default)]
+pub struct FootprintCache(RefCell<Option<(FootprintKey, Option<Footprint>)>>);
+
+impl FootprintCache {
+    pub fn get(
+        &self,
+        key: FootprintKey,
+        compute: impl FnOnce() -> Option<Footprint>,
+    ) -> Option<Footprint> {
+        let mut cached = self.0.borrow_mut();
+        match &*cached {
+            Some((cached_key, footprint)) if *cached_key == key => footprint.clone(),
+            _ => {
+                let footprint = compute();
+                *cached = Some((key, footprint.clone()));
+                footprint
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().take();
+    }
+}
+
+// Points of one half of the hull, turning left only, ending at the last point
+fn half_hull<'a>(points: impl Iterator<Item = &'a Vector2<f32>>) -> Vec<Vector2<f32>> {
+    let mut half: Vec<Vector2<f32>> = Vec::new();
+    for &p in points {
+        while half.len() >= 2 && cross(half[half.len() - 2], half[half.len() - 1], p) <= 0.0 {
+            half.pop();
+        }
+        half.push(p);
+    }
+    half
+}
+
+fn point_segment_distance(p: Vector2<f32>, (a, b): (Vector2<f32>, Vector2<f32>)) -> f32 {
+    let ab = b - a;
+    let length_squared = ab.norm_squared();
+    let t = if length_squared > 0.0 {
+        ((p - a).dot(&ab) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (a + ab * t - p).norm()
+}
+
+fn segment_distance(
+    (a, b): (Vector2<f32>, Vector2<f32>),
+    (c, d): (Vector2<f32>, Vector2<f32>),
+) -> f32 {
+    if cross(a, b, c) * cross(a, b, d) < 0.0 && cross(c, d, a) * cross(c, d, b) < 0.0 {
+        return 0.0;
+    }
+    point_segment_distance(a, (c, d))
+        .min(point_segment_distance(b, (c, d)))
+        .min(point_segment_distance(c, (a, b)))
+        .min(point_segment_distance(d, (a, b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32, y: f32, side: f32) -> Footprint {
+        Footprint::from_points([
+            Vector2::new(x, y),
+            Vector2::new(x + side, y),
+            Vector2::new(x + side, y + side),
+            Vector2::new(x, y + side),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hull() {
+        // A square with points inside it, on its edges and repeated
+        let points = [
+            (0.0, 0.0),
+            (2.0, 2.0),
+            (4.0, 0.0),
+            (1.0, 3.0),
+            (4.0, 4.0),
+            (2.0, 0.0),
+            (0.0, 4.0),
+            (4.0, 4.0),
+        ];
+        let footprint = Footprint::from_points(points.map(|(x, y)| Vector2::new(x, y))).unwrap();
+        assert_eq!(
+            footprint.hull(),
+            &[
+                Vector2::new(0.0, 0.0),
+                Vector2::new(4.0, 0.0),
+                Vector2::new(4.0, 4.0),
+                Vector2::new(0.0, 4.0),
+            ]
+        );
+        assert_eq!(footprint.area(), 16.0);
+        assert_eq!(
+            footprint.bounds(),
+            (Vector2::new(0.0, 0.0), Vector2::new(4.0, 4.0))
+        );
+        assert!(footprint.contains(Vector2::new(4.0, 2.0)));
+        assert!(!footprint.contains(Vector2::new(4.5, 2.0)));
+
+        assert!(Footprint::from_points([]).is_none());
+        let line = Footprint::from_points([Vector2::new(1.0, 1.0), Vector2::new(3.0, 1.0)]);
+        assert_eq!(line.unwrap().area(), 0.0);
+    }
+
+    #[test]
+    fn test_distance_and_overlap() {
+        let a = square(0.0, 0.0, 10.0);
+        assert_eq!(a.distance(&square(5.0, 5.0, 10.0)), 0.0);
+        assert_eq!(a.distance(&square(2.0, 2.0, 1.0)), 0.0);
+        assert_eq!(a.distance(&square(13.0, 0.0, 10.0)), 3.0);
+        assert_eq!(a.distance(&square(13.0, 14.0, 10.0)), 5.0);
+
+        assert!(a.overlaps(&square(9.0, 9.0, 10.0), 0.0));
+        assert!(!a.overlaps(&square(10.0, 0.0, 10.0), 0.0));
+        assert!(a.overlaps(&square(10.5, 0.0, 10.0), 1.0));
+        assert!(!a.overlaps(&square(11.0, 0.0, 10.0), 1.0));
+
+        // A diamond whose bounds overlap the square's corner but whose hull doesn't
+        let diamond = Footprint::from_points([
+            Vector2::new(15.0, 10.0),
+            Vector2::new(20.0, 15.0),
+            Vector2::new(15.0, 20.0),
+            Vector2::new(10.0, 15.0),
+        ])
+        .unwrap();
+        assert!(!a.overlaps(&diamond, 1.0));
+        assert!(a.translated(Vector2::new(3.0, 3.0)).overlaps(&diamond, 1.0));
+        assert!(!a.separated(&a.translated(Vector2::new(9.0, 0.0))));
+        assert!(a.separated(&a.translated(Vector2::new(10.0, 10.0))));
+
+        // Lines on the same line apart from each other
+        let line = |x: f32| {
+            Footprint::from_points([Vector2::new(x, 0.0), Vector2::new(x + 1.0, 0.0)]).unwrap()
+        };
+        assert!(line(0.0).separated(&line(2.0)));
+        assert!(!line(0.0).overlaps(&line(2.0), 0.5));
+    }
+}
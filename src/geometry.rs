@@ -0,0 +1,224 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::AABB;
+use nalgebra::{Matrix4, Point3, Vector3};
+use stl_io::Triangle;
+
+/// A vertex of a triangle in double precision, which the slicer and the volume sums
+/// work in to keep rounding errors of big meshes out of the layers
+pub fn to_f64(vertex: [f32; 3]) -> Vector3<f64> {
+    Vector3::new(vertex[0] as f64, vertex[1] as f64, vertex[2] as f64)
+}
+
+/// The triangles moved by `matrix`, with their normals turned along and normalized.
+/// Normals that can't be normalized, e.g. of degenerate triangles, become zero.
+pub fn transform_triangles(triangles: &[Triangle], matrix: &Matrix4<f32>) -> Vec<Triangle> {
+    triangles
+        .iter()
+        .map(|triangle| Triangle {
+            normal: matrix
+                .transform_vector(&Vector3::from(triangle.normal))
+                .try_normalize(0.0)
+                .unwrap_or_default()
+                .into(),
+            vertices: triangle
+                .vertices
+                .map(|v| matrix.transform_point(&Point3::from(v)).coords.into()),
+        })
+        .collect()
+}
+
+/// Bounds of the triangles, None without any
+pub fn bounding_box(triangles: &[Triangle]) -> Option<AABB> {
+    triangles
+        .iter()
+        .flat_map(|triangle| triangle.vertices.map(Vector3::from))
+        .fold(None, |aabb: Option<AABB>, p| {
+            Some(match aabb {
+                Some(aabb) => AABB {
+                    min: aabb.min.inf(&p),
+                    max: aabb.max.sup(&p),
+                },
+                None => AABB { min: p, max: p },
+            })
+        })
+}
+
+/// Lowest and highest point of the triangles, None without any
+pub fn z_range(triangles: &[Triangle]) -> Option<(f64, f64)> {
+    let mut heights = triangles
+        .iter()
+        .flat_map(|triangle| triangle.vertices.map(|v| v[2] as f64));
+    let first = heights.next()?;
+    Some(heights.fold((first, first), |(min, max), z| (min.min(z), max.max(z))))
+}
+
+/// Length of the longest side of the bounds of the triangles, 0 without any
+pub fn longest_side(triangles: &[Triangle]) -> f64 {
+    bounding_box(triangles).map_or(0.0, |aabb| (aabb.max - aabb.min).max() as f64)
+}
+
+/// Sum of the signed volumes of the tetrahedra between the origin and every triangle, in
+/// cubic units of the triangles. Exact for closed meshes, and negative when their
+/// triangles wind inside out.
+pub fn signed_volume(triangles: &[Triangle]) -> f64 {
+    triangles
+        .iter()
+        .map(|triangle| {
+            let [a, b, c] = triangle.vertices.map(to_f64);
+            a.dot(&b.cross(&c)) / 6.0
+        })
+        .sum()
+}
+
+/// How far along the ray it crosses the triangle, from either side, None when it misses
+/// or runs along its plane
+pub fn ray_triangle_distance(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    [a, b, c]: [Vector3<f32>; 3],
+) -> Option<f32> {
+    let (ab, ac) = (b - a, c - a);
+    let p = direction.cross(&ac);
+    let determinant = ab.dot(&p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let to_origin = origin - a;
+    let u = to_origin.dot(&p) / determinant;
+    let q = to_origin.cross(&ab);
+    let v = direction.dot(&q) / determinant;
+    if u < 0.0 || v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = ac.dot(&q) / determinant;
+    (t >= 0.0).then_some(t)
+}
+
+/// Length of the longest side of a normalized mesh. Tolerances of the analyses that work on
+/// normalized meshes are meant for a mesh this big, and grow and shrink along with it.
+pub const NORMALIZED_SIZE: f32 = 100.0;
+
+/// A uniform rescale around the center of a mesh that brings it to [`NORMALIZED_SIZE`], so
+/// analyses with fixed tolerances treat a mesh modeled in meters like one modeled in
+/// millimeters. Only the analyses see it: the mesh keeps the scale the user gave it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normalization {
+    pub center: Vector3<f32>,
+    pub scale: f32,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self {
+            center: Vector3::zeros(),
+            scale: 1.0,
+        }
+    }
+}
+
+impl Normalization {
+    /// Normalization of the bounds of the points. Without any points, or when they all
+    /// coincide, there is no size to go by and the points are only recentered.
+    pub fn of(points: impl IntoIterator<Item = Vector3<f32>>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self::default();
+        };
+        let (min, max) = points.fold((first, first), |(min, max), p| (min.inf(&p), max.sup(&p)));
+        let size = (max - min).max();
+        Self {
+            center: (min + max) / 2.0,
+            scale: if size > 0.0 && size.is_finite() {
+                NORMALIZED_SIZE / size
+            } else {
+                1.0
+            },
+        }
+    }
+
+    /// Normalization of the vertices of the triangles
+    pub fn of_triangles(triangles: &[Triangle]) -> Self {
+        Self::of(
+            triangles
+                .iter()
+                .flat_map(|triangle| triangle.vertices.map(Vector3::from)),
+        )
+    }
+
+    pub fn apply(&self, point: Vector3<f32>) -> Vector3<f32> {
+        (point - self.center) * self.scale
+    }
+
+    /// A distance on the mesh as it is, in normalized units
+    pub fn length(&self, length: f32) -> f32 {
+        length * self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(vertices: [[f32; 3]; 3]) -> Triangle {
+        Triangle {
+            normal: [0.0, 0.0, 1.0],
+            vertices,
+        }
+    }
+
+    #[test]
+    fn test_transform_triangles() {
+        let triangles = [
+            triangle([[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]),
+            triangle([[0.0, 0.0, 2.0], [1.0, 0.0, 2.0], [0.0, 0.0, 2.0]]),
+        ];
+        let matrix = Matrix4::new_translation(&Vector3::new(1.0, 2.0, 3.0))
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 2.0, 4.0));
+        let moved = transform_triangles(&triangles, &matrix);
+        assert_eq!(moved[0].vertices[1], [3.0, 2.0, 3.0]);
+        assert_eq!(moved[0].normal, [0.0, 0.0, 1.0]);
+
+        let aabb = bounding_box(&moved).unwrap();
+        assert_eq!(aabb.min, Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(aabb.max, Vector3::new(3.0, 4.0, 11.0));
+        assert_eq!(z_range(&moved), Some((3.0, 11.0)));
+        assert_eq!(longest_side(&moved), 8.0);
+
+        let degenerate = Triangle {
+            normal: [0.0; 3],
+            vertices: triangles[1].vertices,
+        };
+        assert_eq!(
+            transform_triangles(&[degenerate], &matrix)[0].normal,
+            [0.0; 3]
+        );
+
+        assert!(bounding_box(&[]).is_none());
+        assert_eq!(z_range(&[]), None);
+        assert_eq!(longest_side(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_normalization() {
+        let points = [Vector3::new(1.0, 2.0, 3.0), Vector3::new(1.5, 2.25, 3.0)];
+        let normalization = Normalization::of(points);
+        assert_eq!(normalization.center, Vector3::new(1.25, 2.125, 3.0));
+        assert_eq!(normalization.scale, 200.0);
+        assert_eq!(
+            normalization.apply(points[1]),
+            Vector3::new(50.0, 25.0, 0.0)
+        );
+        assert_eq!(normalization.length(0.5), 100.0);
+
+        // The same mesh in meters instead of millimeters normalizes to the same points
+        let meters = Normalization::of(points.map(|p| p / 1000.0));
+        assert!((meters.apply(points[1] / 1000.0) - Vector3::new(50.0, 25.0, 0.0)).norm() < 1e-3);
+
+        let single = Normalization::of([points[0]]);
+        assert_eq!(single.scale, 1.0);
+        assert_eq!(single.apply(points[0]), Vector3::zeros());
+        assert_eq!(Normalization::of([]), Normalization::default());
+    }
+}
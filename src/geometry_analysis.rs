@@ -0,0 +1,226 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::mesh::Mesh;
+use nalgebra::{Matrix4, Point3, Vector3};
+use std::collections::HashMap;
+
+/// Per-vertex analysis of a mesh, computed once and shared by everything that wants to
+/// spend more detail where the surface bends, like adaptive layer heights.
+pub struct GeometryAnalysis {
+    /// Mean curvature magnitude of every simple vertex, in 1/mm
+    pub vertex_curvature: Vec<f32>,
+}
+
+impl GeometryAnalysis {
+    /// Computes the discrete mean curvature of every simple vertex from the dihedral
+    /// angles of the edges around it, normalized by a third of the area of its faces.
+    pub fn analyze(mesh: &Mesh) -> Self {
+        let positions: Vec<Vector3<f32>> = mesh
+            .simple_vertices
+            .iter()
+            .map(|v| Vector3::from(v.position))
+            .collect();
+        let faces: Vec<[u32; 3]> = mesh
+            .simple_indices
+            .chunks_exact(3)
+            .map(|f| [f[0], f[1], f[2]])
+            .collect();
+
+        let face_normals: Vec<Vector3<f32>> = faces
+            .iter()
+            .map(|f| {
+                let [a, b, c] = f.map(|i| positions[i as usize]);
+                (b - a).cross(&(c - a))
+            })
+            .collect();
+
+        // Faces on each undirected edge, and a third of the face area on each vertex
+        let mut edge_faces: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        let mut vertex_area = vec![0.0f32; positions.len()];
+        for (face_index, face) in faces.iter().enumerate() {
+            let area = face_normals[face_index].norm() / 2.0;
+            for i in 0..3 {
+                let (a, b) = (face[i], face[(i + 1) % 3]);
+                edge_faces
+                    .entry((a.min(b), a.max(b)))
+                    .or_default()
+                    .push(face_index);
+                vertex_area[face[i] as usize] += area / 3.0;
+            }
+        }
+
+        let mut curvature_sum = vec![0.0f32; positions.len()];
+        for (&(a, b), adjacent) in &edge_faces {
+            // Boundary and non-manifold edges have no well defined dihedral angle
+            if adjacent.len() != 2 {
+                continue;
+            }
+            let n0 = face_normals[adjacent[0]];
+            let n1 = face_normals[adjacent[1]];
+            if n0.norm() == 0.0 || n1.norm() == 0.0 {
+                continue;
+            }
+            let dihedral = n0.angle(&n1);
+            let length = (positions[a as usize] - positions[b as usize]).norm();
+            let contribution = dihedral * length / 4.0;
+            curvature_sum[a as usize] += contribution;
+            curvature_sum[b as usize] += contribution;
+        }
+
+        let vertex_curvature = curvature_sum
+            .iter()
+            .zip(vertex_area.iter())
+            .map(|(&sum, &area)| if area > 0.0 { sum / area } else { 0.0 })
+            .collect();
+        Self { vertex_curvature }
+    }
+
+    /// Highest curvature in each horizontal band of `band_height` of the transformed mesh,
+    /// starting at the lowest vertex. Returns the bottom of the first band and the bands.
+    pub fn z_curvature_profile(
+        &self,
+        mesh: &Mesh,
+        model_matrix: &Matrix4<f32>,
+        band_height: f32,
+    ) -> (f32, Vec<f32>) {
+        let heights: Vec<f32> = mesh
+            .simple_vertices
+            .iter()
+            .map(|v| model_matrix.transform_point(&Point3::from(v.position)).z)
+            .collect();
+        let Some(min_z) = heights.iter().cloned().reduce(f32::min) else {
+            return (0.0, Vec::new());
+        };
+        let max_z = heights.iter().cloned().fold(min_z, f32::max);
+        let band_count = ((max_z - min_z) / band_height).floor() as usize + 1;
+
+        let mut profile = vec![0.0f32; band_count];
+        for (z, &curvature) in heights.iter().zip(self.vertex_curvature.iter()) {
+            let band = (((z - min_z) / band_height) as usize).min(band_count - 1);
+            profile[band] = profile[band].max(curvature);
+        }
+        (min_z, profile)
+    }
+}
+
+/// Layer heights for adaptive slicing: `max_height` where the surface is flat, thinner
+/// layers the more curved the band is, never below `min_height`. `sensitivity` is the
+/// curvature (1/mm) at which the layer height is halved.
+pub fn adaptive_layer_heights(
+    min_z: f32,
+    profile: &[f32],
+    band_height: f32,
+    min_height: f32,
+    max_height: f32,
+    sensitivity: f32,
+) -> Vec<f32> {
+    let top = min_z + profile.len() as f32 * band_height;
+    let mut z = min_z;
+    let mut layer_z = Vec::new();
+    while z < top {
+        let band = (((z - min_z) / band_height) as usize).min(profile.len() - 1);
+        let height =
+            (max_height / (1.0 + profile[band] / sensitivity)).clamp(min_height, max_height);
+        z += height;
+        layer_z.push(z);
+    }
+    layer_z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::SimpleVertex;
+
+    /// A closed unit cube made of 12 triangles
+    fn cube() -> Mesh {
+        let corners = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ];
+        let simple_indices = vec![
+            0, 2, 1, 0, 3, 2, // bottom
+            4, 5, 6, 4, 6, 7, // top
+            0, 1, 5, 0, 5, 4, // front
+            1, 2, 6, 1, 6, 5, // right
+            2, 3, 7, 2, 7, 6, // back
+            3, 0, 4, 3, 4, 7, // left
+        ];
+        Mesh {
+            simple_vertices: corners.iter().map(|&p| SimpleVertex::new(p)).collect(),
+            simple_indices,
+            ..Mesh::default()
+        }
+    }
+
+    /// A flat 3x3 grid of vertices in the XY plane
+    fn flat_grid() -> Mesh {
+        let mut simple_vertices = Vec::new();
+        for y in 0..3 {
+            for x in 0..3 {
+                simple_vertices.push(SimpleVertex::new([x as f32, y as f32, 0.0]));
+            }
+        }
+        let mut simple_indices = Vec::new();
+        for y in 0..2u32 {
+            for x in 0..2u32 {
+                let i = y * 3 + x;
+                simple_indices.extend_from_slice(&[i, i + 1, i + 4, i, i + 4, i + 3]);
+            }
+        }
+        Mesh {
+            simple_vertices,
+            simple_indices,
+            ..Mesh::default()
+        }
+    }
+
+    #[test]
+    fn test_flat_surface_has_no_curvature() {
+        let analysis = GeometryAnalysis::analyze(&flat_grid());
+        assert!(analysis.vertex_curvature.iter().all(|&c| c.abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_cube_corners_are_curved() {
+        let analysis = GeometryAnalysis::analyze(&cube());
+        assert_eq!(analysis.vertex_curvature.len(), 8);
+        assert!(analysis.vertex_curvature.iter().all(|&c| c > 0.1));
+    }
+
+    #[test]
+    fn test_adaptive_layers_are_thinner_where_curved() {
+        // Flat in the lower half, curved in the upper half
+        let profile = [0.0, 0.0, 2.0, 2.0];
+        let layers = adaptive_layer_heights(0.0, &profile, 1.0, 0.025, 0.1, 1.0);
+
+        let lower = layers.iter().filter(|&&z| z <= 2.0).count();
+        let upper = layers.len() - lower;
+        // 2mm of flat surface at the 0.1mm maximum, give or take rounding
+        assert!((19..=21).contains(&lower));
+        assert!(upper > lower);
+        assert!(*layers.last().unwrap() >= 4.0);
+        for pair in layers.windows(2) {
+            assert!(pair[1] - pair[0] >= 0.025 - 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_z_curvature_profile() {
+        let mesh = cube();
+        let analysis = GeometryAnalysis::analyze(&mesh);
+        let lift = Matrix4::new_translation(&Vector3::new(0.0, 0.0, 5.0));
+        let (min_z, profile) = analysis.z_curvature_profile(&mesh, &lift, 0.5);
+        assert_eq!(min_z, 5.0);
+        assert_eq!(profile.len(), 3);
+        assert!(profile[0] > 0.0 && profile[2] > 0.0);
+        assert_eq!(profile[1], 0.0);
+    }
+}
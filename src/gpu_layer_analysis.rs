@@ -0,0 +1,199 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::layer_components::CURED_THRESHOLD;
+use glow::Context as GlowContext;
+use glow::HasContext;
+use image::{ImageBuffer, Luma};
+use std::fs;
+use std::rc::Rc;
+use thiserror::Error;
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+// Invocations per work group, as declared in the shader
+const WORK_GROUP_SIZE: u32 = 256;
+// Spread passes dispatched between reading back whether anything changed
+const PASSES_PER_CHECK: u32 = 32;
+
+#[derive(Error, Debug)]
+pub enum GpuAnalysisError {
+    #[error("OpenGL {0}.{1} has no compute shaders")]
+    Unsupported(u32, u32),
+
+    #[error("Could not read the layer support shader: {0}")]
+    ShaderSource(#[from] std::io::Error),
+
+    #[error("OpenGL error: {0}")]
+    Gl(String),
+}
+
+/// Runs the island analysis of `layer_analysis` as a compute pass, on the GL context of
+/// the 3D view. Needs OpenGL 4.3 or OpenGL ES 3.1 and the context to be current.
+pub struct GpuLayerAnalyzer {
+    gl: Rc<GlowContext>,
+    program: glow::Program,
+}
+
+impl GpuLayerAnalyzer {
+    pub fn new(gl: Rc<GlowContext>) -> Result<Self, GpuAnalysisError> {
+        let version = gl.version();
+        let (major, minor) = (version.major, version.minor);
+        let supported = if version.is_embedded {
+            (major, minor) >= (3, 1)
+        } else {
+            (major, minor) >= (4, 3)
+        };
+        if !supported {
+            return Err(GpuAnalysisError::Unsupported(major, minor));
+        }
+        let header = if version.is_embedded {
+            "#version 310 es\nprecision highp int;\n"
+        } else {
+            "#version 430\n"
+        };
+        let path = format!(
+            "{}/resources/shaders/layer_support.comp",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let source = format!("{}{}", header, fs::read_to_string(path)?);
+
+        unsafe {
+            let program = gl.create_program().map_err(GpuAnalysisError::Gl)?;
+            let shader = gl
+                .create_shader(glow::COMPUTE_SHADER)
+                .map_err(GpuAnalysisError::Gl)?;
+            gl.shader_source(shader, &source);
+            gl.compile_shader(shader);
+            if !gl.get_shader_compile_status(shader) {
+                let log = gl.get_shader_info_log(shader);
+                gl.delete_shader(shader);
+                gl.delete_program(program);
+                return Err(GpuAnalysisError::Gl(log));
+            }
+            gl.attach_shader(program, shader);
+            gl.link_program(program);
+            gl.detach_shader(program, shader);
+            gl.delete_shader(shader);
+            if !gl.get_program_link_status(program) {
+                let log = gl.get_program_info_log(program);
+                gl.delete_program(program);
+                return Err(GpuAnalysisError::Gl(log));
+            }
+            Ok(Self { gl, program })
+        }
+    }
+
+    /// Same as `layer_analysis::supported_pixels`, computed on the GPU
+    pub fn supported_pixels(
+        &self,
+        below: Option<&Layer>,
+        layer: &Layer,
+    ) -> Result<Vec<bool>, GpuAnalysisError> {
+        let gl = &self.gl;
+        let (width, height) = layer.dimensions();
+        let pixel_count = width * height;
+        let groups = pixel_count.div_ceil(WORK_GROUP_SIZE).max(1);
+        // Dispatches are limited to 65535 groups along each axis
+        let groups_x = groups.min(u16::MAX as u32);
+        let groups_y = groups.div_ceil(groups_x);
+
+        // Four pixels to a uint, padded to whole uints
+        let packed = |layer: &Layer| {
+            let mut bytes = layer.as_raw().clone();
+            bytes.resize(bytes.len().next_multiple_of(4), 0);
+            bytes
+        };
+
+        unsafe {
+            let buffers = [
+                gl.create_buffer().map_err(GpuAnalysisError::Gl)?,
+                gl.create_buffer().map_err(GpuAnalysisError::Gl)?,
+                gl.create_buffer().map_err(GpuAnalysisError::Gl)?,
+                gl.create_buffer().map_err(GpuAnalysisError::Gl)?,
+            ];
+            let [layer_buffer, below_buffer, support_buffer, changed_buffer] = buffers;
+            let upload = |binding: u32, buffer: glow::Buffer, data: &[u8]| {
+                gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(buffer));
+                gl.buffer_data_u8_slice(glow::SHADER_STORAGE_BUFFER, data, glow::DYNAMIC_COPY);
+                gl.bind_buffer_base(glow::SHADER_STORAGE_BUFFER, binding, Some(buffer));
+            };
+            upload(0, layer_buffer, &packed(layer));
+            // Unused for the first layer, but every binding needs a buffer
+            upload(1, below_buffer, &below.map(packed).unwrap_or(vec![0; 4]));
+            upload(2, support_buffer, &vec![0; pixel_count as usize * 4]);
+            upload(3, changed_buffer, &[0; 4]);
+
+            gl.use_program(Some(self.program));
+            let set = |name: &str, value: u32| {
+                let location = gl.get_uniform_location(self.program, name);
+                gl.uniform_1_u32(location.as_ref(), value);
+            };
+            set("width", width);
+            set("height", height);
+            set("threshold", CURED_THRESHOLD as u32);
+            set("has_below", below.is_some() as u32);
+            set("spread", 0);
+            gl.dispatch_compute(groups_x, groups_y, 1);
+            gl.memory_barrier(glow::SHADER_STORAGE_BARRIER_BIT);
+
+            // Support spreads at least one pixel per pass, so this ends
+            set("spread", 1);
+            let spread = || loop {
+                gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(changed_buffer));
+                gl.buffer_sub_data_u8_slice(glow::SHADER_STORAGE_BUFFER, 0, &[0; 4]);
+                for _ in 0..PASSES_PER_CHECK {
+                    gl.dispatch_compute(groups_x, groups_y, 1);
+                    // Reading back counts as a buffer update
+                    gl.memory_barrier(
+                        glow::SHADER_STORAGE_BARRIER_BIT | glow::BUFFER_UPDATE_BARRIER_BIT,
+                    );
+                }
+                if read_buffer(gl, changed_buffer, 4)? == [0; 4] {
+                    return read_buffer(gl, support_buffer, pixel_count as usize * 4);
+                }
+            };
+            let support = spread();
+
+            gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, None);
+            gl.use_program(None);
+            for buffer in buffers {
+                gl.delete_buffer(buffer);
+            }
+            Ok(support?
+                .chunks_exact(4)
+                .map(|value| u32::from_ne_bytes(value.try_into().unwrap()) == 2)
+                .collect())
+        }
+    }
+}
+
+// Copies the start of a buffer back from the GPU. Mapped, as GLES can't read buffers
+// directly.
+unsafe fn read_buffer(
+    gl: &GlowContext,
+    buffer: glow::Buffer,
+    len: usize,
+) -> Result<Vec<u8>, GpuAnalysisError> {
+    gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(buffer));
+    let mapped = gl.map_buffer_range(
+        glow::SHADER_STORAGE_BUFFER,
+        0,
+        len as i32,
+        glow::MAP_READ_BIT,
+    );
+    if mapped.is_null() {
+        return Err(GpuAnalysisError::Gl("Could not map a buffer".to_string()));
+    }
+    let data = std::slice::from_raw_parts(mapped, len).to_vec();
+    gl.unmap_buffer(glow::SHADER_STORAGE_BUFFER);
+    Ok(data)
+}
+
+impl Drop for GpuLayerAnalyzer {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_program(self.program);
+        }
+    }
+}
@@ -12,6 +12,9 @@ use glow::Context as GlowContext;
 use std::error::Error;
 
 use crate::body::Body;
+use crate::geometry;
+use crate::printer::Printer;
+use crate::tolerance::TolerancePolicy;
 pub struct GPUSlicer {
     gl: Rc<GlowContext>,
 }
@@ -24,14 +27,18 @@ impl GPUSlicer {
     pub fn slice_bodies(
         &self,
         _bodies: Vec<Rc<RefCell<Body>>>,
+        printer: &Printer,
     ) -> Result<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>, Box<dyn std::error::Error>> {
         let triangles: Vec<Triangle> = Vec::new();
-        self.generate_slice_images(&triangles)
+        // The same tolerances as the CPU slicer, so both produce the same layers
+        let tolerances = TolerancePolicy::for_printer(geometry::longest_side(&triangles), printer);
+        self.generate_slice_images(&triangles, &tolerances)
     }
     // Function to generate slice images
     fn generate_slice_images(
         &self,
         triangles: &[Triangle],
+        _tolerances: &TolerancePolicy,
     ) -> Result<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>, Box<dyn Error>> {
         let gl = &self.gl;
 
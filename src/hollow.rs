@@ -0,0 +1,227 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::Body;
+use crate::geometry::signed_volume;
+use crate::mesh::Mesh;
+use crate::number_format;
+use nalgebra::{Matrix3, Vector3};
+use std::collections::HashMap;
+use stl_io::Triangle;
+use thiserror::Error;
+
+/// The inner shell moves at most this many wall thicknesses at sharp edges, where it has to
+/// move further than the thickness to stay that far from the faces around them
+const MAX_CORNER_FACTOR: f32 = 3.0;
+
+/// Share of the faces of the inner shell allowed to turn over where the body is thinner than
+/// two walls, e.g. small details, before the body counts as too thin to hollow
+const MAX_FLIPPED_SHARE: f32 = 0.01;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum HollowError {
+    #[error("The wall thickness has to be more than 0 mm")]
+    NoWall,
+
+    #[error("{0} is not closed, hollowing it would leave its walls open")]
+    NotClosed(String),
+
+    #[error("{0} is already hollow")]
+    AlreadyHollow(String),
+
+    #[error("{0} is too thin for {1} mm walls")]
+    TooThin(String, String),
+}
+
+/// The inside of a body `wall_thickness` millimeters below its surface as it is scaled,
+/// wound inwards so the slicer leaves it empty. The shell is in the coordinates of the mesh.
+pub fn inner_shell(body: &Body, wall_thickness: f32) -> Result<Vec<Triangle>, HollowError> {
+    if wall_thickness <= 0.0 {
+        return Err(HollowError::NoWall);
+    }
+    let mesh = &body.mesh;
+    if !mesh.non_manifold_edges().is_empty() {
+        return Err(HollowError::NotClosed(body.name.clone()));
+    }
+    if !inner_shells(mesh).is_empty() {
+        return Err(HollowError::AlreadyHollow(body.name.clone()));
+    }
+    let too_thin =
+        || HollowError::TooThin(body.name.clone(), number_format::shortest(wall_thickness));
+
+    // The walls are measured after the scale and rotation, so the offset is too
+    let linear: Matrix3<f32> = body.get_model_matrix().fixed_view::<3, 3>(0, 0).into();
+    let inverse = linear.try_inverse().ok_or_else(too_thin)?;
+    // Mirroring turns the faces inside out
+    let winding = linear.determinant().signum();
+    let world: Vec<Vector3<f32>> = mesh
+        .simple_vertices
+        .iter()
+        .map(|v| linear * Vector3::from(v.position))
+        .collect();
+    let faces: Vec<[usize; 3]> = mesh
+        .simple_indices
+        .chunks_exact(3)
+        .map(|face| [face[0] as usize, face[1] as usize, face[2] as usize])
+        .collect();
+    let face_normal = |positions: &[Vector3<f32>], [a, b, c]: [usize; 3]| {
+        (positions[b] - positions[a]).cross(&(positions[c] - positions[a])) * winding
+    };
+
+    // Normals of the vertices weighted by the angle of each face at them, so they don't
+    // depend on how the faces are split into triangles
+    let face_normals: Vec<Vector3<f32>> = faces.iter().map(|&f| face_normal(&world, f)).collect();
+    let mut normals = vec![Vector3::zeros(); world.len()];
+    for (face, normal) in faces.iter().zip(&face_normals) {
+        let Some(normal) = normal.try_normalize(0.0) else {
+            continue;
+        };
+        for k in 0..3 {
+            let [i, j, l] = [face[k], face[(k + 1) % 3], face[(k + 2) % 3]];
+            normals[i] += normal * (world[j] - world[i]).angle(&(world[l] - world[i]));
+        }
+    }
+    let normals: Vec<Vector3<f32>> = normals
+        .iter()
+        .map(|n| n.try_normalize(0.0).unwrap_or_default())
+        .collect();
+    // How far each vertex moves for the faces around it to move by the wall thickness
+    let mut factors = vec![1.0f32; world.len()];
+    for (face, normal) in faces.iter().zip(&face_normals) {
+        let Some(normal) = normal.try_normalize(0.0) else {
+            continue;
+        };
+        for &i in face {
+            let cosine = normals[i].dot(&normal).max(1.0 / MAX_CORNER_FACTOR);
+            factors[i] = factors[i].max(1.0 / cosine);
+        }
+    }
+    let inner: Vec<Vector3<f32>> = world
+        .iter()
+        .zip(normals.iter().zip(&factors))
+        .map(|(p, (n, &factor))| p - n * wall_thickness * factor)
+        .collect();
+
+    let flipped = faces
+        .iter()
+        .zip(&face_normals)
+        .filter(|&(&face, normal)| face_normal(&inner, face).dot(normal) <= 0.0)
+        .count();
+    if flipped as f32 > faces.len() as f32 * MAX_FLIPPED_SHARE {
+        return Err(too_thin());
+    }
+
+    let shell: Vec<Triangle> = faces
+        .iter()
+        .map(|&[a, b, c]| {
+            let vertices = [a, c, b].map(|i| inverse * inner[i]);
+            let [p, q, r] = vertices;
+            Triangle {
+                normal: (q - p)
+                    .cross(&(r - p))
+                    .try_normalize(0.0)
+                    .unwrap_or_default()
+                    .into(),
+                vertices: vertices.map(Into::into),
+            }
+        })
+        .collect();
+    Ok(shell)
+}
+
+/// Replaces the mesh of the body with its surface and its inner shell
+pub fn hollow(body: &mut Body, wall_thickness: f32) -> Result<(), HollowError> {
+    let mut triangles = body.mesh.get_triangles_for_slicing();
+    triangles.extend(inner_shell(body, wall_thickness)?);
+    body.set_mesh(Mesh::from_triangles(&triangles));
+    Ok(())
+}
+
+/// The closed pieces of the mesh wound inwards, like the inside of a hollow body
+pub fn inner_shells(mesh: &Mesh) -> Vec<Vec<Triangle>> {
+    fn root(piece: &mut [usize], mut i: usize) -> usize {
+        while piece[i] != i {
+            piece[i] = piece[piece[i]];
+            i = piece[i];
+        }
+        i
+    }
+    let mut piece: Vec<usize> = (0..mesh.simple_vertices.len()).collect();
+    for face in mesh.simple_indices.chunks_exact(3) {
+        for &i in &face[1..] {
+            let (a, b) = (
+                root(&mut piece, face[0] as usize),
+                root(&mut piece, i as usize),
+            );
+            piece[a] = b;
+        }
+    }
+    let mut pieces: HashMap<usize, Vec<Triangle>> = HashMap::new();
+    for face in mesh.simple_indices.chunks_exact(3) {
+        let vertices = [0, 1, 2].map(|k| mesh.simple_vertices[face[k] as usize].position);
+        pieces
+            .entry(root(&mut piece, face[0] as usize))
+            .or_default()
+            .push(Triangle {
+                normal: [0.0; 3],
+                vertices,
+            });
+    }
+    pieces
+        .into_values()
+        .filter(|triangles| signed_volume(triangles) < 0.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::solid_box;
+
+    #[test]
+    fn test_hollow_box() {
+        let mut body = solid_box([10.0, 8.0, 6.0]);
+        hollow(&mut body, 1.0).unwrap();
+        let triangles = body.mesh.get_triangles_for_slicing();
+        assert_eq!(triangles.len(), 24);
+        assert!(body.mesh.non_manifold_edges().is_empty());
+        // The walls of a box are exactly as thick as asked, its corners move diagonally
+        let volume = 10.0 * 8.0 * 6.0 - 8.0 * 6.0 * 4.0;
+        assert!((signed_volume(&triangles) - volume).abs() < 1e-3);
+
+        assert_eq!(
+            hollow(&mut body, 1.0),
+            Err(HollowError::AlreadyHollow(body.name.clone()))
+        );
+    }
+
+    #[test]
+    fn test_walls_follow_the_scale() {
+        // Twice as wide on the plate as in the mesh, the walls are still 1 mm
+        let mut body = solid_box([5.0, 8.0, 6.0]);
+        body.scale = Vector3::new(2.0, 1.0, 1.0);
+        let shell = inner_shell(&body, 1.0).unwrap();
+        let xs = shell.iter().flat_map(|t| t.vertices.map(|v| v[0]));
+        let (min, max) = xs.fold((f32::MAX, f32::MIN), |(a, b), x| (a.min(x), b.max(x)));
+        assert!((min - 0.5).abs() < 1e-4);
+        assert!((max - 4.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_unhollowable_bodies() {
+        let body = solid_box([10.0, 8.0, 6.0]);
+        assert_eq!(inner_shell(&body, 0.0), Err(HollowError::NoWall));
+        assert!(matches!(
+            inner_shell(&body, 3.5),
+            Err(HollowError::TooThin(_, _))
+        ));
+
+        let mut triangles = body.mesh.get_triangles_for_slicing();
+        triangles.pop();
+        let open = Body::new(Mesh::from_triangles(&triangles));
+        assert!(matches!(
+            inner_shell(&open, 1.0),
+            Err(HollowError::NotClosed(_))
+        ));
+    }
+}
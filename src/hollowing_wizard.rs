@@ -0,0 +1,238 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::action::{Action, CompoundAction, SetMeshAction};
+use crate::body::Body;
+use crate::cpu_slicer::CPUSlicer;
+use crate::drain_holes::{self, DrainError};
+use crate::geometry::signed_volume;
+use crate::hollow::{self, HollowError};
+use crate::infill::{self, InfillError};
+use crate::mesh::Mesh;
+use crate::number_format;
+use crate::printer::Printer;
+use crate::settings::HollowingSettings;
+use image::{ImageBuffer, Luma};
+use nalgebra::Vector3;
+use std::cell::RefCell;
+use std::rc::Rc;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum HollowingError {
+    #[error(transparent)]
+    Hollow(#[from] HollowError),
+
+    #[error(transparent)]
+    Infill(#[from] InfillError),
+
+    #[error(transparent)]
+    Drain(#[from] DrainError),
+}
+
+/// A copy of the body hollowed with the walls, infill and drain holes of the settings. The
+/// holes are placed before the infill so it stays out of their way. An infill density of 0
+/// leaves the inside empty.
+pub fn hollowed(body: &Body, settings: &HollowingSettings) -> Result<Body, HollowingError> {
+    let mut hollowed = body.clone();
+    hollow::hollow(&mut hollowed, settings.wall_thickness)?;
+    let holes = drain_holes::drain_holes(&hollowed, settings.drain_holes, settings.drain_diameter)?;
+    if settings.infill_density > 0.0 {
+        let mut triangles = hollowed.mesh.get_triangles_for_slicing();
+        triangles.extend(infill::lattice_clear_of(
+            &hollowed,
+            settings.infill_density,
+            settings.strut_width,
+            &holes,
+        )?);
+        hollowed.set_mesh(Mesh::from_triangles(&triangles));
+    }
+    drain_holes::drill(&mut hollowed, &holes);
+    Ok(hollowed)
+}
+
+/// What the hollowing wizard makes of the selected bodies, previewed before it is applied
+pub struct HollowingPlan {
+    pub settings: HollowingSettings,
+    /// The bodies with their hollowed copies
+    pub hollowed: Vec<(Rc<RefCell<Body>>, Body)>,
+    /// Why the other bodies can't be hollowed
+    pub errors: Vec<String>,
+}
+
+impl HollowingPlan {
+    pub fn new(bodies: &[Rc<RefCell<Body>>], settings: &HollowingSettings) -> Self {
+        let mut hollowed = Vec::new();
+        let mut errors = Vec::new();
+        for body_rc in bodies {
+            match self::hollowed(&body_rc.borrow(), settings) {
+                Ok(body) => hollowed.push((Rc::clone(body_rc), body)),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        Self {
+            settings: settings.clone(),
+            hollowed,
+            errors,
+        }
+    }
+
+    /// Milliliters of resin the hollowed bodies take less than the solid ones
+    pub fn resin_saved(&self) -> f64 {
+        self.hollowed
+            .iter()
+            .map(|(body_rc, hollowed)| volume(&body_rc.borrow()) - volume(hollowed))
+            .sum()
+    }
+
+    /// e.g. "Saves 41.2 ml of resin in Cube. Sphere is not closed, hollowing it would leave
+    /// its walls open"
+    pub fn describe(&self) -> String {
+        let mut messages = Vec::new();
+        if !self.hollowed.is_empty() {
+            let names: Vec<String> = self
+                .hollowed
+                .iter()
+                .map(|(body_rc, _)| body_rc.borrow().name.clone())
+                .collect();
+            messages.push(format!(
+                "Saves {} ml of resin in {}",
+                number_format::decimal(self.resin_saved(), 1),
+                names.join(", ")
+            ));
+        }
+        messages.extend(self.errors.iter().cloned());
+        if messages.is_empty() {
+            return "No bodies selected".to_string();
+        }
+        messages.join(". ")
+    }
+
+    /// The outlines of the hollowed bodies the way the layer preview shows them, cut through
+    /// the bottom wall to show the drain holes or else halfway up to show the walls and infill
+    pub fn section(
+        &self,
+        through_bottom: bool,
+        printer: &Printer,
+        width: u32,
+    ) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let bodies: Vec<&Body> = self.hollowed.iter().map(|(_, body)| body).collect();
+        let (bottom, top) = bodies
+            .iter()
+            .filter_map(|body| body.world_aabb())
+            .fold((f32::MAX, f32::MIN), |(bottom, top), aabb| {
+                (bottom.min(aabb.min.z), top.max(aabb.max.z))
+            });
+        let height = if through_bottom {
+            bottom + self.settings.wall_thickness / 2.0
+        } else {
+            (bottom + top) / 2.0
+        };
+        let contours = CPUSlicer::layer_contours(bodies, height as f64);
+        CPUSlicer::lcd_outline_preview(&contours, printer, width)
+    }
+
+    /// Replaces the meshes of the bodies with their hollowed ones, undone as one step
+    pub fn to_action(&self) -> CompoundAction {
+        let actions = self
+            .hollowed
+            .iter()
+            .map(|(body_rc, hollowed)| {
+                Box::new(SetMeshAction {
+                    body: Rc::clone(body_rc),
+                    input: hollowed.mesh.clone(),
+                    previous: body_rc.borrow().mesh.clone(),
+                }) as Box<dyn Action>
+            })
+            .collect();
+        CompoundAction { actions }
+    }
+}
+
+/// Milliliters the body takes on the plate
+fn volume(body: &Body) -> f64 {
+    let triangles = CPUSlicer::world_triangles([body], Vector3::new(1.0, 1.0, 1.0));
+    signed_volume(&triangles).abs() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_thickness::LayerThickness;
+    use crate::test_support::solid_box;
+
+    #[test]
+    fn test_plan_is_one_undo_step() {
+        let body = Rc::new(RefCell::new(solid_box([20.0, 20.0, 20.0])));
+        let mut thin = solid_box([3.0, 20.0, 20.0]);
+        thin.name = "Thin".to_string();
+        let thin = Rc::new(RefCell::new(thin));
+        let settings = HollowingSettings::default();
+        let plan = HollowingPlan::new(&[Rc::clone(&body), Rc::clone(&thin)], &settings);
+        assert_eq!(plan.hollowed.len(), 1);
+        assert_eq!(plan.errors, ["Thin is too thin for 2 mm walls"]);
+        // The hollow inside is 16 mm wide, less the struts and the drain holes
+        let saved = plan.resin_saved();
+        assert!(saved > 3.5 && saved < 16.0f64.powi(3) / 1000.0, "{}", saved);
+        assert!(plan.describe().starts_with("Saves"));
+
+        let solid = body.borrow().mesh.get_triangles_for_slicing();
+        let mut action = plan.to_action();
+        action.execute();
+        assert_eq!(
+            body.borrow().mesh.get_triangles_for_slicing(),
+            plan.hollowed[0].1.mesh.get_triangles_for_slicing()
+        );
+        action.undo();
+        assert_eq!(body.borrow().mesh.get_triangles_for_slicing(), solid);
+    }
+
+    #[test]
+    fn test_walls_infill_and_holes() {
+        let body = solid_box([20.0, 20.0, 20.0]);
+        let hollowed = hollowed(&body, &HollowingSettings::default()).unwrap();
+        // The surface and the inner shell, 27 struts less the two through the holes in
+        // opposite corners, and the holes
+        assert_eq!(
+            hollowed.mesh.get_triangles_for_slicing().len(),
+            12 + 12 + 25 * 12 + 2 * 12
+        );
+
+        let settings = HollowingSettings {
+            infill_density: 0.0,
+            drain_holes: 0,
+            ..HollowingSettings::default()
+        };
+        let hollowed = self::hollowed(&body, &settings).unwrap();
+        assert_eq!(hollowed.mesh.get_triangles_for_slicing().len(), 24);
+    }
+
+    #[test]
+    fn test_drain_holes_stay_open() {
+        let body = solid_box([20.0, 20.0, 20.0]);
+        let mut hollow = body.clone();
+        hollow::hollow(&mut hollow, 2.0).unwrap();
+        let hole = drain_holes::drain_holes(&hollow, 1, 3.0).unwrap()[0].clone();
+        let center = (hole.min + hole.max).xy() / 2.0;
+
+        // The holes go through the bottom wall and open into the hollow, nothing above them
+        // is cured until the top wall
+        let hollowed = hollowed(&body, &HollowingSettings::default()).unwrap();
+        let printer = Printer::default();
+        let thickness = LayerThickness::uniform(0.3);
+        let shrinkage = Vector3::new(1.0, 1.0, 1.0);
+        let heights = CPUSlicer::layer_heights([&hollowed], &thickness, shrinkage);
+        let layers =
+            CPUSlicer::slice_bodies(vec![hollowed], &thickness, &printer, shrinkage).unwrap();
+        let pixel = |layer: &ImageBuffer<Luma<u8>, Vec<u8>>, x: f32, y: f32| {
+            let (x, y) = CPUSlicer::model_to_lcd_coords(x as f64, y as f64, &printer);
+            layer.get_pixel(x as u32, y as u32)[0]
+        };
+        for (height, layer) in heights.iter().zip(&layers) {
+            if *height < 17.5 {
+                assert_eq!(pixel(layer, center.x, center.y), 0, "at {} mm", height);
+            }
+        }
+        assert_eq!(pixel(&layers[0], 1.0, 1.0), 255);
+    }
+}
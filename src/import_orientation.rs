@@ -0,0 +1,201 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::{Body, AABB};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, rc::Rc};
+
+/// The axis pointing up in the program a model comes from. STL has no say in it, most CAD
+/// and slicers use Z while many animation and sculpting tools use Y.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UpAxis {
+    #[default]
+    Z,
+    Y,
+}
+
+impl UpAxis {
+    /// Position in the up axis list of the import dialog
+    pub fn from_index(index: i32) -> Self {
+        match index {
+            1 => UpAxis::Y,
+            _ => UpAxis::Z,
+        }
+    }
+
+    pub fn index(self) -> i32 {
+        match self {
+            UpAxis::Z => 0,
+            UpAxis::Y => 1,
+        }
+    }
+
+    /// The rotation turning this axis to Z, the way up on the plate
+    pub fn to_z_up(self) -> UnitQuaternion<f32> {
+        match self {
+            UpAxis::Z => UnitQuaternion::identity(),
+            UpAxis::Y => {
+                UnitQuaternion::from_axis_angle(&Vector3::x_axis(), std::f32::consts::FRAC_PI_2)
+            }
+        }
+    }
+}
+
+struct ImportedBody {
+    body: Rc<RefCell<Body>>,
+    /// Placement read from the file
+    position: Vector3<f32>,
+    rotation: Quaternion<f32>,
+    /// Bodies of the same file with an arrangement share this, e.g. the items of a 3MF plate
+    arrangement: Option<usize>,
+}
+
+/// The bodies of one import with the placement they were read with, so their orientation can
+/// still be chosen again after they are on the plate
+#[derive(Default)]
+pub struct ImportedBatch {
+    bodies: Vec<ImportedBody>,
+}
+
+impl ImportedBatch {
+    /// Adds the bodies of a file. `arranged` files place their bodies on a plate of their own,
+    /// which is kept together.
+    pub fn add(&mut self, bodies: &[Rc<RefCell<Body>>], arranged: bool) {
+        let arrangement = arranged.then(|| {
+            self.bodies
+                .iter()
+                .filter_map(|imported| imported.arrangement)
+                .max()
+                .map_or(0, |last| last + 1)
+        });
+        self.bodies.extend(bodies.iter().map(|body| {
+            let b = body.borrow();
+            ImportedBody {
+                body: Rc::clone(body),
+                position: b.position,
+                rotation: b.rotation,
+                arrangement,
+            }
+        }));
+    }
+
+    pub fn bodies(&self) -> Vec<Rc<RefCell<Body>>> {
+        self.bodies.iter().map(|b| Rc::clone(&b.body)).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn has_arrangement(&self) -> bool {
+        self.bodies.iter().any(|b| b.arrangement.is_some())
+    }
+
+    /// Places the bodies on the plate as if the files had `up_axis` up. Arranged files are
+    /// kept together around the center of the plate unless `keep_arrangement` is off, which
+    /// centers each of their bodies on its own. Every body ends up standing on the plate.
+    pub fn orient(&self, up_axis: UpAxis, keep_arrangement: bool) {
+        let turn = up_axis.to_z_up();
+        for imported in &self.bodies {
+            let mut body = imported.body.borrow_mut();
+            body.rotation =
+                (turn * UnitQuaternion::from_quaternion(imported.rotation)).into_inner();
+            body.position = turn * imported.position;
+        }
+
+        let mut groups: Vec<Vec<&ImportedBody>> = Vec::new();
+        for imported in &self.bodies {
+            let arrangement = imported.arrangement.filter(|_| keep_arrangement);
+            let group = groups
+                .iter_mut()
+                .find(|group| arrangement.is_some() && group[0].arrangement == arrangement);
+            match group {
+                Some(group) => group.push(imported),
+                None => groups.push(vec![imported]),
+            }
+        }
+        for group in groups {
+            let bounds = group
+                .iter()
+                .filter_map(|imported| imported.body.borrow().world_aabb())
+                .reduce(|a, b| AABB {
+                    min: a.min.inf(&b.min),
+                    max: a.max.sup(&b.max),
+                });
+            let Some(bounds) = bounds else {
+                continue;
+            };
+            // Files without an arrangement keep the position their origin gives them
+            let center = if group[0].arrangement.is_some() {
+                (bounds.min + bounds.max) / 2.0
+            } else {
+                Vector3::zeros()
+            };
+            let offset = Vector3::new(-center.x, -center.y, -bounds.min.z);
+            for imported in group {
+                imported.body.borrow_mut().position += offset;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+    use stl_io::Triangle;
+
+    fn body(position: Vector3<f32>) -> Rc<RefCell<Body>> {
+        // A right triangle standing 2 mm along Y and 1 mm along Z in its own coordinates
+        let triangle = Triangle {
+            normal: [1.0, 0.0, 0.0],
+            vertices: [[0.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 1.0]],
+        };
+        let mut body = Body::new(Mesh::from_triangles(&[triangle]));
+        body.position = position;
+        Rc::new(RefCell::new(body))
+    }
+
+    #[test]
+    fn test_y_up() {
+        let stl = body(Vector3::zeros());
+        let mut batch = ImportedBatch::default();
+        batch.add(&[Rc::clone(&stl)], false);
+        batch.orient(UpAxis::Y, true);
+        let aabb = stl.borrow().world_aabb().unwrap();
+        assert!((aabb.min - Vector3::new(0.0, -1.0, 0.0)).norm() < 1e-6);
+        assert!((aabb.max - Vector3::new(0.0, 0.0, 2.0)).norm() < 1e-6);
+
+        // Choosing again starts over from the file
+        batch.orient(UpAxis::Z, true);
+        let aabb = stl.borrow().world_aabb().unwrap();
+        assert!((aabb.max - Vector3::new(0.0, 2.0, 1.0)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_arrangement() {
+        let plate = [
+            body(Vector3::new(100.0, 100.0, 5.0)),
+            body(Vector3::new(120.0, 100.0, 5.0)),
+        ];
+        let mut batch = ImportedBatch::default();
+        batch.add(&plate, true);
+        assert!(batch.has_arrangement());
+
+        batch.orient(UpAxis::Z, true);
+        let positions: Vec<_> = plate.iter().map(|b| b.borrow().position).collect();
+        assert!((positions[0] - Vector3::new(-10.0, -1.0, 0.0)).norm() < 1e-6);
+        assert!((positions[1] - Vector3::new(10.0, -1.0, 0.0)).norm() < 1e-6);
+
+        batch.orient(UpAxis::Z, false);
+        for body in &plate {
+            assert!((body.borrow().position - Vector3::new(0.0, -1.0, 0.0)).norm() < 1e-6);
+        }
+    }
+}
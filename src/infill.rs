@@ -0,0 +1,283 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::{Body, AABB};
+use crate::geometry::{bounding_box, transform_triangles};
+use crate::hollow::inner_shells;
+use crate::mesh::Mesh;
+use crate::number_format;
+use nalgebra::{Matrix3, Vector2, Vector3};
+use stl_io::Triangle;
+use thiserror::Error;
+
+/// Struts reach this share of their width past the inside of the walls, so they fuse with
+/// them instead of just touching them
+const ANCHOR_SHARE: f32 = 0.5;
+
+/// Crossings of a line with a shell closer than this along the line are one crossing, where
+/// the line passes through an edge or a corner shared by several faces
+const SAME_CROSSING: f32 = 1e-4;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum InfillError {
+    #[error("The infill density has to be more than 0 %")]
+    NoDensity,
+
+    #[error("The struts of the infill have to be wider than 0 mm")]
+    NoStrutWidth,
+
+    #[error("{0} is not hollow, hollow it before adding infill")]
+    NotHollow(String),
+
+    #[error("The inside of {0} is too small for {1} mm struts")]
+    TooSmall(String, String),
+}
+
+/// Distance between the struts of a grid lattice filling `density` percent of the space.
+/// Every cell of the grid holds three struts as long as the cell is wide.
+pub fn cell_size(density: f32, strut_width: f32) -> f32 {
+    strut_width * (3.0 / (density / 100.0).min(1.0)).sqrt()
+}
+
+/// A grid lattice of square struts along the three axes filling the hollow inside of a body,
+/// with the ends of the struts sunk into its walls. The lattice is in the coordinates of the
+/// mesh, but even on the plate, like the walls.
+pub fn lattice(body: &Body, density: f32, strut_width: f32) -> Result<Vec<Triangle>, InfillError> {
+    lattice_clear_of(body, density, strut_width, &[])
+}
+
+/// The lattice without the struts that would cross any of `keep_clear`, boxes scaled and
+/// rotated as on the plate like the drain holes
+pub fn lattice_clear_of(
+    body: &Body,
+    density: f32,
+    strut_width: f32,
+    keep_clear: &[AABB],
+) -> Result<Vec<Triangle>, InfillError> {
+    if density <= 0.0 {
+        return Err(InfillError::NoDensity);
+    }
+    if strut_width <= 0.0 {
+        return Err(InfillError::NoStrutWidth);
+    }
+    let shells = inner_shells(&body.mesh);
+    if shells.is_empty() {
+        return Err(InfillError::NotHollow(body.name.clone()));
+    }
+    let too_small =
+        || InfillError::TooSmall(body.name.clone(), number_format::shortest(strut_width));
+
+    let linear: Matrix3<f32> = body.get_model_matrix().fixed_view::<3, 3>(0, 0).into();
+    let inverse = linear.try_inverse().ok_or_else(too_small)?;
+    let cell = cell_size(density, strut_width);
+    let half = strut_width / 2.0;
+    let anchor = strut_width * ANCHOR_SHARE;
+    let mut struts = Vec::new();
+    for shell in &shells {
+        let shell = transform_triangles(shell, &linear.to_homogeneous());
+        let Some(bounds) = bounding_box(&shell) else {
+            continue;
+        };
+        // Centered in the hollow, so it is filled evenly
+        let center = (bounds.min + bounds.max) / 2.0;
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for a in grid(center[u], bounds.min[u], bounds.max[u], cell) {
+                for b in grid(center[v], bounds.min[v], bounds.max[v], cell) {
+                    let crossings = crossings(&shell, axis, Vector2::new(a, b));
+                    for inside in crossings.chunks_exact(2) {
+                        if inside[1] - inside[0] < strut_width {
+                            continue;
+                        }
+                        let mut min = Vector3::zeros();
+                        let mut max = Vector3::zeros();
+                        (min[axis], max[axis]) = (inside[0] - anchor, inside[1] + anchor);
+                        (min[u], max[u]) = (a - half, a + half);
+                        (min[v], max[v]) = (b - half, b + half);
+                        // Half a strut away from what to keep clear of, so their faces never meet
+                        let in_the_way = keep_clear.iter().any(|clear| {
+                            (0..3).all(|i| {
+                                min[i] < clear.max[i] + half && clear.min[i] - half < max[i]
+                            })
+                        });
+                        if !in_the_way {
+                            struts.push((min, max));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if struts.is_empty() {
+        return Err(too_small());
+    }
+
+    let triangles = struts
+        .iter()
+        .flat_map(|&(min, max)| cuboid(min, max))
+        .map(|corners| {
+            let vertices = corners.map(|p| inverse * p);
+            let [p, q, r] = vertices;
+            Triangle {
+                normal: (q - p)
+                    .cross(&(r - p))
+                    .try_normalize(0.0)
+                    .unwrap_or_default()
+                    .into(),
+                vertices: vertices.map(Into::into),
+            }
+        })
+        .collect();
+    Ok(triangles)
+}
+
+/// Adds the infill lattice to the mesh of a hollow body
+pub fn fill(body: &mut Body, density: f32, strut_width: f32) -> Result<(), InfillError> {
+    let mut triangles = body.mesh.get_triangles_for_slicing();
+    triangles.extend(lattice(body, density, strut_width)?);
+    body.set_mesh(Mesh::from_triangles(&triangles));
+    Ok(())
+}
+
+/// Positions `cell` apart through `center` from `min` to `max`
+fn grid(center: f32, min: f32, max: f32, cell: f32) -> impl Iterator<Item = f32> {
+    let first = ((min - center) / cell).ceil() as i32;
+    let last = ((max - center) / cell).floor() as i32;
+    (first..=last).map(move |k| center + k as f32 * cell)
+}
+
+/// Where the line along `axis` through `point` of the other two axes crosses the shell,
+/// sorted along it. Between each pair of crossings the line is inside the shell.
+fn crossings(shell: &[Triangle], axis: usize, point: Vector2<f32>) -> Vec<f32> {
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut crossings: Vec<(f32, bool)> = shell
+        .iter()
+        .filter_map(|triangle| {
+            let [a, b, c] = triangle.vertices.map(Vector3::from);
+            let flat = |p: Vector3<f32>| Vector2::new(p[u], p[v]);
+            let (ab, ac, ap) = (flat(b) - flat(a), flat(c) - flat(a), point - flat(a));
+            let area = ab.perp(&ac);
+            if area.abs() < f32::EPSILON {
+                return None;
+            }
+            let s = ap.perp(&ac) / area;
+            let t = ab.perp(&ap) / area;
+            (s >= 0.0 && t >= 0.0 && s + t <= 1.0).then(|| {
+                (
+                    a[axis] + (b[axis] - a[axis]) * s + (c[axis] - a[axis]) * t,
+                    area > 0.0,
+                )
+            })
+        })
+        .collect();
+    crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // A line through an edge hits both faces at it, but only crosses once when they face
+    // the same way
+    crossings
+        .dedup_by(|next, previous| next.1 == previous.1 && next.0 - previous.0 < SAME_CROSSING);
+    crossings.into_iter().map(|(at, _)| at).collect()
+}
+
+/// The triangles of a box from `min` to `max`, counterclockwise seen from outside
+pub(crate) fn cuboid(min: Vector3<f32>, max: Vector3<f32>) -> Vec<[Vector3<f32>; 3]> {
+    let corners = |z: f32| {
+        [
+            (min.x, min.y),
+            (max.x, min.y),
+            (max.x, max.y),
+            (min.x, max.y),
+        ]
+        .map(|(x, y)| Vector3::new(x, y, z))
+    };
+    let (bottom, top) = (corners(min.z), corners(max.z));
+    let mut quads = vec![
+        [bottom[0], bottom[3], bottom[2], bottom[1]],
+        [top[0], top[1], top[2], top[3]],
+    ];
+    for i in 0..4 {
+        let j = (i + 1) % 4;
+        quads.push([bottom[i], bottom[j], top[j], top[i]]);
+    }
+    quads
+        .iter()
+        .flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::signed_volume;
+    use crate::hollow::hollow;
+    use crate::test_support::solid_box;
+
+    #[test]
+    fn test_cell_size() {
+        // Three struts 1 mm wide in a 5 mm cell fill about 12 % of it
+        assert!((cell_size(12.0, 1.0) - 5.0).abs() < 1e-5);
+        // A full lattice still leaves room between the struts
+        assert!((cell_size(150.0, 1.0) - 3.0f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_fill_hollow_box() {
+        let mut body = solid_box([20.0, 20.0, 20.0]);
+        hollow(&mut body, 2.0).unwrap();
+        let lattice = lattice(&body, 12.0, 1.0).unwrap();
+        // Three struts across the 16 mm hollow along each axis, in three rows
+        assert_eq!(lattice.len(), 27 * 12);
+        assert!(signed_volume(&lattice) > 0.0);
+        let bounds = bounding_box(&lattice).unwrap();
+        // Sunk half a strut into the walls, never through them
+        assert!((bounds.min - Vector3::repeat(1.5)).norm() < 1e-4);
+        assert!((bounds.max - Vector3::repeat(18.5)).norm() < 1e-4);
+
+        fill(&mut body, 12.0, 1.0).unwrap();
+        assert_eq!(body.mesh.get_triangles_for_slicing().len(), 24 + 27 * 12);
+    }
+
+    #[test]
+    fn test_lattice_clear_of_a_box() {
+        let mut body = solid_box([20.0, 20.0, 20.0]);
+        hollow(&mut body, 2.0).unwrap();
+        // Only the vertical strut through the middle of the floor crosses it
+        let hole = AABB {
+            min: Vector3::new(9.0, 9.0, 0.0),
+            max: Vector3::new(11.0, 11.0, 3.0),
+        };
+        let lattice = lattice_clear_of(&body, 12.0, 1.0, &[hole]).unwrap();
+        assert_eq!(lattice.len(), 26 * 12);
+    }
+
+    #[test]
+    fn test_lattice_follows_the_scale() {
+        // Twice as long on the plate as in the mesh, the struts are still 5 mm apart
+        let mut body = solid_box([10.0, 20.0, 20.0]);
+        body.scale = Vector3::new(2.0, 1.0, 1.0);
+        hollow(&mut body, 2.0).unwrap();
+        let lattice = lattice(&body, 12.0, 1.0).unwrap();
+        let xs = lattice.iter().flat_map(|t| t.vertices.map(|v| v[0]));
+        let (min, max) = xs.fold((f32::MAX, f32::MIN), |(a, b), x| (a.min(x), b.max(x)));
+        assert!((min - 0.75).abs() < 1e-4);
+        assert!((max - 9.25).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_unfillable_bodies() {
+        let mut body = solid_box([20.0, 20.0, 20.0]);
+        assert_eq!(
+            lattice(&body, 12.0, 1.0),
+            Err(InfillError::NotHollow(body.name.clone()))
+        );
+        hollow(&mut body, 2.0).unwrap();
+        assert_eq!(lattice(&body, 0.0, 1.0), Err(InfillError::NoDensity));
+        assert_eq!(lattice(&body, 12.0, 0.0), Err(InfillError::NoStrutWidth));
+
+        let mut small = solid_box([5.0, 5.0, 5.0]);
+        hollow(&mut small, 2.0).unwrap();
+        assert!(matches!(
+            lattice(&small, 12.0, 1.5),
+            Err(InfillError::TooSmall(_, _))
+        ));
+    }
+}
@@ -0,0 +1,303 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::motion_profile::format_duration;
+use crate::number_format;
+use crate::output_formats::{LayerEntry, OutputFormat, PrinterFile, ReadError};
+use image::{ImageBuffer, Luma};
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+#[derive(Error, Debug)]
+pub enum JobEditError {
+    #[error("Could not open the job: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Could not read the job: {0}")]
+    Read(#[from] ReadError),
+
+    #[error(
+        "Only the .pws, .pwma, .goo, .ctb, .sl1 and .uvj files SealSlicer exports can be reopened"
+    )]
+    UnknownFormat,
+
+    #[error("The job only has {0} layers")]
+    NoSuchLayers(usize),
+
+    #[error("A job needs at least one layer")]
+    NoLayersLeft,
+}
+
+/// A printer file exported earlier, read back to adjust its exposures or remove layers
+/// without slicing the models again, like the light edits of UVtools
+pub struct ExportedJob {
+    path: PathBuf,
+    pub file: PrinterFile,
+    pub layers: Vec<Layer>,
+}
+
+impl ExportedJob {
+    pub fn open(path: &Path) -> Result<Self, JobEditError> {
+        let format = path
+            .extension()
+            .and_then(|extension| OutputFormat::of_extension(&extension.to_string_lossy()))
+            .ok_or(JobEditError::UnknownFormat)?;
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let (file, layers) = PrinterFile::decode(format, &name, &fs::read(path)?)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            layers,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Exposure of the normal and the bottom layers in seconds, and how many bottom
+    /// layers there are
+    pub fn set_exposures(&mut self, exposure: f64, bottom_exposure: f64, bottom_layers: usize) {
+        let motion = &mut self.file.motion;
+        motion.exposure_time = exposure;
+        motion.bottom_exposure_time = bottom_exposure;
+        motion.bottom_layer_count = bottom_layers;
+    }
+
+    /// Removes layers, counted from 0. The ones above move down to close the gap with their
+    /// thickness and settings, so the part prints that much shorter. The bottom layers stay
+    /// the first ones.
+    pub fn remove_layers(&mut self, layers: RangeInclusive<usize>) -> Result<(), JobEditError> {
+        if layers.is_empty() || *layers.end() >= self.layers.len() {
+            return Err(JobEditError::NoSuchLayers(self.layers.len()));
+        }
+        if layers.end() - layers.start() + 1 == self.layers.len() {
+            return Err(JobEditError::NoLayersLeft);
+        }
+        let motion = &self.file.motion;
+        let mut top = 0.0;
+        let table: Vec<LayerEntry> = self
+            .file
+            .plan(self.layers.len())
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !layers.contains(index))
+            .enumerate()
+            .map(|(new_index, (index, layer))| {
+                // An exposure of the resin is the one of the layer's new place
+                let mut exposure = layer.timing.exposure;
+                if exposure == motion.layer_timing(index, layer.thickness).exposure {
+                    exposure = motion.layer_timing(new_index, layer.thickness).exposure;
+                }
+                top += layer.thickness;
+                LayerEntry {
+                    top,
+                    exposure,
+                    lift_distance: Some(layer.lift_distance),
+                    rest: Some(layer.timing.rest),
+                }
+            })
+            .collect();
+        self.file.read_layer_table(&table);
+        self.layers.drain(layers);
+        Ok(())
+    }
+
+    /// e.g. "412 layers of 0.05 mm, 2.5 s exposure, 25 s for 4 bottom layers, 1h 05m"
+    pub fn summary(&self) -> String {
+        let motion = &self.file.motion;
+        let print_time = self.file.print_time(self.layers.len());
+        format!(
+            "{} layers of {} mm, {} s exposure, {} s for {} bottom layers, {}",
+            self.layers.len(),
+            number_format::shortest(self.file.layer_height),
+            number_format::shortest(motion.exposure_time),
+            number_format::shortest(motion.bottom_exposure_time),
+            motion.bottom_layer_count,
+            format_duration(print_time)
+        )
+    }
+
+    /// Writes the job over the file it was read from
+    pub fn save(&self) -> io::Result<()> {
+        // Write to a temporary file first so a failed save never loses the job
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, self.file.encode(&self.layers))?;
+        fs::rename(temp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_overrides::LayerOverride;
+    use crate::layer_thickness::LayerHeightRange;
+    use crate::motion_profile::MotionProfile;
+    use crate::output_formats::LayerSetting;
+    use crate::slice_parameters::SliceParameters;
+    use tempfile::tempdir;
+
+    fn job_file(format: OutputFormat) -> PrinterFile {
+        let mut printer = SliceParameters::default().printer;
+        printer.pixel_x = 40;
+        printer.pixel_y = 30;
+        PrinterFile {
+            format,
+            name: "job".to_string(),
+            printer,
+            resin: "Grey".to_string(),
+            motion: MotionProfile::default(),
+            layer_height: 0.05,
+            layer_height_ranges: Vec::new(),
+            layer_overrides: Vec::new(),
+            price_per_liter: None,
+        }
+    }
+
+    fn exported(dir: &Path, file: &PrinterFile) -> PathBuf {
+        // Every layer is as wide as its number, to tell them apart
+        let layers: Vec<Layer> = (1..=6)
+            .map(|i| ImageBuffer::from_fn(40, 30, |x, _| Luma([if x < i { 255 } else { 0 }])))
+            .collect();
+        let path = dir.join(file.file_name());
+        fs::write(&path, file.encode(&layers)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_edit_and_save() {
+        let dir = tempdir().unwrap();
+        let path = exported(dir.path(), &job_file(OutputFormat::Goo));
+        let mut job = ExportedJob::open(&path).unwrap();
+        assert_eq!(job.layers.len(), 6);
+        assert_eq!(job.file.name, "job");
+
+        job.set_exposures(3.0, 30.0, 2);
+        job.remove_layers(1..=2).unwrap();
+        assert!(matches!(
+            job.remove_layers(3..=4),
+            Err(JobEditError::NoSuchLayers(4))
+        ));
+        assert!(matches!(
+            job.remove_layers(0..=3),
+            Err(JobEditError::NoLayersLeft)
+        ));
+        job.save().unwrap();
+
+        let saved = ExportedJob::open(&path).unwrap();
+        assert_eq!(saved.file.motion.exposure_time, 3.0);
+        assert_eq!(saved.file.motion.bottom_exposure_time, 30.0);
+        assert_eq!(saved.file.motion.bottom_layer_count, 2);
+        // Layers 1 and 4 to 6 are left
+        let widths: Vec<usize> = saved
+            .layers
+            .iter()
+            .map(|layer| layer.pixels().take(40).filter(|p| p[0] > 127).count())
+            .collect();
+        assert_eq!(widths, [1, 4, 5, 6]);
+        assert!(saved
+            .summary()
+            .starts_with("4 layers of 0.05 mm, 3 s exposure"));
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn test_open_every_format() {
+        let dir = tempdir().unwrap();
+        for format in [
+            OutputFormat::Pws,
+            OutputFormat::Pwma,
+            OutputFormat::Ctb,
+            OutputFormat::Sl1,
+            OutputFormat::Uvj,
+        ] {
+            let job = ExportedJob::open(&exported(dir.path(), &job_file(format))).unwrap();
+            assert_eq!(job.layers.len(), 6);
+            assert_eq!(job.file.format, format);
+        }
+        let other = dir.path().join("job.cbddlp");
+        fs::write(&other, b"").unwrap();
+        assert!(matches!(
+            ExportedJob::open(&other),
+            Err(JobEditError::UnknownFormat)
+        ));
+    }
+
+    #[test]
+    fn test_keep_layer_height_ranges() {
+        let dir = tempdir().unwrap();
+        for format in [
+            OutputFormat::Pws,
+            OutputFormat::Pwma,
+            OutputFormat::Goo,
+            OutputFormat::Ctb,
+            OutputFormat::Uvj,
+        ] {
+            // Layers 2 to 5 are half as thick
+            let mut file = job_file(format);
+            file.layer_height_ranges.push(LayerHeightRange {
+                from: 0.1,
+                to: 0.2,
+                thickness: 0.025,
+                exposure_time: None,
+            });
+            let path = exported(dir.path(), &file);
+            let mut job = ExportedJob::open(&path).unwrap();
+            assert_eq!(
+                job.file.layer_thickness().layers(6),
+                file.layer_thickness().layers(6)
+            );
+
+            job.remove_layers(0..=1).unwrap();
+            job.save().unwrap();
+            let saved = ExportedJob::open(&path).unwrap();
+            let thickness = saved.file.layer_thickness();
+            assert_eq!(thickness.layers(4).last().unwrap().top, 0.1);
+            assert!(thickness
+                .layers(4)
+                .iter()
+                .all(|layer| layer.thickness == 0.025));
+        }
+    }
+
+    #[test]
+    fn test_keep_layer_overrides() {
+        let dir = tempdir().unwrap();
+        for format in [
+            OutputFormat::Pws,
+            OutputFormat::Pwma,
+            OutputFormat::Goo,
+            OutputFormat::Ctb,
+            OutputFormat::Uvj,
+        ] {
+            let mut file = job_file(format);
+            file.motion.bottom_layer_count = 1;
+            // Exports with the settings a format doesn't keep are refused
+            file.layer_overrides.push(LayerOverride {
+                first_layer: 4,
+                last_layer: 4,
+                exposure_time: Some(4.0),
+                lift_distance: format.keeps_layer(LayerSetting::Lift).then_some(3.0),
+                light_off_delay: format
+                    .keeps_layer(LayerSetting::LightOffDelay)
+                    .then_some(2.0),
+            });
+            let mut kept = file.layer_overrides[0].clone();
+            let path = exported(dir.path(), &file);
+            let mut job = ExportedJob::open(&path).unwrap();
+            assert_eq!(job.file.layer_overrides, [kept.clone()], "{:?}", format);
+
+            // The override moves down with its layer
+            job.remove_layers(1..=2).unwrap();
+            job.save().unwrap();
+            (kept.first_layer, kept.last_layer) = (2, 2);
+            let saved = ExportedJob::open(&path).unwrap();
+            assert_eq!(saved.file.layer_overrides, [kept], "{:?}", format);
+        }
+    }
+}
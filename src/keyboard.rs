@@ -0,0 +1,314 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::axis_gizmo::Axis;
+use crate::body::Body;
+use nalgebra::Vector3;
+use slint::platform::Key;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Degrees of mouse movement one key press orbits by, 10° with the camera sensitivity
+const ORBIT_STEP: f32 = 100.0;
+const PAN_STEP: f32 = 50.0;
+const ZOOM_STEP: f32 = 50.0;
+/// Millimeters a selected body moves per key press
+const NUDGE_STEP: f32 = 1.0;
+
+/// Everything the keyboard can do that otherwise needs the mouse or a button
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    ImportStl,
+    Undo,
+    Redo,
+    SelectAll,
+    SelectNone,
+    SelectNext,
+    SelectPrevious,
+    DeleteSelected,
+    TransformSelected,
+    Nudge(Vector3<f32>),
+    SliceSelected,
+    SliceAll,
+    ExportReport,
+    Orbit(f32, f32),
+    Pan(f32, f32),
+    Zoom(f32),
+    SnapView(Axis),
+    ShowShortcuts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shortcut {
+    /// The text Slint reports for the key, lower case for letters
+    pub key: char,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Shortcut {
+    fn new(key: impl Into<char>) -> Self {
+        Self {
+            key: key.into(),
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    fn ctrl(self) -> Self {
+        Self { ctrl: true, ..self }
+    }
+
+    fn shift(self) -> Self {
+        Self {
+            shift: true,
+            ..self
+        }
+    }
+
+    fn alt(self) -> Self {
+        Self { alt: true, ..self }
+    }
+
+    /// e.g. "Ctrl+Shift+Z"
+    pub fn describe(&self) -> String {
+        let special = [
+            (Key::UpArrow, "Up"),
+            (Key::DownArrow, "Down"),
+            (Key::LeftArrow, "Left"),
+            (Key::RightArrow, "Right"),
+            (Key::PageUp, "Page Up"),
+            (Key::PageDown, "Page Down"),
+            (Key::Escape, "Esc"),
+            (Key::Delete, "Delete"),
+            (Key::Return, "Enter"),
+            (Key::F1, "F1"),
+        ];
+        let key = special
+            .into_iter()
+            .find(|(key, _)| char::from(*key) == self.key)
+            .map_or(self.key.to_uppercase().to_string(), |(_, name)| {
+                name.to_string()
+            });
+        let mut description = String::new();
+        for (held, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.shift, "Shift+"),
+            (self.alt, "Alt+"),
+        ] {
+            if held {
+                description += name;
+            }
+        }
+        description + key.as_str()
+    }
+}
+
+/// Every shortcut with what it does, in the order the help lists them
+pub fn bindings() -> Vec<(Shortcut, Command, &'static str)> {
+    use Command::*;
+    fn k(key: impl Into<char>) -> Shortcut {
+        Shortcut::new(key)
+    }
+    vec![
+        (k('o').ctrl(), ImportStl, "Import STL"),
+        (k('z').ctrl(), Undo, "Undo"),
+        (k('z').ctrl().shift(), Redo, "Redo"),
+        (k('y').ctrl(), Redo, "Redo"),
+        (k('a').ctrl(), SelectAll, "Select all bodies"),
+        (k(Key::Escape), SelectNone, "Clear the selection"),
+        (k(Key::PageDown), SelectNext, "Select the next body"),
+        (k(Key::PageUp), SelectPrevious, "Select the previous body"),
+        (k(Key::Delete), DeleteSelected, "Delete the selected bodies"),
+        (
+            k('t').ctrl(),
+            TransformSelected,
+            "Transform the selected bodies",
+        ),
+        (
+            k(Key::LeftArrow).alt(),
+            Nudge(-Vector3::x() * NUDGE_STEP),
+            "Move the selection along -X",
+        ),
+        (
+            k(Key::RightArrow).alt(),
+            Nudge(Vector3::x() * NUDGE_STEP),
+            "Move the selection along +X",
+        ),
+        (
+            k(Key::DownArrow).alt(),
+            Nudge(-Vector3::y() * NUDGE_STEP),
+            "Move the selection along -Y",
+        ),
+        (
+            k(Key::UpArrow).alt(),
+            Nudge(Vector3::y() * NUDGE_STEP),
+            "Move the selection along +Y",
+        ),
+        (
+            k(Key::PageDown).alt(),
+            Nudge(-Vector3::z() * NUDGE_STEP),
+            "Move the selection down",
+        ),
+        (
+            k(Key::PageUp).alt(),
+            Nudge(Vector3::z() * NUDGE_STEP),
+            "Move the selection up",
+        ),
+        (k(Key::Return).ctrl(), SliceAll, "Slice all"),
+        (
+            k(Key::Return).ctrl().shift(),
+            SliceSelected,
+            "Slice the selected bodies",
+        ),
+        (k('e').ctrl(), ExportReport, "Export the pre-flight report"),
+        (k(Key::LeftArrow), Orbit(-ORBIT_STEP, 0.0), "Orbit left"),
+        (k(Key::RightArrow), Orbit(ORBIT_STEP, 0.0), "Orbit right"),
+        (k(Key::UpArrow), Orbit(0.0, ORBIT_STEP), "Orbit up"),
+        (k(Key::DownArrow), Orbit(0.0, -ORBIT_STEP), "Orbit down"),
+        (k(Key::LeftArrow).shift(), Pan(-PAN_STEP, 0.0), "Pan left"),
+        (k(Key::RightArrow).shift(), Pan(PAN_STEP, 0.0), "Pan right"),
+        (k(Key::UpArrow).shift(), Pan(0.0, -PAN_STEP), "Pan up"),
+        (k(Key::DownArrow).shift(), Pan(0.0, PAN_STEP), "Pan down"),
+        (k('+'), Zoom(ZOOM_STEP), "Zoom in"),
+        (k('='), Zoom(ZOOM_STEP), "Zoom in"),
+        (k('-'), Zoom(-ZOOM_STEP), "Zoom out"),
+        (k('1'), SnapView(Axis::X), "View along X"),
+        (k('2'), SnapView(Axis::Y), "View along Y"),
+        (k('3'), SnapView(Axis::Z), "View from the top"),
+        (k(Key::F1), ShowShortcuts, "Show the keyboard shortcuts"),
+    ]
+}
+
+/// The command for a key press that no focused widget handled
+pub fn command_for(text: &str, ctrl: bool, shift: bool, alt: bool) -> Option<Command> {
+    let mut chars = text.chars();
+    let key = chars.next()?.to_lowercase().next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    let pressed = Shortcut {
+        key,
+        ctrl,
+        // Symbols like + need shift on some layouts, so it doesn't change what they do
+        shift: shift && !key.is_ascii_punctuation(),
+        alt,
+    };
+    bindings()
+        .into_iter()
+        .find(|(shortcut, _, _)| *shortcut == pressed)
+        .map(|(_, command, _)| command)
+}
+
+/// One line per shortcut for the help popup
+pub fn help() -> String {
+    bindings()
+        .iter()
+        .map(|(shortcut, _, description)| format!("{}\t{}", shortcut.describe(), description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Moves a single selection `step` bodies along the list, wrapping around. Bodies that
+/// aren't listed, like the build plate, are skipped.
+pub fn cycle_selection(bodies: &[Rc<RefCell<Body>>], step: isize) {
+    let listed: Vec<&Rc<RefCell<Body>>> = bodies
+        .iter()
+        .filter(|b| b.borrow().display_in_ui_list)
+        .collect();
+    if listed.is_empty() {
+        return;
+    }
+    let count = listed.len() as isize;
+    let next = match listed.iter().position(|b| b.borrow().selected) {
+        Some(current) => (current as isize + step).rem_euclid(count),
+        None if step < 0 => count - 1,
+        None => 0,
+    };
+    for (i, body) in listed.iter().enumerate() {
+        body.borrow_mut().selected = i as isize == next;
+    }
+}
+
+/// Selects or deselects every listed body
+pub fn select_all(bodies: &[Rc<RefCell<Body>>], selected: bool) {
+    for body in bodies {
+        let mut body = body.borrow_mut();
+        if body.display_in_ui_list {
+            body.selected = selected;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_for() {
+        let up = char::from(Key::UpArrow).to_string();
+        assert_eq!(
+            command_for("o", true, false, false),
+            Some(Command::ImportStl)
+        );
+        // Slint reports shifted letters in upper case
+        assert_eq!(command_for("Z", true, true, false), Some(Command::Redo));
+        assert_eq!(
+            command_for(&up, false, false, false),
+            Some(Command::Orbit(0.0, ORBIT_STEP))
+        );
+        assert_eq!(
+            command_for(&up, false, false, true),
+            Some(Command::Nudge(Vector3::new(0.0, NUDGE_STEP, 0.0)))
+        );
+        assert_eq!(
+            command_for("+", false, true, false),
+            Some(Command::Zoom(ZOOM_STEP))
+        );
+        assert_eq!(command_for("o", false, false, false), None);
+        assert_eq!(command_for("", false, false, false), None);
+        assert_eq!(command_for("ab", true, false, false), None);
+    }
+
+    #[test]
+    fn test_bindings_are_unique_and_described() {
+        let bindings = bindings();
+        for (i, (shortcut, _, _)) in bindings.iter().enumerate() {
+            assert!(
+                bindings[i + 1..]
+                    .iter()
+                    .all(|(other, _, _)| other != shortcut),
+                "{} is bound twice",
+                shortcut.describe()
+            );
+        }
+        assert_eq!(Shortcut::new('z').ctrl().shift().describe(), "Ctrl+Shift+Z");
+        assert!(help().contains("Alt+Page Up\tMove the selection up"));
+    }
+
+    #[test]
+    fn test_selection() {
+        let bodies: Vec<_> = (0..3)
+            .map(|_| Rc::new(RefCell::new(Body::default())))
+            .collect();
+        // Like the build plate, which is never selectable
+        bodies[1].borrow_mut().display_in_ui_list = false;
+        bodies[1].borrow_mut().selected = false;
+        let selected = || -> Vec<bool> { bodies.iter().map(|b| b.borrow().selected).collect() };
+
+        select_all(&bodies, false);
+        assert_eq!(selected(), vec![false, false, false]);
+        cycle_selection(&bodies, 1);
+        assert_eq!(selected(), vec![true, false, false]);
+        cycle_selection(&bodies, 1);
+        assert_eq!(selected(), vec![false, false, true]);
+        cycle_selection(&bodies, 1);
+        assert_eq!(selected(), vec![true, false, false]);
+        cycle_selection(&bodies, -1);
+        assert_eq!(selected(), vec![false, false, true]);
+        select_all(&bodies, true);
+        assert_eq!(selected(), vec![true, false, true]);
+    }
+}
@@ -0,0 +1,309 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::cpu_slicer::CPUSlicer;
+#[cfg(feature = "gpu-slicer")]
+use crate::gpu_layer_analysis::{GpuAnalysisError, GpuLayerAnalyzer};
+use crate::layer_components::{neighbors, Component, ComponentMap, CURED_THRESHOLD};
+use crate::printer::Printer;
+use crate::profiler::{self, Stage};
+use image::{ImageBuffer, Luma, Rgb, RgbImage};
+use nalgebra::Vector3;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+const ISLAND_MARK_COLOR: Rgb<u8> = Rgb([230, 40, 40]);
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// Cured pixels of a layer that don't rest on anything: neither on the layer below nor,
+/// through the rest of their own layer, on anything that does
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerIsland {
+    pub layer: usize,
+    pub blob: Component,
+}
+
+impl LayerIsland {
+    /// Where the island is on the build plate in millimeters, undoing the LCD orientation
+    pub fn plate_position(&self, printer: &Printer) -> (f64, f64) {
+        let (x, y) = self.blob.centroid;
+        CPUSlicer::lcd_to_model_coords(x as f64, y as f64, printer)
+    }
+
+    /// e.g. "layer 12 at pixel (640, 320), 25 pixels", the pixel being its middle on the LCD
+    pub fn describe(&self) -> String {
+        let (x, y) = self.blob.centroid;
+        format!(
+            "layer {} at pixel ({}, {}), {} pixels",
+            self.layer,
+            x.round(),
+            y.round(),
+            self.blob.pixels
+        )
+    }
+}
+
+/// Islands and overhangs of every layer of a sliced job
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerAnalysis {
+    pub islands: Vec<LayerIsland>,
+    /// Per layer, the cured pixels that hang over empty space but are held by the rest
+    /// of their layer
+    pub overhang_pixels: Vec<u32>,
+}
+
+impl LayerAnalysis {
+    /// Analyzes the layers with the GPU, falling back to the CPU if it fails. Both give
+    /// identical results.
+    #[cfg(feature = "gpu-slicer")]
+    pub fn on_gpu(layers: &[Layer], gpu: &GpuLayerAnalyzer) -> Self {
+        let _timer = profiler::scope(Stage::Analysis);
+        let summaries: Result<Vec<_>, GpuAnalysisError> = (0..layers.len())
+            .map(|i| {
+                let below = i.checked_sub(1).map(|below| &layers[below]);
+                let supported = gpu.supported_pixels(below, &layers[i])?;
+                Ok(LayerSummary::of(i, below, &layers[i], &supported))
+            })
+            .collect();
+        match summaries {
+            Ok(summaries) => Self::from_summaries(summaries),
+            Err(e) => {
+                eprintln!("GPU layer analysis failed, using the CPU: {}", e);
+                Self::on_cpu(layers)
+            }
+        }
+    }
+
+    /// Analyzes the layers on the CPU
+    pub fn of(layers: &[Layer]) -> Self {
+        let _timer = profiler::scope(Stage::Analysis);
+        Self::on_cpu(layers)
+    }
+
+    fn on_cpu(layers: &[Layer]) -> Self {
+        let summaries = (0..layers.len())
+            .into_par_iter()
+            .map(|i| {
+                let below = i.checked_sub(1).map(|below| &layers[below]);
+                let supported = supported_pixels(below, &layers[i]);
+                LayerSummary::of(i, below, &layers[i], &supported)
+            })
+            .collect();
+        Self::from_summaries(summaries)
+    }
+
+    fn from_summaries(summaries: Vec<LayerSummary>) -> Self {
+        let mut analysis = Self::default();
+        for summary in summaries {
+            analysis.islands.extend(summary.islands);
+            analysis.overhang_pixels.push(summary.overhang_pixels);
+        }
+        analysis
+    }
+
+    pub fn islands_on(&self, layer: usize) -> impl Iterator<Item = &LayerIsland> {
+        self.islands
+            .iter()
+            .filter(move |island| island.layer == layer)
+    }
+
+    /// Frames the islands of a layer on its LCD preview, which shows the whole LCD scaled
+    /// down
+    pub fn mark_islands(&self, layer: usize, preview: &mut RgbImage, printer: &Printer) {
+        for island in self.islands_on(layer) {
+            island.blob.frame(preview, printer, ISLAND_MARK_COLOR);
+        }
+    }
+
+    /// Where supports have to reach, one point under each island at the height of its
+    /// layer, in millimeters on the build plate
+    pub fn support_points(&self, printer: &Printer, layer_heights: &[f64]) -> Vec<Vector3<f64>> {
+        self.islands
+            .iter()
+            .filter_map(|island| {
+                let (x, y) = island.plate_position(printer);
+                Some(Vector3::new(x, y, *layer_heights.get(island.layer)?))
+            })
+            .collect()
+    }
+
+    pub fn summary(&self) -> String {
+        match self.islands.first() {
+            None => "No islands found".to_string(),
+            Some(first) => {
+                let mut layers: Vec<usize> = self.islands.iter().map(|i| i.layer).collect();
+                layers.dedup();
+                format!(
+                    "{} islands on {} layers, the first on {}",
+                    self.islands.len(),
+                    layers.len(),
+                    first.describe()
+                )
+            }
+        }
+    }
+}
+
+struct LayerSummary {
+    islands: Vec<LayerIsland>,
+    overhang_pixels: u32,
+}
+
+impl LayerSummary {
+    // Sorts the cured pixels of a layer by what they rest on, given which are supported
+    fn of(index: usize, below: Option<&Layer>, layer: &Layer, supported: &[bool]) -> Self {
+        let cured_below = |i: usize| below.is_none_or(|below| below.as_raw()[i] >= CURED_THRESHOLD);
+        let raw = layer.as_raw();
+        let overhang_pixels = (0..raw.len())
+            .filter(|&i| raw[i] >= CURED_THRESHOLD && supported[i] && !cured_below(i))
+            .count() as u32;
+
+        // Unsupported pixels are cured too, so islands are the blobs of them
+        let (width, height) = layer.dimensions();
+        let islands = ComponentMap::label(width, height, |i| {
+            raw[i] >= CURED_THRESHOLD && !supported[i]
+        })
+        .components
+        .into_iter()
+        .map(|blob| LayerIsland { layer: index, blob })
+        .collect();
+        Self {
+            islands,
+            overhang_pixels,
+        }
+    }
+}
+
+/// The islands of the layer at `index`, given the layer below it
+pub fn layer_islands(index: usize, below: Option<&Layer>, layer: &Layer) -> Vec<LayerIsland> {
+    LayerSummary::of(index, below, layer, &supported_pixels(below, layer)).islands
+}
+
+/// Which pixels of `layer` rest on the layer below, directly or through other cured pixels
+/// of their own layer. Without a layer below every cured pixel rests on the plate.
+pub fn supported_pixels(below: Option<&Layer>, layer: &Layer) -> Vec<bool> {
+    let raw = layer.as_raw();
+    let mut supported = vec![false; raw.len()];
+    let mut queue = VecDeque::new();
+    for i in 0..raw.len() {
+        if raw[i] >= CURED_THRESHOLD
+            && below.is_none_or(|below| below.as_raw()[i] >= CURED_THRESHOLD)
+        {
+            supported[i] = true;
+            queue.push_back(i);
+        }
+    }
+    let (width, height) = layer.dimensions();
+    while let Some(i) = queue.pop_front() {
+        for neighbor in neighbors(i, width, height) {
+            if !supported[neighbor] && raw[neighbor] >= CURED_THRESHOLD {
+                supported[neighbor] = true;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    supported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::LcdOrientation;
+    use crate::test_support::layer;
+
+    #[test]
+    fn test_islands_and_overhangs() {
+        let layers = [
+            layer(&["##......", "##......", "........", "........"]),
+            // Grows to the right over nothing, and starts a separate block
+            layer(&["####....", "##......", "......##", "......##"]),
+            // Rests on both, the block on the right now bridged over to
+            layer(&["####....", "...#....", "...#####", "......##"]),
+        ];
+        let analysis = LayerAnalysis::of(&layers);
+
+        assert_eq!(analysis.overhang_pixels, vec![0, 2, 4]);
+        assert_eq!(
+            analysis.islands,
+            vec![LayerIsland {
+                layer: 1,
+                blob: Component {
+                    pixels: 4,
+                    min: (6, 2),
+                    max: (7, 3),
+                    centroid: (6.5, 2.5),
+                },
+            }]
+        );
+        assert_eq!(analysis.islands_on(1).count(), 1);
+        assert_eq!(analysis.islands_on(2).count(), 0);
+
+        // Framed on a preview at the size of the LCD
+        let printer = Printer {
+            pixel_x: 8,
+            pixel_y: 4,
+            ..Printer::default()
+        };
+        let mut preview = RgbImage::new(8, 4);
+        analysis.mark_islands(2, &mut preview, &printer);
+        assert!(preview.pixels().all(|pixel| pixel.0 == [0; 3]));
+        analysis.mark_islands(1, &mut preview, &printer);
+        assert_eq!(*preview.get_pixel(4, 2), ISLAND_MARK_COLOR);
+        assert_eq!(*preview.get_pixel(6, 2), Rgb([0; 3]));
+        assert_eq!(
+            analysis.summary(),
+            "1 islands on 1 layers, the first on layer 1 at pixel (7, 3), 4 pixels"
+        );
+    }
+
+    #[test]
+    fn test_supported_pixels() {
+        let below = layer(&["#...", "....", "...."]);
+        let current = layer(&["##..", ".#..", "...#"]);
+        let supported = supported_pixels(Some(&below), &current);
+        let expected = layer(&["##..", ".#..", "...."]);
+        let expected: Vec<bool> = expected.as_raw().iter().map(|&v| v > 0).collect();
+        assert_eq!(supported, expected);
+
+        // The first layer rests on the plate
+        assert!(supported_pixels(None, &current)
+            .iter()
+            .zip(current.as_raw())
+            .all(|(&supported, &value)| supported == (value > 0)));
+    }
+
+    #[test]
+    fn test_support_points() {
+        let printer = Printer {
+            pixel_x: 100,
+            pixel_y: 50,
+            physical_x: 200.0,
+            physical_y: 100.0,
+            lcd_orientation: LcdOrientation {
+                swap_xy: false,
+                mirror_x: true,
+                mirror_y: false,
+            },
+            ..Printer::default()
+        };
+        let analysis = LayerAnalysis {
+            islands: vec![LayerIsland {
+                layer: 1,
+                blob: Component {
+                    pixels: 1,
+                    min: (60, 30),
+                    max: (60, 30),
+                    centroid: (60.0, 30.0),
+                },
+            }],
+            overhang_pixels: vec![0, 0],
+        };
+        // 10 pixels right and 5 down of the middle of the LCD, mirrored back onto the plate
+        assert_eq!(
+            analysis.support_points(&printer, &[0.05, 0.1]),
+            vec![Vector3::new(-20.0, 10.0, 0.1)]
+        );
+        assert!(analysis.support_points(&printer, &[0.05]).is_empty());
+    }
+}
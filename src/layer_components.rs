@@ -0,0 +1,211 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::printer::Printer;
+use image::{ImageBuffer, Luma, Rgb, RgbImage};
+use imageproc::drawing::draw_hollow_rect_mut;
+use imageproc::rect::Rect;
+use std::collections::VecDeque;
+
+/// Pixels at least this bright are cured
+pub const CURED_THRESHOLD: u8 = 128;
+
+/// Frame of the blob clicked on in the layer preview
+pub const SELECTED_COLOR: Rgb<u8> = Rgb([60, 200, 255]);
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// A blob of 4-connected pixels of a layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Component {
+    pub pixels: u32,
+    /// Corners of the bounding box on the LCD, in pixels
+    pub min: (u32, u32),
+    pub max: (u32, u32),
+    /// Middle of the blob on the LCD, in pixels
+    pub centroid: (f32, f32),
+}
+
+impl Component {
+    /// In square millimeters
+    pub fn area(&self, printer: &Printer) -> f64 {
+        let pixel_area = printer.physical_x / printer.pixel_x as f64 * printer.physical_y
+            / printer.pixel_y as f64;
+        self.pixels as f64 * pixel_area
+    }
+
+    /// Width and height of the bounding box in millimeters, along the LCD
+    pub fn size(&self, printer: &Printer) -> (f64, f64) {
+        (
+            (self.max.0 - self.min.0 + 1) as f64 * printer.physical_x / printer.pixel_x as f64,
+            (self.max.1 - self.min.1 + 1) as f64 * printer.physical_y / printer.pixel_y as f64,
+        )
+    }
+
+    /// Frames the blob on a preview showing the whole LCD scaled down, a few pixels wider
+    /// than it so single pixel blobs still show
+    pub fn frame(&self, preview: &mut RgbImage, printer: &Printer, color: Rgb<u8>) {
+        let scale = (
+            preview.width() as f32 / printer.pixel_x as f32,
+            preview.height() as f32 / printer.pixel_y as f32,
+        );
+        let x = (self.min.0 as f32 * scale.0) as i32 - 2;
+        let y = (self.min.1 as f32 * scale.1) as i32 - 2;
+        let width = ((self.max.0 - self.min.0 + 1) as f32 * scale.0) as u32 + 4;
+        let height = ((self.max.1 - self.min.1 + 1) as f32 * scale.1) as u32 + 4;
+        draw_hollow_rect_mut(preview, Rect::at(x, y).of_size(width, height), color);
+    }
+
+    pub fn describe(&self, printer: &Printer) -> String {
+        let (width, height) = self.size(printer);
+        format!(
+            "{:.2} mm² in {:.2} x {:.2} mm, {} pixels",
+            self.area(printer),
+            width,
+            height,
+            self.pixels
+        )
+    }
+}
+
+/// The blobs of an image, with which blob each pixel belongs to
+#[derive(Debug, Clone)]
+pub struct ComponentMap {
+    width: u32,
+    height: u32,
+    /// Per pixel, 0 outside of any blob or the index of its blob plus one
+    labels: Vec<u32>,
+    pub components: Vec<Component>,
+}
+
+impl ComponentMap {
+    /// Labels the pixels `member` accepts by pixel index, in the order their first pixel
+    /// is found scanning rows from the top
+    pub fn label(width: u32, height: u32, member: impl Fn(usize) -> bool) -> Self {
+        let mut labels = vec![0u32; (width * height) as usize];
+        let mut components = Vec::new();
+        let mut queue = VecDeque::new();
+        for start in 0..labels.len() {
+            if labels[start] != 0 || !member(start) {
+                continue;
+            }
+            let label = components.len() as u32 + 1;
+            let mut component = Component {
+                pixels: 0,
+                min: (u32::MAX, u32::MAX),
+                max: (0, 0),
+                centroid: (0.0, 0.0),
+            };
+            let mut sum = (0.0f64, 0.0f64);
+            labels[start] = label;
+            queue.push_back(start);
+            while let Some(i) = queue.pop_front() {
+                let (x, y) = ((i % width as usize) as u32, (i / width as usize) as u32);
+                component.pixels += 1;
+                component.min = (component.min.0.min(x), component.min.1.min(y));
+                component.max = (component.max.0.max(x), component.max.1.max(y));
+                sum = (sum.0 + x as f64, sum.1 + y as f64);
+                for neighbor in neighbors(i, width, height) {
+                    if labels[neighbor] == 0 && member(neighbor) {
+                        labels[neighbor] = label;
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+            component.centroid = (
+                (sum.0 / component.pixels as f64) as f32,
+                (sum.1 / component.pixels as f64) as f32,
+            );
+            components.push(component);
+        }
+        Self {
+            width,
+            height,
+            labels,
+            components,
+        }
+    }
+
+    /// The cured blobs of a layer
+    pub fn of_layer(layer: &Layer) -> Self {
+        let raw = layer.as_raw();
+        Self::label(layer.width(), layer.height(), |i| raw[i] >= CURED_THRESHOLD)
+    }
+
+    /// Index of the blob covering a pixel
+    pub fn index_at(&self, x: u32, y: u32) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let label = self.labels[(y * self.width + x) as usize];
+        (label > 0).then(|| label as usize - 1)
+    }
+}
+
+/// Indices of the pixels left, right, above and below, where there are any
+pub fn neighbors(i: usize, width: u32, height: u32) -> impl Iterator<Item = usize> {
+    let (width, height) = (width as usize, height as usize);
+    let (x, y) = (i % width, i / width);
+    [
+        (x > 0).then(|| i - 1),
+        (x + 1 < width).then(|| i + 1),
+        (y > 0).then(|| i - width),
+        (y + 1 < height).then(|| i + width),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label() {
+        let rows = ["##..#", "#...#", "..#..", "....#"];
+        let pixels = rows
+            .iter()
+            .flat_map(|row| row.bytes().map(|c| if c == b'#' { 255 } else { 0 }))
+            .collect();
+        let layer = ImageBuffer::from_raw(5, 4, pixels).unwrap();
+        let map = ComponentMap::of_layer(&layer);
+
+        // Diagonal neighbors aren't connected
+        assert_eq!(map.components.len(), 4);
+        assert_eq!(
+            map.components[0],
+            Component {
+                pixels: 3,
+                min: (0, 0),
+                max: (1, 1),
+                centroid: (1.0 / 3.0, 1.0 / 3.0),
+            }
+        );
+        assert_eq!(map.components[1].pixels, 2);
+        assert_eq!(map.index_at(4, 1), Some(1));
+        assert_eq!(map.index_at(2, 2), Some(2));
+        assert!(map.index_at(3, 3).is_none());
+        assert!(map.index_at(5, 0).is_none());
+
+        let printer = Printer {
+            pixel_x: 100,
+            pixel_y: 100,
+            physical_x: 50.0,
+            physical_y: 100.0,
+            ..Printer::default()
+        };
+        assert_eq!(map.components[1].area(&printer), 1.0);
+        assert_eq!(map.components[1].size(&printer), (0.5, 2.0));
+
+        // Framed on a preview at twice the size of the LCD
+        let mut preview = RgbImage::new(10, 8);
+        let lcd = Printer {
+            pixel_x: 5,
+            pixel_y: 4,
+            ..Printer::default()
+        };
+        map.components[2].frame(&mut preview, &lcd, SELECTED_COLOR);
+        assert_eq!(*preview.get_pixel(2, 2), SELECTED_COLOR);
+        assert_eq!(*preview.get_pixel(4, 4), Rgb([0; 3]));
+    }
+}
@@ -0,0 +1,159 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::layer_thickness::LayerThickness;
+use crate::motion_profile::{LayerTiming, MotionProfile};
+use crate::number_format;
+use serde::{Deserialize, Serialize};
+
+/// Settings of a run of layers that differ from the resin's, e.g. a longer exposure over the
+/// layers of thin walls. Settings left None stay the resin's.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LayerOverride {
+    pub first_layer: usize,
+    pub last_layer: usize, // inclusive
+    /// Of bottom layers too
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exposure_time: Option<f64>, // seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lift_distance: Option<f64>, // millimeters
+    /// The rest with the light off before the exposure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light_off_delay: Option<f64>, // seconds
+}
+
+impl LayerOverride {
+    pub fn covers(&self, layer: usize) -> bool {
+        (self.first_layer..=self.last_layer).contains(&layer)
+    }
+
+    pub fn describe(&self) -> String {
+        let layers = if self.first_layer == self.last_layer {
+            format!("Layer {}", self.first_layer)
+        } else {
+            format!("Layers {}-{}", self.first_layer, self.last_layer)
+        };
+        let settings: Vec<String> = [
+            ("exposure", self.exposure_time, "s"),
+            ("lift", self.lift_distance, "mm"),
+            ("light off", self.light_off_delay, "s"),
+        ]
+        .into_iter()
+        .filter_map(|(name, value, unit)| {
+            value.map(|value| format!("{} {} {}", name, number_format::shortest(value), unit))
+        })
+        .collect();
+        format!("{}: {}", layers, settings.join(", "))
+    }
+
+    fn apply(&self, motion: &mut MotionProfile) {
+        if let Some(exposure_time) = self.exposure_time {
+            motion.exposure_time = exposure_time;
+            motion.bottom_exposure_time = exposure_time;
+        }
+        if let Some(lift_distance) = self.lift_distance {
+            motion.lift_distance = lift_distance;
+            motion.lift_slow_distance = motion.lift_slow_distance.min(lift_distance);
+        }
+        if let Some(light_off_delay) = self.light_off_delay {
+            motion.rest_before_exposure = light_off_delay;
+        }
+    }
+}
+
+/// What the printer does for one layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerPlan {
+    pub top: f64,           // millimeters, the height of the plate at the exposure
+    pub thickness: f64,     // millimeters
+    pub lift_distance: f64, // millimeters
+    /// The rest is the light off delay
+    pub timing: LayerTiming,
+}
+
+/// Plans the first `count` layers of a print: the resin's motion at the heights and with
+/// the exposures of `thickness`, changed by the overrides. The last override that covers
+/// a layer wins, so newer overrides take precedence.
+pub fn plan(
+    thickness: &LayerThickness,
+    overrides: &[LayerOverride],
+    motion: &MotionProfile,
+    count: usize,
+) -> Vec<LayerPlan> {
+    let timeline = thickness.timeline(motion, count);
+    thickness
+        .layers(count)
+        .into_iter()
+        .zip(timeline)
+        .enumerate()
+        .map(|(index, (span, mut timing))| {
+            let mut layer_motion = motion.clone();
+            if let Some(layer_override) = overrides.iter().rev().find(|o| o.covers(index)) {
+                layer_override.apply(&mut layer_motion);
+                let exposure = timing.exposure;
+                timing = layer_motion.layer_timing(index, span.thickness);
+                if layer_override.exposure_time.is_none() {
+                    timing.exposure = exposure;
+                }
+            }
+            LayerPlan {
+                top: span.top,
+                thickness: span.thickness,
+                lift_distance: layer_motion.lift_distance,
+                timing,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_thickness::LayerHeightRange;
+
+    #[test]
+    fn test_plan() {
+        let motion = MotionProfile {
+            bottom_layer_count: 2,
+            ..MotionProfile::default()
+        };
+        let mut thickness = LayerThickness::uniform(0.125);
+        thickness.ranges.push(LayerHeightRange {
+            from: 0.5,
+            to: 1.0,
+            thickness: 0.125,
+            exposure_time: Some(1.5),
+        });
+        let overrides = [
+            LayerOverride {
+                first_layer: 1,
+                last_layer: 5,
+                exposure_time: None,
+                lift_distance: Some(1.0),
+                light_off_delay: None,
+            },
+            LayerOverride {
+                first_layer: 5,
+                last_layer: 6,
+                exposure_time: Some(4.0),
+                lift_distance: None,
+                light_off_delay: Some(3.0),
+            },
+        ];
+        assert_eq!(overrides[0].describe(), "Layers 1-5: lift 1 mm");
+
+        let plan = plan(&thickness, &overrides, &motion, 9);
+        let uniform = thickness.timeline(&motion, 9);
+        assert_eq!(plan[0].timing, uniform[0]);
+        assert_eq!(plan[8].timing, uniform[8]);
+        assert_eq!(plan[8].top, 1.125);
+        // The lift is shorter, the exposures are still the ones of the bottom and the range
+        assert_eq!(plan[1].lift_distance, 1.0);
+        assert!(plan[1].timing.lift < uniform[1].lift);
+        assert_eq!(plan[1].timing.exposure, motion.bottom_exposure_time);
+        assert_eq!(plan[4].timing.exposure, 1.5);
+        // The later override wins where they overlap
+        assert_eq!(plan[5].lift_distance, motion.lift_distance);
+        assert_eq!((plan[5].timing.exposure, plan[5].timing.rest), (4.0, 3.0));
+    }
+}
@@ -0,0 +1,201 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::printer::Printer;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut};
+use imageproc::rect::Rect;
+
+const GRID_COLOR: Rgb<u8> = Rgb([70, 70, 70]);
+const SCALE_BAR_COLOR: Rgb<u8> = Rgb([230, 230, 230]);
+const MEASURE_COLOR: Rgb<u8> = Rgb([255, 200, 0]);
+
+/// Grid lines closer than this many preview pixels would blur into a solid fill
+const MIN_GRID_PIXELS: f64 = 10.0;
+
+/// The smallest of 1, 2 and 5 times a power of ten that is at least `minimum`
+pub fn nice_step(minimum: f64) -> f64 {
+    if minimum <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(minimum.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= minimum - 1e-9)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Millimeter grid, scale bar and two point measurement drawn over the 2D layer preview.
+/// Points are kept in millimeters on the LCD from its center, so they stay put while
+/// moving between layers.
+#[derive(Debug, Default)]
+pub struct LayerRuler {
+    points: Vec<[f64; 2]>,
+}
+
+impl LayerRuler {
+    /// Millimeters between grid lines on a preview `width` pixels wide
+    pub fn grid_spacing(printer: &Printer, width: u32) -> f64 {
+        nice_step(MIN_GRID_PIXELS * printer.physical_x / width as f64)
+    }
+
+    /// Length of the scale bar in millimeters, about a fifth of the LCD wide
+    pub fn scale_bar_length(printer: &Printer) -> f64 {
+        nice_step(printer.physical_x / 5.0)
+    }
+
+    /// Places a point where the preview was clicked, given as fractions of its width and
+    /// height. A third click starts a new measurement.
+    pub fn click(&mut self, x_fraction: f64, y_fraction: f64, printer: &Printer) {
+        if self.points.len() == 2 {
+            self.points.clear();
+        }
+        self.points.push([
+            (x_fraction - 0.5) * printer.physical_x,
+            (y_fraction - 0.5) * printer.physical_y,
+        ]);
+    }
+
+    /// Removes the points, e.g. when the preview is hidden
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Distance between the two points in millimeters, once both are placed
+    pub fn distance(&self) -> Option<f64> {
+        match self.points[..] {
+            [a, b] => Some(((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()),
+            _ => None,
+        }
+    }
+
+    /// e.g. "Grid 5 mm, bar 50 mm, measured 12.34 mm"
+    pub fn describe(&self, printer: &Printer, width: u32) -> String {
+        let mut description = format!(
+            "Grid {} mm, bar {} mm",
+            Self::grid_spacing(printer, width),
+            Self::scale_bar_length(printer)
+        );
+        match (self.points.len(), self.distance()) {
+            (_, Some(distance)) => description += &format!(", measured {:.2} mm", distance),
+            (1, None) => description += ", click the second point",
+            _ => description += ", click two points to measure",
+        }
+        description
+    }
+
+    /// Draws the grid behind everything that isn't `background`, then the scale bar and
+    /// the measurement on top
+    pub fn draw(&self, image: &mut RgbImage, background: Rgb<u8>, printer: &Printer) {
+        let (width, height) = image.dimensions();
+        let pixels_per_mm_x = width as f64 / printer.physical_x;
+        let pixels_per_mm_y = height as f64 / printer.physical_y;
+        let to_pixels = |point: [f64; 2]| {
+            (
+                (point[0] * pixels_per_mm_x + width as f64 / 2.0) as f32,
+                (point[1] * pixels_per_mm_y + height as f64 / 2.0) as f32,
+            )
+        };
+
+        // Lines through the center of the plate and every spacing away from it
+        let spacing = Self::grid_spacing(printer, width);
+        let lines_x = (printer.physical_x / 2.0 / spacing) as i64;
+        let lines_y = (printer.physical_y / 2.0 / spacing) as i64;
+        for i in -lines_x..=lines_x {
+            let (x, _) = to_pixels([i as f64 * spacing, 0.0]);
+            let x = x.round() as u32;
+            for y in 0..height {
+                if x < width && *image.get_pixel(x, y) == background {
+                    image.put_pixel(x, y, GRID_COLOR);
+                }
+            }
+        }
+        for i in -lines_y..=lines_y {
+            let (_, y) = to_pixels([0.0, i as f64 * spacing]);
+            let y = y.round() as u32;
+            for x in 0..width {
+                if y < height && *image.get_pixel(x, y) == background {
+                    image.put_pixel(x, y, GRID_COLOR);
+                }
+            }
+        }
+
+        // Scale bar in the bottom right corner
+        let bar_width = (Self::scale_bar_length(printer) * pixels_per_mm_x).round() as u32;
+        if bar_width + 8 < width && height > 10 {
+            draw_filled_rect_mut(
+                image,
+                Rect::at((width - bar_width - 6) as i32, (height - 8) as i32).of_size(bar_width, 3),
+                SCALE_BAR_COLOR,
+            );
+        }
+
+        // Measurement
+        for point in &self.points {
+            let (x, y) = to_pixels(*point);
+            draw_line_segment_mut(image, (x - 3.0, y), (x + 3.0, y), MEASURE_COLOR);
+            draw_line_segment_mut(image, (x, y - 3.0), (x, y + 3.0), MEASURE_COLOR);
+        }
+        if let [a, b] = self.points[..] {
+            draw_line_segment_mut(image, to_pixels(a), to_pixels(b), MEASURE_COLOR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nice_step() {
+        assert_eq!(nice_step(0.7), 1.0);
+        assert_eq!(nice_step(1.0), 1.0);
+        assert_eq!(nice_step(1.3), 2.0);
+        assert_eq!(nice_step(3.0), 5.0);
+        assert_eq!(nice_step(43.776), 50.0);
+        assert_eq!(nice_step(0.03), 0.05);
+        assert_eq!(nice_step(0.0), 1.0);
+    }
+
+    #[test]
+    fn test_measure() {
+        let printer = Printer::default();
+        let mut ruler = LayerRuler::default();
+        assert_eq!(ruler.distance(), None);
+
+        ruler.click(0.5, 0.5, &printer);
+        assert_eq!(ruler.distance(), None);
+        ruler.click(0.75, 0.5, &printer);
+        let distance = ruler.distance().unwrap();
+        assert!((distance - printer.physical_x / 4.0).abs() < 1e-9);
+        assert!(ruler
+            .describe(&printer, 240)
+            .ends_with(&format!("measured {:.2} mm", distance)));
+
+        // A third click starts over
+        ruler.click(0.1, 0.1, &printer);
+        assert_eq!(ruler.distance(), None);
+        ruler.clear();
+        assert!(ruler.describe(&printer, 240).ends_with("click two points to measure"));
+    }
+
+    #[test]
+    fn test_grid_stays_behind_the_layer() {
+        let printer = Printer::default();
+        let background = Rgb([40, 40, 40]);
+        let mut image = RgbImage::from_pixel(240, 135, background);
+        // An outline pixel on the center lines of the grid
+        image.put_pixel(120, 30, Rgb([255, 255, 255]));
+        let mut ruler = LayerRuler::default();
+        ruler.click(0.25, 0.25, &printer);
+
+        ruler.draw(&mut image, background, &printer);
+
+        assert_eq!(*image.get_pixel(120, 30), Rgb([255, 255, 255]));
+        assert_eq!(*image.get_pixel(120, 31), GRID_COLOR);
+        assert_eq!(*image.get_pixel(60, 34), MEASURE_COLOR);
+        assert_eq!(*image.get_pixel(233, 128), SCALE_BAR_COLOR);
+        assert!(LayerRuler::grid_spacing(&printer, 240) * 240.0 / printer.physical_x >= 10.0);
+    }
+}
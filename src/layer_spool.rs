@@ -0,0 +1,156 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use image::{ImageBuffer, Luma};
+use memmap2::MmapMut;
+use rayon::prelude::*;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+
+/// A layer in the spool, borrowing its pixels from the mapped file
+pub type SpooledLayer<'a> = ImageBuffer<Luma<u8>, &'a [u8]>;
+
+/// The layers of a job in a memory-mapped file instead of the heap, for jobs that don't
+/// fit in memory. The file is sized for every layer up front and the system pages layers
+/// in and out of it as they are written and read, so only the ones being worked on take
+/// memory. The file is removed when the spool is dropped.
+pub struct LayerSpool {
+    path: PathBuf,
+    map: ManuallyDrop<MmapMut>,
+    width: u32,
+    height: u32,
+    len: usize,
+}
+
+impl LayerSpool {
+    /// Bytes of disk a spool of `count` layers takes
+    pub fn required_bytes(count: usize, width: u32, height: u32) -> u64 {
+        count as u64 * width as u64 * height as u64
+    }
+
+    /// A spool of `count` blank layers in a new file at `path`
+    pub fn create(path: &Path, count: usize, width: u32, height: u32) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        // Mapping an empty file fails, a spool without layers still maps one byte
+        file.set_len(Self::required_bytes(count, width, height).max(1))?;
+        // Safe as long as nothing else changes the file, which is new and removed on drop
+        let map = unsafe { MmapMut::map_mut(&file) };
+        let map = match map {
+            Ok(map) => map,
+            Err(e) => {
+                let _ = fs::remove_file(path);
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            map: ManuallyDrop::new(map),
+            width,
+            height,
+            len: count,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn layer_bytes(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    pub fn layer(&self, index: usize) -> SpooledLayer<'_> {
+        let size = self.layer_bytes();
+        ImageBuffer::from_raw(
+            self.width,
+            self.height,
+            &self.map[index * size..(index + 1) * size],
+        )
+        .expect("spool layers fill their slot")
+    }
+
+    pub fn layers(&self) -> Vec<SpooledLayer<'_>> {
+        (0..self.len).map(|index| self.layer(index)).collect()
+    }
+
+    /// Every layer, to be filled or changed in parallel
+    pub fn layers_mut(
+        &mut self,
+    ) -> impl IndexedParallelIterator<Item = ImageBuffer<Luma<u8>, &mut [u8]>> {
+        let (width, height, size) = (self.width, self.height, self.layer_bytes().max(1));
+        self.map[..size * self.len]
+            .par_chunks_mut(size)
+            .map(move |pixels| {
+                ImageBuffer::from_raw(width, height, pixels).expect("spool layers fill their slot")
+            })
+    }
+
+    /// Keeps the layers `keep` is true for, moving the ones after a gap down to close it
+    pub fn retain(&mut self, keep: &[bool]) {
+        let size = self.layer_bytes();
+        let mut kept = 0;
+        for (index, _) in keep.iter().enumerate().filter(|(_, keep)| **keep) {
+            if index != kept {
+                self.map
+                    .copy_within(index * size..(index + 1) * size, kept * size);
+            }
+            kept += 1;
+        }
+        self.len = kept;
+    }
+}
+
+impl Drop for LayerSpool {
+    fn drop(&mut self) {
+        // Unmapped first, some systems don't remove files that are still mapped
+        unsafe { ManuallyDrop::drop(&mut self.map) };
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_spool_layers() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("layers.spool");
+        let mut spool = LayerSpool::create(&path, 4, 3, 2).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 4 * 3 * 2);
+        assert!(LayerSpool::create(&path, 1, 1, 1).is_err());
+
+        spool
+            .layers_mut()
+            .enumerate()
+            .for_each(|(index, mut layer)| {
+                layer.put_pixel(index as u32 % 3, 0, Luma([index as u8 + 1]));
+            });
+        assert_eq!(spool.layer(2).get_pixel(2, 0)[0], 3);
+        assert_eq!(spool.layer(2).get_pixel(0, 0)[0], 0);
+
+        // The blank layers are left out and the rest closes the gap
+        spool.retain(&[true, false, true, true]);
+        assert_eq!(spool.len(), 3);
+        let firsts: Vec<u8> = spool
+            .layers()
+            .iter()
+            .map(|layer| layer.pixels().map(|p| p[0]).max().unwrap())
+            .collect();
+        assert_eq!(firsts, [1, 3, 4]);
+
+        drop(spool);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,170 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::motion_profile::{LayerTiming, MotionProfile};
+use serde::{Deserialize, Serialize};
+
+/// Layers of their own thickness over a band of the print, e.g. thin layers over fine
+/// details with `{ from = 12.0, to = 15.0, thickness = 0.025 }`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LayerHeightRange {
+    pub from: f64,      // millimeters above the bottom of the print
+    pub to: f64,        // millimeters above the bottom of the print
+    pub thickness: f64, // millimeters
+    /// Exposure of the normal layers in the range, thinner layers cure with less. The
+    /// resin's exposure when None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exposure_time: Option<f64>, // seconds
+}
+
+/// Where one layer of a print starts and ends, from the bottom of the print
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerSpan {
+    pub bottom: f64,    // millimeters
+    pub top: f64,       // millimeters
+    pub thickness: f64, // millimeters
+}
+
+/// How thick the layers of a print are: `base`, unless a range covers the bottom of a
+/// layer. The first range that covers it wins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerThickness {
+    pub base: f64, // millimeters
+    pub ranges: Vec<LayerHeightRange>,
+}
+
+impl LayerThickness {
+    #[cfg(test)]
+    pub fn uniform(base: f64) -> Self {
+        Self {
+            base,
+            ranges: Vec::new(),
+        }
+    }
+
+    fn range_at(&self, height: f64) -> Option<&LayerHeightRange> {
+        self.ranges
+            .iter()
+            .find(|range| range.thickness > 0.0 && range.from <= height && height < range.to)
+    }
+
+    /// Thickness of the layer whose bottom is `height` above the bottom of the print
+    pub fn at(&self, height: f64) -> f64 {
+        self.range_at(height)
+            .map_or(self.base, |range| range.thickness)
+    }
+
+    /// Heights the layers of a print from `bottom` to `top` are sliced at, the bottom of
+    /// every layer
+    pub fn slice_heights(&self, bottom: f64, top: f64) -> Vec<f64> {
+        let mut heights = Vec::new();
+        let mut z = bottom;
+        while z <= top {
+            heights.push(z);
+            z += self.at(z - bottom);
+        }
+        heights
+    }
+
+    /// Each of the first `count` layers. Layers of the same thickness are counted off from
+    /// where they start, so a uniform print lands on exact multiples of its thickness.
+    pub fn layers(&self, count: usize) -> Vec<LayerSpan> {
+        let mut layers = Vec::with_capacity(count);
+        let (mut run_start, mut run_thickness, mut run_layers) = (0.0, self.at(0.0), 0);
+        for _ in 0..count {
+            let bottom = run_start + run_layers as f64 * run_thickness;
+            let thickness = self.at(bottom);
+            if thickness != run_thickness {
+                (run_start, run_thickness, run_layers) = (bottom, thickness, 0);
+            }
+            layers.push(LayerSpan {
+                bottom: run_start + run_layers as f64 * thickness,
+                top: run_start + (run_layers + 1) as f64 * thickness,
+                thickness,
+            });
+            run_layers += 1;
+        }
+        layers
+    }
+
+    /// The time of each of `count` layers. The plate retracts by the thickness of every
+    /// layer and the normal layers of a range take its exposure.
+    pub fn timeline(&self, motion: &MotionProfile, count: usize) -> Vec<LayerTiming> {
+        self.layers(count)
+            .into_iter()
+            .enumerate()
+            .map(|(index, layer)| {
+                let mut timing = motion.layer_timing(index, layer.thickness);
+                if index >= motion.bottom_layer_count {
+                    let range = self.range_at(layer.bottom);
+                    if let Some(exposure) = range.and_then(|range| range.exposure_time) {
+                        timing.exposure = exposure;
+                    }
+                }
+                timing
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fine_band() -> LayerThickness {
+        LayerThickness {
+            base: 0.125,
+            ranges: vec![LayerHeightRange {
+                from: 0.25,
+                to: 0.375,
+                thickness: 0.03125,
+                exposure_time: Some(1.5),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_uniform_layers() {
+        let uniform = LayerThickness::uniform(0.05);
+        assert_eq!(uniform.slice_heights(1.0, 1.1).len(), 3);
+        for (index, layer) in uniform.layers(7).iter().enumerate() {
+            assert_eq!(layer.top, (index + 1) as f64 * 0.05);
+        }
+        let motion = MotionProfile::default();
+        assert_eq!(uniform.timeline(&motion, 5), motion.timeline(5, 0.05));
+    }
+
+    #[test]
+    fn test_fine_band() {
+        let thickness = fine_band();
+        let thicknesses: Vec<f64> = thickness
+            .layers(8)
+            .iter()
+            .map(|layer| layer.thickness)
+            .collect();
+        assert_eq!(
+            thicknesses,
+            [0.125, 0.125, 0.03125, 0.03125, 0.03125, 0.03125, 0.125, 0.125]
+        );
+        let layers = thickness.layers(8);
+        assert_eq!((layers[5].top, layers[7].top), (0.375, 0.625));
+        // Slicing keeps to the same layers
+        let heights = thickness.slice_heights(2.0, 2.6);
+        assert_eq!(heights.len(), 8);
+        assert_eq!(heights[6], 2.375);
+
+        // The range exposes its normal layers for its own time
+        let motion = MotionProfile {
+            bottom_layer_count: 3,
+            ..MotionProfile::default()
+        };
+        let exposures: Vec<f64> = thickness
+            .timeline(&motion, 8)
+            .iter()
+            .map(|timing| timing.exposure)
+            .collect();
+        assert_eq!(exposures[2], motion.bottom_exposure_time);
+        assert_eq!(exposures[3], 1.5);
+        assert_eq!(exposures[6], motion.exposure_time);
+    }
+}
@@ -0,0 +1,372 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::bleed_compensation::BleedChanges;
+use crate::layer_analysis::layer_islands;
+use crate::layer_components::{ComponentMap, CURED_THRESHOLD};
+use crate::printer::Printer;
+use image::{ImageBuffer, Luma, Rgb, RgbImage};
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// Closest the view zooms in, as times the whole LCD
+const MAX_ZOOM: f64 = 64.0;
+
+/// Empty pixels inside a hollow part in the cut-away view, apart from the black around parts
+const INTERIOR: Rgb<u8> = Rgb([40, 90, 160]);
+
+/// Steps through the layers of the last slice as they were written for the printer, with
+/// zoom and pan
+#[derive(Debug, Clone)]
+pub struct LayerViewer {
+    pub printer: Printer,
+    /// Where the layers were written
+    pub output_dir: String,
+    /// 1 shows the whole LCD
+    zoom: f64,
+    /// Middle of the view, as fractions of the LCD
+    center: (f64, f64),
+    /// Shades the inside of hollow parts, to check their walls and infill layer by layer
+    cut_away: bool,
+    /// The inside of the hollow parts on the shown layer, with the index of the layer
+    interiors: Option<(usize, Vec<bool>)>,
+    /// Tints what bleed compensation changed, to compare the layer with and without it
+    compare_bleed: bool,
+    /// What bleed compensation changed on the shown layer, while comparing
+    bleed_changes: Option<BleedChanges>,
+}
+
+impl LayerViewer {
+    pub fn new(printer: Printer, output_dir: String) -> Self {
+        Self {
+            printer,
+            output_dir,
+            zoom: 1.0,
+            center: (0.5, 0.5),
+            cut_away: false,
+            interiors: None,
+            compare_bleed: false,
+            bleed_changes: None,
+        }
+    }
+
+    /// Turns the cut-away view on or off, for the layers shown from then on
+    pub fn set_cut_away(&mut self, cut_away: bool) {
+        self.cut_away = cut_away;
+        if !cut_away {
+            self.interiors = None;
+        }
+    }
+
+    /// Turns the bleed compensation comparison on or off, for the layers shown from then on
+    pub fn set_compare_bleed(&mut self, compare: bool) {
+        self.compare_bleed = compare;
+        if !compare {
+            self.bleed_changes = None;
+        }
+    }
+
+    /// Called before the layer `index` is rendered, finds the inside of its hollow parts
+    /// for the cut-away view and keeps what bleed compensation changed on it for the
+    /// comparison. Zooming and panning keep them.
+    pub fn show_layer(&mut self, index: usize, layer: &Layer, bleed_changes: &[BleedChanges]) {
+        if self.compare_bleed {
+            self.bleed_changes = bleed_changes.get(index).cloned();
+        }
+        if self.cut_away
+            && self
+                .interiors
+                .as_ref()
+                .is_none_or(|(shown, _)| *shown != index)
+        {
+            self.interiors = Some((index, hollow_interiors(layer)));
+        }
+    }
+
+    /// Zooms by `factor`, keeping the point at `at` of the view, as fractions of it, in place
+    pub fn zoom_at(&mut self, factor: f64, at: (f64, f64)) {
+        let (x0, y0, size) = self.window();
+        let point = (x0 + at.0 * size, y0 + at.1 * size);
+        self.zoom = (self.zoom * factor).clamp(1.0, MAX_ZOOM);
+        let size = 1.0 / self.zoom;
+        self.center = (point.0 + (0.5 - at.0) * size, point.1 + (0.5 - at.1) * size);
+        self.clamp_center();
+    }
+
+    /// Moves the view by fractions of itself
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        self.center.0 += dx / self.zoom;
+        self.center.1 += dy / self.zoom;
+        self.clamp_center();
+    }
+
+    /// Back to the whole LCD
+    pub fn fit(&mut self) {
+        self.zoom = 1.0;
+        self.center = (0.5, 0.5);
+    }
+
+    // Keeps the view on the LCD
+    fn clamp_center(&mut self) {
+        let half = 0.5 / self.zoom;
+        self.center.0 = self.center.0.clamp(half, 1.0 - half);
+        self.center.1 = self.center.1.clamp(half, 1.0 - half);
+    }
+
+    // Top left corner and size of the view, as fractions of the LCD
+    fn window(&self) -> (f64, f64, f64) {
+        let size = 1.0 / self.zoom;
+        (self.center.0 - size / 2.0, self.center.1 - size / 2.0, size)
+    }
+
+    /// The part of the layer in view, `width` pixels wide and as high as the LCD's
+    /// proportions make it. Each pixel shows the brightest pixel of the LCD under it, so
+    /// thin walls don't vanish when zoomed out. In the cut-away view empty pixels inside
+    /// hollow parts are shaded. When comparing, pixels bleed compensation dimmed are tinted
+    /// red by as much as they were dimmed, and the ones it brightened green.
+    pub fn render(&self, layer: &Layer, width: u32) -> RgbImage {
+        let height = (width as f64 * self.printer.physical_y / self.printer.physical_x)
+            .round()
+            .max(1.0) as u32;
+        let (x0, y0, size) = self.window();
+        let (lcd_width, lcd_height) = layer.dimensions();
+        // Pixels of the LCD under the `i`th pixel of the view along one side
+        let span = |start: f64, step: f64, i: u32, pixels: u32| {
+            let from = ((start + i as f64 * step) * pixels as f64).floor() as u32;
+            let to = ((start + (i + 1) as f64 * step) * pixels as f64).ceil() as u32;
+            from.min(pixels - 1)..to.clamp(from + 1, pixels)
+        };
+        let interiors = self
+            .interiors
+            .as_ref()
+            .map(|(_, interiors)| interiors)
+            .filter(|interiors| interiors.len() == (lcd_width * lcd_height) as usize);
+        let (step_x, step_y) = (size / width as f64, size / height as f64);
+        RgbImage::from_fn(width, height, |x, y| {
+            let under = || {
+                span(y0, step_y, y, lcd_height)
+                    .flat_map(|sy| span(x0, step_x, x, lcd_width).map(move |sx| (sx, sy)))
+            };
+            let brightest = under()
+                .map(|(sx, sy)| layer.get_pixel(sx, sy)[0])
+                .max()
+                .unwrap_or(0);
+            if let Some(changes) = &self.bleed_changes {
+                let uncompensated = under()
+                    .map(|(sx, sy)| {
+                        changes
+                            .before(sy * lcd_width + sx)
+                            .unwrap_or(layer.get_pixel(sx, sy)[0])
+                    })
+                    .max()
+                    .unwrap_or(0);
+                if uncompensated > brightest {
+                    return Rgb([uncompensated, brightest, brightest]);
+                }
+                if uncompensated < brightest {
+                    return Rgb([uncompensated, brightest, uncompensated]);
+                }
+            }
+            let inside = |interiors: &Vec<bool>| {
+                under().any(|(sx, sy)| interiors[(sy * lcd_width + sx) as usize])
+            };
+            if brightest == 0 && interiors.is_some_and(inside) {
+                return INTERIOR;
+            }
+            Rgb([brightest; 3])
+        })
+    }
+}
+
+/// Per pixel of the layer, whether it is empty but walled in by cured pixels. Going out
+/// from such a pixel crosses the contours an odd number of times more than from outside
+/// the parts, so it is inside a hole of a part: the hollow of a hollowed model, the cells
+/// of its infill or the bore of a tube.
+fn hollow_interiors(layer: &Layer) -> Vec<bool> {
+    let (width, height) = layer.dimensions();
+    let raw = layer.as_raw();
+    let voids = ComponentMap::label(width, height, |i| raw[i] < CURED_THRESHOLD);
+    // Voids reaching the edge of the LCD are around the parts
+    let enclosed: Vec<bool> = voids
+        .components
+        .iter()
+        .map(|void| {
+            void.min.0 > 0 && void.min.1 > 0 && void.max.0 + 1 < width && void.max.1 + 1 < height
+        })
+        .collect();
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| voids.index_at(x, y).is_some_and(|void| enclosed[void]))
+        .collect()
+}
+
+/// What is cured on one layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerStats {
+    /// Square millimeters
+    pub cured_area: f64,
+    /// Separate blobs of cured pixels
+    pub parts: usize,
+    /// Blobs that rest on nothing in the layer below
+    pub islands: usize,
+}
+
+impl LayerStats {
+    pub fn of(index: usize, below: Option<&Layer>, layer: &Layer, printer: &Printer) -> Self {
+        let parts = ComponentMap::of_layer(layer).components;
+        Self {
+            cured_area: parts.iter().map(|part| part.area(printer)).sum(),
+            parts: parts.len(),
+            islands: layer_islands(index, below, layer).len(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{:.2} mm² cured in {} parts, {} islands",
+            self.cured_area, self.parts, self.islands
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bleed_compensation::BleedCompensation;
+    use crate::test_support::layer;
+
+    // An 8 x 4 pixel LCD of 0.5 mm pixels
+    fn viewer() -> LayerViewer {
+        let printer = Printer {
+            physical_x: 4.0,
+            physical_y: 2.0,
+            pixel_x: 8,
+            pixel_y: 4,
+            ..Printer::default()
+        };
+        LayerViewer::new(printer, "out/1".to_string())
+    }
+
+    // Cured pixels of the view, drawn like the layers
+    fn rows(image: &RgbImage) -> Vec<String> {
+        (0..image.height())
+            .map(|y| {
+                (0..image.width())
+                    .map(|x| {
+                        if image.get_pixel(x, y)[0] > 0 {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_zoom_and_pan() {
+        let layer = layer(&["#.......", "........", "........", ".......#"]);
+        let mut viewer = viewer();
+        // Thin details stay when the view is smaller than the LCD
+        assert_eq!(rows(&viewer.render(&layer, 4)), vec!["#...", "...#"]);
+
+        // Zoomed in on the top left corner, which stays in place
+        viewer.zoom_at(2.0, (0.0, 0.0));
+        assert_eq!(viewer.zoom, 2.0);
+        assert_eq!(rows(&viewer.render(&layer, 4)), vec!["#...", "...."]);
+
+        // Can't pan past the edge of the LCD
+        viewer.pan(5.0, 5.0);
+        assert_eq!(rows(&viewer.render(&layer, 4)), vec!["....", "...#"]);
+
+        // Nor zoom out further than the whole of it
+        viewer.zoom_at(0.1, (0.5, 0.5));
+        assert_eq!(viewer.zoom, 1.0);
+        viewer.zoom_at(1000.0, (0.5, 0.5));
+        assert_eq!(viewer.zoom, MAX_ZOOM);
+        viewer.fit();
+        assert_eq!(
+            rows(&viewer.render(&layer, 8)),
+            vec!["#.......", "........", "........", ".......#"]
+        );
+    }
+
+    #[test]
+    fn test_cut_away() {
+        // A hollow part with a strut of infill across its inside, and a solid one
+        let layer = layer(&["#####...", "#.#.#.##", "#####.##", "........"]);
+        let mut viewer = viewer();
+        viewer.show_layer(0, &layer, &[]);
+        assert_eq!(*viewer.render(&layer, 8).get_pixel(1, 1), Rgb([0, 0, 0]));
+
+        viewer.set_cut_away(true);
+        viewer.show_layer(0, &layer, &[]);
+        let image = viewer.render(&layer, 8);
+        assert_eq!(*image.get_pixel(1, 1), INTERIOR);
+        assert_eq!(*image.get_pixel(3, 1), INTERIOR);
+        assert_eq!(*image.get_pixel(2, 1), Rgb([255, 255, 255]));
+        // Around the parts and between them stays black
+        assert_eq!(*image.get_pixel(5, 1), Rgb([0, 0, 0]));
+        assert_eq!(*image.get_pixel(0, 3), Rgb([0, 0, 0]));
+
+        // Zoomed out, walls under a pixel win over the inside next to them
+        assert_eq!(
+            *viewer.render(&layer, 4).get_pixel(0, 0),
+            Rgb([255, 255, 255])
+        );
+
+        viewer.set_cut_away(false);
+        assert_eq!(*viewer.render(&layer, 8).get_pixel(1, 1), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_compare_bleed() {
+        let uncompensated = layer(&["####....", "####....", "####....", "........"]);
+        let compensation = BleedCompensation {
+            erode_pixels: 1,
+            sharpen_amount: 0.0,
+            sharpen_sigma: 1.0,
+        };
+        let mut layers = vec![uncompensated];
+        let changes = compensation.apply_to_all(&mut layers);
+        let layer = &layers[0];
+
+        let mut viewer = viewer();
+        viewer.show_layer(0, layer, &changes);
+        assert_eq!(*viewer.render(layer, 8).get_pixel(3, 1), Rgb([0, 0, 0]));
+
+        viewer.set_compare_bleed(true);
+        viewer.show_layer(0, layer, &changes);
+        let image = viewer.render(layer, 8);
+        // The eroded rim is red, what is left of the part white and the plate black
+        assert_eq!(*image.get_pixel(3, 1), Rgb([255, 0, 0]));
+        assert_eq!(*image.get_pixel(1, 1), Rgb([255, 255, 255]));
+        assert_eq!(*image.get_pixel(6, 1), Rgb([0, 0, 0]));
+
+        viewer.set_compare_bleed(false);
+        assert_eq!(*viewer.render(layer, 8).get_pixel(3, 1), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_layer_stats() {
+        let viewer = viewer();
+        let below = layer(&["##......", "##......", "........", "........"]);
+        let current = layer(&["###.....", "##......", "......##", "......##"]);
+        let stats = LayerStats::of(1, Some(&below), &current, &viewer.printer);
+        assert_eq!(
+            stats,
+            LayerStats {
+                cured_area: 9.0 * 0.25,
+                parts: 2,
+                islands: 1,
+            }
+        );
+        assert_eq!(stats.describe(), "2.25 mm² cured in 2 parts, 1 islands");
+        // The first layer rests on the plate
+        assert_eq!(
+            LayerStats::of(0, None, &current, &viewer.printer).islands,
+            0
+        );
+    }
+}
@@ -1,46 +1,173 @@
 // Distributed under the GNU Affero General Public License v3.0 or later.
 // See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
 
+mod axis_gizmo;
+mod bleed_compensation;
 mod body;
+mod body_layers;
+mod calibration_mask;
 mod camera;
 mod cpu_slicer;
+mod ctb;
+mod display_scale;
+mod drain_holes;
+mod export_queue;
+mod geometry;
+mod geometry_analysis;
+#[cfg(feature = "gpu-slicer")]
+mod gpu_layer_analysis;
+#[cfg(feature = "gpu-slicer")]
 mod gpu_slicer;
+mod hollow;
+mod hollowing_wizard;
+mod import_orientation;
+mod infill;
+mod job_editor;
 mod mesh;
+mod mesh_cache;
 mod mesh_renderer;
+mod model_library;
 mod render_texture;
 mod stl_processor;
 use action_manager::ActionManager;
-use body::Body;
-use cpu_slicer::{CPUSlicer, CPUSlicerError};
+use alignment::{Alignment, AlignmentAction};
+use body::{Body, SliceRole};
+use calibration_mask::CalibrationMask;
+use cpu_slicer::{CPUSlicer, CPUSlicerError, LCD_PREVIEW_BACKGROUND};
+use display_scale::DisplayScale;
+use export_queue::{ExportError, ExportJob, ExportLayers, ExportQueue};
 use glow::Context as GlowContext;
 use glow::HasContext;
-use image::{ImageBuffer, Luma};
+#[cfg(feature = "gpu-slicer")]
+use gpu_layer_analysis::GpuLayerAnalyzer;
+use image::{ImageBuffer, Luma, Rgb, RgbImage};
+use job_editor::ExportedJob;
+use layer_analysis::LayerAnalysis;
+use layer_components::{ComponentMap, SELECTED_COLOR};
+use layer_overrides::LayerOverride;
+use layer_ruler::LayerRuler;
+use layer_spool::LayerSpool;
+use layer_viewer::{LayerStats, LayerViewer};
 use log::debug;
+use material_ledger::{MaterialLedger, StockAdjustment};
 use mesh_renderer::MeshRenderer;
+use model_library::{ModelLibrary, ThumbnailCache};
 use nalgebra::Vector3;
+use plate_drag::PlateDrag;
+use plate_shape::PlateMask;
+use plugin::{EmptyLayerCheck, PluginFinding};
+use print_history::{PrintHistory, PrintOutcome, SHOWN_PER_BODY};
 use printer::Printer;
+use rayon::prelude::*;
+use removable_drive::RemovableDrive;
+use resin::Resin;
+use report::Report;
+#[cfg(feature = "scripting")]
+use scripting::{ScriptConsole, ScriptRun, SliceRequest};
 use rfd::AsyncFileDialog;
-use settings::Settings;
+use settings::{
+    CompressionSettings, HollowingSettings, ImportSettings, PngCompression, Settings,
+    UvToolsSettings,
+};
+use software_renderer::SoftwareRenderer;
+use slice_cache::{SliceCache, SliceSnapshot};
+use slice_diff::SliceComparison;
+use support_density::weak_overhangs;
+use support_styles::{SupportStyleError, SupportStyleLibrary};
+use slice_parameters::{ParameterSnapshots, SliceParameters, Supersampling};
 use slint::platform::PointerEventButton;
 use slint::SharedString;
 use tokio::sync::mpsc::error;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use stl_processor::StlProcessor;
+use transform_stepper::{Scrub, TransformField};
+use usage_stats::UsageStats;
+use vat::Vat;
+use viewport::Viewport;
 use tokio::task;
+use xy_compensation::{XyCompensation, XyCompensationMask};
 mod file_manager;
+mod footprint;
+mod keyboard;
 mod mesh_island_analyzer;
-use crate::file_manager::file_manager::write_webps_to_folder;
+mod open_bottom;
+use crate::file_manager::file_manager::{profile_output_dir, timestamped_output_dir};
+use crate::output_formats::OutputFormat;
+use crate::performance_overlay::FrameClock;
+use crate::preview::PreviewFormat;
+use crate::printer::AntiAliasing;
+use anti_float_tabs::AntiFloatTabs;
+use import_orientation::{ImportedBatch, UpAxis};
+use memory_budget::BudgetCheck;
+use mesh_cache::MeshCache;
 use mesh_island_analyzer::MeshIslandAnalyzer;
+use open_bottom::OpenBottom;
 slint::include_modules!();
 mod action;
 mod action_manager;
+mod alignment;
+mod anti_float_tabs;
+mod layer_analysis;
+mod layer_components;
+mod layer_overrides;
+mod layer_ruler;
+mod layer_spool;
+mod layer_thickness;
+mod layer_viewer;
 mod material;
+mod material_ledger;
+mod memory_budget;
+mod motion_profile;
+#[cfg(feature = "network-printing")]
+mod network_printer;
+mod number_format;
+mod orientation_presets;
+mod output_formats;
+mod performance_overlay;
+mod plate_drag;
+mod plate_shape;
+mod plugin;
+mod polygon_assembly;
+mod preview;
+mod print_history;
 mod printer;
+mod printer_presets;
+mod profiler;
+mod regression;
+mod removable_drive;
+mod report;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod resin;
 mod settings;
-use crate::action::{SetPositionAction, SetRotationAction, SetScaleAction};
+mod sl1;
+mod slice_cache;
+mod slice_debugger;
+mod slice_diff;
+mod slice_parameters;
+mod software_renderer;
+mod support_density;
+mod support_lift;
+mod support_styles;
+#[cfg(test)]
+mod test_support;
+mod three_mf;
+mod tolerance;
+mod transform_stepper;
+mod usage_stats;
+mod uvj;
+mod uvtools;
+mod vat;
+mod viewport;
+mod worker_pool;
+mod xy_compensation;
+use crate::action::{AddBodiesAction, BatchTransform, CircularArray};
+use hollowing_wizard::HollowingPlan;
 use log::error;
 #[derive(Default)]
 struct MouseState {
@@ -57,39 +184,552 @@ struct MouseState {
 }
 
 type SharedBodies = Rc<RefCell<Vec<Rc<RefCell<Body>>>>>;
-type SharedMeshRenderer = Rc<RefCell<Option<MeshRenderer>>>;
+type SharedMeshRenderer = Rc<RefCell<Option<Box<dyn Viewport>>>>;
 type SharedMouseState = Rc<RefCell<MouseState>>;
+type SharedDisplayScale = Rc<RefCell<DisplayScale>>;
+type SharedFrameClock = Rc<RefCell<FrameClock>>;
 type SharedSettings = Arc<Mutex<Settings>>;
 type SharedPrinter = Arc<Mutex<Printer>>;
 type SharedActionManager = Arc<Mutex<ActionManager>>;
+type SharedSliceCache = Rc<RefCell<SliceCache>>;
+type SharedSliceParameters = Rc<RefCell<SliceParameters>>;
+type SharedParameterSnapshots = Rc<RefCell<ParameterSnapshots>>;
+type SharedPrintHistory = Rc<RefCell<PrintHistory>>;
+type SharedMaterialLedger = Rc<RefCell<MaterialLedger>>;
+type SharedUsageStats = Rc<RefCell<UsageStats>>;
+type SharedExportQueue = Rc<ExportQueue>;
+type SharedWorkerPool = Arc<rayon::ThreadPool>;
+type SharedLibraryPanel = Rc<RefCell<LibraryPanel>>;
+type SharedLayerViewer = Rc<RefCell<Option<LayerViewer>>>;
+/// Imports model files the way the import button does, returning the imported bodies
+type ImportModels = Rc<dyn Fn(&[PathBuf]) -> Vec<Rc<RefCell<Body>>>>;
 
 struct AppState {
     mouse_state: SharedMouseState,
+    display_scale: SharedDisplayScale,
+    frame_clock: SharedFrameClock,
     shared_mesh_renderer: SharedMeshRenderer,
     shared_bodies: SharedBodies,
     shared_settings: SharedSettings,
     shared_printer: SharedPrinter,
     shared_action_manager: SharedActionManager,
+    shared_slice_cache: SharedSliceCache,
+    shared_slice_parameters: SharedSliceParameters,
+    shared_parameter_snapshots: SharedParameterSnapshots,
+    shared_print_history: SharedPrintHistory,
+    shared_material_ledger: SharedMaterialLedger,
+    shared_usage_stats: SharedUsageStats,
+    shared_export_queue: SharedExportQueue,
+    shared_worker_pool: SharedWorkerPool,
 }
 
+/// The shared state a slicing job goes through, from the cached layers to the print
+/// history, material ledger and usage statistics it ends up in
+#[derive(Clone)]
+struct SlicingPipeline {
+    slice_cache: SharedSliceCache,
+    export_queue: SharedExportQueue,
+    worker_pool: SharedWorkerPool,
+    print_history: SharedPrintHistory,
+    material_ledger: SharedMaterialLedger,
+    usage_stats: SharedUsageStats,
+    /// Read when the layers are exported, for the compression settings
+    settings: SharedSettings,
+}
+
+impl SlicingPipeline {
+    fn of(state: &AppState) -> Self {
+        SlicingPipeline {
+            slice_cache: Rc::clone(&state.shared_slice_cache),
+            export_queue: Rc::clone(&state.shared_export_queue),
+            worker_pool: Arc::clone(&state.shared_worker_pool),
+            print_history: Rc::clone(&state.shared_print_history),
+            material_ledger: Rc::clone(&state.shared_material_ledger),
+            usage_stats: Rc::clone(&state.shared_usage_stats),
+            settings: Arc::clone(&state.shared_settings),
+        }
+    }
+}
+
+/// What the model library panel lists: the scanned folder, the search typed in and the
+/// thumbnails rendered so far
+#[derive(Default)]
+struct LibraryPanel {
+    library: Option<ModelLibrary>,
+    query: String,
+    thumbnails: HashMap<PathBuf, slint::Image>,
+}
+
+impl LibraryPanel {
+    fn show(&self, app: &App) {
+        let Some(library) = &self.library else {
+            app.set_library_items(Rc::new(slint::VecModel::<LibraryItemUI>::default()).into());
+            return;
+        };
+        let items: Vec<LibraryItemUI> = library
+            .search(&self.query)
+            .into_iter()
+            .map(|entry| LibraryItemUI {
+                name: entry.name.clone().into(),
+                folder: entry.folder.clone().into(),
+                path: entry.path.to_string_lossy().to_string().into(),
+                thumbnail: self
+                    .thumbnails
+                    .get(&entry.path)
+                    .cloned()
+                    .unwrap_or_default(),
+            })
+            .collect();
+        app.set_library_folder(library.dir().display().to_string().into());
+        app.set_library_items(Rc::new(slint::VecModel::from(items)).into());
+    }
+}
+
+/// Recomputes the dry-run stats of the current parameters and of every snapshot
+/// for the bodies in the scene and updates the parameters panel
+fn refresh_parameter_snapshots(
+    app: &App,
+    bodies: &SharedBodies,
+    slice_parameters: &SharedSliceParameters,
+    parameter_snapshots: &SharedParameterSnapshots,
+) {
+    let bodies: Vec<Body> = bodies
+        .borrow()
+        .iter()
+        .filter(|b| b.borrow().display_in_ui_list)
+        .map(|b| b.borrow().clone())
+        .collect();
+    let parameters = slice_parameters.borrow();
+    let snapshots = parameter_snapshots.borrow();
+
+    let snapshots_ui: Vec<ParameterSnapshotUI> = snapshots
+        .compare(&bodies)
+        .into_iter()
+        .map(|(name, stats)| ParameterSnapshotUI {
+            active: snapshots.active.as_deref() == Some(name.as_str()),
+            summary: format!(
+                "{} mm, {}",
+                number_format::shortest(snapshots.snapshots[&name].slice_thickness),
+                stats.summary()
+            )
+            .into(),
+            name: name.into(),
+        })
+        .collect();
+
+    let stats = parameters.dry_run(&bodies);
+    let timeline = parameters.timeline(stats.layer_count);
+    let chart = motion_profile::render_timeline(&timeline, 280, 80);
+    app.set_motion_timeline(slint::Image::from_rgb8(
+        slint::SharedPixelBuffer::clone_from_slice(chart.as_raw(), chart.width(), chart.height()),
+    ));
+    app.set_motion_summary(motion_profile::summary(&timeline).into());
+
+    app.set_layer_height(number_format::shortest(parameters.slice_thickness).into());
+    app.set_anti_aliasing(parameters.printer.anti_aliasing.index());
+    app.set_supersampling(parameters.supersampling.index());
+    app.set_xy_compensation(number_format::shortest(parameters.printer.xy_compensation.0).into());
+    let scripts = match parameters.layer_scripts.len() {
+        0 => String::new(),
+        1 => ", 1 layer script".to_string(),
+        count => format!(", {} layer scripts", count),
+    };
+    let ranges = match parameters.layer_height_ranges.len() {
+        0 => String::new(),
+        1 => ", 1 layer height range".to_string(),
+        count => format!(", {} layer height ranges", count),
+    };
+    let overrides = match parameters.layer_overrides.len() {
+        0 => String::new(),
+        1 => ", 1 layer override".to_string(),
+        count => format!(", {} layer overrides", count),
+    };
+    app.set_current_parameters_summary(
+        format!(
+            "Current: {}{}{}{}",
+            stats.summary(),
+            scripts,
+            ranges,
+            overrides
+        )
+        .into(),
+    );
+    app.set_parameter_snapshots(Rc::new(slint::VecModel::from(snapshots_ui)).into());
+}
+
+/// Keeps the window at the window system's scale factor times the UI scale setting. The
+/// new factor is applied after the current frame, as it resizes the window's surface.
+fn sync_display_scale(app: &App, display_scale: &SharedDisplayScale) {
+    let Some(factor) = display_scale
+        .borrow_mut()
+        .sync(app.window().scale_factor())
+    else {
+        return;
+    };
+    let app_weak = app.as_weak();
+    slint::Timer::single_shot(std::time::Duration::ZERO, move || {
+        if let Some(app) = app_weak.upgrade() {
+            display_scale::apply(app.window(), factor);
+        }
+    });
+}
+
+/// Renders the 3D view and refreshes the body list next to it
+fn update_viewport(
+    app: &App,
+    renderer: &mut dyn Viewport,
+    bodies: &SharedBodies,
+    settings: &SharedSettings,
+    frame_clock: &SharedFrameClock,
+) {
+    let height = app.get_requested_texture_height() as f32;
+    let width = app.get_requested_texture_width() as f32;
+    let renderer_settings = &settings.lock().unwrap().renderer;
+    let render_scale = renderer_settings.render_scale;
+    let texture = renderer.render(
+        (width * render_scale) as u32,
+        (height * render_scale) as u32,
+        renderer_settings,
+    );
+
+    let mut bodies_ui_vec: Vec<BodyUI> = Vec::new();
+    let mut num_bodies = 0;
+    for body in bodies.borrow_mut().iter() {
+        if !body.borrow().display_in_ui_list {
+            continue;
+        }
+        num_bodies += 1;
+        let b = body.borrow_mut();
+        // In degrees, like the rotation steppers edit it
+        let rotation = Body::quaternion_to_euler(&b.rotation);
+        bodies_ui_vec.push(BodyUI {
+            enabled: b.enabled,
+            name: b.name.clone().into(),
+            uuid: b.uuid.clone().to_string().into(),
+            visible: b.visible,
+            subtract: b.slice_role == SliceRole::Subtract,
+            print_profile: b.print_profile.clone().unwrap_or_default().into(),
+            selected: b.selected,
+            p_x: number_format::shortest(b.position.x).into(),
+            p_y: number_format::shortest(b.position.y).into(),
+            p_z: number_format::shortest(b.position.z).into(),
+            r_x: number_format::shortest(rotation.x).into(),
+            r_y: number_format::shortest(rotation.y).into(),
+            r_z: number_format::shortest(rotation.z).into(),
+            s_x: number_format::shortest(b.scale.x).into(),
+            s_y: number_format::shortest(b.scale.y).into(),
+            s_z: number_format::shortest(b.scale.z).into(),
+        })
+    }
+
+    let bodies_model: Rc<slint::VecModel<BodyUI>> =
+        std::rc::Rc::new(slint::VecModel::from(bodies_ui_vec));
+
+    // Update UI model
+    app.set_bodies(bodies_model.into());
+    app.set_num_bodies(num_bodies);
+    app.set_texture(texture);
+    app.set_visualize_edges(renderer_settings.visualize_edges);
+    app.set_visualize_normals(renderer_settings.visualize_normals);
+    app.set_visualize_local_axes(renderer_settings.visualize_local_axes);
+    app.set_visualize_defects(renderer_settings.visualize_defects);
+    app.set_show_performance_overlay(renderer_settings.show_performance_overlay);
+    app.set_show_vat_fill(renderer_settings.show_vat_fill);
+    if renderer_settings.show_performance_overlay {
+        let mut frame_clock = frame_clock.borrow_mut();
+        frame_clock.tick(std::time::Instant::now());
+        let overlay = performance_overlay::overlay_text(
+            &frame_clock,
+            &renderer.frame_stats(),
+            performance_overlay::slicing_throughput().layers_per_second(),
+        );
+        app.set_performance_overlay(overlay.into());
+    }
+}
+
+/// Shows the outlines of the previewed layer with the grid, scale bar and measurement
+fn show_layer_lcd_preview(app: &App, outlines: &RgbImage, ruler: &LayerRuler, printer: &Printer) {
+    let mut preview = outlines.clone();
+    let background = LCD_PREVIEW_BACKGROUND;
+    ruler.draw(&mut preview, Rgb([background; 3]), printer);
+    app.set_layer_lcd_preview(slint::Image::from_rgb8(
+        slint::SharedPixelBuffer::clone_from_slice(
+            preview.as_raw(),
+            preview.width(),
+            preview.height(),
+        ),
+    ));
+    app.set_layer_lcd_preview_ruler(ruler.describe(printer, preview.width()).into());
+}
+
+/// Shows one layer of a slice comparison as a heat map with its stats
+fn show_slice_comparison_layer(app: &App, comparison: &SliceComparison, layer: usize) {
+    let heat_map = comparison.heat_map(layer, 480);
+    app.set_slice_comparison_heat_map(slint::Image::from_rgb8(
+        slint::SharedPixelBuffer::clone_from_slice(
+            heat_map.as_raw(),
+            heat_map.width(),
+            heat_map.height(),
+        ),
+    ));
+    app.set_slice_comparison_layer_stats(comparison.layers[layer].describe().into());
+}
+
+/// Shows the part of the layer of the last slice in view of the layer viewer
+fn show_layer_viewer_image(
+    app: &App,
+    viewer: &LayerViewer,
+    layer: &ImageBuffer<Luma<u8>, Vec<u8>>,
+) {
+    let image = viewer.render(layer, 480);
+    app.set_layer_viewer_image(slint::Image::from_rgb8(
+        slint::SharedPixelBuffer::clone_from_slice(image.as_raw(), image.width(), image.height()),
+    ));
+}
+
+/// Shows one layer of the last slice in the layer viewer, with its stats
+fn show_layer_viewer_layer(
+    app: &App,
+    viewer: &mut LayerViewer,
+    slice_cache: &SliceCache,
+    index: usize,
+) {
+    let layers = slice_cache.latest();
+    let Some(layer) = layers.get(index) else {
+        return;
+    };
+    viewer.show_layer(index, layer, slice_cache.latest_bleed_changes());
+    show_layer_viewer_image(app, viewer, layer);
+    let below = index.checked_sub(1).map(|below| &layers[below]);
+    let stats = LayerStats::of(index, below, layer, &viewer.printer);
+    app.set_layer_viewer_stats(stats.describe().into());
+}
+
+/// Opens the layer viewer on the layers of the slice just written to `output_dir`
+fn open_layer_viewer(
+    app: &App,
+    layer_viewer: &SharedLayerViewer,
+    slice_cache: &SharedSliceCache,
+    printer: Printer,
+    output_dir: String,
+) {
+    let slice_cache = slice_cache.borrow();
+    let layers = slice_cache.latest();
+    if layers.is_empty() {
+        return;
+    }
+    let mut viewer = LayerViewer::new(printer, output_dir);
+    viewer.set_cut_away(app.get_layer_viewer_cut_away());
+    let bleed_compensated = !slice_cache.latest_bleed_changes().is_empty();
+    viewer.set_compare_bleed(bleed_compensated && app.get_layer_viewer_compare_bleed());
+    app.set_layer_viewer_bleed_compensated(bleed_compensated);
+    app.set_layer_viewer_output_dir(viewer.output_dir.clone().into());
+    app.set_layer_viewer_layer_count(layers.len() as i32);
+    app.set_layer_viewer_position(0.0);
+    show_layer_viewer_layer(app, &mut viewer, &slice_cache, 0);
+    app.set_layer_viewer_visible(true);
+    *layer_viewer.borrow_mut() = Some(viewer);
+}
+
+fn report_plugin_findings(findings: &[PluginFinding]) {
+    for finding in findings {
+        println!("[{}] {}", finding.plugin, finding.message);
+    }
+}
+
+/// The ray through the 3D view under the pointer, given in logical pixels
+fn pick_ray(
+    app: &App,
+    renderer: &dyn Viewport,
+    display_scale: &DisplayScale,
+    x: f32,
+    y: f32,
+) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    renderer.pick_ray(
+        display_scale.to_physical(x),
+        display_scale.to_physical(y),
+        app.get_requested_texture_width() as f32,
+        app.get_requested_texture_height() as f32,
+    )
+}
+
+/// The layer of the surface under the pointer and its height, None off the bodies
+fn layer_under_pointer(
+    bodies: &[Rc<RefCell<Body>>],
+    parameters: &SliceParameters,
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+) -> Option<String> {
+    let bodies: Vec<_> = bodies.iter().map(|b| b.borrow()).collect();
+    let on_plate = || {
+        bodies
+            .iter()
+            .map(|b| &**b)
+            .filter(|b| b.display_in_ui_list && b.visible)
+    };
+    let distance = on_plate()
+        .filter_map(|b| b.ray_hit(origin, direction))
+        .min_by(f32::total_cmp)?;
+    let height = (origin + direction * distance).z as f64;
+    let layer = parameters.layer_at_height(on_plate(), height)?;
+    Some(format!(
+        "Layer {} at {} mm",
+        layer,
+        number_format::decimal(height, 2)
+    ))
+}
+
+/// Shows a reopened printer file in its dialog
+fn show_exported_job(app: &App, job: &ExportedJob) {
+    let motion = &job.file.motion;
+    app.set_exported_job_title(job.path().display().to_string().into());
+    app.set_exported_job_summary(job.summary().into());
+    app.set_exported_job_layer_count(job.layers.len() as i32);
+    app.set_exported_job_exposure(number_format::shortest(motion.exposure_time).into());
+    app.set_exported_job_bottom_exposure(
+        number_format::shortest(motion.bottom_exposure_time).into(),
+    );
+    app.set_exported_job_bottom_layers(motion.bottom_layer_count as i32);
+}
+
+/// Lists the support styles of the library after the plain pillars
+fn show_support_styles(app: &App, names: &[String]) {
+    let styles: Vec<SharedString> = std::iter::once("Plain pillars")
+        .chain(names.iter().map(String::as_str))
+        .map(SharedString::from)
+        .collect();
+    app.set_support_styles(Rc::new(slint::VecModel::from(styles)).into());
+}
+
+/// Opens a fresh export in UVtools when the user checks every export there
+fn open_export_in_uvtools(app_weak: &slint::Weak<App>, settings: &UvToolsSettings, dir_path: &str) {
+    if settings.open_after_export {
+        if let Err(e) = uvtools::open(settings, Path::new(dir_path)) {
+            show_notification(app_weak, e.to_string(), true);
+        }
+    }
+}
+
+/// Shows a dismissible message above the 3D view
+fn show_notification(app_weak: &slint::Weak<App>, message: String, is_error: bool) {
+    if is_error {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+    if let Some(app) = app_weak.upgrade() {
+        app.set_notification(message.into());
+        app.set_notification_is_error(is_error);
+    }
+}
+
+/// Shows the cut through the bodies the hollowing wizard hollows and what it saves
+fn show_hollowing_plan(app: &App, plan: &HollowingPlan, through_bottom: bool, printer: &Printer) {
+    let section = plan.section(through_bottom, printer, 240);
+    let section = image::DynamicImage::ImageLuma8(section).to_rgb8();
+    app.set_hollowing_preview(slint::Image::from_rgb8(
+        slint::SharedPixelBuffer::clone_from_slice(
+            section.as_raw(),
+            section.width(),
+            section.height(),
+        ),
+    ));
+    app.set_hollowing_summary(plan.describe().into());
+}
+
+/// The bodies selected in the scene
+fn selected_bodies(bodies: &[Rc<RefCell<Body>>]) -> Vec<Rc<RefCell<Body>>> {
+    bodies
+        .iter()
+        .filter(|body| body.borrow().selected)
+        .cloned()
+        .collect()
+}
 
 fn main() {
+    // `--profile` prints per-stage timings after every slicing job
+    if std::env::args().any(|arg| arg == "--profile") {
+        profiler::global().set_enabled(true);
+        println!("Profiling enabled");
+    }
+    // `--debug-slices` dumps layers with open or self-intersecting contours as SVGs
+    if std::env::args().any(|arg| arg == "--debug-slices") {
+        slice_debugger::global().set_enabled(true);
+        println!("Slice debugging enabled");
+    }
+    // `--simulate-printer` uploads every export to a simulated printer and prints it
+    if std::env::args().any(|arg| arg == "--simulate-printer") {
+        #[cfg(feature = "network-printing")]
+        {
+            network_printer::set_simulator_enabled(true);
+            println!("Printer simulator enabled");
+        }
+        #[cfg(not(feature = "network-printing"))]
+        println!("This build has no network printing, the printer simulator is off");
+    }
+    // `--regression` runs the mesh corpus through import and a slicing dry run, then exits
+    if std::env::args().any(|arg| arg == "--regression") {
+        std::process::exit(regression::run_from_command_line());
+    }
+
+    plugin::registry().register(Box::<EmptyLayerCheck>::default());
+
     // Initialize the Slint application
     let app = App::new().unwrap();
     let app_weak = app.as_weak();
     let settings = Settings::load_user_settings();
+    number_format::set(settings.lock().unwrap().general.decimal_separator);
+    app.global::<NumberFormat>()
+        .on_parse(|text| number_format::parse(&text).unwrap_or(0.0) as f32);
+    let mut parameter_snapshots = ParameterSnapshots::load_user_snapshots();
+    let mut slice_parameters = parameter_snapshots
+        .active
+        .clone()
+        .and_then(|name| parameter_snapshots.activate(&name))
+        .unwrap_or_default();
+    let print_history = PrintHistory::load_user_history();
+    // Estimates start out corrected by the print times measured on the printer so far
+    if let Some(calibration) = print_history.time_calibration(&slice_parameters.printer.name) {
+        slice_parameters.printer.time_calibration = calibration;
+    }
+
+    let worker_pool = Arc::new(
+        worker_pool::build(&settings.lock().unwrap().performance)
+            .expect("Failed to start the worker threads"),
+    );
 
     let state = AppState {
         mouse_state: Rc::new(RefCell::new(MouseState::default())),
+        display_scale: Rc::new(RefCell::new(DisplayScale::new(
+            settings.lock().unwrap().general.ui_scale,
+        ))),
+        frame_clock: Rc::new(RefCell::new(FrameClock::new())),
         shared_mesh_renderer: Rc::new(RefCell::new(None)),
         shared_bodies: Rc::new(RefCell::new(Vec::<Rc<RefCell<Body>>>::new())), // Initialized as empty Vec
         shared_settings: settings.clone(),
         shared_printer: Arc::new(Mutex::new(Printer::default())),
         shared_action_manager: Arc::new(Mutex::new(ActionManager::new())),
+        shared_slice_cache: Rc::new(RefCell::new(SliceCache::new())),
+        shared_slice_parameters: Rc::new(RefCell::new(slice_parameters)),
+        shared_parameter_snapshots: Rc::new(RefCell::new(parameter_snapshots)),
+        shared_print_history: Rc::new(RefCell::new(print_history)),
+        shared_material_ledger: Rc::new(RefCell::new(MaterialLedger::load_user_ledger())),
+        shared_usage_stats: Rc::new(RefCell::new(UsageStats::load_user_stats())),
+        shared_export_queue: Rc::new(ExportQueue::new(Arc::clone(&worker_pool))),
+        shared_worker_pool: worker_pool,
         
     };
 
+    // `--software-viewport` draws the 3D view on the CPU even when OpenGL is available
+    let force_software_viewport = std::env::args().any(|arg| arg == "--software-viewport");
+    // Drives the software viewport when the Slint backend can't notify us before rendering
+    let software_viewport_timer = slint::Timer::default();
+    // Island analysis on the GL context of the 3D view, None without compute shaders
+    #[cfg(feature = "gpu-slicer")]
+    let gpu_layer_analyzer: Rc<RefCell<Option<GpuLayerAnalyzer>>> = Rc::new(RefCell::new(None));
     {
+        #[cfg(feature = "gpu-slicer")]
+        let gpu_layer_analyzer = Rc::clone(&gpu_layer_analyzer);
         // Set the rendering notifier with a closure
         // Create a weak reference to the app for use inside the closure
         let app_weak_clone = app_weak.clone(); // Clone app_weak for use inside the closure
@@ -97,17 +737,29 @@ fn main() {
         let bodies_clone = Rc::clone(&state.shared_bodies);
         let shared_printer = Arc::clone(&state.shared_printer);
         let shared_settings = Arc::clone(&state.shared_settings);
+        let display_scale_clone = Rc::clone(&state.display_scale);
+        let frame_clock = Rc::clone(&state.frame_clock);
         if let Err(error) = app.window().set_rendering_notifier({
             // Move clones into the closure
             move |rendering_state, graphics_api| {
                 match rendering_state {
                     slint::RenderingState::RenderingSetup => {
+                        let render_scale = shared_settings.lock().unwrap().renderer.render_scale;
+                        let size = (1000.0 * render_scale) as u32;
                         // Initialize OpenGL context
                         let gl: GlowContext = match graphics_api {
-                            slint::GraphicsAPI::NativeOpenGL { get_proc_address } => unsafe {
+                            slint::GraphicsAPI::NativeOpenGL { get_proc_address }
+                                if !force_software_viewport =>
+                            unsafe {
                                 GlowContext::from_loader_function_cstr(|s| get_proc_address(s))
                             },
-                            _ => panic!("Unsupported Graphics API"),
+                            _ => {
+                                println!("Using the software viewport");
+                                *mesh_renderer_clone.borrow_mut() = Some(Box::new(
+                                    SoftwareRenderer::new(size, size, &bodies_clone, &shared_printer),
+                                ));
+                                return;
+                            }
                         };
                         let gl = Rc::new(gl); // Wrap in Rc
 
@@ -125,70 +777,33 @@ fn main() {
                             "OpenGL Major Version: {}. OpenGL Minor Version: {}",
                             major_version, minor_version
                         );
-                        let render_scale = shared_settings.lock().unwrap().renderer.render_scale;
                         // Initialize renderer and slicers with cloned Rc
                         let renderer = MeshRenderer::new(
                             gl.clone(),
-                            (1000.0 * render_scale) as u32,
-                            (1000.0 * render_scale) as u32,
+                            size,
+                            size,
                             &bodies_clone,
                             &shared_printer.clone(),
                         );
-                        *mesh_renderer_clone.borrow_mut() = Some(renderer);
+                        *mesh_renderer_clone.borrow_mut() = Some(Box::new(renderer));
+                        #[cfg(feature = "gpu-slicer")]
+                        match GpuLayerAnalyzer::new(gl.clone()) {
+                            Ok(analyzer) => *gpu_layer_analyzer.borrow_mut() = Some(analyzer),
+                            Err(e) => println!("Analyzing layers on the CPU: {}", e),
+                        }
                     }
                     slint::RenderingState::BeforeRendering => {
                         // Access the renderer
                         if let Some(renderer) = mesh_renderer_clone.borrow_mut().as_mut() {
                             if let Some(app) = app_weak_clone.upgrade() {
-                                let height = app.get_requested_texture_height() as f32;
-                                let width = app.get_requested_texture_width() as f32;
-                                let renderer_settings = 
-                                &shared_settings.lock().unwrap().renderer;
-                                let render_scale =
-                                renderer_settings.render_scale;
-                                let texture = renderer.render(
-                                    (width * render_scale) as u32,
-                                    (height * render_scale) as u32,
-                                    renderer_settings.visualize_edges,
-                                    renderer_settings.visualize_normals,
+                                sync_display_scale(&app, &display_scale_clone);
+                                update_viewport(
+                                    &app,
+                                    renderer.as_mut(),
+                                    &bodies_clone,
+                                    &shared_settings,
+                                    &frame_clock,
                                 );
-
-                                let mut bodies_ui_vec: Vec<BodyUI> = Vec::new();
-                                let mut num_bodies = 0;
-                                for body in bodies_clone.borrow_mut().iter() {
-                                    if !body.borrow().display_in_ui_list {
-                                        continue;
-                                    }
-                                    num_bodies += 1;
-                                    let b = body.borrow_mut();
-                                    bodies_ui_vec.push(BodyUI {
-                                        enabled: b.enabled,
-                                        name: b.name.clone().into(),
-                                        uuid: b.uuid.clone().to_string().into(),
-                                        visible: b.visible,
-                                        selected: b.selected,
-                                        p_x: b.position.x.to_string().clone().into(),
-                                        p_y: b.position.y.to_string().clone().into(),
-                                        p_z: b.position.z.to_string().clone().into(),
-                                        r_x: b.rotation.i.to_string().clone().into(),
-                                        r_y: b.rotation.j.to_string().clone().into(),
-                                        r_z: b.rotation.k.to_string().clone().into(),
-                                        s_x: b.scale.x.to_string().clone().into(),
-                                        s_y: b.scale.y.to_string().clone().into(),
-                                        s_z: b.scale.z.to_string().clone().into(),
-                                    })
-                                }
-
-                                let bodies_model: Rc<slint::VecModel<BodyUI>> =
-                                    std::rc::Rc::new(slint::VecModel::from(bodies_ui_vec));
-
-                                // Update UI model
-                                app.set_bodies(bodies_model.into());
-                                app.set_num_bodies(num_bodies);
-                                app.set_texture(texture);
-                                app.set_visualize_edges(renderer_settings.visualize_edges);
-                                app.set_visualize_normals(renderer_settings.visualize_normals);
-
                                 app.window().request_redraw();
                             }
                         }
@@ -199,30 +814,223 @@ fn main() {
                     slint::RenderingState::RenderingTeardown => {
                         // Clean up the renderer
                         *mesh_renderer_clone.borrow_mut() = None;
+                        #[cfg(feature = "gpu-slicer")]
+                        {
+                            *gpu_layer_analyzer.borrow_mut() = None;
+                        }
                     }
                     _ => {}
                 }
             }
         }) {
             match error {
-            slint::SetRenderingNotifierError::Unsupported => eprintln!(
-                "This example requires the use of the GL backend. Please run with the environment variable SLINT_BACKEND=GL set."
-            ),
-            _ => unreachable!(),
-        }
-            std::process::exit(1);
+                // Backends without GL, like Slint's software renderer, get the CPU view
+                // refreshed by a timer instead
+                slint::SetRenderingNotifierError::Unsupported => {
+                    println!("No rendering notifier in this backend, using the software viewport");
+                    let renderer = SoftwareRenderer::new(
+                        1000,
+                        1000,
+                        &state.shared_bodies,
+                        &state.shared_printer,
+                    );
+                    *state.shared_mesh_renderer.borrow_mut() = Some(Box::new(renderer));
+                    let mesh_renderer_clone = Rc::clone(&state.shared_mesh_renderer);
+                    let bodies_clone = Rc::clone(&state.shared_bodies);
+                    let shared_settings = Arc::clone(&state.shared_settings);
+                    let display_scale_clone = Rc::clone(&state.display_scale);
+                    let frame_clock = Rc::clone(&state.frame_clock);
+                    let app_weak_clone = app_weak.clone();
+                    software_viewport_timer.start(
+                        slint::TimerMode::Repeated,
+                        std::time::Duration::from_millis(50),
+                        move || {
+                            if let (Some(renderer), Some(app)) = (
+                                mesh_renderer_clone.borrow_mut().as_mut(),
+                                app_weak_clone.upgrade(),
+                            ) {
+                                sync_display_scale(&app, &display_scale_clone);
+                                update_viewport(
+                                    &app,
+                                    renderer.as_mut(),
+                                    &bodies_clone,
+                                    &shared_settings,
+                                    &frame_clock,
+                                );
+                            }
+                        },
+                    );
+                }
+                _ => unreachable!(),
+            }
         }
     }
 
+    // Layer preview slider
+    let preview_height = Rc::new(Cell::new(0.0f32));
+    let layer_outlines = Rc::new(RefCell::new(RgbImage::new(1, 1)));
+    let layer_ruler = Rc::new(RefCell::new(LayerRuler::default()));
+    // The last island analysis with the slicing input it was made from
+    let layer_analysis: Rc<RefCell<Option<(SliceSnapshot, LayerAnalysis)>>> =
+        Rc::new(RefCell::new(None));
+    {
+        let mesh_renderer_clone = Rc::clone(&state.shared_mesh_renderer);
+        let layer_analysis = Rc::clone(&layer_analysis);
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let preview_height = Rc::clone(&preview_height);
+        let layer_outlines_clone = Rc::clone(&layer_outlines);
+        let layer_ruler_clone = Rc::clone(&layer_ruler);
+        let app_weak_clone = app_weak.clone();
+        app.set_layer_preview_max(state.shared_printer.lock().unwrap().physical_z as f32);
+        app.on_layer_preview_changed(move |height| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            preview_height.set(height);
+            if let Some(renderer) = mesh_renderer_clone.borrow_mut().as_mut() {
+                renderer.set_slice_preview_height((height > 0.0).then_some(height));
+            }
+            app.set_layer_lcd_preview_visible(height > 0.0);
+            if height <= 0.0 {
+                app.set_cursor_layer(SharedString::new());
+                // Measurements last while moving between layers, not past closing the preview
+                layer_ruler_clone.borrow_mut().clear();
+            }
+            if height > 0.0 {
+                let parameters = slice_parameters.borrow();
+                let bodies = bodies_clone.borrow();
+                let bodies: Vec<_> = bodies.iter().map(|b| b.borrow()).collect();
+
+                // Show the layer the way the LCD will, mirrored or rotated as the printer needs
+                let contours = CPUSlicer::layer_contours(
+                    bodies
+                        .iter()
+                        .map(|b| &**b)
+                        .filter(|b| b.display_in_ui_list && b.visible),
+                    height as f64,
+                );
+                let outlines = CPUSlicer::lcd_outline_preview(&contours, &parameters.printer, 240);
+                let mut outlines = image::DynamicImage::ImageLuma8(outlines).to_rgb8();
+                let layer = parameters.layer_at_height(
+                    bodies.iter().map(|b| &**b).filter(|b| b.display_in_ui_list),
+                    height as f64,
+                );
+
+                // Frame the islands of the layer, unless the scene changed since the analysis
+                if let (Some(layer), Some((snapshot, analysis))) =
+                    (layer, layer_analysis.borrow().as_ref())
+                {
+                    let current = SliceSnapshot::capture(
+                        bodies.iter().map(|b| &**b),
+                        &parameters.layer_thickness(),
+                        &parameters.printer,
+                        parameters.supersampling,
+&parameters.resin,
+                    );
+                    if current == *snapshot {
+                        analysis.mark_islands(layer, &mut outlines, &parameters.printer);
+                    }
+                }
+                *layer_outlines_clone.borrow_mut() = outlines;
+                show_layer_lcd_preview(
+                    &app,
+                    &layer_outlines_clone.borrow(),
+                    &layer_ruler_clone.borrow(),
+                    &parameters.printer,
+                );
+
+                // Flag the pauses and commands of the previewed layer
+                if let Some(layer) = layer {
+                    let scripts: Vec<String> =
+                        parameters.scripts_at(layer).map(|s| s.describe()).collect();
+                    if !scripts.is_empty() {
+                        show_notification(&app_weak_clone, scripts.join("; "), false);
+                    }
+                }
+
+                // Point at the debug dump of the previewed layer if it had problems
+                let debugger = slice_debugger::global();
+                if debugger.is_enabled() {
+                    let half_layer = parameters.slice_thickness / 2.0;
+                    if let Some(report) = debugger.report_near(height as f64, half_layer) {
+                        show_notification(&app_weak_clone, report.summary(), true);
+                    }
+                }
+            }
+            app.window().request_redraw();
+        });
+    }
+
+    // Measuring on the 2D layer preview, and the stats of the blob clicked on
+    {
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_cache = Rc::clone(&state.shared_slice_cache);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let preview_height = Rc::clone(&preview_height);
+        let app_weak_clone = app_weak.clone();
+        app.on_layer_preview_clicked(move |x_fraction, y_fraction| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let parameters = slice_parameters.borrow();
+            let printer = &parameters.printer;
+            layer_ruler
+                .borrow_mut()
+                .click(x_fraction as f64, y_fraction as f64, printer);
+            show_layer_lcd_preview(&app, &layer_outlines.borrow(), &layer_ruler.borrow(), printer);
+
+            // Blobs are found in the layers of the last slice, as long as they match the scene
+            let bodies = bodies_clone.borrow();
+            let bodies: Vec<_> = bodies.iter().map(|b| b.borrow()).collect();
+            let snapshot = SliceSnapshot::capture(
+                bodies.iter().map(|b| &**b),
+                &parameters.layer_thickness(),
+                printer,
+                parameters.supersampling,
+&parameters.resin,
+            );
+            let layer = parameters.layer_at_height(
+                bodies.iter().map(|b| &**b).filter(|b| b.display_in_ui_list),
+                preview_height.get() as f64,
+            );
+            let Some(layers) = slice_cache.borrow().get(&snapshot) else {
+                return;
+            };
+            let Some(image) = layer.and_then(|layer| layers.get(layer)) else {
+                return;
+            };
+            let blobs = ComponentMap::of_layer(image);
+            let x = (x_fraction * image.width() as f32) as u32;
+            let y = (y_fraction * image.height() as f32) as u32;
+            if let Some(index) = blobs.index_at(x, y) {
+                // Framed until the next click or layer
+                let blob = &blobs.components[index];
+                let mut selected = layer_outlines.borrow().clone();
+                blob.frame(&mut selected, printer, SELECTED_COLOR);
+                show_layer_lcd_preview(&app, &selected, &layer_ruler.borrow(), printer);
+                let message = format!(
+                    "Blob {} of {} on layer {}: {}",
+                    index + 1,
+                    blobs.components.len(),
+                    layer.unwrap_or_default(),
+                    blob.describe(printer)
+                );
+                show_notification(&app_weak_clone, message, false);
+            }
+        });
+    }
+
     // Handler for scrollwheel zooming TODO: Consider renaming for clarity
     {
         let app_weak_clone = app_weak.clone(); // Clone app_weak again for this closure
         let mesh_renderer_clone = Rc::clone(&state.shared_mesh_renderer); // Clone mesh_renderer for this closure
+        let display_scale_clone = Rc::clone(&state.display_scale);
         app.on_zoom(move |amt| {
             // Access the renderer
             if let Some(renderer) = mesh_renderer_clone.borrow_mut().as_mut() {
                 // Move the camera
-                renderer.zoom(amt);
+                renderer.zoom(display_scale_clone.borrow().pointer_delta(amt));
 
                 // Trigger a redraw
                 if let Some(app) = app_weak_clone.upgrade() {
@@ -232,11 +1040,19 @@ fn main() {
         });
     }
 
+    // Body being dragged across the plate, shared by the mouse handlers
+    let plate_drag: Rc<RefCell<Option<PlateDrag>>> = Rc::new(RefCell::new(None));
+
     // Handler for mouse movement in renderer
     {
+        let plate_drag = Rc::clone(&plate_drag);
+        let bodies = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let preview_height = Rc::clone(&preview_height);
         let app_weak_clone = app_weak.clone(); // Clone app_weak again for this closure
         let mesh_renderer_clone = Rc::clone(&state.shared_mesh_renderer); // Clone mesh_renderer for this closure
         let mouse_state_clone = Rc::clone(&state.mouse_state);
+        let display_scale_clone = Rc::clone(&state.display_scale);
         app.on_mouse_move_renderer(move |x, y| {
             debug!("On mouse move event received");
 
@@ -264,6 +1080,33 @@ fn main() {
 
             // Access the renderer
             if let Some(renderer) = mesh_renderer_clone.borrow_mut().as_mut() {
+                let display_scale = display_scale_clone.borrow();
+                if let (Some(drag), Some(app)) =
+                    (plate_drag.borrow_mut().as_mut(), app_weak_clone.upgrade())
+                {
+                    if let Some((origin, direction)) =
+                        pick_ray(&app, renderer.as_ref(), &display_scale, x, y)
+                    {
+                        drag.update(origin, direction);
+                    }
+                }
+                // The layer under the pointer while the layer preview cuts through the scene
+                if let Some(app) = app_weak_clone.upgrade() {
+                    let layer = (preview_height.get() > 0.0 && !mouse_state.left_pressed)
+                        .then(|| pick_ray(&app, renderer.as_ref(), &display_scale, x, y))
+                        .flatten()
+                        .and_then(|(origin, direction)| {
+                            layer_under_pointer(
+                                &bodies.borrow(),
+                                &slice_parameters.borrow(),
+                                origin,
+                                direction,
+                            )
+                        });
+                    app.set_cursor_layer(layer.unwrap_or_default().into());
+                }
+                let delta_x = display_scale.pointer_delta(delta_x);
+                let delta_y = display_scale.pointer_delta(delta_y);
                 if mouse_state.left_pressed {
                     renderer.camera_pitch_yaw(delta_x, delta_y);
                 }
@@ -281,9 +1124,48 @@ fn main() {
     // Mouse down handler for renderer
     {
         let mouse_state_clone = Rc::clone(&state.mouse_state);
+        let mesh_renderer_clone = Rc::clone(&state.shared_mesh_renderer);
+        let display_scale_clone = Rc::clone(&state.display_scale);
+        let bodies = Rc::clone(&state.shared_bodies);
+        let settings = Arc::clone(&state.shared_settings);
+        let plate_drag = Rc::clone(&plate_drag);
+        let app_weak_clone = app_weak.clone();
         app.on_mouse_down_renderer(move |button| {
             debug!("On mouse down received");
             let mut mouse_state = mouse_state_clone.borrow_mut();
+            // Clicking an axis of the orientation gizmo snaps the view instead of orbiting
+            if button == PointerEventButton::Left {
+                if let (Some(renderer), Some(app)) = (
+                    mesh_renderer_clone.borrow_mut().as_mut(),
+                    app_weak_clone.upgrade(),
+                ) {
+                    // Hit tested in pixels of the rendered view, like the gizmo is drawn
+                    let display_scale = display_scale_clone.borrow();
+                    let x = display_scale.to_physical(mouse_state.x);
+                    let y = display_scale.to_physical(mouse_state.y);
+                    let width = app.get_requested_texture_width() as f32;
+                    let height = app.get_requested_texture_height() as f32;
+                    if renderer.snap_to_gizmo_axis(x, y, width, height) {
+                        app.window().request_redraw();
+                        return;
+                    }
+                    // Pressing a body grabs it, pressing empty space orbits
+                    if let Some((origin, direction)) = pick_ray(
+                        &app,
+                        renderer.as_ref(),
+                        &display_scale,
+                        mouse_state.x,
+                        mouse_state.y,
+                    ) {
+                        let gap = settings.lock().unwrap().layout.gap;
+                        let drag = PlateDrag::begin(&bodies.borrow(), origin, direction, gap);
+                        if drag.is_some() {
+                            *plate_drag.borrow_mut() = drag;
+                            return;
+                        }
+                    }
+                }
+            }
             match button {
                 PointerEventButton::Left => mouse_state.left_pressed = true,
                 PointerEventButton::Other => mouse_state.other_pressed = true,
@@ -298,8 +1180,32 @@ fn main() {
     // Mouse up handler for renderer
     {
         let mouse_state_clone = Rc::clone(&state.mouse_state);
+        let settings = Arc::clone(&state.shared_settings);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
         app.on_mouse_up_renderer(move |button| {
             debug!("On mouse up received");
+            if button == PointerEventButton::Left {
+                if let Some(mut drag) = plate_drag.borrow_mut().take() {
+                    if settings.lock().unwrap().layout.slide_to_free_space {
+                        drag.slide_to_free_space();
+                    }
+                    let overlapping = drag.overlapping();
+                    if !overlapping.is_empty() {
+                        show_notification(
+                            &app_weak_clone,
+                            format!("Dropped overlapping {}", overlapping.join(", ")),
+                            true,
+                        );
+                    }
+                    if let Some(action) = drag.finish() {
+                        action_manager.lock().unwrap().execute(Box::new(action));
+                    }
+                    if let Some(app) = app_weak_clone.upgrade() {
+                        app.window().request_redraw();
+                    }
+                }
+            }
             let mut mouse_state = mouse_state_clone.borrow_mut();
             match button {
                 PointerEventButton::Left => mouse_state.left_pressed = false,
@@ -313,154 +1219,427 @@ fn main() {
         });
     }
 
-    async fn open_files_from_dialog(bodies_clone: &SharedBodies) {
-        // Handling the option prevents crashes
-        if let Some(paths) = AsyncFileDialog::new()
-            .add_filter("stl", &["stl", "STL"])
-            .set_directory("~")
-            .pick_files()
-            .await
-        {
-            let stl_processor = StlProcessor::new();
-            let mut bodies_vec: Vec<Rc<RefCell<Body>>> = Vec::new();
-
-            for path in paths {
-                let body = Rc::new(RefCell::new(Body::new_from_stl(
-                    path.path().as_os_str(),
-                    &stl_processor,
-                )));
-                bodies_vec.push(Rc::clone(&body));
-                println!("Loaded body: {}", path.file_name());
-            }
-            bodies_clone.borrow_mut().append(&mut bodies_vec);
-        } else {
-            println!("File picker returned no files");
+    // Returns the model files picked in the file dialog, none when it was cancelled
+    async fn pick_model_files() -> Vec<PathBuf> {
+        let mut dialog = AsyncFileDialog::new().add_filter("models", &["stl", "STL", "3mf", "3MF"]);
+        if let Some(home) = dirs_next::home_dir() {
+            dialog = dialog.set_directory(home);
+        }
+        // Cancelling the dialog returns None, which is not an error
+        match dialog.pick_files().await {
+            Some(files) => files.iter().map(|file| file.path().to_path_buf()).collect(),
+            None => {
+                println!("File picker returned no files");
+                Vec::new()
+            }
+        }
+    }
+
+    // Returns the bodies that were imported, placed the way the import settings say
+    fn import_files(
+        paths: &[PathBuf],
+        bodies_clone: &SharedBodies,
+        app_weak: &slint::Weak<App>,
+        import_settings: &ImportSettings,
+    ) -> ImportedBatch {
+        let stl_processor = StlProcessor::new();
+        let mesh_cache = MeshCache::in_user_cache_dir();
+        let mut batch = ImportedBatch::default();
+        let mut failures: Vec<String> = Vec::new();
+        let mut open_bottoms: Vec<String> = Vec::new();
+
+        for path in paths {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            // Skip files that would exhaust memory instead of crashing mid-import
+            if let Ok(required) = memory_budget::estimate_import_bytes_for_path(path) {
+                if let BudgetCheck::Exceeds { available, .. } =
+                    memory_budget::check(required, memory_budget::available_memory())
+                {
+                    failures.push(format!(
+                        "{} needs about {} but only {} is available",
+                        file_name,
+                        memory_budget::format_bytes(required),
+                        memory_budget::format_bytes(available)
+                    ));
+                    continue;
+                }
+            }
+            // A 3MF keeps the arrangement of the slicer that wrote it
+            let is_3mf = path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("3mf"));
+            let bodies = if is_3mf {
+                three_mf::import(path).map_err(|e| e.to_string())
+            } else {
+                match &mesh_cache {
+                    Some(cache) => {
+                        Body::new_from_stl_cached(path.as_os_str(), &stl_processor, cache)
+                    }
+                    None => Body::new_from_stl(path.as_os_str(), &stl_processor),
+                }
+                .map(|body| vec![body])
+                .map_err(|e| e.to_string())
+            };
+            match bodies {
+                Ok(bodies) => {
+                    let bodies: Vec<_> = bodies
+                        .into_iter()
+                        .map(|body| Rc::new(RefCell::new(body)))
+                        .collect();
+                    batch.add(&bodies, is_3mf);
+                    println!("Loaded body: {}", file_name);
+                }
+                Err(e) => failures.push(format!("{}: {}", file_name, e)),
+            }
+        }
+        batch.orient(import_settings.up_axis, import_settings.keep_arrangement);
+        let bodies_vec = batch.bodies();
+        for body in &bodies_vec {
+            let body = body.borrow();
+            report_plugin_findings(&plugin::registry().on_import(&body));
+            if let Some(open) = OpenBottom::of(&body) {
+                open_bottoms.push(open.describe(&body.name));
+            }
+        }
+        bodies_clone.borrow_mut().extend(bodies_vec.iter().cloned());
+        if !failures.is_empty() {
+            show_notification(
+                app_weak,
+                format!("Could not import {}", failures.join(", ")),
+                true,
+            );
+        } else if !open_bottoms.is_empty() {
+            let message = format!(
+                "{}. SEAL OPEN BOTTOMS caps the selected bodies.",
+                open_bottoms.join(". ")
+            );
+            show_notification(app_weak, message, false);
         }
+        batch
     }
 
+    #[cfg(feature = "scripting")]
+    let script_console = Rc::new(RefCell::new(ScriptConsole::new(&state.shared_bodies)));
+    // The bodies of the last import, which the orientation dialog places again
+    let last_import = Rc::new(RefCell::new(ImportedBatch::default()));
+
+    // Imports model files, asks for their orientation if the import settings say so and
+    // runs the import macro on them. Returns the imported bodies.
+    let import_models: ImportModels = {
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        #[cfg(feature = "scripting")]
+        let console = Rc::clone(&script_console);
+        let last_import = Rc::clone(&last_import);
+        #[cfg(feature = "scripting")]
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak = app_weak.clone();
+        Rc::new(move |paths| {
+            let import_settings = shared_settings.lock().unwrap().import.clone();
+            let batch = import_files(paths, &bodies_clone, &app_weak, &import_settings);
+            let imported = batch.bodies();
+            if import_settings.confirm_orientation && !batch.is_empty() {
+                if let Some(app) = app_weak.upgrade() {
+                    app.invoke_show_import_orientation(
+                        batch.len() as i32,
+                        import_settings.up_axis.index(),
+                        batch.has_arrangement(),
+                        import_settings.keep_arrangement,
+                    );
+                }
+            }
+            *last_import.borrow_mut() = batch;
+            #[cfg(feature = "scripting")]
+            let import_macro = shared_settings.lock().unwrap().scripting.import_macro.clone();
+            #[cfg(feature = "scripting")]
+            if let (Some(name), false) = (import_macro, imported.is_empty()) {
+                let source = scripting::macros_dir()
+                    .map_err(|e| e.to_string())
+                    .and_then(|dir| scripting::load_macro(&dir, &name).map_err(|e| e.to_string()));
+                match source {
+                    Ok(source) => {
+                        let run = console.borrow_mut().run_on(&source, &imported);
+                        finish_script_run(&app_weak, &action_manager, run);
+                    }
+                    Err(e) => show_notification(
+                        &app_weak,
+                        format!("Could not load import macro {}: {}", name, e),
+                        true,
+                    ),
+                }
+            }
+            imported
+        })
+    };
+
     // Handler for opening STL importer file picker
     {
-        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let import_models = Rc::clone(&import_models);
         app.on_click_import_stl(move || {
-            let bc_clone = Rc::clone(&bodies_clone);
+            let import_models = Rc::clone(&import_models);
             let slint_future = async move {
-                open_files_from_dialog(&bc_clone).await;
+                let paths = pick_model_files().await;
+                import_models(&paths);
             };
             slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
         });
-    }
 
-    // Handlers for objectlistitem editing
-    {
-        let bodies_clone = Rc::clone(&state.shared_bodies);
-        let action_manager = Arc::clone(&state.shared_action_manager);
-        app.on_body_position_edited_single_axis(
-            move |uuid: slint::SharedString, amt: f32, axis: i32| {
-                let bodies = bodies_clone.borrow();
+        // Places the last import again with the up axis and arrangement picked in the dialog
+        let app_weak_clone = app_weak.clone();
+        app.on_import_orientation_chosen(move |up_axis, keep_arrangement| {
+            last_import
+                .borrow()
+                .orient(UpAxis::from_index(up_axis), keep_arrangement);
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.window().request_redraw();
+            }
+        });
+    }
 
-                // Find the body to modify
-                if let Some(body_rc) = bodies
-                    .iter()
-                    .find(|body_rc| body_rc.borrow().eq_uuid_ss(&uuid))
-                {
-                    // Determine the new position vector
-                    let new_position = {
-                        let body = body_rc.borrow(); // Immutable borrow to access position
-                        match axis {
-                            0 => Vector3::new(amt, body.position.y, body.position.z),
-                            1 => Vector3::new(body.position.x, amt, body.position.z),
-                            2 => Vector3::new(body.position.x, body.position.y, amt),
-                            _ => Vector3::default(),
-                        }
-                    }; // Dropping `body` here after usage
-
-                    // Set up the action
-                    let previous_position = body_rc.borrow().position;
-                    let action = SetPositionAction {
-                        body: body_rc.clone(),
-                        input: new_position,
-                        previous: previous_position,
-                    };
+    // Scans the library folder and lists its models right away, then renders the thumbnails
+    // missing from the cache on the worker pool
+    async fn load_model_library(
+        dir: PathBuf,
+        library_panel: SharedLibraryPanel,
+        worker_pool: SharedWorkerPool,
+        app_weak: slint::Weak<App>,
+    ) {
+        let set_status = |status: String| {
+            if let Some(app) = app_weak.upgrade() {
+                app.set_library_status(status.into());
+            }
+        };
+        if let Some(app) = app_weak.upgrade() {
+            app.set_library_folder(dir.display().to_string().into());
+        }
+        set_status(format!("Reading {}", dir.display()));
+        let scan_dir = dir.clone();
+        let library = match task::spawn_blocking(move || ModelLibrary::scan(&scan_dir)).await {
+            Ok(Ok(library)) => library,
+            Ok(Err(e)) => return set_status(format!("Could not read {}: {}", dir.display(), e)),
+            Err(e) => return set_status(format!("Thread join error: {}", e)),
+        };
+        let entries = library.entries().to_vec();
+        library_panel.borrow_mut().library = Some(library);
+        if let Some(app) = app_weak.upgrade() {
+            library_panel.borrow().show(&app);
+        }
+        set_status(format!("{} models, rendering thumbnails", entries.len()));
 
-                    // Execute the action via ActionManager
-                    let mut manager = action_manager.lock().unwrap();
-                    manager.execute(Box::new(action));
+        let rendered = task::spawn_blocking(move || {
+            let thumbnail_cache = ThumbnailCache::in_user_cache_dir();
+            let mesh_cache = MeshCache::in_user_cache_dir();
+            worker_pool.install(|| {
+                entries
+                    .par_iter()
+                    .map(|entry| {
+                        let thumbnail = match &thumbnail_cache {
+                            Some(cache) => cache.get_or_render(entry, mesh_cache.as_ref()),
+                            None => entry.load_bodies(mesh_cache.as_ref()).map(|bodies| {
+                                SoftwareRenderer::thumbnail(bodies, model_library::THUMBNAIL_SIZE)
+                            }),
+                        };
+                        (entry.path.clone(), thumbnail)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .await;
+        let Ok(rendered) = rendered else {
+            return set_status("Could not render the thumbnails".to_string());
+        };
+        let mut failures = 0;
+        let mut panel = library_panel.borrow_mut();
+        for (path, thumbnail) in rendered {
+            match thumbnail {
+                Ok(thumbnail) => {
+                    let image =
+                        slint::Image::from_rgb8(slint::SharedPixelBuffer::clone_from_slice(
+                            thumbnail.as_raw(),
+                            thumbnail.width(),
+                            thumbnail.height(),
+                        ));
+                    panel.thumbnails.insert(path, image);
                 }
-            },
-        );
+                Err(e) => {
+                    println!("No thumbnail for {}: {}", path.display(), e);
+                    failures += 1;
+                }
+            }
+        }
+        let count = panel
+            .library
+            .as_ref()
+            .map_or(0, |library| library.entries().len());
+        if let Some(app) = app_weak.upgrade() {
+            panel.show(&app);
+        }
+        set_status(match failures {
+            0 => format!("{} models", count),
+            _ => format!("{} models, {} could not be read", count, failures),
+        });
+    }
 
-        let bodies_clone = Rc::clone(&state.shared_bodies);
-        let action_manager = Arc::clone(&state.shared_action_manager);
-        app.on_body_rotation_edited_single_axis(
-            move |uuid: slint::SharedString, amt: f32, axis: i32| {
-                let bodies = bodies_clone.borrow();
+    // Handlers for the model library panel
+    {
+        let library_panel: SharedLibraryPanel = Rc::new(RefCell::new(LibraryPanel::default()));
+        let library_dir = state
+            .shared_settings
+            .lock()
+            .unwrap()
+            .paths
+            .library_dir
+            .clone();
+        if let Some(dir) = library_dir {
+            let slint_future = load_model_library(
+                dir,
+                Rc::clone(&library_panel),
+                Arc::clone(&state.shared_worker_pool),
+                app_weak.clone(),
+            );
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        }
 
-                // Find the body to modify
-                if let Some(body_rc) = bodies
-                    .iter()
-                    .find(|body_rc| body_rc.borrow().eq_uuid_ss(&uuid))
-                {
-                    // Calculate new rotation vector without holding mutable borrow
-                    let new_rotation = {
-                        let body = body_rc.borrow(); // Immutable borrow for accessing rotation
-                        let rotation = Body::quaternion_to_euler(&body.rotation);
-                        match axis {
-                            0 => Vector3::new(amt, rotation.y, rotation.z),
-                            1 => Vector3::new(rotation.x, amt, rotation.z),
-                            2 => Vector3::new(rotation.x, rotation.y, amt),
-                            _ => Vector3::default(),
-                        }
-                    }; // Borrow ends here
+        let panel = Rc::clone(&library_panel);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let worker_pool = Arc::clone(&state.shared_worker_pool);
+        let app_weak_clone = app_weak.clone();
+        app.on_choose_library_folder(move || {
+            let panel = Rc::clone(&panel);
+            let shared_settings = Arc::clone(&shared_settings);
+            let worker_pool = Arc::clone(&worker_pool);
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let Some(folder) = AsyncFileDialog::new().pick_folder().await else {
+                    return;
+                };
+                let dir = folder.path().to_path_buf();
+                let library_dir = Some(dir.clone());
+                if let Err(e) = Settings::update_user_settings(&shared_settings, |settings| {
+                    settings.paths.library_dir = library_dir
+                }) {
+                    error!("Error when updating user settings: {:?}", e);
+                }
+                load_model_library(dir, panel, worker_pool, app_weak).await;
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
 
-                    // Set up the action with non-overlapping borrows
-                    let action = SetRotationAction {
-                        body: body_rc.clone(),
-                        input: new_rotation,
-                        previous: body_rc.borrow().rotation, // Immutable borrow for previous value
-                    };
+        let panel = Rc::clone(&library_panel);
+        let worker_pool = Arc::clone(&state.shared_worker_pool);
+        let app_weak_clone = app_weak.clone();
+        app.on_rescan_library(move || {
+            let dir = panel
+                .borrow()
+                .library
+                .as_ref()
+                .map(|library| library.dir().to_path_buf());
+            if let Some(dir) = dir {
+                let slint_future = load_model_library(
+                    dir,
+                    Rc::clone(&panel),
+                    Arc::clone(&worker_pool),
+                    app_weak_clone.clone(),
+                );
+                slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+            }
+        });
 
-                    // Execute the action via ActionManager
-                    let mut manager = action_manager.lock().unwrap();
-                    manager.execute(Box::new(action));
+        let panel = Rc::clone(&library_panel);
+        let app_weak_clone = app_weak.clone();
+        app.on_library_search_changed(move |query| {
+            let mut panel = panel.borrow_mut();
+            panel.query = query.to_string();
+            if let Some(app) = app_weak_clone.upgrade() {
+                panel.show(&app);
+            }
+        });
+
+        // Dropped models land under the pointer, clicked ones where imports go
+        let import_models = Rc::clone(&import_models);
+        let mesh_renderer = Rc::clone(&state.shared_mesh_renderer);
+        let display_scale = Rc::clone(&state.display_scale);
+        let app_weak_clone = app_weak.clone();
+        app.on_insert_library_model(move |path, dropped, x, y| {
+            let imported = import_models(&[PathBuf::from(path.as_str())]);
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            if dropped {
+                if let Some(renderer) = mesh_renderer.borrow().as_ref() {
+                    let ray = pick_ray(&app, renderer.as_ref(), &display_scale.borrow(), x, y);
+                    if let Some(point) = ray.and_then(|(o, d)| model_library::plate_point(o, d)) {
+                        model_library::center_on(&imported, point);
+                    }
                 }
-            },
-        );
+            }
+            app.window().request_redraw();
+        });
+    }
 
-        let bodies_clone = Rc::clone(&state.shared_bodies);
-        let action_manager = Arc::clone(&state.shared_action_manager);
-        app.on_body_scale_edited_single_axis(
+    // Handlers for objectlistitem editing
+    {
+        // A typed value for one axis of a body's position, rotation or scale
+        let edited_single_axis = |field: TransformField| {
+            let bodies_clone = Rc::clone(&state.shared_bodies);
+            let action_manager = Arc::clone(&state.shared_action_manager);
             move |uuid: slint::SharedString, amt: f32, axis: i32| {
+                let (Some(value), 0..=2) = (field.clamp(amt), axis) else {
+                    return;
+                };
                 let bodies = bodies_clone.borrow();
-
-                // Find the body to modify
                 if let Some(body_rc) = bodies
                     .iter()
                     .find(|body_rc| body_rc.borrow().eq_uuid_ss(&uuid))
                 {
-                    // Calculate new scale vector without holding mutable borrow
-                    let new_scale = {
-                        let body = body_rc.borrow(); // Immutable borrow for accessing scale
-                        match axis {
-                            0 => Vector3::new(amt, body.scale.y, body.scale.z),
-                            1 => Vector3::new(body.scale.x, amt, body.scale.z),
-                            2 => Vector3::new(body.scale.x, body.scale.y, amt),
-                            _ => Vector3::default(),
-                        }
-                    }; // Borrow ends here
-
-                    // Set up the action with non-overlapping borrows
-                    let action = SetScaleAction {
-                        body: body_rc.clone(),
-                        input: new_scale,
-                        previous: body_rc.borrow().scale, // Immutable borrow for previous value
-                    };
-
-                    // Execute the action via ActionManager
-                    let mut manager = action_manager.lock().unwrap();
-                    manager.execute(Box::new(action));
+                    let action = field.action(body_rc, axis as usize, value);
+                    action_manager.lock().unwrap().execute(action);
                 }
-            },
-        );
+            }
+        };
+        app.on_body_position_edited_single_axis(edited_single_axis(TransformField::Position));
+        app.on_body_rotation_edited_single_axis(edited_single_axis(TransformField::Rotation));
+        app.on_body_scale_edited_single_axis(edited_single_axis(TransformField::Scale));
+
+        // Dragging a stepper moves the body right away and adds one undo entry on release
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let scrub: Rc<RefCell<Option<Scrub>>> = Rc::new(RefCell::new(None));
+        app.on_body_transform_scrubbed(move |uuid, field, axis, value, finished| {
+            let (Some(field), 0..=2) = (TransformField::from_index(field), axis) else {
+                return;
+            };
+            let axis = axis as usize;
+            let Some(value) = field.clamp(value) else {
+                return;
+            };
+            let bodies = bodies_clone.borrow();
+            let Some(body_rc) = bodies
+                .iter()
+                .find(|body_rc| body_rc.borrow().eq_uuid_ss(&uuid))
+            else {
+                return;
+            };
+            let mut scrub = scrub.borrow_mut();
+            if !scrub
+                .as_ref()
+                .is_some_and(|scrub| scrub.is_for(body_rc, field, axis))
+            {
+                // A drag whose release never arrived still gets its undo entry
+                if let Some(action) = scrub.take().and_then(Scrub::finish) {
+                    action_manager.lock().unwrap().execute(action);
+                }
+                *scrub = Some(Scrub::begin(body_rc, field, axis));
+            }
+            if let Some(current) = scrub.as_mut() {
+                current.update(value);
+            }
+            if finished {
+                if let Some(action) = scrub.take().and_then(Scrub::finish) {
+                    action_manager.lock().unwrap().execute(action);
+                }
+            }
+        });
 
         let bodies_clone = Rc::clone(&state.shared_bodies);
         app.on_toggle_body_selected(move |uuid| {
@@ -471,39 +1650,193 @@ fn main() {
                 }
             }
         });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        app.on_toggle_body_slice_role(move |uuid| {
+            for body_rc in bodies_clone.borrow().iter() {
+                let mut body = body_rc.borrow_mut();
+                if body.eq_uuid_ss(&uuid) {
+                    body.slice_role = body.slice_role.toggled();
+                }
+            }
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        app.on_batch_transform_selected(move |px, py, pz, rx, ry, rz, sx, sy, sz| {
+            let selected: Vec<Rc<RefCell<Body>>> = bodies_clone
+                .borrow()
+                .iter()
+                .filter(|b| b.borrow().selected)
+                .cloned()
+                .collect();
+            if selected.is_empty() {
+                return;
+            }
+
+            // Scale is entered as a percentage, e.g. 101.5 to compensate for resin shrinkage
+            let transform = BatchTransform {
+                translation: Vector3::new(px, py, pz),
+                rotation: Vector3::new(rx, ry, rz),
+                scale: Vector3::new(sx, sy, sz) / 100.0,
+            };
+            let action = transform.to_action(&selected);
+            action_manager.lock().unwrap().execute(Box::new(action));
+        });
+
+        // Quick orientations between laying a model flat and turning it by hand
+        let presets: Vec<SharedString> = state
+            .shared_settings
+            .lock()
+            .unwrap()
+            .orientation
+            .presets
+            .iter()
+            .map(|preset| SharedString::from(&preset.name))
+            .collect();
+        app.set_orientation_presets(Rc::new(slint::VecModel::from(presets)).into());
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        app.on_apply_orientation_preset(move |index| {
+            let settings = shared_settings.lock().unwrap();
+            let Some(preset) = usize::try_from(index)
+                .ok()
+                .and_then(|index| settings.orientation.presets.get(index))
+            else {
+                return;
+            };
+            let selected: Vec<Rc<RefCell<Body>>> = bodies_clone
+                .borrow()
+                .iter()
+                .filter(|b| b.borrow().selected && b.borrow().display_in_ui_list)
+                .cloned()
+                .collect();
+            if selected.is_empty() {
+                return;
+            }
+            let action = preset.to_action(&selected);
+            action_manager.lock().unwrap().execute(Box::new(action));
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_circular_array_selected(move |cx, cy, count| {
+            let selected: Vec<Rc<RefCell<Body>>> = bodies_clone
+                .borrow()
+                .iter()
+                .filter(|b| b.borrow().selected)
+                .cloned()
+                .collect();
+            if selected.is_empty() {
+                return;
+            }
+            if count < 2 {
+                show_notification(
+                    &app_weak_clone,
+                    "A circular array needs at least 2 parts".to_string(),
+                    true,
+                );
+                return;
+            }
+
+            // Copies are full bodies of their own, so each one can be supported and
+            // edited separately
+            let array = CircularArray {
+                center: Vector3::new(cx, cy, 0.0),
+                count: count as usize,
+            };
+            let action = array.to_action(&bodies_clone, &selected);
+            action_manager.lock().unwrap().execute(Box::new(action));
+        });
+    }
+
+    // Align, distribute and snap the selected bodies, each as one undoable step
+    {
+        let selected = {
+            let bodies_clone = Rc::clone(&state.shared_bodies);
+            move || -> Vec<Rc<RefCell<Body>>> {
+                bodies_clone
+                    .borrow()
+                    .iter()
+                    .filter(|b| b.borrow().selected && b.borrow().display_in_ui_list)
+                    .cloned()
+                    .collect()
+            }
+        };
+        let apply = {
+            let action_manager = Arc::clone(&state.shared_action_manager);
+            let app_weak = app_weak.clone();
+            move |action: AlignmentAction, minimum: usize, selected: usize| {
+                if selected < minimum {
+                    show_notification(
+                        &app_weak,
+                        format!("Select at least {} bodies", minimum),
+                        false,
+                    );
+                } else if !action.is_empty() {
+                    action_manager.lock().unwrap().execute(Box::new(action));
+                }
+            }
+        };
+
+        let (selected_clone, apply_clone) = (selected.clone(), apply.clone());
+        app.on_align_selected(move |axis, side| {
+            let (Some(side), 0..=2) = (Alignment::from_index(side), axis) else {
+                return;
+            };
+            let bodies = selected_clone();
+            apply_clone(alignment::align(&bodies, axis as usize, side), 2, bodies.len());
+        });
+
+        let (selected_clone, apply_clone) = (selected.clone(), apply.clone());
+        app.on_distribute_selected(move |axis| {
+            let bodies = selected_clone();
+            if (0..=2).contains(&axis) {
+                apply_clone(alignment::distribute(&bodies, axis as usize), 3, bodies.len());
+            }
+        });
+
+        app.on_snap_selected(move |axis, gap| {
+            let bodies = selected();
+            if (0..=2).contains(&axis) && gap.is_finite() {
+                apply(alignment::snap_adjacent(&bodies, axis as usize, gap), 2, bodies.len());
+            }
+        });
     }
 
     async fn slice_all_bodies(
         bodies_clone: SharedBodies,
-    ) -> Result<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>, CPUSlicerError> {
+        pipeline: SlicingPipeline,
+        parameters: SliceParameters,
+        profile: Option<String>,
+        output_base: PathBuf,
+    ) -> Result<String, CPUSlicerError> {
         // Borrow the bodies vector and copy the data
         let bodies: Vec<Body> = bodies_clone
             .borrow()
             .iter()
             .map(|b| b.borrow().clone())
             .collect();
-        // Offload the CPU-intensive slicing to a blocking thread
-        let handle = task::spawn_blocking(move || {
-            CPUSlicer::slice_bodies(bodies, 0.10, &Printer::default())
-        });
-
-        // Await the result and map the JoinError to CPUSlicerError
-        let inner_result = handle
-            .await
-            .map_err(|e| CPUSlicerError::ThreadJoinError(format!("Thread join error: {}", e)))?;
-
-        // `inner_result` is now `Result<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>, CPUSlicerError>`
-        let output = inner_result?;
-
-        let _ = write_webps_to_folder(&output).await; // TODO: handle this
-
-        // Return the processed images for visualization
-        Ok(output)
+        slice_and_export(
+            bodies,
+            pipeline,
+            parameters,
+            profile,
+            timestamped_output_dir(&output_base),
+        )
+        .await
     }
 
     async fn slice_selected_bodies(
         bodies_clone: SharedBodies,
-    ) -> Result<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>, CPUSlicerError> {
+        pipeline: SlicingPipeline,
+        parameters: SliceParameters,
+        profile: Option<String>,
+        output_base: PathBuf,
+    ) -> Result<String, CPUSlicerError> {
         // Clone the shared bodies to avoid holding the lock during processing
         let bodies: Vec<Body> = {
             let bodies_ref = bodies_clone.borrow();
@@ -513,41 +1846,1918 @@ fn main() {
                 .map(|b| b.borrow().clone())
                 .collect()
         };
-        // Offload the CPU-intensive slicing to a blocking thread
+        slice_and_export(
+            bodies,
+            pipeline,
+            parameters,
+            profile,
+            timestamped_output_dir(&output_base),
+        )
+        .await
+    }
+
+    /// Slices the bodies with one print profile each and exports every batch to its own
+    /// folder named after the profile, returning the folders
+    async fn slice_per_profile(
+        bodies_clone: SharedBodies,
+        pipeline: SlicingPipeline,
+        parameter_snapshots: SharedParameterSnapshots,
+        output_base: PathBuf,
+    ) -> Result<Vec<String>, CPUSlicerError> {
+        let bodies: Vec<Body> = bodies_clone
+            .borrow()
+            .iter()
+            .map(|b| b.borrow().clone())
+            .collect();
+        let batches = parameter_snapshots.borrow().material_batches(&bodies);
+        let base_dir = timestamped_output_dir(&output_base);
+        let mut dirs = Vec::new();
+        for batch in batches {
+            let output_dir = profile_output_dir(&base_dir, &batch.profile);
+            dirs.push(
+                slice_and_export(
+                    batch.bodies,
+                    pipeline.clone(),
+                    batch.parameters,
+                    Some(batch.profile),
+                    output_dir,
+                )
+                .await?,
+            );
+        }
+        Ok(dirs)
+    }
+
+    /// Slices the bodies into one image per layer, reusing the last run's layers when nothing
+    /// that affects slicing has changed
+    async fn slice_layers(
+        bodies: Vec<Body>,
+        slice_cache: SharedSliceCache,
+        worker_pool: SharedWorkerPool,
+        parameters: SliceParameters,
+    ) -> Result<Arc<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>>, CPUSlicerError> {
+        let snapshot = SliceSnapshot::capture(
+            &bodies,
+            &parameters.layer_thickness(),
+            &parameters.printer,
+            parameters.supersampling,
+&parameters.resin,
+        );
+
+        // Reuse the previous output if nothing that affects slicing has changed
+        let cached = slice_cache.borrow().get(&snapshot);
+        match cached {
+            Some(images) => {
+                println!("Nothing changed since the last slice, reusing cached layers");
+                Ok(images)
+            }
+            None => {
+                let diff = slice_cache.borrow().diff(&snapshot);
+                println!(
+                    "Slicing: {} added, {} changed, {} removed, {} unchanged bodies, settings changed: {}",
+                    diff.added.len(),
+                    diff.changed.len(),
+                    diff.removed.len(),
+                    diff.unchanged.len(),
+                    diff.settings_changed
+                );
+                let reusable = slice_cache.borrow().reusable_layers(&snapshot);
+                if !reusable.is_empty() {
+                    println!(
+                        "Reusing the layers of {} bodies if the layer heights still match",
+                        reusable.len()
+                    );
+                }
+
+                // Offload the CPU-intensive slicing to a blocking thread, running the
+                // parallel work on the configured worker pool
+                performance_overlay::slicing_throughput().start_job();
+                let handle = task::spawn_blocking(move || {
+                    worker_pool.install(|| -> Result<_, CPUSlicerError> {
+                        let mut findings = plugin::registry().on_pre_slice(&bodies, &parameters);
+                        let printer = &parameters.printer;
+                        let (mut images, body_layers) = CPUSlicer::slice_bodies_reusing(
+                            bodies,
+                            &parameters.layer_thickness(),
+                            printer,
+                            parameters.supersampling,
+                            parameters.resin.shrinkage_compensation(),
+                            &reusable,
+                        )?;
+                        if let Some(compensation) = XyCompensationMask::for_printer(printer) {
+                            compensation.apply_to_all(&mut images);
+                        }
+                        let bleed_changes = match parameters.bleed_compensation() {
+                            Some(compensation) => compensation.apply_to_all(&mut images),
+                            None => Vec::new(),
+                        };
+                        if let Some(plate_mask) = PlateMask::for_printer(printer) {
+                            plate_mask.apply_to_all(&mut images);
+                        }
+                        if let Some(mask) = CalibrationMask::for_printer(printer)? {
+                            mask.apply_to_all(&mut images);
+                        }
+                        findings.extend(plugin::registry().on_layers(&images));
+                        report_plugin_findings(&findings);
+                        Ok((images, bleed_changes, body_layers))
+                    })
+                });
+
+                // Await the result and map the JoinError to CPUSlicerError
+                let inner_result = handle.await.map_err(|e| {
+                    CPUSlicerError::ThreadJoinError(format!("Thread join error: {}", e))
+                });
+                performance_overlay::slicing_throughput().finish_job();
+                let inner_result = inner_result?;
+
+                // `inner_result` is now `Result<(layers, bleed changes, body layers), _>`
+                let (output, bleed_changes, body_layers) = inner_result?;
+                let output = Arc::new(output);
+                slice_cache.borrow_mut().store(
+                    snapshot,
+                    Arc::clone(&output),
+                    bleed_changes,
+                    body_layers,
+                );
+                Ok(output)
+            }
+        }
+    }
+
+    /// Slices a job too big for memory into a spool file next to `output_dir`, with the
+    /// corrections of `slice_layers` applied one layer at a time. Spooled layers aren't
+    /// cached and the plugins don't see them.
+    async fn spool_layers(
+        bodies: Vec<Body>,
+        worker_pool: SharedWorkerPool,
+        parameters: SliceParameters,
+        layer_count: usize,
+        output_dir: &Path,
+    ) -> Result<LayerSpool, CPUSlicerError> {
+        let name = output_dir.file_name().unwrap_or_default().to_string_lossy();
+        let path = output_dir.with_file_name(format!(".{}.layers", name));
+        let printer = &parameters.printer;
+        let required = LayerSpool::required_bytes(layer_count, printer.pixel_x, printer.pixel_y);
+        if let Some(available) = export_queue::available_disk_space(&path) {
+            if required > available {
+                return Err(ExportError::InsufficientDiskSpace {
+                    required,
+                    available,
+                }
+                .into());
+            }
+        }
+
+        performance_overlay::slicing_throughput().start_job();
         let handle = task::spawn_blocking(move || {
-            CPUSlicer::slice_bodies(bodies, 0.10, &Printer::default())
+            worker_pool.install(|| -> Result<_, CPUSlicerError> {
+                let printer = &parameters.printer;
+                let mut spool = CPUSlicer::slice_bodies_to_spool(
+                    bodies,
+                    &parameters.layer_thickness(),
+                    printer,
+                    parameters.supersampling,
+                    parameters.resin.shrinkage_compensation(),
+                    &path,
+                )?;
+                let xy_compensation = XyCompensationMask::for_printer(printer);
+                let bleed_compensation = parameters
+                    .bleed_compensation()
+                    .filter(|compensation| !compensation.is_noop());
+                let plate_mask = PlateMask::for_printer(printer);
+                let calibration_mask = CalibrationMask::for_printer(printer)?;
+                if xy_compensation.is_none()
+                    && bleed_compensation.is_none()
+                    && plate_mask.is_none()
+                    && calibration_mask.is_none()
+                {
+                    return Ok(spool);
+                }
+                spool.layers_mut().for_each(|mut spooled| {
+                    let (width, height) = spooled.dimensions();
+                    let mut layer = ImageBuffer::from_raw(width, height, spooled.to_vec())
+                        .expect("a layer fills its own buffer");
+                    if let Some(compensation) = &xy_compensation {
+                        layer = compensation.apply(&layer);
+                    }
+                    if let Some(compensation) = bleed_compensation {
+                        layer = compensation.apply(&layer);
+                    }
+                    if let Some(plate_mask) = &plate_mask {
+                        plate_mask.apply(&mut layer);
+                    }
+                    if let Some(mask) = &calibration_mask {
+                        mask.apply(&mut layer);
+                    }
+                    spooled.copy_from_slice(layer.as_raw());
+                });
+                Ok(spool)
+            })
+        });
+        let spool = handle
+            .await
+            .map_err(|e| CPUSlicerError::ThreadJoinError(format!("Thread join error: {}", e)));
+        performance_overlay::slicing_throughput().finish_job();
+        spool?
+    }
+
+    /// Slices the bodies and queues the layers for export to `output_dir`, returning its
+    /// path once they are written. The parameters and the estimated print time are saved
+    /// next to the layers as job.toml, so the exposures of the job can be read back, and
+    /// the parameters in the print history of every printed body. The resin of the job is
+    /// taken out of the material ledger and the job is counted in the usage statistics.
+    async fn slice_and_export(
+        bodies: Vec<Body>,
+        pipeline: SlicingPipeline,
+        parameters: SliceParameters,
+        profile: Option<String>,
+        output_dir: PathBuf,
+    ) -> Result<String, CPUSlicerError> {
+        let printed: Vec<String> = bodies
+            .iter()
+            .filter(|body| body.display_in_ui_list && body.slice_role == SliceRole::Merge)
+            .map(|body| body.name.clone())
+            .collect();
+        let history_parameters = parameters.clone();
+        let resin_used = material_ledger::job_volume(&bodies);
+        let preview_formats = parameters.printer.previews.clone();
+        #[cfg(feature = "network-printing")]
+        let simulated_printer =
+            network_printer::simulator_enabled().then(|| parameters.printer.clone());
+        let resin = &parameters.resin;
+        if resin.compensates_shrinkage() {
+            let compensation = resin.shrinkage_compensation() * 100.0;
+            println!(
+                "Shrinkage compensation for {} is active: X {:.2}%, Y {:.2}%, Z {:.2}%",
+                resin.name, compensation.x, compensation.y, compensation.z
+            );
+        }
+        if let Some(plate_shape) = &parameters.printer.plate_shape {
+            for name in plate_shape.blocked_bodies(&bodies) {
+                println!(
+                    "{} reaches outside the usable area of the build plate, those parts of its layers will be blank",
+                    name
+                );
+            }
+        }
+
+        let SlicingPipeline {
+            slice_cache,
+            export_queue,
+            worker_pool,
+            print_history,
+            material_ledger,
+            usage_stats,
+            settings,
+        } = pipeline;
+        // A folder of layers is written one layer at a time, so a job too big for memory is
+        // spooled to disk instead. Printer files are packed in memory and still refuse it.
+        let dry_run = parameters.dry_run(&bodies);
+        let spooled = parameters.printer.output_format.is_folder()
+            && memory_budget::check(dry_run.memory_bytes, memory_budget::available_memory())
+                != BudgetCheck::Fits;
+        let output = if spooled {
+            println!(
+                "The {} layers of the job don't fit in memory, spooling them to disk. Islands aren't checked",
+                dry_run.layer_count
+            );
+            let spool = spool_layers(
+                bodies,
+                worker_pool,
+                parameters,
+                dry_run.layer_count,
+                &output_dir,
+            )
+            .await?;
+            ExportLayers::Spooled(spool)
+        } else {
+            let output =
+                slice_layers(bodies, slice_cache, Arc::clone(&worker_pool), parameters).await?;
+            // Islands that appear mid-print, which the vertices of the meshes often don't show
+            let (output, analysis) = task::spawn_blocking(move || {
+                let analysis = worker_pool.install(|| LayerAnalysis::of(&output));
+                (output, analysis)
+            })
+            .await
+            .map_err(|e| CPUSlicerError::ThreadJoinError(format!("Thread join error: {}", e)))?;
+            if !analysis.islands.is_empty() {
+                println!("{}", analysis.summary());
+                for island in &analysis.islands {
+                    println!("Island on {}", island.describe());
+                }
+            }
+            ExportLayers::InMemory(output)
+        };
+        let layer_count = output.len();
+
+        let compression = settings.lock().unwrap().compression.clone();
+        let previews =
+            PreviewFormat::render_all(&preview_formats, &output.views(), compression.png)?;
+        let name = output_dir.file_name().unwrap_or_default().to_string_lossy();
+        let printer_file = OutputFormat::printer_file(&history_parameters, &name);
+        let job = ExportJob {
+            layers: output,
+            previews,
+            output_dir,
+            printer_file,
+            compression,
+        };
+        let dir_path = export_queue.submit(job).await?;
+        std::fs::write(
+            dir_path.join("job.toml"),
+            history_parameters.job_file(layer_count),
+        )?;
+        {
+            let mut stats = usage_stats.borrow_mut();
+            stats.record_job(
+                printed.len(),
+                layer_count,
+                history_parameters.print_time(layer_count),
+                profile.as_deref(),
+            );
+            if let Err(e) = stats.save_user_stats() {
+                eprintln!("Failed to save usage statistics: {}", e);
+            }
+        }
+        {
+            let mut history = print_history.borrow_mut();
+            history.record_export(
+                printed.iter().map(String::as_str),
+                &dir_path,
+                profile,
+                &history_parameters,
+                layer_count,
+            );
+            if let Err(e) = history.save_user_history() {
+                eprintln!("Failed to save print history: {}", e);
+            }
+        }
+        {
+            let mut ledger = material_ledger.borrow_mut();
+            ledger.record_job(&history_parameters.resin.label(), resin_used);
+            if let Err(e) = ledger.save_user_ledger() {
+                eprintln!("Failed to save material ledger: {}", e);
+            }
+        }
+
+        #[cfg(feature = "network-printing")]
+        if let Some(printer) = simulated_printer {
+            let job_dir = dir_path.clone();
+            task::spawn_blocking(move || network_printer::run_simulated_print(&printer, &job_dir))
+                .await
+                .map_err(|e| {
+                    CPUSlicerError::ThreadJoinError(format!("Thread join error: {}", e))
+                })??;
+        }
+
+        if profiler::global().is_enabled() {
+            println!("{}", profiler::global().summary());
+            profiler::global().reset();
+        }
+
+        Ok(dir_path.display().to_string())
+    }
+
+    // Slicing parameters and snapshots
+    {
+        refresh_parameter_snapshots(
+            &app,
+            &state.shared_bodies,
+            &state.shared_slice_parameters,
+            &state.shared_parameter_snapshots,
+        );
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_layer_height_edited(move |height| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            if height > 0.0 {
+                slice_parameters.borrow_mut().slice_thickness = height as f64;
+                // The edited parameters no longer match the active snapshot
+                parameter_snapshots.borrow_mut().active = None;
+            }
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let anti_aliasing_levels: Vec<SharedString> = AntiAliasing::ALL
+            .iter()
+            .map(|level| SharedString::from(level.label()))
+            .collect();
+        app.set_anti_aliasing_levels(Rc::new(slint::VecModel::from(anti_aliasing_levels)).into());
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_anti_aliasing_chosen(move |index| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let anti_aliasing = AntiAliasing::from_index(index);
+            if slice_parameters.borrow().printer.anti_aliasing != anti_aliasing {
+                slice_parameters.borrow_mut().printer.anti_aliasing = anti_aliasing;
+                // The edited parameters no longer match the active snapshot
+                parameter_snapshots.borrow_mut().active = None;
+            }
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let supersampling_levels: Vec<SharedString> = Supersampling::ALL
+            .iter()
+            .map(|level| SharedString::from(level.label()))
+            .collect();
+        app.set_supersampling_levels(Rc::new(slint::VecModel::from(supersampling_levels)).into());
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_supersampling_chosen(move |index| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let supersampling = Supersampling::from_index(index);
+            if slice_parameters.borrow().supersampling != supersampling {
+                slice_parameters.borrow_mut().supersampling = supersampling;
+                // The edited parameters no longer match the active snapshot
+                parameter_snapshots.borrow_mut().active = None;
+            }
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_xy_compensation_edited(move |millimeters| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let xy_compensation = XyCompensation(millimeters as f64);
+            if slice_parameters.borrow().printer.xy_compensation != xy_compensation {
+                slice_parameters.borrow_mut().printer.xy_compensation = xy_compensation;
+                // The edited parameters no longer match the active snapshot
+                parameter_snapshots.borrow_mut().active = None;
+            }
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let preview_height_clone = Rc::clone(&preview_height);
+        let app_weak_clone = app_weak.clone();
+        app.on_add_pause_at_preview_layer(move || {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let layer = {
+                let bodies = bodies_clone.borrow();
+                let bodies: Vec<_> = bodies.iter().map(|b| b.borrow()).collect();
+                slice_parameters.borrow().layer_at_height(
+                    bodies.iter().map(|b| &**b).filter(|b| b.display_in_ui_list),
+                    preview_height_clone.get() as f64,
+                )
+            };
+            let Some(layer) = layer else {
+                show_notification(
+                    &app_weak_clone,
+                    "Move the layer preview to the layer to pause at".to_string(),
+                    true,
+                );
+                return;
+            };
+            slice_parameters
+                .borrow_mut()
+                .add_pause(layer, &format!("Paused at layer {}", layer));
+            // The edited parameters no longer match the active snapshot
+            parameter_snapshots.borrow_mut().active = None;
+            show_notification(
+                &app_weak_clone,
+                format!("Added a pause at layer {}", layer),
+                false,
+            );
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_clear_layer_scripts(move || {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            slice_parameters.borrow_mut().layer_scripts.clear();
+            parameter_snapshots.borrow_mut().active = None;
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let preview_height_clone = Rc::clone(&preview_height);
+        let app_weak_clone = app_weak.clone();
+        app.on_add_fine_layers_at_preview(move || {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let range = {
+                let bodies = bodies_clone.borrow();
+                let bodies: Vec<_> = bodies.iter().map(|b| b.borrow()).collect();
+                slice_parameters.borrow_mut().add_fine_layers(
+                    bodies.iter().map(|b| &**b).filter(|b| b.display_in_ui_list),
+                    preview_height_clone.get() as f64,
+                )
+            };
+            let Some(range) = range else {
+                show_notification(
+                    &app_weak_clone,
+                    "Move the layer preview to the details to slice finer".to_string(),
+                    true,
+                );
+                return;
+            };
+            parameter_snapshots.borrow_mut().active = None;
+            show_notification(
+                &app_weak_clone,
+                format!(
+                    "Slicing {} to {} mm above the bottom at {} mm",
+                    number_format::shortest(range.from),
+                    number_format::shortest(range.to),
+                    number_format::shortest(range.thickness)
+                ),
+                false,
+            );
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_adapt_layer_height_ranges(move || {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let count = {
+                let bodies = bodies_clone.borrow();
+                let bodies: Vec<_> = bodies.iter().map(|b| b.borrow()).collect();
+                slice_parameters.borrow_mut().adapt_layer_heights(
+                    bodies.iter().map(|b| &**b).filter(|b| b.display_in_ui_list),
+                )
+            };
+            parameter_snapshots.borrow_mut().active = None;
+            let message = match count {
+                0 => "The bodies are flat enough for the full layer thickness".to_string(),
+                1 => "Slicing 1 curved height range finer".to_string(),
+                count => format!("Slicing {} curved height ranges finer", count),
+            };
+            show_notification(&app_weak_clone, message, false);
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_clear_layer_height_ranges(move || {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            slice_parameters.borrow_mut().layer_height_ranges.clear();
+            parameter_snapshots.borrow_mut().active = None;
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        // Blank fields keep the resin's settings
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let preview_height_clone = Rc::clone(&preview_height);
+        let app_weak_clone = app_weak.clone();
+        app.on_add_layer_override_at_preview(move |layer_count, exposure, lift, light_off| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let layer = {
+                let bodies = bodies_clone.borrow();
+                let bodies: Vec<_> = bodies.iter().map(|b| b.borrow()).collect();
+                slice_parameters.borrow().layer_at_height(
+                    bodies.iter().map(|b| &**b).filter(|b| b.display_in_ui_list),
+                    preview_height_clone.get() as f64,
+                )
+            };
+            let Some(layer) = layer else {
+                show_notification(
+                    &app_weak_clone,
+                    "Move the layer preview to the first layer to override".to_string(),
+                    true,
+                );
+                return;
+            };
+            let layer_count = layer_count.trim().parse::<usize>().unwrap_or(1).max(1);
+            let layer_override = LayerOverride {
+                first_layer: layer,
+                last_layer: layer + layer_count - 1,
+                exposure_time: number_format::parse(&exposure),
+                lift_distance: number_format::parse(&lift),
+                light_off_delay: number_format::parse(&light_off),
+            };
+            if layer_override.exposure_time.is_none()
+                && layer_override.lift_distance.is_none()
+                && layer_override.light_off_delay.is_none()
+            {
+                show_notification(
+                    &app_weak_clone,
+                    "Enter an exposure, lift or light off delay to override".to_string(),
+                    true,
+                );
+                return;
+            }
+            show_notification(&app_weak_clone, layer_override.describe(), false);
+            slice_parameters
+                .borrow_mut()
+                .layer_overrides
+                .push(layer_override);
+            parameter_snapshots.borrow_mut().active = None;
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_clear_layer_overrides(move || {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            slice_parameters.borrow_mut().layer_overrides.clear();
+            parameter_snapshots.borrow_mut().active = None;
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        let mut preset_names = vec![SharedString::from("Keep current printer")];
+        preset_names.extend(
+            printer_presets::presets()
+                .iter()
+                .map(|preset| SharedString::from(preset.name())),
+        );
+        app.set_printer_presets(Rc::new(slint::VecModel::from(preset_names)).into());
+        app.on_save_parameter_snapshot(move |name, preset| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            {
+                // The new snapshot becomes the active one, so a preset applies to the scene too
+                if let Some(preset) = usize::try_from(preset)
+                    .ok()
+                    .and_then(|index| printer_presets::presets().get(index))
+                {
+                    preset.apply(&mut slice_parameters.borrow_mut());
+                }
+                let mut snapshots = parameter_snapshots.borrow_mut();
+                snapshots.save_snapshot(&name, &slice_parameters.borrow());
+                if let Err(e) = snapshots.save_user_snapshots() {
+                    show_notification(
+                        &app_weak_clone,
+                        format!("Could not save parameter snapshots: {}", e),
+                        true,
+                    );
+                }
+            }
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_activate_parameter_snapshot(move |name| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            {
+                let mut snapshots = parameter_snapshots.borrow_mut();
+                if let Some(parameters) = snapshots.activate(&name) {
+                    *slice_parameters.borrow_mut() = parameters;
+                }
+                if let Err(e) = snapshots.save_user_snapshots() {
+                    show_notification(
+                        &app_weak_clone,
+                        format!("Could not save parameter snapshots: {}", e),
+                        true,
+                    );
+                }
+            }
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+        });
+    }
+
+    // Slicing button callbacks
+    {
+        let layer_viewer: SharedLayerViewer = Rc::new(RefCell::new(None));
+
+        let output_formats: Vec<SharedString> = OutputFormat::ALL
+            .iter()
+            .map(|format| SharedString::from(format.label()))
+            .collect();
+        app.set_output_formats(Rc::new(slint::VecModel::from(output_formats)).into());
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        app.on_output_format_chosen(move |index| {
+            slice_parameters.borrow_mut().printer.output_format = OutputFormat::from_index(index);
+        });
+
+        let png_compressions: Vec<SharedString> = PngCompression::ALL
+            .iter()
+            .map(|level| SharedString::from(level.label()))
+            .collect();
+        app.set_png_compressions(Rc::new(slint::VecModel::from(png_compressions)).into());
+        let shared_settings = Arc::clone(&state.shared_settings);
+        app.on_compression_chosen(move |webp_lossless, webp_quality, png, zip_level| {
+            let compression = CompressionSettings {
+                webp_lossless,
+                webp_quality: webp_quality.clamp(0, 100) as f32,
+                png: PngCompression::from_index(png),
+                zip_level: zip_level.clamp(0, 9) as u8,
+            };
+            if shared_settings.lock().unwrap().compression == compression {
+                return;
+            }
+            if let Err(e) = Settings::update_user_settings(&shared_settings, |settings| {
+                settings.compression = compression
+            }) {
+                error!("Error when updating user settings: {:?}", e);
+            }
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let material_ledger = Rc::clone(&state.shared_material_ledger);
+        let app_weak_clone = app_weak.clone();
+        app.on_request_slice(move |selected_only| {
+            let bodies: Vec<Body> = bodies_clone
+                .borrow()
+                .iter()
+                .map(|b| b.borrow())
+                .filter(|b| b.display_in_ui_list && (b.selected || !selected_only))
+                .map(|b| b.clone())
+                .collect();
+            if bodies.is_empty() {
+                let message = if selected_only {
+                    "Select the bodies to slice"
+                } else {
+                    "Import a model to slice"
+                };
+                show_notification(&app_weak_clone, message.to_string(), true);
+                return;
+            }
+            let parameters = slice_parameters.borrow().clone();
+            let output_format = parameters.printer.output_format.index();
+            let compression = shared_settings.lock().unwrap().compression.clone();
+            let island_settings = shared_settings.lock().unwrap().island_detection.clone();
+            let uneven_scale = bodies.iter().any(Body::has_uneven_scale);
+            let material_ledger = Rc::clone(&material_ledger);
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let report_parameters = parameters.clone();
+                // Island analysis of big meshes takes a moment, so it stays off the UI thread
+                let result = task::spawn_blocking(move || {
+                    let findings = plugin::registry().on_pre_slice(&bodies, &report_parameters);
+                    Report::new("", &bodies, &report_parameters, &island_settings, &findings)
+                        .map(|report| (report, material_ledger::job_volume(&bodies)))
+                })
+                .await;
+                let (mut report, resin_needed) = match result {
+                    Ok(Ok(report)) => report,
+                    Ok(Err(e)) => return show_notification(&app_weak, e.to_string(), true),
+                    Err(e) => return show_notification(&app_weak, e.to_string(), true),
+                };
+                {
+                    let ledger = material_ledger.borrow();
+                    let resin = parameters.resin.label();
+                    report
+                        .estimates
+                        .push(("Resin stock".to_string(), ledger.describe(&resin)));
+                    report
+                        .warnings
+                        .extend(ledger.shortage(&resin, resin_needed));
+                }
+                let summary = report.summary(&parameters);
+                if let Some(app) = app_weak.upgrade() {
+                    let title = if selected_only {
+                        "Slice the selected bodies?"
+                    } else {
+                        "Slice all bodies?"
+                    };
+                    app.set_slice_confirmation_title(title.into());
+                    app.set_slice_confirmation_summary(summary.into());
+                    app.set_slice_confirmation_selected(selected_only);
+                    app.set_slice_output_format(output_format);
+                    app.set_slice_webp_lossless(compression.webp_lossless);
+                    app.set_slice_webp_quality(compression.webp_quality.round() as i32);
+                    app.set_slice_png_compression(compression.png.index());
+                    app.set_slice_zip_level(compression.zip_level as i32);
+                    app.set_slice_confirmation_uneven_scale(uneven_scale);
+                    app.set_slice_confirmation_visible(true);
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_make_scale_uniform(move |selected_only| {
+            let bodies: Vec<_> = bodies_clone
+                .borrow()
+                .iter()
+                .filter(|body_rc| {
+                    let body = body_rc.borrow();
+                    body.display_in_ui_list && (body.selected || !selected_only)
+                })
+                .cloned()
+                .collect();
+            let action = action::make_scale_uniform(&bodies);
+            action_manager.lock().unwrap().execute(Box::new(action));
+            // The summary is stale now
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.invoke_request_slice(selected_only);
+            }
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let pipeline = SlicingPipeline::of(&state);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let layer_viewer_clone = Rc::clone(&layer_viewer);
+        let app_weak_clone = app_weak.clone();
+        app.on_slice_selected(move || {
+            action_manager.lock().unwrap().record("slice_selected();");
+            let bodies_clone = Rc::clone(&bodies_clone);
+            let pipeline = pipeline.clone();
+            let parameters = slice_parameters.borrow().clone();
+            let profile = parameter_snapshots.borrow().active.clone();
+            let output_base = shared_settings.lock().unwrap().paths.output_dir.clone();
+            let uvtools_settings = shared_settings.lock().unwrap().uvtools.clone();
+            let printer = parameters.printer.clone();
+            let slice_cache = Rc::clone(&pipeline.slice_cache);
+            let layer_viewer = Rc::clone(&layer_viewer_clone);
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let result =
+                    slice_selected_bodies(bodies_clone, pipeline, parameters, profile, output_base)
+                        .await;
+                match result {
+                    Ok(dir_path) => {
+                        show_notification(
+                            &app_weak,
+                            format!("Slices written to {}", dir_path),
+                            false,
+                        );
+                        if let Some(app) = app_weak.upgrade() {
+                            open_layer_viewer(
+                                &app,
+                                &layer_viewer,
+                                &slice_cache,
+                                printer,
+                                dir_path.clone(),
+                            );
+                        }
+                        open_export_in_uvtools(&app_weak, &uvtools_settings, &dir_path);
+                    }
+                    Err(e) => show_notification(&app_weak, format!("Slicing failed: {}", e), true),
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let pipeline = SlicingPipeline::of(&state);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let layer_viewer_clone = Rc::clone(&layer_viewer);
+        let app_weak_clone = app_weak.clone();
+        app.on_slice_all(move || {
+            action_manager.lock().unwrap().record("slice();");
+            let bodies_clone = Rc::clone(&bodies_clone);
+            let pipeline = pipeline.clone();
+            let parameters = slice_parameters.borrow().clone();
+            let profile = parameter_snapshots.borrow().active.clone();
+            let output_base = shared_settings.lock().unwrap().paths.output_dir.clone();
+            let uvtools_settings = shared_settings.lock().unwrap().uvtools.clone();
+            let printer = parameters.printer.clone();
+            let slice_cache = Rc::clone(&pipeline.slice_cache);
+            let layer_viewer = Rc::clone(&layer_viewer_clone);
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let result =
+                    slice_all_bodies(bodies_clone, pipeline, parameters, profile, output_base)
+                        .await;
+                match result {
+                    Ok(dir_path) => {
+                        show_notification(
+                            &app_weak,
+                            format!("Slices written to {}", dir_path),
+                            false,
+                        );
+                        if let Some(app) = app_weak.upgrade() {
+                            open_layer_viewer(
+                                &app,
+                                &layer_viewer,
+                                &slice_cache,
+                                printer,
+                                dir_path.clone(),
+                            );
+                        }
+                        open_export_in_uvtools(&app_weak, &uvtools_settings, &dir_path);
+                    }
+                    Err(e) => show_notification(&app_weak, format!("Slicing failed: {}", e), true),
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        let slice_cache = Rc::clone(&state.shared_slice_cache);
+        let layer_viewer_clone = Rc::clone(&layer_viewer);
+        let app_weak_clone = app_weak.clone();
+        app.on_layer_viewer_layer_changed(move |layer| {
+            let mut viewer = layer_viewer_clone.borrow_mut();
+            let (Some(app), Some(viewer)) = (app_weak_clone.upgrade(), viewer.as_mut()) else {
+                return;
+            };
+            let slice_cache = slice_cache.borrow();
+            show_layer_viewer_layer(&app, viewer, &slice_cache, layer.max(0) as usize);
+        });
+
+        let slice_cache = Rc::clone(&state.shared_slice_cache);
+        let layer_viewer_clone = Rc::clone(&layer_viewer);
+        let app_weak_clone = app_weak.clone();
+        app.on_layer_viewer_cut_away_toggled(move |cut_away| {
+            let mut viewer = layer_viewer_clone.borrow_mut();
+            let (Some(app), Some(viewer)) = (app_weak_clone.upgrade(), viewer.as_mut()) else {
+                return;
+            };
+            viewer.set_cut_away(cut_away);
+            let index = app.get_layer_viewer_position().round() as usize;
+            show_layer_viewer_layer(&app, viewer, &slice_cache.borrow(), index);
+        });
+
+        let slice_cache = Rc::clone(&state.shared_slice_cache);
+        let layer_viewer_clone = Rc::clone(&layer_viewer);
+        let app_weak_clone = app_weak.clone();
+        app.on_layer_viewer_compare_bleed_toggled(move |compare| {
+            let mut viewer = layer_viewer_clone.borrow_mut();
+            let (Some(app), Some(viewer)) = (app_weak_clone.upgrade(), viewer.as_mut()) else {
+                return;
+            };
+            viewer.set_compare_bleed(compare);
+            let index = app.get_layer_viewer_position().round() as usize;
+            show_layer_viewer_layer(&app, viewer, &slice_cache.borrow(), index);
+        });
+
+        // Zooming and panning only change the image, the stats stay those of the layer
+        let change_view = {
+            let slice_cache = Rc::clone(&state.shared_slice_cache);
+            let app_weak = app_weak.clone();
+            Rc::new(move |change: &dyn Fn(&mut LayerViewer)| {
+                let mut viewer = layer_viewer.borrow_mut();
+                let (Some(app), Some(viewer)) = (app_weak.upgrade(), viewer.as_mut()) else {
+                    return;
+                };
+                change(viewer);
+                let index = app.get_layer_viewer_position().round() as usize;
+                if let Some(layer) = slice_cache.borrow().latest().get(index) {
+                    show_layer_viewer_image(&app, viewer, layer);
+                }
+            })
+        };
+        let change_view_clone = Rc::clone(&change_view);
+        app.on_layer_viewer_zoomed(move |factor, x, y| {
+            change_view_clone(&|viewer| viewer.zoom_at(factor as f64, (x as f64, y as f64)));
+        });
+        let change_view_clone = Rc::clone(&change_view);
+        app.on_layer_viewer_panned(move |dx, dy| {
+            change_view_clone(&|viewer| viewer.pan(dx as f64, dy as f64));
+        });
+        app.on_layer_viewer_fit(move || change_view(&LayerViewer::fit));
+    }
+
+    // Mixed-material plates
+    {
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let app_weak_clone = app_weak.clone();
+        app.on_assign_selected_to_profile(move || {
+            // Without an active snapshot the selected bodies print with every profile
+            let profile = parameter_snapshots.borrow().active.clone();
+            for body_rc in bodies_clone.borrow().iter() {
+                let mut body = body_rc.borrow_mut();
+                if body.selected {
+                    body.print_profile = profile.clone();
+                }
+            }
+            let message = match profile {
+                Some(profile) => format!("Selected bodies print with {}", profile),
+                None => "Selected bodies print with every profile".to_string(),
+            };
+            show_notification(&app_weak_clone, message, false);
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let print_history = Rc::clone(&state.shared_print_history);
+        let app_weak_clone = app_weak.clone();
+        app.on_mark_print(move |success, note| {
+            let outcome = if success {
+                PrintOutcome::Success
+            } else {
+                PrintOutcome::Failed
+            };
+            let mut history = print_history.borrow_mut();
+            let mut marked = Vec::new();
+            for body_rc in bodies_clone.borrow().iter() {
+                let body = body_rc.borrow();
+                if !body.selected {
+                    continue;
+                }
+                match history.mark_latest(&body.name, outcome, note.trim()) {
+                    Some(record) => marked.push(record.describe()),
+                    None => marked.push(format!("{} was never exported", body.name)),
+                }
+            }
+            if marked.is_empty() {
+                show_notification(
+                    &app_weak_clone,
+                    "Select the bodies that printed".to_string(),
+                    true,
+                );
+                return;
+            }
+            if let Err(e) = history.save_user_history() {
+                show_notification(
+                    &app_weak_clone,
+                    format!("Could not save the print history: {}", e),
+                    true,
+                );
+                return;
+            }
+            show_notification(&app_weak_clone, marked.join("\n"), false);
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let print_history = Rc::clone(&state.shared_print_history);
+        let app_weak_clone = app_weak.clone();
+        app.on_record_print_time(move |text| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let Some(seconds) = motion_profile::parse_duration(&text).filter(|s| *s > 0.0) else {
+                show_notification(
+                    &app_weak_clone,
+                    format!(
+                        "\"{}\" is not a print time, e.g. 1h 05m or 2:30",
+                        text.trim()
+                    ),
+                    true,
+                );
+                return;
+            };
+            let mut history = print_history.borrow_mut();
+            let mut recorded = Vec::new();
+            for body_rc in bodies_clone.borrow().iter() {
+                let body = body_rc.borrow();
+                if !body.selected {
+                    continue;
+                }
+                if history.record_print_time(&body.name, seconds).is_none() {
+                    show_notification(
+                        &app_weak_clone,
+                        format!("{} was never exported", body.name),
+                        true,
+                    );
+                    return;
+                }
+                recorded.push(body.name.clone());
+            }
+            if recorded.is_empty() {
+                show_notification(
+                    &app_weak_clone,
+                    "Select the bodies that printed".to_string(),
+                    true,
+                );
+                return;
+            }
+            if let Err(e) = history.save_user_history() {
+                show_notification(
+                    &app_weak_clone,
+                    format!("Could not save the print history: {}", e),
+                    true,
+                );
+                return;
+            }
+            let calibration = {
+                let mut parameters = slice_parameters.borrow_mut();
+                let calibration = history
+                    .time_calibration(&parameters.printer.name)
+                    .unwrap_or_default();
+                parameters.printer.time_calibration = calibration;
+                calibration
+            };
+            drop(history);
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+            show_notification(
+                &app_weak_clone,
+                format!(
+                    "Recorded {} for {}, estimates now use {}",
+                    motion_profile::format_duration(seconds),
+                    recorded.join(", "),
+                    calibration.describe()
+                ),
+                false,
+            );
+        });
+
+        let usage_stats = Rc::clone(&state.shared_usage_stats);
+        let app_weak_clone = app_weak.clone();
+        app.on_show_usage_stats(move || {
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.set_usage_stats(usage_stats.borrow().describe().into());
+                app.set_usage_stats_visible(true);
+            }
+        });
+
+        let usage_stats = Rc::clone(&state.shared_usage_stats);
+        let app_weak_clone = app_weak.clone();
+        app.on_reset_usage_stats(move || {
+            let mut stats = usage_stats.borrow_mut();
+            *stats = UsageStats::default();
+            if let Err(e) = stats.save_user_stats() {
+                show_notification(
+                    &app_weak_clone,
+                    format!("Could not save the usage statistics: {}", e),
+                    true,
+                );
+            }
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.set_usage_stats(stats.describe().into());
+            }
+        });
+
+        // Corrections of the resin left of the current resin, which exports use up
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let material_ledger = Rc::clone(&state.shared_material_ledger);
+        let app_weak_clone = app_weak.clone();
+        app.on_adjust_resin_stock(move |text| {
+            let Some(adjustment) = StockAdjustment::parse(&text) else {
+                show_notification(
+                    &app_weak_clone,
+                    format!(
+                        "\"{}\" is not an amount of resin, e.g. 420 or +1000",
+                        text.trim()
+                    ),
+                    true,
+                );
+                return;
+            };
+            let resin = slice_parameters.borrow().resin.label();
+            let mut ledger = material_ledger.borrow_mut();
+            ledger.adjust(&resin, adjustment);
+            if let Err(e) = ledger.save_user_ledger() {
+                show_notification(
+                    &app_weak_clone,
+                    format!("Could not save the material ledger: {}", e),
+                    true,
+                );
+                return;
+            }
+            show_notification(
+                &app_weak_clone,
+                format!("{}: {}", resin, ledger.describe(&resin)),
+                false,
+            );
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let print_history = Rc::clone(&state.shared_print_history);
+        let app_weak_clone = app_weak.clone();
+        app.on_reprint_last_good(move || {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let selected: Vec<String> = bodies_clone
+                .borrow()
+                .iter()
+                .map(|body_rc| body_rc.borrow())
+                .filter(|body| body.selected)
+                .map(|body| body.name.clone())
+                .collect();
+            let [name] = selected.as_slice() else {
+                show_notification(
+                    &app_weak_clone,
+                    "Select one body to reprint".to_string(),
+                    true,
+                );
+                return;
+            };
+            let Some(record) = print_history.borrow().last_good(name).cloned() else {
+                show_notification(
+                    &app_weak_clone,
+                    format!("{} has no print marked good", name),
+                    true,
+                );
+                return;
+            };
+            *slice_parameters.borrow_mut() = record.parameters.clone();
+            {
+                // The parameters are the ones of the good print even if its snapshot has
+                // been edited since, only a deleted snapshot is left inactive
+                let mut snapshots = parameter_snapshots.borrow_mut();
+                snapshots.active = record
+                    .profile
+                    .clone()
+                    .filter(|profile| snapshots.snapshots.contains_key(profile));
+            }
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+            show_notification(
+                &app_weak_clone,
+                format!("Reprinting: {}", record.describe()),
+                false,
+            );
+            app.invoke_request_slice(true);
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let print_history = Rc::clone(&state.shared_print_history);
+        let app_weak_clone = app_weak.clone();
+        app.on_show_print_history(move || {
+            let history = print_history.borrow();
+            let mut lines = Vec::new();
+            for body_rc in bodies_clone.borrow().iter() {
+                let body = body_rc.borrow();
+                if !body.selected {
+                    continue;
+                }
+                let records: Vec<String> = history
+                    .of_body(&body.name)
+                    .take(SHOWN_PER_BODY)
+                    .map(|record| record.describe())
+                    .collect();
+                if records.is_empty() {
+                    lines.push(format!("{} was never exported", body.name));
+                }
+                lines.extend(records);
+            }
+            if lines.is_empty() {
+                show_notification(
+                    &app_weak_clone,
+                    "Select the bodies to show the prints of".to_string(),
+                    true,
+                );
+                return;
+            }
+            show_notification(&app_weak_clone, lines.join("\n"), false);
+        });
+
+        let print_history = Rc::clone(&state.shared_print_history);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_open_in_uvtools(move || {
+            let Some(record) = print_history.borrow().records.last().cloned() else {
+                show_notification(
+                    &app_weak_clone,
+                    "Nothing was exported yet".to_string(),
+                    true,
+                );
+                return;
+            };
+            let settings = shared_settings.lock().unwrap().uvtools.clone();
+            match uvtools::open(&settings, &record.output_dir) {
+                Ok(()) => show_notification(
+                    &app_weak_clone,
+                    format!("Opening {} in UVtools", record.output_dir.display()),
+                    false,
+                ),
+                Err(e) => show_notification(&app_weak_clone, e.to_string(), true),
+            }
+        });
+
+        // A printer file exported earlier, reopened to adjust its exposures or remove layers
+        let exported_job: Rc<RefCell<Option<ExportedJob>>> = Rc::new(RefCell::new(None));
+        let job = Rc::clone(&exported_job);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_open_exported_job(move || {
+            let job = Rc::clone(&job);
+            let output_dir = shared_settings.lock().unwrap().paths.output_dir.clone();
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let picked = AsyncFileDialog::new()
+                    .add_filter("printer files", &["pws", "pwma", "goo"])
+                    .set_directory(output_dir)
+                    .pick_file()
+                    .await;
+                let Some(picked) = picked else {
+                    return;
+                };
+                let path = picked.path().to_path_buf();
+                match task::spawn_blocking(move || ExportedJob::open(&path)).await {
+                    Ok(Ok(opened)) => {
+                        if let Some(app) = app_weak.upgrade() {
+                            show_exported_job(&app, &opened);
+                            app.set_exported_job_visible(true);
+                        }
+                        *job.borrow_mut() = Some(opened);
+                    }
+                    Ok(Err(e)) => show_notification(&app_weak, e.to_string(), true),
+                    Err(e) => {
+                        show_notification(&app_weak, format!("Thread join error: {}", e), true)
+                    }
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        let job = Rc::clone(&exported_job);
+        let app_weak_clone = app_weak.clone();
+        app.on_remove_exported_job_layers(move |first, last| {
+            let mut job = job.borrow_mut();
+            let Some(job) = job.as_mut() else {
+                return;
+            };
+            let layers = (first.max(1) - 1) as usize..=(last.max(1) - 1) as usize;
+            match job.remove_layers(layers) {
+                Ok(()) => {
+                    if let Some(app) = app_weak_clone.upgrade() {
+                        app.set_exported_job_summary(job.summary().into());
+                        app.set_exported_job_layer_count(job.layers.len() as i32);
+                    }
+                }
+                Err(e) => show_notification(&app_weak_clone, e.to_string(), true),
+            }
+        });
+
+        let job = Rc::clone(&exported_job);
+        let app_weak_clone = app_weak.clone();
+        app.on_save_exported_job(move |exposure, bottom_exposure, bottom_layers| {
+            let Some(mut edited) = job.borrow_mut().take() else {
+                return;
+            };
+            edited.set_exposures(
+                exposure as f64,
+                bottom_exposure as f64,
+                bottom_layers.max(0) as usize,
+            );
+            let job = Rc::clone(&job);
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let saved = task::spawn_blocking(move || {
+                    let result = edited.save();
+                    (edited, result)
+                })
+                .await;
+                match saved {
+                    Ok((edited, Ok(()))) => {
+                        show_notification(
+                            &app_weak,
+                            format!("Saved {}", edited.path().display()),
+                            false,
+                        );
+                        if let Some(app) = app_weak.upgrade() {
+                            app.set_exported_job_visible(false);
+                        }
+                    }
+                    // Keep the edits so saving can be tried again
+                    Ok((edited, Err(e))) => {
+                        show_notification(
+                            &app_weak,
+                            format!("Could not save the job: {}", e),
+                            true,
+                        );
+                        *job.borrow_mut() = Some(edited);
+                    }
+                    Err(e) => {
+                        show_notification(&app_weak, format!("Thread join error: {}", e), true)
+                    }
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let pipeline = SlicingPipeline::of(&state);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_slice_per_profile(move || {
+            action_manager.lock().unwrap().record("slice_per_profile();");
+            let bodies_clone = Rc::clone(&bodies_clone);
+            let pipeline = pipeline.clone();
+            let parameter_snapshots = Rc::clone(&parameter_snapshots);
+            let output_base = shared_settings.lock().unwrap().paths.output_dir.clone();
+            let uvtools_settings = shared_settings.lock().unwrap().uvtools.clone();
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let result =
+                    slice_per_profile(bodies_clone, pipeline, parameter_snapshots, output_base)
+                        .await;
+                match result {
+                    Ok(dirs) if dirs.is_empty() => show_notification(
+                        &app_weak,
+                        "No bodies are assigned to a print profile".to_string(),
+                        true,
+                    ),
+                    Ok(dirs) => {
+                        show_notification(
+                            &app_weak,
+                            format!("Slices written to {}", dirs.join(", ")),
+                            false,
+                        );
+                        for dir_path in &dirs {
+                            open_export_in_uvtools(&app_weak, &uvtools_settings, dir_path);
+                        }
+                    }
+                    Err(e) => show_notification(&app_weak, format!("Slicing failed: {}", e), true),
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+    }
+
+    // Copying the last export to removable drives, which are looked for every few seconds
+    let removable_drive_timer = slint::Timer::default();
+    {
+        let drives: Rc<RefCell<Vec<RemovableDrive>>> = Rc::new(RefCell::new(Vec::new()));
+
+        let drives_clone = Rc::clone(&drives);
+        let detecting = Rc::new(Cell::new(false));
+        let app_weak_clone = app_weak.clone();
+        removable_drive_timer.start(
+            slint::TimerMode::Repeated,
+            std::time::Duration::from_secs(3),
+            move || {
+                // Asking Windows takes a while, so a slow detection isn't started twice
+                if detecting.replace(true) {
+                    return;
+                }
+                let drives = Rc::clone(&drives_clone);
+                let detecting = Rc::clone(&detecting);
+                let app_weak = app_weak_clone.clone();
+                let slint_future = async move {
+                    let detected = task::spawn_blocking(removable_drive::detect)
+                        .await
+                        .unwrap_or_default();
+                    detecting.set(false);
+                    if *drives.borrow() == detected {
+                        return;
+                    }
+                    if let Some(app) = app_weak.upgrade() {
+                        let labels: Vec<SharedString> = detected
+                            .iter()
+                            .map(|drive| SharedString::from(drive.label.as_str()))
+                            .collect();
+                        app.set_usb_drives(Rc::new(slint::VecModel::from(labels)).into());
+                    }
+                    *drives.borrow_mut() = detected;
+                };
+                slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+            },
+        );
+
+        let print_history = Rc::clone(&state.shared_print_history);
+        let app_weak_clone = app_weak.clone();
+        app.on_copy_to_usb(move |index| {
+            let Some(drive) = drives.borrow().get(index as usize).cloned() else {
+                return;
+            };
+            let Some(record) = print_history.borrow().records.last().cloned() else {
+                show_notification(
+                    &app_weak_clone,
+                    "Nothing was exported yet".to_string(),
+                    true,
+                );
+                return;
+            };
+            show_notification(
+                &app_weak_clone,
+                format!("Copying to {}...", drive.label),
+                false,
+            );
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let result = task::spawn_blocking(move || {
+                    let copy = removable_drive::copy_export(&record.output_dir, &drive)?;
+                    removable_drive::eject(&drive).map(|()| (copy, drive.label))
+                })
+                .await;
+                match result {
+                    Ok(Ok((copy, label))) => show_notification(
+                        &app_weak,
+                        format!("Copied to {}, {} can be removed", copy.display(), label),
+                        false,
+                    ),
+                    Ok(Err(e)) => {
+                        show_notification(&app_weak, format!("Copy to USB failed: {}", e), true)
+                    }
+                    Err(e) => show_notification(&app_weak, e.to_string(), true),
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+    }
+
+    // First-run setup: the printer and resin become the active snapshot, the output folder
+    // and theme go into the settings
+    {
+        let resins = Rc::new(Resin::catalog(Path::new(resin::RESIN_DIR)));
+        let resin_names: Vec<SharedString> = resins
+            .iter()
+            .map(|resin| SharedString::from(resin.label()))
+            .collect();
+        app.set_resins(Rc::new(slint::VecModel::from(resin_names)).into());
+        {
+            let settings = state.shared_settings.lock().unwrap();
+            app.invoke_apply_theme(settings.general.theme.as_str().into());
+            app.set_setup_output_dir(settings.paths.output_dir.display().to_string().into());
+            app.set_setup_wizard_visible(!settings.general.setup_complete);
+        }
+
+        let app_weak_clone = app_weak.clone();
+        app.on_browse_setup_output_dir(move || {
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                if let Some(folder) = AsyncFileDialog::new().pick_folder().await {
+                    if let Some(app) = app_weak.upgrade() {
+                        app.set_setup_output_dir(folder.path().display().to_string().into());
+                    }
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let parameter_snapshots = Rc::clone(&state.shared_parameter_snapshots);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_finish_setup(move |preset, resin, output_dir, theme| {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            {
+                let mut parameters = slice_parameters.borrow_mut();
+                if let Some(resin) = usize::try_from(resin).ok().and_then(|i| resins.get(i)) {
+                    parameters.resin = resin.clone();
+                }
+                // The preset's exposures are made for its printer, so they win over the resin's
+                if let Some(preset) = usize::try_from(preset)
+                    .ok()
+                    .and_then(|index| printer_presets::presets().get(index))
+                {
+                    preset.apply(&mut parameters);
+                }
+                let name = format!("{} - {}", parameters.printer.name, parameters.resin.name);
+                let mut snapshots = parameter_snapshots.borrow_mut();
+                snapshots.save_snapshot(&name, &parameters);
+                if let Err(e) = snapshots.save_user_snapshots() {
+                    show_notification(
+                        &app_weak_clone,
+                        format!("Could not save parameter snapshots: {}", e),
+                        true,
+                    );
+                }
+            }
+            refresh_parameter_snapshots(
+                &app,
+                &bodies_clone,
+                &slice_parameters,
+                &parameter_snapshots,
+            );
+
+            let saved = Settings::update_user_settings(&shared_settings, |settings| {
+                settings.paths.output_dir = PathBuf::from(output_dir.as_str());
+                settings.general.theme = theme.to_string();
+                settings.general.setup_complete = true;
+            });
+            if let Err(e) = saved {
+                show_notification(
+                    &app_weak_clone,
+                    format!("Could not save settings: {}", e),
+                    true,
+                );
+            }
+            app.invoke_apply_theme(theme);
+            app.set_setup_wizard_visible(false);
+        });
+
+        // Skipping keeps the defaults and doesn't ask again
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_skip_setup(move || {
+            if let Err(e) = Settings::update_user_settings(&shared_settings, |settings| {
+                settings.general.setup_complete = true
+            }) {
+                error!("Error when updating user settings: {:?}", e);
+            }
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.set_setup_wizard_visible(false);
+            }
+        });
+    }
+
+    // Pre-flight report of the plate
+    {
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_export_report(move || {
+            let bodies: Vec<Body> = bodies_clone
+                .borrow()
+                .iter()
+                .filter(|b| b.borrow().display_in_ui_list)
+                .map(|b| b.borrow().clone())
+                .collect();
+            let parameters = slice_parameters.borrow().clone();
+            let island_settings = shared_settings.lock().unwrap().island_detection.clone();
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let dialog = AsyncFileDialog::new()
+                    .add_filter("html", &["html"])
+                    .set_file_name("report.html");
+                let Some(file) = dialog.save_file().await else {
+                    return;
+                };
+                let path = file.path().to_path_buf();
+                let result = task::spawn_blocking(move || {
+                    let findings = plugin::registry().on_pre_slice(&bodies, &parameters);
+                    let title = format!(
+                        "Print report: {}",
+                        path.file_stem().unwrap_or_default().to_string_lossy()
+                    );
+                    Report::new(&title, &bodies, &parameters, &island_settings, &findings)?
+                        .write(&path)
+                        .map(|_| path)
+                })
+                .await;
+                match result {
+                    Ok(Ok(path)) => show_notification(
+                        &app_weak,
+                        format!("Report written to {}", path.display()),
+                        false,
+                    ),
+                    Ok(Err(e)) => show_notification(&app_weak, e.to_string(), true),
+                    Err(e) => show_notification(&app_weak, e.to_string(), true),
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+    }
+
+    // Script console and macros
+    app.set_scripting_available(cfg!(feature = "scripting"));
+    #[cfg(feature = "scripting")]
+    {
+        let console = Rc::clone(&script_console);
+        refresh_macro_list(&app_weak);
+
+        let console_clone = Rc::clone(&console);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_run_script(move |source| {
+            let run = console_clone.borrow_mut().run(&source);
+            finish_script_run(&app_weak_clone, &action_manager, run);
         });
 
-        // Await the result and map the JoinError to CPUSlicerError
-        let inner_result = handle
-            .await
-            .map_err(|e| CPUSlicerError::ThreadJoinError(format!("Thread join error: {}", e)))?;
+        let app_weak_clone = app_weak.clone();
+        app.on_save_macro(move |name, source| {
+            let result = scripting::macros_dir()
+                .map_err(|e| e.to_string())
+                .and_then(|dir| {
+                    scripting::save_macro(&dir, &name, &source).map_err(|e| e.to_string())
+                });
+            match result {
+                Ok(path) => show_notification(
+                    &app_weak_clone,
+                    format!("Macro saved to {}", path.display()),
+                    false,
+                ),
+                Err(e) => show_notification(&app_weak_clone, e, true),
+            }
+            refresh_macro_list(&app_weak_clone);
+        });
+
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_run_macro(move |name| {
+            let source = scripting::macros_dir()
+                .map_err(|e| e.to_string())
+                .and_then(|dir| scripting::load_macro(&dir, &name).map_err(|e| e.to_string()));
+            match source {
+                Ok(source) => {
+                    let run = console.borrow_mut().run(&source);
+                    finish_script_run(&app_weak_clone, &action_manager, run);
+                }
+                Err(e) => show_notification(&app_weak_clone, e, true),
+            }
+        });
+
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_toggle_macro_recording(move || {
+            let mut manager = action_manager.lock().unwrap();
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            match manager.stop_recording() {
+                // The recording goes into the editor to be reviewed and saved
+                Some(statements) => {
+                    app.set_script_source(scripting::recorded_macro(&statements).into());
+                    show_notification(
+                        &app_weak_clone,
+                        format!("Recorded {} steps", statements.len()),
+                        false,
+                    );
+                }
+                None => manager.start_recording(),
+            }
+            app.set_script_recording(manager.is_recording());
+        });
 
-        // `inner_result` is now `Result<Vec<ImageBuffer<Luma<u8>, Vec<u8>>>, CPUSlicerError>`
-        let output = inner_result?;
+        let shared_settings = Arc::clone(&state.shared_settings);
+        if let Some(name) = &shared_settings.lock().unwrap().scripting.import_macro {
+            app.set_script_import_macro(name.into());
+        }
+        let app_weak_clone = app_weak.clone();
+        app.on_set_import_macro(move |name| {
+            let import_macro = (!name.is_empty()).then(|| name.to_string());
+            if let Err(e) = Settings::update_user_settings(&shared_settings, |settings| {
+                settings.scripting.import_macro = import_macro
+            }) {
+                show_notification(&app_weak_clone, e.to_string(), true);
+            }
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.set_script_import_macro(name);
+            }
+        });
+    }
 
-        let _ = write_webps_to_folder(&output).await; // TODO: handle this
+    #[cfg(feature = "scripting")]
+    fn refresh_macro_list(app_weak: &slint::Weak<App>) {
+        let names = match scripting::macros_dir().map(|dir| scripting::list_macros(&dir)) {
+            Ok(Ok(names)) => names,
+            Ok(Err(e)) => return show_notification(app_weak, e.to_string(), true),
+            Err(e) => return show_notification(app_weak, e.to_string(), true),
+        };
+        if let Some(app) = app_weak.upgrade() {
+            let names: Vec<SharedString> = names.into_iter().map(SharedString::from).collect();
+            app.set_script_macros(Rc::new(slint::VecModel::from(names)).into());
+        }
+    }
 
-        // Return the processed images for visualization
-        Ok(output)
+    // Records what a script did as one undo step, shows its output and starts the slicing
+    // it asked for
+    #[cfg(feature = "scripting")]
+    fn finish_script_run(
+        app_weak: &slint::Weak<App>,
+        action_manager: &SharedActionManager,
+        run: ScriptRun,
+    ) {
+        if let Some(actions) = run.actions {
+            // The actions were applied while the script ran, executing them again sets the
+            // same values
+            action_manager.lock().unwrap().execute(Box::new(actions));
+        }
+        let Some(app) = app_weak.upgrade() else {
+            return;
+        };
+        let mut output = app.get_script_output().to_string();
+        for line in run.output {
+            output.push_str(&line);
+            output.push('\n');
+        }
+        app.set_script_output(output.into());
+        if !run.failed {
+            for request in run.requests {
+                match request {
+                    SliceRequest::All => app.invoke_slice_all(),
+                    SliceRequest::Selected => app.invoke_slice_selected(),
+                    SliceRequest::PerProfile => app.invoke_slice_per_profile(),
+                }
+            }
+        }
+        app.window().request_redraw();
     }
 
-    // Slicing button callbacks
+    // Export queue controls
     {
-        let bodies_clone = Rc::clone(&state.shared_bodies);
-        app.on_slice_selected(move || {
-            let bodies_clone = Rc::clone(&bodies_clone);
-            let slint_future = async move {
-                slice_selected_bodies(bodies_clone).await.unwrap();
-            };
-            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        let export_queue = Rc::clone(&state.shared_export_queue);
+        let app_weak_clone = app_weak.clone();
+        app.on_toggle_export_paused(move || {
+            let control = export_queue.control();
+            if control.is_paused() {
+                control.resume();
+            } else {
+                control.pause();
+            }
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.set_export_paused(control.is_paused());
+            }
         });
 
-        let bodies_clone = Rc::clone(&state.shared_bodies);
-        app.on_slice_all(move || {
-            let bodies_clone = Rc::clone(&bodies_clone);
-            let slint_future = async move { slice_all_bodies(bodies_clone).await };
-            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        let export_queue = Rc::clone(&state.shared_export_queue);
+        app.on_cancel_export(move || {
+            export_queue.control().cancel();
         });
     }
 
@@ -582,17 +3792,672 @@ fn main() {
 
     // Onclick handler for vertex analysis button
 
-    app.on_analyze_vertex_islands(move || {
+    {
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        app.on_analyze_vertex_islands(move || {
+            let island_settings = shared_settings.lock().unwrap().island_detection.clone();
+            let bodies = bodies_clone.borrow();
+            for body_rc in bodies.iter() {
+                let body = body_rc.borrow_mut();
+                if body.selected {
+                    let islands = MeshIslandAnalyzer::analyze_islands(&body, &island_settings);
+                    println!("Islands vertices: {:?}", islands);
+                }
+            }
+        });
+
+        // Flat caps over the openings at the bottom of the selected bodies
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let app_weak_clone = app_weak.clone();
+        app.on_seal_open_bottoms(move || {
+            let mut sealed = Vec::new();
+            for body in bodies_clone.borrow().iter() {
+                let mut body = body.borrow_mut();
+                if !body.selected {
+                    continue;
+                }
+                if let Some(open) = OpenBottom::of(&body) {
+                    open.seal(&mut body);
+                    sealed.push(body.name.clone());
+                }
+            }
+            let message = if sealed.is_empty() {
+                "None of the selected bodies is open at the bottom".to_string()
+            } else {
+                format!("Sealed the bottom of {}", sealed.join(", "))
+            };
+            show_notification(&app_weak_clone, message, false);
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.window().request_redraw();
+            }
+        });
+
+        // An inner shell inside each selected body, leaving walls of the configured thickness
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_hollow_selected(move || {
+            let wall_thickness = shared_settings.lock().unwrap().hollowing.wall_thickness;
+            let mut hollowed = Vec::new();
+            let mut errors = Vec::new();
+            for body in bodies_clone.borrow().iter() {
+                let mut body = body.borrow_mut();
+                if !body.selected {
+                    continue;
+                }
+                match hollow::hollow(&mut body, wall_thickness) {
+                    Ok(()) => hollowed.push(body.name.clone()),
+                    Err(e) => errors.push(e.to_string()),
+                }
+            }
+            if hollowed.is_empty() && errors.is_empty() {
+                show_notification(&app_weak_clone, "No bodies selected".to_string(), true);
+                return;
+            }
+            let mut messages = Vec::new();
+            if !hollowed.is_empty() {
+                messages.push(format!(
+                    "Hollowed {} with {} mm walls",
+                    hollowed.join(", "),
+                    number_format::shortest(wall_thickness)
+                ));
+            }
+            messages.extend(errors);
+            show_notification(&app_weak_clone, messages.join(". "), hollowed.is_empty());
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.window().request_redraw();
+            }
+        });
+
+        // A lattice in the hollow inside of each selected body, so large hollows hold their shape
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_fill_selected(move || {
+            let hollowing = shared_settings.lock().unwrap().hollowing.clone();
+            let mut filled = Vec::new();
+            let mut errors = Vec::new();
+            for body in bodies_clone.borrow().iter() {
+                let mut body = body.borrow_mut();
+                if !body.selected {
+                    continue;
+                }
+                match infill::fill(&mut body, hollowing.infill_density, hollowing.strut_width) {
+                    Ok(()) => filled.push(body.name.clone()),
+                    Err(e) => errors.push(e.to_string()),
+                }
+            }
+            if filled.is_empty() && errors.is_empty() {
+                show_notification(&app_weak_clone, "No bodies selected".to_string(), true);
+                return;
+            }
+            let mut messages = Vec::new();
+            if !filled.is_empty() {
+                messages.push(format!(
+                    "Filled {} with {} % infill of {} mm struts",
+                    filled.join(", "),
+                    number_format::shortest(hollowing.infill_density),
+                    number_format::shortest(hollowing.strut_width)
+                ));
+            }
+            messages.extend(errors);
+            show_notification(&app_weak_clone, messages.join(". "), filled.is_empty());
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.window().request_redraw();
+            }
+        });
+
+        // Tabs tying the small selected parts on the plate together, added as a body of their
+        // own so they can be deleted again like any other
         let bodies_clone = Rc::clone(&state.shared_bodies);
-        let bodies = bodies_clone.borrow();
-        for body_rc in bodies.iter() {
-            let body = body_rc.borrow_mut();
-            if body.selected {
-                let islands = MeshIslandAnalyzer::analyze_islands(&body);
-                println!("Islands vertices: {:?}", islands);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_add_anti_float_tabs(move || {
+            let settings = shared_settings.lock().unwrap().anti_float_tabs.clone();
+            let (names, parts): (Vec<String>, Vec<_>) = bodies_clone
+                .borrow()
+                .iter()
+                .map(|body_rc| body_rc.borrow())
+                .filter(|body| body.selected)
+                .filter_map(|body| {
+                    let footprint = anti_float_tabs::small_part(&body, &settings)?;
+                    Some((body.name.clone(), footprint))
+                })
+                .unzip();
+            if parts.is_empty() {
+                show_notification(
+                    &app_weak_clone,
+                    "None of the selected bodies is a small part on the plate".to_string(),
+                    true,
+                );
+                return;
+            }
+            let tabs = AntiFloatTabs::plan(&parts, &settings);
+            let untied: Vec<&str> = tabs.untied.iter().map(|&i| names[i].as_str()).collect();
+            if tabs.is_empty() {
+                let message = if untied.is_empty() {
+                    "The selected parts already touch each other".to_string()
+                } else {
+                    format!("{} too far apart to tie together", untied.join(", "))
+                };
+                show_notification(&app_weak_clone, message, true);
+                return;
+            }
+            let action = AddBodiesAction {
+                scene: Rc::clone(&bodies_clone),
+                bodies: vec![Rc::new(RefCell::new(tabs.to_body(&settings)))],
+            };
+            action_manager.lock().unwrap().execute(Box::new(action));
+            let mut message = format!("Added {} anti-float tabs", tabs.tabs.len());
+            if !untied.is_empty() {
+                message += &format!(", {} too far from the others to tie", untied.join(", "));
+            }
+            show_notification(&app_weak_clone, message, false);
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.window().request_redraw();
+            }
+        });
+
+        // Tips the user modeled, offered next to the plain pillars
+        let support_styles: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+        match SupportStyleLibrary::user_library().and_then(|library| Ok(library.names()?)) {
+            Ok(names) => {
+                show_support_styles(&app, &names);
+                *support_styles.borrow_mut() = names;
             }
+            Err(e) => println!("Could not read the support styles: {}", e),
         }
-    });
+
+        let support_styles_clone = Rc::clone(&support_styles);
+        let app_weak_clone = app_weak.clone();
+        app.on_import_support_style(move || {
+            let support_styles = Rc::clone(&support_styles_clone);
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let picked = AsyncFileDialog::new()
+                    .add_filter("support tips", &["stl", "STL"])
+                    .pick_file()
+                    .await;
+                let Some(picked) = picked else {
+                    return;
+                };
+                let path = picked.path().to_path_buf();
+                let imported = task::spawn_blocking(move || {
+                    let library = SupportStyleLibrary::user_library()?;
+                    let name = library.import(&path)?;
+                    Ok::<_, SupportStyleError>((name, library.names()?))
+                })
+                .await;
+                match imported {
+                    Ok(Ok((name, names))) => {
+                        if let Some(app) = app_weak.upgrade() {
+                            show_support_styles(&app, &names);
+                        }
+                        *support_styles.borrow_mut() = names;
+                        let message = format!("Added the support style {}", name);
+                        show_notification(&app_weak, message, false);
+                    }
+                    Ok(Err(e)) => show_notification(&app_weak, e.to_string(), true),
+                    Err(e) => {
+                        show_notification(&app_weak, format!("Thread join error: {}", e), true)
+                    }
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let app_weak_clone = app_weak.clone();
+        app.on_support_style_selected(move |index| {
+            // The first entry is the plain pillars
+            let style = (index > 0)
+                .then(|| support_styles.borrow().get(index as usize - 1).cloned())
+                .flatten();
+            for body_rc in bodies_clone.borrow().iter() {
+                let mut body = body_rc.borrow_mut();
+                if body.selected {
+                    body.support_style = style.clone();
+                }
+            }
+            let message = match style {
+                Some(style) => format!("Supports of the selected bodies are topped with {}", style),
+                None => "Selected bodies stand on plain pillars".to_string(),
+            };
+            show_notification(&app_weak_clone, message, false);
+        });
+
+        // Raises the selected bodies by the base gap onto pillars standing on the plate
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_raise_on_supports(move || {
+            let settings = shared_settings.lock().unwrap().supports.clone();
+            let selected: Vec<Rc<RefCell<Body>>> = bodies_clone
+                .borrow()
+                .iter()
+                .filter(|body| body.borrow().selected)
+                .cloned()
+                .collect();
+            if selected.is_empty() {
+                show_notification(&app_weak_clone, "No bodies selected".to_string(), true);
+                return;
+            }
+            let mut styles = HashMap::new();
+            for body in &selected {
+                let Some(name) = body.borrow().support_style.clone() else {
+                    continue;
+                };
+                if styles.contains_key(&name) {
+                    continue;
+                }
+                let loaded =
+                    SupportStyleLibrary::user_library().and_then(|library| library.load(&name));
+                match loaded {
+                    Ok(style) => styles.insert(name, style),
+                    Err(e) => {
+                        let message = format!("Could not load the support style {}: {}", name, e);
+                        return show_notification(&app_weak_clone, message, true);
+                    }
+                };
+            }
+            let action =
+                support_lift::raise_on_supports(&bodies_clone, &selected, &settings, &styles);
+            action_manager.lock().unwrap().execute(Box::new(action));
+            show_notification(
+                &app_weak_clone,
+                format!(
+                    "Raised {} bodies {} mm onto supports",
+                    selected.len(),
+                    number_format::shortest(settings.base_gap)
+                ),
+                false,
+            );
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.window().request_redraw();
+            }
+        });
+
+        // Islands of the sliced layers, framed on the layer preview
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_cache = Rc::clone(&state.shared_slice_cache);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let worker_pool = Arc::clone(&state.shared_worker_pool);
+        let preview_height = Rc::clone(&preview_height);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let layer_analysis_clone = Rc::clone(&layer_analysis);
+        let app_weak_clone = app_weak.clone();
+        app.on_analyze_layer_islands(move || {
+            let bodies: Vec<Body> = bodies_clone
+                .borrow()
+                .iter()
+                .map(|b| b.borrow().clone())
+                .collect();
+            let parameters = slice_parameters.borrow().clone();
+            let snapshot = SliceSnapshot::capture(
+                &bodies,
+                &parameters.layer_thickness(),
+                &parameters.printer,
+                parameters.supersampling,
+&parameters.resin,
+            );
+            let tip_diameter = shared_settings.lock().unwrap().supports.tip_diameter;
+            let slice_cache = Rc::clone(&slice_cache);
+            let worker_pool = Arc::clone(&worker_pool);
+            #[cfg(feature = "gpu-slicer")]
+            let gpu_layer_analyzer = Rc::clone(&gpu_layer_analyzer);
+            let layer_analysis = Rc::clone(&layer_analysis_clone);
+            let preview_height = Rc::clone(&preview_height);
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let (printer, resin) = (parameters.printer.clone(), parameters.resin.clone());
+                let sliced =
+                    slice_layers(bodies, slice_cache, Arc::clone(&worker_pool), parameters);
+                let layers = match sliced.await {
+                    Ok(layers) => layers,
+                    Err(e) => {
+                        let message = format!("Slicing failed: {}", e);
+                        return show_notification(&app_weak, message, true);
+                    }
+                };
+                // Overhangs with too few supports for the strength of the resin
+                let pool = Arc::clone(&worker_pool);
+                let checked = task::spawn_blocking(move || {
+                    let weak = pool.install(|| {
+                        weak_overhangs(&layers, &printer, &resin.strength, tip_diameter)
+                    });
+                    (layers, weak)
+                })
+                .await;
+                let (layers, weak) = match checked {
+                    Ok(checked) => checked,
+                    Err(e) => return show_notification(&app_weak, e.to_string(), true),
+                };
+                // The GPU has to be used from the thread its context is current on
+                #[cfg(feature = "gpu-slicer")]
+                let on_gpu = gpu_layer_analyzer
+                    .borrow()
+                    .as_ref()
+                    .map(|gpu| LayerAnalysis::on_gpu(&layers, gpu));
+                #[cfg(not(feature = "gpu-slicer"))]
+                let on_gpu = None;
+                let analysis = match on_gpu {
+                    Some(analysis) => Ok(analysis),
+                    None => {
+                        task::spawn_blocking(move || {
+                            worker_pool.install(|| LayerAnalysis::of(&layers))
+                        })
+                        .await
+                    }
+                };
+                match analysis {
+                    Ok(analysis) => {
+                        let mut summary = analysis.summary();
+                        if let Some(first) = weak.first() {
+                            summary.push_str(&format!(
+                                ". {} overhangs have too few supports, {}",
+                                weak.len(),
+                                first.describe()
+                            ));
+                        }
+                        let has_problems = !analysis.islands.is_empty() || !weak.is_empty();
+                        show_notification(&app_weak, summary, has_problems);
+                        *layer_analysis.borrow_mut() = Some((snapshot, analysis));
+                        if let Some(app) = app_weak.upgrade() {
+                            app.invoke_layer_preview_changed(preview_height.get());
+                        }
+                    }
+                    Err(e) => show_notification(&app_weak, e.to_string(), true),
+                }
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        // Pillars up to the islands of the last analysis, unless the scene changed since
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let layer_analysis_clone = Rc::clone(&layer_analysis);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_support_layer_islands(move || {
+            let points = {
+                let parameters = slice_parameters.borrow();
+                let bodies = bodies_clone.borrow();
+                let bodies: Vec<_> = bodies.iter().map(|b| b.borrow()).collect();
+                let thickness = parameters.layer_thickness();
+                let current = SliceSnapshot::capture(
+                    bodies.iter().map(|b| &**b),
+                    &thickness,
+                    &parameters.printer,
+                    parameters.supersampling,
+                    &parameters.resin,
+                );
+                match layer_analysis_clone.borrow().as_ref() {
+                    Some((snapshot, analysis)) if *snapshot == current => {
+                        let heights = CPUSlicer::layer_heights(
+                            bodies.iter().map(|b| &**b),
+                            &thickness,
+                            parameters.resin.shrinkage_compensation(),
+                        );
+                        analysis.support_points(&parameters.printer, &heights)
+                    }
+                    _ => {
+                        let message = "Find the layer islands of the scene first".to_string();
+                        return show_notification(&app_weak_clone, message, true);
+                    }
+                }
+            };
+            if points.is_empty() {
+                let message = "There are no islands to support".to_string();
+                return show_notification(&app_weak_clone, message, false);
+            }
+            let points: Vec<Vector3<f32>> = points.iter().map(|point| point.cast()).collect();
+            let settings = shared_settings.lock().unwrap().supports.clone();
+            let mut pillars = support_lift::pillars(&points, &settings, None);
+            pillars.name = "Supports of layer islands".to_string();
+            let action = AddBodiesAction {
+                scene: Rc::clone(&bodies_clone),
+                bodies: vec![Rc::new(RefCell::new(pillars))],
+            };
+            action_manager.lock().unwrap().execute(Box::new(action));
+            show_notification(
+                &app_weak_clone,
+                format!("Added supports under {} islands", points.len()),
+                false,
+            );
+            if let Some(app) = app_weak_clone.upgrade() {
+                app.window().request_redraw();
+            }
+        });
+
+        let shared_settings = Arc::clone(&state.shared_settings);
+        app.set_island_sensitivity(shared_settings.lock().unwrap().island_detection.sensitivity);
+        app.on_island_sensitivity_changed(move |sensitivity| match Settings::update_user_settings(
+            &shared_settings,
+            |settings| settings.island_detection.sensitivity = sensitivity,
+        ) {
+            Ok(_) => println!("User settings updated"),
+            Err(e) => error!("Error when updating user settings: {:?}", e),
+        });
+    }
+
+    // Comparing the slices of two exports, e.g. before and after changing a setting
+    {
+        let slice_comparison: Rc<RefCell<Option<SliceComparison>>> = Rc::new(RefCell::new(None));
+
+        let slice_comparison_clone = Rc::clone(&slice_comparison);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let worker_pool = Arc::clone(&state.shared_worker_pool);
+        let app_weak_clone = app_weak.clone();
+        app.on_compare_slices(move || {
+            let output_dir = shared_settings.lock().unwrap().paths.output_dir.clone();
+            let slice_comparison = Rc::clone(&slice_comparison_clone);
+            let worker_pool = Arc::clone(&worker_pool);
+            let app_weak = app_weak_clone.clone();
+            let slint_future = async move {
+                let pick = |title: &str| {
+                    AsyncFileDialog::new()
+                        .set_title(title)
+                        .set_directory(&output_dir)
+                        .pick_folder()
+                };
+                let Some(first) = pick("Slices before the change").await else {
+                    return;
+                };
+                let Some(second) = pick("Slices after the change").await else {
+                    return;
+                };
+                let (first, second) = (first.path().to_path_buf(), second.path().to_path_buf());
+                let loaded = task::spawn_blocking(move || {
+                    worker_pool.install(|| SliceComparison::load(&first, &second))
+                })
+                .await;
+                let comparison = match loaded {
+                    Ok(Ok(comparison)) => comparison,
+                    Ok(Err(e)) => {
+                        let message = format!("Could not compare the slices: {}", e);
+                        return show_notification(&app_weak, message, true);
+                    }
+                    Err(e) => return show_notification(&app_weak, e.to_string(), true),
+                };
+                let Some(app) = app_weak.upgrade() else {
+                    return;
+                };
+                let name = |dir: &Path| {
+                    dir.file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                };
+                app.set_slice_comparison_first(name(&comparison.first_dir).into());
+                app.set_slice_comparison_second(name(&comparison.second_dir).into());
+                app.set_slice_comparison_summary(comparison.summary().into());
+                app.set_slice_comparison_layer_count(comparison.layers.len() as i32);
+                // Start at the first layer that differs
+                let last = comparison.layers.len() - 1;
+                let layer = comparison.next_difference(last).unwrap_or(0);
+                app.set_slice_comparison_position(layer as f32);
+                show_slice_comparison_layer(&app, &comparison, layer);
+                app.set_slice_comparison_visible(true);
+                *slice_comparison.borrow_mut() = Some(comparison);
+            };
+            slint::spawn_local(async_compat::Compat::new(slint_future)).unwrap();
+        });
+
+        let slice_comparison_clone = Rc::clone(&slice_comparison);
+        let app_weak_clone = app_weak.clone();
+        app.on_slice_comparison_layer_changed(move |layer| {
+            let comparison = slice_comparison_clone.borrow();
+            let (Some(app), Some(comparison)) = (app_weak_clone.upgrade(), comparison.as_ref())
+            else {
+                return;
+            };
+            show_slice_comparison_layer(&app, comparison, layer.max(0) as usize);
+        });
+
+        let app_weak_clone = app_weak.clone();
+        app.on_slice_comparison_next_difference(move || {
+            let comparison = slice_comparison.borrow();
+            let (Some(app), Some(comparison)) = (app_weak_clone.upgrade(), comparison.as_ref())
+            else {
+                return;
+            };
+            let current = app.get_slice_comparison_position().round() as usize;
+            match comparison.next_difference(current) {
+                Some(layer) => {
+                    app.set_slice_comparison_position(layer as f32);
+                    show_slice_comparison_layer(&app, comparison, layer);
+                }
+                None => {
+                    show_notification(&app_weak_clone, "The slices are identical".into(), false)
+                }
+            }
+        });
+    }
+
+    // UI scale on top of the monitor's own scale factor
+    {
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let display_scale_clone = Rc::clone(&state.display_scale);
+        let app_weak_clone = app_weak.clone();
+        app.set_ui_scale_percent(
+            format!("{:.0}", display_scale_clone.borrow().ui_scale() * 100.0).into(),
+        );
+        app.on_ui_scale_changed(move |percent| {
+            let factor = display_scale_clone
+                .borrow_mut()
+                .set_ui_scale(percent / 100.0);
+            if let Some(app) = app_weak_clone.upgrade() {
+                display_scale::apply(app.window(), factor);
+            }
+            let ui_scale = display_scale_clone.borrow().ui_scale();
+            match Settings::update_user_settings(&shared_settings, |settings| {
+                settings.general.ui_scale = ui_scale
+            }) {
+                Ok(_) => println!("User settings updated"),
+                Err(e) => error!("Error when updating user settings: {:?}", e),
+            }
+        });
+    }
+
+    // Hollowing, infill and drain holes in one go, previewed step by step until applied
+    {
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let app_weak_clone = app_weak.clone();
+        app.on_open_hollowing_wizard(move || {
+            let Some(app) = app_weak_clone.upgrade() else {
+                return;
+            };
+            let selected = selected_bodies(&bodies_clone.borrow());
+            if selected.is_empty() {
+                show_notification(&app_weak_clone, "No bodies selected".to_string(), true);
+                return;
+            }
+            let hollowing = shared_settings.lock().unwrap().hollowing.clone();
+            app.set_hollowing_wall_thickness(
+                number_format::shortest(hollowing.wall_thickness).into(),
+            );
+            app.set_hollowing_infill_density(
+                number_format::shortest(hollowing.infill_density).into(),
+            );
+            app.set_hollowing_strut_width(number_format::shortest(hollowing.strut_width).into());
+            app.set_hollowing_drain_holes(hollowing.drain_holes.to_string().into());
+            app.set_hollowing_drain_diameter(
+                number_format::shortest(hollowing.drain_diameter).into(),
+            );
+            let plan = HollowingPlan::new(&selected, &hollowing);
+            show_hollowing_plan(&app, &plan, false, &slice_parameters.borrow().printer);
+            app.set_hollowing_wizard_visible(true);
+        });
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let slice_parameters = Rc::clone(&state.shared_slice_parameters);
+        let app_weak_clone = app_weak.clone();
+        app.on_preview_hollowing(
+            move |through_bottom, wall_thickness, infill_density, strut_width, holes, diameter| {
+                let Some(app) = app_weak_clone.upgrade() else {
+                    return;
+                };
+                let hollowing = HollowingSettings {
+                    wall_thickness,
+                    infill_density,
+                    strut_width,
+                    drain_holes: holes.max(0) as usize,
+                    drain_diameter: diameter,
+                };
+                let plan = HollowingPlan::new(&selected_bodies(&bodies_clone.borrow()), &hollowing);
+                show_hollowing_plan(
+                    &app,
+                    &plan,
+                    through_bottom,
+                    &slice_parameters.borrow().printer,
+                );
+            },
+        );
+
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_finish_hollowing(
+            move |wall_thickness, infill_density, strut_width, holes, diameter| {
+                let Some(app) = app_weak_clone.upgrade() else {
+                    return;
+                };
+                let hollowing = HollowingSettings {
+                    wall_thickness,
+                    infill_density,
+                    strut_width,
+                    drain_holes: holes.max(0) as usize,
+                    drain_diameter: diameter,
+                };
+                let plan = HollowingPlan::new(&selected_bodies(&bodies_clone.borrow()), &hollowing);
+                if plan.hollowed.is_empty() {
+                    app.set_hollowing_summary(plan.describe().into());
+                    return;
+                }
+                action_manager
+                    .lock()
+                    .unwrap()
+                    .execute(Box::new(plan.to_action()));
+                app.set_hollowing_wizard_visible(false);
+                show_notification(&app_weak_clone, plan.describe(), !plan.errors.is_empty());
+
+                // The next wizard starts from what was picked this time
+                if let Err(e) = Settings::update_user_settings(&shared_settings, |settings| {
+                    settings.hollowing = hollowing
+                }) {
+                    eprintln!("Failed to save settings: {}", e);
+                }
+                app.window().request_redraw();
+            },
+        );
+    }
 
     // Onclick handlers for undo and redo buttons
     {
@@ -607,16 +4472,87 @@ fn main() {
         });
     }
 
+    // Keyboard shortcuts for everything the mouse and the buttons do
+    {
+        app.set_keyboard_help(keyboard::help().into());
+        let bodies_clone = Rc::clone(&state.shared_bodies);
+        let mesh_renderer_clone = Rc::clone(&state.shared_mesh_renderer);
+        let action_manager = Arc::clone(&state.shared_action_manager);
+        let app_weak_clone = app_weak.clone();
+        app.on_key_command(move |text, ctrl, shift, alt| {
+            let Some(command) = keyboard::command_for(&text, ctrl, shift, alt) else {
+                return false;
+            };
+            let Some(app) = app_weak_clone.upgrade() else {
+                return false;
+            };
+            let selected = || -> Vec<Rc<RefCell<Body>>> {
+                bodies_clone
+                    .borrow()
+                    .iter()
+                    .filter(|b| b.borrow().selected && b.borrow().display_in_ui_list)
+                    .cloned()
+                    .collect()
+            };
+            use keyboard::Command;
+            match command {
+                Command::ImportStl => app.invoke_click_import_stl(),
+                Command::Undo => app.invoke_undo(),
+                Command::Redo => app.invoke_redo(),
+                Command::SelectAll => keyboard::select_all(&bodies_clone.borrow(), true),
+                Command::SelectNone => keyboard::select_all(&bodies_clone.borrow(), false),
+                Command::SelectNext => keyboard::cycle_selection(&bodies_clone.borrow(), 1),
+                Command::SelectPrevious => keyboard::cycle_selection(&bodies_clone.borrow(), -1),
+                Command::DeleteSelected => {
+                    for body in selected() {
+                        let uuid = body.borrow().uuid.to_string();
+                        app.invoke_delete_item_by_uuid(uuid.into());
+                    }
+                }
+                Command::TransformSelected => app.invoke_show_transform_dialog(),
+                Command::Nudge(translation) => {
+                    let selected = selected();
+                    if !selected.is_empty() {
+                        let transform = BatchTransform {
+                            translation,
+                            rotation: Vector3::zeros(),
+                            scale: Vector3::repeat(1.0),
+                        };
+                        let action = transform.to_action(&selected);
+                        action_manager.lock().unwrap().execute(Box::new(action));
+                    }
+                }
+                Command::SliceSelected => app.invoke_slice_selected(),
+                Command::SliceAll => app.invoke_slice_all(),
+                Command::ExportReport => app.invoke_export_report(),
+                Command::ShowShortcuts => app.invoke_show_shortcuts(),
+                Command::Orbit(..) | Command::Pan(..) | Command::Zoom(_) | Command::SnapView(_) => {
+                    let mut renderer = mesh_renderer_clone.borrow_mut();
+                    let Some(renderer) = renderer.as_mut() else {
+                        return false;
+                    };
+                    match command {
+                        Command::Orbit(x, y) => renderer.camera_pitch_yaw(x, y),
+                        Command::Pan(x, y) => renderer.camera_pan(x, y),
+                        Command::Zoom(amount) => renderer.zoom(amount),
+                        Command::SnapView(axis) => renderer.snap_view(axis),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            app.window().request_redraw();
+            true
+        });
+    }
+
     // Onclick handlers for the visualization options buttons
     {
         
         let shared_settings = Arc::clone(&state.shared_settings);
         app.on_toggle_edge_visualization(move || {
-            let mut mg = shared_settings.lock().unwrap();
-            let v = mg.renderer.visualize_edges;
-            mg.renderer.visualize_edges = !v;
-            
-            match mg.save_user_settings() {
+            match Settings::update_user_settings(&shared_settings, |settings| {
+                settings.renderer.visualize_edges = !settings.renderer.visualize_edges
+            }) {
                 Ok(_) => println!("User settings updated"),
                 Err(e) => error!("Error when updating user settings: {:?}", e),
             }
@@ -624,14 +4560,68 @@ fn main() {
         
         let shared_settings = Arc::clone(&state.shared_settings);
         app.on_toggle_normal_visualization(move || {
-            let mut mg = shared_settings.lock().unwrap();
-            let v = mg.renderer.visualize_normals;
-            mg.renderer.visualize_normals = !v;
-            
-            match mg.save_user_settings() {
+            match Settings::update_user_settings(&shared_settings, |settings| {
+                settings.renderer.visualize_normals = !settings.renderer.visualize_normals
+            }) {
+                Ok(_) => println!("User settings updated"),
+                Err(e) => error!("Error when updating user settings: {:?}", e),
+            }
+        });
+
+        let shared_settings = Arc::clone(&state.shared_settings);
+        app.on_toggle_local_axes_visualization(move || {
+            match Settings::update_user_settings(&shared_settings, |settings| {
+                settings.renderer.visualize_local_axes = !settings.renderer.visualize_local_axes
+            }) {
+                Ok(_) => println!("User settings updated"),
+                Err(e) => error!("Error when updating user settings: {:?}", e),
+            }
+        });
+
+        let shared_settings = Arc::clone(&state.shared_settings);
+        app.on_toggle_defect_visualization(move || {
+            match Settings::update_user_settings(&shared_settings, |settings| {
+                settings.renderer.visualize_defects = !settings.renderer.visualize_defects
+            }) {
+                Ok(_) => println!("User settings updated"),
+                Err(e) => error!("Error when updating user settings: {:?}", e),
+            }
+        });
+
+        let shared_settings = Arc::clone(&state.shared_settings);
+        app.on_toggle_performance_overlay(move || {
+            match Settings::update_user_settings(&shared_settings, |settings| {
+                settings.renderer.show_performance_overlay =
+                    !settings.renderer.show_performance_overlay
+            }) {
+                Ok(_) => println!("User settings updated"),
+                Err(e) => error!("Error when updating user settings: {:?}", e),
+            }
+        });
+
+        let shared_settings = Arc::clone(&state.shared_settings);
+        let shared_bodies = Rc::clone(&state.shared_bodies);
+        let shared_printer = Arc::clone(&state.shared_printer);
+        let app_weak = app.as_weak();
+        app.on_toggle_vat_fill_visualization(move || {
+            match Settings::update_user_settings(&shared_settings, |settings| {
+                settings.renderer.show_vat_fill = !settings.renderer.show_vat_fill
+            }) {
                 Ok(_) => println!("User settings updated"),
                 Err(e) => error!("Error when updating user settings: {:?}", e),
             }
+            if !shared_settings.lock().unwrap().renderer.show_vat_fill {
+                return;
+            }
+            let bodies = shared_bodies.borrow();
+            let borrowed: Vec<_> = bodies
+                .iter()
+                .map(|body| body.borrow())
+                .filter(|body| body.display_in_ui_list)
+                .collect();
+            let volume = material_ledger::job_volume(borrowed.iter().map(|b| &**b));
+            let fill = Vat::of(&shared_printer.lock().unwrap()).fill(volume);
+            show_notification(&app_weak, fill.describe(), fill.overflows);
         });
     }
 
@@ -1,4 +1,8 @@
 use nalgebra::Vector3;
+
+/// Multiplied into the color of a body dragged onto another one
+pub const OVERLAPPING_TINT: Vector3<f32> = Vector3::new(1.0, 0.2, 0.15);
+
 #[derive(Clone)]
 pub struct Material {
     pub roughness: f32,
@@ -33,4 +37,82 @@ impl Material {
             can_visualize_edges: false,
         }
     }
+
+    /// Flat, bright colors for the axis gizmo
+    pub fn axis(color: Vector3<f32>) -> Material {
+        let reflectance_b = 0.05;
+        Self {
+            roughness: 0.9,
+            albedo: color * 6.0,
+            base_reflectance: Vector3::new(reflectance_b, reflectance_b, reflectance_b),
+            metallicity: 0.0,
+            visualize_normals: false,
+            can_visualize_edges: false,
+        }
+    }
+
+    /// Outline of a footprint another body is dragged onto
+    pub fn footprint_overlap() -> Material {
+        let reflectance_b = 0.05;
+        Self {
+            roughness: 0.9,
+            albedo: Vector3::new(8.0, 0.4, 0.3),
+            base_reflectance: Vector3::new(reflectance_b, reflectance_b, reflectance_b),
+            metallicity: 0.0,
+            visualize_normals: false,
+            can_visualize_edges: false,
+        }
+    }
+
+    /// Edges of a mesh without exactly two faces on them
+    pub fn mesh_defect() -> Material {
+        let reflectance_b = 0.05;
+        Self {
+            roughness: 0.9,
+            albedo: Vector3::new(8.0, 7.0, 0.0),
+            base_reflectance: Vector3::new(reflectance_b, reflectance_b, reflectance_b),
+            metallicity: 0.0,
+            visualize_normals: false,
+            can_visualize_edges: false,
+        }
+    }
+
+    pub fn slice_ghost() -> Material {
+        let reflectance_b = 0.05;
+        Self {
+            roughness: 0.9,
+            // Bright enough to stand out against the ambient light the shader adds
+            albedo: Vector3::new(8.0, 2.0, 0.0),
+            base_reflectance: Vector3::new(reflectance_b, reflectance_b, reflectance_b),
+            metallicity: 0.0,
+            visualize_normals: false,
+            can_visualize_edges: false,
+        }
+    }
+
+    /// Walls of the resin vat, drawn see-through
+    pub fn vat() -> Material {
+        let reflectance_b = 0.1;
+        Self {
+            roughness: 0.2,
+            albedo: Vector3::new(0.8, 0.85, 0.9),
+            base_reflectance: Vector3::new(reflectance_b, reflectance_b, reflectance_b),
+            metallicity: 0.0,
+            visualize_normals: false,
+            can_visualize_edges: false,
+        }
+    }
+
+    /// The resin to pour into the vat, the amber of most standard resins
+    pub fn resin_fill() -> Material {
+        let reflectance_b = 0.05;
+        Self {
+            roughness: 0.3,
+            albedo: Vector3::new(2.0, 1.1, 0.1),
+            base_reflectance: Vector3::new(reflectance_b, reflectance_b, reflectance_b),
+            metallicity: 0.0,
+            visualize_normals: false,
+            can_visualize_edges: false,
+        }
+    }
 }
@@ -0,0 +1,214 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::{Body, SliceRole};
+use crate::cpu_slicer::CPUSlicer;
+use crate::geometry::signed_volume;
+use crate::number_format;
+use crate::settings::{write_atomically, SettingsError};
+use dirs_next::config_dir;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Resin a resin profile used in every exported job, and what is left of it
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ResinStock {
+    /// Milliliters all exported jobs used together
+    pub used: f64,
+    /// Milliliters left in the bottles, None until the user enters them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left: Option<f64>,
+}
+
+/// A correction of the resin left, as typed by the user: "+1000" for a new bottle,
+/// "-50" for a spill, or "420" for what is really left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StockAdjustment {
+    Added(f64),
+    Left(f64),
+}
+
+impl StockAdjustment {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().trim_end_matches("ml").trim_end();
+        let adjustment = if text.starts_with(['+', '-']) {
+            StockAdjustment::Added(text.parse().ok()?)
+        } else {
+            StockAdjustment::Left(text.parse().ok()?)
+        };
+        match adjustment {
+            StockAdjustment::Added(ml) if ml.is_finite() => Some(adjustment),
+            StockAdjustment::Left(ml) if ml.is_finite() && ml >= 0.0 => Some(adjustment),
+            _ => None,
+        }
+    }
+}
+
+/// Resin stock of every resin profile, by its label, kept with the user's settings
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct MaterialLedger {
+    #[serde(default)]
+    pub resins: BTreeMap<String, ResinStock>,
+}
+
+impl MaterialLedger {
+    fn user_ledger_path() -> Result<PathBuf, SettingsError> {
+        let config_dir = config_dir().ok_or(SettingsError::ConfigDirNotFound)?;
+        Ok(config_dir
+            .join("SealSlicer")
+            .join("settings")
+            .join("material_ledger.toml"))
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, SettingsError> {
+        let content = fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), SettingsError> {
+        write_atomically(path, &toml::to_string_pretty(self)?)
+    }
+
+    /// Loads the user's ledger, or starts empty if nothing was exported yet
+    pub fn load_user_ledger() -> Self {
+        let path = match Self::user_ledger_path() {
+            Ok(path) if path.exists() => path,
+            _ => return Self::default(),
+        };
+        Self::load_from_file(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to load material ledger: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn save_user_ledger(&self) -> Result<(), SettingsError> {
+        self.save_to_file(&Self::user_ledger_path()?)
+    }
+
+    /// Takes the resin of an exported job out of the stock
+    pub fn record_job(&mut self, resin: &str, volume: f64) {
+        let stock = self.resins.entry(resin.to_string()).or_default();
+        stock.used += volume;
+        stock.left = stock.left.map(|left| (left - volume).max(0.0));
+    }
+
+    pub fn adjust(&mut self, resin: &str, adjustment: StockAdjustment) {
+        let stock = self.resins.entry(resin.to_string()).or_default();
+        let left = match adjustment {
+            StockAdjustment::Added(ml) => stock.left.unwrap_or(0.0) + ml,
+            StockAdjustment::Left(ml) => ml,
+        };
+        stock.left = Some(left.max(0.0));
+    }
+
+    /// A warning when less of the resin is left than a job of `needed` milliliters takes.
+    /// Resins whose stock was never entered are never short.
+    pub fn shortage(&self, resin: &str, needed: f64) -> Option<String> {
+        let left = self.resins.get(resin)?.left?;
+        (left < needed).then(|| {
+            format!(
+                "Only {} ml of {} left, the job takes {} ml",
+                number_format::decimal(left, 1),
+                resin,
+                number_format::decimal(needed, 1)
+            )
+        })
+    }
+
+    /// e.g. "420.0 ml left, 580.0 ml used"
+    pub fn describe(&self, resin: &str) -> String {
+        let stock = self.resins.get(resin).cloned().unwrap_or_default();
+        let used = format!("{} ml used", number_format::decimal(stock.used, 1));
+        match stock.left {
+            Some(left) => format!("{} ml left, {}", number_format::decimal(left, 1), used),
+            None => format!("stock not entered, {}", used),
+        }
+    }
+}
+
+/// Milliliters of resin the cured bodies take, without the ones that are subtracted
+pub fn job_volume<'a>(bodies: impl IntoIterator<Item = &'a Body>) -> f64 {
+    bodies
+        .into_iter()
+        .filter(|body| body.slice_role == SliceRole::Merge)
+        .map(|body| {
+            let triangles = CPUSlicer::world_triangles([body], Vector3::new(1.0, 1.0, 1.0));
+            signed_volume(&triangles).abs() / 1000.0
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_ledger() {
+        let mut ledger = MaterialLedger::default();
+        ledger.record_job("Generic Standard", 30.0);
+        // Never short while the stock isn't entered
+        assert_eq!(ledger.shortage("Generic Standard", 5000.0), None);
+        assert_eq!(
+            ledger.describe("Generic Standard"),
+            "stock not entered, 30.0 ml used"
+        );
+
+        ledger.adjust("Generic Standard", StockAdjustment::Added(1000.0));
+        ledger.record_job("Generic Standard", 120.0);
+        ledger.adjust("Generic Standard", StockAdjustment::Added(-50.0));
+        let stock = &ledger.resins["Generic Standard"];
+        assert_eq!(stock.left, Some(830.0));
+        assert_eq!(stock.used, 150.0);
+        assert_eq!(ledger.shortage("Generic Standard", 800.0), None);
+        assert_eq!(
+            ledger.shortage("Generic Standard", 900.0).unwrap(),
+            "Only 830.0 ml of Generic Standard left, the job takes 900.0 ml"
+        );
+
+        // A job bigger than the bottle empties it
+        ledger.adjust("Acme Tough", StockAdjustment::Left(20.0));
+        ledger.record_job("Acme Tough", 25.0);
+        assert_eq!(ledger.describe("Acme Tough"), "0.0 ml left, 25.0 ml used");
+        assert_eq!(
+            ledger.describe("Acme Clear"),
+            "stock not entered, 0.0 ml used"
+        );
+    }
+
+    #[test]
+    fn test_parse_adjustment() {
+        assert_eq!(
+            StockAdjustment::parse("+1000"),
+            Some(StockAdjustment::Added(1000.0))
+        );
+        assert_eq!(
+            StockAdjustment::parse(" -50 ml"),
+            Some(StockAdjustment::Added(-50.0))
+        );
+        assert_eq!(
+            StockAdjustment::parse("420.5ml"),
+            Some(StockAdjustment::Left(420.5))
+        );
+        assert_eq!(StockAdjustment::parse(""), None);
+        assert_eq!(StockAdjustment::parse("a bottle"), None);
+        assert_eq!(StockAdjustment::parse("inf"), None);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings").join("material_ledger.toml");
+        let mut ledger = MaterialLedger::default();
+        ledger.record_job("Generic Standard", 12.5);
+        ledger.adjust("Acme Tough", StockAdjustment::Left(500.0));
+        ledger.save_to_file(&path).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("[resins.\"Generic Standard\"]"));
+        assert_eq!(MaterialLedger::load_from_file(&path).unwrap(), ledger);
+    }
+}
@@ -0,0 +1,147 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::number_format;
+use std::fs;
+use std::path::Path;
+
+/// Fraction of the available memory a single job is allowed to use
+const BUDGET_FRACTION: f64 = 0.8;
+
+/// Rough resident size of one imported triangle. This covers the parsed stl_io mesh,
+/// the triangle list, the deduplicated render vertices and the simple vertices.
+const BYTES_PER_IMPORTED_TRIANGLE: u64 = 400;
+
+/// Size of one triangle in a binary STL file, the densest format we import
+const BINARY_STL_BYTES_PER_TRIANGLE: u64 = 50;
+
+#[derive(Debug, PartialEq)]
+pub enum BudgetCheck {
+    Fits,
+    Exceeds {
+        required: u64,
+        available: u64,
+        /// Factor to multiply the resolution of both axes by so the job fits
+        suggested_scale: f64,
+    },
+}
+
+/// Memory needed to hold every layer of a slicing job in RAM, plus one RGB
+/// conversion buffer per worker thread used while encoding.
+pub fn estimate_slice_bytes(layer_count: usize, pixel_x: u32, pixel_y: u32) -> u64 {
+    let layer_bytes = pixel_x as u64 * pixel_y as u64;
+    let encode_buffers = rayon::current_num_threads() as u64 * layer_bytes * 3;
+    layer_count as u64 * layer_bytes + encode_buffers
+}
+
+/// Memory needed to import an STL file, assuming the worst case of a binary file
+pub fn estimate_import_bytes(file_size: u64) -> u64 {
+    (file_size / BINARY_STL_BYTES_PER_TRIANGLE) * BYTES_PER_IMPORTED_TRIANGLE
+}
+
+pub fn estimate_import_bytes_for_path(path: &Path) -> std::io::Result<u64> {
+    Ok(estimate_import_bytes(fs::metadata(path)?.len()))
+}
+
+/// Available memory in bytes, or None on platforms where it can't be determined
+pub fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    parse_mem_available(&meminfo)
+}
+
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+/// Compares an estimate with the available memory. Unknown availability always fits.
+pub fn check(required: u64, available: Option<u64>) -> BudgetCheck {
+    let Some(available) = available else {
+        return BudgetCheck::Fits;
+    };
+    let budget = available as f64 * BUDGET_FRACTION;
+    if (required as f64) <= budget {
+        BudgetCheck::Fits
+    } else {
+        BudgetCheck::Exceeds {
+            required,
+            available,
+            // Memory scales with the pixel count, so each axis scales with the square root
+            suggested_scale: (budget / required as f64).sqrt(),
+        }
+    }
+}
+
+/// Formats a byte count for user facing messages
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{} {}", number_format::decimal(value, 1), UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mem_available() {
+        let meminfo = "MemTotal:       16316412 kB\nMemFree:         1234567 kB\nMemAvailable:    8000000 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(8_000_000 * 1024));
+        assert_eq!(parse_mem_available("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn test_slice_estimate_scales_with_layers_and_resolution() {
+        let small = estimate_slice_bytes(100, 1920, 1080);
+        let more_layers = estimate_slice_bytes(200, 1920, 1080);
+        let bigger = estimate_slice_bytes(100, 3840, 2160);
+        assert!(more_layers > small);
+        assert!(bigger > small);
+        assert!(small >= 100 * 1920 * 1080);
+    }
+
+    #[test]
+    fn test_import_estimate() {
+        // A 50 MB binary STL holds about a million triangles
+        assert_eq!(
+            estimate_import_bytes(50_000_000),
+            1_000_000 * BYTES_PER_IMPORTED_TRIANGLE
+        );
+    }
+
+    #[test]
+    fn test_check_fits_and_exceeds() {
+        assert_eq!(check(100, Some(1000)), BudgetCheck::Fits);
+        assert_eq!(check(u64::MAX, None), BudgetCheck::Fits);
+
+        match check(4000, Some(1000)) {
+            BudgetCheck::Exceeds {
+                required,
+                available,
+                suggested_scale,
+            } => {
+                assert_eq!(required, 4000);
+                assert_eq!(available, 1000);
+                // 800 byte budget / 4000 required = 0.2 of the pixels
+                assert!((suggested_scale - 0.2f64.sqrt()).abs() < 1e-9);
+            }
+            BudgetCheck::Fits => panic!("Expected the job to exceed the budget"),
+        }
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512.0 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
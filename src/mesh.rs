@@ -1,10 +1,12 @@
 // Distributed under the GNU Affero General Public License v3.0 or later.
 // See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+use crate::geometry::{signed_volume, Normalization};
+use crate::mesh_cache::MeshCache;
+use crate::profiler::{self, Stage};
 use crate::stl_processor::StlProcessorTrait;
-use approx::relative_eq;
 use bytemuck::{Pod, Zeroable};
 use nalgebra::{Quaternion, UnitQuaternion, Vector3};
-use std::{collections::HashMap, ffi::OsStr, hash::Hash, hash::Hasher};
+use std::{collections::HashMap, ffi::OsStr, hash::Hash, hash::Hasher, io, path::Path};
 use stl_io::Triangle;
 
 #[repr(C)]
@@ -15,11 +17,12 @@ pub struct Vertex {
     pub barycentric: [f32; 3],
 }
 
+// Compares what is hashed, so deduplicating through a HashMap doesn't depend on its seed
 impl PartialEq for Vertex {
     fn eq(&self, other: &Self) -> bool {
-        relative_eq!(self.position[0], other.position[0])
-            && relative_eq!(self.position[1], other.position[1])
-            && relative_eq!(self.position[2], other.position[2])
+        self.position_bits() == other.position_bits()
+            && self.normal_bits() == other.normal_bits()
+            && self.barycentric_bits() == other.barycentric_bits()
     }
 }
 
@@ -57,8 +60,8 @@ impl Vertex {
         let scale = 10f32.powi(decimal_places as i32);
         // Round the floating-point number to the specified decimal places
         let rounded = (f * scale).round();
-        // Convert to integer bits for hashing
-        rounded.to_bits()
+        // Convert to integer bits for hashing, with -0.0 the same as 0.0
+        (rounded + 0.0).to_bits()
     } /*  */
 
     // Helper method to get bit representation of normal
@@ -82,6 +85,7 @@ impl Vertex {
     }
 }
 
+#[repr(C)]
 #[derive(Default, Clone, Copy, Debug)]
 pub struct SimpleVertex {
     pub position: [f32; 3],
@@ -96,14 +100,15 @@ impl From<Vector3<f32>> for SimpleVertex {
 }
 impl PartialEq for SimpleVertex {
     fn eq(&self, other: &Self) -> bool {
-        relative_eq!(self.position[0], other.position[0])
-            && relative_eq!(self.position[1], other.position[1])
-            && relative_eq!(self.position[2], other.position[2])
+        self.position_bits() == other.position_bits()
     }
 }
 
 impl Eq for SimpleVertex {}
 
+unsafe impl Zeroable for SimpleVertex {}
+unsafe impl Pod for SimpleVertex {}
+
 impl Hash for SimpleVertex {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.position_bits().hash(state);
@@ -130,8 +135,8 @@ impl SimpleVertex {
         let scale = 10f32.powi(decimal_places as i32);
         // Round the floating-point number to the specified decimal places
         let rounded = (f * scale).round();
-        // Convert to integer bits for hashing
-        rounded.to_bits()
+        // Convert to integer bits for hashing, with -0.0 the same as 0.0
+        (rounded + 0.0).to_bits()
     }
 
     // Helper method to get bit representation of normal
@@ -154,26 +159,56 @@ pub struct Mesh {
 }
 
 impl Mesh {
-    pub fn get_triangles_for_slicing(&mut self) -> Vec<Triangle> {
+    /// Welds loose triangles into a mesh, the same way imports do
+    pub fn from_triangles(triangles: &[Triangle]) -> Self {
+        let mut mesh = Self::default();
+        mesh.generate_vertices_and_indices(triangles);
+        mesh.generate_simple_vertices_and_indices(triangles);
+        mesh
+    }
+
+    /// Edges of the welded mesh without exactly two faces on them, as pairs of simple
+    /// vertex indices. Holes leave edges with one face, non-manifold edges have three or more.
+    pub fn non_manifold_edges(&self) -> Vec<[u32; 2]> {
+        let mut edge_faces: HashMap<(u32, u32), usize> = HashMap::new();
+        for face in self.simple_indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (a, b) = (face[i], face[(i + 1) % 3]);
+                *edge_faces.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        let mut edges: Vec<[u32; 2]> = edge_faces
+            .into_iter()
+            .filter(|&(_, faces)| faces != 2)
+            .map(|((a, b), _)| [a, b])
+            .collect();
+        edges.sort_unstable();
+        edges
+    }
+
+    pub fn get_triangles_for_slicing(&self) -> Vec<Triangle> {
         self.into_triangle_vec()
     }
 
-    fn generate_simple_vertices_and_indices(&mut self, original_triangles: &Vec<Triangle>) {
+    fn generate_simple_vertices_and_indices(&mut self, original_triangles: &[Triangle]) {
         let mut unique_simple_vertices: Vec<SimpleVertex> = Vec::new();
         let mut simple_indices: Vec<u32> = Vec::new();
+        // Keyed by normalized position, so vertices weld the same at any scale
         let mut simple_vertex_map: HashMap<SimpleVertex, u32> = HashMap::new();
+        let normalization = Normalization::of_triangles(original_triangles);
 
         for triangle in original_triangles {
             for &vertex_pos in &triangle.vertices {
                 let simple_vertex = SimpleVertex {
                     position: vertex_pos,
                 };
-                let index = if let Some(&existing_index) = simple_vertex_map.get(&simple_vertex) {
+                let key = SimpleVertex::from(normalization.apply(Vector3::from(vertex_pos)));
+                let index = if let Some(&existing_index) = simple_vertex_map.get(&key) {
                     existing_index
                 } else {
                     let new_index = unique_simple_vertices.len() as u32;
                     unique_simple_vertices.push(simple_vertex);
-                    simple_vertex_map.insert(simple_vertex, new_index);
+                    simple_vertex_map.insert(key, new_index);
                     new_index
                 };
                 simple_indices.push(index);
@@ -183,10 +218,12 @@ impl Mesh {
         self.simple_indices = simple_indices;
     }
 
-    fn generate_vertices_and_indices(&mut self, original_triangles: &Vec<Triangle>) {
+    fn generate_vertices_and_indices(&mut self, original_triangles: &[Triangle]) {
         let mut unique_vertices = Vec::new();
         let mut indices: Vec<u32> = Vec::new();
+        // Keyed by normalized position, so vertices weld the same at any scale
         let mut vertex_map: HashMap<Vertex, u32> = HashMap::new();
+        let normalization = Normalization::of_triangles(original_triangles);
 
         let b0 = [1.0, 0.0, 0.0];
         let b1 = [0.0, 1.0, 0.0];
@@ -210,12 +247,16 @@ impl Mesh {
                 };
 
                 // Insert the vertex into the map if it's not already present
-                let index = if let Some(&existing_index) = vertex_map.get(&vertex) {
+                let key = Vertex {
+                    position: normalization.apply(Vector3::from(vertex_pos)).into(),
+                    ..vertex
+                };
+                let index = if let Some(&existing_index) = vertex_map.get(&key) {
                     existing_index
                 } else {
                     let new_index = unique_vertices.len() as u32;
                     unique_vertices.push(vertex);
-                    vertex_map.insert(vertex, new_index);
+                    vertex_map.insert(key, new_index);
                     new_index
                 };
                 indices.push(index);
@@ -258,16 +299,67 @@ impl Mesh {
         &mut self,
         filename: P,
         processor: &Processor,
-    ) {
-        let imported_triangles: Vec<Triangle> = processor
-            .read_stl(filename.as_ref())
-            .expect("Error processing STL file");
+    ) -> io::Result<()> {
+        let mut imported_triangles: Vec<Triangle> = {
+            let _timer = profiler::scope(Stage::Import);
+            processor.read_stl(filename.as_ref())?
+        };
+        if imported_triangles.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the file has no triangles",
+            ));
+        }
+        if repair_inverted(&mut imported_triangles) {
+            println!(
+                "{} is inside out, flipped its triangles",
+                Path::new(filename.as_ref()).display()
+            );
+        }
+        let _timer = profiler::scope(Stage::Dedup);
         self.generate_vertices_and_indices(&imported_triangles);
         self.generate_simple_vertices_and_indices(&imported_triangles);
         self.get_triangles_for_slicing();
+        Ok(())
+    }
+
+    /// Same as `import_stl` but reuses a previously welded mesh from `cache` when the file
+    /// content hasn't changed, and stores the result in the cache otherwise.
+    pub fn import_stl_cached<P: AsRef<OsStr>, Processor: StlProcessorTrait>(
+        &mut self,
+        filename: P,
+        processor: &Processor,
+        cache: &MeshCache,
+    ) -> io::Result<()> {
+        let key = MeshCache::key_for_file(Path::new(filename.as_ref())).ok();
+        if let Some(mesh) = key.and_then(|key| cache.load(&key)) {
+            *self = mesh;
+            return Ok(());
+        }
+
+        self.import_stl(&filename, processor)?;
+        if let Some(key) = key {
+            if let Err(e) = cache.store(&key, self) {
+                eprintln!("Failed to write mesh cache entry: {}", e);
+            }
+        }
+        Ok(())
     }
 }
 
+/// Turns a mesh that is inside out as a whole right side out, reversing the winding and
+/// normals of every triangle. Returns whether it had to.
+pub fn repair_inverted(triangles: &mut [Triangle]) -> bool {
+    if signed_volume(triangles) >= 0.0 {
+        return false;
+    }
+    for triangle in triangles {
+        triangle.vertices.swap(1, 2);
+        triangle.normal = triangle.normal.map(|n| -n);
+    }
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -281,6 +373,42 @@ mod tests {
         assert!(mesh.indices.is_empty(), "Default indices should be empty");
     }
 
+    #[test]
+    fn test_non_manifold_edges() {
+        let triangle = |vertices: [[f32; 3]; 3]| Triangle {
+            normal: [0.0, 0.0, 0.0],
+            vertices,
+        };
+        let [a, b, c, d] = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ];
+        let mut tetrahedron = vec![
+            triangle([a, c, b]),
+            triangle([a, b, d]),
+            triangle([b, c, d]),
+            triangle([c, a, d]),
+        ];
+        let closed = Mesh::from_triangles(&tetrahedron);
+        assert!(closed.non_manifold_edges().is_empty());
+
+        // A fin on edge a-b gives it a third face and leaves two open edges
+        tetrahedron.push(triangle([a, b, [0.5, -1.0, 0.0]]));
+        let mesh = Mesh::from_triangles(&tetrahedron);
+        let edges = mesh.non_manifold_edges();
+        assert_eq!(edges.len(), 3);
+        let position = |i: u32| mesh.simple_vertices[i as usize].position;
+        let on_fin = |&[i, j]: &[u32; 2]| [position(i), position(j)] == [a, b];
+        assert!(edges.iter().any(on_fin));
+
+        // Without its last face the edges around the hole have one face each
+        tetrahedron.truncate(3);
+        let open = Mesh::from_triangles(&tetrahedron);
+        assert_eq!(open.non_manifold_edges().len(), 3);
+    }
+
     #[test]
     fn test_single_triangle_normal() {
         // Create a mesh with a single triangle lying on the XY-plane
@@ -371,6 +499,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_repair_inverted() {
+        // A tetrahedron wound counterclockwise seen from outside, or the other way round
+        let tetrahedron = |inside_out: bool| -> Vec<Triangle> {
+            let corners = [
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+            ];
+            let faces = [
+                ([0, 2, 1], [0.0, 0.0, -1.0]),
+                ([0, 1, 3], [0.0, -1.0, 0.0]),
+                ([0, 3, 2], [-1.0, 0.0, 0.0]),
+                ([1, 2, 3], [1.0, 1.0, 1.0]),
+            ];
+            faces
+                .iter()
+                .map(|&([a, b, c], normal): &([usize; 3], [f32; 3])| {
+                    let (b, c) = if inside_out { (c, b) } else { (b, c) };
+                    Triangle {
+                        normal: normal.map(|n| if inside_out { -n } else { n }),
+                        vertices: [corners[a], corners[b], corners[c]],
+                    }
+                })
+                .collect()
+        };
+        let outward = tetrahedron(false);
+        assert!((signed_volume(&outward) - 1.0 / 6.0).abs() < 1e-9);
+
+        let mut repaired = tetrahedron(false);
+        assert!(!repair_inverted(&mut repaired));
+        assert_eq!(repaired[3].vertices, outward[3].vertices);
+
+        let mut inverted = tetrahedron(true);
+        assert!((signed_volume(&inverted) + 1.0 / 6.0).abs() < 1e-9);
+        assert!(repair_inverted(&mut inverted));
+        assert!((signed_volume(&inverted) - 1.0 / 6.0).abs() < 1e-9);
+        for (repaired, original) in inverted.iter().zip(&outward) {
+            assert_eq!(repaired.normal, original.normal);
+        }
+    }
+
     #[test]
     fn test_degenerate_triangle_normal() {
         // Create a mesh with a degenerate triangle (all vertices have the same position)
@@ -464,4 +635,28 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_welding_ignores_scale() {
+        let triangle = |vertices: [[f32; 3]; 3]| Triangle {
+            normal: [0.0, 0.0, 1.0],
+            vertices,
+        };
+        // A 20 mm square with a small triangle 4 µm above its corner
+        let millimeters = [
+            triangle([[0.0, 0.0, 0.0], [20.0, 0.0, 0.0], [0.0, 20.0, 0.0]]),
+            triangle([[20.0, 0.0, 0.0], [20.0, 20.0, 0.0], [0.0, 20.0, 0.0]]),
+            triangle([[0.0, 0.0, 0.004], [1.0, 0.0, 0.004], [0.0, 1.0, 0.004]]),
+        ];
+        let meters = millimeters
+            .each_ref()
+            .map(|t| triangle(t.vertices.map(|v| v.map(|c| c / 1000.0))));
+
+        let mesh = Mesh::from_triangles(&millimeters);
+        assert_eq!(mesh.simple_vertices.len(), 7);
+        assert_eq!(mesh.vertices.len(), 8);
+        let scaled = Mesh::from_triangles(&meters);
+        assert_eq!(scaled.simple_indices, mesh.simple_indices);
+        assert_eq!(scaled.indices, mesh.indices);
+    }
 }
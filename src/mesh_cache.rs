@@ -0,0 +1,256 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::mesh::{Mesh, SimpleVertex, Vertex};
+use bytemuck::Pod;
+use dirs_next::cache_dir;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"SSMC";
+/// Bump whenever the layout of `Vertex`, the welding in `Mesh` or the header changes
+const FORMAT_VERSION: u32 = 3;
+
+/// SHA-256 of the content of a source file
+pub type MeshKey = [u8; 32];
+
+/// On-disk cache of imported and deduplicated meshes keyed by the SHA-256 of the source
+/// file, so re-importing a large STL skips parsing and welding. Each entry carries the
+/// SHA-256 of its payload too, so a damaged entry is re-imported instead of loaded.
+pub struct MeshCache {
+    dir: PathBuf,
+}
+
+impl MeshCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Cache in the user's cache directory, e.g. ~/.cache/SealSlicer/meshes
+    pub fn in_user_cache_dir() -> Option<Self> {
+        cache_dir().map(|dir| Self::new(dir.join("SealSlicer").join("meshes")))
+    }
+
+    /// Hashes the content of a file in chunks so huge files don't have to fit in memory twice
+    pub fn key_for_file(path: &Path) -> io::Result<MeshKey> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1 << 16];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(hasher.finalize().into())
+    }
+
+    fn entry_path(&self, key: &MeshKey) -> PathBuf {
+        let name: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.dir.join(format!("{}.mesh", name))
+    }
+
+    /// Returns the cached mesh, or None if it is missing, corrupt or from another format version
+    pub fn load(&self, key: &MeshKey) -> Option<Mesh> {
+        let bytes = fs::read(self.entry_path(key)).ok()?;
+        Self::decode(&bytes)
+    }
+
+    pub fn store(&self, key: &MeshKey, mesh: &Mesh) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first so a crash never leaves a truncated entry behind
+        let final_path = self.entry_path(key);
+        let temp_path = final_path.with_extension("tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&Self::encode(mesh))?;
+        file.sync_all()?;
+        fs::rename(temp_path, final_path)
+    }
+
+    /// The header holds the magic, the format version, the four lengths and the SHA-256 of
+    /// the payload after it
+    fn encode(mesh: &Mesh) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(bytemuck::cast_slice(&mesh.vertices));
+        payload.extend_from_slice(bytemuck::cast_slice(&mesh.indices));
+        payload.extend_from_slice(bytemuck::cast_slice(&mesh.simple_vertices));
+        payload.extend_from_slice(bytemuck::cast_slice(&mesh.simple_indices));
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        for len in [
+            mesh.vertices.len(),
+            mesh.indices.len(),
+            mesh.simple_vertices.len(),
+            mesh.simple_indices.len(),
+        ] {
+            bytes.extend_from_slice(&(len as u64).to_le_bytes());
+        }
+        bytes.extend_from_slice(&Sha256::digest(&payload));
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Mesh> {
+        let mut cursor = 0usize;
+        let mut take = |count: usize| -> Option<&[u8]> {
+            let slice = bytes.get(cursor..cursor.checked_add(count)?)?;
+            cursor += count;
+            Some(slice)
+        };
+
+        if take(4)? != MAGIC {
+            return None;
+        }
+        if u32::from_le_bytes(take(4)?.try_into().ok()?) != FORMAT_VERSION {
+            return None;
+        }
+        let mut lengths = [0usize; 4];
+        for length in lengths.iter_mut() {
+            *length = u64::from_le_bytes(take(8)?.try_into().ok()?) as usize;
+        }
+        let digest = take(32)?;
+        // The payload follows the magic, the version, the lengths and the digest
+        if Sha256::digest(&bytes[4 + 4 + 4 * 8 + 32..]).as_slice() != digest {
+            return None;
+        }
+
+        fn read_vec<T: Pod>(bytes: &[u8]) -> Vec<T> {
+            // The buffer from fs::read has no alignment guarantee, so copy element by element
+            bytes
+                .chunks_exact(std::mem::size_of::<T>())
+                .map(bytemuck::pod_read_unaligned)
+                .collect()
+        }
+
+        let vertices: Vec<Vertex> = read_vec(take(
+            lengths[0].checked_mul(std::mem::size_of::<Vertex>())?,
+        )?);
+        let indices: Vec<u32> = read_vec(take(lengths[1].checked_mul(4)?)?);
+        let simple_vertices: Vec<SimpleVertex> = read_vec(take(
+            lengths[2].checked_mul(std::mem::size_of::<SimpleVertex>())?,
+        )?);
+        let simple_indices: Vec<u32> = read_vec(take(lengths[3].checked_mul(4)?)?);
+
+        Some(Mesh {
+            vertices,
+            indices,
+            simple_vertices,
+            simple_indices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn test_mesh() -> Mesh {
+        Mesh {
+            vertices: vec![
+                Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+                Vertex::new([1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+                Vertex::new([0.0, 1.0, 0.5], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0]),
+            ],
+            indices: vec![0, 1, 2],
+            simple_vertices: vec![
+                SimpleVertex::new([0.0, 0.0, 0.0]),
+                SimpleVertex::new([1.0, 0.0, 0.0]),
+                SimpleVertex::new([0.0, 1.0, 0.5]),
+            ],
+            simple_indices: vec![0, 1, 2],
+        }
+    }
+
+    #[test]
+    fn test_store_and_load_round_trip() {
+        let dir = tempdir().unwrap();
+        let cache = MeshCache::new(dir.path().join("meshes"));
+        let mesh = test_mesh();
+
+        assert!(cache.load(&[42; 32]).is_none());
+        cache.store(&[42; 32], &mesh).unwrap();
+        let loaded = cache.load(&[42; 32]).expect("Mesh should be cached");
+
+        assert_eq!(loaded.vertices, mesh.vertices);
+        assert_eq!(loaded.indices, mesh.indices);
+        assert_eq!(loaded.simple_vertices, mesh.simple_vertices);
+        assert_eq!(loaded.simple_indices, mesh.simple_indices);
+    }
+
+    #[test]
+    fn test_corrupt_entry_is_a_miss() {
+        let dir = tempdir().unwrap();
+        let cache = MeshCache::new(dir.path().to_path_buf());
+        let key = [7; 32];
+        cache.store(&key, &test_mesh()).unwrap();
+
+        // Truncate the entry
+        let path = cache.entry_path(&key);
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+        assert!(cache.load(&key).is_none());
+
+        // Flip a bit of the payload, which keeps its length
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        fs::write(&path, &flipped).unwrap();
+        assert!(cache.load(&key).is_none());
+
+        // An entry of the previous format version
+        let mut old = bytes.clone();
+        old[4..8].copy_from_slice(&2u32.to_le_bytes());
+        fs::write(&path, &old).unwrap();
+        assert!(cache.load(&key).is_none());
+
+        // Wrong magic
+        fs::write(&path, b"nope").unwrap();
+        assert!(cache.load(&key).is_none());
+
+        fs::write(&path, &bytes).unwrap();
+        assert!(cache.load(&key).is_some());
+    }
+
+    #[test]
+    fn test_key_depends_on_content() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.stl");
+        let b = dir.path().join("b.stl");
+        let c = dir.path().join("c.stl");
+        fs::write(&a, b"solid a").unwrap();
+        fs::write(&b, b"solid a").unwrap();
+        fs::write(&c, b"solid c").unwrap();
+
+        let key_a = MeshCache::key_for_file(&a).unwrap();
+        assert_eq!(key_a, MeshCache::key_for_file(&b).unwrap());
+        assert_eq!(key_a, <MeshKey>::from(Sha256::digest(b"solid a")));
+        assert_ne!(key_a, MeshCache::key_for_file(&c).unwrap());
+        assert!(MeshCache::key_for_file(&dir.path().join("missing.stl")).is_err());
+    }
+
+    #[test]
+    fn test_second_import_comes_from_cache() {
+        use crate::stl_processor::StlProcessor;
+
+        let dir = tempdir().unwrap();
+        let cache = MeshCache::new(dir.path().to_path_buf());
+        let processor = StlProcessor::new();
+        let filename = "test_stls/with_holes.stl";
+
+        let mut first = Mesh::default();
+        first.import_stl_cached(filename, &processor, &cache).unwrap();
+        let key = MeshCache::key_for_file(Path::new(filename)).unwrap();
+        assert!(cache.load(&key).is_some());
+
+        let mut second = Mesh::default();
+        second.import_stl_cached(filename, &processor, &cache).unwrap();
+        assert_eq!(second.vertices, first.vertices);
+        assert_eq!(second.indices, first.indices);
+        assert_eq!(second.simple_indices, first.simple_indices);
+    }
+}
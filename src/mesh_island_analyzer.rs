@@ -1,13 +1,22 @@
+use crate::geometry::Normalization;
+use crate::profiler::{self, Stage};
+use crate::settings::IslandDetectionSettings;
 use crate::{body::Body, mesh::SimpleVertex};
 use nalgebra::{UnitQuaternion, Vector3};
 use std::collections::{HashMap, HashSet};
 
 pub struct MeshIslandAnalyzer;
 impl MeshIslandAnalyzer {
-    pub fn analyze_islands(body: &Body) -> (Vec<SimpleVertex>, Vec<u32>) {
+    pub fn analyze_islands(
+        body: &Body,
+        settings: &IslandDetectionSettings,
+    ) -> (Vec<SimpleVertex>, Vec<u32>) {
+        let _timer = profiler::scope(Stage::Analysis);
         let mesh = &body.mesh;
-        let up_direction = Vector3::new(0.0, 0.0, -1.0); // Negative Z is up
-        let build_platform_z = 0.0; // Assuming build platform is at z = 0
+        let up_direction = Vector3::from(settings.up_axis).normalize();
+        let build_platform_height = 0.0; // The build platform passes through the origin
+        // An edge has to lead down at least this steeply to support a vertex
+        let min_support_slope = settings.sensitivity.clamp(0.0, 1.0);
 
         // Step 1: Build a mapping from each vertex to its connected vertices
         let mut vertex_connections: HashMap<u32, HashSet<u32>> = HashMap::new();
@@ -38,31 +47,43 @@ impl MeshIslandAnalyzer {
             vertex_connections.entry(vertex_index).or_default();
         }
 
+        // The vertices as placed, and how to measure distances on them the same at any scale
+        let rotation = UnitQuaternion::from_quaternion(body.rotation);
+        let rotated: Vec<SimpleVertex> = mesh
+            .simple_vertices
+            .iter()
+            .map(|vertex| vertex.apply_rotation(rotation))
+            .collect();
+        let normalization = Normalization::of(
+            rotated
+                .iter()
+                .map(|vertex| vertex.get_position_vector3() + body.position),
+        );
+
         // Step 2: Identify potential island vertices
         let mut islands: HashSet<SimpleVertex> = HashSet::new();
         let mut island_simple_indices = HashSet::new();
 
         for (&vertex_index, connected_vertices) in &vertex_connections {
             // let v0: &SimpleVertex = &mesh.simple_vertices[vertex_index as usize];
-            let vertex = &mesh.simple_vertices[vertex_index as usize].apply_rotation(UnitQuaternion::from_quaternion(body.rotation));
+            let vertex = &rotated[vertex_index as usize];
 
             // Exclude vertices on the build platform
-            let z_delta = (vertex.position[2] - build_platform_z + body.position.z).abs();
-            if z_delta.abs() < 0.001 {
+            let height = (vertex.get_position_vector3() + body.position).dot(&up_direction);
+            if normalization.length(height - build_platform_height).abs()
+                < settings.platform_tolerance
+            {
                 continue;
             }
 
             let mut is_island = true;
 
             for &connected_index in connected_vertices {
-                let connected_vertex = &mesh.simple_vertices[connected_index as usize].apply_rotation(UnitQuaternion::from_quaternion(body.rotation));
+                let connected_vertex = &rotated[connected_index as usize];
 
                 // Compute the direction vector from current vertex to connected vertex
-                let direction = Vector3::new(
-                    vertex.position[0] - connected_vertex.position[0],
-                    vertex.position[1] - connected_vertex.position[1],
-                    vertex.position[2] - connected_vertex.position[2],
-                );
+                let direction =
+                    connected_vertex.get_position_vector3() - vertex.get_position_vector3();
 
                 // Avoid zero-length vectors
                 if direction.norm() == 0.0 {
@@ -75,15 +96,14 @@ impl MeshIslandAnalyzer {
                 // Compute the dot product with the up_direction
                 let dot = normalized_direction.dot(&up_direction);
 
-                // If any edge points downwards (dot < 0), it's not an island
-                if dot < 0.0 {
+                // If any edge points downwards steeply enough, it's not an island
+                if dot < -min_support_slope {
                     is_island = false;
                     break;
                 }
 
-                // If the edge is horizontal, we should check its connected vertices as well
-                // here is where I want you to insert the code, only in this if statement!
-                if dot == 0.0 {
+                // If the edge is horizontal or too shallow, check its connected vertices as well
+                if dot <= 0.0 {
                     // Check if the connected vertex has any edges pointing downwards
                     let connected_vertex_connections = &vertex_connections[&connected_index];
                     let mut has_downward_edge = false;
@@ -93,14 +113,11 @@ impl MeshIslandAnalyzer {
                             continue; // Skip back to the original vertex
                         }
 
-                        let cc_vertex = &mesh.simple_vertices[cc_index as usize].apply_rotation(UnitQuaternion::from_quaternion(body.rotation));
+                        let cc_vertex = &rotated[cc_index as usize];
 
                         // Compute the direction vector from connected vertex to cc_vertex
-                        let cc_direction = Vector3::new(
-                            connected_vertex.position[0] - cc_vertex.position[0],
-                            connected_vertex.position[1] - cc_vertex.position[1],
-                            connected_vertex.position[2] - cc_vertex.position[2],
-                        );
+                        let cc_direction = cc_vertex.get_position_vector3()
+                            - connected_vertex.get_position_vector3();
 
                         // Avoid zero-length vectors
                         if cc_direction.norm() == 0.0 {
@@ -114,7 +131,7 @@ impl MeshIslandAnalyzer {
                         let cc_dot = cc_normalized_direction.dot(&up_direction);
 
                         // If any connected edge points downwards, the current vertex is supported
-                        if cc_dot < 0.0 {
+                        if cc_dot < -min_support_slope {
                             has_downward_edge = true;
                             break;
                         }
@@ -152,9 +169,9 @@ mod tests {
         let filename = "test_stls/flat_overhang_4_points.stl";
         let processor = StlProcessor::new();
         let mut mesh = Mesh::default();
-        mesh.import_stl(filename, &processor);
+        mesh.import_stl(filename, &processor).unwrap();
         let body = Body::new(mesh);
-        let islands = MeshIslandAnalyzer::analyze_islands(&body);
+        let islands = MeshIslandAnalyzer::analyze_islands(&body, &IslandDetectionSettings::default());
         // let v0 = [1.601282, 18.610937, 8.000000];
         // let v1 = [1.601282, 21.813501, 8.000000];
         // let v2 = [-1.601282, 18.610937, 8.000000];
@@ -176,9 +193,9 @@ mod tests {
         let filename = "test_stls/pointed_overhang_1_point.stl";
         let processor = StlProcessor::new();
         let mut mesh = Mesh::default();
-        mesh.import_stl(filename, &processor);
+        mesh.import_stl(filename, &processor).unwrap();
         let body = Body::new(mesh);
-        let islands = MeshIslandAnalyzer::analyze_islands(&body);
+        let islands = MeshIslandAnalyzer::analyze_islands(&body, &IslandDetectionSettings::default());
 
         islands.0.iter().for_each(|el| println!("{:?}", el.position));
         assert_eq!(
@@ -194,9 +211,9 @@ mod tests {
         let filename = "test_stls/pointed_overhang_2_points.stl";
         let processor: StlProcessor = StlProcessor::new();
         let mut mesh = Mesh::default();
-        mesh.import_stl(filename, &processor);
+        mesh.import_stl(filename, &processor).unwrap();
         let body = Body::new(mesh);
-        let islands = MeshIslandAnalyzer::analyze_islands(&body);
+        let islands = MeshIslandAnalyzer::analyze_islands(&body, &IslandDetectionSettings::default());
 
         islands.0.iter().for_each(|el| println!("{:?}", el.position));
         assert_eq!(
@@ -213,11 +230,11 @@ mod tests {
         let filename = "test_stls/pointed_overhang_1_point.stl";
         let processor = StlProcessor::new();
         let mut mesh = Mesh::default();
-        mesh.import_stl(filename, &processor);
+        mesh.import_stl(filename, &processor).unwrap();
         let mut body = Body::new(mesh);
         body.set_rotation(Vector3::new(-90.0,0.0,0.0));
         body.set_position(Vector3::new(0.0,0.0,12.5));
-        let islands = MeshIslandAnalyzer::analyze_islands(&body);
+        let islands = MeshIslandAnalyzer::analyze_islands(&body, &IslandDetectionSettings::default());
 
         islands.0.iter().for_each(|el| println!("{:?}", el.position));
         assert_eq!(
@@ -227,4 +244,77 @@ mod tests {
             islands.0.len()
         );
     }
+
+    #[test]
+    fn test_up_axis_is_configurable() {
+        let filename = "test_stls/pointed_overhang_1_point.stl";
+        let processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl(filename, &processor).unwrap();
+        let mut body = Body::new(mesh);
+        // Upside down, growing towards -Z, the model is the same print as before
+        body.set_rotation(Vector3::new(180.0, 0.0, 0.0));
+        let settings = IslandDetectionSettings {
+            up_axis: [0.0, 0.0, -1.0],
+            ..IslandDetectionSettings::default()
+        };
+        let islands = MeshIslandAnalyzer::analyze_islands(&body, &settings);
+        assert_eq!(islands.0.len(), 1);
+    }
+
+    #[test]
+    fn test_sensitivity_reports_shallow_overhangs() {
+        let filename = "test_stls/pointed_overhang_1_point.stl";
+        let processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl(filename, &processor).unwrap();
+        let body = Body::new(mesh);
+
+        let default = MeshIslandAnalyzer::analyze_islands(&body, &IslandDetectionSettings::default());
+        // At full sensitivity no edge is steep enough, so every vertex off the platform is an island
+        let settings = IslandDetectionSettings {
+            sensitivity: 1.0,
+            ..IslandDetectionSettings::default()
+        };
+        let strict = MeshIslandAnalyzer::analyze_islands(&body, &settings);
+        let off_platform = body
+            .mesh
+            .simple_vertices
+            .iter()
+            .filter(|v| v.position[2].abs() >= settings.platform_tolerance)
+            .count();
+        assert!(strict.1.len() > default.1.len());
+        assert_eq!(strict.1.len(), off_platform);
+    }
+
+    #[test]
+    fn test_islands_ignore_modeling_units() {
+        let filename = "test_stls/flat_overhang_4_points.stl";
+        let processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl(filename, &processor).unwrap();
+        let millimeters = Body::new(mesh);
+        // The same model exported in meters
+        let triangles: Vec<_> = millimeters
+            .mesh
+            .get_triangles_for_slicing()
+            .into_iter()
+            .map(|mut triangle| {
+                triangle.vertices = triangle.vertices.map(|v| v.map(|c| c / 1000.0));
+                triangle
+            })
+            .collect();
+        let meters = Body::new(Mesh::from_triangles(&triangles));
+
+        let settings = IslandDetectionSettings {
+            platform_tolerance: 0.25,
+            ..IslandDetectionSettings::default()
+        };
+        let mut expected = MeshIslandAnalyzer::analyze_islands(&millimeters, &settings).1;
+        let mut found = MeshIslandAnalyzer::analyze_islands(&meters, &settings).1;
+        expected.sort();
+        found.sort();
+        assert_eq!(expected.len(), 4);
+        assert_eq!(found, expected);
+    }
 }
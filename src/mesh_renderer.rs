@@ -1,21 +1,33 @@
 // Distributed under the GNU Affero General Public License v3.0 or later.
 // See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::fs;
 use std::rc::Rc;
 slint::include_modules!();
-use crate::body::Body;
+use crate::axis_gizmo::{self, Axis};
+use crate::body::{Body, SliceRole};
 use crate::camera::Camera;
-use crate::material::Material;
+use crate::cpu_slicer::CPUSlicer;
+use crate::footprint::Footprint;
+use crate::material::{Material, OVERLAPPING_TINT};
+use crate::material_ledger;
 use crate::mesh::{Mesh, Vertex};
 use crate::render_texture::RenderTexture;
+use crate::settings::RendererSettings;
+use crate::vat::Vat;
+use crate::viewport::{FrameStats, Viewport};
 use crate::ScopedVAOBinding;
 use crate::ScopedVBOBinding;
 use crate::SharedBodies;
 use crate::SharedPrinter;
 use glow::Context as GlowContext;
 use glow::HasContext;
-use nalgebra::Vector3;
+use nalgebra::{Matrix4, UnitQuaternion, Vector3};
+
+/// Length of the local axes drawn on selected bodies, in millimeters
+const LOCAL_AXES_LENGTH: f32 = 25.0;
+
 pub struct MeshRenderer {
     gl: Rc<GlowContext>,
     program: glow::Program,
@@ -33,13 +45,38 @@ pub struct MeshRenderer {
     visualize_normals_location: glow::UniformLocation,
     visualize_edges_location: glow::UniformLocation,
     edge_thickness_location: glow::UniformLocation,
+    visualize_back_faces_location: glow::UniformLocation,
+    opacity_location: glow::UniformLocation,
     displayed_texture: RenderTexture,
     next_texture: RenderTexture,
     bodies: SharedBodies,
     camera: Camera,
     printer: SharedPrinter,
+    slice_ghost: Vec<Vertex>,
+    defect_edges: HashMap<*const RefCell<Body>, DefectEdges>,
+    vat_fill: Option<VatFillOverlay>,
+    /// Counted while drawing, set from inside the closures that draw
+    frame_stats: Cell<FrameStats>,
 }
 
+/// Line vertices of the non-manifold edges of a body
+struct DefectEdges {
+    /// Number of simple vertices and indices of the mesh they were found in
+    mesh_size: (usize, usize),
+    vertices: Vec<Vertex>,
+}
+
+/// The vat and the resin to pour for the job, drawn see-through around the plate
+struct VatFillOverlay {
+    /// What the volume of the job depends on, to only measure it again when that changes
+    key: (Vat, Vec<VatFillKey>),
+    walls: Vec<Vertex>,
+    resin: Vec<Vertex>,
+}
+
+/// Mesh size, scale and role of a body
+type VatFillKey = (*const RefCell<Body>, usize, Vector3<f32>, SliceRole);
+
 impl MeshRenderer {
     pub fn new(
         gl: Rc<GlowContext>,
@@ -151,6 +188,10 @@ impl MeshRenderer {
             let edge_thickness_location = gl
                 .get_uniform_location(shader_program, "edge_thickness")
                 .unwrap();
+            let visualize_back_faces_location = gl
+                .get_uniform_location(shader_program, "visualize_back_faces")
+                .unwrap();
+            let opacity_location = gl.get_uniform_location(shader_program, "opacity").unwrap();
 
             // Set up VBO, EBO, VAO
             let vbo = gl.create_buffer().expect("Cannot create buffer");
@@ -247,20 +288,37 @@ impl MeshRenderer {
                 printer: printer.clone(),
                 visualize_edges_location,
                 edge_thickness_location,
+                visualize_back_faces_location,
+                opacity_location,
+                slice_ghost: Vec::new(),
+                defect_edges: HashMap::new(),
+                vat_fill: None,
+                frame_stats: Cell::new(FrameStats::default()),
             };
             let p = printer.lock().unwrap();
             me.add_printer_plate_plane(p.physical_x as f32, p.physical_y as f32);
             me
         }
     }
+}
 
-    pub fn render(
-        &mut self,
-        width: u32,
-        height: u32,
-        visualize_edges: bool,
-        visualize_normals: bool,
-    ) -> slint::Image {
+impl Viewport for MeshRenderer {
+    fn render(&mut self, width: u32, height: u32, settings: &RendererSettings) -> slint::Image {
+        let &RendererSettings {
+            visualize_edges,
+            visualize_normals,
+            visualize_local_axes,
+            visualize_defects,
+            show_vat_fill,
+            ..
+        } = settings;
+        if visualize_defects {
+            self.update_defect_edges();
+        }
+        if show_vat_fill {
+            self.update_vat_fill();
+        }
+        self.frame_stats.set(FrameStats::default());
         unsafe {
             let gl = &self.gl;
             gl.use_program(Some(self.program));
@@ -312,6 +370,7 @@ impl MeshRenderer {
 
                 // Set the light direction (e.g., a fixed directional light)
                 gl.uniform_3_f32(Some(&self.light_direction_location), 0.0, 0.0, 1.0);
+                gl.uniform_1_f32(Some(&self.opacity_location), 1.0);
 
                 // Convert view_proj_matrix to column-major array
                 let view_proj_matrix: [f32; 16] = view_proj
@@ -338,13 +397,13 @@ impl MeshRenderer {
                 for body in self.bodies.borrow().iter() {
                     // PBR Uniforms
                     let material = &body.borrow().material;
+                    let albedo = if body.borrow().overlapping {
+                        material.albedo.component_mul(&OVERLAPPING_TINT)
+                    } else {
+                        material.albedo
+                    };
                     gl.uniform_1_f32(Some(&self.roughness_location), material.roughness);
-                    gl.uniform_3_f32(
-                        Some(&self.albedo_location),
-                        material.albedo.x,
-                        material.albedo.y,
-                        material.albedo.z,
-                    );
+                    gl.uniform_3_f32(Some(&self.albedo_location), albedo.x, albedo.y, albedo.z);
                     gl.uniform_3_f32(
                         Some(&self.base_reflectance_location),
                         material.base_reflectance.x,
@@ -362,6 +421,15 @@ impl MeshRenderer {
                         (material.can_visualize_edges && visualize_edges) as u32,
                     );
                     gl.uniform_1_f32(Some(&self.edge_thickness_location), 3.0);
+                    // Back faces are drawn to be seen, not culled
+                    let show_back_faces = visualize_defects && material.can_visualize_edges;
+                    gl.uniform_1_u32(
+                        Some(&self.visualize_back_faces_location),
+                        show_back_faces as u32,
+                    );
+                    if show_back_faces {
+                        gl.disable(glow::CULL_FACE);
+                    }
                     let mesh = &body.borrow().mesh;
                     // Set the model uniform
                     gl.uniform_matrix_4_f32_slice(
@@ -394,7 +462,246 @@ impl MeshRenderer {
                         glow::UNSIGNED_INT,
                         0,
                     );
+                    count_draw(
+                        &self.frame_stats,
+                        mesh.indices.len(),
+                        std::mem::size_of_val(&mesh.vertices[..])
+                            + std::mem::size_of_val(&mesh.indices[..]),
+                    );
+                    gl.enable(glow::CULL_FACE);
                 }
+                gl.uniform_1_u32(Some(&self.visualize_back_faces_location), 0);
+
+                // Non-manifold edges, seen through the surfaces in front of them
+                if visualize_defects {
+                    let material = Material::mesh_defect();
+                    gl.uniform_1_f32(Some(&self.roughness_location), material.roughness);
+                    gl.uniform_3_f32(
+                        Some(&self.albedo_location),
+                        material.albedo.x,
+                        material.albedo.y,
+                        material.albedo.z,
+                    );
+                    gl.uniform_3_f32(
+                        Some(&self.base_reflectance_location),
+                        material.base_reflectance.x,
+                        material.base_reflectance.y,
+                        material.base_reflectance.z,
+                    );
+                    gl.uniform_1_u32(Some(&self.visualize_normals_location), 0);
+                    gl.uniform_1_u32(Some(&self.visualize_edges_location), 0);
+                    gl.disable(glow::DEPTH_TEST);
+                    for body in self.bodies.borrow().iter() {
+                        let Some(edges) = self.defect_edges.get(&Rc::as_ptr(body)) else {
+                            continue;
+                        };
+                        gl.uniform_matrix_4_f32_slice(
+                            Some(&self.model_location),
+                            false,
+                            body.borrow().get_model_matrix().as_slice(),
+                        );
+                        gl.buffer_data_u8_slice(
+                            glow::ARRAY_BUFFER,
+                            bytemuck::cast_slice(&edges.vertices),
+                            glow::STATIC_DRAW,
+                        );
+                        gl.draw_arrays(glow::LINES, 0, edges.vertices.len() as i32);
+                        count_draw(
+                            &self.frame_stats,
+                            edges.vertices.len(),
+                            std::mem::size_of_val(&edges.vertices[..]),
+                        );
+                    }
+                    gl.enable(glow::DEPTH_TEST);
+                }
+
+                // Slice preview ghost and the footprints of overlapping bodies, drawn last
+                // and pulled towards the camera so they aren't hidden by the surface they
+                // lie on
+                let draw_band = |vertices: &[Vertex], material: Material| {
+                    if vertices.is_empty() {
+                        return;
+                    }
+                    gl.uniform_1_f32(Some(&self.roughness_location), material.roughness);
+                    gl.uniform_3_f32(
+                        Some(&self.albedo_location),
+                        material.albedo.x,
+                        material.albedo.y,
+                        material.albedo.z,
+                    );
+                    gl.uniform_3_f32(
+                        Some(&self.base_reflectance_location),
+                        material.base_reflectance.x,
+                        material.base_reflectance.y,
+                        material.base_reflectance.z,
+                    );
+                    gl.uniform_1_u32(Some(&self.visualize_normals_location), 0);
+                    gl.uniform_1_u32(Some(&self.visualize_edges_location), 0);
+                    gl.uniform_matrix_4_f32_slice(
+                        Some(&self.model_location),
+                        false,
+                        Matrix4::<f32>::identity().as_slice(),
+                    );
+                    self.gl.buffer_data_u8_slice(
+                        glow::ARRAY_BUFFER,
+                        bytemuck::cast_slice(vertices),
+                        glow::STATIC_DRAW,
+                    );
+
+                    // The band is seen from both sides
+                    gl.disable(glow::CULL_FACE);
+                    gl.enable(glow::POLYGON_OFFSET_FILL);
+                    gl.polygon_offset(-1.0, -1.0);
+                    gl.draw_arrays(glow::TRIANGLES, 0, vertices.len() as i32);
+                    count_draw(
+                        &self.frame_stats,
+                        vertices.len(),
+                        std::mem::size_of_val(vertices),
+                    );
+                    gl.disable(glow::POLYGON_OFFSET_FILL);
+                    gl.enable(glow::CULL_FACE);
+                };
+                draw_band(&self.slice_ghost, Material::slice_ghost());
+                let overlapping: Vec<_> = self
+                    .bodies
+                    .borrow()
+                    .iter()
+                    .filter(|body| body.borrow().overlapping)
+                    .filter_map(|body| body.borrow().footprint())
+                    .map(|footprint| footprint_outline(&footprint))
+                    .collect();
+                draw_band(
+                    &Self::contour_band_vertices(&overlapping, 0.15),
+                    Material::footprint_overlap(),
+                );
+
+                // The vat and the resin in it, after everything solid so the bodies show
+                // through them. Without writing depth the far walls aren't hidden by the
+                // near ones.
+                if let Some(overlay) = self.vat_fill.as_ref().filter(|_| show_vat_fill) {
+                    gl.enable(glow::BLEND);
+                    gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+                    gl.depth_mask(false);
+                    gl.disable(glow::CULL_FACE);
+                    gl.uniform_1_u32(Some(&self.visualize_normals_location), 0);
+                    gl.uniform_1_u32(Some(&self.visualize_edges_location), 0);
+                    gl.uniform_matrix_4_f32_slice(
+                        Some(&self.model_location),
+                        false,
+                        Matrix4::<f32>::identity().as_slice(),
+                    );
+                    for (vertices, material, opacity) in [
+                        (&overlay.resin, Material::resin_fill(), 0.5),
+                        (&overlay.walls, Material::vat(), 0.15),
+                    ] {
+                        gl.uniform_1_f32(Some(&self.roughness_location), material.roughness);
+                        gl.uniform_3_f32(
+                            Some(&self.albedo_location),
+                            material.albedo.x,
+                            material.albedo.y,
+                            material.albedo.z,
+                        );
+                        gl.uniform_3_f32(
+                            Some(&self.base_reflectance_location),
+                            material.base_reflectance.x,
+                            material.base_reflectance.y,
+                            material.base_reflectance.z,
+                        );
+                        gl.uniform_1_f32(Some(&self.opacity_location), opacity);
+                        gl.buffer_data_u8_slice(
+                            glow::ARRAY_BUFFER,
+                            bytemuck::cast_slice(vertices),
+                            glow::STATIC_DRAW,
+                        );
+                        gl.draw_arrays(glow::TRIANGLES, 0, vertices.len() as i32);
+                        count_draw(
+                            &self.frame_stats,
+                            vertices.len(),
+                            std::mem::size_of_val(&vertices[..]),
+                        );
+                    }
+                    gl.uniform_1_f32(Some(&self.opacity_location), 1.0);
+                    gl.enable(glow::CULL_FACE);
+                    gl.depth_mask(true);
+                    gl.disable(glow::BLEND);
+                }
+
+                let draw_axes = || {
+                    for axis in Axis::ALL {
+                        let material = Material::axis(axis.color());
+                        gl.uniform_1_f32(Some(&self.roughness_location), material.roughness);
+                        gl.uniform_3_f32(
+                            Some(&self.albedo_location),
+                            material.albedo.x,
+                            material.albedo.y,
+                            material.albedo.z,
+                        );
+                        gl.uniform_3_f32(
+                            Some(&self.base_reflectance_location),
+                            material.base_reflectance.x,
+                            material.base_reflectance.y,
+                            material.base_reflectance.z,
+                        );
+                        let vertices = axis_gizmo::axis_vertices(axis);
+                        gl.buffer_data_u8_slice(
+                            glow::ARRAY_BUFFER,
+                            bytemuck::cast_slice(&vertices),
+                            glow::STATIC_DRAW,
+                        );
+                        gl.draw_arrays(glow::TRIANGLES, 0, vertices.len() as i32);
+                        count_draw(
+                            &self.frame_stats,
+                            vertices.len(),
+                            std::mem::size_of_val(&vertices[..]),
+                        );
+                    }
+                };
+                gl.uniform_1_u32(Some(&self.visualize_normals_location), 0);
+                gl.uniform_1_u32(Some(&self.visualize_edges_location), 0);
+                gl.disable(glow::CULL_FACE);
+
+                // Local axes of the selected bodies
+                if visualize_local_axes {
+                    for body in self.bodies.borrow().iter() {
+                        let body = body.borrow();
+                        if !body.selected || !body.display_in_ui_list {
+                            continue;
+                        }
+                        let model = Matrix4::new_translation(&body.position)
+                            * UnitQuaternion::from_quaternion(body.rotation).to_homogeneous()
+                            * Matrix4::new_scaling(LOCAL_AXES_LENGTH);
+                        gl.uniform_matrix_4_f32_slice(
+                            Some(&self.model_location),
+                            false,
+                            model.as_slice(),
+                        );
+                        draw_axes();
+                    }
+                }
+
+                // World axes in their own corner of the view, over everything else
+                let (_, gizmo_top, gizmo_side) =
+                    axis_gizmo::gizmo_rect(width as f32, height as f32);
+                let (gizmo_top, gizmo_side) = (gizmo_top as i32, gizmo_side as i32);
+                gl.viewport(0, gizmo_top, gizmo_side, gizmo_side);
+                gl.enable(glow::SCISSOR_TEST);
+                gl.scissor(0, gizmo_top, gizmo_side, gizmo_side);
+                gl.clear(glow::DEPTH_BUFFER_BIT);
+                gl.disable(glow::SCISSOR_TEST);
+                gl.uniform_matrix_4_f32_slice(
+                    Some(&self.view_proj_location),
+                    false,
+                    axis_gizmo::view_proj(&view).as_slice(),
+                );
+                let eye = -self.camera.get_view_direction_vector() * 3.0;
+                gl.uniform_3_f32(Some(&self.camera_position_location), eye.x, eye.y, eye.z);
+                gl.uniform_matrix_4_f32_slice(
+                    Some(&self.model_location),
+                    false,
+                    Matrix4::<f32>::identity().as_slice(),
+                );
+                draw_axes();
+                gl.enable(glow::CULL_FACE);
 
                 // Unbind the buffers
                 gl.bind_vertex_array(None);
@@ -413,6 +720,13 @@ impl MeshRenderer {
             gl.use_program(None);
         }
 
+        // Both render textures stay allocated, each with a color and a depth attachment
+        let mut frame_stats = self.frame_stats.get();
+        for texture in [&self.next_texture, &self.displayed_texture] {
+            frame_stats.vram_bytes += texture.width as u64 * texture.height as u64 * 8;
+        }
+        self.frame_stats.set(frame_stats);
+
         // Create the result texture
         let result_texture = unsafe {
             slint::BorrowedOpenGLTextureBuilder::new_gl_2d_rgba_texture(
@@ -428,18 +742,175 @@ impl MeshRenderer {
         result_texture
     }
 
-    pub fn camera_pitch_yaw(&mut self, delta_x: f32, delta_y: f32) {
+    fn camera_pitch_yaw(&mut self, delta_x: f32, delta_y: f32) {
         self.camera.pitch_yaw(delta_x, -delta_y);
     }
 
-    pub fn camera_pan(&mut self, delta_x: f32, delta_y: f32) {
+    fn camera_pan(&mut self, delta_x: f32, delta_y: f32) {
         self.camera.pan(delta_x, delta_y);
     }
 
-    pub(crate) fn zoom(&mut self, amt: f32) {
+    fn zoom(&mut self, amt: f32) {
         self.camera.zoom(amt);
     }
 
+    fn snap_to_gizmo_axis(&mut self, x: f32, y: f32, width: f32, height: f32) -> bool {
+        match axis_gizmo::hit_test(&self.camera.view_matrix(), x, y, width, height) {
+            Some(axis) => {
+                self.camera.snap_to_axis(axis);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pick_ray(
+        &self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let view_proj = self.camera.projection_matrix * self.camera.view_matrix();
+        Camera::pick_ray(&view_proj, x, y, width, height)
+    }
+
+    fn snap_view(&mut self, axis: Axis) {
+        self.camera.snap_to_axis(axis);
+    }
+
+    fn set_slice_preview_height(&mut self, height: Option<f32>) {
+        self.slice_ghost = match height {
+            Some(height) => {
+                let bodies = self.bodies.borrow();
+                let borrowed: Vec<_> = bodies
+                    .iter()
+                    .map(|body| body.borrow())
+                    .filter(|body| body.display_in_ui_list && body.visible)
+                    .collect();
+                let contours =
+                    CPUSlicer::layer_contours(borrowed.iter().map(|b| &**b), height as f64);
+                Self::contour_band_vertices(&contours, 0.15)
+            }
+            None => Vec::new(),
+        };
+    }
+
+    fn frame_stats(&self) -> FrameStats {
+        self.frame_stats.get()
+    }
+}
+
+/// Counts a draw call of `vertices` vertices. Every draw uploads its data to the same
+/// buffers first, so only the largest upload of the frame stays on the GPU.
+fn count_draw(frame_stats: &Cell<FrameStats>, vertices: usize, buffer_bytes: usize) {
+    let mut stats = frame_stats.get();
+    stats.draw_calls += 1;
+    stats.vertices += vertices as u64;
+    stats.vram_bytes = stats.vram_bytes.max(buffer_bytes as u64);
+    frame_stats.set(stats);
+}
+
+/// The hull of a footprint as a contour lying on the plate
+pub fn footprint_outline(footprint: &Footprint) -> Vec<Vector3<f64>> {
+    footprint
+        .hull()
+        .iter()
+        .map(|p| Vector3::new(p.x as f64, p.y as f64, 0.0))
+        .collect()
+}
+
+impl MeshRenderer {
+    // Finds the non-manifold edges of the bodies shown in the list, again only for meshes
+    // that changed since
+    fn update_defect_edges(&mut self) {
+        let bodies = self.bodies.borrow();
+        self.defect_edges
+            .retain(|key, _| bodies.iter().any(|body| Rc::as_ptr(body) == *key));
+        for body in bodies.iter() {
+            let b = body.borrow();
+            if !b.display_in_ui_list || !b.visible {
+                self.defect_edges.remove(&Rc::as_ptr(body));
+                continue;
+            }
+            let mesh = &b.mesh;
+            let size = (mesh.simple_vertices.len(), mesh.simple_indices.len());
+            if matches!(self.defect_edges.get(&Rc::as_ptr(body)), Some(edges) if edges.mesh_size == size)
+            {
+                continue;
+            }
+            let up = [0.0, 0.0, 1.0];
+            let barycentric = [1.0, 1.0, 1.0];
+            let vertices = mesh
+                .non_manifold_edges()
+                .iter()
+                .flatten()
+                .map(|&i| Vertex::new(mesh.simple_vertices[i as usize].position, up, barycentric))
+                .collect();
+            self.defect_edges.insert(
+                Rc::as_ptr(body),
+                DefectEdges {
+                    mesh_size: size,
+                    vertices,
+                },
+            );
+        }
+    }
+
+    // Measures the job again when a body was added, removed, scaled or had its role changed,
+    // or the printer changed
+    fn update_vat_fill(&mut self) {
+        let vat = Vat::of(&self.printer.lock().unwrap());
+        let bodies = self.bodies.borrow();
+        let key: Vec<VatFillKey> = bodies
+            .iter()
+            .map(|body| {
+                let b = body.borrow();
+                (
+                    Rc::as_ptr(body),
+                    b.mesh.vertices.len(),
+                    b.scale,
+                    b.slice_role,
+                )
+            })
+            .collect();
+        if matches!(&self.vat_fill, Some(overlay) if overlay.key.0 == vat && overlay.key.1 == key) {
+            return;
+        }
+        let borrowed: Vec<_> = bodies
+            .iter()
+            .map(|body| body.borrow())
+            .filter(|body| body.display_in_ui_list)
+            .collect();
+        let fill = vat.fill(material_ledger::job_volume(borrowed.iter().map(|b| &**b)));
+        self.vat_fill = Some(VatFillOverlay {
+            key: (vat, key),
+            walls: vat.wall_vertices(),
+            resin: vat.resin_vertices(fill.level),
+        });
+    }
+
+    // Extrudes each contour edge into a vertical quad reaching half_height above and below it
+    fn contour_band_vertices(contours: &[Vec<Vector3<f64>>], half_height: f32) -> Vec<Vertex> {
+        let up = [0.0, 0.0, 1.0];
+        let barycentric = [1.0, 1.0, 1.0];
+        let mut vertices = Vec::new();
+        for contour in contours {
+            for i in 0..contour.len() {
+                let a = contour[i].cast::<f32>();
+                let b = contour[(i + 1) % contour.len()].cast::<f32>();
+                let a_low = [a.x, a.y, a.z - half_height];
+                let a_high = [a.x, a.y, a.z + half_height];
+                let b_low = [b.x, b.y, b.z - half_height];
+                let b_high = [b.x, b.y, b.z + half_height];
+                for position in [a_low, b_low, b_high, a_low, b_high, a_high] {
+                    vertices.push(Vertex::new(position, up, barycentric));
+                }
+            }
+        }
+        vertices
+    }
+
     fn create_xy_plane_mesh() -> Mesh {
         let vertices = vec![
             Vertex {
@@ -477,7 +948,7 @@ impl MeshRenderer {
         }
     }
 
-    fn create_plane_body(x: f32, y: f32) -> Rc<RefCell<Body>> {
+    pub(crate) fn create_plane_body(x: f32, y: f32) -> Rc<RefCell<Body>> {
         let plane_mesh = Self::create_xy_plane_mesh();
         let mut body = Body::new(plane_mesh);
         body.set_position(Vector3::new(0.0, 0.0, 0.0));
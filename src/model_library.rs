@@ -0,0 +1,323 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::{Body, AABB};
+use crate::mesh_cache::MeshCache;
+use crate::software_renderer::SoftwareRenderer;
+use crate::stl_processor::StlProcessor;
+use crate::three_mf::{self, ThreeMfError};
+use dirs_next::cache_dir;
+use image::{ImageError, RgbImage};
+use nalgebra::{Vector2, Vector3};
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// Width and height of the thumbnails, in pixels
+pub const THUMBNAIL_SIZE: u32 = 96;
+/// Bump whenever thumbnails are drawn differently, so the cached ones are drawn again
+const THUMBNAIL_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum LibraryError {
+    #[error("Could not read the model: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Could not read the model: {0}")]
+    ThreeMf(#[from] ThreeMfError),
+
+    #[error("Could not store the thumbnail: {0}")]
+    Image(#[from] ImageError),
+}
+
+/// A model file in the library folder
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    /// File name without its extension
+    pub name: String,
+    /// Subfolder of the library the file is in, empty at the top
+    pub folder: String,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl LibraryEntry {
+    /// Changes whenever the file does, so edited models get a new thumbnail
+    fn thumbnail_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.path.hash(&mut hasher);
+        self.len.hash(&mut hasher);
+        self.modified.hash(&mut hasher);
+        THUMBNAIL_VERSION.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn is_3mf(&self) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("3mf"))
+    }
+
+    /// The bodies of the model the way they are stored in the file
+    pub fn load_bodies(&self, mesh_cache: Option<&MeshCache>) -> Result<Vec<Body>, LibraryError> {
+        if self.is_3mf() {
+            return Ok(three_mf::import(&self.path)?);
+        }
+        let stl_processor = StlProcessor::new();
+        let body = match mesh_cache {
+            Some(cache) => Body::new_from_stl_cached(&self.path, &stl_processor, cache)?,
+            None => Body::new_from_stl(&self.path, &stl_processor)?,
+        };
+        Ok(vec![body])
+    }
+}
+
+/// The STL and 3MF files of a folder the user keeps their models in, subfolders included
+pub struct ModelLibrary {
+    dir: PathBuf,
+    entries: Vec<LibraryEntry>,
+}
+
+impl ModelLibrary {
+    /// Reads the folder and its subfolders, leaving out hidden ones. Entries are sorted by
+    /// folder and name.
+    pub fn scan(dir: &Path) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        Self::scan_folder(dir, dir, &mut entries)?;
+        entries.sort_by_cached_key(|entry: &LibraryEntry| {
+            (entry.folder.to_lowercase(), entry.name.to_lowercase())
+        });
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            entries,
+        })
+    }
+
+    fn scan_folder(root: &Path, dir: &Path, entries: &mut Vec<LibraryEntry>) -> io::Result<()> {
+        for item in fs::read_dir(dir)? {
+            let item = item?;
+            let path = item.path();
+            if item.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let metadata = item.metadata()?;
+            if metadata.is_dir() {
+                // An unreadable subfolder shouldn't hide the rest of the library
+                if let Err(e) = Self::scan_folder(root, &path, entries) {
+                    println!("Skipping {} in the model library: {}", path.display(), e);
+                }
+                continue;
+            }
+            let is_model = path.extension().is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("stl") || extension.eq_ignore_ascii_case("3mf")
+            });
+            if !is_model {
+                continue;
+            }
+            let folder = path
+                .parent()
+                .and_then(|parent| parent.strip_prefix(root).ok())
+                .map(|folder| folder.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            entries.push(LibraryEntry {
+                name: path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                folder,
+                path,
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn entries(&self) -> &[LibraryEntry] {
+        &self.entries
+    }
+
+    /// Entries whose name or folder contains every word of `query`, ignoring case. An
+    /// empty query finds them all.
+    pub fn search(&self, query: &str) -> Vec<&LibraryEntry> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        self.entries
+            .iter()
+            .filter(|entry| {
+                let text = format!("{} {}", entry.folder, entry.name).to_lowercase();
+                words.iter().all(|word| text.contains(word.as_str()))
+            })
+            .collect()
+    }
+}
+
+/// On-disk cache of the thumbnails of the library, rendered offscreen the first time a
+/// model is shown
+pub struct ThumbnailCache {
+    dir: PathBuf,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Cache in the user's cache directory, e.g. ~/.cache/SealSlicer/thumbnails
+    pub fn in_user_cache_dir() -> Option<Self> {
+        cache_dir().map(|dir| Self::new(dir.join("SealSlicer").join("thumbnails")))
+    }
+
+    fn entry_path(&self, entry: &LibraryEntry) -> PathBuf {
+        self.dir.join(format!("{:016x}.png", entry.thumbnail_key()))
+    }
+
+    /// The cached thumbnail of the entry, rendering and storing it when there is none
+    pub fn get_or_render(
+        &self,
+        entry: &LibraryEntry,
+        mesh_cache: Option<&MeshCache>,
+    ) -> Result<RgbImage, LibraryError> {
+        let path = self.entry_path(entry);
+        if let Ok(cached) = image::open(&path) {
+            return Ok(cached.to_rgb8());
+        }
+        let thumbnail = SoftwareRenderer::thumbnail(entry.load_bodies(mesh_cache)?, THUMBNAIL_SIZE);
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first so a crash never leaves a truncated entry behind
+        let temp_path = path.with_extension("tmp");
+        thumbnail.save_with_format(&temp_path, image::ImageFormat::Png)?;
+        fs::rename(temp_path, path)?;
+        Ok(thumbnail)
+    }
+}
+
+/// Where a ray through the 3D view meets the plate, None when it doesn't reach it
+pub fn plate_point(origin: Vector3<f32>, direction: Vector3<f32>) -> Option<Vector2<f32>> {
+    if direction.z.abs() < f32::EPSILON {
+        return None;
+    }
+    let t = -origin.z / direction.z;
+    (t >= 0.0).then(|| (origin + direction * t).xy())
+}
+
+/// Moves the bodies of a dropped model together so the center of their bounds is above
+/// `point` of the plate
+pub fn center_on(bodies: &[Rc<RefCell<Body>>], point: Vector2<f32>) {
+    let bounds = bodies
+        .iter()
+        .filter_map(|body| body.borrow().world_aabb())
+        .reduce(|a, b| AABB {
+            min: a.min.inf(&b.min),
+            max: a.max.sup(&b.max),
+        });
+    let Some(bounds) = bounds else {
+        return;
+    };
+    let offset = point - (bounds.min.xy() + bounds.max.xy()) / 2.0;
+    for body in bodies {
+        let mut body = body.borrow_mut();
+        let position = body.position + Vector3::new(offset.x, offset.y, 0.0);
+        body.set_position(position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    fn library_dir() -> TempDir {
+        let dir = tempdir().unwrap();
+        let parts = dir.path().join("Parts");
+        fs::create_dir(&parts).unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::copy("test_stls/with_holes.stl", parts.join("Bracket.stl")).unwrap();
+        fs::copy(
+            "test_stls/with_holes.stl",
+            dir.path().join("calibration.STL"),
+        )
+        .unwrap();
+        fs::write(dir.path().join(".git").join("hidden.stl"), b"").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_scan_and_search() {
+        let dir = library_dir();
+        let library = ModelLibrary::scan(dir.path()).unwrap();
+        let names: Vec<_> = library
+            .entries()
+            .iter()
+            .map(|entry| (entry.folder.as_str(), entry.name.as_str()))
+            .collect();
+        assert_eq!(names, [("", "calibration"), ("Parts", "Bracket")]);
+
+        assert_eq!(library.search("").len(), 2);
+        assert_eq!(library.search("  BRACK ")[0].name, "Bracket");
+        // Words match the folder too, all of them have to
+        assert_eq!(library.search("parts bracket").len(), 1);
+        assert!(library.search("parts calibration").is_empty());
+    }
+
+    #[test]
+    fn test_thumbnails_are_cached() {
+        let dir = library_dir();
+        let library = ModelLibrary::scan(dir.path()).unwrap();
+        let entry = &library.entries()[0];
+        let cache_dir = tempdir().unwrap();
+        let cache = ThumbnailCache::new(cache_dir.path().to_path_buf());
+
+        let thumbnail = cache.get_or_render(entry, None).unwrap();
+        assert_eq!(thumbnail.dimensions(), (THUMBNAIL_SIZE, THUMBNAIL_SIZE));
+        assert!(cache.entry_path(entry).exists());
+
+        // A changed file gets a thumbnail of its own
+        let mut changed = entry.clone();
+        changed.len += 1;
+        assert_ne!(cache.entry_path(&changed), cache.entry_path(entry));
+
+        // The cached one is used without reading the model again
+        fs::remove_file(&entry.path).unwrap();
+        assert_eq!(cache.get_or_render(entry, None).unwrap(), thumbnail);
+        assert!(cache.get_or_render(&changed, None).is_err());
+    }
+
+    #[test]
+    fn test_dropping_on_the_plate() {
+        let origin = Vector3::new(10.0, 0.0, 100.0);
+        assert_eq!(
+            plate_point(origin, Vector3::new(0.0, 1.0, -1.0)),
+            Some(Vector2::new(10.0, 100.0))
+        );
+        assert_eq!(plate_point(origin, Vector3::new(0.0, 1.0, 1.0)), None);
+        assert_eq!(plate_point(origin, Vector3::new(1.0, 0.0, 0.0)), None);
+
+        let dir = library_dir();
+        let library = ModelLibrary::scan(dir.path()).unwrap();
+        let bodies: Vec<_> = library.entries()[0]
+            .load_bodies(None)
+            .unwrap()
+            .into_iter()
+            .map(|body| Rc::new(RefCell::new(body)))
+            .collect();
+        let z = bodies[0].borrow().position.z;
+        center_on(&bodies, Vector2::new(-20.0, 35.0));
+        let bounds = bodies[0].borrow().world_aabb().unwrap();
+        let center = (bounds.min + bounds.max) / 2.0;
+        assert!((center.xy() - Vector2::new(-20.0, 35.0)).norm() < 1e-3);
+        assert_eq!(bodies[0].borrow().position.z, z);
+    }
+}
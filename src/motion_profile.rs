@@ -0,0 +1,450 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::number_format;
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+
+const EXPOSURE_COLOR: Rgb<u8> = Rgb([70, 110, 220]);
+const LIFT_COLOR: Rgb<u8> = Rgb([240, 150, 40]);
+const RETRACT_COLOR: Rgb<u8> = Rgb([90, 180, 90]);
+const REST_COLOR: Rgb<u8> = Rgb([170, 170, 170]);
+const BACKGROUND_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Exposure and Z motion of every layer. Moves use two-stage motion control (TSMC):
+/// the part of the move close to the film runs slowly, the rest of it fast.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct MotionProfile {
+    pub exposure_time: f64,        // seconds
+    pub bottom_exposure_time: f64, // seconds
+    pub bottom_layer_count: usize,
+    pub lift_distance: f64,         // millimeters
+    pub lift_slow_distance: f64,    // millimeters, peeling the layer off the film
+    pub lift_slow_speed: f64,       // millimeters per minute
+    pub lift_fast_speed: f64,       // millimeters per minute
+    pub retract_slow_distance: f64, // millimeters, pushing the resin out from under the plate
+    pub retract_slow_speed: f64,    // millimeters per minute
+    pub retract_fast_speed: f64,    // millimeters per minute
+    pub rest_before_exposure: f64,  // seconds
+}
+
+impl Default for MotionProfile {
+    fn default() -> Self {
+        Self {
+            exposure_time: 2.5,
+            bottom_exposure_time: 25.0,
+            bottom_layer_count: 4,
+            lift_distance: 6.0,
+            lift_slow_distance: 2.0,
+            lift_slow_speed: 60.0,
+            lift_fast_speed: 180.0,
+            retract_slow_distance: 2.0,
+            retract_slow_speed: 60.0,
+            retract_fast_speed: 180.0,
+            rest_before_exposure: 1.0,
+        }
+    }
+}
+
+/// Where the time of one layer goes, in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LayerTiming {
+    pub exposure: f64,
+    pub lift: f64,
+    pub retract: f64,
+    pub rest: f64,
+}
+
+impl LayerTiming {
+    pub fn motion(&self) -> f64 {
+        self.lift + self.retract + self.rest
+    }
+
+    pub fn total(&self) -> f64 {
+        self.exposure + self.motion()
+    }
+}
+
+impl MotionProfile {
+    /// Seconds a move of `distance` takes when its first `slow_distance` runs at the slow speed
+    fn move_time(distance: f64, slow_distance: f64, slow_speed: f64, fast_speed: f64) -> f64 {
+        let slow = slow_distance.clamp(0.0, distance.max(0.0));
+        let fast = (distance - slow).max(0.0);
+        let minutes = |distance: f64, speed: f64| if speed > 0.0 { distance / speed } else { 0.0 };
+        (minutes(slow, slow_speed) + minutes(fast, fast_speed)) * 60.0
+    }
+
+    pub fn layer_timing(&self, layer: usize, layer_height: f64) -> LayerTiming {
+        let exposure = if layer < self.bottom_layer_count {
+            self.bottom_exposure_time
+        } else {
+            self.exposure_time
+        };
+        // The plate comes back down one layer height higher than it left
+        let retract_distance = (self.lift_distance - layer_height).max(0.0);
+        LayerTiming {
+            exposure,
+            lift: Self::move_time(
+                self.lift_distance,
+                self.lift_slow_distance,
+                self.lift_slow_speed,
+                self.lift_fast_speed,
+            ),
+            retract: Self::move_time(
+                retract_distance,
+                self.retract_slow_distance,
+                self.retract_slow_speed,
+                self.retract_fast_speed,
+            ),
+            rest: self.rest_before_exposure,
+        }
+    }
+
+    pub fn timeline(&self, layer_count: usize, layer_height: f64) -> Vec<LayerTiming> {
+        (0..layer_count)
+            .map(|layer| self.layer_timing(layer, layer_height))
+            .collect()
+    }
+}
+
+/// Corrects the estimates of a printer to the print times measured on it. The motion of
+/// every layer takes `motion_factor` times as long as its speeds say, plus `layer_overhead`
+/// seconds the firmware spends between layers.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct TimeCalibration {
+    pub motion_factor: f64,
+    pub layer_overhead: f64, // seconds
+}
+
+impl Default for TimeCalibration {
+    fn default() -> Self {
+        Self {
+            motion_factor: 1.0,
+            layer_overhead: 0.0,
+        }
+    }
+}
+
+/// A print whose time was measured, with what the estimate expected of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeasuredPrint {
+    /// Seconds of exposure and of motion the uncalibrated estimate adds up to
+    pub exposure: f64,
+    pub motion: f64,
+    pub layer_count: usize,
+    /// Seconds the print really took
+    pub measured: f64,
+}
+
+impl MeasuredPrint {
+    pub fn of(
+        motion: &MotionProfile,
+        layer_count: usize,
+        layer_height: f64,
+        measured: f64,
+    ) -> Self {
+        let timeline = motion.timeline(layer_count, layer_height);
+        Self {
+            exposure: timeline.iter().map(|t| t.exposure).sum(),
+            motion: timeline.iter().map(LayerTiming::motion).sum(),
+            layer_count,
+            measured,
+        }
+    }
+}
+
+impl TimeCalibration {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The least squares fit to the prints, None without any. Prints that all move the
+    /// same way can't tell a slower motion from a pause between layers, so only the motion
+    /// factor is fitted to them.
+    pub fn fit(prints: &[MeasuredPrint]) -> Option<Self> {
+        let prints: Vec<&MeasuredPrint> = prints
+            .iter()
+            .filter(|p| p.layer_count > 0 && p.measured > 0.0)
+            .collect();
+        if prints.is_empty() {
+            return None;
+        }
+        let sum =
+            |term: &dyn Fn(&MeasuredPrint) -> f64| prints.iter().map(|&p| term(p)).sum::<f64>();
+        let layers = |p: &MeasuredPrint| p.layer_count as f64;
+        let unexplained = |p: &MeasuredPrint| p.measured - p.exposure;
+        let mm = sum(&|p| p.motion * p.motion);
+        let mn = sum(&|p| p.motion * layers(p));
+        let nn = sum(&|p| layers(p) * layers(p));
+        let my = sum(&|p| p.motion * unexplained(p));
+        let ny = sum(&|p| layers(p) * unexplained(p));
+        let determinant = mm * nn - mn * mn;
+        if determinant > COLLINEAR_TOLERANCE * mm * nn {
+            let motion_factor = (my * nn - ny * mn) / determinant;
+            let layer_overhead = (ny * mm - my * mn) / determinant;
+            if motion_factor > 0.0 {
+                return Some(Self {
+                    motion_factor,
+                    layer_overhead,
+                });
+            }
+        }
+        if mm > 0.0 {
+            return Some(Self {
+                motion_factor: (my / mm).max(0.0),
+                layer_overhead: 0.0,
+            });
+        }
+        // Nothing moves, everything past the exposure is time between layers
+        Some(Self {
+            motion_factor: 1.0,
+            layer_overhead: ny / nn,
+        })
+    }
+
+    pub fn apply(&self, timeline: &mut [LayerTiming]) {
+        for timing in timeline {
+            timing.lift *= self.motion_factor;
+            timing.retract *= self.motion_factor;
+            timing.rest = timing.rest * self.motion_factor + self.layer_overhead;
+        }
+    }
+
+    /// e.g. "motion x1.12 + 0.8 s per layer"
+    pub fn describe(&self) -> String {
+        format!(
+            "motion x{} + {} s per layer",
+            number_format::decimal(self.motion_factor, 2),
+            number_format::decimal(self.layer_overhead, 1)
+        )
+    }
+}
+
+/// Below this, relative to how much the prints differ in motion and in layers, the two
+/// can't be told apart
+const COLLINEAR_TOLERANCE: f64 = 1e-6;
+
+/// e.g. "1h 05m", "12m 30s" or "8s"
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Seconds of a duration typed by the user, like "1h 05m", "2:30" for hours and minutes,
+/// "2:30:15" with seconds too, or a plain number of minutes
+pub fn parse_duration(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if text.contains(':') {
+        let parts: Vec<f64> = text
+            .split(':')
+            .map(|part| part.trim().parse::<u32>().ok().map(f64::from))
+            .collect::<Option<_>>()?;
+        return match parts[..] {
+            [hours, minutes] => Some(hours * 3600.0 + minutes * 60.0),
+            [hours, minutes, seconds] => Some(hours * 3600.0 + minutes * 60.0 + seconds),
+            _ => None,
+        };
+    }
+    if let Some(minutes) = number_format::parse(text) {
+        return (minutes >= 0.0).then_some(minutes * 60.0);
+    }
+    let mut seconds = 0.0;
+    for part in text.split_whitespace() {
+        let split = part.find(|c: char| c.is_alphabetic())?;
+        let value: f64 = number_format::parse(&part[..split])?;
+        seconds += value
+            * match &part[split..] {
+                "h" => 3600.0,
+                "m" | "min" => 60.0,
+                "s" => 1.0,
+                _ => return None,
+            };
+    }
+    (seconds > 0.0).then_some(seconds)
+}
+
+/// Total time split into exposure and motion
+pub fn summary(timeline: &[LayerTiming]) -> String {
+    let exposure: f64 = timeline.iter().map(|t| t.exposure).sum();
+    let motion: f64 = timeline.iter().map(|t| t.motion()).sum();
+    let total = exposure + motion;
+    if total <= 0.0 {
+        return "No layers".to_string();
+    }
+    format!(
+        "{} total: exposure {}, motion {} ({:.0}%)",
+        format_duration(total),
+        format_duration(exposure),
+        format_duration(motion),
+        motion / total * 100.0
+    )
+}
+
+/// Stacked bar chart of the time of every layer, bottom to top exposure, lift, retract
+/// and rest. Layers are averaged when there are more of them than columns.
+pub fn render_timeline(timeline: &[LayerTiming], width: u32, height: u32) -> RgbImage {
+    let mut chart = RgbImage::from_pixel(width, height, BACKGROUND_COLOR);
+    if timeline.is_empty() || width == 0 {
+        return chart;
+    }
+    let longest = timeline.iter().map(LayerTiming::total).fold(0.0, f64::max);
+    if longest <= 0.0 {
+        return chart;
+    }
+
+    let columns = (width as usize).min(timeline.len());
+    let column_width = width as usize / columns;
+    for column in 0..columns {
+        let layers =
+            &timeline[column * timeline.len() / columns..(column + 1) * timeline.len() / columns];
+        let average = |part: fn(&LayerTiming) -> f64| {
+            layers.iter().map(part).sum::<f64>() / layers.len() as f64
+        };
+        let mut top = height as f64;
+        for (seconds, color) in [
+            (average(|t| t.exposure), EXPOSURE_COLOR),
+            (average(|t| t.lift), LIFT_COLOR),
+            (average(|t| t.retract), RETRACT_COLOR),
+            (average(|t| t.rest), REST_COLOR),
+        ] {
+            let bottom = top;
+            top -= seconds / longest * height as f64;
+            for y in top.round().max(0.0) as u32..bottom.round() as u32 {
+                for x in column * column_width..(column + 1) * column_width {
+                    chart.put_pixel(x as u32, y, color);
+                }
+            }
+        }
+    }
+    chart
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_timing() {
+        let profile = MotionProfile::default();
+
+        let bottom = profile.layer_timing(0, 0.05);
+        assert_eq!(bottom.exposure, 25.0);
+        // 2mm at 60mm/min and 4mm at 180mm/min
+        assert!((bottom.lift - (2.0 + 4.0 / 3.0)).abs() < 1e-9);
+        // 5.95mm back down, the last 2mm slowly
+        assert!((bottom.retract - (2.0 + 3.95 / 3.0)).abs() < 1e-9);
+        assert_eq!(bottom.rest, 1.0);
+
+        let normal = profile.layer_timing(4, 0.05);
+        assert_eq!(normal.exposure, 2.5);
+        assert_eq!(normal.motion(), bottom.motion());
+
+        // A slow stage longer than the whole move only runs slowly
+        let short = MotionProfile {
+            lift_distance: 1.0,
+            ..MotionProfile::default()
+        };
+        assert!((short.layer_timing(10, 0.05).lift - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_summary_and_format() {
+        assert_eq!(format_duration(8.4), "8s");
+        assert_eq!(format_duration(750.0), "12m 30s");
+        assert_eq!(format_duration(3900.0), "1h 05m");
+
+        let timeline = vec![
+            LayerTiming {
+                exposure: 30.0,
+                lift: 10.0,
+                retract: 10.0,
+                rest: 10.0,
+            };
+            10
+        ];
+        assert_eq!(
+            summary(&timeline),
+            "10m 00s total: exposure 5m 00s, motion 5m 00s (50%)"
+        );
+        assert_eq!(summary(&[]), "No layers");
+    }
+
+    #[test]
+    fn test_time_calibration() {
+        let profile = MotionProfile::default();
+        let slow = MotionProfile {
+            lift_fast_speed: 90.0,
+            retract_fast_speed: 90.0,
+            ..MotionProfile::default()
+        };
+        // The printer really moves 10% slower and pauses 1.5 s between layers
+        let truth = TimeCalibration {
+            motion_factor: 1.1,
+            layer_overhead: 1.5,
+        };
+        let measure = |motion: &MotionProfile, layer_count: usize| {
+            let mut timeline = motion.timeline(layer_count, 0.05);
+            truth.apply(&mut timeline);
+            let measured = timeline.iter().map(LayerTiming::total).sum();
+            MeasuredPrint::of(motion, layer_count, 0.05, measured)
+        };
+        let prints = [measure(&profile, 400), measure(&slow, 250)];
+        let fitted = TimeCalibration::fit(&prints).unwrap();
+        assert!((fitted.motion_factor - 1.1).abs() < 1e-6);
+        assert!((fitted.layer_overhead - 1.5).abs() < 1e-6);
+
+        // Prints moving alike only tell how much slower the motion is overall
+        let alike = TimeCalibration::fit(&[measure(&profile, 400), measure(&profile, 100)]);
+        let alike = alike.unwrap();
+        assert_eq!(alike.layer_overhead, 0.0);
+        let per_layer = profile.layer_timing(10, 0.05).motion();
+        assert!((alike.motion_factor - (1.1 + 1.5 / per_layer)).abs() < 1e-6);
+
+        assert_eq!(TimeCalibration::fit(&[]), None);
+        assert!(TimeCalibration::default().is_identity());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1h 05m"), Some(3900.0));
+        assert_eq!(parse_duration("12m 30s"), Some(750.0));
+        assert_eq!(parse_duration("2:30"), Some(9000.0));
+        assert_eq!(parse_duration("2:30:15"), Some(9015.0));
+        assert_eq!(parse_duration("90"), Some(5400.0));
+        assert_eq!(parse_duration("1,5"), Some(90.0));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration("2:x"), None);
+    }
+
+    #[test]
+    fn test_render_timeline() {
+        let profile = MotionProfile::default();
+        let timeline = profile.timeline(400, 0.05);
+
+        let chart = render_timeline(&timeline, 200, 100);
+
+        assert_eq!(chart.dimensions(), (200, 100));
+        // The bottom layers are the tallest bars and start with exposure
+        assert_eq!(*chart.get_pixel(0, 99), EXPOSURE_COLOR);
+        assert_eq!(*chart.get_pixel(0, 0), REST_COLOR);
+        // Normal layers spend most of their time moving
+        assert_eq!(*chart.get_pixel(199, 0), BACKGROUND_COLOR);
+        assert_eq!(*chart.get_pixel(199, 99), EXPOSURE_COLOR);
+        assert!(chart.pixels().any(|pixel| *pixel == LIFT_COLOR));
+        assert!(chart.pixels().any(|pixel| *pixel == RETRACT_COLOR));
+        assert_eq!(
+            render_timeline(&[], 10, 10),
+            RgbImage::from_pixel(10, 10, BACKGROUND_COLOR)
+        );
+    }
+}
@@ -0,0 +1,292 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::printer::Printer;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+/// What a printer reports while it receives and prints a job
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrinterStatus {
+    Idle,
+    Printing {
+        job: String,
+        layer: usize,
+        total_layers: usize,
+    },
+    Finished {
+        job: String,
+    },
+    Failed {
+        job: String,
+        reason: String,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum NetworkPrinterError {
+    #[error("Could not read the job: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("{0} contains no layers")]
+    EmptyJob(String),
+
+    #[error("The printer has no job called {0}")]
+    UnknownJob(String),
+
+    #[error("The printer is busy printing {0}")]
+    Busy(String),
+}
+
+/// A printer that exported jobs are sent to over the network
+pub trait NetworkPrinter: Send {
+    fn name(&self) -> &str;
+
+    /// Sends the layers of an exported job, returning the name the printer stores it under
+    fn upload(&mut self, job_dir: &Path) -> Result<String, NetworkPrinterError>;
+
+    fn start_print(&mut self, job: &str) -> Result<(), NetworkPrinterError>;
+
+    fn status(&mut self) -> Result<PrinterStatus, NetworkPrinterError>;
+}
+
+static SIMULATOR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Sends every export to a simulated printer, set by `--simulate-printer`
+pub fn set_simulator_enabled(enabled: bool) {
+    SIMULATOR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn simulator_enabled() -> bool {
+    SIMULATOR_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Stands in for real hardware in end-to-end tests. Every status poll "prints" the
+/// next layers by decoding them and checking they match the resolution of the printer.
+pub struct SimulatedPrinter {
+    name: String,
+    resolution: (u32, u32),
+    layers_per_poll: usize,
+    jobs: BTreeMap<String, Vec<Vec<u8>>>,
+    status: PrinterStatus,
+}
+
+impl SimulatedPrinter {
+    pub fn new(printer: &Printer, layers_per_poll: usize) -> Self {
+        Self {
+            name: format!("{} (simulated)", printer.name),
+            resolution: (printer.pixel_x, printer.pixel_y),
+            layers_per_poll: layers_per_poll.max(1),
+            jobs: BTreeMap::new(),
+            status: PrinterStatus::Idle,
+        }
+    }
+
+    /// Checks a layer the way the firmware would before exposing it
+    fn expose(&self, bytes: &[u8]) -> Result<(), String> {
+        let image = webp::Decoder::new(bytes)
+            .decode()
+            .ok_or_else(|| "not a valid WebP image".to_string())?;
+        if (image.width(), image.height()) != self.resolution {
+            return Err(format!(
+                "{}x{} does not match the {}x{} screen",
+                image.width(),
+                image.height(),
+                self.resolution.0,
+                self.resolution.1
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl NetworkPrinter for SimulatedPrinter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn upload(&mut self, job_dir: &Path) -> Result<String, NetworkPrinterError> {
+        let job = job_dir.file_name().map_or_else(
+            || "job".to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+
+        let mut layer_paths: Vec<_> = fs::read_dir(job_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        layer_paths.retain(|path| {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            file_name.starts_with("slice_") && file_name.ends_with(".webp")
+        });
+        // The layer number is zero padded, so name order is print order
+        layer_paths.sort();
+        if layer_paths.is_empty() {
+            return Err(NetworkPrinterError::EmptyJob(job));
+        }
+
+        let layers = layer_paths.iter().map(fs::read).collect::<Result<_, _>>()?;
+        self.jobs.insert(job.clone(), layers);
+        Ok(job)
+    }
+
+    fn start_print(&mut self, job: &str) -> Result<(), NetworkPrinterError> {
+        if let PrinterStatus::Printing { job, .. } = &self.status {
+            return Err(NetworkPrinterError::Busy(job.clone()));
+        }
+        let layers = self
+            .jobs
+            .get(job)
+            .ok_or_else(|| NetworkPrinterError::UnknownJob(job.to_string()))?;
+        self.status = PrinterStatus::Printing {
+            job: job.to_string(),
+            layer: 0,
+            total_layers: layers.len(),
+        };
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<PrinterStatus, NetworkPrinterError> {
+        if let PrinterStatus::Printing {
+            job,
+            layer,
+            total_layers,
+        } = &self.status
+        {
+            let layers = &self.jobs[job];
+            let end = (layer + self.layers_per_poll).min(*total_layers);
+            let failure = (*layer..end).find_map(|i| self.expose(&layers[i]).err().map(|e| (i, e)));
+            self.status = match failure {
+                Some((i, reason)) => PrinterStatus::Failed {
+                    job: job.clone(),
+                    reason: format!("Layer {} {}", i, reason),
+                },
+                None if end == *total_layers => PrinterStatus::Finished { job: job.clone() },
+                None => PrinterStatus::Printing {
+                    job: job.clone(),
+                    layer: end,
+                    total_layers: *total_layers,
+                },
+            };
+        }
+        Ok(self.status.clone())
+    }
+}
+
+/// Uploads and prints an export on a simulated printer, logging every status change
+pub fn run_simulated_print(
+    printer: &Printer,
+    job_dir: &Path,
+) -> Result<PrinterStatus, NetworkPrinterError> {
+    let mut simulator = SimulatedPrinter::new(printer, 10);
+    let job = simulator.upload(job_dir)?;
+    println!("Uploaded {} to {}", job, simulator.name());
+    simulator.start_print(&job)?;
+    loop {
+        let status = simulator.status()?;
+        println!("{}: {:?}", simulator.name(), status);
+        if !matches!(status, PrinterStatus::Printing { .. }) {
+            return Ok(status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export_queue::{export, ExportControl, ExportJob, ExportLayers};
+    use crate::settings::CompressionSettings;
+    use image::{ImageBuffer, Luma};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn exported_job(dir: &Path, printer: &Printer, layer_count: usize) -> std::path::PathBuf {
+        let job = ExportJob {
+            layers: ExportLayers::InMemory(Arc::new(
+                (0..layer_count)
+                    .map(|_| ImageBuffer::from_pixel(printer.pixel_x, printer.pixel_y, Luma([0u8])))
+                    .collect(),
+            )),
+            previews: Vec::new(),
+            output_dir: dir.join("job"),
+            printer_file: None,
+            compression: CompressionSettings::default(),
+        };
+        export(&job, &ExportControl::default()).unwrap()
+    }
+
+    #[test]
+    fn test_export_upload_and_print() {
+        let dir = tempdir().unwrap();
+        let printer = Printer::default();
+        let job_dir = exported_job(dir.path(), &printer, 5);
+        let mut simulator = SimulatedPrinter::new(&printer, 2);
+        assert_eq!(simulator.status().unwrap(), PrinterStatus::Idle);
+
+        let job = simulator.upload(&job_dir).unwrap();
+        assert_eq!(job, "job");
+        simulator.start_print(&job).unwrap();
+        assert!(matches!(
+            simulator.start_print(&job),
+            Err(NetworkPrinterError::Busy(_))
+        ));
+
+        let mut layers = Vec::new();
+        let finished = loop {
+            match simulator.status().unwrap() {
+                PrinterStatus::Printing {
+                    layer,
+                    total_layers,
+                    ..
+                } => {
+                    assert_eq!(total_layers, 5);
+                    layers.push(layer);
+                }
+                status => break status,
+            }
+        };
+        assert_eq!(layers, vec![2, 4]);
+        assert_eq!(finished, PrinterStatus::Finished { job: job.clone() });
+
+        // A finished printer takes the next job
+        simulator.start_print(&job).unwrap();
+    }
+
+    #[test]
+    fn test_print_fails_on_bad_layers() {
+        let dir = tempdir().unwrap();
+        let printer = Printer::default();
+        let job_dir = exported_job(dir.path(), &printer, 3);
+        fs::write(job_dir.join("slice_0001.webp"), b"not an image").unwrap();
+
+        let status = run_simulated_print(&printer, &job_dir).unwrap();
+
+        match status {
+            PrinterStatus::Failed { reason, .. } => assert!(reason.starts_with("Layer 1 ")),
+            status => panic!("Expected the print to fail, got {:?}", status),
+        }
+    }
+
+    #[test]
+    fn test_upload_errors() {
+        let dir = tempdir().unwrap();
+        let printer = Printer::default();
+        let mut simulator = SimulatedPrinter::new(&printer, 1);
+
+        assert!(matches!(
+            simulator.upload(dir.path()),
+            Err(NetworkPrinterError::EmptyJob(_))
+        ));
+        assert!(matches!(
+            simulator.upload(&dir.path().join("missing")),
+            Err(NetworkPrinterError::Io(_))
+        ));
+        assert!(matches!(
+            simulator.start_print("missing"),
+            Err(NetworkPrinterError::UnknownJob(_))
+        ));
+    }
+}
@@ -0,0 +1,163 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Languages that write decimals with a comma, as in the first part of a locale like `de_DE`
+const COMMA_LANGUAGES: [&str; 30] = [
+    "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr", "hu", "id", "is",
+    "it", "lt", "lv", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sv",
+];
+
+/// Countries that write decimals with a point even though their language usually doesn't
+const POINT_COUNTRIES: [&str; 2] = ["CH", "LI"];
+
+/// How decimals are written in the UI and in reports
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DecimalSeparator {
+    /// Whatever the locale of the system uses
+    #[default]
+    Auto,
+    Point,
+    Comma,
+}
+
+static DECIMAL_COMMA: AtomicBool = AtomicBool::new(false);
+
+impl DecimalSeparator {
+    /// Point or comma, looking `Auto` up in the locale of the system
+    pub fn resolve(self) -> Self {
+        match self {
+            DecimalSeparator::Auto => ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|locale| !locale.is_empty())
+                .map_or(DecimalSeparator::Point, |locale| Self::of_locale(&locale)),
+            separator => separator,
+        }
+    }
+
+    /// The separator of a POSIX locale name like `de_DE.UTF-8` or `pt-BR`
+    pub fn of_locale(locale: &str) -> Self {
+        let name = locale.split(['.', '@']).next().unwrap_or_default();
+        let mut parts = name.split(['_', '-']);
+        let language = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let country = parts.next().unwrap_or_default().to_ascii_uppercase();
+        if COMMA_LANGUAGES.contains(&language.as_str())
+            && !POINT_COUNTRIES.contains(&country.as_str())
+        {
+            DecimalSeparator::Comma
+        } else {
+            DecimalSeparator::Point
+        }
+    }
+
+    /// `value` with `decimals` digits after the separator
+    pub fn decimal(self, value: f64, decimals: usize) -> String {
+        self.localize(format!("{:.*}", decimals, value))
+    }
+
+    /// `value` with as few digits as it takes to read it back exactly
+    pub fn shortest(self, value: impl Display) -> String {
+        self.localize(value.to_string())
+    }
+
+    fn localize(self, text: String) -> String {
+        if self == DecimalSeparator::Comma {
+            text.replace('.', ",")
+        } else {
+            text
+        }
+    }
+}
+
+/// Uses `separator` for every number formatted from now on
+pub fn set(separator: DecimalSeparator) {
+    DECIMAL_COMMA.store(
+        separator.resolve() == DecimalSeparator::Comma,
+        Ordering::Relaxed,
+    );
+}
+
+/// The separator numbers are formatted with, point until `set` is called
+pub fn current() -> DecimalSeparator {
+    if DECIMAL_COMMA.load(Ordering::Relaxed) {
+        DecimalSeparator::Comma
+    } else {
+        DecimalSeparator::Point
+    }
+}
+
+/// `value` with `decimals` digits after the separator of the UI
+pub fn decimal(value: f64, decimals: usize) -> String {
+    current().decimal(value, decimals)
+}
+
+/// `value` with the separator of the UI and as few digits as it takes to read it back
+pub fn shortest(value: impl Display) -> String {
+    current().shortest(value)
+}
+
+/// A number typed by the user, with either a point or a comma before the decimals whatever
+/// the locale, as the fields never group thousands
+pub fn parse(text: &str) -> Option<f64> {
+    let text = text.trim().replace(',', ".");
+    if text.is_empty() {
+        return None;
+    }
+    text.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_separator_of_locale() {
+        assert_eq!(
+            DecimalSeparator::of_locale("de_DE.UTF-8"),
+            DecimalSeparator::Comma
+        );
+        assert_eq!(
+            DecimalSeparator::of_locale("pt-BR"),
+            DecimalSeparator::Comma
+        );
+        assert_eq!(
+            DecimalSeparator::of_locale("fr_FR@euro"),
+            DecimalSeparator::Comma
+        );
+        assert_eq!(
+            DecimalSeparator::of_locale("de_CH.UTF-8"),
+            DecimalSeparator::Point
+        );
+        assert_eq!(
+            DecimalSeparator::of_locale("en_US.UTF-8"),
+            DecimalSeparator::Point
+        );
+        assert_eq!(DecimalSeparator::of_locale("C"), DecimalSeparator::Point);
+        assert_eq!(DecimalSeparator::Comma.resolve(), DecimalSeparator::Comma);
+    }
+
+    #[test]
+    fn test_formatting() {
+        assert_eq!(DecimalSeparator::Comma.decimal(12.345, 1), "12,3");
+        assert_eq!(DecimalSeparator::Point.decimal(12.346, 2), "12.35");
+        assert_eq!(DecimalSeparator::Comma.shortest(0.05f32), "0,05");
+        assert_eq!(DecimalSeparator::Comma.shortest(-20.0f32), "-20");
+        assert_eq!(DecimalSeparator::Point.shortest(1.5f32), "1.5");
+        assert_eq!(DecimalSeparator::Comma.shortest(0.025f64), "0,025");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("1,5"), Some(1.5));
+        assert_eq!(parse(" -0.25 "), Some(-0.25));
+        assert_eq!(parse("12"), Some(12.0));
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("1,2,3"), None);
+        assert_eq!(parse("abc"), None);
+    }
+}
@@ -0,0 +1,275 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::Body;
+use crate::mesh::Mesh;
+use crate::number_format;
+use nalgebra::{Point3, Vector2, Vector3};
+use std::collections::HashMap;
+use stl_io::Triangle;
+
+/// Openings this close to the lowest point of a body are at its bottom, in millimeters
+const BOTTOM_TOLERANCE: f32 = 0.01;
+
+/// Openings of a mesh that lie flat on the build plate, e.g. a miniature hollowed from
+/// below. Slicing them gives rings instead of a solid base for the first layers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenBottom {
+    /// Simple vertex indices around each opening, in the direction of the faces along it
+    pub loops: Vec<Vec<u32>>,
+    /// Area of the openings on the plate, in square millimeters
+    pub area: f32,
+}
+
+impl OpenBottom {
+    /// The openings at the bottom of a body as it is placed, None if it has none
+    pub fn of(body: &Body) -> Option<Self> {
+        let positions = plate_positions(body);
+        let lowest = positions.iter().map(|p| p.z).fold(f32::INFINITY, f32::min);
+        let loops: Vec<Vec<u32>> = boundary_loops(&body.mesh.simple_indices)
+            .into_iter()
+            .filter(|boundary| {
+                boundary
+                    .iter()
+                    .all(|&i| positions[i as usize].z - lowest <= BOTTOM_TOLERANCE)
+            })
+            .collect();
+        if loops.is_empty() {
+            return None;
+        }
+        let area = loops
+            .iter()
+            .map(|boundary| signed_area(&outline(boundary, &positions)).abs())
+            .sum();
+        Some(Self { loops, area })
+    }
+
+    pub fn describe(&self, name: &str) -> String {
+        format!(
+            "{} is open at the bottom ({} openings, {} mm²), so its first layers will be rings rather than a solid base",
+            name,
+            self.loops.len(),
+            number_format::decimal(self.area as f64, 1)
+        )
+    }
+
+    /// Closes every opening with a flat cap, wound like the faces around it
+    pub fn seal(&self, body: &mut Body) {
+        let positions = plate_positions(body);
+        let mesh = &body.mesh;
+        let mut triangles = mesh.get_triangles_for_slicing();
+        for boundary in &self.loops {
+            // The faces along an opening run around it one way, so its cap runs the other
+            let cap: Vec<u32> = boundary.iter().rev().copied().collect();
+            for corners in triangulate(&outline(&cap, &positions)) {
+                let vertices = corners.map(|k| mesh.simple_vertices[cap[k] as usize].position);
+                let [a, b, c] = vertices.map(Vector3::from);
+                let normal = (b - a)
+                    .cross(&(c - a))
+                    .try_normalize(0.0)
+                    .unwrap_or_default();
+                triangles.push(Triangle {
+                    normal: normal.into(),
+                    vertices,
+                });
+            }
+        }
+        body.set_mesh(Mesh::from_triangles(&triangles));
+    }
+}
+
+// Where the simple vertices of a body are on the plate
+fn plate_positions(body: &Body) -> Vec<Vector3<f32>> {
+    let model = body.get_model_matrix();
+    body.mesh
+        .simple_vertices
+        .iter()
+        .map(|v| model.transform_point(&Point3::from(v.position)).coords)
+        .collect()
+}
+
+fn outline(boundary: &[u32], positions: &[Vector3<f32>]) -> Vec<Vector2<f32>> {
+    boundary
+        .iter()
+        .map(|&i| positions[i as usize].xy())
+        .collect()
+}
+
+/// Closed loops of the edges only one face uses, following the faces' winding. Where
+/// several loops touch at a vertex only one of them is followed through it.
+fn boundary_loops(indices: &[u32]) -> Vec<Vec<u32>> {
+    let mut edge_faces: HashMap<(u32, u32), u32> = HashMap::new();
+    for face in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (a, b) = (face[i], face[(i + 1) % 3]);
+            *edge_faces.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+    let mut next: HashMap<u32, u32> = HashMap::new();
+    for face in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (a, b) = (face[i], face[(i + 1) % 3]);
+            if edge_faces[&(a.min(b), a.max(b))] == 1 {
+                next.insert(a, b);
+            }
+        }
+    }
+
+    // Starting from the lowest index keeps the loops the same from run to run
+    let mut starts: Vec<u32> = next.keys().copied().collect();
+    starts.sort_unstable();
+    let mut loops = Vec::new();
+    for start in starts {
+        let mut boundary = Vec::new();
+        let mut vertex = start;
+        while let Some(following) = next.remove(&vertex) {
+            boundary.push(vertex);
+            vertex = following;
+        }
+        if vertex == start && boundary.len() >= 3 {
+            loops.push(boundary);
+        }
+    }
+    loops
+}
+
+fn cross(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Positive for counterclockwise polygons
+fn signed_area(points: &[Vector2<f32>]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| cross(points[i], points[(i + 1) % n]))
+        .sum::<f32>()
+        / 2.0
+}
+
+/// Splits a simple polygon into triangles by clipping ears, keeping its winding
+fn triangulate(points: &[Vector2<f32>]) -> Vec<[usize; 3]> {
+    let orientation = signed_area(points).signum();
+    let turns_inward = |a: Vector2<f32>, b: Vector2<f32>, p: Vector2<f32>| {
+        cross(b - a, p - a) * orientation >= 0.0
+    };
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let corner = |i: usize| {
+            [
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            ]
+        };
+        let ear = (0..n).find(|&i| {
+            let [a, b, c] = corner(i);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            cross(pb - pa, pc - pb) * orientation > 0.0
+                && !remaining.iter().any(|&j| {
+                    ![a, b, c].contains(&j)
+                        && turns_inward(pa, pb, points[j])
+                        && turns_inward(pb, pc, points[j])
+                        && turns_inward(pc, pa, points[j])
+                })
+        });
+        // Degenerate outlines can run out of proper ears, then any corner goes
+        let i = ear.unwrap_or(0);
+        triangles.push(corner(i));
+        remaining.remove(i);
+    }
+    if let [a, b, c] = remaining[..] {
+        triangles.push([a, b, c]);
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A box of the given size standing on the plate, without its bottom face
+    fn open_box(size: [f32; 3]) -> Body {
+        let [x, y, z] = size;
+        let corners = [
+            [0.0, 0.0, 0.0],
+            [x, 0.0, 0.0],
+            [x, y, 0.0],
+            [0.0, y, 0.0],
+            [0.0, 0.0, z],
+            [x, 0.0, z],
+            [x, y, z],
+            [0.0, y, z],
+        ];
+        // Quads counterclockwise seen from outside
+        let quads = [
+            [4, 5, 6, 7],
+            [0, 1, 5, 4],
+            [1, 2, 6, 5],
+            [2, 3, 7, 6],
+            [3, 0, 4, 7],
+        ];
+        let triangles: Vec<Triangle> = quads
+            .iter()
+            .flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+            .map(|face: [usize; 3]| {
+                let vertices = face.map(|i| corners[i]);
+                let [p, q, r] = vertices.map(Vector3::from);
+                Triangle {
+                    normal: (q - p).cross(&(r - p)).normalize().into(),
+                    vertices,
+                }
+            })
+            .collect();
+        Body::new(Mesh::from_triangles(&triangles))
+    }
+
+    #[test]
+    fn test_detect_and_seal() {
+        let mut body = open_box([4.0, 2.0, 3.0]);
+        let open = OpenBottom::of(&body).unwrap();
+        assert_eq!(open.loops.len(), 1);
+        assert_eq!(open.loops[0].len(), 4);
+        assert!((open.area - 8.0).abs() < 1e-4);
+
+        open.seal(&mut body);
+        assert!(OpenBottom::of(&body).is_none());
+        let triangles = body.mesh.get_triangles_for_slicing();
+        assert_eq!(triangles.len(), 12);
+        // Closed and wound outwards, with the cap facing down
+        assert!((crate::geometry::signed_volume(&triangles) - 24.0).abs() < 1e-4);
+        let cap = &triangles[10..];
+        assert!(cap.iter().all(|t| (t.normal[2] + 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_openings_above_the_bottom_are_ignored() {
+        // Upside down, the opening is at the top
+        let mut body = open_box([1.0, 1.0, 1.0]);
+        body.scale = Vector3::new(1.0, 1.0, -1.0);
+        assert!(OpenBottom::of(&body).is_none());
+    }
+
+    #[test]
+    fn test_triangulate_concave() {
+        // An L shape, clockwise
+        let points: Vec<Vector2<f32>> = [
+            (0.0, 0.0),
+            (0.0, 2.0),
+            (1.0, 2.0),
+            (1.0, 1.0),
+            (2.0, 1.0),
+            (2.0, 0.0),
+        ]
+        .iter()
+        .map(|&(x, y)| Vector2::new(x, y))
+        .collect();
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), 4);
+        let corners = |t: &[usize; 3]| t.map(|i| points[i]);
+        let area: f32 = triangles.iter().map(|t| signed_area(&corners(t))).sum();
+        assert!((area + 3.0).abs() < 1e-5);
+        assert!(triangles.iter().all(|t| signed_area(&corners(t)) < 0.0));
+    }
+}
@@ -0,0 +1,127 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::action::{Action, CompoundAction, SetPositionAction, SetRotationQuatAction};
+use crate::body::Body;
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, rc::Rc};
+
+/// A rotation for a kind of model, one click between laying it flat and orienting it by hand
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OrientationPreset {
+    pub name: String,
+    /// Degrees the top of the model leans towards the back of the plate, +Y
+    pub tilt: f32,
+    /// Degrees the model is turned around Z before it is tilted, so long edges and flat
+    /// faces don't come off the film all at once
+    #[serde(default)]
+    pub turn: f32,
+}
+
+impl OrientationPreset {
+    fn new(name: &str, tilt: f32, turn: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            tilt,
+            turn,
+        }
+    }
+
+    /// The presets of new settings files
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new("Tilt 30°", 30.0, 0.0),
+            Self::new("Tilt 45°", 45.0, 0.0),
+            // Leaning back far, so the face and the front look away from the supports
+            Self::new("Miniature", 45.0, 30.0),
+            // Leaning back a little, so holes stay round and the layers stay small
+            Self::new("Functional part", 15.0, 45.0),
+        ]
+    }
+
+    pub fn rotation(&self) -> UnitQuaternion<f32> {
+        let tilt = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -self.tilt.to_radians());
+        let turn = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), self.turn.to_radians());
+        tilt * turn
+    }
+
+    /// Replaces the rotation of the bodies with this preset, so applying it again or
+    /// another one doesn't add up. Each body keeps its lowest point at the same height,
+    /// standing on the plate or raised on its supports, as one undoable step.
+    pub fn to_action(&self, bodies: &[Rc<RefCell<Body>>]) -> CompoundAction {
+        let rotation = self.rotation().into_inner();
+        let mut actions: Vec<Box<dyn Action>> = Vec::new();
+        for body_rc in bodies {
+            let (position, previous, before, after) = {
+                let mut body = body_rc.borrow_mut();
+                let (position, previous) = (body.position, body.rotation);
+                let before = body.world_aabb();
+                // Turned for a moment to measure where its lowest point ends up
+                body.set_rotation_quat(rotation);
+                let after = body.world_aabb();
+                body.set_rotation_quat(previous);
+                (position, previous, before, after)
+            };
+            let (Some(before), Some(after)) = (before, after) else {
+                continue;
+            };
+            actions.push(Box::new(SetRotationQuatAction {
+                body: Rc::clone(body_rc),
+                input: rotation,
+                previous,
+            }));
+            actions.push(Box::new(SetPositionAction {
+                body: Rc::clone(body_rc),
+                input: position + Vector3::new(0.0, 0.0, before.min.z - after.min.z),
+                previous: position,
+            }));
+        }
+        CompoundAction { actions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+    use stl_io::Triangle;
+
+    #[test]
+    fn test_tilts_towards_the_back() {
+        let preset = OrientationPreset::new("Tilt 30°", 30.0, 0.0);
+        let up = preset.rotation() * Vector3::z();
+        assert!(up.y > 0.0);
+        assert!((up.z - 30f32.to_radians().cos()).abs() < 1e-6);
+
+        // The tilt is towards the back whichever way the model was turned
+        let turned = OrientationPreset::new("Turned", 30.0, 90.0);
+        assert!(((turned.rotation() * Vector3::z()) - up).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_keeps_lowest_point() {
+        // A post 10 mm high, raised 5 mm on supports
+        let triangle = Triangle {
+            normal: [1.0, 0.0, 0.0],
+            vertices: [[0.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 10.0]],
+        };
+        let mut body = Body::new(Mesh::from_triangles(&[triangle]));
+        body.set_position(Vector3::new(0.0, 0.0, 5.0));
+        let body = Rc::new(RefCell::new(body));
+
+        let preset = OrientationPreset::new("Tilt 45°", 45.0, 0.0);
+        let mut action = preset.to_action(&[Rc::clone(&body)]);
+        action.execute();
+        assert!((body.borrow().world_aabb().unwrap().min.z - 5.0).abs() < 1e-5);
+        assert_eq!(body.borrow().rotation, preset.rotation().into_inner());
+
+        // Applying it again changes nothing
+        let position = body.borrow().position;
+        preset.to_action(&[Rc::clone(&body)]).execute();
+        assert!((body.borrow().position - position).norm() < 1e-5);
+
+        action.undo();
+        assert_eq!(body.borrow().position, Vector3::new(0.0, 0.0, 5.0));
+    }
+}
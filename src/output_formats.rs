@@ -0,0 +1,1448 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::ctb;
+use crate::layer_overrides::{self, LayerOverride, LayerPlan};
+use crate::layer_thickness::{LayerHeightRange, LayerThickness};
+use crate::motion_profile::{LayerTiming, MotionProfile};
+use crate::preview::{PreviewEncoding, PreviewFormat};
+use crate::printer::Printer;
+use crate::profiler::{self, Stage};
+use crate::settings::PngCompression;
+use crate::sl1;
+use crate::slice_parameters::SliceParameters;
+use crate::uvj;
+use image::{ImageBuffer, Luma};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub(crate) type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// What the printer reads the layers from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// A folder of lossless WebP layers and previews, read by SealSlicer's own tools
+    #[default]
+    Folder,
+    /// Anycubic Photon Workshop file for the Photon S, one bit per pixel
+    Pws,
+    /// Anycubic Photon Workshop file for the Photon Mono 4 and similar, 16 levels of gray
+    Pwma,
+    /// Elegoo file for the Mars 4, Saturn 3 and later, 256 levels of gray
+    Goo,
+    /// ChiTuBox file for Elegoo, Phrozen and other printers with a ChiTu board, 128 levels
+    /// of gray
+    Ctb,
+    /// Prusa archive for the SL1 and SL1S, a zip of PNG layers and their settings
+    Sl1,
+    /// UVtools project, a zip of PNG layers and their settings to post-process in UVtools
+    /// before converting them for a printer
+    Uvj,
+}
+
+impl OutputFormat {
+    /// In the order the slice dialog lists them
+    pub const ALL: [OutputFormat; 7] = [
+        OutputFormat::Folder,
+        OutputFormat::Pws,
+        OutputFormat::Pwma,
+        OutputFormat::Goo,
+        OutputFormat::Ctb,
+        OutputFormat::Sl1,
+        OutputFormat::Uvj,
+    ];
+
+    pub fn is_folder(&self) -> bool {
+        *self == OutputFormat::Folder
+    }
+
+    /// False when the files have the same `setting` for every layer, so the layer height
+    /// ranges and layer overrides that change it can't be written to them
+    pub fn keeps_layer(&self, setting: LayerSetting) -> bool {
+        match self {
+            OutputFormat::Folder | OutputFormat::Goo | OutputFormat::Uvj => true,
+            // Version 1 of the layer table has no light off delay
+            OutputFormat::Pws | OutputFormat::Pwma => setting != LayerSetting::LightOffDelay,
+            // The moves are in the print parameters, the same for every layer
+            OutputFormat::Ctb => matches!(setting, LayerSetting::Height | LayerSetting::Exposure),
+            OutputFormat::Sl1 => false,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutputFormat::Folder => "Folder of layers",
+            OutputFormat::Pws => "Anycubic .pws",
+            OutputFormat::Pwma => "Anycubic .pwma",
+            OutputFormat::Goo => "Elegoo .goo",
+            OutputFormat::Ctb => "ChiTuBox .ctb",
+            OutputFormat::Sl1 => "Prusa .sl1",
+            OutputFormat::Uvj => "UVtools .uvj",
+        }
+    }
+
+    /// Position in `ALL`
+    pub fn from_index(index: i32) -> Self {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| Self::ALL.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn index(self) -> i32 {
+        Self::ALL
+            .iter()
+            .position(|&format| format == self)
+            .unwrap_or(0) as i32
+    }
+
+    /// The format of a printer file from its extension, None for anything else
+    pub fn of_extension(extension: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| {
+            !format.is_folder() && format!("{:?}", format).eq_ignore_ascii_case(extension)
+        })
+    }
+
+    /// The printer file the layers of a job are packed into, None for a folder of layers
+    pub fn printer_file(parameters: &SliceParameters, name: &str) -> Option<PrinterFile> {
+        let format = parameters.printer.output_format;
+        (!format.is_folder()).then(|| PrinterFile {
+            format,
+            name: name.to_string(),
+            printer: parameters.printer.clone(),
+            resin: parameters.resin.name.clone(),
+            motion: parameters.resin.motion.clone(),
+            layer_height: parameters.slice_thickness,
+            layer_height_ranges: parameters.layer_height_ranges.clone(),
+            layer_overrides: parameters.layer_overrides.clone(),
+            price_per_liter: parameters.resin.price_per_liter,
+        })
+    }
+}
+
+/// What a layer can have of its own, through a layer height range or a layer override
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerSetting {
+    Height,
+    Exposure,
+    Lift,
+    LightOffDelay,
+}
+
+impl LayerSetting {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LayerSetting::Height => "height",
+            LayerSetting::Exposure => "exposure",
+            LayerSetting::Lift => "lift",
+            LayerSetting::LightOffDelay => "light off delay",
+        }
+    }
+}
+
+/// The stages of the .goo moves around a layer `thickness` high, each a distance and a
+/// speed: the slow then the fast stage of a lift by `lift_distance`, then the fast and the
+/// slow stage of the retract back down
+fn goo_moves(motion: &MotionProfile, lift_distance: f64, thickness: f64) -> [[(f64, f64); 2]; 2] {
+    let lift_slow_distance = motion.lift_slow_distance.min(lift_distance);
+    let retract_distance = (lift_distance - thickness).max(0.0);
+    let retract_slow_distance = motion.retract_slow_distance.min(retract_distance);
+    [
+        [
+            (lift_slow_distance, motion.lift_slow_speed),
+            (lift_distance - lift_slow_distance, motion.lift_fast_speed),
+        ],
+        [
+            (
+                retract_distance - retract_slow_distance,
+                motion.retract_fast_speed,
+            ),
+            (retract_slow_distance, motion.retract_slow_speed),
+        ],
+    ]
+}
+
+/// The price per liter of the resin of a file that costs `price` for `volume` milliliters
+pub(crate) fn price_per_liter(price: f64, volume: f64) -> Option<f64> {
+    (price > 0.0 && volume > 0.0).then(|| price / volume * 1000.0)
+}
+
+/// Photon Workshop files start with this, padded with zeros to 12 bytes
+const MARK: &str = "ANYCUBIC";
+
+/// Size of the preview every Photon Workshop file carries
+const PREVIEW_WIDTH: u32 = 224;
+const PREVIEW_HEIGHT: u32 = 168;
+
+/// Longest run of one bit in a PWS layer
+const PWS_MAX_RUN: u32 = 125;
+
+/// Follows the version at the start of a .goo file and ends it
+const GOO_MAGIC: [u8; 8] = [0x07, 0x00, 0x00, 0x00, 0x44, 0x4C, 0x50, 0x00];
+const GOO_ENDING: [u8; 11] = [
+    0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x44, 0x4C, 0x50, 0x00,
+];
+/// Ends the previews and every layer of a .goo file
+const GOO_DELIMITER: [u8; 2] = [0x0D, 0x0A];
+/// Starts the encoded image of every .goo layer
+const GOO_LAYER_MAGIC: u8 = 0x55;
+/// Longest run a .goo chunk can hold, 28 bits
+const GOO_MAX_RUN: u32 = 0x0FFF_FFFF;
+const GOO_SMALL_PREVIEW: u32 = 116;
+const GOO_BIG_PREVIEW: u32 = 290;
+
+/// Above how far the f32 values of a layer table are off, below any real difference
+/// between the heights or settings of layers
+const LAYER_TABLE_TOLERANCE: f64 = 1e-4;
+
+#[derive(Error, Debug)]
+pub enum ReadError {
+    #[error("The file ends early")]
+    Truncated,
+
+    #[error("Not a {0} file")]
+    WrongFormat(&'static str),
+
+    #[error("Layer {0} is damaged")]
+    DamagedLayer(usize),
+}
+
+/// One layer of the layer table of a printer file, with the settings the format keeps for
+/// every layer. The ones it doesn't keep are None.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct LayerEntry {
+    pub top: f64,                   // millimeters, the height of the plate at the exposure
+    pub exposure: f64,              // seconds
+    pub lift_distance: Option<f64>, // millimeters
+    /// The light off delay
+    pub rest: Option<f64>, // seconds
+}
+
+/// Everything a single printer file holds besides the layers
+#[derive(Debug, Clone)]
+pub struct PrinterFile {
+    pub format: OutputFormat,
+    /// File name without the extension
+    pub name: String,
+    pub printer: Printer,
+    /// Name of the resin profile
+    pub resin: String,
+    pub motion: MotionProfile,
+    pub layer_height: f64, // millimeters
+    /// Bands of layers of their own height, see `LayerThickness`
+    pub layer_height_ranges: Vec<LayerHeightRange>,
+    /// Layers with their own exposure, lift or light off delay
+    pub layer_overrides: Vec<LayerOverride>,
+    /// Of the resin, for the cost the file shows
+    pub price_per_liter: Option<f64>,
+}
+
+impl PrinterFile {
+    pub fn file_name(&self) -> String {
+        let extension = match self.format {
+            OutputFormat::Folder => "",
+            OutputFormat::Pws => ".pws",
+            OutputFormat::Pwma => ".pwma",
+            OutputFormat::Goo => ".goo",
+            OutputFormat::Ctb => ".ctb",
+            OutputFormat::Sl1 => ".sl1",
+            OutputFormat::Uvj => ".uvj",
+        };
+        format!("{}{}", self.name, extension)
+    }
+
+    /// The whole file. Photon Workshop files are a table of addresses followed by the
+    /// header, the preview, one lift and exposure entry per layer and the encoded layers,
+    /// little endian throughout. Both formats write the sections of version 1, which the
+    /// later versions extend at their end and which have no light off delay per layer.
+    pub fn encode(&self, layers: &[Layer]) -> Vec<u8> {
+        let _timer = profiler::scope(Stage::Encoding);
+        match self.format {
+            OutputFormat::Goo => return self.encode_goo(layers),
+            OutputFormat::Ctb => return ctb::encode(self, layers),
+            OutputFormat::Sl1 => return sl1::encode(self, layers),
+            OutputFormat::Uvj => return uvj::encode(self, layers),
+            _ => {}
+        }
+        let version = match self.format {
+            OutputFormat::Pwma => 515,
+            _ => 1,
+        };
+        let encoded: Vec<Vec<u8>> = layers
+            .iter()
+            .map(|layer| match self.format {
+                OutputFormat::Pws => encode_pws(layer),
+                _ => encode_pw0(layer),
+            })
+            .collect();
+
+        let header = self.header(layers);
+        let preview = self.preview(layers);
+        const FILE_MARK_LENGTH: usize = 12 + 9 * 4;
+        const SECTION_HEADER_LENGTH: usize = 12 + 4;
+        const LAYER_DEFINITION_LENGTH: usize = 32;
+        let header_address = FILE_MARK_LENGTH;
+        let preview_address = header_address + SECTION_HEADER_LENGTH + header.len();
+        let layer_definition_address = preview_address + SECTION_HEADER_LENGTH + preview.len();
+        let layer_definitions_length = 4 + LAYER_DEFINITION_LENGTH * layers.len();
+        let layer_image_address =
+            layer_definition_address + SECTION_HEADER_LENGTH + layer_definitions_length;
+
+        let mut file = Writer::default();
+        file.name(MARK);
+        file.u32(version);
+        // Header, preview, layer definitions and layer images
+        file.u32(4);
+        // Every address but the last one is followed by padding
+        for address in [header_address, preview_address, layer_definition_address] {
+            file.u32(address as u32);
+            file.u32(0);
+        }
+        file.u32(layer_image_address as u32);
+
+        file.section("HEADER", &header);
+        file.section("PREVIEW", &preview);
+
+        let mut definitions = Writer::default();
+        definitions.u32(layers.len() as u32);
+        let mut data_address = layer_image_address;
+        for (data, plan) in encoded.iter().zip(self.plan(layers.len())) {
+            definitions.u32(data_address as u32);
+            definitions.u32(data.len() as u32);
+            definitions.f32(plan.lift_distance);
+            definitions.f32(self.motion.lift_fast_speed / 60.0);
+            definitions.f32(plan.timing.exposure);
+            // Height of the plate when the layer is exposed
+            definitions.f32(plan.top);
+            definitions.u32(0);
+            definitions.u32(0);
+            data_address += data.len();
+        }
+        file.section("LAYERDEF", &definitions.bytes);
+
+        for data in &encoded {
+            file.bytes.extend(data);
+        }
+        file.bytes
+    }
+
+    fn header(&self, layers: &[Layer]) -> Vec<u8> {
+        let printer = &self.printer;
+        let motion = &self.motion;
+        let pixel_size = printer.physical_x / printer.pixel_x as f64;
+
+        let mut header = Writer::default();
+        header.f32(pixel_size * 1000.0); // micrometers
+        header.f32(self.layer_height);
+        header.f32(motion.exposure_time);
+        header.f32(motion.rest_before_exposure);
+        header.f32(motion.bottom_exposure_time);
+        header.f32(motion.bottom_layer_count as f64);
+        header.f32(motion.lift_distance);
+        header.f32(motion.lift_fast_speed / 60.0); // millimeters per second
+        header.f32(motion.retract_fast_speed / 60.0);
+        header.f32(self.volume_ml(layers));
+        header.u32(1); // Anti-aliasing, the layers are already anti-aliased
+        header.u32(printer.pixel_x);
+        header.u32(printer.pixel_y);
+        header.f32(0.0); // Weight in grams
+        header.f32(self.price(layers));
+        header.u32(0); // Currency symbol
+        header.u32(1); // The layer definitions override the exposure and lift of the header
+        header.u32(self.print_time(layers.len()).round() as u32);
+        header.u32(0); // Transition layers
+        header.u32(0);
+        header.bytes
+    }
+
+    /// Resin the cured pixels of the layers take, in milliliters
+    pub(crate) fn volume_ml(&self, layers: &[Layer]) -> f64 {
+        let printer = &self.printer;
+        let pixel_area = printer.physical_x / printer.pixel_x as f64 * printer.physical_y
+            / printer.pixel_y as f64;
+        let spans = self.layer_thickness().layers(layers.len());
+        let cured_volume: f64 = layers
+            .iter()
+            .zip(spans)
+            .map(|(layer, span)| {
+                layer.pixels().filter(|p| p[0] > 127).count() as f64 * span.thickness
+            })
+            .sum();
+        cured_volume * pixel_area / 1000.0
+    }
+
+    /// The settings the layer height ranges and the layer overrides give single layers
+    pub fn layer_settings(&self) -> Vec<LayerSetting> {
+        let ranges = &self.layer_height_ranges;
+        let overrides = &self.layer_overrides;
+        [
+            (LayerSetting::Height, !ranges.is_empty()),
+            (
+                LayerSetting::Exposure,
+                ranges.iter().any(|range| range.exposure_time.is_some())
+                    || overrides.iter().any(|o| o.exposure_time.is_some()),
+            ),
+            (
+                LayerSetting::Lift,
+                overrides.iter().any(|o| o.lift_distance.is_some()),
+            ),
+            (
+                LayerSetting::LightOffDelay,
+                overrides.iter().any(|o| o.light_off_delay.is_some()),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(setting, used)| used.then_some(setting))
+        .collect()
+    }
+
+    pub fn layer_thickness(&self) -> LayerThickness {
+        LayerThickness {
+            base: self.layer_height,
+            ranges: self.layer_height_ranges.clone(),
+        }
+    }
+
+    /// Rebuilds the layer height ranges and the layer overrides from the layer table of a
+    /// file, so that `plan` gives the layers the same heights and settings again. The ranges
+    /// only set the thickness of their layers, and start and end where `LayerThickness`
+    /// counts the layers to, to the bit. Whatever else differs from the motion of the file
+    /// becomes an override.
+    pub(crate) fn read_layer_table(&mut self, table: &[LayerEntry]) {
+        let same = |a: f64, b: f64| (a - b).abs() < LAYER_TABLE_TOLERANCE;
+        // Runs of layers of the same thickness: their bottom, their top and their count
+        let mut runs: Vec<(f64, f64, usize)> = Vec::new();
+        let mut bottom = 0.0;
+        for &LayerEntry { top, .. } in table {
+            match runs.last_mut() {
+                Some((run_bottom, run_top, count))
+                    if same(top - bottom, (*run_top - *run_bottom) / *count as f64) =>
+                {
+                    *run_top = top;
+                    *count += 1;
+                }
+                _ => runs.push((bottom, top, 1)),
+            }
+            bottom = top;
+        }
+
+        self.layer_height_ranges.clear();
+        let mut from = 0.0;
+        for (run_bottom, run_top, count) in runs {
+            let mut thickness = ((run_top - run_bottom) / count as f64 * 1e6).round() / 1e6;
+            if same(thickness, self.layer_height) {
+                thickness = self.layer_height;
+            }
+            let to = from + count as f64 * thickness;
+            if thickness != self.layer_height {
+                self.layer_height_ranges.push(LayerHeightRange {
+                    from,
+                    to,
+                    thickness,
+                    exposure_time: None,
+                });
+            }
+            from = to;
+        }
+
+        self.layer_overrides.clear();
+        let plan = self.plan(table.len());
+        for (index, (entry, layer)) in table.iter().zip(plan).enumerate() {
+            let differs = |value: Option<f64>, planned: f64| value.filter(|&v| !same(v, planned));
+            let settings = (
+                differs(Some(entry.exposure), layer.timing.exposure),
+                differs(entry.lift_distance, layer.lift_distance),
+                differs(entry.rest, layer.timing.rest),
+            );
+            if settings == (None, None, None) {
+                continue;
+            }
+            match self.layer_overrides.last_mut() {
+                Some(last)
+                    if last.last_layer + 1 == index
+                        && (last.exposure_time, last.lift_distance, last.light_off_delay)
+                            == settings =>
+                {
+                    last.last_layer = index
+                }
+                _ => self.layer_overrides.push(LayerOverride {
+                    first_layer: index,
+                    last_layer: index,
+                    exposure_time: settings.0,
+                    lift_distance: settings.1,
+                    light_off_delay: settings.2,
+                }),
+            }
+        }
+    }
+
+    /// Every layer as the file tells the printer
+    pub(crate) fn plan(&self, layer_count: usize) -> Vec<LayerPlan> {
+        layer_overrides::plan(
+            &self.layer_thickness(),
+            &self.layer_overrides,
+            &self.motion,
+            layer_count,
+        )
+    }
+
+    /// What the resin of the layers costs, 0 without a price
+    pub(crate) fn price(&self, layers: &[Layer]) -> f64 {
+        self.price_per_liter
+            .map_or(0.0, |price| price * self.volume_ml(layers) / 1000.0)
+    }
+
+    /// Seconds the print takes, corrected to the print times measured on the printer
+    pub fn print_time(&self, layer_count: usize) -> f64 {
+        let mut timeline: Vec<LayerTiming> = self
+            .plan(layer_count)
+            .iter()
+            .map(|layer| layer.timing)
+            .collect();
+        self.printer.time_calibration.apply(&mut timeline);
+        timeline.iter().map(|timing| timing.total()).sum()
+    }
+
+    /// A .goo file: a fixed size header with two previews, then the settings and image of
+    /// every layer and an ending, big endian throughout. Moves are in two stages like the
+    /// motion profile, the first one of a lift and the second one of a retract slow.
+    fn encode_goo(&self, layers: &[Layer]) -> Vec<u8> {
+        let printer = &self.printer;
+        let motion = &self.motion;
+        let [lift, retract] = goo_moves(motion, motion.lift_distance, self.layer_height);
+
+        let mut file = Writer::big_endian();
+        file.bytes.extend(b"V3.0");
+        file.bytes.extend(GOO_MAGIC);
+        file.text("SealSlicer", 32);
+        file.text(env!("CARGO_PKG_VERSION"), 24);
+        // Creation time, which printers don't show
+        file.text("", 24);
+        file.text(&printer.model, 32);
+        file.text("MSLA", 32);
+        file.text(&self.resin, 32);
+        file.u16(1); // Anti-aliasing, the layers are already anti-aliased
+        file.u16(1); // Gray levels
+        file.u16(0); // Blur
+        for size in [GOO_SMALL_PREVIEW, GOO_BIG_PREVIEW] {
+            file.bytes.extend(self.goo_preview(layers, size));
+            file.bytes.extend(GOO_DELIMITER);
+        }
+        file.u32(layers.len() as u32);
+        file.u16(printer.pixel_x as u16);
+        file.u16(printer.pixel_y as u16);
+        file.u8(0); // Mirror X
+        file.u8(0); // Mirror Y
+        file.f32(printer.physical_x);
+        file.f32(printer.physical_y);
+        file.f32(printer.physical_z);
+        file.f32(self.layer_height);
+        file.f32(motion.exposure_time);
+        file.u8(1); // Waits before and after moves instead of a light off delay
+        file.f32(0.0); // Light off delay
+                       // Waits before lifting, after lifting and after retracting, bottom and normal layers
+        for _ in 0..2 {
+            file.f32(0.0);
+            file.f32(0.0);
+            file.f32(motion.rest_before_exposure);
+        }
+        file.f32(motion.bottom_exposure_time);
+        file.u32(motion.bottom_layer_count as u32);
+        // First stages then second stages, each of them lift then retract, each of those
+        // for bottom then normal layers
+        for stages in [[lift[0], retract[0]], [lift[1], retract[1]]] {
+            for (distance, speed) in stages {
+                for _ in 0..2 {
+                    file.f32(distance);
+                    file.f32(speed); // millimeters per minute
+                }
+            }
+        }
+        file.u16(255); // Bottom light power
+        file.u16(255); // Light power
+        file.u8(1); // Every layer has its own settings
+        file.u32(self.print_time(layers.len()).round() as u32);
+        file.f32(self.volume_ml(layers));
+        file.f32(0.0); // Weight in grams
+        file.f32(self.price(layers));
+        file.text("", 8); // Currency symbol
+        let layer_definition_address = file.bytes.len() + 4 + 1 + 2;
+        file.u32(layer_definition_address as u32);
+        file.u8(0); // Gray values from 0x00 to 0xFF
+        file.u16(0); // Transition layers
+
+        for (layer, plan) in layers.iter().zip(self.plan(layers.len())) {
+            file.u16(0); // Pause
+            file.f32(printer.physical_z); // Where the plate goes on a pause
+            file.f32(plan.top);
+            file.f32(plan.timing.exposure);
+            file.f32(0.0); // Light off delay
+            file.f32(0.0); // Wait before lifting
+            file.f32(0.0); // Wait after lifting
+            file.f32(plan.timing.rest); // Wait after retracting
+                                        // The lift, then the retract to one layer higher than before
+            for (distance, speed) in goo_moves(motion, plan.lift_distance, plan.thickness)
+                .into_iter()
+                .flatten()
+            {
+                file.f32(distance);
+                file.f32(speed);
+            }
+            file.u16(255); // Light power
+            file.bytes.extend(GOO_DELIMITER);
+            let data = encode_goo(layer);
+            file.u32(data.len() as u32);
+            file.bytes.extend(data);
+            file.bytes.extend(GOO_DELIMITER);
+        }
+        file.bytes.extend(GOO_ENDING);
+        file.bytes
+    }
+
+    /// Reads a file `encode` wrote back into what it holds and its layers. Only the
+    /// settings the format stores come back, the rest are defaults: Photon Workshop
+    /// files keep neither the printer nor the resin and move at a single speed.
+    pub fn decode(
+        format: OutputFormat,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(Self, Vec<Layer>), ReadError> {
+        match format {
+            OutputFormat::Folder => Err(ReadError::WrongFormat("printer")),
+            OutputFormat::Goo => Self::decode_goo(name, bytes),
+            OutputFormat::Ctb => ctb::decode(name, bytes),
+            OutputFormat::Sl1 => sl1::decode(name, bytes),
+            OutputFormat::Uvj => uvj::decode(name, bytes),
+            _ => Self::decode_photon(format, name, bytes),
+        }
+    }
+
+    fn decode_photon(
+        format: OutputFormat,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(Self, Vec<Layer>), ReadError> {
+        let mut file = Reader::new(bytes);
+        if file.name()? != MARK {
+            return Err(ReadError::WrongFormat("Photon Workshop"));
+        }
+        file.u32()?; // Version
+        file.u32()?; // Number of sections
+        let header_address = file.u32()? as usize;
+        file.u32()?;
+        file.u32()?; // Preview
+        file.u32()?;
+        let layer_definition_address = file.u32()? as usize;
+
+        file.at = header_address;
+        let mut header = Reader::new(file.section("HEADER")?);
+        let pixel_size = header.f32()? / 1000.0;
+        let layer_height = header.f32()?;
+        let motion = MotionProfile {
+            exposure_time: header.f32()?,
+            rest_before_exposure: header.f32()?,
+            bottom_exposure_time: header.f32()?,
+            bottom_layer_count: header.f32()? as usize,
+            lift_distance: header.f32()?,
+            lift_fast_speed: header.f32()? * 60.0,
+            retract_fast_speed: header.f32()? * 60.0,
+            lift_slow_distance: 0.0,
+            retract_slow_distance: 0.0,
+            ..MotionProfile::default()
+        };
+        let volume = header.f32()?;
+        header.u32()?; // Anti-aliasing
+        let (width, height) = (header.u32()?, header.u32()?);
+        header.f32()?; // Weight
+        let price = header.f32()?;
+        let printer = Printer {
+            pixel_x: width,
+            pixel_y: height,
+            physical_x: pixel_size * width as f64,
+            physical_y: pixel_size * height as f64,
+            output_format: format,
+            ..Printer::default()
+        };
+
+        file.at = layer_definition_address;
+        let mut definitions = Reader::new(file.section("LAYERDEF")?);
+        let mut table = Vec::new();
+        let layers = (0..definitions.u32()? as usize)
+            .map(|index| {
+                let address = definitions.u32()? as usize;
+                let length = definitions.u32()? as usize;
+                let lift_distance = definitions.f32()?;
+                definitions.f32()?; // Lift speed, the one of the header
+                let exposure = definitions.f32()?;
+                table.push(LayerEntry {
+                    top: definitions.f32()?,
+                    exposure,
+                    lift_distance: Some(lift_distance),
+                    rest: None,
+                });
+                definitions.take(4 * 2)?;
+                let data = bytes
+                    .get(address..address + length)
+                    .ok_or(ReadError::Truncated)?;
+                match format {
+                    OutputFormat::Pws => decode_pws(data, width, height),
+                    _ => decode_pw0(data, width, height),
+                }
+                .ok_or(ReadError::DamagedLayer(index))
+            })
+            .collect::<Result<_, _>>()?;
+        let mut file = PrinterFile {
+            format,
+            name: name.to_string(),
+            printer,
+            resin: String::new(),
+            motion,
+            layer_height,
+            layer_height_ranges: Vec::new(),
+            layer_overrides: Vec::new(),
+            price_per_liter: price_per_liter(price, volume),
+        };
+        file.read_layer_table(&table);
+        Ok((file, layers))
+    }
+
+    fn decode_goo(name: &str, bytes: &[u8]) -> Result<(Self, Vec<Layer>), ReadError> {
+        let mut file = Reader::big_endian(bytes);
+        file.take(4)?; // Version
+        if file.take(GOO_MAGIC.len())? != GOO_MAGIC {
+            return Err(ReadError::WrongFormat(".goo"));
+        }
+        file.take(32 + 24 + 24)?; // Software, its version and the creation time
+        let model = file.text(32)?;
+        file.take(32)?; // Technology
+        let resin = file.text(32)?;
+        file.take(3 * 2)?; // Anti-aliasing, gray levels and blur
+        for size in [GOO_SMALL_PREVIEW, GOO_BIG_PREVIEW] {
+            file.take((size * size * 2) as usize + GOO_DELIMITER.len())?;
+        }
+        let layer_count = file.u32()? as usize;
+        let (width, height) = (file.u16()? as u32, file.u16()? as u32);
+        file.take(2)?; // Mirroring
+        let printer = Printer {
+            name: model.clone(),
+            model,
+            pixel_x: width,
+            pixel_y: height,
+            physical_x: file.f32()?,
+            physical_y: file.f32()?,
+            physical_z: file.f32()?,
+            output_format: OutputFormat::Goo,
+            ..Printer::default()
+        };
+        let layer_height = file.f32()?;
+        let exposure_time = file.f32()?;
+        file.take(1 + 4 + 4 * 5)?; // Light off delay and the waits before the last one
+        let rest_before_exposure = file.f32()?;
+        let bottom_exposure_time = file.f32()?;
+        let bottom_layer_count = file.u32()? as usize;
+        // Bottom then normal layers of every stage, the normal ones are kept
+        let mut stage = || -> Result<(f64, f64), ReadError> {
+            file.take(8)?;
+            Ok((file.f32()?, file.f32()?))
+        };
+        let (lift_slow_distance, lift_slow_speed) = stage()?;
+        let (_, retract_fast_speed) = stage()?;
+        let (lift_fast_distance, lift_fast_speed) = stage()?;
+        let (retract_slow_distance, retract_slow_speed) = stage()?;
+        let motion = MotionProfile {
+            exposure_time,
+            bottom_exposure_time,
+            bottom_layer_count,
+            lift_distance: lift_slow_distance + lift_fast_distance,
+            lift_slow_distance,
+            lift_slow_speed,
+            lift_fast_speed,
+            retract_slow_distance,
+            retract_slow_speed,
+            retract_fast_speed,
+            rest_before_exposure,
+        };
+        file.take(2 + 2 + 1 + 4)?; // Light power and print time
+        let volume = file.f32()?;
+        file.f32()?; // Weight
+        let price = file.f32()?;
+        file.take(8)?; // Currency symbol
+        file.at = file.u32()? as usize;
+
+        let mut table = Vec::with_capacity(layer_count);
+        let layers = (0..layer_count)
+            .map(|index| {
+                file.take(2 + 4)?; // Pause
+                let (top, exposure) = (file.f32()?, file.f32()?);
+                file.take(4 * 3)?; // Light off delay and the waits before the last one
+                let rest = file.f32()?;
+                // The two stages of the lift, then the retract that follows from them
+                let (lift_slow_distance, _) = (file.f32()?, file.f32()?);
+                let (lift_fast_distance, _) = (file.f32()?, file.f32()?);
+                file.take(4 * 4 + 2)?; // Retract and light power
+                table.push(LayerEntry {
+                    top,
+                    exposure,
+                    lift_distance: Some(lift_slow_distance + lift_fast_distance),
+                    rest: Some(rest),
+                });
+                if file.take(GOO_DELIMITER.len())? != GOO_DELIMITER {
+                    return Err(ReadError::DamagedLayer(index));
+                }
+                let length = file.u32()? as usize;
+                let layer = decode_goo_layer(file.take(length)?, width, height)
+                    .ok_or(ReadError::DamagedLayer(index))?;
+                file.take(GOO_DELIMITER.len())?;
+                Ok(layer)
+            })
+            .collect::<Result<_, _>>()?;
+        let mut file = PrinterFile {
+            format: OutputFormat::Goo,
+            name: name.to_string(),
+            printer,
+            resin,
+            motion,
+            layer_height,
+            layer_height_ranges: Vec::new(),
+            layer_overrides: Vec::new(),
+            price_per_liter: price_per_liter(price, volume),
+        };
+        file.read_layer_table(&table);
+        Ok((file, layers))
+    }
+
+    /// A square preview, RGB565 big endian
+    fn goo_preview(&self, layers: &[Layer], size: u32) -> Vec<u8> {
+        let format = PreviewFormat {
+            width: size,
+            height: size,
+            encoding: PreviewEncoding::Rgb565,
+            model_color: [255, 140, 40],
+            background_color: [0, 0, 0],
+        };
+        let mut preview = format
+            .encode(&format.render(layers), PngCompression::default())
+            .expect("RGB565 can't fail");
+        for pixel in preview.chunks_exact_mut(2) {
+            pixel.swap(0, 1);
+        }
+        preview
+    }
+
+    fn preview(&self, layers: &[Layer]) -> Vec<u8> {
+        let format = PreviewFormat {
+            width: PREVIEW_WIDTH,
+            height: PREVIEW_HEIGHT,
+            encoding: PreviewEncoding::Rgb565,
+            model_color: [255, 140, 40],
+            background_color: [0, 0, 0],
+        };
+        let mut preview = Writer::default();
+        preview.u32(PREVIEW_WIDTH);
+        preview.u32('x' as u32);
+        preview.u32(PREVIEW_HEIGHT);
+        preview.bytes.extend(
+            format
+                .encode(&format.render(layers), PngCompression::default())
+                .expect("RGB565 can't fail"),
+        );
+        preview.bytes
+    }
+}
+
+/// Little endian unless made with `big_endian`
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub(crate) bytes: Vec<u8>,
+    big_endian: bool,
+}
+
+impl Writer {
+    fn big_endian() -> Self {
+        Self {
+            bytes: Vec::new(),
+            big_endian: true,
+        }
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        let bytes = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.bytes.extend(bytes);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        let bytes = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.bytes.extend(bytes);
+    }
+
+    pub(crate) fn f32(&mut self, value: f64) {
+        self.u32((value as f32).to_bits());
+    }
+
+    /// Zero padded to `length` bytes, cut short if longer
+    fn text(&mut self, text: &str, length: usize) {
+        let mut field = vec![0u8; length];
+        let text = &text.as_bytes()[..text.len().min(length)];
+        field[..text.len()].copy_from_slice(text);
+        self.bytes.extend(field);
+    }
+
+    /// Zero padded to 12 bytes
+    fn name(&mut self, name: &str) {
+        self.text(name, 12);
+    }
+
+    fn section(&mut self, name: &str, data: &[u8]) {
+        self.name(name);
+        self.u32(data.len() as u32);
+        self.bytes.extend(data);
+    }
+}
+
+/// Reads what `Writer` writes, little endian unless made with `big_endian`
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub(crate) at: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            at: 0,
+            big_endian: false,
+        }
+    }
+
+    fn big_endian(bytes: &'a [u8]) -> Self {
+        Self {
+            big_endian: true,
+            ..Self::new(bytes)
+        }
+    }
+
+    pub(crate) fn take(&mut self, length: usize) -> Result<&'a [u8], ReadError> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at + length)
+            .ok_or(ReadError::Truncated)?;
+        self.at += length;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, ReadError> {
+        let bytes = self.take(2)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, ReadError> {
+        let bytes = self.take(4)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// Rounded to the 6 decimals an f32 keeps of the values written, so 0.05 mm comes
+    /// back as 0.05
+    pub(crate) fn f32(&mut self) -> Result<f64, ReadError> {
+        let value = f32::from_bits(self.u32()?) as f64;
+        Ok((value * 1e6).round() / 1e6)
+    }
+
+    /// Up to the first zero of the field
+    pub(crate) fn text(&mut self, length: usize) -> Result<String, ReadError> {
+        let field = self.take(length)?;
+        let end = field.iter().position(|&byte| byte == 0).unwrap_or(length);
+        Ok(String::from_utf8_lossy(&field[..end]).into_owned())
+    }
+
+    fn name(&mut self) -> Result<String, ReadError> {
+        self.text(12)
+    }
+
+    /// The data of the section that should start here
+    fn section(&mut self, name: &'static str) -> Result<&'a [u8], ReadError> {
+        if self.name()? != name {
+            return Err(ReadError::WrongFormat("Photon Workshop"));
+        }
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+/// Runs of pixels with the same value, row by row
+pub(crate) fn runs(
+    layer: &Layer,
+    value: impl Fn(u8) -> u8,
+    max_run: impl Fn(u8) -> u32,
+) -> Vec<(u8, u32)> {
+    let mut runs: Vec<(u8, u32)> = Vec::new();
+    for pixel in layer.pixels() {
+        let value = value(pixel[0]);
+        match runs.last_mut() {
+            Some((last, count)) if *last == value && *count < max_run(value) => *count += 1,
+            _ => runs.push((value, 1)),
+        }
+    }
+    runs
+}
+
+/// One bit per pixel: the top bit of each byte is the color, the rest the length of the run
+fn encode_pws(layer: &Layer) -> Vec<u8> {
+    runs(layer, |pixel| (pixel > 127) as u8, |_| PWS_MAX_RUN)
+        .into_iter()
+        .map(|(white, count)| (white << 7) | count as u8)
+        .collect()
+}
+
+/// Sixteen levels of gray in the top four bits of each run. Black and white runs are up to
+/// 4095 pixels long with the length in the next twelve bits, gray ones up to 15 pixels.
+fn encode_pw0(layer: &Layer) -> Vec<u8> {
+    let max_run = |level: u8| {
+        if level == 0 || level == 0xF {
+            0xFFF
+        } else {
+            0xF
+        }
+    };
+    let mut bytes = Vec::new();
+    for (level, count) in runs(layer, |pixel| pixel >> 4, max_run) {
+        if level == 0 || level == 0xF {
+            bytes.push((level << 4) | (count >> 8) as u8);
+            bytes.push(count as u8);
+        } else {
+            bytes.push((level << 4) | count as u8);
+        }
+    }
+    bytes
+}
+
+/// Runs of black, white and one gray value, each a chunk of up to five bytes: the kind of
+/// run in the top two bits of the first byte, then how many more bytes the length takes and
+/// its top four bits. Gray runs have their value in the second byte. The encoded runs start
+/// with a magic byte and end with a checksum, the inverted sum of the runs.
+fn encode_goo(layer: &Layer) -> Vec<u8> {
+    let mut data = vec![GOO_LAYER_MAGIC];
+    for (value, count) in runs(layer, |pixel| pixel, |_| GOO_MAX_RUN) {
+        let kind: u8 = match value {
+            0x00 => 0b00,
+            0xFF => 0b11,
+            _ => 0b01,
+        };
+        let extra_bytes = match count {
+            0..=0xF => 0,
+            0x10..=0xFFF => 1,
+            0x1000..=0xF_FFFF => 2,
+            _ => 3,
+        };
+        data.push((kind << 6) | (extra_bytes << 4) | (count >> (8 * extra_bytes)) as u8 & 0xF);
+        if kind == 0b01 {
+            data.push(value);
+        }
+        for byte in (0..extra_bytes).rev() {
+            data.push((count >> (8 * byte)) as u8);
+        }
+    }
+    let sum = data[1..]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    data.push(!sum);
+    data
+}
+
+/// A layer from its runs, None unless they fill it exactly
+pub(crate) fn layer_of_runs(
+    width: u32,
+    height: u32,
+    runs: impl IntoIterator<Item = (u8, u32)>,
+) -> Option<Layer> {
+    let size = width as usize * height as usize;
+    let mut pixels = Vec::with_capacity(size);
+    for (value, count) in runs {
+        if pixels.len() + count as usize > size {
+            return None;
+        }
+        pixels.extend(std::iter::repeat_n(value, count as usize));
+    }
+    if pixels.len() != size {
+        return None;
+    }
+    ImageBuffer::from_raw(width, height, pixels)
+}
+
+fn decode_pws(data: &[u8], width: u32, height: u32) -> Option<Layer> {
+    let runs = data
+        .iter()
+        .map(|byte| (if byte >> 7 == 1 { 255 } else { 0 }, (byte & 0x7F) as u32));
+    layer_of_runs(width, height, runs)
+}
+
+fn decode_pw0(data: &[u8], width: u32, height: u32) -> Option<Layer> {
+    let mut runs = Vec::new();
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        let level = byte >> 4;
+        let mut count = (byte & 0xF) as u32;
+        if level == 0 || level == 0xF {
+            count = (count << 8) | *bytes.next()? as u32;
+        }
+        runs.push((level * 17, count));
+    }
+    layer_of_runs(width, height, runs)
+}
+
+/// None when the magic byte or the checksum is wrong
+fn decode_goo_layer(data: &[u8], width: u32, height: u32) -> Option<Layer> {
+    let (&checksum, chunks) = data.split_last()?;
+    let (&magic, chunks) = chunks.split_first()?;
+    let sum = chunks.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    if magic != GOO_LAYER_MAGIC || checksum != !sum {
+        return None;
+    }
+    let mut runs = Vec::new();
+    let mut bytes = chunks.iter();
+    while let Some(&first) = bytes.next() {
+        let extra_bytes = (first >> 4) & 0b11;
+        let mut count = (first & 0xF) as u32;
+        let value = match first >> 6 {
+            0b00 => 0x00,
+            0b11 => 0xFF,
+            _ => *bytes.next()?,
+        };
+        for _ in 0..extra_bytes {
+            count = (count << 8) | *bytes.next()? as u32;
+        }
+        runs.push((value, count));
+    }
+    layer_of_runs(width, height, runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn f32_at(bytes: &[u8], at: usize) -> f32 {
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn layers() -> Vec<Layer> {
+        (0..3)
+            .map(|i| {
+                ImageBuffer::from_fn(100, 80, |x, y| {
+                    Luma([if x > 20 + i && y < 50 {
+                        255
+                    } else if x == 20 + i {
+                        128
+                    } else {
+                        0
+                    }])
+                })
+            })
+            .collect()
+    }
+
+    fn file(format: OutputFormat) -> PrinterFile {
+        let mut printer = SliceParameters::default().printer;
+        printer.pixel_x = 100;
+        printer.pixel_y = 80;
+        printer.physical_x = 5.0;
+        printer.physical_y = 4.0;
+        PrinterFile {
+            format,
+            name: "job".to_string(),
+            printer,
+            resin: "Grey".to_string(),
+            motion: MotionProfile::default(),
+            layer_height: 0.05,
+            layer_height_ranges: Vec::new(),
+            layer_overrides: Vec::new(),
+            price_per_liter: None,
+        }
+    }
+
+    #[test]
+    fn test_layer_height_ranges() {
+        let layers = layers();
+        let mut uniform = file(OutputFormat::Pwma);
+        uniform.motion.bottom_layer_count = 1;
+        let mut file = uniform.clone();
+        file.layer_height_ranges.push(LayerHeightRange {
+            from: 0.05,
+            to: 0.1,
+            thickness: 0.025,
+            exposure_time: Some(1.0),
+        });
+        let bytes = file.encode(&layers);
+
+        let definitions = u32_at(&bytes, 36) as usize;
+        let entry = |index: usize, offset: usize| {
+            f32_at(&bytes, definitions + 20 + index * 32 + offset) as f64
+        };
+        let tops: Vec<f64> = (0..3).map(|index| entry(index, 20)).collect();
+        assert_eq!(tops, [0.05, 0.075, 0.1].map(|z: f64| z as f32 as f64));
+        assert_eq!(entry(1, 16), 1.0);
+        // The thinner layers take less resin and less time
+        assert!(file.volume_ml(&layers) < uniform.volume_ml(&layers));
+        assert!(file.print_time(3) < uniform.print_time(3));
+    }
+
+    #[test]
+    fn test_layer_overrides() {
+        let layers = layers();
+        let mut file = file(OutputFormat::Pwma);
+        file.layer_overrides.push(LayerOverride {
+            first_layer: 1,
+            last_layer: 1,
+            exposure_time: Some(8.0),
+            lift_distance: Some(3.0),
+            light_off_delay: None,
+        });
+        let bytes = file.encode(&layers);
+
+        let definitions = u32_at(&bytes, 36) as usize;
+        let entry = |index: usize, offset: usize| {
+            f32_at(&bytes, definitions + 20 + index * 32 + offset) as f64
+        };
+        // Lift and exposure of the layer, the others keep the resin's
+        assert_eq!((entry(1, 8), entry(1, 16)), (3.0, 8.0));
+        assert_eq!(entry(2, 8), file.motion.lift_distance);
+        assert_eq!(entry(2, 16), file.motion.bottom_exposure_time);
+    }
+
+    #[test]
+    fn test_pwma_layout() {
+        let layers = layers();
+        let file = file(OutputFormat::Pwma);
+        assert_eq!(file.file_name(), "job.pwma");
+        let bytes = file.encode(&layers);
+
+        assert_eq!(&bytes[..12], b"ANYCUBIC\0\0\0\0");
+        assert_eq!(u32_at(&bytes, 12), 515);
+        let [header, preview, definitions, images] =
+            [20, 28, 36, 44].map(|at| u32_at(&bytes, at) as usize);
+        assert_eq!(&bytes[header..header + 6], b"HEADER");
+        assert_eq!(f32_at(&bytes, header + 16), 50.0); // Pixel size in micrometers
+        assert_eq!(u32_at(&bytes, header + 16 + 44), 100);
+        assert_eq!(&bytes[preview..preview + 7], b"PREVIEW");
+        assert_eq!(u32_at(&bytes, preview + 12), 12 + 224 * 168 * 2);
+        assert_eq!(&bytes[definitions..definitions + 8], b"LAYERDEF");
+        assert_eq!(u32_at(&bytes, definitions + 16), 3);
+
+        let motion = MotionProfile::default();
+        for (index, layer) in layers.iter().enumerate() {
+            let entry = definitions + 20 + index * 32;
+            let address = u32_at(&bytes, entry) as usize;
+            let length = u32_at(&bytes, entry + 4) as usize;
+            if index == 0 {
+                assert_eq!(address, images);
+            }
+            // The first layers are bottom layers
+            assert_eq!(
+                f32_at(&bytes, entry + 16) as f64,
+                motion.bottom_exposure_time
+            );
+            assert!((f32_at(&bytes, entry + 20) - 0.05 * (index + 1) as f32).abs() < 1e-6);
+
+            let expected: Vec<u8> = layer.pixels().map(|p| (p[0] >> 4) * 17).collect();
+            let decoded = decode_pw0(&bytes[address..address + length], 100, 80).unwrap();
+            assert_eq!(decoded.into_raw(), expected);
+        }
+        let last = definitions + 20 + 2 * 32;
+        assert_eq!(
+            u32_at(&bytes, last) as usize + u32_at(&bytes, last + 4) as usize,
+            bytes.len()
+        );
+    }
+
+    #[test]
+    fn test_pws_layers() {
+        let layer = &layers()[0];
+        let data = encode_pws(layer);
+        let decoded: Vec<bool> = data
+            .iter()
+            .flat_map(|byte| std::iter::repeat_n(byte >> 7 == 1, (byte & 0x7F) as usize))
+            .collect();
+        let expected: Vec<bool> = layer.pixels().map(|p| p[0] > 127).collect();
+        assert_eq!(decoded, expected);
+        assert!(data.iter().all(|byte| (byte & 0x7F) as u32 <= PWS_MAX_RUN));
+        assert_eq!(file(OutputFormat::Pws).file_name(), "job.pws");
+    }
+
+    #[test]
+    fn test_goo_layout() {
+        let layers = layers();
+        let file = file(OutputFormat::Goo);
+        assert_eq!(file.file_name(), "job.goo");
+        let bytes = file.encode(&layers);
+
+        assert_eq!(&bytes[..4], b"V3.0");
+        assert_eq!(bytes[4..12], GOO_MAGIC);
+        assert_eq!(&bytes[12..22], b"SealSlicer");
+        // Every .goo header is this long
+        let header_length = 195_477;
+        let be_u32 = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        assert_eq!(be_u32(header_length - 7) as usize, header_length);
+        let previews = 194 + 116 * 116 * 2 + 2 + 290 * 290 * 2 + 2;
+        assert_eq!(be_u32(previews), 3);
+        assert_eq!(bytes[previews - 2..previews], GOO_DELIMITER);
+
+        let motion = MotionProfile::default();
+        let mut at = header_length;
+        for (index, layer) in layers.iter().enumerate() {
+            let be_f32 = |at: usize| f32::from_bits(be_u32(at));
+            assert!((be_f32(at + 6) - 0.05 * (index + 1) as f32).abs() < 1e-6);
+            assert_eq!(be_f32(at + 10) as f64, motion.bottom_exposure_time);
+            // The slow part of the lift first
+            assert_eq!(be_f32(at + 30) as f64, motion.lift_slow_distance);
+            assert_eq!(be_f32(at + 34) as f64, motion.lift_slow_speed);
+            at += 66;
+            let length = be_u32(at) as usize;
+            let data = &bytes[at + 4..at + 4 + length];
+            assert_eq!(data[0], GOO_LAYER_MAGIC);
+            let sum = data[1..length - 1]
+                .iter()
+                .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            assert_eq!(data[length - 1], !sum);
+            assert_eq!(decode_goo_layer(data, 100, 80).as_ref(), Some(layer));
+            at += 4 + length + 2;
+        }
+        assert_eq!(bytes[at..], GOO_ENDING);
+    }
+
+    #[test]
+    fn test_goo_long_runs() {
+        let layer = ImageBuffer::from_fn(3000, 200, |x, _| Luma([if x < 5 { 90 } else { 0 }]));
+        let data = encode_goo(&layer);
+        assert_eq!(decode_goo_layer(&data, 3000, 200), Some(layer));
+        // The first gray run is short enough for the first byte
+        assert_eq!(data[1..3], [0b0100_0101, 90]);
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let layers = layers();
+        for format in [
+            OutputFormat::Pws,
+            OutputFormat::Pwma,
+            OutputFormat::Goo,
+            OutputFormat::Ctb,
+            OutputFormat::Sl1,
+            OutputFormat::Uvj,
+        ] {
+            let mut file = file(format);
+            file.motion.exposure_time = 3.2;
+            file.motion.bottom_layer_count = 2;
+            let bytes = file.encode(&layers);
+            let (decoded, decoded_layers) = PrinterFile::decode(format, "job", &bytes).unwrap();
+
+            assert_eq!(decoded.format, format);
+            assert_eq!(decoded.layer_height, 0.05);
+            assert_eq!(decoded.motion.exposure_time, 3.2);
+            assert_eq!(decoded.motion.bottom_layer_count, 2);
+            assert_eq!(decoded.motion.lift_distance, file.motion.lift_distance);
+            assert_eq!(decoded.motion.lift_fast_speed, file.motion.lift_fast_speed);
+            assert_eq!(
+                (decoded.printer.pixel_x, decoded.printer.pixel_y),
+                (100, 80)
+            );
+            assert_eq!(
+                (decoded.printer.physical_x, decoded.printer.physical_y),
+                (5.0, 4.0)
+            );
+            // .goo and .ctb files keep every setting they are written from but the resin, so
+            // they encode the same
+            if matches!(format, OutputFormat::Goo | OutputFormat::Ctb) {
+                assert_eq!(decoded.encode(&decoded_layers), bytes);
+            }
+
+            let quantize = |pixel: u8| match format {
+                OutputFormat::Pws => (pixel > 127) as u8 * 255,
+                OutputFormat::Pwma => (pixel >> 4) * 17,
+                OutputFormat::Ctb => ((pixel >> 1) << 1) | ((pixel >> 1) & 1),
+                _ => pixel,
+            };
+            for (layer, decoded) in layers.iter().zip(&decoded_layers) {
+                let expected: Vec<u8> = layer.pixels().map(|p| quantize(p[0])).collect();
+                assert_eq!(decoded.as_raw(), &expected);
+            }
+        }
+
+        let goo = file(OutputFormat::Goo);
+        let (decoded, _) =
+            PrinterFile::decode(OutputFormat::Goo, "job", &goo.encode(&layers)).unwrap();
+        assert_eq!(decoded.motion, goo.motion);
+        assert_eq!(decoded.resin, "Grey");
+        assert_eq!(decoded.printer.model, goo.printer.model);
+        assert_eq!(decoded.price_per_liter, None);
+
+        // The price of the resin comes back from the cost of the layers, which are made a
+        // few milliliters so the rounding of the file doesn't matter
+        for format in [
+            OutputFormat::Pwma,
+            OutputFormat::Goo,
+            OutputFormat::Ctb,
+            OutputFormat::Sl1,
+        ] {
+            let mut file = file(format);
+            (file.printer.physical_x, file.printer.physical_y) = (500.0, 400.0);
+            file.price_per_liter = Some(40.0);
+            let (decoded, _) = PrinterFile::decode(format, "job", &file.encode(&layers)).unwrap();
+            assert!((decoded.price_per_liter.unwrap() - 40.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_decode_damaged_files() {
+        let layers = layers();
+        let bytes = file(OutputFormat::Pwma).encode(&layers);
+        assert!(matches!(
+            PrinterFile::decode(OutputFormat::Pwma, "job", &bytes[..bytes.len() - 1]),
+            Err(ReadError::Truncated)
+        ));
+        assert!(matches!(
+            PrinterFile::decode(OutputFormat::Goo, "job", &bytes),
+            Err(ReadError::WrongFormat(_))
+        ));
+
+        let mut goo = file(OutputFormat::Goo).encode(&layers);
+        // The last byte of the first layer before its checksum
+        let first_layer = 195_477 + 66;
+        let length = u32::from_be_bytes(goo[first_layer..first_layer + 4].try_into().unwrap());
+        goo[first_layer + 4 + length as usize - 2] ^= 1;
+        assert!(matches!(
+            PrinterFile::decode(OutputFormat::Goo, "job", &goo),
+            Err(ReadError::DamagedLayer(0))
+        ));
+
+        assert_eq!(OutputFormat::of_extension("GOO"), Some(OutputFormat::Goo));
+        assert_eq!(OutputFormat::of_extension("pwma"), Some(OutputFormat::Pwma));
+        assert_eq!(OutputFormat::of_extension("folder"), None);
+        assert_eq!(OutputFormat::of_extension("ctb"), Some(OutputFormat::Ctb));
+        assert_eq!(OutputFormat::of_extension("cbddlp"), None);
+    }
+}
@@ -0,0 +1,173 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::viewport::FrameStats;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Frames older than this are left out of the frame rate
+const FRAME_WINDOW: Duration = Duration::from_secs(1);
+
+/// When the last frames of the 3D view were drawn, for its frame rate and frame time
+#[derive(Default)]
+pub struct FrameClock {
+    frames: VecDeque<Instant>,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        self.frames.push_back(now);
+        while let Some(&oldest) = self.frames.front() {
+            if now.duration_since(oldest) <= FRAME_WINDOW {
+                break;
+            }
+            self.frames.pop_front();
+        }
+    }
+
+    /// Mean time between the frames of the last second, None until two frames were drawn
+    pub fn frame_time(&self) -> Option<Duration> {
+        let (first, last) = (self.frames.front()?, self.frames.back()?);
+        let intervals = self.frames.len() as u32 - 1;
+        (intervals > 0).then(|| last.duration_since(*first) / intervals)
+    }
+
+    pub fn fps(&self) -> Option<f64> {
+        self.frame_time()
+            .filter(|time| !time.is_zero())
+            .map(|time| 1.0 / time.as_secs_f64())
+    }
+}
+
+/// Layers of bodies rasterized by the slicing job that is running, if one is
+pub struct SlicingThroughput {
+    layers: AtomicU64,
+    started: Mutex<Option<Instant>>,
+}
+
+static SLICING_THROUGHPUT: SlicingThroughput = SlicingThroughput::new();
+
+/// Throughput of the slicing job of the app
+pub fn slicing_throughput() -> &'static SlicingThroughput {
+    &SLICING_THROUGHPUT
+}
+
+impl SlicingThroughput {
+    pub const fn new() -> Self {
+        Self {
+            layers: AtomicU64::new(0),
+            started: Mutex::new(None),
+        }
+    }
+
+    pub fn start_job(&self) {
+        self.layers.store(0, Ordering::Relaxed);
+        *self.started.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn finish_job(&self) {
+        *self.started.lock().unwrap() = None;
+    }
+
+    pub fn add_layer(&self) {
+        self.layers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Layers per second since the job started, None while no job runs
+    pub fn layers_per_second(&self) -> Option<f64> {
+        let elapsed = (*self.started.lock().unwrap())?.elapsed().as_secs_f64();
+        let layers = self.layers.load(Ordering::Relaxed) as f64;
+        Some(if elapsed > 0.0 { layers / elapsed } else { 0.0 })
+    }
+}
+
+/// The lines of the overlay drawn over the 3D view
+pub fn overlay_text(
+    clock: &FrameClock,
+    stats: &FrameStats,
+    layers_per_second: Option<f64>,
+) -> String {
+    let mut lines = vec![
+        match (clock.fps(), clock.frame_time()) {
+            (Some(fps), Some(time)) => {
+                format!("{:.0} FPS, {:.1} ms", fps, time.as_secs_f64() * 1000.0)
+            }
+            _ => "-- FPS".to_string(),
+        },
+        format!(
+            "{} draw calls, {} vertices",
+            stats.draw_calls, stats.vertices
+        ),
+        format!(
+            "VRAM ~{:.1} MB",
+            stats.vram_bytes as f64 / (1024.0 * 1024.0)
+        ),
+    ];
+    if let Some(layers_per_second) = layers_per_second {
+        lines.push(format!("Slicing {:.1} body layers/s", layers_per_second));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_clock() {
+        let mut clock = FrameClock::new();
+        let start = Instant::now();
+        clock.tick(start);
+        assert_eq!(clock.fps(), None);
+
+        for frame in 1..=20 {
+            clock.tick(start + Duration::from_millis(20 * frame));
+        }
+        assert_eq!(clock.frame_time(), Some(Duration::from_millis(20)));
+        assert!((clock.fps().unwrap() - 50.0).abs() < 1e-9);
+
+        // A pause drops the frames before it out of the window
+        clock.tick(start + Duration::from_secs(5));
+        assert_eq!(clock.fps(), None);
+    }
+
+    #[test]
+    fn test_slicing_throughput() {
+        let throughput = SlicingThroughput::new();
+        assert_eq!(throughput.layers_per_second(), None);
+        throughput.start_job();
+        for _ in 0..10 {
+            throughput.add_layer();
+        }
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(throughput.layers_per_second().unwrap() > 0.0);
+        throughput.finish_job();
+        assert_eq!(throughput.layers_per_second(), None);
+    }
+
+    #[test]
+    fn test_overlay_text() {
+        let mut clock = FrameClock::new();
+        let start = Instant::now();
+        clock.tick(start);
+        clock.tick(start + Duration::from_millis(10));
+        let stats = FrameStats {
+            draw_calls: 12,
+            vertices: 3456,
+            vram_bytes: 3 * 1024 * 1024,
+        };
+
+        let text = overlay_text(&clock, &stats, None);
+        assert_eq!(
+            text,
+            "100 FPS, 10.0 ms\n12 draw calls, 3456 vertices\nVRAM ~3.0 MB"
+        );
+        assert!(overlay_text(&clock, &stats, Some(42.0)).ends_with("Slicing 42.0 body layers/s"));
+    }
+}
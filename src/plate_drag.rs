@@ -0,0 +1,245 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::action::SetPositionAction;
+use crate::body::Body;
+use crate::footprint::Footprint;
+use nalgebra::{Vector2, Vector3};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Bodies the user arranges, leaving out the build plate
+fn on_plate(body: &Body) -> bool {
+    body.display_in_ui_list && body.visible
+}
+
+/// A body being dragged across the plate in the 3D view. It slides on the horizontal
+/// plane through the point it was grabbed at, outside of the undo history, and the whole
+/// drag becomes a single entry when it ends. Bodies whose footprints come closer than
+/// `gap` are flagged as overlapping while it moves.
+pub struct PlateDrag {
+    body: Rc<RefCell<Body>>,
+    start: Vector3<f32>,
+    plane_z: f32,
+    /// From the grabbed point to the body's position
+    grab_offset: Vector2<f32>,
+    /// At the start position
+    footprint: Footprint,
+    obstacles: Vec<(Rc<RefCell<Body>>, Footprint)>,
+    gap: f32,
+}
+
+impl PlateDrag {
+    /// Grabs the nearest body the ray hits, None when it misses them all
+    pub fn begin(
+        bodies: &[Rc<RefCell<Body>>],
+        origin: Vector3<f32>,
+        direction: Vector3<f32>,
+        gap: f32,
+    ) -> Option<Self> {
+        let (body, distance) = bodies
+            .iter()
+            .filter(|body| {
+                let b = body.borrow();
+                on_plate(&b) && b.selectable
+            })
+            .filter_map(|body| {
+                let distance = body
+                    .borrow()
+                    .world_aabb()?
+                    .ray_distance(origin, direction)?;
+                Some((body, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))?;
+        let grabbed = origin + direction * distance;
+        let b = body.borrow();
+        let obstacles = bodies
+            .iter()
+            .filter(|other| !Rc::ptr_eq(other, body) && on_plate(&other.borrow()))
+            .filter_map(|other| Some((Rc::clone(other), other.borrow().footprint()?)))
+            .collect();
+        Some(Self {
+            body: Rc::clone(body),
+            start: b.position,
+            plane_z: grabbed.z,
+            grab_offset: b.position.xy() - grabbed.xy(),
+            footprint: b.footprint()?,
+            obstacles,
+            gap,
+        })
+    }
+
+    /// Moves the body under the ray, false when the ray doesn't reach the drag plane
+    pub fn update(&mut self, origin: Vector3<f32>, direction: Vector3<f32>) -> bool {
+        if direction.z.abs() < f32::EPSILON {
+            return false;
+        }
+        let t = (self.plane_z - origin.z) / direction.z;
+        if t < 0.0 {
+            return false;
+        }
+        let xy = (origin + direction * t).xy() + self.grab_offset;
+        self.move_to(xy);
+        true
+    }
+
+    /// Names of the bodies the dragged one is too close to
+    pub fn overlapping(&self) -> Vec<String> {
+        self.obstacles
+            .iter()
+            .filter(|(other, _)| other.borrow().overlapping)
+            .map(|(other, _)| other.borrow().name.clone())
+            .collect()
+    }
+
+    /// Moves the body to the nearest spot where it keeps `gap` to every other body, trying
+    /// the sides of the bounds of each one. False when it already was free or no such spot
+    /// was found.
+    pub fn slide_to_free_space(&mut self) -> bool {
+        let current = self.current_footprint();
+        let (min, max) = current.bounds();
+        let is_free = |footprint: &Footprint| {
+            self.obstacles
+                .iter()
+                .all(|(_, other)| !footprint.overlaps(other, self.gap))
+        };
+        if is_free(&current) {
+            return false;
+        }
+        // Offsets along one axis that put the body just beside one of the others
+        let candidates = |axis: usize| {
+            let mut offsets = vec![0.0];
+            for (_, other) in &self.obstacles {
+                let (other_min, other_max) = other.bounds();
+                offsets.push(other_max[axis] + self.gap - min[axis]);
+                offsets.push(other_min[axis] - self.gap - max[axis]);
+            }
+            offsets
+        };
+        let (x_offsets, y_offsets) = (candidates(0), candidates(1));
+        let nearest = x_offsets
+            .iter()
+            .flat_map(|x| y_offsets.iter().map(move |y| Vector2::new(*x, *y)))
+            .filter(|offset| is_free(&current.translated(*offset)))
+            .min_by(|a, b| a.norm_squared().total_cmp(&b.norm_squared()));
+        match nearest {
+            Some(offset) => {
+                let xy = self.body.borrow().position.xy() + offset;
+                self.move_to(xy);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Puts the body back where the drag started and returns the action taking it to where
+    /// the drag ended, None if it never moved
+    pub fn finish(self) -> Option<SetPositionAction> {
+        for (other, _) in &self.obstacles {
+            other.borrow_mut().overlapping = false;
+        }
+        let mut body = self.body.borrow_mut();
+        body.overlapping = false;
+        let end = body.position;
+        body.set_position(self.start);
+        drop(body);
+        (end != self.start).then_some(SetPositionAction {
+            body: self.body,
+            input: end,
+            previous: self.start,
+        })
+    }
+
+    fn current_footprint(&self) -> Footprint {
+        let moved = self.body.borrow().position - self.start;
+        self.footprint.translated(moved.xy())
+    }
+
+    fn move_to(&mut self, xy: Vector2<f32>) {
+        let z = self.body.borrow().position.z;
+        self.body
+            .borrow_mut()
+            .set_position(Vector3::new(xy.x, xy.y, z));
+        let footprint = self.current_footprint();
+        let mut any = false;
+        for (other, other_footprint) in &self.obstacles {
+            let overlaps = footprint.overlaps(other_footprint, self.gap);
+            other.borrow_mut().overlapping = overlaps;
+            any |= overlaps;
+        }
+        self.body.borrow_mut().overlapping = any;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::mesh::{Mesh, Vertex};
+
+    // A 10 mm cube with its corner at `x`, `y`
+    fn cube(name: &str, x: f32, y: f32) -> Rc<RefCell<Body>> {
+        let corners = (0..8).map(|i| {
+            let corner = [i & 1, i & 2, i & 4].map(|bit| if bit == 0 { 0.0 } else { 10.0 });
+            Vertex::new(corner, [0.0, 0.0, 1.0], [0.0, 0.0, 0.0])
+        });
+        let mesh = Mesh {
+            vertices: corners.collect(),
+            ..Default::default()
+        };
+        let mut body = Body::new(mesh);
+        body.name = name.to_string();
+        body.set_position(Vector3::new(x, y, 0.0));
+        Rc::new(RefCell::new(body))
+    }
+
+    // Straight down onto a point of the plate
+    fn down_at(x: f32, y: f32) -> (Vector3<f32>, Vector3<f32>) {
+        (Vector3::new(x, y, 100.0), Vector3::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn test_drag_flags_overlaps_and_is_one_move() {
+        let bodies = vec![cube("a", 0.0, 0.0), cube("b", 30.0, 0.0)];
+        let (origin, direction) = down_at(5.0, 5.0);
+        let mut drag = PlateDrag::begin(&bodies, origin, direction, 1.0).unwrap();
+
+        // Grabbed at the middle of the top face, the body keeps that point under the ray
+        let (origin, direction) = down_at(28.0, 5.0);
+        assert!(drag.update(origin, direction));
+        assert_eq!(bodies[0].borrow().position, Vector3::new(23.0, 0.0, 0.0));
+        assert!(bodies[0].borrow().overlapping);
+        assert!(bodies[1].borrow().overlapping);
+        assert_eq!(drag.overlapping(), vec!["b".to_string()]);
+
+        let (origin, direction) = down_at(15.0, 5.0);
+        drag.update(origin, direction);
+        assert!(!bodies[1].borrow().overlapping);
+        assert!(drag.overlapping().is_empty());
+
+        let mut action = drag.finish().unwrap();
+        assert_eq!(bodies[0].borrow().position, Vector3::zeros());
+        action.execute();
+        assert_eq!(bodies[0].borrow().position, Vector3::new(10.0, 0.0, 0.0));
+        assert!(!bodies[0].borrow().overlapping);
+
+        // Empty space isn't grabbed
+        let (origin, direction) = down_at(-20.0, -20.0);
+        assert!(PlateDrag::begin(&bodies, origin, direction, 1.0).is_none());
+    }
+
+    #[test]
+    fn test_slide_to_free_space() {
+        let bodies = vec![cube("a", 0.0, 0.0), cube("b", 30.0, 0.0)];
+        let (origin, direction) = down_at(5.0, 5.0);
+        let mut drag = PlateDrag::begin(&bodies, origin, direction, 1.0).unwrap();
+        let (origin, direction) = down_at(32.0, 7.0);
+        drag.update(origin, direction);
+        assert!(drag.slide_to_free_space());
+        // Nearest is just left of b, 1 mm away, rather than above it
+        assert_eq!(bodies[0].borrow().position, Vector3::new(19.0, 2.0, 0.0));
+        assert!(!bodies[0].borrow().overlapping);
+        assert!(!drag.slide_to_free_space());
+        assert!(drag.finish().is_some());
+    }
+}
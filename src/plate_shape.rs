@@ -0,0 +1,262 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::{Body, SliceRole};
+use crate::cpu_slicer::CPUSlicer;
+use crate::printer::Printer;
+use geo::{Contains, Coord, Intersects, LineString, Polygon, Rect, Triangle};
+use image::{ImageBuffer, Luma};
+use imageproc::drawing::draw_polygon_mut;
+use imageproc::point::Point;
+use nalgebra::Vector3;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Usable area of a build plate, for printers with clips or damaged LCD regions.
+/// Points are in millimeters with the origin at the center of the plate, the same
+/// coordinates as the body positions, so the LCD orientation applies to them too.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct PlateShape {
+    /// Outline of the usable area, the whole plate when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outline: Vec<[f64; 2]>,
+    /// Areas inside the outline that are never exposed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclusion_zones: Vec<Vec<[f64; 2]>>,
+}
+
+impl PlateShape {
+    fn polygon(points: &[[f64; 2]]) -> Polygon<f64> {
+        let coords: Vec<(f64, f64)> = points.iter().map(|p| (p[0], p[1])).collect();
+        Polygon::new(LineString::from(coords), vec![])
+    }
+
+    /// White where the plate may be exposed, black elsewhere
+    pub fn mask(&self, printer: &Printer) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let to_pixels = |points: &[[f64; 2]]| -> Vec<Point<i32>> {
+            let mut pixels: Vec<Point<i32>> = Vec::new();
+            for p in points {
+                let (x, y) = CPUSlicer::model_to_lcd_coords(p[0], p[1], printer);
+                let pixel = Point::new(x, y);
+                if pixels.last() != Some(&pixel) {
+                    pixels.push(pixel);
+                }
+            }
+            // draw_polygon_mut closes the polygon itself and panics on a repeated start
+            if pixels.len() > 1 && pixels.first() == pixels.last() {
+                pixels.pop();
+            }
+            pixels
+        };
+
+        let outline = to_pixels(&self.outline);
+        let mut mask = if outline.len() >= 3 {
+            let mut mask = ImageBuffer::from_pixel(printer.pixel_x, printer.pixel_y, Luma([0u8]));
+            draw_polygon_mut(&mut mask, &outline, Luma([255u8]));
+            mask
+        } else {
+            ImageBuffer::from_pixel(printer.pixel_x, printer.pixel_y, Luma([255u8]))
+        };
+        for zone in &self.exclusion_zones {
+            let zone = to_pixels(zone);
+            if zone.len() >= 3 {
+                draw_polygon_mut(&mut mask, &zone, Luma([0u8]));
+            }
+        }
+        mask
+    }
+
+    /// Names of the printed bodies that reach outside the outline or into an exclusion
+    /// zone, where their layers will be blanked
+    pub fn blocked_bodies(&self, bodies: &[Body]) -> Vec<String> {
+        let outline = (self.outline.len() >= 3).then(|| Self::polygon(&self.outline));
+        let zones: Vec<Polygon<f64>> = self
+            .exclusion_zones
+            .iter()
+            .filter(|zone| zone.len() >= 3)
+            .map(|zone| Self::polygon(zone))
+            .collect();
+
+        bodies
+            .iter()
+            .filter(|body| body.slice_role == SliceRole::Merge)
+            .filter(|body| {
+                // Bodies whose bounds clear every edge don't need their triangles checked
+                let Some(aabb) = body.world_aabb() else {
+                    return false;
+                };
+                let bounds = Rect::new(
+                    Coord {
+                        x: aabb.min.x as f64,
+                        y: aabb.min.y as f64,
+                    },
+                    Coord {
+                        x: aabb.max.x as f64,
+                        y: aabb.max.y as f64,
+                    },
+                );
+                let clear = outline
+                    .as_ref()
+                    .is_none_or(|outline| outline.contains(&bounds))
+                    && !zones.iter().any(|zone| zone.intersects(&bounds));
+                !clear
+            })
+            .filter(|body| {
+                let triangles = CPUSlicer::world_triangles([*body], Vector3::new(1.0, 1.0, 1.0));
+                triangles.iter().any(|triangle| {
+                    let [a, b, c] = triangle.vertices.map(|v| Coord {
+                        x: v[0] as f64,
+                        y: v[1] as f64,
+                    });
+                    let outside = outline
+                        .as_ref()
+                        .is_some_and(|outline| [a, b, c].iter().any(|v| !outline.intersects(v)));
+                    outside
+                        || zones
+                            .iter()
+                            .any(|zone| zone.intersects(&Triangle::new(a, b, c)))
+                })
+            })
+            .map(|body| body.name.clone())
+            .collect()
+    }
+}
+
+/// Blanks the pixels outside the usable area of the plate
+pub struct PlateMask {
+    mask: ImageBuffer<Luma<u8>, Vec<u8>>,
+}
+
+impl PlateMask {
+    /// The mask for the plate shape of the printer, if it has one
+    pub fn for_printer(printer: &Printer) -> Option<Self> {
+        printer.plate_shape.as_ref().map(|shape| Self {
+            mask: shape.mask(printer),
+        })
+    }
+
+    pub fn apply(&self, image: &mut ImageBuffer<Luma<u8>, Vec<u8>>) {
+        image
+            .iter_mut()
+            .zip(self.mask.iter())
+            .for_each(|(pixel, &mask)| {
+                if mask == 0 {
+                    *pixel = 0;
+                }
+            });
+    }
+
+    pub fn apply_to_all(&self, images: &mut [ImageBuffer<Luma<u8>, Vec<u8>>]) {
+        images.par_iter_mut().for_each(|image| self.apply(image));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+    use crate::stl_processor::StlProcessor;
+
+    fn square(center: [f64; 2], half_size: f64) -> Vec<[f64; 2]> {
+        vec![
+            [center[0] - half_size, center[1] - half_size],
+            [center[0] + half_size, center[1] - half_size],
+            [center[0] + half_size, center[1] + half_size],
+            [center[0] - half_size, center[1] + half_size],
+        ]
+    }
+
+    #[test]
+    fn test_mask_blanks_exclusion_zones() {
+        let printer = Printer {
+            plate_shape: Some(PlateShape {
+                outline: Vec::new(),
+                exclusion_zones: vec![square([50.0, 0.0], 5.0)],
+            }),
+            ..Printer::default()
+        };
+        let plate_mask = PlateMask::for_printer(&printer).unwrap();
+        let mut image = ImageBuffer::from_pixel(printer.pixel_x, printer.pixel_y, Luma([255u8]));
+
+        plate_mask.apply(&mut image);
+
+        let pixel = |x: f64, y: f64| {
+            let (x, y) = CPUSlicer::model_to_image_coords(
+                x,
+                y,
+                printer.pixel_x,
+                printer.physical_x,
+                printer.pixel_y,
+                printer.physical_y,
+            );
+            image.get_pixel(x as u32, y as u32)[0]
+        };
+        assert_eq!(pixel(50.0, 0.0), 0);
+        assert_eq!(pixel(0.0, 0.0), 255);
+        assert_eq!(pixel(-50.0, 0.0), 255);
+    }
+
+    #[test]
+    fn test_mask_outline() {
+        let printer = Printer::default();
+        let shape = PlateShape {
+            // Closed explicitly, which must not trip up the rasterizer
+            outline: vec![[-10.0, -10.0], [10.0, -10.0], [0.0, 10.0], [-10.0, -10.0]],
+            exclusion_zones: Vec::new(),
+        };
+
+        let mask = shape.mask(&printer);
+
+        let center = mask.get_pixel(printer.pixel_x / 2, printer.pixel_y / 2)[0];
+        assert_eq!(center, 255);
+        assert_eq!(mask.get_pixel(0, 0)[0], 0);
+        assert!(PlateMask::for_printer(&Printer::default()).is_none());
+    }
+
+    #[test]
+    fn test_blocked_bodies() {
+        let stl_processor = StlProcessor::new();
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &stl_processor)
+            .unwrap();
+        let mut body = Body::new(mesh);
+        body.name = "part".to_string();
+        let bodies = [body];
+
+        // The part is about 42mm wide around the origin
+        let clear = PlateShape {
+            outline: square([0.0, 0.0], 50.0),
+            exclusion_zones: vec![square([40.0, 40.0], 5.0)],
+        };
+        assert!(clear.blocked_bodies(&bodies).is_empty());
+
+        let clip = PlateShape {
+            outline: Vec::new(),
+            exclusion_zones: vec![square([20.0, 0.0], 5.0)],
+        };
+        assert_eq!(clip.blocked_bodies(&bodies), vec!["part".to_string()]);
+
+        let small_plate = PlateShape {
+            outline: square([0.0, 0.0], 15.0),
+            exclusion_zones: Vec::new(),
+        };
+        assert_eq!(
+            small_plate.blocked_bodies(&bodies),
+            vec!["part".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_printer_profile_round_trip() {
+        let printer = Printer {
+            plate_shape: Some(PlateShape {
+                outline: square([0.0, 0.0], 60.0),
+                exclusion_zones: vec![square([50.0, 50.0], 5.0)],
+            }),
+            ..Printer::default()
+        };
+        let content = toml::to_string(&printer).unwrap();
+        let loaded: Printer = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.plate_shape, printer.plate_shape);
+    }
+}
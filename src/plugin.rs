@@ -0,0 +1,237 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::Body;
+use crate::slice_parameters::SliceParameters;
+use image::{ImageBuffer, Luma};
+use std::sync::{Mutex, MutexGuard};
+
+/// Something a plugin wants to tell the user, e.g. a failed QA check
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginFinding {
+    pub plugin: String,
+    pub message: String,
+}
+
+/// Hooks for third-party analysis of the scene. Every hook has an empty default,
+/// so a plugin only implements the ones it needs and returns its findings.
+pub trait ScenePlugin: Send {
+    /// Unique name, used to unregister the plugin and to attribute its findings
+    fn name(&self) -> &str;
+
+    /// Called for every body after it was imported
+    fn on_import(&mut self, _body: &Body) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Called once before slicing with the bodies and parameters of the job
+    fn on_pre_slice(&mut self, _bodies: &[Body], _parameters: &SliceParameters) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Called for every layer, in order, after slicing and post-processing
+    fn on_layer(&mut self, _index: usize, _image: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Called once after the last layer
+    fn on_post_slice(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The registered plugins, called in registration order
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Box<dyn ScenePlugin>>,
+}
+
+static REGISTRY: Mutex<PluginRegistry> = Mutex::new(PluginRegistry::new());
+
+/// Global plugin registry. Plugins can be registered and removed at any time.
+pub fn registry() -> MutexGuard<'static, PluginRegistry> {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl PluginRegistry {
+    pub const fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+        }
+    }
+
+    /// Adds a plugin, replacing a registered plugin with the same name
+    pub fn register(&mut self, plugin: Box<dyn ScenePlugin>) {
+        self.unregister(plugin.name());
+        self.plugins.push(plugin);
+    }
+
+    /// Removes the plugin with the given name, returning whether one was registered
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let count = self.plugins.len();
+        self.plugins.retain(|plugin| plugin.name() != name);
+        self.plugins.len() != count
+    }
+
+    #[allow(dead_code)]
+    pub fn names(&self) -> Vec<String> {
+        self.plugins.iter().map(|p| p.name().to_string()).collect()
+    }
+
+    fn collect(
+        &mut self,
+        mut hook: impl FnMut(&mut dyn ScenePlugin) -> Vec<String>,
+    ) -> Vec<PluginFinding> {
+        let mut findings = Vec::new();
+        for plugin in self.plugins.iter_mut() {
+            for message in hook(plugin.as_mut()) {
+                findings.push(PluginFinding {
+                    plugin: plugin.name().to_string(),
+                    message,
+                });
+            }
+        }
+        findings
+    }
+
+    pub fn on_import(&mut self, body: &Body) -> Vec<PluginFinding> {
+        self.collect(|plugin| plugin.on_import(body))
+    }
+
+    pub fn on_pre_slice(
+        &mut self,
+        bodies: &[Body],
+        parameters: &SliceParameters,
+    ) -> Vec<PluginFinding> {
+        self.collect(|plugin| plugin.on_pre_slice(bodies, parameters))
+    }
+
+    /// Runs the per-layer hooks over all layers followed by the post-slice hooks
+    pub fn on_layers(&mut self, images: &[ImageBuffer<Luma<u8>, Vec<u8>>]) -> Vec<PluginFinding> {
+        let mut findings = Vec::new();
+        for (index, image) in images.iter().enumerate() {
+            findings.extend(self.collect(|plugin| plugin.on_layer(index, image)));
+        }
+        findings.extend(self.collect(|plugin| plugin.on_post_slice()));
+        findings
+    }
+}
+
+/// Built-in QA check: reports empty layers between exposed layers, where the print
+/// would separate into two parts
+#[derive(Default)]
+pub struct EmptyLayerCheck {
+    last_exposed: Option<usize>,
+    empty_run_start: Option<usize>,
+    findings: Vec<String>,
+}
+
+impl ScenePlugin for EmptyLayerCheck {
+    fn name(&self) -> &str {
+        "empty layer check"
+    }
+
+    fn on_pre_slice(&mut self, _bodies: &[Body], _parameters: &SliceParameters) -> Vec<String> {
+        *self = Self::default();
+        Vec::new()
+    }
+
+    fn on_layer(&mut self, index: usize, image: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<String> {
+        let exposed = image.iter().any(|&value| value > 0);
+        if !exposed {
+            self.empty_run_start.get_or_insert(index);
+            return Vec::new();
+        }
+        if let (Some(start), Some(_)) = (self.empty_run_start.take(), self.last_exposed) {
+            self.findings.push(format!(
+                "Layers {} to {} are empty, the print separates there",
+                start,
+                index - 1
+            ));
+        }
+        self.last_exposed = Some(index);
+        Vec::new()
+    }
+
+    fn on_post_slice(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.findings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingPlugin {
+        name: String,
+        imports: usize,
+    }
+
+    impl ScenePlugin for CountingPlugin {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn on_import(&mut self, body: &Body) -> Vec<String> {
+            self.imports += 1;
+            vec![format!(
+                "imported {} bodies, last {}",
+                self.imports, body.name
+            )]
+        }
+    }
+
+    fn layer(value: u8) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_pixel(4, 4, Luma([value]))
+    }
+
+    #[test]
+    fn test_register_replace_and_unregister() {
+        let mut registry = PluginRegistry::new();
+        let counter = |name: &str| {
+            Box::new(CountingPlugin {
+                name: name.to_string(),
+                imports: 0,
+            })
+        };
+        registry.register(counter("a"));
+        registry.register(counter("b"));
+        registry.register(counter("a"));
+        assert_eq!(registry.names(), vec!["b", "a"]);
+
+        let findings = registry.on_import(&Body::default());
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].plugin, "b");
+
+        assert!(registry.unregister("b"));
+        assert!(!registry.unregister("b"));
+        assert_eq!(registry.on_import(&Body::default()).len(), 1);
+    }
+
+    #[test]
+    fn test_empty_layer_check() {
+        let mut registry = PluginRegistry::new();
+        registry.register(Box::<EmptyLayerCheck>::default());
+
+        // Leading and trailing empty layers are fine, the gap in the middle is not
+        let images = vec![
+            layer(0),
+            layer(255),
+            layer(0),
+            layer(0),
+            layer(200),
+            layer(0),
+        ];
+        let findings = registry.on_layers(&images);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].message,
+            "Layers 2 to 3 are empty, the print separates there"
+        );
+
+        registry.on_pre_slice(&[], &SliceParameters::default());
+        assert!(registry.on_layers(&[layer(255), layer(255)]).is_empty());
+    }
+}
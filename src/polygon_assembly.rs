@@ -0,0 +1,301 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+// Only depends on nalgebra, so the fuzz targets in fuzz/ can build it on its own
+use nalgebra::Vector3;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A line where a triangle crosses the slicing plane and the normal of that triangle
+pub type Segment = ((Vector3<f64>, Vector3<f64>), [f32; 3]);
+
+/// A closed contour of a layer and its place among the others: an outer boundary, a hole
+/// in it, an island in that hole and so on
+#[derive(Clone, Debug, PartialEq)]
+pub struct NestedContour {
+    pub points: Vec<Vector3<f64>>,
+    /// Index of the smallest contour around this one, None for outer boundaries
+    pub parent: Option<usize>,
+    /// Number of contours around this one
+    pub depth: usize,
+}
+
+impl NestedContour {
+    /// Holes are inside an odd number of contours, whichever way they are wound
+    pub fn is_hole(&self) -> bool {
+        self.depth % 2 == 1
+    }
+}
+
+/// Assembles segments into closed contours and nests them in each other. Ends of segments
+/// within `epsilon` of each other are joined. Segments that don't close a loop are left out.
+pub fn assemble_polygons(segments: &[Segment], epsilon: f64) -> Vec<NestedContour> {
+    fn point_to_key(p: &Vector3<f64>, epsilon: f64) -> (i64, i64) {
+        let scale = 1.0 / epsilon;
+        let x = (p[0] * scale).round() as i64;
+        let y = (p[1] * scale).round() as i64;
+        (x, y)
+    }
+
+    // Ordered maps, so polygons are traced from the same start point in the same
+    // direction on every run
+    let mut point_coords: BTreeMap<(i64, i64), Vector3<f64>> = BTreeMap::new();
+    let mut adjacency: BTreeMap<(i64, i64), Vec<(i64, i64)>> = BTreeMap::new();
+
+    // Build adjacency map
+    for &((ref start, ref end), _) in segments {
+        let start_key = point_to_key(start, epsilon);
+        let end_key = point_to_key(end, epsilon);
+
+        point_coords.entry(start_key).or_insert(*start);
+        point_coords.entry(end_key).or_insert(*end);
+
+        adjacency.entry(start_key).or_default().push(end_key);
+        adjacency.entry(end_key).or_default().push(start_key);
+    }
+
+    let mut polygons = Vec::new();
+    let mut visited_edges: BTreeSet<((i64, i64), (i64, i64))> = BTreeSet::new();
+
+    // Traverse the graph to assemble polygons
+    for &start_key in adjacency.keys() {
+        for &next_key in &adjacency[&start_key] {
+            let edge = (start_key, next_key);
+            if visited_edges.contains(&edge) || visited_edges.contains(&(next_key, start_key)) {
+                continue;
+            }
+
+            let mut polygon_keys = vec![start_key];
+            let mut current_key = next_key;
+            visited_edges.insert(edge);
+
+            loop {
+                polygon_keys.push(current_key);
+
+                if let Some(neighbors) = adjacency.get(&current_key) {
+                    // Find the next neighbor that hasn't been visited
+                    let mut found = false;
+                    for &neighbor_key in neighbors {
+                        let edge = (current_key, neighbor_key);
+                        if neighbor_key != polygon_keys[polygon_keys.len() - 2]
+                            && !visited_edges.contains(&edge)
+                            && !visited_edges.contains(&(neighbor_key, current_key))
+                        {
+                            visited_edges.insert(edge);
+                            current_key = neighbor_key;
+                            found = true;
+                            break;
+                        }
+                    }
+
+                    if !found {
+                        break;
+                    }
+
+                    // Check if the polygon is closed
+                    if current_key == start_key {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            // If we have a closed polygon
+            if polygon_keys.len() >= 3 && current_key == start_key {
+                // Convert keys back to points
+                polygons.push(polygon_keys.iter().map(|key| point_coords[key]).collect());
+            }
+        }
+    }
+    nest_contours(polygons)
+}
+
+/// Finds which contours lie inside which. The contours of a closed mesh don't cross each
+/// other, so one point of a contour tells whether it is inside another.
+pub fn nest_contours(contours: Vec<Vec<Vector3<f64>>>) -> Vec<NestedContour> {
+    let bounds: Vec<Bounds> = contours.iter().map(|contour| Bounds::of(contour)).collect();
+    let areas: Vec<f64> = contours.iter().map(|contour| area(contour).abs()).collect();
+    let around: Vec<Vec<usize>> = (0..contours.len())
+        .map(|i| {
+            (0..contours.len())
+                .filter(|&j| {
+                    j != i
+                        && bounds[j].encloses(&bounds[i])
+                        && contains(&contours[j], contours[i][0])
+                })
+                .collect()
+        })
+        .collect();
+    contours
+        .into_iter()
+        .zip(around)
+        .map(|(points, around)| NestedContour {
+            points,
+            parent: around
+                .iter()
+                .copied()
+                .min_by(|&a, &b| areas[a].total_cmp(&areas[b])),
+            depth: around.len(),
+        })
+        .collect()
+}
+
+/// Axis aligned bounds of a contour in the slicing plane
+struct Bounds {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+impl Bounds {
+    fn of(contour: &[Vector3<f64>]) -> Self {
+        contour.iter().fold(
+            Self {
+                min: (f64::INFINITY, f64::INFINITY),
+                max: (f64::NEG_INFINITY, f64::NEG_INFINITY),
+            },
+            |bounds, p| Self {
+                min: (bounds.min.0.min(p.x), bounds.min.1.min(p.y)),
+                max: (bounds.max.0.max(p.x), bounds.max.1.max(p.y)),
+            },
+        )
+    }
+
+    fn encloses(&self, other: &Bounds) -> bool {
+        self.min.0 <= other.min.0
+            && self.min.1 <= other.min.1
+            && self.max.0 >= other.max.0
+            && self.max.1 >= other.max.1
+    }
+}
+
+/// Signed area by the shoelace formula, positive when wound counterclockwise
+fn area(contour: &[Vector3<f64>]) -> f64 {
+    let mut sum = 0.0;
+    for (i, a) in contour.iter().enumerate() {
+        let b = contour[(i + 1) % contour.len()];
+        sum += a.x * b.y - b.x * a.y;
+    }
+    sum / 2.0
+}
+
+/// Whether a point is inside a contour, by the number of its edges a ray to +X crosses
+fn contains(contour: &[Vector3<f64>], point: Vector3<f64>) -> bool {
+    let mut inside = false;
+    for (i, a) in contour.iter().enumerate() {
+        let b = contour[(i + 1) % contour.len()];
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) * (b.x - a.x) / (b.y - a.y)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_square() {
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let outward = [
+            [0.0, -1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [-1.0, 0.0, 0.0],
+        ];
+        let point = |(x, y): (f64, f64)| Vector3::new(x, y, 0.0);
+        // Shuffled and flipped, the way triangles of a mesh hit the plane
+        let segments: Vec<Segment> = [2, 0, 3, 1]
+            .iter()
+            .map(|&i| {
+                let (a, b) = (point(corners[i]), point(corners[(i + 1) % 4]));
+                (if i % 2 == 0 { (b, a) } else { (a, b) }, outward[i])
+            })
+            .collect();
+
+        let polygons = assemble_polygons(&segments, 1e-6);
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].points.len(), 4);
+        assert_eq!(polygons[0].parent, None);
+
+        // Open chains are dropped
+        assert!(assemble_polygons(&segments[..3], 1e-6).is_empty());
+
+        // Ends that don't quite meet are joined within the tolerance only
+        let mut gapped = segments.clone();
+        gapped[0].0 .0.x += 1e-5;
+        assert!(assemble_polygons(&gapped, 1e-6).is_empty());
+        assert_eq!(assemble_polygons(&gapped, 1e-4).len(), 1);
+    }
+
+    #[test]
+    fn test_nested_contours() {
+        let square = |center: f64, half: f64| {
+            vec![
+                Vector3::new(center - half, -half, 0.0),
+                Vector3::new(center + half, -half, 0.0),
+                Vector3::new(center + half, half, 0.0),
+                Vector3::new(center - half, half, 0.0),
+            ]
+        };
+        // A tube with a rod in its bore, the rod hollow, and a part next to the tube
+        let contours = nest_contours(vec![
+            square(0.0, 2.0),
+            square(0.0, 8.0),
+            square(20.0, 3.0),
+            square(0.0, 1.0),
+            square(0.0, 5.0),
+        ]);
+        let nesting: Vec<(Option<usize>, usize)> = contours
+            .iter()
+            .map(|contour| (contour.parent, contour.depth))
+            .collect();
+        assert_eq!(
+            nesting,
+            vec![
+                (Some(4), 2),
+                (None, 0),
+                (None, 0),
+                (Some(0), 3),
+                (Some(1), 1),
+            ]
+        );
+        let holes: Vec<bool> = contours.iter().map(NestedContour::is_hole).collect();
+        assert_eq!(holes, vec![false, false, false, true, true]);
+    }
+
+    #[test]
+    fn test_assemble_segment_soups() {
+        // The same as the fuzz target, on soups of segments between a few grid points so
+        // that loops, branches and dead ends are common, at scales up to non-finite ones
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+        let scales = [1.0, 3.3e-7, 1e9, 1e300, f64::INFINITY, f64::NAN];
+        for _ in 0..5000 {
+            let scale = scales[next(scales.len() as u64) as usize];
+            let segments: Vec<Segment> = (0..next(12))
+                .map(|_| {
+                    let mut point = || {
+                        let (x, y) = (next(4) as f64 - 1.5, next(4) as f64 - 1.5);
+                        Vector3::new(x * scale, y * scale, 0.0)
+                    };
+                    let (a, b) = (point(), point());
+                    let normal = [next(3) as f32 - 1.0, next(3) as f32 - 1.0, 0.0];
+                    ((a, b), normal)
+                })
+                .collect();
+            let contours = assemble_polygons(&segments, 1e-6);
+            for contour in &contours {
+                assert!(contour.points.len() >= 3);
+                assert!(contour.parent.is_none_or(|parent| parent < contours.len()));
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::settings::PngCompression;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ImageBuffer, ImageError, Luma, Rgb};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::ops::Deref;
+
+/// How a preview image is stored in the exported job
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewEncoding {
+    /// Raw 16 bit pixels, 5 bits red, 6 green and 5 blue, little endian, row by row
+    Rgb565,
+    Png,
+}
+
+impl PreviewEncoding {
+    fn extension(&self) -> &'static str {
+        match self {
+            PreviewEncoding::Rgb565 => "rgb565",
+            PreviewEncoding::Png => "png",
+        }
+    }
+}
+
+/// A preview image the printer firmware expects, configured per printer:
+///
+/// ```toml
+/// [[previews]]
+/// width = 224
+/// height = 168
+/// encoding = "rgb565"
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PreviewFormat {
+    pub width: u32,
+    pub height: u32,
+    pub encoding: PreviewEncoding,
+    /// Color of the highest point of the model, lower layers are drawn darker
+    #[serde(default = "default_model_color")]
+    pub model_color: [u8; 3],
+    #[serde(default)]
+    pub background_color: [u8; 3],
+}
+
+fn default_model_color() -> [u8; 3] {
+    [255, 140, 40]
+}
+
+/// A rendered and encoded preview, ready to be written next to the layers
+pub struct EncodedPreview {
+    pub format: PreviewFormat,
+    pub bytes: Vec<u8>,
+}
+
+impl EncodedPreview {
+    pub fn file_name(&self) -> String {
+        format!(
+            "preview_{}x{}.{}",
+            self.format.width,
+            self.format.height,
+            self.format.encoding.extension()
+        )
+    }
+}
+
+impl PreviewFormat {
+    /// Renders a top-down view of the plate from the sliced layers: every pixel shows
+    /// the highest exposed layer at that spot, shaded by height. The plate is scaled to
+    /// fit the preview with its aspect ratio kept.
+    pub fn render<C: Deref<Target = [u8]>>(
+        &self,
+        layers: &[ImageBuffer<Luma<u8>, C>],
+    ) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        let mut preview =
+            ImageBuffer::from_pixel(self.width, self.height, Rgb(self.background_color));
+        let Some(first) = layers.first() else {
+            return preview;
+        };
+        let (plate_width, plate_height) = first.dimensions();
+        let scale =
+            (self.width as f32 / plate_width as f32).min(self.height as f32 / plate_height as f32);
+        let offset_x = (self.width as f32 - plate_width as f32 * scale) / 2.0;
+        let offset_y = (self.height as f32 - plate_height as f32 * scale) / 2.0;
+
+        for (x, y, pixel) in preview.enumerate_pixels_mut() {
+            // Sample the plate at the center of the preview pixel
+            let plate_x = ((x as f32 + 0.5 - offset_x) / scale).floor();
+            let plate_y = ((y as f32 + 0.5 - offset_y) / scale).floor();
+            if plate_x < 0.0
+                || plate_y < 0.0
+                || plate_x >= plate_width as f32
+                || plate_y >= plate_height as f32
+            {
+                continue;
+            }
+            let top_layer = layers
+                .iter()
+                .rposition(|layer| layer.get_pixel(plate_x as u32, plate_y as u32)[0] > 127);
+            if let Some(index) = top_layer {
+                let shade = 0.35 + 0.65 * (index + 1) as f32 / layers.len() as f32;
+                *pixel = Rgb(self
+                    .model_color
+                    .map(|channel| (channel as f32 * shade).round() as u8));
+            }
+        }
+        preview
+    }
+
+    /// `png` only applies to PNG previews, the other encodings aren't compressed
+    pub fn encode(
+        &self,
+        preview: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+        png: PngCompression,
+    ) -> Result<Vec<u8>, ImageError> {
+        match self.encoding {
+            PreviewEncoding::Rgb565 => Ok(preview
+                .pixels()
+                .flat_map(|Rgb([r, g, b])| {
+                    let packed =
+                        ((*r as u16 >> 3) << 11) | ((*g as u16 >> 2) << 5) | (*b as u16 >> 3);
+                    packed.to_le_bytes()
+                })
+                .collect()),
+            PreviewEncoding::Png => {
+                let compression = match png {
+                    PngCompression::Fast => CompressionType::Fast,
+                    PngCompression::Default => CompressionType::Default,
+                    PngCompression::Best => CompressionType::Best,
+                };
+                let mut bytes = Vec::new();
+                let encoder = PngEncoder::new_with_quality(
+                    Cursor::new(&mut bytes),
+                    compression,
+                    FilterType::Adaptive,
+                );
+                preview.write_with_encoder(encoder)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Renders and encodes every preview format of a printer
+    pub fn render_all<C: Deref<Target = [u8]>>(
+        formats: &[PreviewFormat],
+        layers: &[ImageBuffer<Luma<u8>, C>],
+        png: PngCompression,
+    ) -> Result<Vec<EncodedPreview>, ImageError> {
+        formats
+            .iter()
+            .map(|format| {
+                Ok(EncodedPreview {
+                    format: format.clone(),
+                    bytes: format.encode(&format.render(layers), png)?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(width: u32, height: u32, encoding: PreviewEncoding) -> PreviewFormat {
+        PreviewFormat {
+            width,
+            height,
+            encoding,
+            model_color: [255, 255, 255],
+            background_color: [0, 0, 0],
+        }
+    }
+
+    #[test]
+    fn test_render_shades_by_height_and_keeps_aspect_ratio() {
+        // A 40x20 plate, the left half is one layer tall and the right half two
+        let bottom = ImageBuffer::from_pixel(40, 20, Luma([255u8]));
+        let top = ImageBuffer::from_fn(40, 20, |x, _| Luma([if x >= 20 { 255 } else { 0 }]));
+        let preview = format(20, 20, PreviewEncoding::Png).render(&[bottom, top]);
+
+        // The plate is letterboxed to 20x10 in the middle of the preview
+        assert_eq!(preview.get_pixel(5, 2), &Rgb([0, 0, 0]));
+        assert_eq!(preview.get_pixel(15, 17), &Rgb([0, 0, 0]));
+        let low = preview.get_pixel(5, 10)[0];
+        let high = preview.get_pixel(15, 10)[0];
+        assert!(low > 0 && low < high);
+        assert_eq!(high, 255);
+    }
+
+    #[test]
+    fn test_rgb565_encoding() {
+        let preview_format = format(224, 168, PreviewEncoding::Rgb565);
+        let preview = ImageBuffer::from_pixel(224, 168, Rgb([255u8, 0, 255]));
+        let bytes = preview_format
+            .encode(&preview, PngCompression::Fast)
+            .unwrap();
+
+        assert_eq!(bytes.len(), 224 * 168 * 2);
+        assert_eq!(
+            u16::from_le_bytes([bytes[0], bytes[1]]),
+            0xF81F
+        );
+    }
+
+    #[test]
+    fn test_render_all_from_printer_profile() {
+        let content = r#"
+            [[previews]]
+            width = 224
+            height = 168
+            encoding = "rgb565"
+
+            [[previews]]
+            width = 64
+            height = 64
+            encoding = "png"
+        "#;
+        #[derive(Deserialize)]
+        struct Profile {
+            previews: Vec<PreviewFormat>,
+        }
+        let profile: Profile = toml::from_str(content).unwrap();
+        assert_eq!(profile.previews[0].model_color, default_model_color());
+
+        let layers = vec![ImageBuffer::from_pixel(32, 18, Luma([255u8]))];
+        let previews =
+            PreviewFormat::render_all(&profile.previews, &layers, PngCompression::Best).unwrap();
+        assert_eq!(previews[0].file_name(), "preview_224x168.rgb565");
+        assert_eq!(previews[1].file_name(), "preview_64x64.png");
+        let png = image::load_from_memory(&previews[1].bytes).unwrap();
+        assert_eq!((png.width(), png.height()), (64, 64));
+    }
+}
@@ -0,0 +1,313 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::motion_profile::{MeasuredPrint, TimeCalibration};
+use crate::settings::{write_atomically, SettingsError};
+use crate::slice_parameters::SliceParameters;
+use dirs_next::config_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many of the newest exports of a body are listed when showing its history
+pub const SHOWN_PER_BODY: usize = 5;
+
+/// How a print came out, as marked by the user
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrintOutcome {
+    #[default]
+    Unknown,
+    Success,
+    Failed,
+}
+
+/// One export of a body, with everything needed to slice it the same way again
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrintRecord {
+    /// Name of the body, which is the name of the file it was imported from
+    pub body: String,
+    /// Seconds since the Unix epoch
+    pub exported_at: u64,
+    pub output_dir: PathBuf,
+    /// The parameter snapshot that was active, None if the parameters were edited
+    pub profile: Option<String>,
+    pub parameters: SliceParameters,
+    #[serde(default)]
+    pub outcome: PrintOutcome,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+    /// Layers of the whole job the body was exported with
+    #[serde(default)]
+    pub layer_count: usize,
+    /// Seconds the job took on the printer, as entered by the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub measured_print_time: Option<f64>,
+}
+
+impl PrintRecord {
+    pub fn describe(&self) -> String {
+        let mut description = format!(
+            "{} exported {} with {}",
+            self.body,
+            age(self.exported_at),
+            self.profile.as_deref().unwrap_or("edited parameters")
+        );
+        match self.outcome {
+            PrintOutcome::Unknown => {}
+            PrintOutcome::Success => description.push_str(", printed fine"),
+            PrintOutcome::Failed => description.push_str(", failed"),
+        }
+        if !self.note.is_empty() {
+            description.push_str(&format!(" ({})", self.note));
+        }
+        description
+    }
+}
+
+// How long ago a time was, roughly
+fn age(seconds: u64) -> String {
+    let elapsed = now().saturating_sub(seconds);
+    match elapsed {
+        0..=3599 => format!("{} minutes ago", elapsed / 60),
+        3600..=86399 => format!("{} hours ago", elapsed / 3600),
+        _ => format!("{} days ago", elapsed / 86400),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Every export of every body, oldest first, kept with the user's settings
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PrintHistory {
+    #[serde(default)]
+    pub records: Vec<PrintRecord>,
+}
+
+impl PrintHistory {
+    fn user_history_path() -> Result<PathBuf, SettingsError> {
+        let config_dir = config_dir().ok_or(SettingsError::ConfigDirNotFound)?;
+        Ok(config_dir
+            .join("SealSlicer")
+            .join("settings")
+            .join("print_history.toml"))
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, SettingsError> {
+        let content = fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), SettingsError> {
+        write_atomically(path, &toml::to_string_pretty(self)?)
+    }
+
+    /// Loads the user's history, or starts empty if nothing was exported yet
+    pub fn load_user_history() -> Self {
+        let path = match Self::user_history_path() {
+            Ok(path) if path.exists() => path,
+            _ => return Self::default(),
+        };
+        Self::load_from_file(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to load print history: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn save_user_history(&self) -> Result<(), SettingsError> {
+        self.save_to_file(&Self::user_history_path()?)
+    }
+
+    /// Adds a record for every body of an export
+    pub fn record_export<'a>(
+        &mut self,
+        bodies: impl IntoIterator<Item = &'a str>,
+        output_dir: &Path,
+        profile: Option<String>,
+        parameters: &SliceParameters,
+        layer_count: usize,
+    ) {
+        let exported_at = now();
+        for body in bodies {
+            self.records.push(PrintRecord {
+                body: body.to_string(),
+                exported_at,
+                output_dir: output_dir.to_path_buf(),
+                profile: profile.clone(),
+                parameters: parameters.clone(),
+                outcome: PrintOutcome::Unknown,
+                note: String::new(),
+                layer_count,
+                measured_print_time: None,
+            });
+        }
+    }
+
+    /// Marks the last export of a body, returning it. None if the body was never exported.
+    pub fn mark_latest(
+        &mut self,
+        body: &str,
+        outcome: PrintOutcome,
+        note: &str,
+    ) -> Option<&PrintRecord> {
+        let record = self.records.iter_mut().rev().find(|r| r.body == body)?;
+        record.outcome = outcome;
+        if !note.is_empty() {
+            record.note = note.to_string();
+        }
+        Some(record)
+    }
+
+    /// Sets how long the last export of a body took to print, on every body of that job.
+    /// Returns the record, None if the body was never exported.
+    pub fn record_print_time(&mut self, body: &str, seconds: f64) -> Option<&PrintRecord> {
+        let index = self.records.iter().rposition(|r| r.body == body)?;
+        let (output_dir, exported_at) = {
+            let record = &self.records[index];
+            (record.output_dir.clone(), record.exported_at)
+        };
+        self.records
+            .iter_mut()
+            .filter(|r| r.output_dir == output_dir && r.exported_at == exported_at)
+            .for_each(|r| r.measured_print_time = Some(seconds));
+        Some(&self.records[index])
+    }
+
+    /// The calibration fitted to the measured jobs of a printer, None when none of its jobs
+    /// were measured
+    pub fn time_calibration(&self, printer: &str) -> Option<TimeCalibration> {
+        let mut jobs: Vec<&PrintRecord> = self
+            .records
+            .iter()
+            .filter(|r| r.parameters.printer.name == printer && r.measured_print_time.is_some())
+            .collect();
+        // Every body of a job has its own record
+        jobs.dedup_by(|a, b| a.output_dir == b.output_dir && a.exported_at == b.exported_at);
+        let prints: Vec<MeasuredPrint> = jobs
+            .iter()
+            .filter_map(|r| {
+                Some(MeasuredPrint::of(
+                    &r.parameters.resin.motion,
+                    r.layer_count,
+                    r.parameters.slice_thickness,
+                    r.measured_print_time?,
+                ))
+            })
+            .collect();
+        TimeCalibration::fit(&prints)
+    }
+
+    /// The last export of a body that printed fine
+    pub fn last_good(&self, body: &str) -> Option<&PrintRecord> {
+        self.records
+            .iter()
+            .rev()
+            .find(|r| r.body == body && r.outcome == PrintOutcome::Success)
+    }
+
+    /// Every export of a body, newest first
+    pub fn of_body<'a>(&'a self, body: &'a str) -> impl Iterator<Item = &'a PrintRecord> {
+        self.records.iter().rev().filter(move |r| r.body == body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_history() {
+        let mut history = PrintHistory::default();
+        let mut parameters = SliceParameters {
+            slice_thickness: 0.05,
+            ..SliceParameters::default()
+        };
+        history.record_export(
+            ["ring.stl", "base.stl"],
+            Path::new("out/1"),
+            Some("Fine".to_string()),
+            &parameters,
+            400,
+        );
+        parameters.slice_thickness = 0.1;
+        history.record_export(["ring.stl"], Path::new("out/2"), None, &parameters, 200);
+
+        assert!(history.last_good("ring.stl").is_none());
+        assert!(history
+            .mark_latest("ring.stl", PrintOutcome::Failed, "warped")
+            .is_some());
+        assert!(history
+            .mark_latest("cube.stl", PrintOutcome::Success, "")
+            .is_none());
+        // Only the first export of the ring printed fine
+        history.records[0].outcome = PrintOutcome::Success;
+        let good = history.last_good("ring.stl").unwrap();
+        assert_eq!(good.output_dir, Path::new("out/1"));
+        assert_eq!(good.parameters.slice_thickness, 0.05);
+
+        let ring: Vec<_> = history.of_body("ring.stl").collect();
+        assert_eq!(ring.len(), 2);
+        assert_eq!(
+            ring[0].describe(),
+            "ring.stl exported 0 minutes ago with edited parameters, failed (warped)"
+        );
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("print_history.toml");
+        history.save_to_file(&path).unwrap();
+        let loaded = PrintHistory::load_from_file(&path).unwrap();
+        assert_eq!(loaded.records.len(), 3);
+        assert_eq!(loaded.records[2].outcome, PrintOutcome::Failed);
+        assert_eq!(loaded.records[2].note, "warped");
+        assert_eq!(loaded.records[1].profile.as_deref(), Some("Fine"));
+        assert_eq!(loaded.records[1].layer_count, 400);
+    }
+
+    #[test]
+    fn test_time_calibration_from_history() {
+        let mut history = PrintHistory::default();
+        let parameters = SliceParameters::default();
+        let printer = parameters.printer.name.clone();
+        history.record_export(
+            ["ring.stl", "base.stl"],
+            Path::new("out/1"),
+            None,
+            &parameters,
+            400,
+        );
+        assert_eq!(history.time_calibration(&printer), None);
+
+        // Both bodies of the job get the time, the job counts once
+        let estimate: f64 = parameters
+            .resin
+            .motion
+            .timeline(400, parameters.slice_thickness)
+            .iter()
+            .map(|t| t.total())
+            .sum();
+        assert!(history
+            .record_print_time("base.stl", estimate + 400.0)
+            .is_some());
+        assert!(history.record_print_time("cube.stl", 60.0).is_none());
+        assert!(history
+            .records
+            .iter()
+            .all(|r| r.measured_print_time.is_some()));
+
+        let calibration = history.time_calibration(&printer).unwrap();
+        let mut timeline = parameters
+            .resin
+            .motion
+            .timeline(400, parameters.slice_thickness);
+        calibration.apply(&mut timeline);
+        let calibrated: f64 = timeline.iter().map(|t| t.total()).sum();
+        assert!((calibrated - estimate - 400.0).abs() < 1e-6);
+        assert_eq!(history.time_calibration("Other printer"), None);
+    }
+}
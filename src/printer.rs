@@ -1,7 +1,20 @@
-use std::{fs, io::Write, path::Path};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
+use crate::bleed_compensation::BleedCompensation;
+use crate::motion_profile::TimeCalibration;
+use crate::output_formats::OutputFormat;
+use crate::plate_shape::PlateShape;
+use crate::preview::PreviewFormat;
+use crate::tolerance::ToleranceOverrides;
+use crate::vat::Vat;
+use crate::xy_compensation::XyCompensation;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Printer {
     pub name: String,
@@ -12,6 +25,139 @@ pub struct Printer {
     pub physical_z: f64, // millimeters
     pub pixel_x: u32,
     pub pixel_y: u32,
+    /// Optional grayscale image with one value per LCD pixel used to even out the illumination
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calibration_mask: Option<PathBuf>,
+    /// Outlines moved outwards, or inwards when negative, to calibrate holes and pegs
+    #[serde(default, skip_serializing_if = "XyCompensation::is_off")]
+    pub xy_compensation: XyCompensation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bleed_compensation: Option<BleedCompensation>,
+    /// Preview images the firmware shows before printing, one per required size/encoding
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previews: Vec<PreviewFormat>,
+    /// Usable area of the plate for printers with clips or damaged LCD regions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plate_shape: Option<PlateShape>,
+    #[serde(default, skip_serializing_if = "LcdOrientation::is_identity")]
+    pub lcd_orientation: LcdOrientation,
+    /// Slicing tolerances for experts, derived from the size of each body when left out
+    #[serde(default, skip_serializing_if = "ToleranceOverrides::is_default")]
+    pub tolerances: ToleranceOverrides,
+    /// The file the firmware reads, a folder of layers unless set
+    #[serde(default, skip_serializing_if = "OutputFormat::is_folder")]
+    pub output_format: OutputFormat,
+    /// Gray edges instead of hard steps along the outlines of the layers
+    #[serde(default, skip_serializing_if = "AntiAliasing::is_off")]
+    pub anti_aliasing: AntiAliasing,
+    /// Fitted to the print times measured on this printer
+    #[serde(default, skip_serializing_if = "TimeCalibration::is_identity")]
+    pub time_calibration: TimeCalibration,
+    /// Inside of the resin vat, a bit larger than the plate when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vat: Option<Vat>,
+}
+
+/// Gray levels the edges of the layers are blurred into, e.g. `anti_aliasing = "4x"`. More
+/// levels follow the outline closer.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum AntiAliasing {
+    #[default]
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "2x")]
+    X2,
+    #[serde(rename = "4x")]
+    X4,
+    #[serde(rename = "8x")]
+    X8,
+}
+
+impl AntiAliasing {
+    /// In the order the parameters panel lists them
+    pub const ALL: [AntiAliasing; 4] = [
+        AntiAliasing::Off,
+        AntiAliasing::X2,
+        AntiAliasing::X4,
+        AntiAliasing::X8,
+    ];
+
+    pub fn is_off(&self) -> bool {
+        *self == AntiAliasing::Off
+    }
+
+    /// Steps between black and white an edge pixel can take
+    pub fn levels(&self) -> u32 {
+        match self {
+            AntiAliasing::Off => 1,
+            AntiAliasing::X2 => 2,
+            AntiAliasing::X4 => 4,
+            AntiAliasing::X8 => 8,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AntiAliasing::Off => "Off",
+            AntiAliasing::X2 => "2x",
+            AntiAliasing::X4 => "4x",
+            AntiAliasing::X8 => "8x",
+        }
+    }
+
+    /// Position in `ALL`
+    pub fn from_index(index: i32) -> Self {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| Self::ALL.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn index(self) -> i32 {
+        Self::ALL
+            .iter()
+            .position(|&level| level == self)
+            .unwrap_or(0) as i32
+    }
+}
+
+/// How the layers are laid out on the LCD relative to the plate seen from above.
+/// Applied when rasterizing, before millimeters are turned into pixels.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct LcdOrientation {
+    /// Swaps the X and Y axes first, for LCDs mounted at 90 degrees to the plate
+    pub swap_xy: bool,
+    /// Mirrors the layers left to right, needed by most printers as the LCD is seen from below
+    pub mirror_x: bool,
+    pub mirror_y: bool,
+}
+
+impl LcdOrientation {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Where a point on the plate ends up on the LCD, both in millimeters from the center
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let (x, y) = if self.swap_xy { (y, x) } else { (x, y) };
+        (
+            if self.mirror_x { -x } else { x },
+            if self.mirror_y { -y } else { y },
+        )
+    }
+
+    /// Where a point on the LCD is on the plate, undoing `apply`
+    pub fn invert(&self, x: f64, y: f64) -> (f64, f64) {
+        let x = if self.mirror_x { -x } else { x };
+        let y = if self.mirror_y { -y } else { y };
+        if self.swap_xy {
+            (y, x)
+        } else {
+            (x, y)
+        }
+    }
 }
 
 impl Default for Printer {
@@ -0,0 +1,137 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::motion_profile::{MotionProfile, TimeCalibration};
+use crate::output_formats::OutputFormat;
+use crate::printer::{AntiAliasing, LcdOrientation, Printer};
+use crate::slice_parameters::SliceParameters;
+use crate::tolerance::ToleranceOverrides;
+use crate::xy_compensation::XyCompensation;
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+/// Built into the binary so new users can pick their printer without any config files
+const PRESETS: &str = include_str!("../config/printers/presets.toml");
+
+/// A common printer with its LCD resolution, build volume and motion settings to start from
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct PrinterPreset {
+    pub brand: String,
+    pub model: String,
+    pub physical_x: f64, // millimeters
+    pub physical_y: f64, // millimeters
+    pub physical_z: f64, // millimeters
+    pub pixel_x: u32,
+    pub pixel_y: u32,
+    #[serde(default)]
+    pub lcd_orientation: LcdOrientation,
+    #[serde(default)]
+    pub motion: MotionProfile,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Deserialize)]
+struct PresetFile {
+    presets: Vec<PrinterPreset>,
+}
+
+/// Every preset, in the order they are listed
+pub fn presets() -> &'static [PrinterPreset] {
+    static PARSED: OnceLock<Vec<PrinterPreset>> = OnceLock::new();
+    PARSED.get_or_init(|| {
+        toml::from_str::<PresetFile>(PRESETS)
+            .expect("built-in printer presets are valid TOML")
+            .presets
+    })
+}
+
+impl PrinterPreset {
+    pub fn name(&self) -> String {
+        format!("{} {}", self.brand, self.model)
+    }
+
+    /// A printer profile of this model without any calibration of a particular machine
+    pub fn printer(&self) -> Printer {
+        Printer {
+            name: self.name(),
+            brand: self.brand.clone(),
+            model: self.model.clone(),
+            physical_x: self.physical_x,
+            physical_y: self.physical_y,
+            physical_z: self.physical_z,
+            pixel_x: self.pixel_x,
+            pixel_y: self.pixel_y,
+            calibration_mask: None,
+            xy_compensation: XyCompensation::default(),
+            bleed_compensation: None,
+            previews: Vec::new(),
+            plate_shape: None,
+            lcd_orientation: self.lcd_orientation,
+            tolerances: ToleranceOverrides::default(),
+            output_format: self.output_format,
+            anti_aliasing: AntiAliasing::Off,
+            time_calibration: TimeCalibration::default(),
+            vat: None,
+        }
+    }
+
+    /// Switches the parameters to this printer and its motion settings, keeping the layer
+    /// height, the anti-aliasing, the rest of the resin and the layer scripts
+    pub fn apply(&self, parameters: &mut SliceParameters) {
+        parameters.printer = Printer {
+            anti_aliasing: parameters.printer.anti_aliasing,
+            ..self.printer()
+        };
+        parameters.resin.motion = self.motion.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The preset called `name`, as listed by `PrinterPreset::name`
+    fn find(name: &str) -> Option<&'static PrinterPreset> {
+        presets().iter().find(|preset| preset.name() == name)
+    }
+
+    #[test]
+    fn test_presets_are_sane() {
+        let presets = presets();
+        assert!(presets.len() >= 10);
+        for preset in presets {
+            let name = preset.name();
+            // Pixels of 15 to 60 micrometers, not always square
+            let pixel_size_x = preset.physical_x / preset.pixel_x as f64 * 1000.0;
+            let pixel_size_y = preset.physical_y / preset.pixel_y as f64 * 1000.0;
+            assert!((15.0..=60.0).contains(&pixel_size_x), "{}", name);
+            assert!((15.0..=60.0).contains(&pixel_size_y), "{}", name);
+            assert!(preset.physical_z > 100.0, "{}", name);
+            assert!(preset.motion.lift_slow_distance <= preset.motion.lift_distance);
+            assert_eq!(find(&name), Some(preset));
+        }
+        for series in ["Mars", "Saturn", "Photon", "Sonic"] {
+            assert!(presets.iter().any(|preset| preset.model.contains(series)));
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut parameters = SliceParameters {
+            slice_thickness: 0.05,
+            ..Default::default()
+        };
+        let preset = find("ELEGOO Mars 3").unwrap();
+        preset.apply(&mut parameters);
+
+        assert_eq!(parameters.printer.name, "ELEGOO Mars 3");
+        assert_eq!(
+            (parameters.printer.pixel_x, parameters.printer.pixel_y),
+            (4098, 2560)
+        );
+        assert_eq!(parameters.resin.motion.bottom_exposure_time, 35.0);
+        assert_eq!(parameters.slice_thickness, 0.05);
+        assert!(find("Nonexistent Printer").is_none());
+    }
+}
@@ -0,0 +1,200 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The stages of the import -> slice -> export pipeline that get timed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Import,
+    Dedup,
+    Analysis,
+    Intersection,
+    Assembly,
+    Rasterization,
+    Encoding,
+    Export,
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Import => "import",
+            Stage::Dedup => "dedup",
+            Stage::Analysis => "analysis",
+            Stage::Intersection => "intersection",
+            Stage::Assembly => "polygon assembly",
+            Stage::Rasterization => "rasterization",
+            Stage::Encoding => "encoding",
+            Stage::Export => "export",
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct StageTiming {
+    pub calls: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+/// Collects wall time per stage. Stages that run on many threads at once (per layer work)
+/// are summed across threads, so their total can exceed the wall time of the whole job.
+#[derive(Default)]
+pub struct Profiler {
+    enabled: AtomicBool,
+    timings: Mutex<BTreeMap<Stage, StageTiming>>,
+}
+
+static PROFILER: Profiler = Profiler::new();
+
+/// Global profiler, enabled with the `--profile` command line flag
+pub fn global() -> &'static Profiler {
+    &PROFILER
+}
+
+/// Times `stage` on the global profiler until the returned guard is dropped
+pub fn scope(stage: Stage) -> StageGuard<'static> {
+    PROFILER.scope(stage)
+}
+
+impl Profiler {
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            timings: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn scope(&self, stage: Stage) -> StageGuard<'_> {
+        let enabled = self.is_enabled();
+        StageGuard {
+            profiler: self,
+            stage,
+            start: Instant::now(),
+            enabled,
+            _span: tracing::info_span!("stage", name = stage.name()).entered(),
+        }
+    }
+
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        let mut timings = self.timings.lock().unwrap();
+        let timing = timings.entry(stage).or_default();
+        timing.calls += 1;
+        timing.total += elapsed;
+        timing.max = timing.max.max(elapsed);
+    }
+
+    pub fn timings(&self) -> BTreeMap<Stage, StageTiming> {
+        self.timings.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        self.timings.lock().unwrap().clear();
+    }
+
+    /// Formats the collected timings as a plain text table
+    pub fn summary(&self) -> String {
+        let mut out = format!(
+            "{:<18} {:>8} {:>12} {:>12} {:>12}\n",
+            "stage", "calls", "total ms", "mean ms", "max ms"
+        );
+        for (stage, timing) in self.timings() {
+            let total_ms = timing.total.as_secs_f64() * 1000.0;
+            out.push_str(&format!(
+                "{:<18} {:>8} {:>12.2} {:>12.3} {:>12.3}\n",
+                stage.name(),
+                timing.calls,
+                total_ms,
+                total_ms / timing.calls as f64,
+                timing.max.as_secs_f64() * 1000.0
+            ));
+        }
+        out
+    }
+}
+
+/// Records the elapsed time for its stage when dropped
+pub struct StageGuard<'a> {
+    profiler: &'a Profiler,
+    stage: Stage,
+    start: Instant,
+    enabled: bool,
+    _span: tracing::span::EnteredSpan,
+}
+
+impl Drop for StageGuard<'_> {
+    fn drop(&mut self) {
+        if self.enabled {
+            self.profiler.record(self.stage, self.start.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_profiler_records_nothing() {
+        let profiler = Profiler::new();
+        {
+            let _guard = profiler.scope(Stage::Import);
+        }
+        assert!(profiler.timings().is_empty());
+    }
+
+    #[test]
+    fn test_scopes_accumulate_per_stage() {
+        let profiler = Profiler::new();
+        profiler.set_enabled(true);
+        for _ in 0..3 {
+            let _guard = profiler.scope(Stage::Intersection);
+        }
+        {
+            let _guard = profiler.scope(Stage::Export);
+        }
+
+        let timings = profiler.timings();
+        assert_eq!(timings[&Stage::Intersection].calls, 3);
+        assert_eq!(timings[&Stage::Export].calls, 1);
+        assert!(!timings.contains_key(&Stage::Import));
+    }
+
+    #[test]
+    fn test_record_tracks_total_and_max() {
+        let profiler = Profiler::new();
+        profiler.record(Stage::Encoding, Duration::from_millis(5));
+        profiler.record(Stage::Encoding, Duration::from_millis(15));
+
+        let timing = profiler.timings()[&Stage::Encoding];
+        assert_eq!(timing.total, Duration::from_millis(20));
+        assert_eq!(timing.max, Duration::from_millis(15));
+    }
+
+    #[test]
+    fn test_summary_lists_stages_in_pipeline_order() {
+        let profiler = Profiler::new();
+        profiler.record(Stage::Export, Duration::from_millis(1));
+        profiler.record(Stage::Import, Duration::from_millis(1));
+
+        let summary = profiler.summary();
+        let import_line = summary.find("import").unwrap();
+        let export_line = summary.find("export").unwrap();
+        assert!(import_line < export_line);
+
+        profiler.reset();
+        assert!(profiler.timings().is_empty());
+    }
+}
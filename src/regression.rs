@@ -0,0 +1,486 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::Body;
+use crate::slice_parameters::SliceParameters;
+use crate::stl_processor::StlProcessor;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+use stl_io::Triangle;
+use thiserror::Error;
+
+/// Meshes of the regression corpus, paths in it are relative to its folder
+pub const CORPUS_MANIFEST: &str = "test_stls/corpus.toml";
+
+#[derive(Error, Debug)]
+pub enum RegressionError {
+    #[error("Could not read the corpus: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Could not parse the corpus manifest: {0}")]
+    Manifest(#[from] toml::de::Error),
+
+    #[error("Could not download {0}: {1}")]
+    Download(String, String),
+}
+
+/// Meshes the corpus builds instead of downloading, tricky in ways small real meshes
+/// rarely are
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GeneratedMesh {
+    /// A 20 mm box without its top, sliced into open contours
+    OpenBox,
+    /// Two 10 mm cubes sharing a vertical edge, which four faces meet at
+    SharedEdge,
+    /// A hollow 20 mm box with 0.2 mm walls
+    ThinWalls,
+    /// A 40 mm box with every face split into a fine grid, 270000 triangles
+    Huge,
+}
+
+impl GeneratedMesh {
+    pub fn triangles(self) -> Vec<Triangle> {
+        match self {
+            GeneratedMesh::OpenBox => grid_box([0.0; 3], [20.0; 3], 1, false)
+                .into_iter()
+                .filter(|triangle| triangle.normal != [0.0, 0.0, 1.0])
+                .collect(),
+            GeneratedMesh::SharedEdge => {
+                let mut triangles = grid_box([0.0; 3], [10.0; 3], 1, false);
+                triangles.extend(grid_box([10.0, 10.0, 0.0], [20.0, 20.0, 10.0], 1, false));
+                triangles
+            }
+            GeneratedMesh::ThinWalls => {
+                let mut triangles = grid_box([0.0; 3], [20.0; 3], 1, false);
+                triangles.extend(grid_box([0.2; 3], [19.8; 3], 1, true));
+                triangles
+            }
+            GeneratedMesh::Huge => grid_box([0.0; 3], [40.0; 3], 150, false),
+        }
+    }
+}
+
+/// A mesh that has broken the pipeline before, or looks like it could
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct CorpusMesh {
+    pub name: String,
+    /// Where the mesh is, relative to the manifest
+    pub file: PathBuf,
+    /// Where to download the mesh from when it's missing
+    pub url: Option<String>,
+    /// What to build when the mesh is missing, instead of downloading it
+    pub generate: Option<GeneratedMesh>,
+    /// SHA-256 of the file, checked when given so a run is of the same meshes
+    pub sha256: Option<String>,
+    /// What makes the mesh tricky
+    #[serde(default)]
+    pub description: String,
+    /// Expected results, checked when given
+    pub triangles: Option<usize>,
+    pub layers: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Corpus {
+    #[serde(skip)]
+    pub dir: PathBuf,
+    #[serde(rename = "mesh", default)]
+    pub meshes: Vec<CorpusMesh>,
+}
+
+/// How one mesh went through import and the slicing dry run
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegressionResult {
+    pub name: String,
+    /// The failure, None if the mesh passed
+    pub failure: Option<String>,
+    pub triangles: usize,
+    pub layers: usize,
+    pub import_time: Duration,
+    pub dry_run_time: Duration,
+}
+
+impl Corpus {
+    pub fn load(manifest: &Path) -> Result<Self, RegressionError> {
+        let mut corpus: Corpus = toml::from_str(&fs::read_to_string(manifest)?)?;
+        corpus.dir = manifest.parent().unwrap_or(Path::new("")).to_path_buf();
+        Ok(corpus)
+    }
+
+    /// Generates or downloads with curl the meshes that aren't there yet, returning what
+    /// failed
+    pub fn fetch(&self) -> Vec<RegressionError> {
+        let missing = self
+            .meshes
+            .iter()
+            .filter(|mesh| !self.dir.join(&mesh.file).exists());
+        missing
+            .filter_map(|mesh| {
+                let path = self.dir.join(&mesh.file);
+                if let Some(generated) = mesh.generate {
+                    println!("Generating {}", mesh.name);
+                    return write_stl(&path, &generated.triangles()).err();
+                }
+                let url = mesh.url.as_ref()?;
+                println!("Downloading {} from {}", mesh.name, url);
+                download(url, &path).err()
+            })
+            .collect()
+    }
+
+    /// Imports every mesh and dry runs slicing it, one after the other so the timings
+    /// are comparable between runs
+    pub fn run(&self, parameters: &SliceParameters) -> Vec<RegressionResult> {
+        self.meshes
+            .iter()
+            .map(|mesh| run_mesh(mesh, &self.dir.join(&mesh.file), parameters))
+            .collect()
+    }
+}
+
+// Downloads to a partial file first, so an interrupted download is retried next time
+fn download(url: &str, path: &Path) -> Result<(), RegressionError> {
+    let failed = |reason: String| RegressionError::Download(url.to_string(), reason);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("part");
+    let output = Command::new("curl")
+        .args([
+            "--fail",
+            "--location",
+            "--silent",
+            "--show-error",
+            "--output",
+        ])
+        .arg(&partial)
+        .arg(url)
+        .output()
+        .map_err(|e| failed(format!("could not run curl: {}", e)))?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        return Err(failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    fs::rename(partial, path)?;
+    Ok(())
+}
+
+/// Every face of the box from `min` to `max` split into `divisions` by `divisions`
+/// squares, facing out or `inward`
+fn grid_box(min: [f32; 3], max: [f32; 3], divisions: usize, inward: bool) -> Vec<Triangle> {
+    let mut triangles = Vec::with_capacity(12 * divisions * divisions);
+    let at = |axis: usize, step: usize| {
+        min[axis] + (max[axis] - min[axis]) * (step as f32 / divisions as f32)
+    };
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for (side, facing_up) in [(min[axis], false), (max[axis], true)] {
+            // From u to v turns counterclockwise around +axis
+            let reversed = facing_up == inward;
+            let mut normal = [0.0; 3];
+            normal[axis] = if facing_up != inward { 1.0 } else { -1.0 };
+            let point = |i: usize, j: usize| {
+                let mut point = [0.0; 3];
+                point[axis] = side;
+                point[u] = at(u, i);
+                point[v] = at(v, j);
+                point
+            };
+            for i in 0..divisions {
+                for j in 0..divisions {
+                    let quad = [
+                        point(i, j),
+                        point(i + 1, j),
+                        point(i + 1, j + 1),
+                        point(i, j + 1),
+                    ];
+                    for mut vertices in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+                        if reversed {
+                            vertices.swap(1, 2);
+                        }
+                        triangles.push(Triangle { normal, vertices });
+                    }
+                }
+            }
+        }
+    }
+    triangles
+}
+
+// Binary STL, the same bytes for the same triangles so generated meshes can be checksummed
+fn write_stl(path: &Path, triangles: &[Triangle]) -> Result<(), RegressionError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut bytes = vec![0u8; 80];
+    bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for triangle in triangles {
+        for value in triangle
+            .normal
+            .iter()
+            .chain(triangle.vertices.iter().flatten())
+        {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+    }
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+fn sha256(path: &Path) -> io::Result<String> {
+    let digest = Sha256::digest(fs::read(path)?);
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn run_mesh(mesh: &CorpusMesh, path: &Path, parameters: &SliceParameters) -> RegressionResult {
+    let mut result = RegressionResult {
+        name: mesh.name.clone(),
+        failure: None,
+        triangles: 0,
+        layers: 0,
+        import_time: Duration::ZERO,
+        dry_run_time: Duration::ZERO,
+    };
+    // A panic is a failure of this mesh, not the end of the run
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        let start = Instant::now();
+        let body = Body::new_from_stl(path.as_os_str(), &StlProcessor::new())
+            .map_err(|e| format!("import failed: {}", e))?;
+        result.import_time = start.elapsed();
+        result.triangles = body.mesh.indices.len() / 3;
+
+        let start = Instant::now();
+        result.layers = parameters.dry_run([&body]).layer_count;
+        result.dry_run_time = start.elapsed();
+        if result.triangles == 0 || result.layers == 0 {
+            return Err("nothing to slice".to_string());
+        }
+        Ok(())
+    }));
+    let mut failures: Vec<String> = match outcome {
+        Ok(Ok(())) => Vec::new(),
+        Ok(Err(e)) => vec![e],
+        Err(_) => vec!["panicked".to_string()],
+    };
+    if let Some(expected) = &mesh.sha256 {
+        match sha256(path) {
+            Ok(actual) if actual != *expected => {
+                failures.push(format!("SHA-256 {} instead of {}", actual, expected))
+            }
+            _ => {}
+        }
+    }
+    if let Some(triangles) = mesh.triangles.filter(|&t| t != result.triangles) {
+        failures.push(format!(
+            "{} triangles instead of {}",
+            result.triangles, triangles
+        ));
+    }
+    if let Some(layers) = mesh.layers.filter(|&l| l != result.layers) {
+        failures.push(format!("{} layers instead of {}", result.layers, layers));
+    }
+    if !failures.is_empty() {
+        result.failure = Some(failures.join(", "));
+    }
+    result
+}
+
+/// The results as CSV, one mesh per line
+pub fn to_csv(results: &[RegressionResult]) -> String {
+    let mut csv = String::from("name,result,triangles,layers,import_ms,dry_run_ms\n");
+    for result in results {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{}",
+            csv_field(&result.name),
+            csv_field(result.failure.as_deref().unwrap_or("pass")),
+            result.triangles,
+            result.layers,
+            result.import_time.as_millis(),
+            result.dry_run_time.as_millis()
+        );
+    }
+    csv
+}
+
+// Quoted when it holds a comma or a quote
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// `--regression`: fetches the corpus, runs it and writes the results next to the
+/// manifest. Returns the exit code, 1 if anything failed.
+pub fn run_from_command_line() -> i32 {
+    let corpus = match Corpus::load(Path::new(CORPUS_MANIFEST)) {
+        Ok(corpus) => corpus,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let download_failures = corpus.fetch();
+    for failure in &download_failures {
+        eprintln!("{}", failure);
+    }
+
+    let results = corpus.run(&SliceParameters::default());
+    for result in &results {
+        println!(
+            "{} {}: {} triangles, {} layers, import {} ms, dry run {} ms",
+            if result.failure.is_none() {
+                "PASS"
+            } else {
+                "FAIL"
+            },
+            result.name,
+            result.triangles,
+            result.layers,
+            result.import_time.as_millis(),
+            result.dry_run_time.as_millis()
+        );
+        if let Some(failure) = &result.failure {
+            println!("    {}", failure);
+        }
+    }
+    let csv_path = corpus.dir.join("regression_results.csv");
+    if let Err(e) = fs::write(&csv_path, to_csv(&results)) {
+        eprintln!("Could not write {}: {}", csv_path.display(), e);
+    }
+
+    let failed = results.iter().filter(|r| r.failure.is_some()).count();
+    println!(
+        "{} of {} meshes passed",
+        results.len() - failed,
+        results.len()
+    );
+    i32::from(failed > 0 || !download_failures.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_run_corpus() {
+        let dir = tempdir().unwrap();
+        let manifest = dir.path().join("corpus.toml");
+        let with_holes = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_stls/with_holes.stl");
+        fs::write(
+            &manifest,
+            format!(
+                r#"
+[[mesh]]
+name = "with holes"
+file = "{}"
+description = "Through holes"
+sha256 = "0"
+layers = 1
+
+[[mesh]]
+name = "missing"
+file = "missing.stl"
+"#,
+                with_holes.display()
+            ),
+        )
+        .unwrap();
+
+        let corpus = Corpus::load(&manifest).unwrap();
+        assert_eq!(corpus.dir, dir.path());
+        assert_eq!(corpus.meshes[0].description, "Through holes");
+        // Missing meshes without a URL are left to fail when run
+        assert!(corpus.fetch().is_empty());
+
+        let results = corpus.run(&SliceParameters::default());
+        assert!(results[0].triangles > 0);
+        assert_eq!(
+            results[0].failure,
+            Some(format!(
+                "SHA-256 {} instead of 0, {} layers instead of 1",
+                sha256(&with_holes).unwrap(),
+                results[0].layers
+            ))
+        );
+        assert!(results[1]
+            .failure
+            .as_ref()
+            .unwrap()
+            .starts_with("import failed"));
+
+        let csv = to_csv(&results);
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("missing,import failed"));
+        assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+    }
+
+    #[test]
+    fn test_shipped_manifest() {
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join(CORPUS_MANIFEST);
+        let corpus = Corpus::load(&manifest).unwrap();
+        assert!(!corpus.meshes.is_empty());
+        assert!(corpus.meshes.iter().all(|mesh| mesh.sha256.is_some()));
+
+        // Generated meshes come out as checksummed, wherever they are generated
+        let dir = tempdir().unwrap();
+        let generated = Corpus {
+            dir: dir.path().to_path_buf(),
+            meshes: corpus
+                .meshes
+                .into_iter()
+                .filter(|mesh| mesh.generate.is_some())
+                .collect(),
+        };
+        assert!(generated.fetch().is_empty());
+        for mesh in &generated.meshes {
+            let path = generated.dir.join(&mesh.file);
+            assert_eq!(sha256(&path).ok(), mesh.sha256, "{}", mesh.name);
+            let triangles = mesh.generate.unwrap().triangles();
+            assert_eq!(Some(triangles.len()), mesh.triangles, "{}", mesh.name);
+        }
+    }
+
+    #[test]
+    fn test_generated_meshes_are_closed_or_open() {
+        // Every edge of a closed mesh is on two faces, once in each direction
+        let edge_balance = |triangles: &[Triangle]| {
+            let mut balance: HashMap<[[u32; 3]; 2], i32> = HashMap::new();
+            for triangle in triangles {
+                let key = |vertex: [f32; 3]| vertex.map(f32::to_bits);
+                for i in 0..3 {
+                    let (a, b) = (
+                        key(triangle.vertices[i]),
+                        key(triangle.vertices[(i + 1) % 3]),
+                    );
+                    let (edge, direction) = if a < b { ([a, b], 1) } else { ([b, a], -1) };
+                    *balance.entry(edge).or_default() += direction;
+                }
+            }
+            balance.values().filter(|&&b| b != 0).count()
+        };
+        assert_eq!(edge_balance(&GeneratedMesh::ThinWalls.triangles()), 0);
+        assert_eq!(edge_balance(&GeneratedMesh::SharedEdge.triangles()), 0);
+        assert_eq!(edge_balance(&GeneratedMesh::OpenBox.triangles()), 4);
+        assert_eq!(GeneratedMesh::Huge.triangles().len(), 270000);
+    }
+}
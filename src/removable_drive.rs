@@ -0,0 +1,247 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DriveError {
+    #[error("IO Error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to eject {0}: {1}")]
+    Eject(String, String),
+}
+
+/// A USB stick or memory card, the way jobs usually get to the printer
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemovableDrive {
+    /// Name of the volume, e.g. SATURN
+    pub label: String,
+    pub mount_point: PathBuf,
+    /// Block device the drive is mounted from, needed to eject it on Linux
+    pub device: Option<PathBuf>,
+}
+
+/// The removable drives mounted right now. Asks the OS, which can take a moment on Windows.
+#[cfg(target_os = "linux")]
+pub fn detect() -> Vec<RemovableDrive> {
+    fs::read_to_string("/proc/mounts")
+        .map(|mounts| parse_mounts(&mounts))
+        .unwrap_or_default()
+}
+
+#[cfg(target_os = "macos")]
+pub fn detect() -> Vec<RemovableDrive> {
+    let Ok(entries) = fs::read_dir("/Volumes") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        // The startup disk is a symlink to /
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .map(|entry| RemovableDrive {
+            label: entry.file_name().to_string_lossy().into_owned(),
+            mount_point: entry.path(),
+            device: None,
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+pub fn detect() -> Vec<RemovableDrive> {
+    // Drive type 2 is removable
+    let output = Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "Get-CimInstance Win32_LogicalDisk -Filter 'DriveType=2' | ForEach-Object { $_.DeviceID + '|' + $_.VolumeName }",
+        ])
+        .output();
+    let Ok(output) = output else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (letter, name) = line.trim().split_once('|')?;
+            Some(RemovableDrive {
+                label: if name.is_empty() { letter } else { name }.to_string(),
+                mount_point: PathBuf::from(format!("{}\\", letter)),
+                device: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn detect() -> Vec<RemovableDrive> {
+    Vec::new()
+}
+
+/// Removable drives in the contents of /proc/mounts, which desktop environments mount
+/// under /media or /run/media
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_mounts(mounts: &str) -> Vec<RemovableDrive> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = unescape_mount_field(fields.next()?);
+            let mount_point = PathBuf::from(unescape_mount_field(fields.next()?));
+            if !device.starts_with("/dev/")
+                || !(mount_point.starts_with("/media") || mount_point.starts_with("/run/media"))
+            {
+                return None;
+            }
+            Some(RemovableDrive {
+                label: mount_point.file_name()?.to_string_lossy().into_owned(),
+                mount_point,
+                device: Some(PathBuf::from(device)),
+            })
+        })
+        .collect()
+}
+
+// Fields of /proc/mounts have spaces, tabs and backslashes escaped as octal, e.g. \040
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        });
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                unescaped.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Copies an export folder to the root of the drive and flushes it to the drive, returning
+/// where the copy is
+pub fn copy_export(export_dir: &Path, drive: &RemovableDrive) -> Result<PathBuf, DriveError> {
+    let name = export_dir
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The export has no name"))?;
+    let destination = drive.mount_point.join(name);
+    copy_dir(export_dir, &destination)?;
+    Ok(destination)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+            // Written through before the drive is ejected
+            File::open(&target)?.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+/// Unmounts the drive so it can be pulled out safely
+pub fn eject(drive: &RemovableDrive) -> Result<(), DriveError> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("diskutil");
+        command.arg("eject").arg(&drive.mount_point);
+        command
+    } else if cfg!(windows) {
+        let script = format!(
+            "(New-Object -ComObject Shell.Application).Namespace(17).ParseName('{}').InvokeVerb('Eject')",
+            drive.mount_point.display().to_string().trim_end_matches('\\')
+        );
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-Command", &script]);
+        command
+    } else {
+        let device = drive
+            .device
+            .as_ref()
+            .ok_or_else(|| DriveError::Eject(drive.label.clone(), "unknown device".to_string()))?;
+        let mut command = Command::new("udisksctl");
+        command.arg("unmount").arg("-b").arg(device);
+        command
+    };
+    let output = command
+        .output()
+        .map_err(|e| DriveError::Eject(drive.label.clone(), e.to_string()))?;
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(DriveError::Eject(drive.label.clone(), reason));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_mounts() {
+        let mounts = "\
+/dev/nvme0n1p2 / ext4 rw,relatime 0 0
+tmpfs /run tmpfs rw,nosuid 0 0
+/dev/sdb1 /media/egg/SATURN vfat rw,nosuid,nodev 0 0
+/dev/sdc1 /run/media/egg/MY\\040PRINTS exfat rw 0 0
+";
+        let drives = parse_mounts(mounts);
+        assert_eq!(
+            drives,
+            [
+                RemovableDrive {
+                    label: "SATURN".to_string(),
+                    mount_point: PathBuf::from("/media/egg/SATURN"),
+                    device: Some(PathBuf::from("/dev/sdb1")),
+                },
+                RemovableDrive {
+                    label: "MY PRINTS".to_string(),
+                    mount_point: PathBuf::from("/run/media/egg/MY PRINTS"),
+                    device: Some(PathBuf::from("/dev/sdc1")),
+                },
+            ]
+        );
+        assert_eq!(unescape_mount_field("a\\134b\\0"), "a\\b\\0");
+    }
+
+    #[test]
+    fn test_copy_export() {
+        let dir = tempdir().unwrap();
+        let export = dir.path().join("20241018-1200");
+        fs::create_dir_all(export.join("previews")).unwrap();
+        fs::write(export.join("slice_0000.webp"), [1, 2, 3]).unwrap();
+        fs::write(export.join("previews").join("small.png"), [4]).unwrap();
+        let drive = RemovableDrive {
+            label: "SATURN".to_string(),
+            mount_point: dir.path().join("usb"),
+            device: None,
+        };
+
+        let copy = copy_export(&export, &drive).unwrap();
+        assert_eq!(copy, drive.mount_point.join("20241018-1200"));
+        assert_eq!(fs::read(copy.join("slice_0000.webp")).unwrap(), [1, 2, 3]);
+        assert_eq!(
+            fs::read(copy.join("previews").join("small.png")).unwrap(),
+            [4]
+        );
+    }
+}
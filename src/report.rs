@@ -0,0 +1,471 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::{Body, SliceRole};
+use crate::cpu_slicer::CPUSlicer;
+use crate::geometry;
+use crate::memory_budget;
+use crate::mesh_island_analyzer::MeshIslandAnalyzer;
+use crate::motion_profile;
+use crate::number_format;
+use crate::open_bottom::OpenBottom;
+use crate::plugin::PluginFinding;
+use crate::printer::Printer;
+use crate::settings::IslandDetectionSettings;
+use crate::slice_parameters::SliceParameters;
+use image::{ImageError, ImageFormat, Rgb, RgbImage};
+use imageproc::drawing::draw_polygon_mut;
+use imageproc::point::Point;
+use nalgebra::Vector3;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+use thiserror::Error;
+
+const PLATE_COLOR: Rgb<u8> = Rgb([30, 30, 30]);
+const MODEL_COLOR: [u8; 3] = [255, 140, 40];
+
+#[derive(Error, Debug)]
+pub enum ReportError {
+    #[error("Could not write the report: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Could not encode the plate image: {0}")]
+    Image(#[from] ImageError),
+
+    #[error("Could not serialize the settings: {0}")]
+    Settings(#[from] toml::ser::Error),
+}
+
+/// Size and mesh statistics of one body, in world coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct BodyStats {
+    pub name: String,
+    pub triangles: usize,
+    pub size: Vector3<f32>, // millimeters
+    pub volume: f64,        // milliliters
+    pub island_vertices: usize,
+    pub slice_role: SliceRole,
+}
+
+impl BodyStats {
+    pub fn of(body: &Body, island_settings: &IslandDetectionSettings) -> Self {
+        let triangles = CPUSlicer::world_triangles([body], Vector3::new(1.0, 1.0, 1.0));
+        let volume = geometry::signed_volume(&triangles);
+        let (island_vertices, _) = MeshIslandAnalyzer::analyze_islands(body, island_settings);
+        Self {
+            name: body.name.clone(),
+            triangles: triangles.len(),
+            size: geometry::bounding_box(&triangles).map_or(Vector3::zeros(), |b| b.max - b.min),
+            volume: volume.abs() / 1000.0,
+            island_vertices: island_vertices.len(),
+            slice_role: body.slice_role,
+        }
+    }
+}
+
+/// Pre-flight report of a plate: what will be printed, with what, how long it takes and
+/// what might go wrong
+pub struct Report {
+    pub title: String,
+    pub plate: RgbImage,
+    pub bodies: Vec<BodyStats>,
+    pub warnings: Vec<String>,
+    pub estimates: Vec<(String, String)>,
+    pub settings: String,
+}
+
+impl Report {
+    pub fn new(
+        title: &str,
+        bodies: &[Body],
+        parameters: &SliceParameters,
+        island_settings: &IslandDetectionSettings,
+        findings: &[PluginFinding],
+    ) -> Result<Self, ReportError> {
+        let stats: Vec<BodyStats> = bodies
+            .iter()
+            .map(|body| BodyStats::of(body, island_settings))
+            .collect();
+
+        let mut warnings = Vec::new();
+        for body in &stats {
+            if body.slice_role == SliceRole::Merge && body.island_vertices > 0 {
+                warnings.push(format!(
+                    "{} has {} unsupported vertices (islands)",
+                    body.name, body.island_vertices
+                ));
+            }
+        }
+        for body in bodies {
+            if body.slice_role == SliceRole::Merge {
+                if let Some(open) = OpenBottom::of(body) {
+                    warnings.push(open.describe(&body.name));
+                }
+            }
+        }
+        for body in bodies {
+            if body.has_uneven_scale() {
+                warnings.push(uneven_scale_warning(body));
+            }
+        }
+        if let Some(plate_shape) = &parameters.printer.plate_shape {
+            for name in plate_shape.blocked_bodies(bodies) {
+                warnings.push(format!(
+                    "{} reaches outside the usable area of the plate",
+                    name
+                ));
+            }
+        }
+        warnings.extend(
+            findings
+                .iter()
+                .map(|finding| format!("{}: {}", finding.plugin, finding.message)),
+        );
+
+        let dry_run = parameters.dry_run(bodies);
+        let timeline = parameters.timeline(dry_run.layer_count);
+        let resin: f64 = stats
+            .iter()
+            .filter(|body| body.slice_role == SliceRole::Merge)
+            .map(|body| body.volume)
+            .sum();
+        let estimates = vec![
+            ("Layers".to_string(), dry_run.layer_count.to_string()),
+            ("Print time".to_string(), motion_profile::summary(&timeline)),
+            ("Resin".to_string(), resin_estimate(resin, parameters)),
+            (
+                "Slicing memory".to_string(),
+                memory_budget::format_bytes(dry_run.memory_bytes),
+            ),
+        ];
+
+        Ok(Self {
+            title: title.to_string(),
+            plate: plate_overview(bodies, &parameters.printer, 480),
+            bodies: stats,
+            warnings,
+            estimates,
+            settings: toml::to_string_pretty(parameters)?,
+        })
+    }
+
+    /// A single HTML file with the plate image embedded, so it can be mailed as is
+    pub fn to_html(&self) -> Result<String, ReportError> {
+        let mut png = Vec::new();
+        self.plate
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+        let mut html = String::new();
+        html += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n";
+        html += &format!("<title>{}</title>\n", escape(&self.title));
+        html += "<style>\nbody { font-family: sans-serif; margin: 2em; }\n\
+                 table { border-collapse: collapse; }\n\
+                 td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n\
+                 .warning { color: #b3261e; }\n</style>\n</head>\n<body>\n";
+        html += &format!("<h1>{}</h1>\n", escape(&self.title));
+        html += &format!(
+            "<img alt=\"Plate\" src=\"data:image/png;base64,{}\">\n",
+            base64(&png)
+        );
+
+        html += "<h2>Bodies</h2>\n<table>\n<tr><th>Name</th><th>Role</th><th>Size (mm)</th>\
+                 <th>Volume</th><th>Triangles</th><th>Island vertices</th></tr>\n";
+        for body in &self.bodies {
+            html += &format!(
+                "<tr><td>{}</td><td>{:?}</td><td>{} x {} x {}</td><td>{} ml</td>\
+                 <td>{}</td><td>{}</td></tr>\n",
+                escape(&body.name),
+                body.slice_role,
+                number_format::decimal(body.size.x as f64, 1),
+                number_format::decimal(body.size.y as f64, 1),
+                number_format::decimal(body.size.z as f64, 1),
+                number_format::decimal(body.volume, 2),
+                body.triangles,
+                body.island_vertices
+            );
+        }
+        html += "</table>\n";
+
+        html += "<h2>Warnings</h2>\n";
+        if self.warnings.is_empty() {
+            html += "<p>None</p>\n";
+        } else {
+            html += "<ul>\n";
+            for warning in &self.warnings {
+                html += &format!("<li class=\"warning\">{}</li>\n", escape(warning));
+            }
+            html += "</ul>\n";
+        }
+
+        html += "<h2>Estimates</h2>\n<table>\n";
+        for (name, value) in &self.estimates {
+            html += &format!("<tr><th>{}</th><td>{}</td></tr>\n", name, escape(value));
+        }
+        html += "</table>\n";
+
+        html += &format!(
+            "<h2>Settings</h2>\n<pre>{}</pre>\n</body>\n</html>\n",
+            escape(&self.settings)
+        );
+        Ok(html)
+    }
+
+    /// The report as a few lines of text, what a slicing job is asked to be confirmed with
+    pub fn summary(&self, parameters: &SliceParameters) -> String {
+        let names = |role: SliceRole| {
+            self.bodies
+                .iter()
+                .filter(|body| body.slice_role == role)
+                .map(|body| body.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut summary = format!("Bodies: {}\n", names(SliceRole::Merge));
+        let subtracted = names(SliceRole::Subtract);
+        if !subtracted.is_empty() {
+            summary += &format!("Subtracted: {}\n", subtracted);
+        }
+        summary += &format!(
+            "Printer: {}\nResin: {}, {} mm layers\n",
+            parameters.printer.name,
+            parameters.resin.label(),
+            number_format::shortest(parameters.slice_thickness)
+        );
+        for (name, value) in &self.estimates {
+            summary += &format!("{}: {}\n", name, value);
+        }
+        match self.warnings.len() {
+            0 => summary += "No warnings",
+            1 => summary += "1 warning:",
+            count => summary += &format!("{} warnings:", count),
+        }
+        for warning in &self.warnings {
+            summary += &format!("\n- {}", warning);
+        }
+        summary
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), ReportError> {
+        fs::write(path, self.to_html()?)?;
+        Ok(())
+    }
+}
+
+/// e.g. "12.5 ml, costs 0.50", or just the milliliters when the resin has no price
+fn resin_estimate(milliliters: f64, parameters: &SliceParameters) -> String {
+    let volume = format!("{} ml", number_format::decimal(milliliters, 1));
+    match parameters.resin.cost(milliliters) {
+        Some(cost) => format!("{}, costs {}", volume, number_format::decimal(cost, 2)),
+        None => volume,
+    }
+}
+
+/// Top-down view of the plate, every triangle shaded by its height so the parts read
+/// without slicing them
+/// Names the factors in percent, e.g. "X 100.0%, Y 100.0%, Z 102.5%"
+fn uneven_scale_warning(body: &Body) -> String {
+    let percent = body
+        .scale
+        .map(|axis| number_format::decimal(axis as f64 * 100.0, 1));
+    format!(
+        "{} is scaled unevenly (X {}%, Y {}%, Z {}%), so its threads and press fits won't mate",
+        body.name, percent.x, percent.y, percent.z
+    )
+}
+
+pub fn plate_overview(bodies: &[Body], printer: &Printer, width: u32) -> RgbImage {
+    let height = ((width as f64 * printer.physical_y / printer.physical_x).round() as u32).max(1);
+    let mut image = RgbImage::from_pixel(width, height, PLATE_COLOR);
+    let printed = bodies
+        .iter()
+        .filter(|body| body.slice_role == SliceRole::Merge);
+    let mut triangles = CPUSlicer::world_triangles(printed, Vector3::new(1.0, 1.0, 1.0));
+    let top = geometry::z_range(&triangles).map_or(0.0, |(_, top)| top as f32);
+    // Painter's algorithm, the highest triangles are drawn last
+    let highest = |t: &stl_io::Triangle| t.vertices.iter().map(|v| v[2]).fold(f32::MIN, f32::max);
+    triangles.sort_by(|a, b| highest(a).total_cmp(&highest(b)));
+
+    for triangle in &triangles {
+        let mut points: Vec<Point<i32>> = Vec::with_capacity(3);
+        for vertex in triangle.vertices {
+            let (x, y) = CPUSlicer::model_to_image_coords(
+                vertex[0] as f64,
+                vertex[1] as f64,
+                width,
+                printer.physical_x,
+                height,
+                printer.physical_y,
+            );
+            let point = Point::new(x, y);
+            if !points.contains(&point) {
+                points.push(point);
+            }
+        }
+        if points.len() < 3 {
+            continue;
+        }
+        let shade = 0.35 + 0.65 * (highest(triangle) / top.max(f32::EPSILON)).clamp(0.0, 1.0);
+        let color = Rgb(MODEL_COLOR.map(|channel| (channel as f32 * shade).round() as u8));
+        draw_polygon_mut(&mut image, &points, color);
+    }
+    image
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+    use crate::stl_processor::StlProcessor;
+    use tempfile::tempdir;
+
+    fn test_body(name: &str) -> Body {
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &StlProcessor::new())
+            .unwrap();
+        let mut body = Body::new(mesh);
+        body.name = name.to_string();
+        body
+    }
+
+    #[test]
+    fn test_base64_and_escape() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+
+    #[test]
+    fn test_body_stats() {
+        let body = test_body("part");
+
+        let stats = BodyStats::of(&body, &IslandDetectionSettings::default());
+
+        assert_eq!(stats.name, "part");
+        assert_eq!(stats.triangles, body.mesh.indices.len() / 3);
+        assert!(stats.size.x > 0.0 && stats.size.y > 0.0 && stats.size.z > 0.0);
+        // Hollow parts hold less than their bounding box
+        let box_volume = (stats.size.x * stats.size.y * stats.size.z) as f64 / 1000.0;
+        assert!(stats.volume > 0.0 && stats.volume < box_volume);
+    }
+
+    #[test]
+    fn test_write_report() {
+        let dir = tempdir().unwrap();
+        let bodies = vec![test_body("<bracket>")];
+        let findings = vec![PluginFinding {
+            plugin: "qa".to_string(),
+            message: "Layer 3 is empty".to_string(),
+        }];
+        let report = Report::new(
+            "Job 42",
+            &bodies,
+            &SliceParameters::default(),
+            &IslandDetectionSettings::default(),
+            &findings,
+        )
+        .unwrap();
+        assert!(report.plate.pixels().any(|pixel| *pixel != PLATE_COLOR));
+
+        let path = dir.path().join("report.html");
+        report.write(&path).unwrap();
+
+        let html = fs::read_to_string(path).unwrap();
+        assert!(html.contains("<title>Job 42</title>"));
+        assert!(html.contains("data:image/png;base64,iVBORw0KGgo"));
+        assert!(html.contains("&lt;bracket&gt;"));
+        assert!(html.contains("qa: Layer 3 is empty"));
+        assert!(html.contains("slice_thickness = 0.1"));
+        assert!(html.contains("<th>Layers</th>"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut drain = test_body("drain");
+        drain.slice_role = SliceRole::Subtract;
+        let bodies = vec![test_body("ring"), test_body("base"), drain];
+        let parameters = SliceParameters::default();
+        let findings = vec![PluginFinding {
+            plugin: "qa".to_string(),
+            message: "Layer 3 is empty".to_string(),
+        }];
+        let report = Report::new(
+            "Job",
+            &bodies,
+            &parameters,
+            &IslandDetectionSettings::default(),
+            &findings,
+        )
+        .unwrap();
+
+        let summary = report.summary(&parameters);
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines[0], "Bodies: ring, base");
+        assert_eq!(lines[1], "Subtracted: drain");
+        assert_eq!(lines[2], format!("Printer: {}", parameters.printer.name));
+        assert!(lines.iter().any(|line| line.starts_with("Layers: ")));
+        assert!(lines.iter().any(|line| line.starts_with("Print time: ")));
+        assert!(lines.iter().any(|line| line.starts_with("Resin: ")));
+        assert_eq!(
+            lines[lines.len() - 2..],
+            ["1 warning:", "- qa: Layer 3 is empty"]
+        );
+    }
+
+    #[test]
+    fn test_resin_estimate() {
+        let mut parameters = SliceParameters::default();
+        parameters.resin.price_per_liter = None;
+        assert_eq!(resin_estimate(12.5, &parameters), "12.5 ml");
+        parameters.resin.price_per_liter = Some(40.0);
+        assert_eq!(resin_estimate(12.5, &parameters), "12.5 ml, costs 0.50");
+    }
+
+    #[test]
+    fn test_uneven_scale_warning() {
+        let mut lid = test_body("lid");
+        lid.set_scale(Vector3::new(1.0, 1.0, 1.025));
+        let bodies = vec![lid, test_body("base")];
+        let report = Report::new(
+            "Job",
+            &bodies,
+            &SliceParameters::default(),
+            &IslandDetectionSettings::default(),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            report.warnings,
+            ["lid is scaled unevenly (X 100.0%, Y 100.0%, Z 102.5%), so its threads and press fits won't mate"]
+        );
+    }
+}
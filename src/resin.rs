@@ -0,0 +1,201 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use std::{fs, path::Path};
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::bleed_compensation::BleedCompensation;
+use crate::motion_profile::MotionProfile;
+
+/// Resin profiles shipped with the application, one folder per brand
+pub const RESIN_DIR: &str = "config/resins";
+
+/// Material profile of a resin
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Resin {
+    pub name: String,
+    pub brand: String,
+    #[serde(default)]
+    pub shrinkage_x: f32, // percent
+    #[serde(default)]
+    pub shrinkage_y: f32, // percent
+    #[serde(default)]
+    pub shrinkage_z: f32, // percent
+    #[serde(default)]
+    pub motion: MotionProfile,
+    #[serde(default)]
+    pub strength: ResinStrength,
+    /// What a liter of the resin costs, in the user's currency. Jobs have no cost without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_per_liter: Option<f64>,
+    /// Replaces the bleed compensation of the printer, for resins that bleed more or less
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bleed_compensation: Option<BleedCompensation>,
+}
+
+/// How strong freshly cured resin is against the pull of peeling each layer off the film
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ResinStrength {
+    pub tensile_strength: f32, // MPa, before post-curing
+    pub peel_pressure: f32,    // kPa on the area cured in a layer
+    /// How many times the peel force the supports have to hold
+    pub safety_factor: f32,
+}
+
+impl Default for ResinStrength {
+    fn default() -> Self {
+        Self {
+            tensile_strength: 8.0,
+            peel_pressure: 15.0,
+            safety_factor: 4.0,
+        }
+    }
+}
+
+impl ResinStrength {
+    /// Fewest support contacts with tips of `tip_diameter` millimeters that hold one square
+    /// millimeter of overhang while it is peeled
+    pub fn contacts_per_mm2(&self, tip_diameter: f32) -> f64 {
+        let tip_area = std::f64::consts::PI * (tip_diameter as f64 / 2.0).powi(2);
+        // MPa are N/mm², kPa a thousandth of that
+        let held_by_contact = self.tensile_strength as f64 * tip_area;
+        let load = self.peel_pressure as f64 / 1000.0 * self.safety_factor as f64;
+        load / held_by_contact
+    }
+}
+
+impl Default for Resin {
+    fn default() -> Self {
+        Resin::load_from_file(Path::new("config/resins/generic/standard.toml")).unwrap()
+    }
+}
+
+impl Resin {
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path)?;
+        let resin: Resin = toml::from_str(&content)?;
+        Ok(resin)
+    }
+
+    /// Scale applied to the whole build volume so the part ends up at its modeled size
+    /// after curing, e.g. 1.0204 for a resin that shrinks by 2%
+    pub fn shrinkage_compensation(&self) -> Vector3<f32> {
+        Vector3::new(self.shrinkage_x, self.shrinkage_y, self.shrinkage_z)
+            .map(|shrinkage| 100.0 / (100.0 - shrinkage))
+    }
+
+    pub fn compensates_shrinkage(&self) -> bool {
+        self.shrinkage_x != 0.0 || self.shrinkage_y != 0.0 || self.shrinkage_z != 0.0
+    }
+
+    /// Every resin profile in the brand folders of `dir`, sorted by brand and name. Files
+    /// that don't parse are skipped.
+    pub fn catalog(dir: &Path) -> Vec<Resin> {
+        let files = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|brand| fs::read_dir(brand.path()).ok())
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"));
+        let mut resins: Vec<_> = files
+            .filter_map(|path| match Resin::load_from_file(&path) {
+                Ok(resin) => Some(resin),
+                Err(e) => {
+                    eprintln!("Skipping resin profile {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        resins.sort_by(|a, b| (&a.brand, &a.name).cmp(&(&b.brand, &b.name)));
+        resins
+    }
+
+    /// What `milliliters` of the resin cost, None without a price
+    pub fn cost(&self, milliliters: f64) -> Option<f64> {
+        self.price_per_liter
+            .map(|price| price * milliliters / 1000.0)
+    }
+
+    /// The brand and name, as listed to pick from
+    pub fn label(&self) -> String {
+        format!("{} {}", self.brand, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shrinkage_compensation() {
+        let mut resin = Resin::default();
+        assert!(!resin.compensates_shrinkage());
+        assert_eq!(resin.shrinkage_compensation(), Vector3::new(1.0, 1.0, 1.0));
+
+        resin.shrinkage_x = 2.0;
+        resin.shrinkage_z = 0.5;
+        assert!(resin.compensates_shrinkage());
+        let compensation = resin.shrinkage_compensation();
+        // 98% of the compensated size is the modeled size
+        assert!((compensation.x * 0.98 - 1.0).abs() < 1e-6);
+        assert_eq!(compensation.y, 1.0);
+        assert!((compensation.z * 0.995 - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_cost() {
+        let mut resin = Resin::default();
+        assert_eq!(resin.cost(250.0), None);
+        resin.price_per_liter = Some(40.0);
+        assert_eq!(resin.cost(250.0), Some(10.0));
+        let content = toml::to_string(&resin).unwrap();
+        assert!(content.contains("price_per_liter = 40.0"));
+    }
+
+    #[test]
+    fn test_contacts_per_mm2() {
+        let strength = ResinStrength::default();
+        // One 0.4 mm tip for about every 17 mm² of overhang
+        let density = strength.contacts_per_mm2(0.4);
+        assert!((1.0 / density - 16.755).abs() < 0.01);
+        // Tips twice as wide hold four times as much
+        assert!((strength.contacts_per_mm2(0.8) * 4.0 - density).abs() < 1e-9);
+
+        let stronger = ResinStrength {
+            tensile_strength: 16.0,
+            ..strength
+        };
+        assert!((stronger.contacts_per_mm2(0.4) * 2.0 - density).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write("siraya/blu.toml", "name = \"Blu\"\nbrand = \"Siraya\"\n");
+        write("acme/tough.toml", "name = \"Tough\"\nbrand = \"Acme\"\n");
+        write("acme/clear.toml", "name = \"Clear\"\nbrand = \"Acme\"\n");
+        write("acme/notes.txt", "not a resin");
+        write("acme/broken.toml", "name = ");
+
+        let labels: Vec<String> = Resin::catalog(dir.path())
+            .iter()
+            .map(Resin::label)
+            .collect();
+        assert_eq!(labels, vec!["Acme Clear", "Acme Tough", "Siraya Blu"]);
+
+        // The shipped profiles include the generic one
+        assert!(Resin::catalog(Path::new(RESIN_DIR)).contains(&Resin::default()));
+        assert!(Resin::catalog(&dir.path().join("missing")).is_empty());
+    }
+}
@@ -0,0 +1,554 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::action::{Action, BatchTransform, CompoundAction, SetPositionAction};
+use crate::alignment::{self, Alignment, AlignmentAction};
+use crate::body::Body;
+use crate::settings::SettingsError;
+use crate::SharedBodies;
+use dirs_next::config_dir;
+use nalgebra::Vector3;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use uuid::Uuid;
+
+/// Slicing a script asks for, started once the script has finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceRequest {
+    All,
+    Selected,
+    PerProfile,
+}
+
+/// What running a script did
+pub struct ScriptRun {
+    /// Printed lines, followed by the error if the script failed
+    pub output: Vec<String>,
+    /// The transforms the script applied, already executed, as one undoable step
+    pub actions: Option<CompoundAction>,
+    pub requests: Vec<SliceRequest>,
+    pub failed: bool,
+}
+
+/// A body of the scene as seen from scripts
+#[derive(Clone)]
+struct ScriptBody(Rc<RefCell<Body>>);
+
+type Actions = Rc<RefCell<Vec<Box<dyn Action>>>>;
+type TransformFn = fn(Vector3<f32>) -> BatchTransform;
+type Targets = Rc<RefCell<Option<Vec<Rc<RefCell<Body>>>>>>;
+
+fn number(value: Dynamic) -> Result<f32, Box<EvalAltResult>> {
+    if let Ok(float) = value.as_float() {
+        Ok(float as f32)
+    } else if let Ok(int) = value.as_int() {
+        Ok(int as f32)
+    } else {
+        Err(format!("Expected a number, got {}", value.type_name()).into())
+    }
+}
+
+fn vector(x: Dynamic, y: Dynamic, z: Dynamic) -> Result<Vector3<f32>, Box<EvalAltResult>> {
+    Ok(Vector3::new(number(x)?, number(y)?, number(z)?))
+}
+
+fn to_array(vector: Vector3<f32>) -> Array {
+    vector
+        .iter()
+        .map(|&v| Dynamic::from_float(v as f64))
+        .collect()
+}
+
+/// Rhai console with bindings to the scene. Variables survive between runs, like in a
+/// REPL, and every run is undone as one step.
+///
+/// ```rhai
+/// for body in selected() {
+///     body.translate(10, 0, 0);
+///     duplicate(body).rotate(0, 0, 90);
+/// }
+/// slice();
+/// ```
+pub struct ScriptConsole {
+    engine: Engine,
+    scope: Scope<'static>,
+    output: Rc<RefCell<Vec<String>>>,
+    actions: Actions,
+    requests: Rc<RefCell<Vec<SliceRequest>>>,
+    targets: Targets,
+}
+
+impl ScriptConsole {
+    pub fn new(bodies: &SharedBodies) -> Self {
+        let mut engine = Engine::new();
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let actions: Actions = Rc::new(RefCell::new(Vec::new()));
+        let requests = Rc::new(RefCell::new(Vec::new()));
+        let targets: Targets = Rc::new(RefCell::new(None));
+
+        let printed = Rc::clone(&output);
+        engine.on_print(move |text| printed.borrow_mut().push(text.to_string()));
+        let printed = Rc::clone(&output);
+        engine.on_debug(move |text, _, position| {
+            printed
+                .borrow_mut()
+                .push(format!("{:?}: {}", position, text))
+        });
+
+        Self::register_scene(&mut engine, bodies, &targets);
+        Self::register_body(&mut engine, &actions);
+        Self::register_alignment(&mut engine, &actions);
+        for (name, request) in [
+            ("slice", SliceRequest::All),
+            ("slice_selected", SliceRequest::Selected),
+            ("slice_per_profile", SliceRequest::PerProfile),
+        ] {
+            let requests = Rc::clone(&requests);
+            engine.register_fn(name, move || requests.borrow_mut().push(request));
+        }
+
+        Self {
+            engine,
+            scope: Scope::new(),
+            output,
+            actions,
+            requests,
+            targets,
+        }
+    }
+
+    // Functions listing and finding the bodies in the scene
+    fn register_scene(engine: &mut Engine, bodies: &SharedBodies, targets: &Targets) {
+        let scene = Rc::clone(bodies);
+        let listed = move |only_selected: bool| -> Array {
+            scene
+                .borrow()
+                .iter()
+                .filter(|b| {
+                    let b = b.borrow();
+                    b.display_in_ui_list && (b.selected || !only_selected)
+                })
+                .map(|b| Dynamic::from(ScriptBody(Rc::clone(b))))
+                .collect()
+        };
+        let all = listed.clone();
+        engine.register_fn("bodies", move || all(false));
+        let selected = listed.clone();
+        engine.register_fn("selected", move || selected(true));
+
+        // The bodies a recorded macro applies to
+        let targets = Rc::clone(targets);
+        engine.register_fn("targets", move || match &*targets.borrow() {
+            Some(bodies) => bodies
+                .iter()
+                .map(|b| Dynamic::from(ScriptBody(Rc::clone(b))))
+                .collect(),
+            None => listed(true),
+        });
+
+        let scene = Rc::clone(bodies);
+        engine.register_fn("find", move |name: &str| -> Dynamic {
+            scene
+                .borrow()
+                .iter()
+                .find(|b| b.borrow().display_in_ui_list && b.borrow().name == name)
+                .map_or(Dynamic::UNIT, |b| Dynamic::from(ScriptBody(Rc::clone(b))))
+        });
+
+        // Copies are added to the scene right away and are not part of the undo step
+        let scene = Rc::clone(bodies);
+        engine.register_fn("duplicate", move |body: &mut ScriptBody| -> ScriptBody {
+            let mut copy = body.0.borrow().clone();
+            copy.uuid = Uuid::new_v4();
+            copy.name = format!("{} copy", copy.name);
+            copy.selected = false;
+            let copy = Rc::new(RefCell::new(copy));
+            scene.borrow_mut().push(Rc::clone(&copy));
+            ScriptBody(copy)
+        });
+    }
+
+    // Properties and transforms of a single body
+    fn register_body(engine: &mut Engine, actions: &Actions) {
+        engine
+            .register_type_with_name::<ScriptBody>("Body")
+            .register_get("name", |b: &mut ScriptBody| b.0.borrow().name.clone())
+            .register_set("name", |b: &mut ScriptBody, name: &str| {
+                b.0.borrow_mut().name = name.to_string()
+            })
+            .register_get("selected", |b: &mut ScriptBody| b.0.borrow().selected)
+            .register_set("selected", |b: &mut ScriptBody, selected: bool| {
+                b.0.borrow_mut().selected = selected
+            })
+            .register_get("position", |b: &mut ScriptBody| {
+                to_array(b.0.borrow().position)
+            })
+            .register_get("scale", |b: &mut ScriptBody| to_array(b.0.borrow().scale))
+            .register_fn("to_string", |b: &mut ScriptBody| {
+                format!("Body({})", b.0.borrow().name)
+            });
+
+        // Relative transforms go through BatchTransform like the transform dialog
+        let transforms: [(&str, TransformFn); 3] = [
+            ("translate", |v| BatchTransform {
+                translation: v,
+                rotation: Vector3::zeros(),
+                scale: Vector3::repeat(1.0),
+            }),
+            ("rotate", |v| BatchTransform {
+                translation: Vector3::zeros(),
+                rotation: v,
+                scale: Vector3::repeat(1.0),
+            }),
+            ("scale", |v| BatchTransform {
+                translation: Vector3::zeros(),
+                rotation: Vector3::zeros(),
+                scale: v,
+            }),
+        ];
+        for (name, transform) in transforms {
+            let actions = Rc::clone(actions);
+            engine.register_fn(
+                name,
+                move |b: &mut ScriptBody, x: Dynamic, y: Dynamic, z: Dynamic| {
+                    let mut action = transform(vector(x, y, z)?).to_action(&[Rc::clone(&b.0)]);
+                    action.execute();
+                    actions.borrow_mut().push(Box::new(action));
+                    Ok::<_, Box<EvalAltResult>>(())
+                },
+            );
+        }
+
+        let actions = Rc::clone(actions);
+        engine.register_fn(
+            "move_to",
+            move |b: &mut ScriptBody, x: Dynamic, y: Dynamic, z: Dynamic| {
+                let mut action = SetPositionAction {
+                    body: Rc::clone(&b.0),
+                    input: vector(x, y, z)?,
+                    previous: b.0.borrow().position,
+                };
+                action.execute();
+                actions.borrow_mut().push(Box::new(action));
+                Ok::<_, Box<EvalAltResult>>(())
+            },
+        );
+    }
+
+    // Alignment commands on an array of bodies, e.g. align(selected(), "x", "min")
+    fn register_alignment(engine: &mut Engine, actions: &Actions) {
+        fn bodies_of(array: Array) -> Result<Vec<Rc<RefCell<Body>>>, Box<EvalAltResult>> {
+            array
+                .into_iter()
+                .map(|item| {
+                    let type_name = item.type_name();
+                    item.try_cast::<ScriptBody>()
+                        .map(|b| b.0)
+                        .ok_or_else(|| format!("Expected a body, got {}", type_name).into())
+                })
+                .collect()
+        }
+        fn axis(name: &str) -> Result<usize, Box<EvalAltResult>> {
+            alignment::axis_from_name(name)
+                .ok_or_else(|| format!("Unknown axis {}, expected x, y or z", name).into())
+        }
+        let push = {
+            let actions = Rc::clone(actions);
+            move |mut action: AlignmentAction| {
+                action.execute();
+                actions.borrow_mut().push(Box::new(action));
+            }
+        };
+
+        let apply = push.clone();
+        engine.register_fn("align", move |bodies: Array, axis_name: &str, side: &str| {
+            let side = Alignment::from_name(side).ok_or_else(|| {
+                format!("Unknown side {}, expected min, center or max", side)
+            })?;
+            apply(alignment::align(&bodies_of(bodies)?, axis(axis_name)?, side));
+            Ok::<_, Box<EvalAltResult>>(())
+        });
+        let apply = push.clone();
+        engine.register_fn("distribute", move |bodies: Array, axis_name: &str| {
+            apply(alignment::distribute(&bodies_of(bodies)?, axis(axis_name)?));
+            Ok::<_, Box<EvalAltResult>>(())
+        });
+        engine.register_fn(
+            "snap_adjacent",
+            move |bodies: Array, axis_name: &str, gap: Dynamic| {
+                push(alignment::snap_adjacent(
+                    &bodies_of(bodies)?,
+                    axis(axis_name)?,
+                    number(gap)?,
+                ));
+                Ok::<_, Box<EvalAltResult>>(())
+            },
+        );
+    }
+
+    /// Runs a script with `targets()` returning the given bodies instead of the selection,
+    /// e.g. a macro on freshly imported models
+    pub fn run_on(&mut self, script: &str, targets: &[Rc<RefCell<Body>>]) -> ScriptRun {
+        *self.targets.borrow_mut() = Some(targets.to_vec());
+        let run = self.run(script);
+        *self.targets.borrow_mut() = None;
+        run
+    }
+
+    pub fn run(&mut self, script: &str) -> ScriptRun {
+        let result = self.engine.run_with_scope(&mut self.scope, script);
+
+        let mut output = std::mem::take(&mut *self.output.borrow_mut());
+        if let Err(e) = &result {
+            output.push(format!("Error: {}", e));
+        }
+        let actions = std::mem::take(&mut *self.actions.borrow_mut());
+        ScriptRun {
+            output,
+            // Transforms made before an error are kept so they can be undone
+            actions: (!actions.is_empty()).then_some(CompoundAction { actions }),
+            requests: std::mem::take(&mut *self.requests.borrow_mut()),
+            failed: result.is_err(),
+        }
+    }
+}
+
+/// Source of a macro recorded with `ActionManager::start_recording`
+pub fn recorded_macro(statements: &[String]) -> String {
+    let mut source = String::from(
+        "// Recorded macro. targets() are the selected bodies, or the imported ones\n\
+         // when this is the import macro.\n",
+    );
+    for statement in statements {
+        source.push_str(statement);
+        source.push('\n');
+    }
+    source
+}
+
+/// Where saved macros live, one `.rhai` file per macro
+pub fn macros_dir() -> Result<PathBuf, SettingsError> {
+    let config_dir = config_dir().ok_or(SettingsError::ConfigDirNotFound)?;
+    Ok(config_dir.join("SealSlicer").join("macros"))
+}
+
+fn macro_path(dir: &Path, name: &str) -> PathBuf {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.join(format!("{}.rhai", name))
+}
+
+/// Names of the saved macros, sorted
+pub fn list_macros(dir: &Path) -> io::Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "rhai")
+        })
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+pub fn save_macro(dir: &Path, name: &str, source: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = macro_path(dir, name);
+    fs::write(&path, source)?;
+    Ok(path)
+}
+
+pub fn load_macro(dir: &Path, name: &str) -> io::Result<String> {
+    fs::read_to_string(macro_path(dir, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action_manager::ActionManager;
+    use tempfile::tempdir;
+
+    fn scene() -> SharedBodies {
+        let part = Body {
+            name: "part".to_string(),
+            selected: false,
+            ..Default::default()
+        };
+        let plate = Body {
+            display_in_ui_list: false,
+            ..Default::default()
+        };
+        Rc::new(RefCell::new(vec![
+            Rc::new(RefCell::new(part)),
+            Rc::new(RefCell::new(plate)),
+        ]))
+    }
+
+    #[test]
+    fn test_transform_and_undo() {
+        let bodies = scene();
+        let mut console = ScriptConsole::new(&bodies);
+        let part = Rc::clone(&bodies.borrow()[0]);
+
+        let run = console.run(
+            r#"
+            let part = find("part");
+            part.translate(10, 0, 2.5);
+            part.scale(2, 2, 2);
+            print(part.name + " at " + part.position[0]);
+            "#,
+        );
+
+        assert!(!run.failed);
+        assert_eq!(run.output, vec!["part at 10.0"]);
+        assert_eq!(part.borrow().position, Vector3::new(10.0, 0.0, 2.5));
+        assert_eq!(part.borrow().scale, Vector3::new(2.0, 2.0, 2.0));
+
+        let mut action_manager = ActionManager::new();
+        action_manager.execute(Box::new(run.actions.unwrap()));
+        action_manager.undo();
+        assert_eq!(part.borrow().position, Vector3::zeros());
+        assert_eq!(part.borrow().scale, Vector3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_scene_functions_and_requests() {
+        let bodies = scene();
+        let mut console = ScriptConsole::new(&bodies);
+
+        // Variables persist between runs like in a REPL
+        console.run("let copy = duplicate(bodies()[0]);");
+        let run = console.run(
+            r#"
+            copy.move_to(0, 50, 0);
+            copy.selected = true;
+            print(bodies().len());
+            print(selected()[0].name);
+            print(find("missing") == ());
+            slice_selected();
+            "#,
+        );
+
+        assert!(!run.failed, "{:?}", run.output);
+        assert_eq!(run.output, vec!["2", "part copy", "true"]);
+        assert_eq!(run.requests, vec![SliceRequest::Selected]);
+        assert_eq!(bodies.borrow().len(), 3);
+        assert_eq!(
+            bodies.borrow()[2].borrow().position,
+            Vector3::new(0.0, 50.0, 0.0)
+        );
+        assert_ne!(
+            bodies.borrow()[2].borrow().uuid,
+            bodies.borrow()[0].borrow().uuid
+        );
+    }
+
+    #[test]
+    fn test_errors_keep_earlier_transforms() {
+        let bodies = scene();
+        let mut console = ScriptConsole::new(&bodies);
+
+        let run =
+            console.run("bodies()[0].translate(1, 0, 0); bodies()[0].translate(\"far\", 0, 0);");
+
+        assert!(run.failed);
+        assert!(run.output.last().unwrap().starts_with("Error: "));
+        assert!(run.actions.is_some());
+        assert!(run.requests.is_empty());
+    }
+
+    #[test]
+    fn test_alignment_is_recorded_as_one_statement() {
+        let bar = |x: f32| {
+            let mut mesh = crate::mesh::Mesh::default();
+            mesh.vertices = vec![
+                crate::mesh::Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0; 3]),
+                crate::mesh::Vertex::new([x, 1.0, 1.0], [0.0, 0.0, 1.0], [0.0; 3]),
+            ];
+            let mut body = Body::new(mesh);
+            body.set_position(Vector3::new(x, 0.0, 0.0));
+            Rc::new(RefCell::new(body))
+        };
+        let recorded = [bar(1.0), bar(4.0)];
+        let mut action_manager = ActionManager::new();
+        action_manager.start_recording();
+        action_manager.execute(Box::new(alignment::align(&recorded, 0, Alignment::Max)));
+        let statements = action_manager.stop_recording().unwrap();
+        assert_eq!(statements, vec!["align(targets(), \"x\", \"max\");"]);
+
+        let replayed = [bar(2.0), bar(10.0)];
+        let bodies: SharedBodies = Rc::new(RefCell::new(replayed.to_vec()));
+        let run = ScriptConsole::new(&bodies).run_on(&recorded_macro(&statements), &replayed);
+
+        assert!(!run.failed, "{:?}", run.output);
+        // Spanning 2 to 4, moved to end at 20 like the other one
+        assert_eq!(replayed[0].borrow().position.x, 18.0);
+        assert!(ScriptConsole::new(&bodies)
+            .run("align(bodies(), \"w\", \"max\");")
+            .failed);
+    }
+
+    #[test]
+    fn test_replay_recording() {
+        let bodies = scene();
+        let part = Rc::clone(&bodies.borrow()[0]);
+        let mut action_manager = ActionManager::new();
+        action_manager.start_recording();
+        action_manager.execute(Box::new(
+            BatchTransform {
+                translation: Vector3::new(0.0, 0.0, 5.0),
+                rotation: Vector3::new(30.0, 0.0, 0.0),
+                scale: Vector3::repeat(1.0),
+            }
+            .to_action(&[Rc::clone(&part)]),
+        ));
+        action_manager.record("slice();");
+        let source = recorded_macro(&action_manager.stop_recording().unwrap());
+        let imported = Rc::new(RefCell::new(Body::default()));
+        bodies.borrow_mut().push(Rc::clone(&imported));
+
+        let run = ScriptConsole::new(&bodies).run_on(&source, &[Rc::clone(&imported)]);
+
+        assert!(!run.failed, "{:?}", run.output);
+        assert_eq!(run.requests, vec![SliceRequest::All]);
+        let (imported, part) = (imported.borrow(), part.borrow());
+        assert_eq!(imported.position, part.position);
+        assert!((imported.rotation - part.rotation).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_macros() {
+        let dir = tempdir().unwrap();
+        let macros = dir.path().join("macros");
+        assert!(list_macros(&macros).unwrap().is_empty());
+
+        save_macro(
+            &macros,
+            "center all",
+            "for b in bodies() { b.move_to(0, 0, 0); }",
+        )
+        .unwrap();
+        save_macro(&macros, "a/b", "slice();").unwrap();
+
+        assert_eq!(list_macros(&macros).unwrap(), vec!["a_b", "center all"]);
+        assert_eq!(load_macro(&macros, "a/b").unwrap(), "slice();");
+        assert!(load_macro(&macros, "missing").is_err());
+    }
+}
@@ -1,3 +1,7 @@
+use crate::file_manager::file_manager::DEFAULT_OUTPUT_DIR;
+use crate::import_orientation::UpAxis;
+use crate::number_format::DecimalSeparator;
+use crate::orientation_presets::OrientationPreset;
 use crate::SharedSettings; // Ensure this is correctly defined as Arc<Mutex<Settings>> or similar
 use dirs_next::config_dir; // Use dirs-next for better maintenance
 use serde::{Deserialize, Serialize};
@@ -5,7 +9,7 @@ use std::{
     fs,
     io::Write,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, PoisonError},
 };
 use thiserror::Error; // For better error handling
 
@@ -14,6 +18,26 @@ pub struct GeneralSettings {
     pub username: String,
     pub theme: String,
     pub auto_save: bool,
+    /// Multiplies the scale factor the window system reports, for larger or smaller
+    /// controls than the monitor's DPI would give
+    #[serde(default = "default_ui_scale")]
+    pub ui_scale: f32,
+    /// False until the first-run setup has been finished. Settings saved before there was
+    /// a setup count as set up.
+    #[serde(default = "default_setup_complete")]
+    pub setup_complete: bool,
+    /// Decimal point or comma for the numbers shown and typed in, from the system's locale
+    /// by default
+    #[serde(default)]
+    pub decimal_separator: DecimalSeparator,
+}
+
+fn default_ui_scale() -> f32 {
+    1.0
+}
+
+fn default_setup_complete() -> bool {
+    true
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -21,6 +45,19 @@ pub struct RendererSettings {
     pub render_scale: f32,
     pub visualize_edges: bool,
     pub visualize_normals: bool,
+    /// Draws the local X/Y/Z axes of the selected bodies
+    #[serde(default)]
+    pub visualize_local_axes: bool,
+    /// Colors the back faces and the edges without exactly two faces of every body
+    #[serde(default)]
+    pub visualize_defects: bool,
+    /// Shows the frame rate, draw calls, vertices and VRAM of the 3D view over it, and
+    /// the throughput of a running slicing job
+    #[serde(default)]
+    pub show_performance_overlay: bool,
+    /// Draws the vat around the plate, filled with the resin to pour for the job
+    #[serde(default)]
+    pub show_vat_fill: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -29,11 +66,322 @@ pub struct NetworkSettings {
     pub use_https: bool,
 }
 
+/// What the island analyzer treats as up, as the build platform and as an island
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct IslandDetectionSettings {
+    /// Direction the print grows in, +Z like the slicer
+    pub up_axis: [f32; 3],
+    /// Vertices closer than this to the build platform rest on it, in mm for a body
+    /// 100 mm across. Scales with the size of the body, whatever units it was modeled in.
+    pub platform_tolerance: f32,
+    /// From 0 to 1: how steeply an edge has to lead down to support a vertex.
+    /// Higher values report vertices on shallow overhangs as islands too.
+    pub sensitivity: f32,
+}
+
+impl Default for IslandDetectionSettings {
+    fn default() -> Self {
+        Self {
+            up_axis: [0.0, 0.0, 1.0],
+            platform_tolerance: 0.001,
+            sensitivity: 0.0,
+        }
+    }
+}
+
+/// How much of the machine slicing and exporting may use
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct PerformanceSettings {
+    /// Number of worker threads, 0 picks one based on the number of cores
+    pub worker_threads: usize,
+    /// Run the workers at a lower priority and leave a core free for the UI
+    pub background_priority: bool,
+}
+
+/// Console macros run by the application itself
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct ScriptingSettings {
+    /// Macro run on every newly imported model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub import_macro: Option<String>,
+}
+
+/// Where the application reads and writes files
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct PathSettings {
+    /// Every export gets its own folder inside this one
+    pub output_dir: PathBuf,
+    /// Models shown in the library panel, with the ones in its subfolders
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library_dir: Option<PathBuf>,
+}
+
+impl Default for PathSettings {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::from(DEFAULT_OUTPUT_DIR),
+            library_dir: None,
+        }
+    }
+}
+
+/// The supports the model is printed on, which are made outside of SealSlicer
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct SupportSettings {
+    /// Diameter of the tip where a support touches the model, in mm
+    pub tip_diameter: f32,
+    /// Height of the lowest point of a body raised onto supports above the plate, in mm
+    pub base_gap: f32,
+    /// Distance between the pillars under a raised body, in mm
+    pub pillar_spacing: f32,
+    /// Width of a pillar at the plate, in mm
+    pub pillar_diameter: f32,
+}
+
+impl Default for SupportSettings {
+    fn default() -> Self {
+        Self {
+            tip_diameter: 0.4,
+            base_gap: 5.0,
+            pillar_spacing: 3.0,
+            pillar_diameter: 1.0,
+        }
+    }
+}
+
+/// Tabs tying small parts on the plate together so they don't come off during the print,
+/// for plates printed without a raft
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct AntiFloatTabSettings {
+    /// Bodies whose footprint fits in a square this wide get tabs, in mm
+    pub max_part_size: f32,
+    /// Parts further apart than this aren't tied to each other, in mm
+    pub max_span: f32,
+    /// Width of a tab at the plate, in mm
+    pub width: f32,
+    pub height: f32,
+    /// How much narrower a tab is on each side at the top, in mm, so it breaks off cleanly
+    pub chamfer: f32,
+    /// Tie every part to a bar in front of them instead of to each other
+    pub scaffold: bool,
+    /// Distance between the parts and the scaffold bar, in mm
+    pub scaffold_gap: f32,
+}
+
+impl Default for AntiFloatTabSettings {
+    fn default() -> Self {
+        Self {
+            max_part_size: 15.0,
+            max_span: 10.0,
+            width: 1.0,
+            height: 0.6,
+            chamfer: 0.3,
+            scaffold: false,
+            scaffold_gap: 3.0,
+        }
+    }
+}
+
+/// Arranging bodies by dragging them around the plate
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct LayoutSettings {
+    /// Move a body dropped onto another one to the nearest spot where it fits
+    pub slide_to_free_space: bool,
+    /// Space kept between the footprints of bodies, in mm
+    pub gap: f32,
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        Self {
+            slide_to_free_space: true,
+            gap: 1.0,
+        }
+    }
+}
+
+/// How imported models are placed on the plate
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct ImportSettings {
+    /// Up in the files, for STLs and for meshes of 3MFs, which are always Z up
+    pub up_axis: UpAxis,
+    /// Keep bodies where the file arranged them, e.g. the plate of a 3MF from another slicer
+    pub keep_arrangement: bool,
+    /// Ask after every import whether the orientation is right
+    pub confirm_orientation: bool,
+}
+
+impl Default for ImportSettings {
+    fn default() -> Self {
+        Self {
+            up_axis: UpAxis::Z,
+            keep_arrangement: true,
+            confirm_orientation: true,
+        }
+    }
+}
+
+/// Quick orientations for the selected bodies, from the presets of new settings files
+/// until the user edits the list
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct OrientationSettings {
+    pub presets: Vec<OrientationPreset>,
+}
+
+impl Default for OrientationSettings {
+    fn default() -> Self {
+        Self {
+            presets: OrientationPreset::defaults(),
+        }
+    }
+}
+
+/// Handing exports over to UVtools, for users who check their layers there
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct UvToolsSettings {
+    /// The UVtools executable, or its .app bundle on macOS. Looked for in the usual
+    /// install locations and on the PATH when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executable: Option<PathBuf>,
+    /// Open every export in UVtools as soon as it is written
+    pub open_after_export: bool,
+}
+
+/// How hard exports are compressed. Stronger compression makes smaller files but takes
+/// longer, which is worth it on fast machines writing to slow drives and not the other way
+/// around.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct CompressionSettings {
+    /// Lossless WebP layers keep exactly the gray levels of the slicer
+    pub webp_lossless: bool,
+    /// From 0 to 100, for lossy WebP layers
+    pub webp_quality: f32,
+    pub png: PngCompression,
+    /// From 0, which stores files without compressing them, to 9
+    pub zip_level: u8,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        Self {
+            webp_lossless: true,
+            webp_quality: 90.0,
+            png: PngCompression::Fast,
+            zip_level: 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PngCompression {
+    #[default]
+    Fast,
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    /// In the order the slice dialog lists them
+    pub const ALL: [PngCompression; 3] = [
+        PngCompression::Fast,
+        PngCompression::Default,
+        PngCompression::Best,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PngCompression::Fast => "Fast",
+            PngCompression::Default => "Default",
+            PngCompression::Best => "Best",
+        }
+    }
+
+    /// Position in `ALL`
+    pub fn from_index(index: i32) -> Self {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| Self::ALL.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn index(self) -> i32 {
+        Self::ALL
+            .iter()
+            .position(|&level| level == self)
+            .unwrap_or(0) as i32
+    }
+}
+
+/// Hollowing bodies to save resin and keep large cross sections from blowing out
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct HollowingSettings {
+    /// Thickness of the walls left around the hollow inside, in mm
+    pub wall_thickness: f32,
+    /// Share of the hollow inside the infill lattice fills, in percent
+    pub infill_density: f32,
+    /// Width of the square struts of the infill lattice, in mm
+    pub strut_width: f32,
+    /// Holes through the bottom the resin drains out of and air comes in by, 0 for none
+    pub drain_holes: usize,
+    /// Width of the square drain holes, in mm
+    pub drain_diameter: f32,
+}
+
+impl Default for HollowingSettings {
+    fn default() -> Self {
+        Self {
+            wall_thickness: 2.0,
+            infill_density: 10.0,
+            strut_width: 1.0,
+            drain_holes: 2,
+            drain_diameter: 3.0,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Settings {
     pub general: GeneralSettings,
     pub renderer: RendererSettings,
     pub network: NetworkSettings,
+    #[serde(default)]
+    pub island_detection: IslandDetectionSettings,
+    #[serde(default)]
+    pub performance: PerformanceSettings,
+    #[serde(default)]
+    pub scripting: ScriptingSettings,
+    #[serde(default)]
+    pub layout: LayoutSettings,
+    #[serde(default)]
+    pub paths: PathSettings,
+    #[serde(default)]
+    pub supports: SupportSettings,
+    #[serde(default)]
+    pub anti_float_tabs: AntiFloatTabSettings,
+    #[serde(default)]
+    pub import: ImportSettings,
+    #[serde(default)]
+    pub orientation: OrientationSettings,
+    #[serde(default)]
+    pub uvtools: UvToolsSettings,
+    #[serde(default)]
+    pub compression: CompressionSettings,
+    #[serde(default)]
+    pub hollowing: HollowingSettings,
 }
 
 impl Default for Settings {
@@ -43,16 +391,35 @@ impl Default for Settings {
                 username: String::from("Egg"),
                 theme: String::from("system"),
                 auto_save: true,
+                ui_scale: 1.0,
+                setup_complete: true,
+                decimal_separator: DecimalSeparator::Auto,
             },
             renderer: RendererSettings {
                 render_scale: 1.0,
                 visualize_edges: true,
                 visualize_normals: false,
+                visualize_local_axes: false,
+                visualize_defects: false,
+                show_performance_overlay: false,
+                show_vat_fill: false,
             },
             network: NetworkSettings {
                 timeout: 30,
                 use_https: true,
             },
+            island_detection: IslandDetectionSettings::default(),
+            performance: PerformanceSettings::default(),
+            scripting: ScriptingSettings::default(),
+            layout: LayoutSettings::default(),
+            paths: PathSettings::default(),
+            supports: SupportSettings::default(),
+            anti_float_tabs: AntiFloatTabSettings::default(),
+            import: ImportSettings::default(),
+            orientation: OrientationSettings::default(),
+            uvtools: UvToolsSettings::default(),
+            compression: CompressionSettings::default(),
+            hollowing: HollowingSettings::default(),
         }
     }
 }
@@ -96,15 +463,11 @@ impl Settings {
         Ok(settings)
     }
 
-    /// Saves settings to a specified file path, ensuring the directory exists.
+    /// Saves settings to a specified file path, ensuring the directory exists. The previous
+    /// version is kept next to it, see `write_atomically`.
     pub fn save_to_file(&self, path: &Path) -> Result<(), SettingsError> {
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
         let content = toml::to_string_pretty(self)?;
-        fs::write(path, content)?;
-        Ok(())
+        write_atomically(path, &content)
     }
 
     /// Saves settings to the user settings file.
@@ -113,6 +476,19 @@ impl Settings {
         self.save_to_file(&user_path)
     }
 
+    /// Changes the shared settings and saves them to the user settings file while still
+    /// holding the lock, so the UI and background jobs never save over each other's changes.
+    /// Everything that changes the user's settings goes through here.
+    pub fn update_user_settings<R>(
+        shared: &SharedSettings,
+        change: impl FnOnce(&mut Settings) -> R,
+    ) -> Result<R, SettingsError> {
+        let mut settings = shared.lock().unwrap_or_else(PoisonError::into_inner);
+        let result = change(&mut settings);
+        settings.save_user_settings()?;
+        Ok(result)
+    }
+
     /// Loads user settings, handling defaults and creating necessary files.
     pub fn load_user_settings() -> SharedSettings {
         match Settings::initialize_settings() {
@@ -134,39 +510,96 @@ impl Settings {
             match Settings::load_from_file(&user_settings_path) {
                 Ok(settings) => Ok(settings),
                 Err(e) => {
+                    let backup_path = backup_path(&user_settings_path);
+                    if let Ok(settings) = Settings::load_from_file(&backup_path) {
+                        eprintln!(
+                            "Failed to load user settings: {}. Restored the previous version.",
+                            e
+                        );
+                        settings.save_to_file(&user_settings_path)?;
+                        return Ok(settings);
+                    }
                     eprintln!(
                         "Failed to load user settings: {}. Attempting to load defaults.",
                         e
                     );
-                    Settings::load_defaults(user_settings_path, default_settings_path)
+                    Settings::load_defaults(user_settings_path, default_settings_path, false)
                 }
             }
         } else {
-            // User settings do not exist; attempt to load defaults
+            // User settings do not exist, so this is the first launch; attempt to load
+            // defaults and leave the rest to the setup
             println!("User settings not found. Loading default settings.");
-            Settings::load_defaults(user_settings_path, default_settings_path)
+            Settings::load_defaults(user_settings_path, default_settings_path, true)
         }
     }
 
-    /// Loads default settings and saves them as user settings.
-    fn load_defaults(user_path: PathBuf, default_path: PathBuf) -> Result<Self, SettingsError> {
+    /// Loads default settings and saves them as user settings, marked for the first-run
+    /// setup when `first_run` is set.
+    fn load_defaults(
+        user_path: PathBuf,
+        default_path: PathBuf,
+        first_run: bool,
+    ) -> Result<Self, SettingsError> {
         if default_path.exists() {
-            let settings = Settings::load_from_file(&default_path)?;
+            let mut settings = Settings::load_from_file(&default_path)?;
+            settings.general.setup_complete = !first_run;
             // Save default settings as user settings
             settings.save_to_file(&user_path)?;
             println!("Default settings loaded and saved as user settings.");
             Ok(settings)
         } else {
             eprintln!("Default settings file not found. Using hardcoded defaults.");
-            let settings = Settings::default();
+            let mut settings = Settings::default();
             // Save hardcoded defaults as default and user settings
             settings.save_to_file(&default_path)?;
+            settings.general.setup_complete = !first_run;
             settings.save_to_file(&user_path)?;
             println!("Hardcoded defaults saved as default and user settings.");
             Ok(settings)
         }
     }
 }
+/// Where `write_atomically` keeps the previous version of a file
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Writes a file of the user's settings so that it is never left half written: the content
+/// goes to a temporary file next to it, which then replaces it in one step. The version it
+/// replaces is kept as a backup.
+pub fn write_atomically(path: &Path, content: &str) -> Result<(), SettingsError> {
+    // A bare file name has an empty parent, which is the working directory
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temp_path = path.with_file_name(name);
+
+    let written = fs::File::create(&temp_path).and_then(|mut file| {
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.into());
+    }
+    if path.is_file() {
+        fs::copy(path, backup_path(path))?;
+    }
+    if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -280,6 +713,7 @@ mod tests {
             username = "TestUser"
             theme = "dark"
             auto_save = false
+            ui_scale = 1.25
 
             [renderer]
             render_scale = 2.5
@@ -299,6 +733,7 @@ mod tests {
         assert_eq!(settings.general.username, "TestUser");
         assert_eq!(settings.general.theme, "dark");
         assert_eq!(settings.general.auto_save, false);
+        assert_eq!(settings.general.ui_scale, 1.25);
 
         assert_eq!(settings.renderer.render_scale, 2.5);
         assert_eq!(settings.renderer.visualize_edges, false);
@@ -346,6 +781,8 @@ mod tests {
         assert_eq!(settings.general.username, "DefaultUser");
         assert_eq!(settings.general.theme, "light");
         assert_eq!(settings.general.auto_save, true);
+        // Settings from before the UI scale existed keep the window system's scale
+        assert_eq!(settings.general.ui_scale, 1.0);
 
         assert_eq!(settings.renderer.render_scale, 1.5);
         assert_eq!(settings.renderer.visualize_edges, true);
@@ -405,8 +842,12 @@ mod tests {
         assert_eq!(settings.network.timeout, 45);
         assert_eq!(settings.network.use_https, true);
 
-        // Assert that user_settings.toml is created with default settings
+        // Assert that user_settings.toml is created with default settings, waiting for the
+        // first-run setup
         assert!(user_settings_path.exists());
+        assert!(!settings.general.setup_complete);
+        let saved = Settings::load_from_file(&user_settings_path).unwrap();
+        assert!(!saved.general.setup_complete);
 
         reset_config_dir(&original_home, original_xdg_config_home.as_deref());
     }
@@ -444,6 +885,10 @@ mod tests {
         // Assert that both default_settings.toml and user_settings.toml are created
         assert!(default_settings_path.exists());
         assert!(user_settings_path.exists());
+        assert!(!settings.general.setup_complete);
+        // Only the user's settings wait for the setup
+        let defaults = Settings::load_from_file(&default_settings_path).unwrap();
+        assert!(defaults.general.setup_complete);
 
         reset_config_dir(&original_home, original_xdg_config_home.as_deref());
     }
@@ -520,16 +965,35 @@ mod tests {
                 username: "CustomUser".to_string(),
                 theme: "dark".to_string(),
                 auto_save: false,
+                ui_scale: 1.0,
+                setup_complete: true,
+                decimal_separator: DecimalSeparator::Auto,
             },
             renderer: RendererSettings {
                 render_scale: 2.0,
                 visualize_edges: false,
                 visualize_normals: true,
+                visualize_local_axes: false,
+                visualize_defects: false,
+                show_performance_overlay: false,
+                show_vat_fill: false,
             },
             network: NetworkSettings {
                 timeout: 50,
                 use_https: false,
             },
+            island_detection: IslandDetectionSettings::default(),
+            performance: PerformanceSettings::default(),
+            scripting: ScriptingSettings::default(),
+            layout: LayoutSettings::default(),
+            paths: PathSettings::default(),
+            supports: SupportSettings::default(),
+            anti_float_tabs: AntiFloatTabSettings::default(),
+            import: ImportSettings::default(),
+            orientation: OrientationSettings::default(),
+            uvtools: UvToolsSettings::default(),
+            compression: CompressionSettings::default(),
+            hollowing: HollowingSettings::default(),
         };
 
         // Save user settings
@@ -569,16 +1033,35 @@ mod tests {
                 username: "DefaultUser".to_string(),
                 theme: "light".to_string(),
                 auto_save: true,
+                ui_scale: 1.0,
+                setup_complete: true,
+                decimal_separator: DecimalSeparator::Auto,
             },
             renderer: RendererSettings {
                 render_scale: 1.2,
                 visualize_edges: true,
                 visualize_normals: false,
+                visualize_local_axes: false,
+                visualize_defects: false,
+                show_performance_overlay: false,
+                show_vat_fill: false,
             },
             network: NetworkSettings {
                 timeout: 40,
                 use_https: true,
             },
+            island_detection: IslandDetectionSettings::default(),
+            performance: PerformanceSettings::default(),
+            scripting: ScriptingSettings::default(),
+            layout: LayoutSettings::default(),
+            paths: PathSettings::default(),
+            supports: SupportSettings::default(),
+            anti_float_tabs: AntiFloatTabSettings::default(),
+            import: ImportSettings::default(),
+            orientation: OrientationSettings::default(),
+            uvtools: UvToolsSettings::default(),
+            compression: CompressionSettings::default(),
+            hollowing: HollowingSettings::default(),
         };
 
         // Save default settings
@@ -823,16 +1306,87 @@ mod tests {
                 username: "SerializeUser".to_string(),
                 theme: "blue".to_string(),
                 auto_save: false,
+                ui_scale: 1.0,
+                setup_complete: true,
+                decimal_separator: DecimalSeparator::Comma,
             },
             renderer: RendererSettings {
                 render_scale: 3.0,
                 visualize_edges: true,
                 visualize_normals: true,
+                visualize_local_axes: false,
+                visualize_defects: false,
+                show_performance_overlay: false,
+                show_vat_fill: false,
             },
             network: NetworkSettings {
                 timeout: 100,
                 use_https: false,
             },
+            island_detection: IslandDetectionSettings {
+                up_axis: [0.0, 0.0, 1.0],
+                platform_tolerance: 0.25,
+                sensitivity: 0.5,
+            },
+            performance: PerformanceSettings {
+                worker_threads: 4,
+                background_priority: true,
+            },
+            scripting: ScriptingSettings {
+                import_macro: Some("orient".to_string()),
+            },
+            layout: LayoutSettings {
+                slide_to_free_space: false,
+                gap: 2.5,
+            },
+            paths: PathSettings {
+                output_dir: PathBuf::from("/tmp/jobs"),
+                library_dir: Some(PathBuf::from("/home/me/models")),
+            },
+            supports: SupportSettings {
+                tip_diameter: 0.5,
+                base_gap: 4.0,
+                pillar_spacing: 2.5,
+                pillar_diameter: 1.25,
+            },
+            anti_float_tabs: AntiFloatTabSettings {
+                max_part_size: 12.0,
+                max_span: 8.0,
+                width: 1.5,
+                height: 0.5,
+                chamfer: 0.25,
+                scaffold: true,
+                scaffold_gap: 2.0,
+            },
+            import: ImportSettings {
+                up_axis: UpAxis::Y,
+                keep_arrangement: false,
+                confirm_orientation: false,
+            },
+            orientation: OrientationSettings {
+                presets: vec![OrientationPreset {
+                    name: "Bust".to_string(),
+                    tilt: 20.0,
+                    turn: 0.0,
+                }],
+            },
+            uvtools: UvToolsSettings {
+                executable: Some(PathBuf::from("/opt/UVtools/UVtools")),
+                open_after_export: true,
+            },
+            compression: CompressionSettings {
+                webp_lossless: false,
+                webp_quality: 80.0,
+                png: PngCompression::Best,
+                zip_level: 6,
+            },
+            hollowing: HollowingSettings {
+                wall_thickness: 1.5,
+                infill_density: 20.0,
+                strut_width: 0.75,
+                drain_holes: 1,
+                drain_diameter: 4.0,
+            },
         };
 
         let serialized = toml::to_string_pretty(&settings).unwrap();
@@ -841,15 +1395,88 @@ mod tests {
 username = "SerializeUser"
 theme = "blue"
 auto_save = false
+ui_scale = 1.0
+setup_complete = true
+decimal_separator = "comma"
 
 [renderer]
 render_scale = 3.0
 visualize_edges = true
 visualize_normals = true
+visualize_local_axes = false
+visualize_defects = false
+show_performance_overlay = false
+show_vat_fill = false
 
 [network]
 timeout = 100
 use_https = false
+
+[island_detection]
+up_axis = [
+    0.0,
+    0.0,
+    1.0,
+]
+platform_tolerance = 0.25
+sensitivity = 0.5
+
+[performance]
+worker_threads = 4
+background_priority = true
+
+[scripting]
+import_macro = "orient"
+
+[layout]
+slide_to_free_space = false
+gap = 2.5
+
+[paths]
+output_dir = "/tmp/jobs"
+library_dir = "/home/me/models"
+
+[supports]
+tip_diameter = 0.5
+base_gap = 4.0
+pillar_spacing = 2.5
+pillar_diameter = 1.25
+
+[anti_float_tabs]
+max_part_size = 12.0
+max_span = 8.0
+width = 1.5
+height = 0.5
+chamfer = 0.25
+scaffold = true
+scaffold_gap = 2.0
+
+[import]
+up_axis = "y"
+keep_arrangement = false
+confirm_orientation = false
+
+[[orientation.presets]]
+name = "Bust"
+tilt = 20.0
+turn = 0.0
+
+[uvtools]
+executable = "/opt/UVtools/UVtools"
+open_after_export = true
+
+[compression]
+webp_lossless = false
+webp_quality = 80.0
+png = "best"
+zip_level = 6
+
+[hollowing]
+wall_thickness = 1.5
+infill_density = 20.0
+strut_width = 0.75
+drain_holes = 1
+drain_diameter = 4.0
 "#.trim();
 
         assert_eq!(serialized.trim(), expected);
@@ -886,6 +1513,10 @@ use_https = false
 
         assert_eq!(settings.network.timeout, 120);
         assert_eq!(settings.network.use_https, true);
+        assert_eq!(settings.layout, LayoutSettings::default());
+        assert_eq!(settings.paths.output_dir, PathBuf::from("slices"));
+        assert_eq!(settings.supports, SupportSettings::default());
+        assert!(settings.general.setup_complete);
     }
 
     /// Test Case 5c: Handling Missing Fields During Deserialization
@@ -923,6 +1554,11 @@ use_https = false
 
         assert_eq!(default_settings.network.timeout, 30);
         assert_eq!(default_settings.network.use_https, true);
+
+        // Exports stay lossless and as fast as before compression was configurable
+        assert!(default_settings.compression.webp_lossless);
+        assert_eq!(default_settings.compression.png, PngCompression::Fast);
+        assert_eq!(default_settings.compression.zip_level, 0);
     }
 
     /// Test Case 6b: Overriding Defaults When Loading from Files
@@ -1230,4 +1866,86 @@ use_https = false
 
         reset_config_dir(&original_home, original_xdg_config_home.as_deref());
     }
+
+    /// Test Case 10c: Saving keeps the previous version and leaves no temporary file
+    #[test]
+    fn test_atomic_save_keeps_backup() {
+        let (_temp_dir, config_dir) = setup_temp_config_dir();
+        let path = config_dir.join("settings").join("user_settings.toml");
+
+        let mut settings = Settings::default();
+        settings.save_to_file(&path).unwrap();
+        assert!(!backup_path(&path).exists());
+
+        settings.general.username = "Second".to_string();
+        settings.save_to_file(&path).unwrap();
+        assert_eq!(Settings::load_from_file(&path).unwrap(), settings);
+        assert_eq!(
+            Settings::load_from_file(&backup_path(&path)).unwrap(),
+            Settings::default()
+        );
+        let names: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 2);
+    }
+
+    /// Test Case 10d: A corrupted user settings file is restored from its backup
+    #[test]
+    #[serial]
+    fn test_recovery_from_backup() {
+        let (_temp_dir, config_dir) = setup_temp_config_dir();
+        let original_home = env::var("HOME").unwrap_or_default();
+        let original_xdg_config_home = env::var("XDG_CONFIG_HOME").ok();
+        override_config_dir(&config_dir);
+
+        let user_settings_path = Settings::user_settings_path().unwrap();
+        let mut settings = Settings::default();
+        settings.general.username = "BackedUp".to_string();
+        settings.save_to_file(&user_settings_path).unwrap();
+        settings.save_to_file(&user_settings_path).unwrap();
+        fs::write(&user_settings_path, "corrupted content").unwrap();
+
+        let restored = Settings::initialize_settings().unwrap();
+        assert_eq!(restored.general.username, "BackedUp");
+        assert_eq!(
+            Settings::load_from_file(&user_settings_path).unwrap(),
+            restored
+        );
+
+        reset_config_dir(&original_home, original_xdg_config_home.as_deref());
+    }
+
+    /// Test Case 10e: Changes made through the shared handle are saved with them
+    #[test]
+    #[serial]
+    fn test_update_user_settings() {
+        let (_temp_dir, config_dir) = setup_temp_config_dir();
+        let original_home = env::var("HOME").unwrap_or_default();
+        let original_xdg_config_home = env::var("XDG_CONFIG_HOME").ok();
+        override_config_dir(&config_dir);
+
+        let shared_settings: SharedSettings = Arc::new(Mutex::new(Settings::default()));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let shared_clone = Arc::clone(&shared_settings);
+                std::thread::spawn(move || {
+                    Settings::update_user_settings(&shared_clone, |settings| {
+                        settings.network.timeout += 1
+                    })
+                    .unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let saved = Settings::load_from_file(&Settings::user_settings_path().unwrap()).unwrap();
+        assert_eq!(saved.network.timeout, 34);
+        assert_eq!(*shared_settings.lock().unwrap(), saved);
+
+        reset_config_dir(&original_home, original_xdg_config_home.as_deref());
+    }
 }
@@ -0,0 +1,264 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::motion_profile::MotionProfile;
+use crate::output_formats::{Layer, OutputFormat, PrinterFile, ReadError};
+use crate::preview::{PreviewEncoding, PreviewFormat};
+use crate::printer::Printer;
+use crate::settings::PngCompression;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::ImageFormat;
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// The thumbnails PrusaSlicer writes, the printer shows the larger one
+const THUMBNAILS: [(u32, u32); 2] = [(400, 400), (800, 480)];
+
+/// An SL1 archive for the Prusa SL1 and SL1S: a zip of the job settings in `config.ini`,
+/// the printer and resin the way PrusaSlicer keeps them in `prusaslicer.ini`, a grayscale
+/// PNG per layer and the thumbnails. The printers tilt the vat instead of lifting the
+/// plate, so the motion profile doesn't go in, and the bottom exposure fades into the
+/// normal one over the bottom layers. There is one layer height for the whole print.
+pub fn encode(file: &PrinterFile, layers: &[Layer]) -> Vec<u8> {
+    let printer = &file.printer;
+    let motion = &file.motion;
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    let mut add = |name: &str, bytes: &[u8]| -> zip::result::ZipResult<()> {
+        zip.start_file(name, options)?;
+        zip.write_all(bytes)?;
+        Ok(())
+    };
+
+    let config = [
+        ("action", "print".to_string()),
+        ("jobDir", file.name.clone()),
+        ("expTime", motion.exposure_time.to_string()),
+        ("expTimeFirst", motion.bottom_exposure_time.to_string()),
+        ("layerHeight", file.layer_height.to_string()),
+        ("materialName", file.resin.clone()),
+        ("numFade", motion.bottom_layer_count.to_string()),
+        ("numFast", layers.len().to_string()),
+        ("numSlow", "0".to_string()),
+        (
+            "printTime",
+            file.print_time(layers.len()).round().to_string(),
+        ),
+        ("printerModel", printer.model.clone()),
+        (
+            "prusaSlicerVersion",
+            format!("SealSlicer {}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("usedMaterial", format!("{:.3}", file.volume_ml(layers))),
+    ];
+    let mut slicer_config = vec![
+        ("display_pixels_x", printer.pixel_x.to_string()),
+        ("display_pixels_y", printer.pixel_y.to_string()),
+        ("display_width", printer.physical_x.to_string()),
+        ("display_height", printer.physical_y.to_string()),
+        ("max_print_height", printer.physical_z.to_string()),
+        ("printer_model", printer.model.clone()),
+        ("printer_technology", "SLA".to_string()),
+    ];
+    if let Some(price) = file.price_per_liter {
+        slicer_config.push(("bottle_cost", price.to_string()));
+        slicer_config.push(("bottle_volume", "1000".to_string()));
+    }
+
+    // Writing to memory only fails on a bug, like the encoders of the other formats
+    let written: zip::result::ZipResult<()> = (|| {
+        add("config.ini", ini(&config).as_bytes())?;
+        add("prusaslicer.ini", ini(&slicer_config).as_bytes())?;
+        for (index, layer) in layers.iter().enumerate() {
+            add(&layer_name(&file.name, index), &encode_png(layer))?;
+        }
+        for (width, height) in THUMBNAILS {
+            let format = PreviewFormat {
+                width,
+                height,
+                encoding: PreviewEncoding::Png,
+                model_color: [255, 140, 40],
+                background_color: [0, 0, 0],
+            };
+            let thumbnail = format
+                .encode(&format.render(layers), PngCompression::default())
+                .expect("PNG to memory can't fail");
+            add(
+                &format!("thumbnail/thumbnail{}x{}.png", width, height),
+                &thumbnail,
+            )?;
+        }
+        Ok(())
+    })();
+    written.expect("zip to memory can't fail");
+    zip.finish().expect("zip to memory can't fail").into_inner()
+}
+
+/// Reads an archive `encode` wrote. The lift and retract aren't stored, they come back as
+/// the defaults.
+pub fn decode(name: &str, bytes: &[u8]) -> Result<(PrinterFile, Vec<Layer>), ReadError> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(bytes)).map_err(|_| ReadError::WrongFormat(".sl1"))?;
+    let mut read = |name: &str| -> Result<Vec<u8>, ReadError> {
+        let mut entry = archive
+            .by_name(name)
+            .map_err(|_| ReadError::WrongFormat(".sl1"))?;
+        let mut bytes = Vec::new();
+        entry
+            .read_to_end(&mut bytes)
+            .map_err(|_| ReadError::Truncated)?;
+        Ok(bytes)
+    };
+    let config = parse_ini(&read("config.ini")?);
+    let slicer_config = parse_ini(&read("prusaslicer.ini")?);
+    let value = |config: &HashMap<String, String>, key: &str| -> Result<f64, ReadError> {
+        config
+            .get(key)
+            .and_then(|value| value.parse().ok())
+            .ok_or(ReadError::WrongFormat(".sl1"))
+    };
+
+    let motion = MotionProfile {
+        exposure_time: value(&config, "expTime")?,
+        bottom_exposure_time: value(&config, "expTimeFirst")?,
+        bottom_layer_count: value(&config, "numFade")? as usize,
+        ..MotionProfile::default()
+    };
+    let layer_count = (value(&config, "numFast")? + value(&config, "numSlow")?) as usize;
+    let model = config.get("printerModel").cloned().unwrap_or_default();
+    let printer = Printer {
+        name: model.clone(),
+        model,
+        pixel_x: value(&slicer_config, "display_pixels_x")? as u32,
+        pixel_y: value(&slicer_config, "display_pixels_y")? as u32,
+        physical_x: value(&slicer_config, "display_width")?,
+        physical_y: value(&slicer_config, "display_height")?,
+        physical_z: value(&slicer_config, "max_print_height")?,
+        output_format: OutputFormat::Sl1,
+        ..Printer::default()
+    };
+    let price_per_liter = value(&slicer_config, "bottle_cost")
+        .ok()
+        .zip(value(&slicer_config, "bottle_volume").ok())
+        .and_then(|(price, volume)| (volume > 0.0).then(|| price / volume * 1000.0));
+
+    let job_dir = config.get("jobDir").cloned().unwrap_or_default();
+    let layers = (0..layer_count)
+        .map(|index| {
+            let png = read(&layer_name(&job_dir, index))?;
+            image::load_from_memory_with_format(&png, ImageFormat::Png)
+                .ok()
+                .map(|image| image.to_luma8())
+                .filter(|layer| layer.dimensions() == (printer.pixel_x, printer.pixel_y))
+                .ok_or(ReadError::DamagedLayer(index))
+        })
+        .collect::<Result<_, _>>()?;
+    let file = PrinterFile {
+        format: OutputFormat::Sl1,
+        name: name.to_string(),
+        printer,
+        resin: config.get("materialName").cloned().unwrap_or_default(),
+        motion,
+        layer_height: value(&config, "layerHeight")?,
+        layer_height_ranges: Vec::new(),
+        layer_overrides: Vec::new(),
+        price_per_liter,
+    };
+    Ok((file, layers))
+}
+
+/// Layers are numbered from 0 after the name of the job, five digits wide
+fn layer_name(job_dir: &str, index: usize) -> String {
+    format!("{}{:05}.png", job_dir, index)
+}
+
+fn encode_png(layer: &Layer) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let encoder = PngEncoder::new_with_quality(
+        Cursor::new(&mut bytes),
+        CompressionType::Fast,
+        FilterType::Adaptive,
+    );
+    layer
+        .write_with_encoder(encoder)
+        .expect("PNG to memory can't fail");
+    bytes
+}
+
+/// One `key = value` line each, the way PrusaSlicer writes them
+fn ini(entries: &[(&str, String)]) -> String {
+    entries
+        .iter()
+        .map(|(key, value)| format!("{} = {}\n", key, value))
+        .collect()
+}
+
+fn parse_ini(bytes: &[u8]) -> HashMap<String, String> {
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slice_parameters::SliceParameters;
+    use image::{ImageBuffer, Luma};
+
+    #[test]
+    fn test_sl1_archive() {
+        let mut printer = SliceParameters::default().printer;
+        (printer.pixel_x, printer.pixel_y) = (60, 40);
+        printer.model = "SL1S".to_string();
+        let file = PrinterFile {
+            format: OutputFormat::Sl1,
+            name: "job".to_string(),
+            printer,
+            resin: "Prusa Orange Tough".to_string(),
+            motion: MotionProfile::default(),
+            layer_height: 0.05,
+            layer_height_ranges: Vec::new(),
+            layer_overrides: Vec::new(),
+            price_per_liter: None,
+        };
+        let layers: Vec<Layer> = (0..12)
+            .map(|i| ImageBuffer::from_fn(60, 40, |x, _| Luma([if x < i { 255 } else { 0 }])))
+            .collect();
+        assert_eq!(file.file_name(), "job.sl1");
+        let bytes = encode(&file, &layers);
+
+        let mut archive = ZipArchive::new(Cursor::new(&bytes)).unwrap();
+        for name in [
+            "config.ini",
+            "prusaslicer.ini",
+            "job00000.png",
+            "job00011.png",
+            "thumbnail/thumbnail800x480.png",
+        ] {
+            assert!(archive.by_name(name).is_ok(), "{} is missing", name);
+        }
+        let mut config = String::new();
+        archive
+            .by_name("config.ini")
+            .unwrap()
+            .read_to_string(&mut config)
+            .unwrap();
+        let config = parse_ini(config.as_bytes());
+        assert_eq!(config["jobDir"], "job");
+        assert_eq!(config["numFast"], "12");
+        assert_eq!(config["materialName"], "Prusa Orange Tough");
+
+        let (decoded, decoded_layers) = decode("job", &bytes).unwrap();
+        assert_eq!(decoded.resin, "Prusa Orange Tough");
+        assert_eq!(decoded.printer.model, "SL1S");
+        assert_eq!(decoded_layers, layers);
+        assert!(matches!(
+            decode("job", b"not a zip"),
+            Err(ReadError::WrongFormat(_))
+        ));
+    }
+}
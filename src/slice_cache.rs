@@ -0,0 +1,471 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::bleed_compensation::BleedChanges;
+use crate::body::Body;
+use crate::body_layers::BodyLayers;
+use crate::layer_thickness::LayerThickness;
+use crate::printer::Printer;
+use crate::resin::Resin;
+use crate::slice_parameters::Supersampling;
+use image::{ImageBuffer, Luma};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use uuid::Uuid;
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// A keyed snapshot of everything that affects the output of a slicing run.
+/// Each body is reduced to a single hash of its mesh and transform, and the
+/// slicing parameters are reduced to one more hash.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SliceSnapshot {
+    pub settings_key: u64,
+    pub body_keys: BTreeMap<Uuid, u64>,
+}
+
+/// What changed between two snapshots, by body uuid
+#[derive(Default, Debug, PartialEq)]
+pub struct SnapshotDiff {
+    pub added: Vec<Uuid>,
+    pub removed: Vec<Uuid>,
+    pub changed: Vec<Uuid>,
+    pub unchanged: Vec<Uuid>,
+    pub settings_changed: bool,
+}
+
+impl SliceSnapshot {
+    pub fn capture<'a>(
+        bodies: impl IntoIterator<Item = &'a Body>,
+        thickness: &LayerThickness,
+        printer: &Printer,
+        supersampling: Supersampling,
+        resin: &Resin,
+    ) -> Self {
+        let body_keys = bodies
+            .into_iter()
+            .map(|body| (body.uuid, Self::body_key(body)))
+            .collect();
+        Self {
+            settings_key: Self::settings_key(thickness, printer, supersampling, resin),
+            body_keys,
+        }
+    }
+
+    /// Hashes the mesh data, the transform and the slice role of a body
+    pub fn body_key(body: &Body) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(bytemuck::cast_slice(&body.mesh.vertices));
+        hasher.write(bytemuck::cast_slice(&body.mesh.indices));
+        for value in body.position.iter().chain(body.scale.iter()) {
+            value.to_bits().hash(&mut hasher);
+        }
+        for value in body.rotation.coords.iter() {
+            value.to_bits().hash(&mut hasher);
+        }
+        body.slice_role.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Hashes the slicing parameters. The printer and resin are hashed through its serialized
+    /// form so new profile fields are picked up without touching this function.
+    pub fn settings_key(
+        thickness: &LayerThickness,
+        printer: &Printer,
+        supersampling: Supersampling,
+        resin: &Resin,
+    ) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        thickness.base.to_bits().hash(&mut hasher);
+        for range in &thickness.ranges {
+            for value in [range.from, range.to, range.thickness] {
+                value.to_bits().hash(&mut hasher);
+            }
+            range.exposure_time.map(f64::to_bits).hash(&mut hasher);
+        }
+        toml::to_string(printer)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        supersampling.factor().hash(&mut hasher);
+        toml::to_string(resin).unwrap_or_default().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Compares this snapshot against a previous one
+    pub fn diff(&self, previous: &SliceSnapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff {
+            settings_changed: self.settings_key != previous.settings_key,
+            ..Default::default()
+        };
+
+        for (uuid, key) in &self.body_keys {
+            match previous.body_keys.get(uuid) {
+                None => diff.added.push(*uuid),
+                Some(previous_key) if previous_key != key => diff.changed.push(*uuid),
+                Some(_) => diff.unchanged.push(*uuid),
+            }
+        }
+
+        for uuid in previous.body_keys.keys() {
+            if !self.body_keys.contains_key(uuid) {
+                diff.removed.push(*uuid);
+            }
+        }
+
+        diff
+    }
+}
+
+/// Holds the images from the most recent slicing run alongside the snapshot
+/// they were produced from, so an unchanged scene can skip slicing entirely. The images
+/// are shared with the export they were sliced for rather than copied.
+/// The layers of every body are kept too, so a scene where only some bodies changed
+/// slices just those, and what bleed compensation changed on each layer.
+#[derive(Default)]
+pub struct SliceCache {
+    snapshot: Option<SliceSnapshot>,
+    images: Arc<Vec<Layer>>,
+    bleed_changes: Vec<BleedChanges>,
+    body_layers: BTreeMap<Uuid, (u64, Arc<BodyLayers>)>,
+}
+
+impl SliceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Diff against the cached snapshot. Everything counts as added if the cache is empty.
+    pub fn diff(&self, snapshot: &SliceSnapshot) -> SnapshotDiff {
+        match &self.snapshot {
+            Some(previous) => snapshot.diff(previous),
+            None => snapshot.diff(&SliceSnapshot {
+                settings_key: !snapshot.settings_key,
+                body_keys: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the cached images if they were produced from an identical snapshot
+    pub fn get(&self, snapshot: &SliceSnapshot) -> Option<Arc<Vec<Layer>>> {
+        match &self.snapshot {
+            Some(previous) if previous == snapshot => Some(Arc::clone(&self.images)),
+            _ => None,
+        }
+    }
+
+    /// The images of the most recent slicing run, whatever the scene looks like now
+    pub fn latest(&self) -> &[Layer] {
+        &self.images
+    }
+
+    /// What bleed compensation changed on each layer of the most recent slicing run, empty
+    /// when it had none
+    pub fn latest_bleed_changes(&self) -> &[BleedChanges] {
+        &self.bleed_changes
+    }
+
+    /// The layers of the bodies that haven't changed since they were sliced with the same
+    /// settings. They only fit a job whose layers start at the same height.
+    pub fn reusable_layers(&self, snapshot: &SliceSnapshot) -> HashMap<Uuid, Arc<BodyLayers>> {
+        let settings_unchanged = self
+            .snapshot
+            .as_ref()
+            .is_some_and(|previous| previous.settings_key == snapshot.settings_key);
+        if !settings_unchanged {
+            return HashMap::new();
+        }
+        snapshot
+            .body_keys
+            .iter()
+            .filter_map(|(uuid, key)| match self.body_layers.get(uuid) {
+                Some((cached_key, layers)) if cached_key == key => {
+                    Some((*uuid, Arc::clone(layers)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn store(
+        &mut self,
+        snapshot: SliceSnapshot,
+        images: Arc<Vec<Layer>>,
+        bleed_changes: Vec<BleedChanges>,
+        body_layers: Vec<(Uuid, Arc<BodyLayers>)>,
+    ) {
+        self.body_layers = body_layers
+            .into_iter()
+            .filter_map(|(uuid, layers)| Some((uuid, (*snapshot.body_keys.get(&uuid)?, layers))))
+            .collect();
+        self.snapshot = Some(snapshot);
+        self.images = images;
+        self.bleed_changes = bleed_changes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::body::SliceRole;
+    use crate::layer_thickness::LayerHeightRange;
+    use crate::mesh::{Mesh, Vertex};
+    use nalgebra::Vector3;
+
+    fn test_body() -> Body {
+        let mesh = Mesh {
+            vertices: vec![
+                Vertex::new([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+                Vertex::new([1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+                Vertex::new([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0]),
+            ],
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        };
+        Body::new(mesh)
+    }
+
+    #[test]
+    fn test_identical_snapshots_have_empty_diff() {
+        let printer = Printer::default();
+        let resin = Resin::default();
+        let bodies = vec![test_body(), test_body()];
+        let first = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+        let second = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+
+        let diff = second.diff(&first);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty());
+        assert!(!diff.settings_changed);
+        assert_eq!(diff.unchanged.len(), 2);
+    }
+
+    #[test]
+    fn test_transform_change_marks_body_changed() {
+        let printer = Printer::default();
+        let resin = Resin::default();
+        let mut bodies = vec![test_body(), test_body()];
+        let first = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+
+        bodies[1].set_position(Vector3::new(5.0, 0.0, 0.0));
+        let second = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+
+        let diff = second.diff(&first);
+        assert_eq!(diff.changed, vec![bodies[1].uuid]);
+        assert_eq!(diff.unchanged, vec![bodies[0].uuid]);
+    }
+
+    #[test]
+    fn test_slice_role_change_marks_body_changed() {
+        let printer = Printer::default();
+        let resin = Resin::default();
+        let mut bodies = vec![test_body()];
+        let first = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+
+        bodies[0].slice_role = SliceRole::Subtract;
+        let second = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+
+        assert_eq!(second.diff(&first).changed, vec![bodies[0].uuid]);
+    }
+
+    #[test]
+    fn test_added_and_removed_bodies() {
+        let printer = Printer::default();
+        let resin = Resin::default();
+        let a = test_body();
+        let b = test_body();
+        let first = SliceSnapshot::capture(
+            std::slice::from_ref(&a),
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+        let second = SliceSnapshot::capture(
+            std::slice::from_ref(&b),
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+
+        let diff = second.diff(&first);
+        assert_eq!(diff.added, vec![b.uuid]);
+        assert_eq!(diff.removed, vec![a.uuid]);
+    }
+
+    #[test]
+    fn test_settings_change_is_detected() {
+        let printer = Printer::default();
+        let resin = Resin::default();
+        let bodies = vec![test_body()];
+        let first = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+        let second = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.05),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+
+        let diff = second.diff(&first);
+        assert!(diff.settings_changed);
+
+        let shrinking_resin = Resin {
+            shrinkage_x: 1.5,
+            ..resin.clone()
+        };
+        let third = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &shrinking_resin,
+        );
+        assert!(third.diff(&first).settings_changed);
+
+        // A band of finer layers changes the layer heights too
+        let mut fine_band = LayerThickness::uniform(0.1);
+        fine_band.ranges.push(LayerHeightRange {
+            from: 2.0,
+            to: 3.0,
+            thickness: 0.05,
+            exposure_time: None,
+        });
+        let fourth =
+            SliceSnapshot::capture(&bodies, &fine_band, &printer, Supersampling::Off, &resin);
+        assert!(fourth.diff(&first).settings_changed);
+    }
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let printer = Printer::default();
+        let resin = Resin::default();
+        let mut bodies = vec![test_body()];
+        let snapshot = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+        let mut cache = SliceCache::new();
+        assert!(cache.get(&snapshot).is_none());
+        let diff = cache.diff(&snapshot);
+        assert_eq!(diff.added, vec![bodies[0].uuid]);
+        assert!(diff.settings_changed);
+
+        let images = Arc::new(vec![ImageBuffer::new(2, 2)]);
+        cache.store(
+            snapshot.clone(),
+            Arc::clone(&images),
+            Vec::new(),
+            Vec::new(),
+        );
+        // The cache shares the images instead of copying them
+        assert!(Arc::ptr_eq(&cache.get(&snapshot).unwrap(), &images));
+
+        bodies[0].set_scale(Vector3::new(2.0, 2.0, 2.0));
+        let changed = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+        assert!(cache.get(&changed).is_none());
+    }
+
+    #[test]
+    fn test_reusable_layers() {
+        let printer = Printer::default();
+        let resin = Resin::default();
+        let mut bodies = vec![test_body(), test_body()];
+        let layers = |body: &Body| {
+            let layers = BodyLayers {
+                role: body.slice_role,
+                first_z: 0.0,
+                thickness: LayerThickness::uniform(0.1),
+                layers: Vec::new(),
+            };
+            (body.uuid, Arc::new(layers))
+        };
+        let snapshot = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+        let mut cache = SliceCache::new();
+        assert!(cache.reusable_layers(&snapshot).is_empty());
+        cache.store(
+            snapshot,
+            Arc::default(),
+            Vec::new(),
+            bodies.iter().map(layers).collect(),
+        );
+
+        bodies[1].set_position(Vector3::new(5.0, 0.0, 0.0));
+        let moved = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+        let reusable = cache.reusable_layers(&moved);
+        assert_eq!(reusable.keys().collect::<Vec<_>>(), [&bodies[0].uuid]);
+
+        let thinner = SliceSnapshot::capture(
+            &bodies,
+            &LayerThickness::uniform(0.05),
+            &printer,
+            Supersampling::Off,
+            &resin,
+        );
+        assert!(cache.reusable_layers(&thinner).is_empty());
+    }
+}
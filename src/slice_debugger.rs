@@ -0,0 +1,369 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::polygon_assembly::Segment;
+use nalgebra::{Vector2, Vector3};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Endpoints closer than this are the same point, matching the polygon assembly
+const ENDPOINT_EPSILON: f64 = 1e-6;
+
+/// A layer whose contours could not be assembled cleanly
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerReport {
+    pub index: usize,
+    pub plane_z: f64,
+    /// Segment endpoints that don't connect to another segment, so their contour stays open
+    pub open_ends: Vec<Vector2<f64>>,
+    /// Points where an assembled polygon crosses itself
+    pub self_intersections: Vec<Vector2<f64>>,
+    /// Annotated drawing of the layer
+    pub svg_path: Option<PathBuf>,
+}
+
+impl LayerReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Layer {} (z = {:.3}mm): {} open contour ends, {} self-intersections",
+            self.index,
+            self.plane_z,
+            self.open_ends.len(),
+            self.self_intersections.len()
+        );
+        if let Some(path) = &self.svg_path {
+            let _ = write!(summary, ", see {}", path.display());
+        }
+        summary
+    }
+}
+
+/// Debug mode of the slicing pipeline: inspects every layer for open contours and
+/// self-intersecting polygons and dumps the problem layers as annotated SVGs with
+/// every segment endpoint marked
+#[derive(Default)]
+pub struct SliceDebugger {
+    enabled: AtomicBool,
+    output_dir: Mutex<Option<PathBuf>>,
+    reports: Mutex<Vec<LayerReport>>,
+}
+
+static DEBUGGER: SliceDebugger = SliceDebugger::new();
+
+/// Global slice debugger, enabled with the `--debug-slices` command line flag
+pub fn global() -> &'static SliceDebugger {
+    &DEBUGGER
+}
+
+impl SliceDebugger {
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            output_dir: Mutex::new(None),
+            reports: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Forgets the reports of the previous job and picks a new output directory
+    pub fn begin_job(&self) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        *self.output_dir.lock().unwrap() = Some(
+            PathBuf::from("slices")
+                .join("debug")
+                .join(timestamp.to_string()),
+        );
+        self.reports.lock().unwrap().clear();
+    }
+
+    /// Checks a layer and writes its SVG if anything is wrong with it
+    pub fn inspect_layer(
+        &self,
+        index: usize,
+        plane_z: f64,
+        segments: &[Segment],
+        polygons: &[Vec<Vector3<f64>>],
+    ) {
+        let open_ends = open_ends(segments);
+        let self_intersections: Vec<Vector2<f64>> = polygons
+            .iter()
+            .flat_map(|polygon| self_intersections(polygon))
+            .collect();
+        if open_ends.is_empty() && self_intersections.is_empty() {
+            return;
+        }
+
+        let mut report = LayerReport {
+            index,
+            plane_z,
+            open_ends,
+            self_intersections,
+            svg_path: None,
+        };
+        let output_dir = self.output_dir.lock().unwrap().clone();
+        if let Some(dir) = output_dir {
+            let path = dir.join(format!("layer_{:04}.svg", index));
+            match write_svg(&path, &report, segments) {
+                Ok(()) => report.svg_path = Some(path),
+                Err(e) => eprintln!("Could not write {}: {}", path.display(), e),
+            }
+        }
+        println!("{}", report.summary());
+        self.reports.lock().unwrap().push(report);
+    }
+
+    /// Report of the problem layer closest to `z`, if one is within `max_distance`
+    pub fn report_near(&self, z: f64, max_distance: f64) -> Option<LayerReport> {
+        self.reports
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|report| (report.plane_z - z).abs() <= max_distance)
+            .min_by(|a, b| (a.plane_z - z).abs().total_cmp(&(b.plane_z - z).abs()))
+            .cloned()
+    }
+}
+
+fn endpoint_key(p: &Vector3<f64>) -> (i64, i64) {
+    (
+        (p.x / ENDPOINT_EPSILON).round() as i64,
+        (p.y / ENDPOINT_EPSILON).round() as i64,
+    )
+}
+
+/// Endpoints used by an odd number of segments. Every point of a closed contour
+/// joins exactly two segments, so these are where contours stay open.
+fn open_ends(segments: &[Segment]) -> Vec<Vector2<f64>> {
+    let mut degree: HashMap<(i64, i64), (usize, Vector2<f64>)> = HashMap::new();
+    for ((start, end), _) in segments {
+        for point in [start, end] {
+            degree
+                .entry(endpoint_key(point))
+                .or_insert((0, point.xy()))
+                .0 += 1;
+        }
+    }
+    let mut ends: Vec<Vector2<f64>> = degree
+        .into_values()
+        .filter(|(count, _)| count % 2 == 1)
+        .map(|(_, point)| point)
+        .collect();
+    ends.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    ends
+}
+
+/// Points where two non-adjacent edges of a closed polygon cross
+fn self_intersections(polygon: &[Vector3<f64>]) -> Vec<Vector2<f64>> {
+    let n = polygon.len();
+    let mut crossings = Vec::new();
+    if n < 4 {
+        return crossings;
+    }
+    let edge = |i: usize| (polygon[i].xy(), polygon[(i + 1) % n].xy());
+    for i in 0..n {
+        let (a, b) = edge(i);
+        // Skip the neighbouring edges, which always share an endpoint with this one
+        for j in i + 2..n {
+            if i == 0 && j == n - 1 {
+                continue;
+            }
+            let (c, d) = edge(j);
+            if let Some(point) = segment_intersection(a, b, c, d) {
+                crossings.push(point);
+            }
+        }
+    }
+    crossings
+}
+
+fn cross(a: Vector2<f64>, b: Vector2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Intersection of the segments ab and cd if they properly cross
+fn segment_intersection(
+    a: Vector2<f64>,
+    b: Vector2<f64>,
+    c: Vector2<f64>,
+    d: Vector2<f64>,
+) -> Option<Vector2<f64>> {
+    let r = b - a;
+    let s = d - c;
+    let denominator = cross(r, s);
+    if denominator.abs() < f64::EPSILON {
+        return None;
+    }
+    let t = cross(c - a, s) / denominator;
+    let u = cross(c - a, r) / denominator;
+    let inside = |v: f64| v > 1e-9 && v < 1.0 - 1e-9;
+    (inside(t) && inside(u)).then(|| a + r * t)
+}
+
+fn write_svg(path: &Path, report: &LayerReport, segments: &[Segment]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, render_svg(report, segments))
+}
+
+/// Draws the segments of a layer with every endpoint as a small dot, open ends as red
+/// rings and self-intersections as orange rings. Y points up like in the model.
+fn render_svg(report: &LayerReport, segments: &[Segment]) -> String {
+    let points = segments
+        .iter()
+        .flat_map(|((start, end), _)| [start.xy(), end.xy()]);
+    let (mut min, mut max) = (
+        Vector2::repeat(f64::INFINITY),
+        Vector2::repeat(f64::NEG_INFINITY),
+    );
+    for point in points {
+        min = min.inf(&point);
+        max = max.sup(&point);
+    }
+    if min.x > max.x {
+        (min, max) = (Vector2::zeros(), Vector2::repeat(1.0));
+    }
+    let size = (max - min).max().max(1e-3);
+    let margin = size * 0.05;
+    let marker = size * 0.01;
+    let stroke = size * 0.002;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        min.x - margin,
+        -max.y - margin,
+        max.x - min.x + 2.0 * margin,
+        max.y - min.y + 2.0 * margin
+    );
+    let _ = writeln!(
+        svg,
+        r#"<title>{}</title><g transform="scale(1,-1)" fill="none">"#,
+        report.summary()
+    );
+    for ((start, end), _) in segments {
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="{}"/>"#,
+            start.x, start.y, end.x, end.y, stroke
+        );
+        for point in [start, end] {
+            let _ = writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" fill="gray"/>"#,
+                point.x,
+                point.y,
+                stroke * 1.5
+            );
+        }
+    }
+    for (points, color) in [
+        (&report.open_ends, "red"),
+        (&report.self_intersections, "orange"),
+    ] {
+        for point in points {
+            let _ = writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" stroke="{}" stroke-width="{}"/>"#,
+                point.x,
+                point.y,
+                marker,
+                color,
+                stroke * 2.0
+            );
+        }
+    }
+    let _ = writeln!(svg, "</g>");
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" font-size="{}">{}</text>"#,
+        min.x - margin * 0.8,
+        -max.y - margin * 0.3,
+        margin * 0.4,
+        report.summary()
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn segment(a: (f64, f64), b: (f64, f64)) -> Segment {
+        (
+            (Vector3::new(a.0, a.1, 0.0), Vector3::new(b.0, b.1, 0.0)),
+            [0.0, 0.0, 1.0],
+        )
+    }
+
+    #[test]
+    fn test_open_ends() {
+        // A closed triangle and a chain of two segments that never closes
+        let segments = vec![
+            segment((0.0, 0.0), (1.0, 0.0)),
+            segment((1.0, 0.0), (0.0, 1.0)),
+            segment((0.0, 1.0), (0.0, 0.0)),
+            segment((5.0, 5.0), (6.0, 5.0)),
+            segment((6.0, 5.0), (6.0, 6.0)),
+        ];
+        assert_eq!(
+            open_ends(&segments),
+            vec![Vector2::new(5.0, 5.0), Vector2::new(6.0, 6.0)]
+        );
+    }
+
+    #[test]
+    fn test_self_intersections() {
+        let square: Vec<Vector3<f64>> = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]
+            .iter()
+            .map(|&(x, y)| Vector3::new(x, y, 0.0))
+            .collect();
+        assert!(self_intersections(&square).is_empty());
+
+        // The same points in bow tie order cross in the middle
+        let bow_tie = vec![square[0], square[1], square[3], square[2]];
+        assert_eq!(self_intersections(&bow_tie), vec![Vector2::new(1.0, 1.0)]);
+    }
+
+    #[test]
+    fn test_inspect_layer_writes_svg_for_problem_layers() {
+        let dir = tempdir().unwrap();
+        let debugger = SliceDebugger::new();
+        *debugger.output_dir.lock().unwrap() = Some(dir.path().to_path_buf());
+
+        let closed = vec![
+            segment((0.0, 0.0), (1.0, 0.0)),
+            segment((1.0, 0.0), (0.0, 1.0)),
+            segment((0.0, 1.0), (0.0, 0.0)),
+        ];
+        debugger.inspect_layer(0, 0.1, &closed, &[]);
+        assert!(debugger.report_near(0.1, 0.05).is_none());
+
+        debugger.inspect_layer(1, 0.2, &closed[..2], &[]);
+        let report = debugger.report_near(0.22, 0.05).unwrap();
+        assert_eq!(report.index, 1);
+        assert_eq!(report.open_ends.len(), 2);
+        let svg = fs::read_to_string(report.svg_path.unwrap()).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches(r#"stroke="red""#).count(), 2);
+    }
+}
@@ -0,0 +1,292 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::layer_components::CURED_THRESHOLD;
+use image::{ImageBuffer, Luma, Rgb, RgbImage};
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+#[derive(Error, Debug)]
+pub enum SliceDiffError {
+    #[error("Could not read the slice folder: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Could not read a slice image: {0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("No slice images in {}", .0.display())]
+    Empty(PathBuf),
+
+    #[error("The jobs were sliced for different LCDs, {0}x{1} and {2}x{3} pixels")]
+    SizeMismatch(u32, u32, u32, u32),
+}
+
+/// How one layer differs between two jobs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerDiff {
+    /// Pixels with any difference in brightness, anti-aliased edges included
+    pub changed_pixels: u32,
+    /// Pixels cured only in the first job
+    pub only_first: u32,
+    /// Pixels cured only in the second job
+    pub only_second: u32,
+    pub max_difference: u8,
+}
+
+impl LayerDiff {
+    fn of(first: Option<&Layer>, second: Option<&Layer>, pixel_count: usize) -> Self {
+        let mut diff = Self::default();
+        for i in 0..pixel_count {
+            let (a, b) = (value(first, i), value(second, i));
+            if a == b {
+                continue;
+            }
+            diff.changed_pixels += 1;
+            diff.max_difference = diff.max_difference.max(a.abs_diff(b));
+            match (a >= CURED_THRESHOLD, b >= CURED_THRESHOLD) {
+                (true, false) => diff.only_first += 1,
+                (false, true) => diff.only_second += 1,
+                _ => {}
+            }
+        }
+        diff
+    }
+
+    pub fn describe(&self) -> String {
+        if self.changed_pixels == 0 {
+            return "Identical".to_string();
+        }
+        format!(
+            "{} pixels changed by up to {}, {} cured only before, {} only after",
+            self.changed_pixels, self.max_difference, self.only_first, self.only_second
+        )
+    }
+}
+
+// A pixel of a layer, empty past the end of the job
+fn value(layer: Option<&Layer>, i: usize) -> u8 {
+    layer.map_or(0, |layer| layer.as_raw()[i])
+}
+
+/// Two slice stacks layer by layer, e.g. before and after changing anti-aliasing or
+/// compensation. A job with fewer layers is compared as empty past its last one.
+pub struct SliceComparison {
+    pub first_dir: PathBuf,
+    pub second_dir: PathBuf,
+    first: Vec<Layer>,
+    second: Vec<Layer>,
+    pub layers: Vec<LayerDiff>,
+}
+
+impl SliceComparison {
+    /// Compares the slices exported to two folders
+    pub fn load(first_dir: &Path, second_dir: &Path) -> Result<Self, SliceDiffError> {
+        let first = load_slice_stack(first_dir)?;
+        let second = load_slice_stack(second_dir)?;
+        Self::new(first_dir.into(), first, second_dir.into(), second)
+    }
+
+    pub fn new(
+        first_dir: PathBuf,
+        first: Vec<Layer>,
+        second_dir: PathBuf,
+        second: Vec<Layer>,
+    ) -> Result<Self, SliceDiffError> {
+        let mut sizes = first.iter().chain(&second).map(|layer| layer.dimensions());
+        let (width, height) = sizes.next().unwrap_or_default();
+        if let Some((other_width, other_height)) = sizes.find(|&size| size != (width, height)) {
+            return Err(SliceDiffError::SizeMismatch(
+                width,
+                height,
+                other_width,
+                other_height,
+            ));
+        }
+        let pixel_count = (width * height) as usize;
+        let layers = (0..first.len().max(second.len()))
+            .into_par_iter()
+            .map(|i| LayerDiff::of(first.get(i), second.get(i), pixel_count))
+            .collect();
+        Ok(Self {
+            first_dir,
+            second_dir,
+            first,
+            second,
+            layers,
+        })
+    }
+
+    /// The first layer after `layer` that differs, wrapping around to the start
+    pub fn next_difference(&self, layer: usize) -> Option<usize> {
+        let count = self.layers.len();
+        (1..=count)
+            .map(|step| (layer + step) % count)
+            .find(|&i| self.layers[i].changed_pixels > 0)
+    }
+
+    /// A layer scaled down to `width`, with what only the first job cures in red, what
+    /// only the second cures in green and what both cure in grey. Anti-aliasing changes
+    /// show dimmer the smaller they are.
+    pub fn heat_map(&self, layer: usize, width: u32) -> RgbImage {
+        let (first, second) = (self.first.get(layer), self.second.get(layer));
+        let (lcd_width, lcd_height) = first.or(second).map_or((1, 1), |l| l.dimensions());
+        let width = width.clamp(1, lcd_width);
+        let height = ((lcd_height as u64 * width as u64) / lcd_width as u64).max(1) as u32;
+
+        // Each pixel of the map shows the largest differences of the LCD pixels it covers
+        let mut heat_map = RgbImage::new(width, height);
+        for y in 0..lcd_height {
+            let map_y = (y as u64 * height as u64 / lcd_height as u64) as u32;
+            for x in 0..lcd_width {
+                let map_x = (x as u64 * width as u64 / lcd_width as u64) as u32;
+                let i = (y * lcd_width + x) as usize;
+                let (a, b) = (value(first, i), value(second, i));
+                let both = a.min(b) / 4;
+                let pixel = heat_map.get_pixel_mut(map_x, map_y);
+                *pixel = Rgb([
+                    pixel[0].max(both).max(a.saturating_sub(b)),
+                    pixel[1].max(both).max(b.saturating_sub(a)),
+                    pixel[2].max(both),
+                ]);
+            }
+        }
+        heat_map
+    }
+
+    pub fn summary(&self) -> String {
+        let differing = self.layers.iter().filter(|l| l.changed_pixels > 0).count();
+        let changed: u64 = self.layers.iter().map(|l| l.changed_pixels as u64).sum();
+        let mut summary = format!(
+            "{} of {} layers differ, {} pixels in total",
+            differing,
+            self.layers.len(),
+            changed
+        );
+        if self.first.len() != self.second.len() {
+            summary.push_str(&format!(
+                ". The jobs have {} and {} layers",
+                self.first.len(),
+                self.second.len()
+            ));
+        }
+        summary
+    }
+}
+
+/// The layers of a job exported as a folder of WebP slices, in order
+pub fn load_slice_stack(dir: &Path) -> Result<Vec<Layer>, SliceDiffError> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with("slice_") && name.ends_with(".webp")
+        })
+        .collect();
+    if paths.is_empty() {
+        return Err(SliceDiffError::Empty(dir.into()));
+    }
+    // The layer numbers are zero padded, so names sort in layer order
+    paths.sort();
+    paths
+        .par_iter()
+        .map(|path| Ok(image::open(path)?.to_luma8()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_manager::file_manager::{encode_webp, slice_file_name};
+    use crate::settings::CompressionSettings;
+    use tempfile::tempdir;
+
+    fn layer(values: &[u8]) -> Layer {
+        ImageBuffer::from_raw(values.len() as u32, 1, values.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_compare() {
+        let first = vec![layer(&[255, 255, 0, 0]), layer(&[255, 0, 0, 0])];
+        let second = vec![
+            layer(&[255, 200, 255, 0]),
+            layer(&[0, 0, 0, 0]),
+            layer(&[0, 0, 0, 255]),
+        ];
+        let comparison =
+            SliceComparison::new("before".into(), first, "after".into(), second).unwrap();
+
+        assert_eq!(
+            comparison.layers,
+            vec![
+                LayerDiff {
+                    changed_pixels: 2,
+                    only_first: 0,
+                    only_second: 1,
+                    max_difference: 255,
+                },
+                LayerDiff {
+                    changed_pixels: 1,
+                    only_first: 1,
+                    only_second: 0,
+                    max_difference: 255,
+                },
+                LayerDiff {
+                    changed_pixels: 1,
+                    only_first: 0,
+                    only_second: 1,
+                    max_difference: 255,
+                },
+            ]
+        );
+        assert_eq!(comparison.next_difference(0), Some(1));
+        assert_eq!(comparison.next_difference(2), Some(0));
+        assert_eq!(
+            comparison.summary(),
+            "3 of 3 layers differ, 4 pixels in total. The jobs have 2 and 3 layers"
+        );
+
+        let heat_map = comparison.heat_map(0, 4);
+        assert_eq!(heat_map.dimensions(), (4, 1));
+        assert_eq!(*heat_map.get_pixel(0, 0), Rgb([63, 63, 63]));
+        assert_eq!(*heat_map.get_pixel(1, 0), Rgb([55, 50, 50]));
+        assert_eq!(*heat_map.get_pixel(2, 0), Rgb([0, 255, 0]));
+        assert_eq!(*heat_map.get_pixel(3, 0), Rgb([0, 0, 0]));
+        // Scaled down, the strongest difference of the covered pixels shows
+        assert_eq!(*comparison.heat_map(1, 2).get_pixel(0, 0), Rgb([255, 0, 0]));
+
+        let other_lcd = vec![layer(&[0, 0])];
+        assert!(matches!(
+            SliceComparison::new("a".into(), other_lcd, "b".into(), vec![layer(&[0; 4])]),
+            Err(SliceDiffError::SizeMismatch(2, 1, 4, 1))
+        ));
+    }
+
+    #[test]
+    fn test_load_slice_stack() {
+        let dir = tempdir().unwrap();
+        for (i, value) in [10, 20].into_iter().enumerate().rev() {
+            let bytes = encode_webp(
+                &ImageBuffer::from_pixel(3, 2, Luma([value])),
+                &CompressionSettings::default(),
+            );
+            fs::write(dir.path().join(slice_file_name(i)), bytes).unwrap();
+        }
+        fs::write(dir.path().join("preview_224x168.rgb565"), [0; 4]).unwrap();
+
+        let layers = load_slice_stack(dir.path()).unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[1].dimensions(), (3, 2));
+        assert_eq!(layers[0].get_pixel(1, 1).0, [10]);
+        assert_eq!(layers[1].get_pixel(1, 1).0, [20]);
+
+        let empty = tempdir().unwrap();
+        assert!(matches!(
+            load_slice_stack(empty.path()),
+            Err(SliceDiffError::Empty(_))
+        ));
+    }
+}
@@ -0,0 +1,682 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::bleed_compensation::BleedCompensation;
+use crate::body::Body;
+use crate::cpu_slicer::CPUSlicer;
+use crate::geometry_analysis::{adaptive_layer_heights, GeometryAnalysis};
+use crate::layer_overrides::{self, LayerOverride};
+use crate::layer_thickness::{LayerHeightRange, LayerThickness};
+use crate::memory_budget;
+use crate::motion_profile::LayerTiming;
+use crate::printer::Printer;
+use crate::resin::Resin;
+use crate::settings::{write_atomically, SettingsError};
+use dirs_next::config_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Curvature (1/mm) at which adaptive layers are half the layer thickness
+const ADAPTIVE_LAYER_SENSITIVITY: f32 = 1.0;
+/// Adaptive layer thicknesses are rounded to this, millimeters
+const ADAPTIVE_LAYER_STEP: f64 = 0.005;
+
+/// Something to do at a layer of the job, e.g. pausing to embed magnets or inserts
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum LayerScript {
+    /// Stops with the plate raised before the layer is exposed, until resumed on the printer
+    Pause { layer: usize, message: String },
+    /// Passed to the printer as is by formats that support custom commands
+    Command { layer: usize, command: String },
+}
+
+impl LayerScript {
+    pub fn layer(&self) -> usize {
+        match self {
+            LayerScript::Pause { layer, .. } | LayerScript::Command { layer, .. } => *layer,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            LayerScript::Pause { layer, message } => format!("Layer {}: pause, {}", layer, message),
+            LayerScript::Command { layer, command } => format!("Layer {}: {}", layer, command),
+        }
+    }
+}
+
+/// Times the resolution of the LCD each layer is filled at before it is scaled down to it,
+/// e.g. `supersampling = "3x"`. Each pixel gets the share of its samples inside the layer
+/// as its gray level, which keeps corners sharper than blurring the edges afterwards.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum Supersampling {
+    #[default]
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "2x")]
+    X2,
+    #[serde(rename = "3x")]
+    X3,
+    #[serde(rename = "4x")]
+    X4,
+}
+
+impl Supersampling {
+    /// In the order the parameters panel lists them
+    pub const ALL: [Supersampling; 4] = [
+        Supersampling::Off,
+        Supersampling::X2,
+        Supersampling::X3,
+        Supersampling::X4,
+    ];
+
+    pub fn is_off(&self) -> bool {
+        *self == Supersampling::Off
+    }
+
+    /// Samples per pixel along each axis
+    pub fn factor(&self) -> u32 {
+        match self {
+            Supersampling::Off => 1,
+            Supersampling::X2 => 2,
+            Supersampling::X3 => 3,
+            Supersampling::X4 => 4,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Supersampling::Off => "Off",
+            Supersampling::X2 => "2x",
+            Supersampling::X3 => "3x",
+            Supersampling::X4 => "4x",
+        }
+    }
+
+    /// Position in `ALL`
+    pub fn from_index(index: i32) -> Self {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| Self::ALL.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn index(self) -> i32 {
+        Self::ALL
+            .iter()
+            .position(|&factor| factor == self)
+            .unwrap_or(0) as i32
+    }
+}
+
+/// Everything that configures a slicing job
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SliceParameters {
+    pub slice_thickness: f64, // millimeters
+    pub printer: Printer,
+    pub resin: Resin,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layer_scripts: Vec<LayerScript>,
+    /// Fills the layers at a multiple of the resolution of the LCD, in place of or along
+    /// with the anti-aliasing of the printer
+    #[serde(default, skip_serializing_if = "Supersampling::is_off")]
+    pub supersampling: Supersampling,
+    /// Bands of the print sliced at their own thickness instead of `slice_thickness`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layer_height_ranges: Vec<LayerHeightRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layer_overrides: Vec<LayerOverride>,
+}
+
+impl Default for SliceParameters {
+    fn default() -> Self {
+        Self {
+            slice_thickness: 0.10,
+            printer: Printer::default(),
+            resin: Resin::default(),
+            layer_scripts: Vec::new(),
+            supersampling: Supersampling::Off,
+            layer_height_ranges: Vec::new(),
+            layer_overrides: Vec::new(),
+        }
+    }
+}
+
+/// Quick numbers about a job, computed without slicing
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunStats {
+    pub layer_count: usize,
+    pub memory_bytes: u64,
+}
+
+impl DryRunStats {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} layers, ~{}",
+            self.layer_count,
+            memory_budget::format_bytes(self.memory_bytes)
+        )
+    }
+}
+
+impl SliceParameters {
+    pub fn layer_thickness(&self) -> LayerThickness {
+        LayerThickness {
+            base: self.slice_thickness,
+            ranges: self.layer_height_ranges.clone(),
+        }
+    }
+
+    /// The bleed compensation of the resin, or else of the printer
+    pub fn bleed_compensation(&self) -> Option<&BleedCompensation> {
+        self.resin
+            .bleed_compensation
+            .as_ref()
+            .or(self.printer.bleed_compensation.as_ref())
+    }
+
+    /// The layer sliced closest to `height`, None above or below the bodies
+    pub fn layer_at_height<'a>(
+        &self,
+        bodies: impl IntoIterator<Item = &'a Body>,
+        height: f64,
+    ) -> Option<usize> {
+        let thickness = self.layer_thickness();
+        let heights =
+            CPUSlicer::layer_heights(bodies, &thickness, self.resin.shrinkage_compensation());
+        let (first, last) = (*heights.first()?, *heights.last()?);
+        if height < first - thickness.at(0.0) / 2.0
+            || height > last + thickness.at(last - first) / 2.0
+        {
+            return None;
+        }
+        (0..heights.len()).min_by(|&a, &b| {
+            (heights[a] - height)
+                .abs()
+                .total_cmp(&(heights[b] - height).abs())
+        })
+    }
+
+    pub fn scripts_at(&self, layer: usize) -> impl Iterator<Item = &LayerScript> {
+        self.layer_scripts
+            .iter()
+            .filter(move |script| script.layer() == layer)
+    }
+
+    /// Adds a pause before `layer`, unless there already is one
+    pub fn add_pause(&mut self, layer: usize, message: &str) {
+        if self
+            .scripts_at(layer)
+            .any(|script| matches!(script, LayerScript::Pause { .. }))
+        {
+            return;
+        }
+        self.layer_scripts.push(LayerScript::Pause {
+            layer,
+            message: message.to_string(),
+        });
+        self.layer_scripts.sort_by_key(LayerScript::layer);
+    }
+
+    /// Slices the millimeter below and above `height` at half the layer thickness, for
+    /// fine details there. None when `height` is above or below the bodies.
+    pub fn add_fine_layers<'a>(
+        &mut self,
+        bodies: impl IntoIterator<Item = &'a Body>,
+        height: f64,
+    ) -> Option<LayerHeightRange> {
+        let bodies: Vec<&Body> = bodies.into_iter().collect();
+        let layer = self.layer_at_height(bodies.iter().copied(), height)?;
+        let heights = CPUSlicer::layer_heights(
+            bodies,
+            &self.layer_thickness(),
+            self.resin.shrinkage_compensation(),
+        );
+        let above_bottom = heights[layer] - heights[0];
+        let range = LayerHeightRange {
+            from: (above_bottom - 1.0).max(0.0),
+            to: above_bottom + 1.0,
+            thickness: self.slice_thickness / 2.0,
+            exposure_time: None,
+        };
+        self.layer_height_ranges.push(range.clone());
+        Some(range)
+    }
+
+    /// Replaces the layer height ranges with ones that follow the curvature of the bodies:
+    /// the layer thickness where they are flat, down to a quarter of it where they bend.
+    /// Returns how many ranges there are now.
+    pub fn adapt_layer_heights<'a>(&mut self, bodies: impl IntoIterator<Item = &'a Body>) -> usize {
+        let band = self.slice_thickness as f32;
+        let profiles: Vec<(f32, Vec<f32>)> = bodies
+            .into_iter()
+            .map(|body| {
+                GeometryAnalysis::analyze(&body.mesh).z_curvature_profile(
+                    &body.mesh,
+                    &body.get_model_matrix(),
+                    band,
+                )
+            })
+            .filter(|(_, profile)| !profile.is_empty())
+            .collect();
+        self.layer_height_ranges.clear();
+        let Some(bottom) = profiles.iter().map(|(min_z, _)| *min_z).reduce(f32::min) else {
+            return 0;
+        };
+
+        // The most curved band of any body at each height above the bottom
+        let mut profile = Vec::new();
+        for (min_z, bands) in &profiles {
+            let offset = ((min_z - bottom) / band).round() as usize;
+            if profile.len() < offset + bands.len() {
+                profile.resize(offset + bands.len(), 0.0f32);
+            }
+            for (index, curvature) in bands.iter().enumerate() {
+                profile[offset + index] = profile[offset + index].max(*curvature);
+            }
+        }
+        let tops = adaptive_layer_heights(
+            0.0,
+            &profile,
+            band,
+            band / 4.0,
+            band,
+            ADAPTIVE_LAYER_SENSITIVITY,
+        );
+
+        // Runs of layers of the same thickness, rounded to what printers can move, make
+        // the ranges. The layers of the full thickness need none.
+        let mut layer_bottom = 0.0;
+        for top in tops {
+            let top = top as f64;
+            let thickness =
+                ((top - layer_bottom) / ADAPTIVE_LAYER_STEP).round() * ADAPTIVE_LAYER_STEP;
+            if thickness < self.slice_thickness - ADAPTIVE_LAYER_STEP / 2.0 {
+                match self.layer_height_ranges.last_mut() {
+                    Some(range) if range.thickness == thickness && range.to == layer_bottom => {
+                        range.to = top
+                    }
+                    _ => self.layer_height_ranges.push(LayerHeightRange {
+                        from: layer_bottom,
+                        to: top,
+                        thickness,
+                        exposure_time: None,
+                    }),
+                }
+            }
+            layer_bottom = top;
+        }
+        self.layer_height_ranges.len()
+    }
+
+    /// The time of every layer, corrected to the print times measured on the printer
+    pub fn timeline(&self, layer_count: usize) -> Vec<LayerTiming> {
+        let mut timeline: Vec<LayerTiming> = layer_overrides::plan(
+            &self.layer_thickness(),
+            &self.layer_overrides,
+            &self.resin.motion,
+            layer_count,
+        )
+        .iter()
+        .map(|layer| layer.timing)
+        .collect();
+        self.printer.time_calibration.apply(&mut timeline);
+        timeline
+    }
+
+    /// Seconds the print of `layer_count` layers takes, see `timeline`
+    pub fn print_time(&self, layer_count: usize) -> f64 {
+        self.timeline(layer_count)
+            .iter()
+            .map(LayerTiming::total)
+            .sum()
+    }
+
+    /// job.toml of an exported job: the estimated print time in whole seconds, followed by
+    /// the parameters it was sliced with
+    pub fn job_file(&self, layer_count: usize) -> String {
+        format!(
+            "estimated_print_time = {}\n\n{}",
+            self.print_time(layer_count).round() as u64,
+            toml::to_string_pretty(self).expect("Slice parameters are always serializable")
+        )
+    }
+
+    pub fn dry_run<'a>(&self, bodies: impl IntoIterator<Item = &'a Body>) -> DryRunStats {
+        let layer_count = CPUSlicer::layer_count(
+            bodies,
+            &self.layer_thickness(),
+            self.resin.shrinkage_compensation(),
+        );
+        DryRunStats {
+            layer_count,
+            memory_bytes: memory_budget::estimate_slice_bytes(
+                layer_count,
+                self.printer.pixel_x,
+                self.printer.pixel_y,
+            ),
+        }
+    }
+}
+
+/// The bodies of a mixed-material plate that print with one snapshot
+#[derive(Clone)]
+pub struct MaterialBatch {
+    pub profile: String,
+    pub parameters: SliceParameters,
+    pub bodies: Vec<Body>,
+}
+
+/// Named copies of the slicing parameters, so different configurations can be
+/// saved and switched between to compare them on the same scene.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ParameterSnapshots {
+    #[serde(default)]
+    pub active: Option<String>,
+    #[serde(default)]
+    pub snapshots: BTreeMap<String, SliceParameters>,
+}
+
+impl ParameterSnapshots {
+    fn user_snapshots_path() -> Result<PathBuf, SettingsError> {
+        let config_dir = config_dir().ok_or(SettingsError::ConfigDirNotFound)?;
+        Ok(config_dir
+            .join("SealSlicer")
+            .join("settings")
+            .join("parameter_snapshots.toml"))
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, SettingsError> {
+        let content = fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), SettingsError> {
+        write_atomically(path, &toml::to_string_pretty(self)?)
+    }
+
+    /// Loads the user's snapshots, or starts with none if there are no saved snapshots yet
+    pub fn load_user_snapshots() -> Self {
+        let path = match Self::user_snapshots_path() {
+            Ok(path) if path.exists() => path,
+            _ => return Self::default(),
+        };
+        Self::load_from_file(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to load parameter snapshots: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn save_user_snapshots(&self) -> Result<(), SettingsError> {
+        self.save_to_file(&Self::user_snapshots_path()?)
+    }
+
+    /// Saves the parameters under `name`, replacing an existing snapshot of that name
+    pub fn save_snapshot(&mut self, name: &str, parameters: &SliceParameters) {
+        self.snapshots.insert(name.to_string(), parameters.clone());
+        self.active = Some(name.to_string());
+    }
+
+    /// Makes a snapshot the active one and returns its parameters
+    pub fn activate(&mut self, name: &str) -> Option<SliceParameters> {
+        let parameters = self.snapshots.get(name)?.clone();
+        self.active = Some(name.to_string());
+        Some(parameters)
+    }
+
+    /// Splits a plate into one batch per snapshot that bodies are assigned to. Bodies
+    /// without a profile are part of every batch, bodies assigned to a snapshot that no
+    /// longer exists are left out.
+    pub fn material_batches(&self, bodies: &[Body]) -> Vec<MaterialBatch> {
+        self.snapshots
+            .iter()
+            .filter(|(name, _)| {
+                bodies
+                    .iter()
+                    .any(|body| body.print_profile.as_ref() == Some(*name))
+            })
+            .map(|(name, parameters)| MaterialBatch {
+                profile: name.clone(),
+                parameters: parameters.clone(),
+                bodies: bodies
+                    .iter()
+                    .filter(|body| {
+                        body.print_profile
+                            .as_ref()
+                            .is_none_or(|profile| profile == name)
+                    })
+                    .cloned()
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Dry-run stats of every snapshot for the same bodies, by snapshot name
+    pub fn compare(&self, bodies: &[Body]) -> Vec<(String, DryRunStats)> {
+        self.snapshots
+            .iter()
+            .map(|(name, parameters)| (name.clone(), parameters.dry_run(bodies)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+    use crate::stl_processor::StlProcessor;
+    use nalgebra::Vector3;
+    use tempfile::tempdir;
+
+    fn test_body() -> Body {
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &StlProcessor::new())
+            .unwrap();
+        Body::new(mesh)
+    }
+
+    #[test]
+    fn test_compare_recomputes_stats_per_snapshot() {
+        let bodies = vec![test_body()];
+        let mut snapshots = ParameterSnapshots::default();
+        let coarse = SliceParameters {
+            slice_thickness: 0.1,
+            ..SliceParameters::default()
+        };
+        let fine = SliceParameters {
+            slice_thickness: 0.05,
+            ..SliceParameters::default()
+        };
+        snapshots.save_snapshot("coarse", &coarse);
+        snapshots.save_snapshot("fine", &fine);
+        assert_eq!(snapshots.active.as_deref(), Some("fine"));
+
+        let comparison = snapshots.compare(&bodies);
+        assert_eq!(comparison.len(), 2);
+        let (_, coarse_stats) = &comparison[0];
+        let (_, fine_stats) = &comparison[1];
+        assert!(coarse_stats.layer_count > 0);
+        assert!(fine_stats.layer_count > coarse_stats.layer_count);
+        assert!(fine_stats.memory_bytes > coarse_stats.memory_bytes);
+    }
+
+    #[test]
+    fn test_material_batches() {
+        let mut snapshots = ParameterSnapshots::default();
+        snapshots.save_snapshot("clear", &SliceParameters::default());
+        snapshots.save_snapshot("grey", &SliceParameters::default());
+        snapshots.save_snapshot("unused", &SliceParameters::default());
+
+        let shared = test_body();
+        let mut clear = test_body();
+        clear.print_profile = Some("clear".to_string());
+        let mut grey = test_body();
+        grey.print_profile = Some("grey".to_string());
+        let mut orphan = test_body();
+        orphan.print_profile = Some("deleted".to_string());
+        let bodies = vec![shared.clone(), clear.clone(), grey.clone(), orphan];
+
+        let batches = snapshots.material_batches(&bodies);
+
+        let profiles: Vec<&str> = batches.iter().map(|b| b.profile.as_str()).collect();
+        assert_eq!(profiles, vec!["clear", "grey"]);
+        let uuids = |batch: &MaterialBatch| -> Vec<_> {
+            batch.bodies.iter().map(|b| b.uuid).collect()
+        };
+        assert_eq!(uuids(&batches[0]), vec![shared.uuid, clear.uuid]);
+        assert_eq!(uuids(&batches[1]), vec![shared.uuid, grey.uuid]);
+        assert!(ParameterSnapshots::default()
+            .material_batches(&bodies)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_layer_scripts() {
+        let bodies = vec![test_body()];
+        let mut parameters = SliceParameters::default();
+        let heights = CPUSlicer::layer_heights(
+            &bodies,
+            &LayerThickness::uniform(0.1),
+            Vector3::new(1.0, 1.0, 1.0),
+        );
+
+        let layer = parameters.layer_at_height(&bodies, heights[10] + 0.02);
+        assert_eq!(layer, Some(10));
+        assert_eq!(parameters.layer_at_height(&bodies, heights[0] - 1.0), None);
+        assert_eq!(parameters.layer_at_height(&bodies, heights[heights.len() - 1] + 1.0), None);
+
+        parameters.add_pause(20, "Insert magnets");
+        parameters.add_pause(20, "Twice");
+        parameters.layer_scripts.push(LayerScript::Command {
+            layer: 20,
+            command: "M6054".to_string(),
+        });
+        parameters.add_pause(5, "Nuts");
+        assert_eq!(parameters.layer_scripts[0].layer(), 5);
+        let at_20: Vec<String> = parameters.scripts_at(20).map(|s| s.describe()).collect();
+        assert_eq!(at_20, vec!["Layer 20: pause, Insert magnets", "Layer 20: M6054"]);
+
+        // The scripts are part of the job parameters written next to every export
+        let content = toml::to_string_pretty(&parameters).unwrap();
+        assert!(content.contains("kind = \"pause\""));
+        let loaded: SliceParameters = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.layer_scripts, parameters.layer_scripts);
+    }
+
+    #[test]
+    fn test_fine_layers() {
+        let bodies = vec![test_body()];
+        let mut parameters = SliceParameters::default();
+        let coarse = parameters.dry_run(&bodies).layer_count;
+        let heights = CPUSlicer::layer_heights(
+            &bodies,
+            &parameters.layer_thickness(),
+            Vector3::new(1.0, 1.0, 1.0),
+        );
+
+        assert!(parameters
+            .add_fine_layers(&bodies, heights[0] - 1.0)
+            .is_none());
+        let range = parameters.add_fine_layers(&bodies, heights[5]).unwrap();
+        assert_eq!(range.thickness, 0.05);
+        assert_eq!(range.from, 0.0);
+        assert!(range.to > heights[heights.len() - 1] - heights[0]);
+        // The range covers the whole body, which takes twice the layers
+        let fine = parameters.dry_run(&bodies).layer_count;
+        assert!((2 * coarse - 2..=2 * coarse).contains(&fine));
+
+        let content = toml::to_string_pretty(&parameters).unwrap();
+        let loaded: SliceParameters = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.layer_height_ranges, parameters.layer_height_ranges);
+    }
+
+    #[test]
+    fn test_adaptive_layers() {
+        let bodies = vec![test_body()];
+        let mut parameters = SliceParameters::default();
+        let coarse = parameters.dry_run(&bodies).layer_count;
+
+        let count = parameters.adapt_layer_heights(&bodies);
+        assert!(count > 0);
+        assert_eq!(parameters.layer_height_ranges.len(), count);
+        for range in &parameters.layer_height_ranges {
+            assert!(range.from < range.to);
+            assert!(range.thickness >= parameters.slice_thickness / 4.0 - 1e-9);
+            assert!(range.thickness < parameters.slice_thickness);
+        }
+        for pair in parameters.layer_height_ranges.windows(2) {
+            assert!(pair[0].to <= pair[1].from);
+        }
+        assert!(parameters.dry_run(&bodies).layer_count > coarse);
+
+        // Nothing to adapt to clears the ranges
+        assert_eq!(parameters.adapt_layer_heights(&[]), 0);
+        assert!(parameters.layer_height_ranges.is_empty());
+    }
+
+    #[test]
+    fn test_overrides_in_timeline() {
+        let parameters = SliceParameters::default();
+        let mut slow = parameters.clone();
+        slow.layer_overrides.push(LayerOverride {
+            first_layer: 10,
+            last_layer: 19,
+            exposure_time: Some(10.0),
+            lift_distance: None,
+            light_off_delay: None,
+        });
+        let timeline = slow.timeline(100);
+        assert_eq!(timeline.len(), 100);
+        assert_eq!(timeline[10].exposure, 10.0);
+        assert_eq!(timeline[20], parameters.timeline(100)[20]);
+
+        let content = toml::to_string_pretty(&slow).unwrap();
+        let loaded: SliceParameters = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.layer_overrides, slow.layer_overrides);
+    }
+
+    #[test]
+    fn test_job_file() {
+        let parameters = SliceParameters::default();
+        let job_file = parameters.job_file(100);
+        let table: toml::Table = toml::from_str(&job_file).unwrap();
+        assert_eq!(
+            table["estimated_print_time"].as_integer(),
+            Some(parameters.print_time(100).round() as i64)
+        );
+        assert!(parameters.print_time(100) > parameters.print_time(50));
+        // Still reads back as the parameters
+        let read: SliceParameters = toml::from_str(&job_file).unwrap();
+        assert_eq!(
+            toml::to_string_pretty(&read).unwrap(),
+            toml::to_string_pretty(&parameters).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_activate_and_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("snapshots.toml");
+        let mut snapshots = ParameterSnapshots::default();
+        snapshots.save_snapshot(
+            "thin",
+            &SliceParameters {
+                slice_thickness: 0.03,
+                ..SliceParameters::default()
+            },
+        );
+        snapshots.save_snapshot("default", &SliceParameters::default());
+        snapshots.save_to_file(&path).unwrap();
+
+        let mut loaded = ParameterSnapshots::load_from_file(&path).unwrap();
+        assert_eq!(loaded.active.as_deref(), Some("default"));
+        assert!(loaded.activate("missing").is_none());
+        let thin = loaded.activate("thin").unwrap();
+        assert_eq!(thin.slice_thickness, 0.03);
+        assert_eq!(loaded.active.as_deref(), Some("thin"));
+    }
+}
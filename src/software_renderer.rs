@@ -0,0 +1,421 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::axis_gizmo::Axis;
+use crate::body::{Body, AABB};
+use crate::camera::Camera;
+use crate::cpu_slicer::CPUSlicer;
+use crate::material::OVERLAPPING_TINT;
+use crate::mesh_renderer::{footprint_outline, MeshRenderer};
+use crate::settings::RendererSettings;
+use crate::viewport::{FrameStats, Viewport};
+use crate::SharedBodies;
+use crate::SharedPrinter;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::draw_line_segment_mut;
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Bodies with more triangles than this are drawn as their bounding box, so the view
+/// stays responsive without a GPU
+const MAX_TRIANGLES_PER_BODY: usize = 50_000;
+
+const BACKGROUND: Rgb<u8> = Rgb([30, 30, 34]);
+const SELECTED_TINT: Vector3<f32> = Vector3::new(1.0, 0.6, 0.2);
+const SLICE_OUTLINE_COLOR: Rgb<u8> = Rgb([255, 200, 0]);
+const OVERLAP_OUTLINE_COLOR: Rgb<u8> = Rgb([255, 40, 30]);
+
+/// CPU fallback for machines without usable OpenGL, e.g. virtual machines. Draws flat
+/// shaded bodies with an orthographic projection of the same camera, which is enough to
+/// arrange and slice the plate.
+pub struct SoftwareRenderer {
+    bodies: SharedBodies,
+    camera: Camera,
+    slice_preview_height: Option<f32>,
+    frame_stats: FrameStats,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: u32, height: u32, bodies: &SharedBodies, printer: &SharedPrinter) -> Self {
+        let p = printer.lock().unwrap();
+        let plate = MeshRenderer::create_plane_body(p.physical_x as f32, p.physical_y as f32);
+        bodies.borrow_mut().push(plate);
+        Self {
+            bodies: Rc::clone(bodies),
+            camera: Camera::new(width as f32 / height as f32),
+            slice_preview_height: None,
+            frame_stats: FrameStats::default(),
+        }
+    }
+
+    /// Draws `bodies` on their own, without the plate, from the angle the view starts at and
+    /// framed to fill a square image
+    pub fn thumbnail(bodies: Vec<Body>, size: u32) -> RgbImage {
+        let mut camera = Camera::new(1.0);
+        let bounds = bodies
+            .iter()
+            .filter_map(|body| body.world_aabb())
+            .reduce(|a, b| AABB {
+                min: a.min.inf(&b.min),
+                max: a.max.sup(&b.max),
+            });
+        if let Some(bounds) = bounds {
+            let center = Point3::from((bounds.min + bounds.max) / 2.0);
+            let radius = ((bounds.max - bounds.min).norm() / 2.0).max(0.01);
+            let direction = (camera.position - camera.target).normalize();
+            // The orthographic view shows as far around the target as the perspective one
+            camera.target = center;
+            camera.position = center + direction * radius / (75.0_f32.to_radians() / 2.0).tan();
+        }
+        let bodies = bodies
+            .into_iter()
+            .map(|mut body| {
+                body.selected = false;
+                Rc::new(RefCell::new(body))
+            })
+            .collect();
+        let mut renderer = Self {
+            bodies: Rc::new(RefCell::new(bodies)),
+            camera,
+            slice_preview_height: None,
+            frame_stats: FrameStats::default(),
+        };
+        renderer.render_image(size, size)
+    }
+
+    // Orthographic projection framing what the perspective camera shows at its target
+    fn view_proj(&self, width: u32, height: u32) -> Matrix4<f32> {
+        let half_height = (self.camera.position - self.camera.target).norm()
+            * (75.0_f32.to_radians() / 2.0).tan();
+        let half_width = half_height * width as f32 / height as f32;
+        let projection = Matrix4::new_orthographic(
+            -half_width,
+            half_width,
+            -half_height,
+            half_height,
+            -1000.0,
+            1000.0,
+        );
+        projection * self.camera.view_matrix()
+    }
+
+    /// Draws the scene into an image whose first row is the top of the view
+    pub fn render_image(&mut self, width: u32, height: u32) -> RgbImage {
+        let mut image = RgbImage::from_pixel(width, height, BACKGROUND);
+        let mut depth = vec![f32::INFINITY; (width * height) as usize];
+        let view_proj = self.view_proj(width, height);
+        let view_direction = self.camera.get_view_direction_vector();
+        // Nothing is kept on the GPU, every body is one pass over its triangles
+        let mut frame_stats = FrameStats::default();
+
+        let bodies = self.bodies.borrow();
+        for body in bodies.iter() {
+            let body = body.borrow();
+            if !body.visible {
+                continue;
+            }
+            frame_stats.draw_calls += 1;
+            let model = body.get_model_matrix();
+            let mut color = body.material.albedo;
+            if body.selected && body.display_in_ui_list {
+                color = color.component_mul(&SELECTED_TINT);
+            }
+            if body.overlapping {
+                color = color.component_mul(&OVERLAPPING_TINT);
+            }
+            for triangle in Self::triangles(&body) {
+                let world = triangle.map(|p| (model * p.to_homogeneous()).xyz());
+                let normal = (world[1] - world[0]).cross(&(world[2] - world[0]));
+                if normal.norm() == 0.0 {
+                    continue;
+                }
+                // Lit from the camera, both sides, so open or flipped meshes still show
+                let light = 0.35 + 0.65 * normal.normalize().dot(&view_direction).abs();
+                let shade = Rgb((color * light * 255.0)
+                    .map(|c| c.clamp(0.0, 255.0) as u8)
+                    .into());
+                let screen = world.map(|p| Self::to_screen(&view_proj, p, width, height));
+                Self::fill_triangle(&mut image, &mut depth, screen, shade);
+                frame_stats.vertices += 3;
+            }
+        }
+        self.frame_stats = frame_stats;
+
+        // Layer outline and overlapping footprints on top of everything, like the bands of
+        // the GL view
+        let mut outlines = Vec::new();
+        if let Some(height_mm) = self.slice_preview_height {
+            let borrowed: Vec<_> = bodies
+                .iter()
+                .map(|body| body.borrow())
+                .filter(|body| body.display_in_ui_list && body.visible)
+                .collect();
+            let contours =
+                CPUSlicer::layer_contours(borrowed.iter().map(|b| &**b), height_mm as f64);
+            outlines.extend(contours.into_iter().map(|c| (c, SLICE_OUTLINE_COLOR)));
+        }
+        outlines.extend(
+            bodies
+                .iter()
+                .filter(|body| body.borrow().overlapping)
+                .filter_map(|body| body.borrow().footprint())
+                .map(|footprint| (footprint_outline(&footprint), OVERLAP_OUTLINE_COLOR)),
+        );
+        for (contour, color) in outlines {
+            for i in 0..contour.len() {
+                let a = Self::to_screen(&view_proj, contour[i].cast(), width, height);
+                let b = Self::to_screen(
+                    &view_proj,
+                    contour[(i + 1) % contour.len()].cast(),
+                    width,
+                    height,
+                );
+                draw_line_segment_mut(&mut image, (a.x, a.y), (b.x, b.y), color);
+            }
+        }
+        image
+    }
+
+    // The mesh in model space, or the 12 triangles of its bounding box when it's too big
+    fn triangles(body: &Body) -> Vec<[Point3<f32>; 3]> {
+        let mesh = &body.mesh;
+        let vertex = |i: u32| Point3::from(mesh.vertices[i as usize].position);
+        if mesh.indices.len() / 3 <= MAX_TRIANGLES_PER_BODY {
+            return mesh
+                .indices
+                .chunks_exact(3)
+                .map(|t| [vertex(t[0]), vertex(t[1]), vertex(t[2])])
+                .collect();
+        }
+        let (min, max) = mesh.vertices.iter().fold(
+            (Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)),
+            |(min, max), v| {
+                let p = Vector3::from(v.position);
+                (min.inf(&p), max.sup(&p))
+            },
+        );
+        let corner = |i: usize| {
+            Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        const FACES: [[usize; 4]; 6] = [
+            [0, 1, 3, 2],
+            [4, 6, 7, 5],
+            [0, 4, 5, 1],
+            [2, 3, 7, 6],
+            [0, 2, 6, 4],
+            [1, 5, 7, 3],
+        ];
+        FACES
+            .iter()
+            .flat_map(|f| {
+                [
+                    [corner(f[0]), corner(f[1]), corner(f[2])],
+                    [corner(f[0]), corner(f[2]), corner(f[3])],
+                ]
+            })
+            .collect()
+    }
+
+    // Pixel coordinates with depth, y growing downwards like the GL texture is shown
+    fn to_screen(
+        view_proj: &Matrix4<f32>,
+        point: Vector3<f32>,
+        width: u32,
+        height: u32,
+    ) -> Vector3<f32> {
+        let clip = view_proj * Vector4::new(point.x, point.y, point.z, 1.0);
+        Vector3::new(
+            (clip.x + 1.0) / 2.0 * width as f32,
+            (clip.y + 1.0) / 2.0 * height as f32,
+            clip.z,
+        )
+    }
+
+    fn fill_triangle(
+        image: &mut RgbImage,
+        depth: &mut [f32],
+        [a, b, c]: [Vector3<f32>; 3],
+        color: Rgb<u8>,
+    ) {
+        let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        if area.abs() < 1e-6 {
+            return;
+        }
+        let (width, height) = image.dimensions();
+        let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as u32;
+        let max_x = a.x.max(b.x).max(c.x).ceil().min(width as f32 - 1.0);
+        let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as u32;
+        let max_y = a.y.max(b.y).max(c.y).ceil().min(height as f32 - 1.0);
+        if max_x < 0.0 || max_y < 0.0 {
+            return;
+        }
+        for y in min_y..=max_y as u32 {
+            for x in min_x..=max_x as u32 {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = ((b.x - px) * (c.y - py) - (b.y - py) * (c.x - px)) / area;
+                let w1 = ((c.x - px) * (a.y - py) - (c.y - py) * (a.x - px)) / area;
+                let w2 = 1.0 - w0 - w1;
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+                let z = w0 * a.z + w1 * b.z + w2 * c.z;
+                let index = (y * width + x) as usize;
+                if z < depth[index] {
+                    depth[index] = z;
+                    image.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+}
+
+impl Viewport for SoftwareRenderer {
+    fn render(&mut self, width: u32, height: u32, _settings: &RendererSettings) -> slint::Image {
+        let image = self.render_image(width.max(1), height.max(1));
+        slint::Image::from_rgb8(slint::SharedPixelBuffer::clone_from_slice(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+        ))
+    }
+
+    fn camera_pitch_yaw(&mut self, delta_x: f32, delta_y: f32) {
+        self.camera.pitch_yaw(delta_x, -delta_y);
+    }
+
+    fn camera_pan(&mut self, delta_x: f32, delta_y: f32) {
+        self.camera.pan(delta_x, delta_y);
+    }
+
+    fn zoom(&mut self, amt: f32) {
+        self.camera.zoom(amt);
+    }
+
+    // There is no gizmo in the fallback view
+    fn snap_to_gizmo_axis(&mut self, _x: f32, _y: f32, _width: f32, _height: f32) -> bool {
+        false
+    }
+
+    fn pick_ray(
+        &self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let view_proj = self.view_proj(width.max(1.0) as u32, height.max(1.0) as u32);
+        Camera::pick_ray(&view_proj, x, y, width, height)
+    }
+
+    fn snap_view(&mut self, axis: Axis) {
+        self.camera.snap_to_axis(axis);
+    }
+
+    fn set_slice_preview_height(&mut self, height: Option<f32>) {
+        self.slice_preview_height = height;
+    }
+
+    fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+    use crate::printer::Printer;
+    use crate::stl_processor::StlProcessor;
+    use std::sync::{Arc, Mutex};
+
+    fn renderer(bodies: &SharedBodies) -> SoftwareRenderer {
+        let printer = Arc::new(Mutex::new(Printer::default()));
+        SoftwareRenderer::new(200, 100, bodies, &printer)
+    }
+
+    #[test]
+    fn test_draws_plate_and_selection() {
+        let bodies: SharedBodies = Rc::new(RefCell::new(Vec::new()));
+        let mut renderer = renderer(&bodies);
+        assert_eq!(bodies.borrow().len(), 1);
+        renderer.camera.snap_to_axis(Axis::Z);
+
+        let empty = renderer.render_image(200, 100);
+        // Looking straight down the plate fills the middle of the view
+        assert_ne!(*empty.get_pixel(100, 50), BACKGROUND);
+
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &StlProcessor::new())
+            .unwrap();
+        let body = Rc::new(RefCell::new(Body::new(mesh)));
+        body.borrow_mut().set_position(Vector3::new(0.0, 0.0, 5.0));
+        bodies.borrow_mut().push(Rc::clone(&body));
+        let unselected = {
+            body.borrow_mut().selected = false;
+            renderer.render_image(200, 100)
+        };
+        body.borrow_mut().selected = true;
+        let selected = renderer.render_image(200, 100);
+
+        assert_ne!(unselected, empty);
+        assert_ne!(selected, unselected);
+    }
+
+    #[test]
+    fn test_thumbnail_frames_bodies() {
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &StlProcessor::new())
+            .unwrap();
+        let mut body = Body::new(mesh);
+        // Far from the plate center, where the view starts looking
+        body.set_position(Vector3::new(500.0, -300.0, 40.0));
+
+        let thumbnail = SoftwareRenderer::thumbnail(vec![body], 64);
+        assert_eq!(thumbnail.dimensions(), (64, 64));
+        // The plate with holes covers much of the image, whatever its holes leave out
+        let covered = thumbnail.pixels().filter(|p| **p != BACKGROUND).count();
+        assert!(covered > 64 * 64 / 4);
+        assert_eq!(*thumbnail.get_pixel(0, 0), BACKGROUND);
+        assert_eq!(
+            SoftwareRenderer::thumbnail(Vec::new(), 8),
+            RgbImage::from_pixel(8, 8, BACKGROUND)
+        );
+    }
+
+    #[test]
+    fn test_large_meshes_become_boxes() {
+        let mut mesh = Mesh::default();
+        mesh.import_stl("test_stls/with_holes.stl", &StlProcessor::new())
+            .unwrap();
+        let body = Body::new(mesh);
+        let triangles = SoftwareRenderer::triangles(&body);
+        assert_eq!(triangles.len(), body.mesh.indices.len() / 3);
+
+        let mut big = body.clone();
+        let mut mesh = body.mesh.clone();
+        mesh.indices = body
+            .mesh
+            .indices
+            .iter()
+            .cycle()
+            .take((MAX_TRIANGLES_PER_BODY + 1) * 3)
+            .copied()
+            .collect();
+        big.set_mesh(mesh);
+        let boxed = SoftwareRenderer::triangles(&big);
+        assert_eq!(boxed.len(), 12);
+        // Every corner of the box lies on the bounds of the mesh
+        let max_x = body
+            .mesh
+            .vertices
+            .iter()
+            .map(|v| v.position[0])
+            .fold(f32::MIN, f32::max);
+        assert!(boxed.iter().flatten().any(|p| p.x == max_x));
+    }
+}
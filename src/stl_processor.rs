@@ -2,9 +2,14 @@
 // Distributed under the GNU Affero General Public License v3.0 or later.
 // See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
 
-use std::io::BufReader;
+use std::io::{self, BufReader, Read, Seek};
 use std::{ffi::OsStr, fs::File};
 use stl_io::{self, Triangle};
+
+/// Coordinates past this are garbage or in the wrong units, in millimeters. Slicing a
+/// mesh that big would take more layers than fit in memory.
+pub const MAX_COORDINATE: f32 = 100_000.0;
+
 pub struct StlProcessor;
 
 // Define a trait for processing STL files
@@ -25,8 +30,26 @@ impl StlProcessor {
     // Read the STL file and return the list of triangles
     pub fn read_stl(filename: &OsStr) -> Result<Vec<Triangle>, std::io::Error> {
         let file = File::open(filename)?;
-        let mut reader = BufReader::new(file);
-        let indexed_mesh = stl_io::read_stl(&mut reader)?;
+        Self::read_stl_from(&mut BufReader::new(file))
+    }
+
+    /// Reads ASCII or binary STL from any source. Vertices that aren't finite or lie
+    /// beyond `MAX_COORDINATE` are an error, normals that aren't finite become zero.
+    pub fn read_stl_from<R: Read + Seek>(reader: &mut R) -> Result<Vec<Triangle>, io::Error> {
+        let indexed_mesh = stl_io::read_stl(reader)?;
+        if let Some(vertex) = indexed_mesh
+            .vertices
+            .iter()
+            .find(|v| v.iter().any(|c| c.is_nan() || c.abs() > MAX_COORDINATE))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "vertex {:?} is not a number or further than {} mm out",
+                    vertex, MAX_COORDINATE
+                ),
+            ));
+        }
 
         // Convert IndexedMesh into Vec<Triangle>
         let triangles = indexed_mesh
@@ -38,10 +61,13 @@ impl StlProcessor {
                     indexed_mesh.vertices[face.vertices[1]],
                     indexed_mesh.vertices[face.vertices[2]],
                 ];
-                Triangle {
-                    normal: face.normal,
-                    vertices,
-                }
+                // Some exporters write NaN for the normals of degenerate triangles
+                let normal = if face.normal.iter().all(|c| c.is_finite()) {
+                    face.normal
+                } else {
+                    [0.0; 3]
+                };
+                Triangle { normal, vertices }
             })
             .collect();
 
@@ -377,4 +403,67 @@ mod tests {
             "read_stl should return Err for malformed binary STL files"
         );
     }
+
+    #[test]
+    fn test_read_stl_garbage_values() {
+        let triangle = |vertex: [f32; 3], normal: [f32; 3]| {
+            create_triangle(vertex, [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], normal)
+        };
+        let processor = StlProcessor::new();
+        for vertex in [
+            [f32::NAN, 0.0, 0.0],
+            [0.0, f32::INFINITY, 0.0],
+            [0.0, 0.0, 1e9],
+        ] {
+            let temp_file = write_binary_stl(&[triangle(vertex, [0.0, 0.0, 1.0])]);
+            let error = processor
+                .read_stl(temp_file.path().as_os_str())
+                .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        let temp_file = write_binary_stl(&[triangle([0.0; 3], [f32::NAN; 3])]);
+        let read_triangles = processor.read_stl(temp_file.path().as_os_str()).unwrap();
+        assert_eq!(read_triangles[0].normal, [0.0; 3]);
+    }
+
+    #[test]
+    fn test_read_stl_from_corrupted_bytes() {
+        // The same as the fuzz target, with a few thousand corruptions of valid files
+        let triangles = [create_triangle(
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        )];
+        let files = [write_ascii_stl(&triangles), write_binary_stl(&triangles)]
+            .map(|file| std::fs::read(file.path()).unwrap());
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        const STL_BYTES: &[u8] = b" 0123456789.eE-+\nfacetvertex";
+        for round in 0..4000 {
+            let mut bytes = files[round % 2].clone();
+            for _ in 0..1 + next() % 4 {
+                let i = next() % bytes.len();
+                match next() % 3 {
+                    0 => bytes[i] = next() as u8,
+                    1 => bytes.truncate(i),
+                    _ => bytes.insert(i, STL_BYTES[next() % STL_BYTES.len()]),
+                }
+                if bytes.is_empty() {
+                    break;
+                }
+            }
+            if let Ok(read) = StlProcessor::read_stl_from(&mut std::io::Cursor::new(&bytes)) {
+                let finite = |v: &[f32; 3]| v.iter().all(|c| c.abs() <= MAX_COORDINATE);
+                assert!(read.iter().all(|t| t.vertices.iter().all(finite)));
+                assert!(read.iter().all(|t| t.normal.iter().all(|c| c.is_finite())));
+            }
+        }
+    }
 }
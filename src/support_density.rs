@@ -0,0 +1,151 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::layer_components::{Component, ComponentMap, CURED_THRESHOLD};
+use crate::printer::Printer;
+use crate::resin::ResinStrength;
+use image::{ImageBuffer, Luma};
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+type Layer = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// A part of a layer that hangs over more area than the support contacts under it hold
+/// while it is peeled off the film
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeakOverhang {
+    pub layer: usize,
+    /// The part, a blob of the layer
+    pub blob: Component,
+    /// Area of the part that isn't cured in the layer below, in mm²
+    pub overhang_area: f64,
+    /// Contacts with the layer below, wider ones counted as the tips they match in area
+    pub contacts: u32,
+    pub recommended_contacts: u32,
+}
+
+impl WeakOverhang {
+    pub fn describe(&self) -> String {
+        format!(
+            "layer {} hangs {:.1} mm² on {} support contacts, {} recommended",
+            self.layer, self.overhang_area, self.contacts, self.recommended_contacts
+        )
+    }
+}
+
+/// The parts of the sliced layers with fewer support contacts than the resin's strength
+/// calls for. Parts without any contact are islands, which `LayerAnalysis` finds.
+pub fn weak_overhangs(
+    layers: &[Layer],
+    printer: &Printer,
+    strength: &ResinStrength,
+    tip_diameter: f32,
+) -> Vec<WeakOverhang> {
+    let density = strength.contacts_per_mm2(tip_diameter);
+    let pixel_area =
+        printer.physical_x / printer.pixel_x as f64 * printer.physical_y / printer.pixel_y as f64;
+    let tip_pixels = (PI * (tip_diameter as f64 / 2.0).powi(2) / pixel_area).max(1.0);
+    (1..layers.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let parts = parts_with_contacts(&layers[i - 1], &layers[i], tip_pixels);
+            parts
+                .into_iter()
+                .filter_map(move |(blob, overhang, contacts)| {
+                    let overhang_area = overhang as f64 * pixel_area;
+                    let recommended_contacts = (overhang_area * density).ceil() as u32;
+                    (contacts > 0 && contacts < recommended_contacts).then_some(WeakOverhang {
+                        layer: i,
+                        blob,
+                        overhang_area,
+                        contacts,
+                        recommended_contacts,
+                    })
+                })
+        })
+        .collect()
+}
+
+// Every blob of a layer with its overhanging pixels and its contacts with the layer below
+fn parts_with_contacts(
+    below: &Layer,
+    layer: &Layer,
+    tip_pixels: f64,
+) -> Vec<(Component, u32, u32)> {
+    let (raw, raw_below) = (layer.as_raw(), below.as_raw());
+    let (width, height) = layer.dimensions();
+    let parts = ComponentMap::of_layer(layer);
+    let contacts = ComponentMap::label(width, height, |i| {
+        raw[i] >= CURED_THRESHOLD && raw_below[i] >= CURED_THRESHOLD
+    });
+
+    let mut overhang = vec![0u32; parts.components.len()];
+    // Contacts are cured in this layer too, so each lies within one part
+    let mut owner = vec![0usize; contacts.components.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let Some(part) = parts.index_at(x, y) else {
+                continue;
+            };
+            match contacts.index_at(x, y) {
+                Some(contact) => owner[contact] = part,
+                None => overhang[part] += 1,
+            }
+        }
+    }
+    let mut held = vec![0.0f64; parts.components.len()];
+    for (contact, part) in contacts.components.iter().zip(owner) {
+        held[part] += (contact.pixels as f64 / tip_pixels).max(1.0);
+    }
+
+    parts
+        .components
+        .into_iter()
+        .zip(overhang)
+        .zip(held)
+        .map(|((blob, overhang), held)| (blob, overhang, held.floor() as u32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::layer;
+
+    #[test]
+    fn test_weak_overhangs() {
+        // Square millimeter pixels and tips, needing a contact for every 4 mm² of overhang
+        let printer = Printer {
+            pixel_x: 10,
+            pixel_y: 3,
+            physical_x: 10.0,
+            physical_y: 3.0,
+            ..Printer::default()
+        };
+        let strength = ResinStrength {
+            tensile_strength: 1.0,
+            peel_pressure: 250.0,
+            safety_factor: 1.0,
+        };
+        let tip_diameter = (4.0 / std::f32::consts::PI).sqrt();
+        let layers = [
+            layer(&["#.........", "..........", "#...#....."]),
+            // A bar on a single contact, one on two and an island
+            layer(&["########..", "........##", "#####....."]),
+            // A wide contact holds like several tips
+            layer(&["##########", "..........", ".........."]),
+        ];
+
+        let weak = weak_overhangs(&layers, &printer, &strength, tip_diameter);
+        assert_eq!(weak.len(), 1);
+        assert_eq!(weak[0].layer, 1);
+        assert_eq!(weak[0].blob.min, (0, 0));
+        assert!((weak[0].overhang_area - 7.0).abs() < 1e-9);
+        assert_eq!(weak[0].contacts, 1);
+        assert_eq!(weak[0].recommended_contacts, 2);
+        assert_eq!(
+            weak[0].describe(),
+            "layer 1 hangs 7.0 mm² on 1 support contacts, 2 recommended"
+        );
+    }
+}
@@ -0,0 +1,300 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::action::{Action, AddBodiesAction, CompoundAction, SetPositionAction};
+use crate::body::Body;
+use crate::geometry::transform_triangles;
+use crate::mesh::Mesh;
+use crate::settings::SupportSettings;
+use crate::support_styles::SupportStyle;
+use nalgebra::{Vector2, Vector3};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use stl_io::Triangle;
+
+/// The bottom of a body is the part of it this close to its lowest point, in millimeters.
+/// Pillars hold it up wherever it is.
+const CONTACT_BAND: f32 = 1.0;
+
+/// Pillars reach this far into the body, so they fuse with it instead of just touching it
+const TIP_DEPTH: f32 = 0.2;
+
+/// Where the pillars under a body go, on a grid of `pillar_spacing` across its bottom as it
+/// is placed. Bodies too small to cover a point of the grid get one pillar under their
+/// lowest vertex.
+pub fn contact_points(body: &Body, settings: &SupportSettings) -> Vec<Vector3<f32>> {
+    let triangles = transform_triangles(
+        &body.mesh.get_triangles_for_slicing(),
+        &body.get_model_matrix(),
+    );
+    let Some(lowest) = triangles
+        .iter()
+        .flat_map(|t| t.vertices)
+        .min_by(|a, b| a[2].total_cmp(&b[2]))
+        .map(Vector3::from)
+    else {
+        return Vec::new();
+    };
+    let spacing = settings.pillar_spacing.max(settings.pillar_diameter);
+    // The lowest surface above each point of the grid
+    let mut bottom: HashMap<(i32, i32), f32> = HashMap::new();
+    for triangle in &triangles {
+        let [a, b, c] = triangle.vertices.map(Vector3::from);
+        if a.z.min(b.z).min(c.z) > lowest.z + CONTACT_BAND {
+            continue;
+        }
+        let min = a.xy().inf(&b.xy()).inf(&c.xy()) / spacing;
+        let max = a.xy().sup(&b.xy()).sup(&c.xy()) / spacing;
+        for i in min.x.ceil() as i32..=max.x.floor() as i32 {
+            for j in min.y.ceil() as i32..=max.y.floor() as i32 {
+                let point = Vector2::new(i as f32, j as f32) * spacing;
+                if let Some(z) = height_at(point, [a, b, c]) {
+                    let z_at = bottom.entry((i, j)).or_insert(f32::INFINITY);
+                    *z_at = z_at.min(z);
+                }
+            }
+        }
+    }
+    let mut points: Vec<Vector3<f32>> = bottom
+        .into_iter()
+        .filter(|&(_, z)| z <= lowest.z + CONTACT_BAND)
+        .map(|((i, j), z)| Vector3::new(i as f32 * spacing, j as f32 * spacing, z))
+        .collect();
+    if points.is_empty() {
+        points.push(lowest);
+    }
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points
+}
+
+/// Height of the triangle above `point` of the plate, None when it isn't above it
+fn height_at(point: Vector2<f32>, [a, b, c]: [Vector3<f32>; 3]) -> Option<f32> {
+    let (ab, ac, ap) = (b.xy() - a.xy(), c.xy() - a.xy(), point - a.xy());
+    let area = ab.perp(&ac);
+    if area.abs() < f32::EPSILON {
+        return None;
+    }
+    let u = ap.perp(&ac) / area;
+    let v = ab.perp(&ap) / area;
+    (u >= 0.0 && v >= 0.0 && u + v <= 1.0).then(|| a.z + (b.z - a.z) * u + (c.z - a.z) * v)
+}
+
+/// Pillars from the plate up to each of `points`, wide at the plate and narrowing to the
+/// tip diameter, as one body. With a support style the pillars end under its tip instead,
+/// wherever there is room for it above the plate.
+pub fn pillars(
+    points: &[Vector3<f32>],
+    settings: &SupportSettings,
+    style: Option<&SupportStyle>,
+) -> Body {
+    let triangles: Vec<Triangle> = points
+        .iter()
+        .flat_map(|&point| {
+            let top = point + Vector3::new(0.0, 0.0, TIP_DEPTH);
+            match style {
+                Some(style) if style.height < top.z => {
+                    let mut triangles = pillar(
+                        top - Vector3::new(0.0, 0.0, style.height),
+                        settings.pillar_diameter,
+                        style.base_width,
+                    );
+                    triangles.extend(style.placed_at(top));
+                    triangles
+                }
+                _ => pillar(top, settings.pillar_diameter, settings.tip_diameter),
+            }
+        })
+        .collect();
+    let mut body = Body::new(Mesh::from_triangles(&triangles));
+    body.selected = false;
+    body
+}
+
+/// A square pillar standing on the plate under `top`
+fn pillar(top: Vector3<f32>, base_width: f32, tip_width: f32) -> Vec<Triangle> {
+    let corners = |z: f32, width: f32| {
+        let half = width / 2.0;
+        [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(x, y)| Vector3::new(top.x + x * half, top.y + y * half, z))
+    };
+    let bottom = corners(0.0, base_width);
+    let tip = corners(top.z, tip_width.min(base_width));
+    // Quads counterclockwise seen from outside
+    let mut quads = vec![
+        [bottom[0], bottom[3], bottom[2], bottom[1]],
+        [tip[0], tip[1], tip[2], tip[3]],
+    ];
+    for i in 0..4 {
+        let j = (i + 1) % 4;
+        quads.push([bottom[i], bottom[j], tip[j], tip[i]]);
+    }
+    quads
+        .iter()
+        .flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+        .map(|[a, b, c]| Triangle {
+            normal: (b - a)
+                .cross(&(c - a))
+                .try_normalize(0.0)
+                .unwrap_or_default()
+                .into(),
+            vertices: [a.into(), b.into(), c.into()],
+        })
+        .collect()
+}
+
+/// Raises each body so its lowest point is `base_gap` above the plate and adds pillars under
+/// it, topped with the body's support style out of `styles`, undone as one step
+pub fn raise_on_supports(
+    scene: &Rc<RefCell<Vec<Rc<RefCell<Body>>>>>,
+    bodies: &[Rc<RefCell<Body>>],
+    settings: &SupportSettings,
+    styles: &HashMap<String, SupportStyle>,
+) -> CompoundAction {
+    let mut actions: Vec<Box<dyn Action>> = Vec::new();
+    let mut supports = Vec::new();
+    for body_rc in bodies {
+        let body = body_rc.borrow();
+        let Some(aabb) = body.world_aabb() else {
+            continue;
+        };
+        let lift = Vector3::new(0.0, 0.0, settings.base_gap - aabb.min.z);
+        let points: Vec<Vector3<f32>> = contact_points(&body, settings)
+            .into_iter()
+            .map(|point| point + lift)
+            .collect();
+        let style = body
+            .support_style
+            .as_ref()
+            .and_then(|name| styles.get(name));
+        let mut pillars = pillars(&points, settings, style);
+        pillars.name = format!("Supports of {}", body.name);
+        supports.push(Rc::new(RefCell::new(pillars)));
+        actions.push(Box::new(SetPositionAction {
+            body: Rc::clone(body_rc),
+            input: body.position + lift,
+            previous: body.position,
+        }));
+    }
+    actions.push(Box::new(AddBodiesAction {
+        scene: Rc::clone(scene),
+        bodies: supports,
+    }));
+    CompoundAction { actions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::signed_volume;
+
+    // A closed box on the plate, from `min` to `max` seen from above
+    fn solid_box(min: [f32; 2], max: [f32; 3]) -> Body {
+        let [x0, y0] = min;
+        let [x1, y1, z] = max;
+        let corners = [
+            [x0, y0, 0.0],
+            [x1, y0, 0.0],
+            [x1, y1, 0.0],
+            [x0, y1, 0.0],
+            [x0, y0, z],
+            [x1, y0, z],
+            [x1, y1, z],
+            [x0, y1, z],
+        ];
+        // Quads counterclockwise seen from outside
+        let quads = [
+            [0, 3, 2, 1],
+            [4, 5, 6, 7],
+            [0, 1, 5, 4],
+            [1, 2, 6, 5],
+            [2, 3, 7, 6],
+            [3, 0, 4, 7],
+        ];
+        let triangles: Vec<Triangle> = quads
+            .iter()
+            .flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+            .map(|face: [usize; 3]| {
+                let vertices = face.map(|i| corners[i]);
+                let [p, q, r] = vertices.map(Vector3::from);
+                Triangle {
+                    normal: (q - p).cross(&(r - p)).normalize().into(),
+                    vertices,
+                }
+            })
+            .collect();
+        let mut body = Body::new(Mesh::from_triangles(&triangles));
+        body.name = "Box".to_string();
+        body
+    }
+
+    fn settings() -> SupportSettings {
+        SupportSettings {
+            tip_diameter: 0.4,
+            base_gap: 5.0,
+            pillar_spacing: 3.0,
+            pillar_diameter: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_contact_points() {
+        let body = solid_box([0.5, 0.5], [7.0, 4.0, 2.0]);
+        let points = contact_points(&body, &settings());
+        let xy: Vec<(f32, f32)> = points.iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(xy, [(3.0, 3.0), (6.0, 3.0)]);
+        assert!(points.iter().all(|p| p.z.abs() < 1e-5));
+
+        // Too small for the grid
+        let small = solid_box([0.5, 0.5], [1.0, 1.0, 1.0]);
+        assert_eq!(contact_points(&small, &settings()).len(), 1);
+    }
+
+    #[test]
+    fn test_styled_pillars() {
+        // A 1 mm high tip, 0.6 mm wide at its bottom
+        let style =
+            SupportStyle::from_triangles("Mini", pillar(Vector3::new(0.0, 0.0, 1.0), 0.6, 0.2))
+                .unwrap();
+        let points = [Vector3::new(2.0, 2.0, 4.0), Vector3::new(6.0, 2.0, 0.5)];
+        let body = pillars(&points, &settings(), Some(&style));
+        let triangles = body.mesh.get_triangles_for_slicing();
+        // The low point has no room for the tip and gets a plain pillar
+        assert_eq!(triangles.len(), 12 * 3);
+        let top = triangles
+            .iter()
+            .flat_map(|t| t.vertices)
+            .filter(|v| (v[2] - 4.0 - TIP_DEPTH).abs() < 1e-5)
+            .map(|v| v[0])
+            .fold((f32::MAX, f32::MIN), |(a, b), x| (a.min(x), b.max(x)));
+        assert!((top.0 - 1.9).abs() < 1e-5 && (top.1 - 2.1).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_raise_and_undo() {
+        let body = Rc::new(RefCell::new(solid_box([0.5, 0.5], [7.0, 4.0, 2.0])));
+        let scene = Rc::new(RefCell::new(vec![Rc::clone(&body)]));
+        let mut action =
+            raise_on_supports(&scene, &[Rc::clone(&body)], &settings(), &HashMap::new());
+
+        action.execute();
+        assert!((body.borrow().world_aabb().unwrap().min.z - 5.0).abs() < 1e-5);
+        assert_eq!(scene.borrow().len(), 2);
+        let supports = scene.borrow()[1].borrow().clone();
+        assert_eq!(supports.name, "Supports of Box");
+        let triangles = transform_triangles(
+            &supports.mesh.get_triangles_for_slicing(),
+            &supports.get_model_matrix(),
+        );
+        // Two pillars from the plate into the bottom of the body
+        assert_eq!(triangles.len(), 24);
+        let bounds = crate::geometry::bounding_box(&triangles).unwrap();
+        assert!(bounds.min.z.abs() < 1e-5);
+        assert!((bounds.max.z - 5.0 - TIP_DEPTH).abs() < 1e-5);
+        assert!(signed_volume(&triangles) > 0.0);
+
+        action.undo();
+        assert!(body.borrow().world_aabb().unwrap().min.z.abs() < 1e-5);
+        assert_eq!(scene.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,198 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::geometry::bounding_box;
+use crate::stl_processor::{StlProcessor, StlProcessorTrait};
+use dirs_next::config_dir;
+use nalgebra::Vector3;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use stl_io::Triangle;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SupportStyleError {
+    #[error("Could not read the support style: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("The user configuration directory could not be found")]
+    ConfigDirNotFound,
+
+    #[error("{0} has no triangles to top supports with")]
+    Empty(String),
+}
+
+/// A tip the user modeled to top supports with, like the fine tips miniatures need. The
+/// mesh is moved so its highest point is at the origin, the point that touches the model,
+/// and everything else of it is below.
+#[derive(Debug)]
+pub struct SupportStyle {
+    pub tip: Vec<Triangle>,
+    /// From the point of the tip down to its lowest point, in mm
+    pub height: f32,
+    /// Width of the bottom of the tip, where the pillar under it ends, in mm
+    pub base_width: f32,
+}
+
+impl SupportStyle {
+    pub fn from_triangles(name: &str, triangles: Vec<Triangle>) -> Result<Self, SupportStyleError> {
+        let bounds =
+            bounding_box(&triangles).ok_or_else(|| SupportStyleError::Empty(name.to_string()))?;
+        let offset = Vector3::new(
+            (bounds.min.x + bounds.max.x) / 2.0,
+            (bounds.min.y + bounds.max.y) / 2.0,
+            bounds.max.z,
+        );
+        let tip = triangles
+            .into_iter()
+            .map(|triangle| Triangle {
+                normal: triangle.normal,
+                vertices: triangle
+                    .vertices
+                    .map(|vertex| (Vector3::from(vertex) - offset).into()),
+            })
+            .collect();
+        Ok(Self {
+            tip,
+            height: bounds.max.z - bounds.min.z,
+            base_width: (bounds.max.x - bounds.min.x).min(bounds.max.y - bounds.min.y),
+        })
+    }
+
+    /// The tip with its point at `point`
+    pub fn placed_at(&self, point: Vector3<f32>) -> impl Iterator<Item = Triangle> + '_ {
+        self.tip.iter().map(move |triangle| Triangle {
+            normal: triangle.normal,
+            vertices: triangle
+                .vertices
+                .map(|vertex| (Vector3::from(vertex) + point).into()),
+        })
+    }
+}
+
+/// The STL files of the tips in the support library folder, one style each, named after the
+/// file
+pub struct SupportStyleLibrary {
+    dir: PathBuf,
+}
+
+impl SupportStyleLibrary {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The library kept with the user's settings
+    pub fn user_library() -> Result<Self, SupportStyleError> {
+        let config_dir = config_dir().ok_or(SupportStyleError::ConfigDirNotFound)?;
+        Ok(Self::new(
+            config_dir.join("SealSlicer").join("support_styles"),
+        ))
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.stl", name))
+    }
+
+    /// Names of the styles, sorted. Empty until a tip is imported.
+    pub fn names(&self) -> io::Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names: Vec<String> = fs::read_dir(&self.dir)?
+            .filter_map(|item| item.ok().map(|item| item.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("stl"))
+            })
+            .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        Ok(names)
+    }
+
+    /// Copies the tip mesh of an STL file into the library, replacing a style of the same
+    /// name. Returns the name of the new style.
+    pub fn import(&self, path: &Path) -> Result<String, SupportStyleError> {
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        // Checked before it is copied, so the library only holds usable tips
+        let triangles = StlProcessor::new().read_stl(path.as_os_str())?;
+        SupportStyle::from_triangles(&name, triangles)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::copy(path, self.path(&name))?;
+        Ok(name)
+    }
+
+    pub fn load(&self, name: &str) -> Result<SupportStyle, SupportStyleError> {
+        let triangles = StlProcessor::new().read_stl(self.path(name).as_os_str())?;
+        SupportStyle::from_triangles(name, triangles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // A square pyramid 2 mm high pointing up, somewhere off the origin
+    fn pyramid() -> Vec<Triangle> {
+        let point = [10.0, 20.0, 32.0];
+        let base = [
+            [9.0, 19.0, 30.0],
+            [11.0, 19.0, 30.0],
+            [11.0, 21.0, 30.0],
+            [9.0, 21.0, 30.0],
+        ];
+        let mut triangles: Vec<Triangle> = (0..4)
+            .map(|i| Triangle {
+                normal: [0.0, 0.0, 0.0],
+                vertices: [point, base[(i + 1) % 4], base[i]],
+            })
+            .collect();
+        triangles.push(Triangle {
+            normal: [0.0, 0.0, -1.0],
+            vertices: [base[0], base[1], base[2]],
+        });
+        triangles.push(Triangle {
+            normal: [0.0, 0.0, -1.0],
+            vertices: [base[0], base[2], base[3]],
+        });
+        triangles
+    }
+
+    #[test]
+    fn test_style_is_moved_to_its_point() {
+        let style = SupportStyle::from_triangles("Cone", pyramid()).unwrap();
+        assert_eq!(style.height, 2.0);
+        assert_eq!(style.base_width, 2.0);
+        let placed: Vec<Triangle> = style.placed_at(Vector3::new(1.0, 1.0, 5.0)).collect();
+        let bounds = bounding_box(&placed).unwrap();
+        assert_eq!(bounds.min, Vector3::new(0.0, 0.0, 3.0));
+        assert_eq!(bounds.max, Vector3::new(2.0, 2.0, 5.0));
+
+        assert!(matches!(
+            SupportStyle::from_triangles("Nothing", Vec::new()),
+            Err(SupportStyleError::Empty(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_and_load() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("Mini Tip.stl");
+        let mut writer = fs::File::create(&file).unwrap();
+        stl_io::write_stl(&mut writer, pyramid().iter()).unwrap();
+
+        let library = SupportStyleLibrary::new(dir.path().join("support_styles"));
+        assert!(library.names().unwrap().is_empty());
+        assert_eq!(library.import(&file).unwrap(), "Mini Tip");
+        assert_eq!(library.names().unwrap(), vec!["Mini Tip"]);
+        let style = library.load("Mini Tip").unwrap();
+        assert_eq!(style.tip.len(), 6);
+        assert!(library.load("Missing").is_err());
+    }
+}
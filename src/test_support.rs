@@ -0,0 +1,39 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::Body;
+use crate::infill::cuboid;
+use crate::mesh::Mesh;
+use image::{ImageBuffer, Luma};
+use nalgebra::Vector3;
+use stl_io::Triangle;
+
+/// A layer drawn with '#' for cured pixels and '+' for a shade too dim to cure, like the
+/// holes inside a subtracting body
+pub fn layer(rows: &[&str]) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let width = rows[0].len() as u32;
+    let pixels = rows
+        .iter()
+        .flat_map(|row| {
+            row.bytes().map(|c| match c {
+                b'#' => 255,
+                b'+' => 69,
+                _ => 0,
+            })
+        })
+        .collect();
+    ImageBuffer::from_raw(width, rows.len() as u32, pixels).unwrap()
+}
+
+/// A closed box of the given size standing on the plate, for the tests of the hollowing,
+/// infill and drain holes
+pub fn solid_box(size: [f32; 3]) -> Body {
+    let triangles: Vec<Triangle> = cuboid(Vector3::zeros(), Vector3::from(size))
+        .into_iter()
+        .map(|[p, q, r]| Triangle {
+            normal: (q - p).cross(&(r - p)).normalize().into(),
+            vertices: [p.into(), q.into(), r.into()],
+        })
+        .collect();
+    Body::new(Mesh::from_triangles(&triangles))
+}
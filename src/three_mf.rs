@@ -0,0 +1,405 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::body::Body;
+use crate::mesh::Mesh;
+use nalgebra::{Matrix3, Matrix4, Point3, Rotation3, UnitQuaternion, Vector3};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use stl_io::Triangle;
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Where the model is when the package doesn't say
+const DEFAULT_MODEL_PATH: &str = "3D/3dmodel.model";
+
+/// Components can refer to each other, this deep is taken for a cycle
+const MAX_COMPONENT_DEPTH: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum ThreeMfError {
+    #[error("IO Error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not a 3MF package: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Invalid model XML: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Invalid model: {0}")]
+    Format(String),
+}
+
+/// One item of the build, the way the slicer that wrote the file placed it on its plate
+#[derive(Debug)]
+pub struct BuildItem {
+    pub name: String,
+    /// In millimeters, in the coordinates of the object
+    pub triangles: Vec<Triangle>,
+    /// Where the item is on the plate, Z up like every 3MF
+    pub transform: Matrix4<f32>,
+}
+
+impl BuildItem {
+    /// A body placed the way the item is, with the rotation and scale of its transform
+    pub fn to_body(&self) -> Body {
+        let mut body = Body::new(Mesh::from_triangles(&self.triangles));
+        body.name = self.name.clone();
+        let (position, rotation, scale) = decompose(&self.transform);
+        body.position = position;
+        body.rotation = rotation.into_inner();
+        body.scale = scale;
+        body
+    }
+}
+
+/// The build items of a 3MF file as bodies, in the place they have on the plate of the slicer
+/// that wrote it
+pub fn import(path: &Path) -> Result<Vec<Body>, ThreeMfError> {
+    Ok(read(path)?.iter().map(BuildItem::to_body).collect())
+}
+
+pub fn read(path: &Path) -> Result<Vec<BuildItem>, ThreeMfError> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let model_path = match read_entry(&mut archive, "_rels/.rels") {
+        Ok(rels) => model_path(&rels)?,
+        Err(_) => DEFAULT_MODEL_PATH.to_string(),
+    };
+    let model = read_entry(&mut archive, &model_path)?;
+    let fallback_name = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    parse_model(&model, &fallback_name)
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<String, ThreeMfError> {
+    let mut contents = String::new();
+    archive.by_name(name)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// The part of the package the relationships name as the model
+fn model_path(rels: &str) -> Result<String, ThreeMfError> {
+    let document = roxmltree::Document::parse(rels)?;
+    let target = document
+        .descendants()
+        .filter(|node| node.has_tag_name("Relationship"))
+        .find(|node| {
+            node.attribute("Type")
+                .is_some_and(|kind| kind.ends_with("/3dmodel"))
+        })
+        .and_then(|node| node.attribute("Target"));
+    Ok(target
+        .map(|target| target.trim_start_matches('/').to_string())
+        .unwrap_or_else(|| DEFAULT_MODEL_PATH.to_string()))
+}
+
+/// An object of the model: its own mesh and the objects it is put together from
+#[derive(Default)]
+struct Object {
+    name: Option<String>,
+    triangles: Vec<Triangle>,
+    components: Vec<(String, Matrix4<f32>)>,
+}
+
+/// The build items of the model XML. Items without a name are named after the file.
+pub fn parse_model(xml: &str, fallback_name: &str) -> Result<Vec<BuildItem>, ThreeMfError> {
+    let document = roxmltree::Document::parse(xml)?;
+    let model = document.root_element();
+    let to_mm = unit_scale(model.attribute("unit").unwrap_or("millimeter"))?;
+
+    let mut objects: HashMap<String, Object> = HashMap::new();
+    for node in model.descendants().filter(|n| n.has_tag_name("object")) {
+        let id = required(&node, "id")?.to_string();
+        let mut object = Object {
+            name: node.attribute("name").map(str::to_string),
+            ..Default::default()
+        };
+        for child in node.descendants() {
+            match child.tag_name().name() {
+                "mesh" => object.triangles = parse_mesh(&child, to_mm)?,
+                "component" => object.components.push((
+                    required(&child, "objectid")?.to_string(),
+                    parse_transform(child.attribute("transform"), to_mm)?,
+                )),
+                _ => {}
+            }
+        }
+        objects.insert(id, object);
+    }
+
+    let build = model.children().find(|n| n.has_tag_name("build"));
+    let items = build.iter().flat_map(|build| build.children());
+    let mut build_items = Vec::new();
+    for (i, item) in items.filter(|n| n.has_tag_name("item")).enumerate() {
+        let id = required(&item, "objectid")?;
+        let triangles = flatten(&objects, id, &Matrix4::identity(), 0)?;
+        let name = objects[id]
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("{} {}", fallback_name, i + 1));
+        build_items.push(BuildItem {
+            name,
+            triangles,
+            transform: parse_transform(item.attribute("transform"), to_mm)?,
+        });
+    }
+    Ok(build_items)
+}
+
+/// Millimeters per unit of the model
+fn unit_scale(unit: &str) -> Result<f32, ThreeMfError> {
+    match unit {
+        "micron" => Ok(0.001),
+        "millimeter" => Ok(1.0),
+        "centimeter" => Ok(10.0),
+        "inch" => Ok(25.4),
+        "foot" => Ok(304.8),
+        "meter" => Ok(1000.0),
+        _ => Err(ThreeMfError::Format(format!("unknown unit {}", unit))),
+    }
+}
+
+fn required<'a>(node: &roxmltree::Node<'a, '_>, name: &str) -> Result<&'a str, ThreeMfError> {
+    node.attribute(name).ok_or_else(|| {
+        ThreeMfError::Format(format!("<{}> without {}", node.tag_name().name(), name))
+    })
+}
+
+fn number(node: &roxmltree::Node, name: &str) -> Result<f32, ThreeMfError> {
+    let value = required(node, name)?;
+    value
+        .parse()
+        .map_err(|_| ThreeMfError::Format(format!("{} is not a number: {}", name, value)))
+}
+
+fn parse_mesh(mesh: &roxmltree::Node, to_mm: f32) -> Result<Vec<Triangle>, ThreeMfError> {
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for node in mesh.descendants() {
+        match node.tag_name().name() {
+            "vertex" => vertices.push(
+                Vector3::new(
+                    number(&node, "x")?,
+                    number(&node, "y")?,
+                    number(&node, "z")?,
+                ) * to_mm,
+            ),
+            "triangle" => {
+                let corners = ["v1", "v2", "v3"].map(|name| required(&node, name));
+                let mut indices = [0; 3];
+                for (index, corner) in indices.iter_mut().zip(corners) {
+                    let corner = corner?;
+                    *index = corner
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&i| i < vertices.len())
+                        .ok_or_else(|| {
+                            ThreeMfError::Format(format!("no vertex {} in the mesh", corner))
+                        })?;
+                }
+                triangles.push(triangle(indices.map(|i| vertices[i])));
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+fn triangle(vertices: [Vector3<f32>; 3]) -> Triangle {
+    let [a, b, c] = vertices;
+    let normal = (b - a)
+        .cross(&(c - a))
+        .try_normalize(0.0)
+        .unwrap_or_default();
+    Triangle {
+        normal: normal.into(),
+        vertices: vertices.map(Into::into),
+    }
+}
+
+/// The 3MF transform attribute: twelve numbers, the rows of a 4x3 matrix points are
+/// multiplied with as row vectors. The translation is in model units.
+fn parse_transform(transform: Option<&str>, to_mm: f32) -> Result<Matrix4<f32>, ThreeMfError> {
+    let Some(transform) = transform else {
+        return Ok(Matrix4::identity());
+    };
+    let values: Vec<f32> = transform
+        .split_whitespace()
+        .map(|value| value.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| ThreeMfError::Format(format!("invalid transform {}", transform)))?;
+    let [m00, m01, m02, m10, m11, m12, m20, m21, m22, m30, m31, m32] = values[..] else {
+        return Err(ThreeMfError::Format(format!(
+            "a transform has 12 numbers, not {}",
+            values.len()
+        )));
+    };
+    #[rustfmt::skip]
+    let matrix = Matrix4::new(
+        m00, m10, m20, m30 * to_mm,
+        m01, m11, m21, m31 * to_mm,
+        m02, m12, m22, m32 * to_mm,
+        0.0, 0.0, 0.0, 1.0,
+    );
+    Ok(matrix)
+}
+
+/// The triangles of an object and of the objects it's put together from, in its coordinates
+fn flatten(
+    objects: &HashMap<String, Object>,
+    id: &str,
+    transform: &Matrix4<f32>,
+    depth: usize,
+) -> Result<Vec<Triangle>, ThreeMfError> {
+    if depth > MAX_COMPONENT_DEPTH {
+        return Err(ThreeMfError::Format(format!(
+            "object {} contains itself",
+            id
+        )));
+    }
+    let object = objects
+        .get(id)
+        .ok_or_else(|| ThreeMfError::Format(format!("no object {}", id)))?;
+    let mut triangles: Vec<Triangle> = object
+        .triangles
+        .iter()
+        .map(|t| {
+            triangle(
+                t.vertices
+                    .map(|v| transform.transform_point(&Point3::from(v)).coords),
+            )
+        })
+        .collect();
+    for (component, component_transform) in &object.components {
+        triangles.extend(flatten(
+            objects,
+            component,
+            &(transform * component_transform),
+            depth + 1,
+        )?);
+    }
+    Ok(triangles)
+}
+
+/// Translation, rotation and scale of an affine transform without shear. A mirroring
+/// transform comes out with a negative scale along X.
+fn decompose(transform: &Matrix4<f32>) -> (Vector3<f32>, UnitQuaternion<f32>, Vector3<f32>) {
+    let linear: Matrix3<f32> = transform.fixed_view::<3, 3>(0, 0).into();
+    let mut scale = Vector3::from_fn(|i, _| linear.column(i).norm());
+    if linear.determinant() < 0.0 {
+        scale.x = -scale.x;
+    }
+    let mut rotation = linear;
+    for i in 0..3 {
+        if scale[i] != 0.0 {
+            rotation.column_mut(i).unscale_mut(scale[i]);
+        }
+    }
+    let rotation = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix(&rotation));
+    (transform.fixed_view::<3, 1>(0, 3).into(), rotation, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+    use zip::write::SimpleFileOptions;
+
+    const MODEL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="centimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+  <resources>
+    <object id="1" name="tooth" type="model">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0"/>
+          <vertex x="1" y="0" z="0"/>
+          <vertex x="0" y="1" z="0"/>
+          <vertex x="0" y="0" z="1"/>
+        </vertices>
+        <triangles>
+          <triangle v1="0" v2="2" v3="1"/>
+          <triangle v1="0" v2="1" v3="3"/>
+          <triangle v1="0" v2="3" v3="2"/>
+          <triangle v1="1" v2="2" v3="3"/>
+        </triangles>
+      </mesh>
+    </object>
+    <object id="2" type="model">
+      <components>
+        <component objectid="1"/>
+        <component objectid="1" transform="1 0 0 0 1 0 0 0 1 2 0 0"/>
+      </components>
+    </object>
+  </resources>
+  <build>
+    <item objectid="1" transform="0 1 0 -1 0 0 0 0 1 5 6 0"/>
+    <item objectid="2"/>
+  </build>
+</model>"#;
+
+    #[test]
+    fn test_parse_model() {
+        let items = parse_model(MODEL, "plate").unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name, "tooth");
+        assert_eq!(items[1].name, "plate 2");
+        assert_eq!(items[0].triangles.len(), 4);
+        assert_eq!(items[1].triangles.len(), 8);
+        // Centimeters
+        let vertex = |item: usize, triangle: usize, corner: usize| {
+            Vector3::from(items[item].triangles[triangle].vertices[corner])
+        };
+        assert_eq!(vertex(0, 0, 1), Vector3::new(0.0, 10.0, 0.0));
+        assert_eq!(vertex(1, 4, 0), Vector3::new(20.0, 0.0, 0.0));
+
+        let body = items[0].to_body();
+        assert_eq!(body.position, Vector3::new(50.0, 60.0, 0.0));
+        assert!((body.scale - Vector3::new(1.0, 1.0, 1.0)).norm() < 1e-6);
+        // A quarter turn counterclockwise around Z, the X axis of the object pointing along Y
+        let x_axis = UnitQuaternion::from_quaternion(body.rotation) * Vector3::x();
+        assert!((x_axis - Vector3::y()).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_invalid_model() {
+        let missing = MODEL.replace(r#"v3="3"/>"#, r#"v3="9"/>"#);
+        assert!(matches!(
+            parse_model(&missing, "plate"),
+            Err(ThreeMfError::Format(_))
+        ));
+        let cycle = MODEL.replace(
+            r#"<component objectid="1"/>"#,
+            r#"<component objectid="2"/>"#,
+        );
+        assert!(matches!(
+            parse_model(&cycle, "plate"),
+            Err(ThreeMfError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_package() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("teeth.3mf");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = SimpleFileOptions::default();
+        zip.start_file("_rels/.rels", options).unwrap();
+        zip.write_all(
+            br#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Target="/3D/teeth.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>"#,
+        )
+        .unwrap();
+        zip.start_file("3D/teeth.model", options).unwrap();
+        zip.write_all(MODEL.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        let bodies = import(&path).unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[1].name, "teeth 2");
+    }
+}
@@ -0,0 +1,115 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::printer::Printer;
+use serde::{Deserialize, Serialize};
+
+/// Fraction of the size of a body the slicing plane has to be away from a vertex for the
+/// vertex to count as off the plane. 1e-6 mm for a body 100 mm across.
+const INTERSECTION_FRACTION: f64 = 1e-8;
+
+/// Fraction of the size of a body within which the ends of two segments are joined
+const ASSEMBLY_FRACTION: f64 = 1e-8;
+
+/// The derived tolerances never get coarser than this fraction of a pixel, so huge bodies
+/// can't blur details the printer can resolve
+const MAX_PIXEL_FRACTION: f64 = 1e-3;
+
+/// Contours are simplified until they deviate at most this fraction of a pixel
+const SIMPLIFICATION_PIXEL_FRACTION: f64 = 0.5;
+
+/// Below this, in mm, tolerances drown in the rounding errors of doubles
+const MIN_TOLERANCE: f64 = 1e-12;
+
+/// Tolerances set by hand in the printer profile, in mm, for models the derived ones don't
+/// suit. Leave them out to derive them from the size of each body and the pixel pitch.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct ToleranceOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intersection: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assembly: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simplification: Option<f64>,
+}
+
+impl ToleranceOverrides {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Every tolerance slicing a body goes by, in mm
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TolerancePolicy {
+    /// Vertices closer than this to the slicing plane are on it
+    pub intersection: f64,
+    /// Ends of segments closer than this are joined into one point of a contour
+    pub assembly: f64,
+    /// Simplified contours deviate at most this far from the sliced ones
+    pub simplification: f64,
+}
+
+impl TolerancePolicy {
+    /// The tolerances for a body `model_size` mm across, rasterized at `pixel_pitch` mm per
+    /// pixel. Pass an infinite pitch for contours that aren't rasterized.
+    pub fn derive(model_size: f64, pixel_pitch: f64, overrides: &ToleranceOverrides) -> Self {
+        let max = pixel_pitch * MAX_PIXEL_FRACTION;
+        let scaled =
+            |fraction: f64| (model_size * fraction).clamp(MIN_TOLERANCE, max.max(MIN_TOLERANCE));
+        Self {
+            intersection: overrides
+                .intersection
+                .unwrap_or_else(|| scaled(INTERSECTION_FRACTION)),
+            assembly: overrides
+                .assembly
+                .unwrap_or_else(|| scaled(ASSEMBLY_FRACTION)),
+            simplification: overrides
+                .simplification
+                .unwrap_or(pixel_pitch * SIMPLIFICATION_PIXEL_FRACTION),
+        }
+    }
+
+    /// The tolerances for a body `model_size` mm across, sliced for `printer`
+    pub fn for_printer(model_size: f64, printer: &Printer) -> Self {
+        Self::derive(model_size, Self::pixel_pitch(printer), &printer.tolerances)
+    }
+
+    /// Size of the smaller side of an LCD pixel, in mm
+    pub fn pixel_pitch(printer: &Printer) -> f64 {
+        let pitch_x = printer.physical_x / printer.pixel_x as f64;
+        let pitch_y = printer.physical_y / printer.pixel_y as f64;
+        pitch_x.min(pitch_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive() {
+        let prop = TolerancePolicy::derive(1000.0, 0.05, &ToleranceOverrides::default());
+        let figure = TolerancePolicy::derive(100.0, 0.05, &ToleranceOverrides::default());
+        let ring = TolerancePolicy::derive(10.0, 0.05, &ToleranceOverrides::default());
+        assert!((figure.intersection - 1e-6).abs() < 1e-15);
+        assert!(ring.intersection < figure.intersection);
+        assert!(figure.intersection < prop.intersection);
+        assert!((figure.simplification - 0.025).abs() < 1e-12);
+
+        // Capped by the pixel pitch
+        let huge = TolerancePolicy::derive(1e9, 0.05, &ToleranceOverrides::default());
+        assert!((huge.assembly - 5e-5).abs() < 1e-15);
+        let contours = TolerancePolicy::derive(1e9, f64::INFINITY, &ToleranceOverrides::default());
+        assert_eq!(contours.intersection, 10.0);
+
+        let overrides = ToleranceOverrides {
+            assembly: Some(0.01),
+            ..Default::default()
+        };
+        let set = TolerancePolicy::derive(100.0, 0.05, &overrides);
+        assert_eq!(set.assembly, 0.01);
+        assert_eq!(set.intersection, figure.intersection);
+    }
+}
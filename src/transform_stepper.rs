@@ -0,0 +1,180 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::action::{Action, SetPositionAction, SetRotationAction, SetScaleAction};
+use crate::body::Body;
+use nalgebra::{Quaternion, Vector3};
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+/// The transform a numeric stepper in the body list edits, numbered like the UI does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformField {
+    Position,
+    Rotation,
+    Scale,
+}
+
+impl TransformField {
+    pub fn from_index(index: i32) -> Option<Self> {
+        match index {
+            0 => Some(Self::Position),
+            1 => Some(Self::Rotation),
+            2 => Some(Self::Scale),
+            _ => None,
+        }
+    }
+
+    /// Values the steppers accept: millimeters, degrees and a factor
+    pub fn range(self) -> RangeInclusive<f32> {
+        match self {
+            Self::Position => -1000.0..=1000.0,
+            Self::Rotation => -360.0..=360.0,
+            Self::Scale => 0.01..=100.0,
+        }
+    }
+
+    /// None for text that isn't a number
+    pub fn clamp(self, value: f32) -> Option<f32> {
+        let range = self.range();
+        value
+            .is_finite()
+            .then(|| value.clamp(*range.start(), *range.end()))
+    }
+
+    /// The undo entry setting one axis of the field, leaving the other two as they are
+    pub fn action(self, body: &Rc<RefCell<Body>>, axis: usize, value: f32) -> Box<dyn Action> {
+        let with_axis = |mut current: Vector3<f32>| {
+            current[axis] = value;
+            current
+        };
+        let b = body.borrow();
+        match self {
+            Self::Position => Box::new(SetPositionAction {
+                body: Rc::clone(body),
+                input: with_axis(b.position),
+                previous: b.position,
+            }),
+            Self::Rotation => Box::new(SetRotationAction {
+                body: Rc::clone(body),
+                input: with_axis(Body::quaternion_to_euler(&b.rotation)),
+                previous: b.rotation,
+            }),
+            Self::Scale => Box::new(SetScaleAction {
+                body: Rc::clone(body),
+                input: with_axis(b.scale),
+                previous: b.scale,
+            }),
+        }
+    }
+}
+
+/// A drag in progress on one stepper. The body follows the drag outside of the undo
+/// history, and the whole drag becomes a single entry when it ends.
+pub struct Scrub {
+    body: Rc<RefCell<Body>>,
+    field: TransformField,
+    axis: usize,
+    start: (Vector3<f32>, Quaternion<f32>, Vector3<f32>),
+    value: Option<f32>,
+}
+
+impl Scrub {
+    pub fn begin(body: &Rc<RefCell<Body>>, field: TransformField, axis: usize) -> Self {
+        let start = {
+            let b = body.borrow();
+            (b.position, b.rotation, b.scale)
+        };
+        Self {
+            body: Rc::clone(body),
+            field,
+            axis,
+            start,
+            value: None,
+        }
+    }
+
+    pub fn is_for(&self, body: &Rc<RefCell<Body>>, field: TransformField, axis: usize) -> bool {
+        Rc::ptr_eq(&self.body, body) && self.field == field && self.axis == axis
+    }
+
+    /// Moves the body to where the drag is now
+    pub fn update(&mut self, value: f32) {
+        self.restore();
+        self.field.action(&self.body, self.axis, value).execute();
+        self.value = Some(value);
+    }
+
+    /// Puts the body back where the drag started and returns the action taking it to where
+    /// the drag ended, None if it never moved
+    pub fn finish(self) -> Option<Box<dyn Action>> {
+        self.restore();
+        let value = self.value?;
+        Some(self.field.action(&self.body, self.axis, value))
+    }
+
+    fn restore(&self) {
+        let mut body = self.body.borrow_mut();
+        body.set_position(self.start.0);
+        body.set_rotation_quat(self.start.1);
+        body.set_scale(self.start.2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action_manager::ActionManager;
+
+    #[test]
+    fn test_clamp() {
+        assert_eq!(
+            TransformField::from_index(1),
+            Some(TransformField::Rotation)
+        );
+        assert_eq!(TransformField::from_index(3), None);
+        assert_eq!(TransformField::Scale.clamp(0.0), Some(0.01));
+        assert_eq!(TransformField::Rotation.clamp(400.0), Some(360.0));
+        assert_eq!(TransformField::Position.clamp(-12.5), Some(-12.5));
+        assert_eq!(TransformField::Position.clamp(f32::NAN), None);
+    }
+
+    #[test]
+    fn test_scrub_is_one_undo_entry() {
+        let body = Rc::new(RefCell::new(Body::default()));
+        body.borrow_mut().set_position(Vector3::new(1.0, 2.0, 3.0));
+        let mut manager = ActionManager::new();
+
+        let mut scrub = Scrub::begin(&body, TransformField::Position, 0);
+        assert!(scrub.is_for(&body, TransformField::Position, 0));
+        assert!(!scrub.is_for(&body, TransformField::Scale, 0));
+        for value in [1.5, 4.0, 7.5] {
+            scrub.update(value);
+            assert_eq!(body.borrow().position, Vector3::new(value, 2.0, 3.0));
+        }
+        manager.execute(scrub.finish().unwrap());
+        assert_eq!(body.borrow().position, Vector3::new(7.5, 2.0, 3.0));
+
+        manager.undo();
+        assert_eq!(body.borrow().position, Vector3::new(1.0, 2.0, 3.0));
+        manager.redo();
+        assert_eq!(body.borrow().position, Vector3::new(7.5, 2.0, 3.0));
+
+        // A click without a drag changes nothing
+        let scrub = Scrub::begin(&body, TransformField::Scale, 2);
+        assert!(scrub.finish().is_none());
+    }
+
+    #[test]
+    fn test_rotation_keeps_other_axes() {
+        let body = Rc::new(RefCell::new(Body::default()));
+        body.borrow_mut()
+            .set_rotation(Vector3::new(10.0, 20.0, 30.0));
+        let mut scrub = Scrub::begin(&body, TransformField::Rotation, 2);
+        scrub.update(45.0);
+        scrub.update(60.0);
+        let rotation = Body::quaternion_to_euler(&body.borrow().rotation);
+        assert!((rotation - Vector3::new(10.0, 20.0, 60.0)).norm() < 1e-3);
+    }
+}
@@ -0,0 +1,154 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::motion_profile::format_duration;
+use crate::settings::{write_atomically, SettingsError};
+use dirs_next::config_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Profiles the statistics name as the most used
+const SHOWN_PROFILES: usize = 3;
+
+/// What was sliced on this computer, counted with every export. Kept with the user's
+/// settings and never sent anywhere.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct UsageStats {
+    pub jobs: u64,
+    /// Bodies of all the jobs together
+    pub models_sliced: u64,
+    pub layers: u64,
+    /// Estimated print time of all the jobs, seconds
+    pub print_time: f64,
+    /// Jobs sliced with every parameter snapshot, by its name
+    pub profiles: BTreeMap<String, u64>,
+}
+
+impl UsageStats {
+    fn user_stats_path() -> Result<PathBuf, SettingsError> {
+        let config_dir = config_dir().ok_or(SettingsError::ConfigDirNotFound)?;
+        Ok(config_dir
+            .join("SealSlicer")
+            .join("settings")
+            .join("usage_stats.toml"))
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, SettingsError> {
+        let content = fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), SettingsError> {
+        write_atomically(path, &toml::to_string_pretty(self)?)
+    }
+
+    /// Loads the user's statistics, or starts from zero if nothing was exported yet
+    pub fn load_user_stats() -> Self {
+        let path = match Self::user_stats_path() {
+            Ok(path) if path.exists() => path,
+            _ => return Self::default(),
+        };
+        Self::load_from_file(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to load usage statistics: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn save_user_stats(&self) -> Result<(), SettingsError> {
+        self.save_to_file(&Self::user_stats_path()?)
+    }
+
+    /// Counts an exported job of `models` bodies, sliced with the parameter snapshot
+    /// `profile` or with edited parameters
+    pub fn record_job(
+        &mut self,
+        models: usize,
+        layers: usize,
+        print_time: f64,
+        profile: Option<&str>,
+    ) {
+        self.jobs += 1;
+        self.models_sliced += models as u64;
+        self.layers += layers as u64;
+        self.print_time += print_time;
+        if let Some(profile) = profile {
+            *self.profiles.entry(profile.to_string()).or_default() += 1;
+        }
+    }
+
+    /// The profiles with the most jobs and how many, most used first
+    pub fn most_used_profiles(&self, count: usize) -> Vec<(&str, u64)> {
+        let mut profiles: Vec<(&str, u64)> = self
+            .profiles
+            .iter()
+            .map(|(name, jobs)| (name.as_str(), *jobs))
+            .collect();
+        // Stable, so profiles used as often stay in the order of their names
+        profiles.sort_by_key(|&(_, jobs)| std::cmp::Reverse(jobs));
+        profiles.truncate(count);
+        profiles
+    }
+
+    /// One line for every number, as the statistics page shows them
+    pub fn describe(&self) -> String {
+        if self.jobs == 0 {
+            return "Nothing exported yet".to_string();
+        }
+        let profiles = match self.most_used_profiles(SHOWN_PROFILES)[..] {
+            [] => "none, every job was sliced with edited parameters".to_string(),
+            ref profiles => profiles
+                .iter()
+                .map(|(name, jobs)| format!("{} ({})", name, jobs))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        format!(
+            "Jobs exported: {}\nModels sliced: {}\nLayers: {}\nEstimated print time: {}\nMost used profiles: {}",
+            self.jobs,
+            self.models_sliced,
+            self.layers,
+            format_duration(self.print_time),
+            profiles
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_record_and_describe() {
+        let mut stats = UsageStats::default();
+        assert_eq!(stats.describe(), "Nothing exported yet");
+
+        stats.record_job(2, 400, 3600.0, Some("Fine"));
+        stats.record_job(1, 200, 1800.0, None);
+        stats.record_job(3, 100, 900.0, Some("Draft"));
+        stats.record_job(1, 100, 900.0, Some("Draft"));
+        stats.record_job(1, 100, 900.0, Some("Clear"));
+        assert_eq!(
+            stats.most_used_profiles(2),
+            vec![("Draft", 2), ("Clear", 1)]
+        );
+        assert_eq!(
+            stats.describe(),
+            "Jobs exported: 5\nModels sliced: 8\nLayers: 900\n\
+             Estimated print time: 2h 15m\nMost used profiles: Draft (2), Clear (1), Fine (1)"
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings").join("usage_stats.toml");
+        let mut stats = UsageStats::default();
+        stats.record_job(2, 400, 3600.0, Some("Fine"));
+        stats.save_to_file(&path).unwrap();
+        assert_eq!(UsageStats::load_from_file(&path).unwrap(), stats);
+    }
+}
@@ -0,0 +1,344 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::motion_profile::MotionProfile;
+use crate::output_formats::{Layer, LayerEntry, OutputFormat, PrinterFile, ReadError};
+use crate::preview::{PreviewEncoding, PreviewFormat};
+use crate::printer::Printer;
+use crate::settings::PngCompression;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read, Write};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// The previews UVtools shows for the project, by their name in the archive
+const PREVIEWS: [(&str, u32, u32); 2] = [("huge", 400, 400), ("tiny", 200, 125)];
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Config {
+    properties: Properties,
+    layers: Vec<LayerSettings>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Properties {
+    size: Size,
+    exposure: Exposure,
+    bottom: Bottom,
+    /// The resin isn't part of UVtools' own layout, it keeps what it doesn't know
+    #[serde(default)]
+    material_name: String,
+    #[serde(default)]
+    printer_model: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Size {
+    x: u32,
+    y: u32,
+    millimeter: Millimeter,
+    layers: usize,
+    layer_height: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Millimeter {
+    x: f64,
+    y: f64,
+}
+
+/// Seconds, millimeters and millimeters per minute, like the rest of UVtools
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Exposure {
+    light_on_time: f64,
+    light_off_time: f64,
+    #[serde(rename = "LightPWM")]
+    light_pwm: u8,
+    lift_height: f64,
+    lift_speed: f64,
+    retract_height: f64,
+    retract_speed: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Bottom {
+    count: usize,
+    #[serde(flatten)]
+    exposure: Exposure,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LayerSettings {
+    z: f64,
+    exposure: Exposure,
+}
+
+/// A UVtools project: a zip of `config.json` with the job settings and one entry per
+/// layer, a grayscale PNG per layer and the previews. UVtools opens it to post-process
+/// the layers before they are converted for a printer. Like the Photon Workshop files
+/// the lift and retract move at a single speed.
+pub fn encode(file: &PrinterFile, layers: &[Layer]) -> Vec<u8> {
+    let printer = &file.printer;
+    let motion = &file.motion;
+    let exposure = |light_on_time: f64, light_off_time: f64, lift_height: f64| Exposure {
+        light_on_time,
+        light_off_time,
+        light_pwm: 255,
+        lift_height,
+        lift_speed: motion.lift_fast_speed,
+        retract_height: lift_height,
+        retract_speed: motion.retract_fast_speed,
+    };
+    let config = Config {
+        properties: Properties {
+            size: Size {
+                x: printer.pixel_x,
+                y: printer.pixel_y,
+                millimeter: Millimeter {
+                    x: printer.physical_x,
+                    y: printer.physical_y,
+                },
+                layers: layers.len(),
+                layer_height: file.layer_height,
+            },
+            exposure: exposure(
+                motion.exposure_time,
+                motion.rest_before_exposure,
+                motion.lift_distance,
+            ),
+            bottom: Bottom {
+                count: motion.bottom_layer_count,
+                exposure: exposure(
+                    motion.bottom_exposure_time,
+                    motion.rest_before_exposure,
+                    motion.lift_distance,
+                ),
+            },
+            material_name: file.resin.clone(),
+            printer_model: printer.model.clone(),
+        },
+        layers: file
+            .plan(layers.len())
+            .into_iter()
+            .map(|layer| LayerSettings {
+                z: layer.top,
+                exposure: exposure(
+                    layer.timing.exposure,
+                    layer.timing.rest,
+                    layer.lift_distance,
+                ),
+            })
+            .collect(),
+    };
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    let mut add = |name: &str, bytes: &[u8]| -> zip::result::ZipResult<()> {
+        zip.start_file(name, options)?;
+        zip.write_all(bytes)?;
+        Ok(())
+    };
+    // Writing to memory only fails on a bug, like the encoders of the other formats
+    let written: zip::result::ZipResult<()> = (|| {
+        add(
+            "config.json",
+            &serde_json::to_vec_pretty(&config).expect("the config is always serializable"),
+        )?;
+        for (index, layer) in layers.iter().enumerate() {
+            add(&layer_name(index), &encode_png(layer))?;
+        }
+        for (name, width, height) in PREVIEWS {
+            let format = PreviewFormat {
+                width,
+                height,
+                encoding: PreviewEncoding::Png,
+                model_color: [255, 140, 40],
+                background_color: [0, 0, 0],
+            };
+            let preview = format
+                .encode(&format.render(layers), PngCompression::default())
+                .expect("PNG to memory can't fail");
+            add(&format!("preview/{}.png", name), &preview)?;
+        }
+        Ok(())
+    })();
+    written.expect("zip to memory can't fail");
+    zip.finish().expect("zip to memory can't fail").into_inner()
+}
+
+/// Reads a project `encode` wrote, or one UVtools saved. The slow stages of the lift and
+/// retract aren't stored, they come back as the defaults.
+pub fn decode(name: &str, bytes: &[u8]) -> Result<(PrinterFile, Vec<Layer>), ReadError> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(bytes)).map_err(|_| ReadError::WrongFormat(".uvj"))?;
+    let mut read = |name: &str| -> Result<Vec<u8>, ReadError> {
+        let mut entry = archive
+            .by_name(name)
+            .map_err(|_| ReadError::WrongFormat(".uvj"))?;
+        let mut bytes = Vec::new();
+        entry
+            .read_to_end(&mut bytes)
+            .map_err(|_| ReadError::Truncated)?;
+        Ok(bytes)
+    };
+    let config: Config = serde_json::from_slice(&read("config.json")?)
+        .map_err(|_| ReadError::WrongFormat(".uvj"))?;
+    let Properties {
+        size,
+        exposure,
+        bottom,
+        material_name,
+        printer_model,
+    } = config.properties;
+
+    let motion = MotionProfile {
+        exposure_time: exposure.light_on_time,
+        bottom_exposure_time: bottom.exposure.light_on_time,
+        bottom_layer_count: bottom.count,
+        lift_distance: exposure.lift_height,
+        lift_fast_speed: exposure.lift_speed,
+        retract_fast_speed: exposure.retract_speed,
+        rest_before_exposure: exposure.light_off_time,
+        ..MotionProfile::default()
+    };
+    let printer = Printer {
+        name: printer_model.clone(),
+        model: printer_model,
+        pixel_x: size.x,
+        pixel_y: size.y,
+        physical_x: size.millimeter.x,
+        physical_y: size.millimeter.y,
+        output_format: OutputFormat::Uvj,
+        ..Printer::default()
+    };
+    let layers = (0..size.layers)
+        .map(|index| {
+            let png = read(&layer_name(index))?;
+            image::load_from_memory_with_format(&png, ImageFormat::Png)
+                .ok()
+                .map(|image| image.to_luma8())
+                .filter(|layer| layer.dimensions() == (printer.pixel_x, printer.pixel_y))
+                .ok_or(ReadError::DamagedLayer(index))
+        })
+        .collect::<Result<_, _>>()?;
+    let mut file = PrinterFile {
+        format: OutputFormat::Uvj,
+        name: name.to_string(),
+        printer,
+        resin: material_name,
+        motion,
+        layer_height: size.layer_height,
+        layer_height_ranges: Vec::new(),
+        layer_overrides: Vec::new(),
+        price_per_liter: None,
+    };
+    let table: Vec<LayerEntry> = config
+        .layers
+        .iter()
+        .map(|layer| LayerEntry {
+            top: layer.z,
+            exposure: layer.exposure.light_on_time,
+            lift_distance: Some(layer.exposure.lift_height),
+            rest: Some(layer.exposure.light_off_time),
+        })
+        .collect();
+    file.read_layer_table(&table);
+    Ok((file, layers))
+}
+
+/// Layers are numbered from 0, eight digits wide
+fn layer_name(index: usize) -> String {
+    format!("slice/{:08}.png", index)
+}
+
+fn encode_png(layer: &Layer) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let encoder = PngEncoder::new_with_quality(
+        Cursor::new(&mut bytes),
+        CompressionType::Fast,
+        FilterType::Adaptive,
+    );
+    layer
+        .write_with_encoder(encoder)
+        .expect("PNG to memory can't fail");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slice_parameters::SliceParameters;
+    use image::{ImageBuffer, Luma};
+
+    #[test]
+    fn test_uvj_project() {
+        let mut printer = SliceParameters::default().printer;
+        (printer.pixel_x, printer.pixel_y) = (60, 40);
+        let motion = MotionProfile {
+            bottom_layer_count: 3,
+            ..MotionProfile::default()
+        };
+        let file = PrinterFile {
+            format: OutputFormat::Uvj,
+            name: "job".to_string(),
+            printer,
+            resin: "Grey".to_string(),
+            motion: motion.clone(),
+            layer_height: 0.05,
+            layer_height_ranges: Vec::new(),
+            layer_overrides: Vec::new(),
+            price_per_liter: None,
+        };
+        let layers: Vec<Layer> = (0..10)
+            .map(|i| ImageBuffer::from_fn(60, 40, |x, _| Luma([if x < i { 255 } else { 0 }])))
+            .collect();
+        assert_eq!(file.file_name(), "job.uvj");
+        let bytes = encode(&file, &layers);
+
+        let mut archive = ZipArchive::new(Cursor::new(&bytes)).unwrap();
+        for name in [
+            "config.json",
+            "slice/00000000.png",
+            "slice/00000009.png",
+            "preview/huge.png",
+            "preview/tiny.png",
+        ] {
+            assert!(archive.by_name(name).is_ok(), "{} is missing", name);
+        }
+        let mut config = Vec::new();
+        archive
+            .by_name("config.json")
+            .unwrap()
+            .read_to_end(&mut config)
+            .unwrap();
+        let config: serde_json::Value = serde_json::from_slice(&config).unwrap();
+        assert_eq!(config["Properties"]["Size"]["Layers"], 10);
+        assert_eq!(config["Properties"]["Bottom"]["Count"], 3);
+        let exposures: Vec<f64> = config["Layers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|layer| layer["Exposure"]["LightOnTime"].as_f64().unwrap())
+            .collect();
+        assert_eq!(exposures[2], motion.bottom_exposure_time);
+        assert_eq!(exposures[3], motion.exposure_time);
+
+        let (decoded, decoded_layers) = decode("job", &bytes).unwrap();
+        assert_eq!(decoded.resin, "Grey");
+        assert_eq!(decoded.motion, motion);
+        assert_eq!(decoded_layers, layers);
+        assert!(matches!(
+            decode("job", b"not a zip"),
+            Err(ReadError::WrongFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,124 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::settings::UvToolsSettings;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum UvToolsError {
+    #[error("UVtools wasn't found, set its executable in the settings")]
+    NotFound,
+    #[error("Failed to launch {0}: {1}")]
+    Launch(PathBuf, std::io::Error),
+}
+
+/// Names the UVtools executable goes by on the PATH
+#[cfg(windows)]
+const EXECUTABLE_NAMES: &[&str] = &["UVtools.exe"];
+#[cfg(not(windows))]
+const EXECUTABLE_NAMES: &[&str] = &["UVtools", "uvtools"];
+
+/// Where the installers put UVtools
+fn install_locations() -> Vec<PathBuf> {
+    let mut locations = Vec::new();
+    if cfg!(windows) {
+        for variable in ["ProgramFiles", "LOCALAPPDATA"] {
+            if let Some(dir) = env::var_os(variable) {
+                locations.push(PathBuf::from(dir).join("UVtools").join("UVtools.exe"));
+            }
+        }
+    } else if cfg!(target_os = "macos") {
+        locations.push(PathBuf::from("/Applications/UVtools.app"));
+    }
+    locations
+}
+
+/// The first UVtools executable in the directories of a PATH-like variable
+fn find_on_path(path: &OsStr) -> Option<PathBuf> {
+    env::split_paths(path)
+        .flat_map(|dir| EXECUTABLE_NAMES.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// UVtools in its usual install location or on the PATH, None if it isn't installed
+pub fn find_executable() -> Option<PathBuf> {
+    install_locations()
+        .into_iter()
+        .find(|location| location.exists())
+        .or_else(|| find_on_path(&env::var_os("PATH").unwrap_or_default()))
+}
+
+/// The command that opens `file` in UVtools. Bundles on macOS are started through `open`,
+/// which hands them the file.
+pub fn command(executable: &Path, file: &Path) -> Command {
+    if executable.extension() == Some(OsStr::new("app")) {
+        let mut command = Command::new("open");
+        command.args([OsString::from("-a"), executable.into(), file.into()]);
+        command
+    } else {
+        let mut command = Command::new(executable);
+        command.arg(file);
+        command
+    }
+}
+
+/// Launches UVtools on an export without waiting for it to be closed
+pub fn open(settings: &UvToolsSettings, file: &Path) -> Result<(), UvToolsError> {
+    let executable = match &settings.executable {
+        Some(executable) => executable.clone(),
+        None => find_executable().ok_or(UvToolsError::NotFound)?,
+    };
+    let mut child = command(&executable, file)
+        .spawn()
+        .map_err(|e| UvToolsError::Launch(executable, e))?;
+    // Reaps the process once the user closes UVtools
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_command() {
+        let file = Path::new("out/job 1");
+        let plain = command(Path::new("/opt/UVtools/UVtools"), file);
+        assert_eq!(plain.get_program(), "/opt/UVtools/UVtools");
+        assert_eq!(plain.get_args().collect::<Vec<_>>(), ["out/job 1"]);
+
+        let bundle = command(Path::new("/Applications/UVtools.app"), file);
+        assert_eq!(bundle.get_program(), "open");
+        assert_eq!(
+            bundle.get_args().collect::<Vec<_>>(),
+            ["-a", "/Applications/UVtools.app", "out/job 1"]
+        );
+    }
+
+    #[test]
+    fn test_find_on_path() {
+        let empty = tempdir().unwrap();
+        let installed = tempdir().unwrap();
+        let executable = installed.path().join(EXECUTABLE_NAMES[0]);
+        std::fs::write(&executable, "").unwrap();
+
+        let path = env::join_paths([empty.path(), installed.path()]).unwrap();
+        assert_eq!(find_on_path(&path), Some(executable));
+        assert_eq!(find_on_path(empty.path().as_os_str()), None);
+    }
+
+    #[test]
+    fn test_open_reports_missing_executable() {
+        let settings = UvToolsSettings {
+            executable: Some(PathBuf::from("/nonexistent/UVtools")),
+            open_after_export: false,
+        };
+        let error = open(&settings, Path::new("out")).unwrap_err();
+        assert!(matches!(error, UvToolsError::Launch(..)));
+    }
+}
@@ -0,0 +1,193 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::mesh::Vertex;
+use crate::number_format;
+use crate::printer::Printer;
+use serde::{Deserialize, Serialize};
+
+/// Resin left on the floor of the vat when the job is done, so the film never runs dry
+const MIN_DEPTH: f64 = 3.0; // millimeters
+
+/// Room around the plate of printers whose profile doesn't give their vat
+const DEFAULT_MARGIN: f64 = 10.0; // millimeters
+const DEFAULT_HEIGHT: f64 = 40.0; // millimeters
+
+/// Inside of the resin vat, centered over the plate like the body positions
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Vat {
+    pub width: f64,  // millimeters
+    pub depth: f64,  // millimeters
+    pub height: f64, // millimeters
+}
+
+/// What to pour into the vat for a job
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VatFill {
+    /// Milliliters, the job and the floor it leaves behind
+    pub pour: f64,
+    /// Height of the resin in the vat, at most the height of the vat, in mm
+    pub level: f64,
+    /// The job needs more resin than the vat holds, it has to be topped up while printing
+    pub overflows: bool,
+}
+
+impl Vat {
+    /// The vat of the printer profile, or one a bit larger than the plate when it has none
+    pub fn of(printer: &Printer) -> Self {
+        printer.vat.unwrap_or(Self {
+            width: printer.physical_x + 2.0 * DEFAULT_MARGIN,
+            depth: printer.physical_y + 2.0 * DEFAULT_MARGIN,
+            height: DEFAULT_HEIGHT,
+        })
+    }
+
+    /// The resin for a job of `volume` milliliters
+    pub fn fill(&self, volume: f64) -> VatFill {
+        let floor_area = self.width * self.depth; // mm², 1000 of them 1 mm deep are a ml
+        let pour = volume + MIN_DEPTH * floor_area / 1000.0;
+        let level = pour * 1000.0 / floor_area;
+        VatFill {
+            pour,
+            level: level.min(self.height),
+            overflows: level > self.height,
+        }
+    }
+
+    /// The walls and floor of the vat, open at the top
+    pub fn wall_vertices(&self) -> Vec<Vertex> {
+        self.box_vertices(self.height, false)
+    }
+
+    /// The resin in the vat, filled up to `level`
+    pub fn resin_vertices(&self, level: f64) -> Vec<Vertex> {
+        self.box_vertices(level, true)
+    }
+
+    // Two triangles for each side of a box standing on the plate, facing outwards
+    fn box_vertices(&self, height: f64, closed: bool) -> Vec<Vertex> {
+        let (x, y, z) = (
+            self.width as f32 / 2.0,
+            self.depth as f32 / 2.0,
+            height as f32,
+        );
+        let corner = |i: usize, top: bool| {
+            let (cx, cy) = [(-x, -y), (x, -y), (x, y), (-x, y)][i % 4];
+            [cx, cy, if top { z } else { 0.0 }]
+        };
+        let mut quads: Vec<([[f32; 3]; 4], [f32; 3])> = (0..4)
+            .map(|i| {
+                let normal = [
+                    [0.0, -1.0, 0.0],
+                    [1.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0],
+                    [-1.0, 0.0, 0.0],
+                ];
+                let side = [
+                    corner(i, false),
+                    corner(i + 1, false),
+                    corner(i + 1, true),
+                    corner(i, true),
+                ];
+                (side, normal[i])
+            })
+            .collect();
+        let floor = [
+            corner(0, false),
+            corner(3, false),
+            corner(2, false),
+            corner(1, false),
+        ];
+        quads.push((floor, [0.0, 0.0, -1.0]));
+        if closed {
+            let top = [
+                corner(0, true),
+                corner(1, true),
+                corner(2, true),
+                corner(3, true),
+            ];
+            quads.push((top, [0.0, 0.0, 1.0]));
+        }
+        let barycentric = [1.0, 1.0, 1.0];
+        quads
+            .into_iter()
+            .flat_map(|(quad, normal)| {
+                [0, 1, 2, 0, 2, 3].map(|i| Vertex::new(quad[i], normal, barycentric))
+            })
+            .collect()
+    }
+}
+
+impl VatFill {
+    pub fn describe(&self) -> String {
+        let pour = number_format::decimal(self.pour, 0);
+        if self.overflows {
+            format!(
+                "The job needs {} ml of resin, more than the vat holds. Fill it to the top and \
+                 add more while printing.",
+                pour
+            )
+        } else {
+            format!(
+                "Pour {} ml of resin, {} mm deep in the vat",
+                pour,
+                number_format::decimal(self.level, 1)
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_level() {
+        let vat = Vat {
+            width: 100.0,
+            depth: 50.0,
+            height: 20.0,
+        };
+        // 5 ml per mm of depth, 15 ml stay on the floor
+        let fill = vat.fill(25.0);
+        assert_eq!(fill.pour, 40.0);
+        assert_eq!(fill.level, 8.0);
+        assert!(!fill.overflows);
+
+        let fill = vat.fill(200.0);
+        assert_eq!(fill.pour, 215.0);
+        assert_eq!(fill.level, 20.0);
+        assert!(fill.overflows);
+
+        let printer = Printer {
+            physical_x: 80.0,
+            physical_y: 30.0,
+            vat: None,
+            ..Printer::default()
+        };
+        // 10 mm around the plate
+        assert_eq!(
+            Vat::of(&printer),
+            Vat {
+                height: 40.0,
+                ..vat
+            }
+        );
+    }
+
+    #[test]
+    fn test_vat_is_open_at_the_top() {
+        let vat = Vat {
+            width: 100.0,
+            depth: 50.0,
+            height: 20.0,
+        };
+        let walls = vat.wall_vertices();
+        assert_eq!(walls.len(), 5 * 6);
+        assert!(walls.iter().all(|v| v.position[2] <= 20.0));
+        let resin = vat.resin_vertices(8.0);
+        assert_eq!(resin.len(), 6 * 6);
+        assert!(resin.iter().any(|v| v.position[2] == 8.0));
+        assert!(resin.iter().all(|v| v.position[2] <= 8.0));
+    }
+}
@@ -0,0 +1,44 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::axis_gizmo::Axis;
+use crate::settings::RendererSettings;
+use nalgebra::Vector3;
+
+/// What drawing the last frame of a view took
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    pub draw_calls: u32,
+    pub vertices: u64,
+    /// Estimated from the buffers and textures the view allocated on the GPU
+    pub vram_bytes: u64,
+}
+
+/// The 3D view of the plate, drawn with OpenGL or on the CPU where no GL is available
+pub trait Viewport {
+    /// Draws the plate with what the renderer settings turn on over it
+    fn render(&mut self, width: u32, height: u32, settings: &RendererSettings) -> slint::Image;
+
+    fn camera_pitch_yaw(&mut self, delta_x: f32, delta_y: f32);
+
+    fn camera_pan(&mut self, delta_x: f32, delta_y: f32);
+
+    fn zoom(&mut self, amt: f32);
+
+    /// Turns the view to look down the axis under the pointer, false when the pointer
+    /// isn't on an axis
+    fn snap_to_gizmo_axis(&mut self, x: f32, y: f32, width: f32, height: f32) -> bool;
+
+    /// The ray through the view at a point in pixels of the rendered image, as (origin,
+    /// direction) in world space
+    fn pick_ray(&self, x: f32, y: f32, width: f32, height: f32)
+        -> Option<(Vector3<f32>, Vector3<f32>)>;
+
+    /// Looks at the plate from the positive end of the axis
+    fn snap_view(&mut self, axis: Axis);
+
+    /// Highlights the outline of the layer at `height` on the bodies, or hides it with None
+    fn set_slice_preview_height(&mut self, height: Option<f32>);
+
+    fn frame_stats(&self) -> FrameStats;
+}
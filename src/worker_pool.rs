@@ -0,0 +1,91 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use crate::settings::PerformanceSettings;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use std::thread;
+
+/// Nice value of the worker threads in background priority mode
+#[cfg(target_os = "linux")]
+const BACKGROUND_NICE: libc::c_int = 10;
+
+/// Number of worker threads for the settings. 0 uses every core, or every core but
+/// one in background priority mode so the UI always has a core to itself.
+pub fn thread_count(settings: &PerformanceSettings) -> usize {
+    if settings.worker_threads > 0 {
+        return settings.worker_threads;
+    }
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    if settings.background_priority {
+        (cores - 1).max(1)
+    } else {
+        cores
+    }
+}
+
+/// Builds the thread pool slicing and exporting run in
+pub fn build(settings: &PerformanceSettings) -> Result<ThreadPool, ThreadPoolBuildError> {
+    let background_priority = settings.background_priority;
+    ThreadPoolBuilder::new()
+        .num_threads(thread_count(settings))
+        .thread_name(|index| format!("slicer-worker-{}", index))
+        .start_handler(move |_| {
+            if background_priority {
+                lower_current_thread_priority();
+            }
+        })
+        .build()
+}
+
+/// On Linux the nice value is per thread, so this only affects the calling worker
+#[cfg(target_os = "linux")]
+fn lower_current_thread_priority() {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, BACKGROUND_NICE) } != 0 {
+        eprintln!(
+            "Could not lower the priority of a worker thread: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Elsewhere the priority applies to the whole process including the UI, so only the
+/// reduced thread count takes effect
+#[cfg(not(target_os = "linux"))]
+fn lower_current_thread_priority() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_count() {
+        let explicit = PerformanceSettings {
+            worker_threads: 3,
+            background_priority: true,
+        };
+        assert_eq!(thread_count(&explicit), 3);
+
+        let cores = thread::available_parallelism().unwrap().get();
+        assert_eq!(thread_count(&PerformanceSettings::default()), cores);
+        let background = PerformanceSettings {
+            worker_threads: 0,
+            background_priority: true,
+        };
+        assert_eq!(thread_count(&background), (cores - 1).max(1));
+    }
+
+    #[test]
+    fn test_pool_runs_with_configured_threads() {
+        let settings = PerformanceSettings {
+            worker_threads: 2,
+            background_priority: true,
+        };
+        let pool = build(&settings).unwrap();
+        assert_eq!(pool.install(rayon::current_num_threads), 2);
+        #[cfg(target_os = "linux")]
+        {
+            let nice = pool.install(|| unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) });
+            assert!(nice >= BACKGROUND_NICE);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+// Distributed under the GNU Affero General Public License v3.0 or later.
+// See accompanying file LICENSE or https://www.gnu.org/licenses/agpl-3.0.html for details.
+
+use image::{GrayImage, ImageBuffer, Luma};
+use imageproc::morphology::{grayscale_dilate, grayscale_erode, Mask};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::printer::Printer;
+
+/// Millimeters every outline of the layers is moved outwards, or inwards when negative, so
+/// the fit of holes and pegs can be calibrated without editing the models. Set per printer
+/// in its profile:
+///
+/// ```toml
+/// xy_compensation = -0.05
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(transparent)]
+pub struct XyCompensation(pub f64);
+
+impl XyCompensation {
+    pub fn is_off(&self) -> bool {
+        self.0 == 0.0
+    }
+}
+
+/// Grows or shrinks the lit areas of the layers by the XY compensation of a printer
+pub struct XyCompensationMask {
+    mask: Mask,
+    grow: bool,
+}
+
+impl XyCompensationMask {
+    /// The mask for the compensation of the printer, None when it is under half a pixel
+    pub fn for_printer(printer: &Printer) -> Option<Self> {
+        let millimeters = printer.xy_compensation.0;
+        // Pixels may not be square, so the distance is an ellipse in pixels
+        let radius = |physical: f64, pixels: u32| {
+            (millimeters.abs() * pixels as f64 / physical)
+                .round()
+                .min(u8::MAX as f64) as u32
+        };
+        let radius_x = radius(printer.physical_x, printer.pixel_x);
+        let radius_y = radius(printer.physical_y, printer.pixel_y);
+        if radius_x == 0 && radius_y == 0 {
+            return None;
+        }
+        let ellipse = GrayImage::from_fn(2 * radius_x + 1, 2 * radius_y + 1, |x, y| {
+            let dx = (x as f64 - radius_x as f64) / radius_x.max(1) as f64;
+            let dy = (y as f64 - radius_y as f64) / radius_y.max(1) as f64;
+            Luma([if dx * dx + dy * dy <= 1.0 { 255 } else { 0 }])
+        });
+        Some(Self {
+            mask: Mask::from_image(&ellipse, radius_x as u8, radius_y as u8),
+            grow: millimeters > 0.0,
+        })
+    }
+
+    pub fn apply(&self, image: &ImageBuffer<Luma<u8>, Vec<u8>>) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        if self.grow {
+            grayscale_dilate(image, &self.mask)
+        } else {
+            grayscale_erode(image, &self.mask)
+        }
+    }
+
+    pub fn apply_to_all(&self, images: &mut [ImageBuffer<Luma<u8>, Vec<u8>>]) {
+        images
+            .par_iter_mut()
+            .for_each(|image| *image = self.apply(image));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A lit 20x20 square with a 6x6 hole in the middle, on a printer with 0.05 mm pixels
+    fn square_with_hole() -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(30, 30, |x, y| {
+            let in_square = (5..25).contains(&x) && (5..25).contains(&y);
+            let in_hole = (12..18).contains(&x) && (12..18).contains(&y);
+            Luma([if in_square && !in_hole { 255 } else { 0 }])
+        })
+    }
+
+    fn printer(xy_compensation: f64) -> Printer {
+        Printer {
+            physical_x: 1.5,
+            physical_y: 1.5,
+            pixel_x: 30,
+            pixel_y: 30,
+            xy_compensation: XyCompensation(xy_compensation),
+            ..Printer::default()
+        }
+    }
+
+    /// Lit pixels along the middle row
+    fn row(image: &ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<u32> {
+        (0..30).filter(|&x| image.get_pixel(x, 15)[0] > 0).collect()
+    }
+
+    #[test]
+    fn test_grow_and_shrink() {
+        let image = square_with_hole();
+        assert!(XyCompensationMask::for_printer(&printer(0.0)).is_none());
+        // Under half a pixel
+        assert!(XyCompensationMask::for_printer(&printer(0.02)).is_none());
+
+        let grown = XyCompensationMask::for_printer(&printer(0.1))
+            .unwrap()
+            .apply(&image);
+        // Two pixels wider on the outside, two narrower in the hole
+        let lit: Vec<u32> = (3..14).chain(16..27).collect();
+        assert_eq!(row(&grown), lit);
+
+        let mut images = vec![image];
+        XyCompensationMask::for_printer(&printer(-0.05))
+            .unwrap()
+            .apply_to_all(&mut images);
+        let lit: Vec<u32> = (6..11).chain(19..24).collect();
+        assert_eq!(row(&images[0]), lit);
+    }
+
+    #[test]
+    fn test_printer_profile_round_trip() {
+        let printer = printer(-0.05);
+        let content = toml::to_string(&printer).unwrap();
+        assert!(content.contains("xy_compensation = -0.05"));
+        let loaded: Printer = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.xy_compensation, printer.xy_compensation);
+        let content = toml::to_string(&Printer::default()).unwrap();
+        assert!(!content.contains("xy_compensation"));
+    }
+}